@@ -231,6 +231,29 @@ void            dc_context_unref             (dc_context_t* context);
 dc_event_emitter_t* dc_get_event_emitter(dc_context_t* context);
 
 
+/**
+ * Receive only the events of the given kinds.
+ * Other events are dropped before they are queued for the event emitter,
+ * this is useful eg. for bots not interested in the log events
+ * #DC_EVENT_INFO and #DC_EVENT_WARNING.
+ *
+ * Example:
+ *
+ * ~~~
+ * int event_ids[] = { DC_EVENT_INCOMING_MSG, DC_EVENT_ERROR };
+ * dc_set_event_filter(context, event_ids, 2);
+ * ~~~
+ *
+ * @memberof dc_context_t
+ * @param context The context object as created by dc_context_new().
+ * @param event_ids Array of the @ref DC_EVENT IDs to receive.
+ *     NULL to receive all events again, which is the default.
+ * @param count The number of IDs in event_ids.
+ * @return None.
+ */
+void            dc_set_event_filter          (dc_context_t* context, const int* event_ids, int count);
+
+
 /**
  * Get the blob directory.
  *
@@ -258,7 +281,8 @@ char*           dc_get_blobdir               (const dc_context_t* context);
  * - `imap_certificate_checks` = how to check IMAP certificates, one of the @ref DC_CERTCK flags, defaults to #DC_CERTCK_AUTO (0)
  * - `smtp_certificate_checks` = how to check SMTP certificates, one of the @ref DC_CERTCK flags, defaults to #DC_CERTCK_AUTO (0)
  * - `displayname`  = Own name to use when sending messages.  MUAs are allowed to spread this way eg. using CC, defaults to empty
- * - `selfstatus`   = Own status to display eg. in email footers, defaults to a standard text
+ * - `selfstatus`   = Own status to display eg. in email footers, defaults to a standard text.
+ *                    The status is shown to other Delta Chat users as part of the profile, see dc_contact_get_status().
  * - `selfavatar`   = File containing avatar. Will immediately be copied to the 
  *                    `blobdir`; the original image will not be needed anymore.
  *                    NULL to remove the avatar.
@@ -271,11 +295,39 @@ char*           dc_get_blobdir               (const dc_context_t* context);
  *                    0=do not watch the `INBOX`-folder
  * - `sentbox_watch`= 1=watch `Sent`-folder for changes (default),
  *                    0=do not watch the `Sent`-folder
- * - `mvbox_watch`  = 1=watch `DeltaChat`-folder for changes (default),
- *                    0=do not watch the `DeltaChat`-folder
- * - `mvbox_move`   = 1=heuristically detect chat-messages
- *                    and move them to the `DeltaChat`-folder,
- *                    0=do not move chat-messages
+ * - `spam_watch`   = 1=scan the spam folder for messages of known contacts
+ *                    and move them back to the inbox,
+ *                    0=do not scan the spam folder (default)
+ * - `sync_since`   = fetch messages received since this time,
+ *                    either a unix timestamp or a number of days followed by `d`,
+ *                    eg. `30d` for the last 30 days;
+ *                    if unset, only new messages are fetched (default),
+ *                    see also dc_extend_sync_window()
+ * - `watch_folders` = additional folders to scan for messages, one folder per line;
+ *                    the folders are scanned in turn about every minute,
+ *                    independently of the folders watched above
+ * - `idle_timeout` = seconds an IDLE session may stay without any response from the server,
+ *                    the folder is checked for missed messages afterwards, defaults to 1380 (23 minutes);
+ *                    if IDLE repeatedly misses messages, the folders are polled instead
+ * - `poll_interval` = seconds between two checks for new messages
+ *                    if IDLE is not supported or does not work, defaults to 60;
+ *                    the checks of all folders are aligned to wake up the device only once
+ * - `mvbox_policy` = DC_MVBOX_POLICY_OFF (0)=
+ *                    do not create or watch the `DeltaChat`-folder, chat-messages stay in the inbox,
+ *                    DC_MVBOX_POLICY_WATCH (1)=
+ *                    create and watch the `DeltaChat`-folder, but do not move messages there,
+ *                    DC_MVBOX_POLICY_MOVE_CHATS (2)=
+ *                    also heuristically detect chat-messages and move them there (default),
+ *                    DC_MVBOX_POLICY_MOVE_ALL (3)=
+ *                    also move classic emails there
+ * - `mvbox_folder` = name of the folder chat-messages are moved to, defaults to `DeltaChat`;
+ *                    changing `mvbox_folder` or `mvbox_policy` makes the folders be looked up again
+ * - `archive_move` = 1=move the messages of archived chats to the `Archive`-folder
+ *                    of the provider and back when the chats are unarchived,
+ *                    0=do not move messages of archived chats (default)
+ * - `delete_to_trash` = 1=move messages deleted on the server to the `Trash`-folder,
+ *                    the folder is created if the provider has none,
+ *                    0=delete messages on the server at once (default)
  * - `show_emails`  = DC_SHOW_EMAILS_OFF (0)=
  *                    show direct replies to chats only (default),
  *                    DC_SHOW_EMAILS_ACCEPTED_CONTACTS (1)=
@@ -288,9 +340,26 @@ char*           dc_get_blobdir               (const dc_context_t* context);
  *                    generate RSA 2048 keypair
  *                    DC_KEY_GEN_ED25519 (2)=
  *                    generate Ed25519 keypair
+ * - `gossip_policy` = DC_GOSSIP_POLICY_ALWAYS (0)=
+ *                    send the keys of the members in encrypted group messages (default),
+ *                    DC_GOSSIP_POLICY_VERIFIED_ONLY (1)=
+ *                    send the keys only in verified groups,
+ *                    DC_GOSSIP_POLICY_NEVER (2)=
+ *                    never send the keys of other members.
+ *                    Without gossip, members may not be able to encrypt to each other.
+ * - `gossip_accept` = DC_GOSSIP_ACCEPT_ALWAYS (0)=
+ *                    use keys gossiped by other members (default),
+ *                    DC_GOSSIP_ACCEPT_UNKNOWN_PEERS (1)=
+ *                    use gossiped keys only for contacts without a known key,
+ *                    DC_GOSSIP_ACCEPT_NEVER (2)=
+ *                    ignore gossiped keys; members introduced to verified groups cannot be verified then.
+ *                    To see where the key of a contact comes from, use dc_contact_get_key_source().
  * - `save_mime_headers` = 1=save mime headers
  *                    and make dc_get_mime_headers() work for subsequent calls,
  *                    0=do not save mime headers (default)
+ * - `save_html`    = 1=save the sanitized HTML body of incoming messages
+ *                    and make dc_get_msg_html() work for subsequent messages,
+ *                    0=do not save HTML bodies (default)
  * - `delete_device_after` = 0=do not delete messages from device automatically (default),
  *                    >=1=seconds, after which messages are deleted automatically from the device.
  *                    Messages in the "saved messages" chat (see dc_chat_is_self_talk()) are skipped.
@@ -302,14 +371,70 @@ char*           dc_get_blobdir               (const dc_context_t* context);
  *                    "Saved messages" are deleted from the server as well as
  *                    emails matching the `show_emails` settings above, the UI should clearly point that out.
  *                    See also dc_estimate_deletion_cnt().
+ * - `download_limit` = Messages up to this number of bytes are downloaded automatically.
+ *                    For larger messages, only the header is downloaded and a placeholder is shown.
+ *                    The full message can be downloaded using dc_download_full_msg() then.
+ *                    The limit is compared against raw message sizes, including headers.
+ *                    0=no limit (default).
  * - `media_quality` = DC_MEDIA_QUALITY_BALANCED (0) =
  *                    good outgoing images/videos/voice quality at reasonable sizes (default)
  *                    DC_MEDIA_QUALITY_WORSE (1)
  *                    allow worse images/videos/voice quality to gain smaller sizes,
  *                    suitable for providers or areas known to have a bad connection.
- *                    In contrast to other options, the implementation of this option is currently up to the UIs;
- *                    this may change in future, however,
- *                    having the option in the core allows provider-specific-defaults already today.
+ *                    DC_MEDIA_QUALITY_ORIGINAL (2)
+ *                    send images as they are.
+ *                    The core downscales and re-encodes outgoing JPEG images of type #DC_MSG_IMAGE accordingly,
+ *                    images sent as #DC_MSG_FILE are never changed.
+ *                    For videos and voice messages, the implementation of this option is currently up to the UIs.
+ * - `typing_indicators` = 1=send typing notifications using dc_send_typing()
+ *                    and show typing notifications of others, see #DC_EVENT_CONTACT_TYPING,
+ *                    0=do not send typing notifications (default).
+ *                    Typing notifications are only sent in end-to-end encrypted chats.
+ * - `sync_msgs`    = 1=send seen messages, archived, pinned and muted chats and deleted chats
+ *                    to the other devices of the user and apply these changes when they are received,
+ *                    0=do not send these changes to other devices (default).
+ *                    The changes are sent as hidden, end-to-end encrypted messages to self.
+ * - `wkd_lookup`   = 1=look up the keys of recipients without known key
+ *                    in the Web Key Directory (WKD) of their domain in the background
+ *                    after sending; found keys are trusted like gossiped keys,
+ *                    0=do not look up keys (default).
+ *                    The lookup lets the domain of the recipient know that their key was requested.
+ * - `keyserver_lookup` = 1=look up the keys of recipients without known key
+ *                    on the keyserver in the background after sending,
+ *                    after the WKD lookup if enabled, 0=do not look up keys (default).
+ *                    Keys are only imported from keyservers verifying the addresses,
+ *                    currently keys.openpgp.org and keys.mailvelope.com.
+ *                    The lookup lets the keyserver know that the key was requested.
+ * - `keyserver`    = HTTPS URL of the keyserver used for lookups and by dc_publish_key(),
+ *                    defaults to `https://keys.openpgp.org`.
+ * - `smime_certificate` = own S/MIME certificate in PEM format,
+ *                    followed by the intermediate certificates of the chain.
+ *                    If set together with `smime_key` and the chain is valid,
+ *                    messages that are not end-to-end encrypted with OpenPGP are signed using S/MIME
+ *                    and encrypted if the certificates of all recipients are known.
+ *                    Certificates of contacts are collected from their signed messages.
+ *                    Requires the core to be built with the `smime` feature.
+ * - `smime_key`    = private key of `smime_certificate` in PEM format.
+ * - `smime_ca`     = certificate authorities in PEM format
+ *                    trusted for S/MIME in addition to the ones of the system.
+ * - `socks5_host` = host of a SOCKS5 proxy, eg. `127.0.0.1` for a local Tor daemon.
+ *                    If set, IMAP and SMTP connections and HTTP requests, eg. for autoconfig or OAuth2,
+ *                    also the ones made by dc_configure(), are made through the proxy
+ *                    and host names are resolved by the proxy.
+ *                    Unset or empty to connect directly (default).
+ * - `socks5_port` = port of the SOCKS5 proxy, defaults to 1080
+ * - `socks5_user` = username for the SOCKS5 proxy, leave out if no authentication is needed
+ * - `socks5_password` = password for the SOCKS5 proxy
+ * - `job_max_tries` = number of tries after which sending a message or another background job is given up,
+ *                    messages that could not be sent are marked as failed then.
+ *                    0=use the default of the job type (default), eg. 17 tries for sending messages.
+ * - `job_max_backoff` = maximum delay in seconds between two tries of a failed job,
+ *                    the delay is doubled with every try starting with about a minute.
+ *                    0=use the default of the job type (default), eg. one day for sending messages.
+ *                    See dc_msg_get_next_retry_timestamp() for showing when a message is retried.
+ *
+ * Boolean options only accept `0` and `1`, numeric options only accept numbers
+ * or an empty string; other values are refused.
  *
  * If you want to retrieve a value, use dc_get_config().
  *
@@ -317,7 +442,7 @@ char*           dc_get_blobdir               (const dc_context_t* context);
  * @param context The context object
  * @param key The option to change, see above.
  * @param value The value to save for "key"
- * @return 0=failure, 1=success
+ * @return 0=failure, eg. unknown key or invalid value, 1=success
  */
 int             dc_set_config                (dc_context_t* context, const char* key, const char* value);
 
@@ -364,10 +489,12 @@ int             dc_set_stock_translation(dc_context_t* context, uint32_t stock_i
 /**
  * Set configuration values from a QR code containing an account.
  * Before this function is called, dc_check_qr() should confirm the type of the
- * QR code is DC_QR_ACCOUNT.
+ * QR code is DC_QR_ACCOUNT or DC_QR_LOGIN.
  *
  * Internally, the function will call dc_set_config()
  * at least with the keys `addr` and `mail_pw`.
+ * For DC_QR_LOGIN, the server settings given in the QR code are set as well,
+ * server settings not given are reset.
  *
  * @memberof dc_context_t
  * @param context The context object
@@ -377,6 +504,27 @@ int             dc_set_stock_translation(dc_context_t* context, uint32_t stock_i
 int             dc_set_config_from_qr   (dc_context_t* context, const char* qr);
 
 
+/**
+ * Create an account from a QR code and configure it.
+ * Before this function is called, dc_check_qr() should confirm the type of the
+ * QR code is DC_QR_ACCOUNT or DC_QR_LOGIN.
+ *
+ * This is a shortcut for dc_set_config_from_qr() followed by dc_configure():
+ * for DC_QR_ACCOUNT, the credentials are requested from the server given in the QR code,
+ * for DC_QR_LOGIN, they are taken from the QR code directly;
+ * the account is configured using these credentials then.
+ * As dc_configure(), the function returns immediately
+ * and reports the progress by @ref DC_EVENT_CONFIGURE_PROGRESS;
+ * also errors on requesting the credentials are reported this way.
+ *
+ * @memberof dc_context_t
+ * @param context The context object
+ * @param qr scanned QR code
+ * @return None.
+ */
+void            dc_configure_from_qr    (dc_context_t* context, const char* qr);
+
+
 /**
  * Get information about the context.
  *
@@ -455,9 +603,14 @@ char*           dc_get_oauth2_url            (dc_context_t* context, const char*
  * they indicate a successful configuration as well as errors
  * and may be used to create a progress bar.
  *
+ * Moreover, #DC_EVENT_CONFIGURE_STAGE events tell which stage is running
+ * and which server settings are tried;
+ * this may help the user to find out why a configuration fails.
+ *
  * Additional calls to dc_configure() while a config-job is running are ignored.
  * To interrupt a configuration prematurely, use dc_stop_ongoing_process();
- * this is not needed if #DC_EVENT_CONFIGURE_PROGRESS reports success.
+ * the configuration is stopped immediately then and reports failure.
+ * This is not needed if #DC_EVENT_CONFIGURE_PROGRESS reports success.
  *
  * If #DC_EVENT_CONFIGURE_PROGRESS reports failure,
  * the core continues to use the last working configuration
@@ -481,6 +634,40 @@ char*           dc_get_oauth2_url            (dc_context_t* context, const char*
 void            dc_configure                 (dc_context_t* context);
 
 
+/**
+ * @defgroup DC_CONFIGURE_STAGE DC_CONFIGURE_STAGE
+ *
+ * Stages of the configuration reported by #DC_EVENT_CONFIGURE_STAGE.
+ *
+ * @addtogroup DC_CONFIGURE_STAGE
+ * @{
+ */
+
+/**
+ * The server settings are looked up in the provider database or by autoconfig.
+ */
+#define         DC_CONFIGURE_STAGE_AUTOCONFIG   100
+
+/**
+ * Logging in to an IMAP server is tried.
+ */
+#define         DC_CONFIGURE_STAGE_IMAP_PROBE   200
+
+/**
+ * Logging in to an SMTP server is tried.
+ */
+#define         DC_CONFIGURE_STAGE_SMTP_PROBE   300
+
+/**
+ * The IMAP login is checked by setting up the folders and selecting INBOX.
+ */
+#define         DC_CONFIGURE_STAGE_LOGIN_CHECK  400
+
+/**
+ * @}
+ */
+
+
 /**
  * Check if the context is already configured.
  *
@@ -495,6 +682,67 @@ void            dc_configure                 (dc_context_t* context);
 int             dc_is_configured   (const dc_context_t* context);
 
 
+/**
+ * Get the kind of the error of the last failed call.
+ *
+ * Currently, the kind is recorded by dc_configure(), dc_configure_from_qr(),
+ * dc_prepare_msg(), dc_send_msg(), dc_send_msg_sync() and dc_send_text_msg().
+ * Successful calls do not reset the kind,
+ * so the value is only meaningful directly after one of these functions failed.
+ * Details about the error are logged using #DC_EVENT_WARNING or #DC_EVENT_ERROR.
+ *
+ * @memberof dc_context_t
+ * @param context The context object as created by dc_context_new().
+ * @return One of the @ref DC_ERROR_KIND constants,
+ *     DC_ERROR_KIND_OTHER (0) if no error was recorded yet.
+ */
+int             dc_get_last_error_kind (const dc_context_t* context);
+
+
+/**
+ * @defgroup DC_ERROR_KIND DC_ERROR_KIND
+ *
+ * These constants are returned by dc_get_last_error_kind().
+ *
+ * @addtogroup DC_ERROR_KIND
+ * @{
+ */
+
+/**
+ * The error is none of the other kinds.
+ */
+#define         DC_ERROR_KIND_OTHER             0
+
+/**
+ * The database could not be accessed.
+ */
+#define         DC_ERROR_KIND_SQL               1
+
+/**
+ * Connecting to or talking to the IMAP server failed.
+ */
+#define         DC_ERROR_KIND_IMAP              2
+
+/**
+ * Connecting to or talking to the SMTP server failed.
+ */
+#define         DC_ERROR_KIND_SMTP              3
+
+/**
+ * dc_configure() or dc_configure_from_qr() failed.
+ */
+#define         DC_ERROR_KIND_CONFIGURE         4
+
+/**
+ * The message could not be prepared or sent.
+ */
+#define         DC_ERROR_KIND_SEND_MSG          5
+
+/**
+ * @}
+ */
+
+
 /**
  * Start job and IMAP/SMTP tasks.
  * If IO is already running, nothing happens.
@@ -518,6 +766,8 @@ int             dc_is_io_running(const dc_context_t* context);
 
 /**
  * Stop job and IMAP/SMTP tasks and return when they are finished. 
+ * Jobs and fetches in progress are completed first, IDLE is aborted,
+ * so the app can be suspended safely afterwards.
  * If IO is not running, nothing happens.
  * To check the current IO state, use dc_is_io_running().
  *
@@ -529,8 +779,11 @@ void            dc_stop_io(dc_context_t* context);
 
 /**
  * This function can be called whenever there is a hint
- * that the network is available again.
- * The library will try to send pending messages out.
+ * that the network is available again or has changed,
+ * eg. when the operating system reports a connectivity change.
+ * The library will try to send pending messages out right away
+ * instead of waiting for the next retry
+ * and re-establish the connections to the server.
  *
  * @memberof dc_context_t
  * @param context The context as created by dc_context_new().
@@ -539,6 +792,165 @@ void            dc_stop_io(dc_context_t* context);
 void            dc_maybe_network             (dc_context_t* context);
 
 
+/**
+ * Get the overall state of the connections to the server.
+ * Whenever the state changes, #DC_EVENT_CONNECTIVITY_CHANGED is emitted.
+ *
+ * The state is the one of the inbox connection,
+ * but #DC_CONNECTIVITY_NOT_CONNECTED if sending messages fails.
+ * UIs may show eg. "Not connected", "Connecting…", "Updating…" or "Connected".
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @return One of the @ref DC_CONNECTIVITY constants.
+ */
+int             dc_get_connectivity          (dc_context_t* context);
+
+
+/**
+ * Get the state of each connection to the server as JSON array.
+ *
+ * Each object contains the `connection` (`Inbox`, `Mvbox`, `Sentbox` or `Smtp`),
+ * the `state` (`NotConnected`, `Connecting`, `Working`, `Connected` or `Interrupted`)
+ * and `since`, the unix timestamp the state was entered or 0.
+ * `Interrupted` states additionally contain the `error`
+ * and `next_retry`, the unix timestamp of the next try or null.
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @return JSON array, must be released using dc_str_unref() after usage.
+ */
+char*           dc_get_connectivity_json     (dc_context_t* context);
+
+
+/**
+ * Get the pending background jobs, eg. messages waiting to be sent.
+ *
+ * This function is meant for debugging and support,
+ * eg. to find out why a message stays in the state #DC_STATE_OUT_PENDING.
+ * The jobs are returned as a JSON array in the order they are run,
+ * each job is an object with the following fields:
+ *
+ * - `job_id`: ID of the job to be passed to dc_cancel_job()
+ * - `action`: type of the job, eg. `SendMsgToSmtp` or `MoveMsg`
+ * - `foreign_id`: the object the job works on, eg. the message ID for sending a message
+ * - `tries`: number of unsuccessful tries
+ * - `next_run`: time at which the job is run next, as unix timestamp in seconds
+ * - `last_error`: error of the last unsuccessful try or null
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @return JSON array of jobs, must be released using dc_str_unref() after usage.
+ */
+char*           dc_get_jobs_json             (dc_context_t* context);
+
+
+/**
+ * Cancel a pending background job returned by dc_get_jobs_json().
+ *
+ * If the job sends a message, the message is marked as failed
+ * and #DC_EVENT_MSG_FAILED is emitted.
+ * A job that is running already is not interrupted.
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @param job_id ID of the job to cancel.
+ * @return 1=success, 0=error, eg. the job does not exist.
+ */
+int             dc_cancel_job                (dc_context_t* context, uint32_t job_id);
+
+
+/**
+ * Get the messages waiting to be sent.
+ *
+ * The messages are returned as a JSON array in the order they are sent,
+ * each message is an object with the following fields:
+ *
+ * - `msg_id`: ID of the message, may be passed to dc_cancel_send()
+ * - `chat_id`: ID of the chat the message belongs to
+ * - `size`: size of the rendered message in bytes
+ * - `tries`: number of unsuccessful tries
+ * - `next_run`: time at which sending is tried next, as unix timestamp in seconds
+ * - `last_error`: error of the last unsuccessful try, eg. a rejection by the server, or null
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @return JSON array of messages, must be released using dc_str_unref() after usage.
+ */
+char*           dc_get_outgoing_queue_json   (dc_context_t* context);
+
+
+/**
+ * Cancel sending a message.
+ *
+ * The message is removed from the outgoing queue
+ * and becomes the draft of its chat again, replacing the previous draft.
+ * A message that is being sent right now may be delivered nevertheless.
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @param msg_id ID of the message as returned by dc_get_outgoing_queue_json().
+ * @return 1=success, 0=error, eg. the message is not waiting to be sent.
+ */
+int             dc_cancel_send               (dc_context_t* context, uint32_t msg_id);
+
+
+/**
+ * Set the push notification token of the device.
+ *
+ * If the server supports push notifications,
+ * the token is uploaded the next time the IMAP connection is established,
+ * the server can then wake up the device when new messages arrive.
+ * The token has to be set again whenever the operating system changes it.
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @param token The push token as provided by the operating system,
+ *     eg. the hex-encoded APNS device token.
+ * @return 1=success, 0=error, eg. the token contains invalid characters.
+ */
+int             dc_set_push_device_token     (dc_context_t* context, const char* token);
+
+
+/**
+ * Fetch new messages from the inbox once.
+ *
+ * This function is meant to be called when the app is woken up
+ * by a push notification while IO is not running, eg. on iOS.
+ * The function connects to the server, fetches the inbox and disconnects,
+ * it returns after at most 25 seconds.
+ * Incoming messages are reported by the usual events.
+ * If IO is running, the function just triggers a fetch and returns.
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @return 1=success, 0=error or timeout.
+ */
+int             dc_fetch_on_wakeup           (dc_context_t* context);
+
+
+/**
+ * Run housekeeping now.
+ *
+ * Housekeeping also runs automatically after messages or chats are deleted,
+ * however, the UI may offer to free space on demand, eg. in the storage settings.
+ * While running, #DC_EVENT_HOUSEKEEPING_PROGRESS events are emitted.
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @param flags The steps to run, a combination of:
+ *     - DC_HOUSEKEEPING_BLOBS: delete files not referenced anymore
+ *     - DC_HOUSEKEEPING_MDNS: delete read receipts of deleted messages
+ *     - DC_HOUSEKEEPING_TOMBSTONES: delete leftovers of deleted messages
+ *     - DC_HOUSEKEEPING_VACUUM: rebuild the database to return unused space
+ *       to the file system, this may take a while.
+ * @return A JSON object with the keys `removed_blobs`, `freed_bytes` and `removed_rows`.
+ *     NULL on errors.
+ *     If not NULL, the returned string must be released using dc_str_unref().
+ */
+char*           dc_run_housekeeping          (dc_context_t* context, int flags);
+
+
 
 /**
  * Save a keypair as the default keys for the user.
@@ -627,6 +1039,44 @@ int             dc_preconfigure_keypair        (dc_context_t* context, const cha
 dc_chatlist_t*  dc_get_chatlist              (dc_context_t* context, int flags, const char* query_str, uint32_t query_id);
 
 
+/**
+ * Get a part of the chatlist.
+ *
+ * Returns the items of the list returned by dc_get_chatlist()
+ * starting at the index `offset`.
+ * Special chats as the deaddrop or the archive link are returned
+ * at the same positions as in the whole list,
+ * so UIs showing thousands of chats can load the list page by page
+ * and use dc_get_chatlist_cnt() to get the total number of items.
+ *
+ * @memberof dc_context_t
+ * @param context The context object as returned by dc_context_new()
+ * @param flags A combination of flags as for dc_get_chatlist().
+ * @param query_str An optional query for filtering the list as for dc_get_chatlist().
+ * @param query_id An optional contact ID for filtering the list as for dc_get_chatlist().
+ * @param offset Index of the first item to return.
+ * @param limit Maximum number of items to return, 0 for all remaining items.
+ * @return A chatlist as an dc_chatlist_t object,
+ *     on errors, NULL is returned.
+ *     Must be freed using dc_chatlist_unref() when no longer used.
+ */
+dc_chatlist_t*  dc_get_chatlist_page         (dc_context_t* context, int flags, const char* query_str, uint32_t query_id, int offset, int limit);
+
+
+/**
+ * Get the number of items in the chatlist without loading the list.
+ *
+ * @memberof dc_context_t
+ * @param context The context object as returned by dc_context_new()
+ * @param flags A combination of flags as for dc_get_chatlist().
+ * @param query_str An optional query for filtering the list as for dc_get_chatlist().
+ * @param query_id An optional contact ID for filtering the list as for dc_get_chatlist().
+ * @return The number of items dc_get_chatlist() would return, including special chats.
+ *     -1 on errors.
+ */
+int             dc_get_chatlist_cnt          (dc_context_t* context, int flags, const char* query_str, uint32_t query_id);
+
+
 // handle chats
 
 /**
@@ -806,6 +1256,27 @@ uint32_t        dc_send_msg_sync                  (dc_context_t* context, uint32
 uint32_t        dc_send_text_msg             (dc_context_t* context, uint32_t chat_id, const char* text_to_send);
 
 
+/**
+ * Tell the other members of a chat that the user is typing.
+ *
+ * The UI should call this function with typing=1 when the user starts typing
+ * and may call it again while the user keeps typing;
+ * the core sends at most one notification every few seconds.
+ * When the user stops typing without sending, the UI should call this function with typing=0.
+ *
+ * Typing notifications are only sent if the config option `typing_indicators` is enabled,
+ * if the chat is end-to-end encrypted and if there was recent activity in the chat.
+ * They are not stored in the database and do not appear as messages.
+ *
+ * @memberof dc_context_t
+ * @param context The context object as returned from dc_context_new().
+ * @param chat_id Chat ID to send the typing notification to.
+ * @param typing 1=the user is typing, 0=the user stopped typing.
+ * @return 1=a typing notification was queued for sending, 0=nothing was sent.
+ */
+int             dc_send_typing               (dc_context_t* context, uint32_t chat_id, int typing);
+
+
 /**
  * Save a draft for a chat in the database.
  *
@@ -820,6 +1291,11 @@ uint32_t        dc_send_text_msg             (dc_context_t* context, uint32_t ch
  *
  * Each chat can have its own draft but only one draft per chat is possible.
  *
+ * Drafts may contain an attachment set by dc_msg_set_file(),
+ * the file is copied to the blobdir immediately
+ * and deleted when the draft is discarded.
+ * Quotes and locations set by dc_msg_set_location() are kept as well.
+ *
  * If the draft is modified, an #DC_EVENT_MSGS_CHANGED will be sent.
  *
  * @memberof dc_context_t
@@ -828,8 +1304,6 @@ uint32_t        dc_send_text_msg             (dc_context_t* context, uint32_t ch
  * @param msg The message to save as a draft.
  *     Existing draft will be overwritten.
  *     NULL deletes the existing draft, if any, without sending it.
- *     Currently, also non-text-messages
- *     will delete the existing drafts.
  * @return None.
  */
 void            dc_set_draft                 (dc_context_t* context, uint32_t chat_id, dc_msg_t* msg);
@@ -956,6 +1430,62 @@ dc_msg_t*       dc_get_draft                 (dc_context_t* context, uint32_t ch
 dc_array_t*     dc_get_chat_msgs             (dc_context_t* context, uint32_t chat_id, uint32_t flags, uint32_t marker1before);
 
 
+/**
+ * Get a window of the message IDs of a chat.
+ *
+ * Other than dc_get_chat_msgs(), only `count` messages are loaded,
+ * so this is the way to go for chats with many messages.
+ * The list is sorted as by dc_get_chat_msgs() and starts with the oldest message.
+ * To load older messages, eg. when the user scrolls up,
+ * call this function again with the first message ID of the list as `before_msg_id`.
+ *
+ * @memberof dc_context_t
+ * @param context The context object as returned from dc_context_new().
+ * @param chat_id The chat ID of which the messages IDs should be queried.
+ *     Special chats as DC_CHAT_ID_STARRED are not supported.
+ * @param before_msg_id Return the messages before this message ID.
+ *     Set this to 0 to get the newest messages.
+ * @param count The maximum number of messages to return, markers are not counted.
+ * @param flags If set to DC_GCM_ADDDAYMARKER, the marker DC_MSG_ID_DAYMARKER will
+ *     be added before each day (regarding the local timezone).
+ *     The first message only gets a day marker if the message before the window is from another day.
+ * @return Array of message IDs, must be dc_array_unref()'d when no longer used.
+ *     On errors, an empty array is returned.
+ */
+dc_array_t*     dc_get_chat_msgs_page        (dc_context_t* context, uint32_t chat_id, uint32_t before_msg_id, int count, uint32_t flags);
+
+
+/**
+ * Get a window of the message IDs of a chat around the first unread message.
+ *
+ * This is typically used when opening a chat with fresh messages.
+ * The returned list contains up to `count` messages,
+ * the first fresh message is preceded by DC_MSG_ID_MARKER1.
+ * If there are no fresh messages, the newest messages are returned
+ * as by dc_get_chat_msgs_page().
+ *
+ * @memberof dc_context_t
+ * @param context The context object as returned from dc_context_new().
+ * @param chat_id The chat ID of which the messages IDs should be queried.
+ * @param count The maximum number of messages to return, markers are not counted.
+ * @param flags See dc_get_chat_msgs_page().
+ * @return Array of message IDs, must be dc_array_unref()'d when no longer used.
+ *     On errors, an empty array is returned.
+ */
+dc_array_t*     dc_get_chat_msgs_unread_page (dc_context_t* context, uint32_t chat_id, int count, uint32_t flags);
+
+
+/**
+ * Get the ID of the oldest fresh message of a chat.
+ *
+ * @memberof dc_context_t
+ * @param context The context object as returned from dc_context_new().
+ * @param chat_id The chat ID to get the first fresh message for.
+ * @return The message ID, 0 if there are no fresh messages or on errors.
+ */
+uint32_t        dc_get_first_unread_msg      (dc_context_t* context, uint32_t chat_id);
+
+
 /**
  * Get the total number of messages in a chat.
  *
@@ -998,6 +1528,7 @@ int             dc_estimate_deletion_cnt    (dc_context_t* context, int from_ser
 /**
  * Returns the message IDs of all _fresh_ messages of any chat.
  * Typically used for implementing notification summaries.
+ * Messages of muted chats are not returned.
  * The list is already sorted and starts with the most recent fresh message.
  *
  * @memberof dc_context_t
@@ -1149,6 +1680,9 @@ dc_array_t*     dc_get_chat_contacts         (dc_context_t* context, uint32_t ch
  * Searching can be done globally (chat_id=0) or in a specified chat only (chat_id
  * set).
  *
+ * Every word of the query has to match the beginning of a word
+ * in the message text, eg. "app" finds messages containing "apples".
+ *
  * Global chat results are typically displayed using dc_msg_get_summary(), chat
  * search results may just hilite the corresponding messages and present a
  * prev/next button.
@@ -1282,7 +1816,10 @@ int             dc_set_chat_name             (dc_context_t* context, uint32_t ch
  * If the group is already _promoted_ (any message was sent to the group),
  * all group members are informed by a special status message that is sent automatically by this function.
  *
- * Sends out #DC_EVENT_CHAT_MODIFIED and #DC_EVENT_MSGS_CHANGED if a status message was sent.
+ * Images larger than the avatar size are downscaled.
+ * If the image does not differ from the current group image, nothing is done.
+ *
+ * Sends out #DC_EVENT_CHAT_IMAGE_CHANGED and #DC_EVENT_MSGS_CHANGED if a status message was sent.
  *
  * To find out the profile image of a chat, use dc_chat_get_profile_image()
  *
@@ -1314,6 +1851,53 @@ int             dc_set_chat_profile_image    (dc_context_t* context, uint32_t ch
  */
 int             dc_set_chat_mute_duration             (dc_context_t* context, uint32_t chat_id, int64_t duration);
 
+
+/**
+ * Set the ephemeral timer of a chat.
+ *
+ * Messages sent to the chat afterwards are deleted locally and on the server
+ * when the timer expired; the timer of outgoing messages starts when they are sent,
+ * the timer of incoming messages when they are seen.
+ * The timer is sent to the other chat members and added to the chat as info message.
+ *
+ * Sends out #DC_EVENT_CHAT_MODIFIED.
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @param chat_id The chat ID to set the ephemeral timer for.
+ * @param timer The timer in seconds, 0 disables ephemeral messages.
+ * @return 1=success, 0=error
+ */
+int             dc_set_chat_ephemeral_timer  (dc_context_t* context, uint32_t chat_id, uint32_t timer);
+
+
+/**
+ * Get the ephemeral timer of a chat, see dc_set_chat_ephemeral_timer().
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @param chat_id The chat ID to get the ephemeral timer for.
+ * @return The timer in seconds, 0 if ephemeral messages are disabled or on errors.
+ */
+uint32_t        dc_get_chat_ephemeral_timer  (dc_context_t* context, uint32_t chat_id);
+
+
+/**
+ * Set whether read receipts are sent and requested in a chat.
+ *
+ * The policy overrides the global config option `mdns_enabled` for the chat.
+ *
+ * Sends out #DC_EVENT_CHAT_MODIFIED.
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @param chat_id The chat ID to set the policy for.
+ * @param policy One of DC_MDN_POLICY_GLOBAL (follow `mdns_enabled`),
+ *     DC_MDN_POLICY_ALWAYS or DC_MDN_POLICY_NEVER.
+ * @return 1=success, 0=error
+ */
+int             dc_set_chat_mdn_policy                (dc_context_t* context, uint32_t chat_id, int policy);
+
 // handle messages
 
 /**
@@ -1331,6 +1915,76 @@ int             dc_set_chat_mute_duration             (dc_context_t* context, ui
 char*           dc_get_msg_info              (dc_context_t* context, uint32_t msg_id);
 
 
+/**
+ * Get information about a single message as JSON.
+ *
+ * Contains the same information as dc_get_msg_info()
+ * as an object that does not need to be parsed, with the following fields:
+ *
+ * - `msg_id`, `from_id`: IDs of the message and of the sender
+ * - `from`: name and address of the sender
+ * - `timestamp_sent`, `timestamp_received`: unix timestamps in seconds,
+ *   `timestamp_received` is null for outgoing messages
+ * - `is_info`: true for device-internal info messages
+ * - `read_receipts`: array of objects with `contact_id`, `name` and `timestamp`
+ * - `recipient_states`: delivery state for each recipient of outgoing messages,
+ *   array of objects with `contact_id`, `state`, `timestamp` and `error`
+ * - `state`: one of `InFresh`, `InNoticed`, `InSeen`, `OutPreparing`, `OutDraft`,
+ *   `OutPending`, `OutFailed`, `OutDelivered` and `OutMdnRcvd`
+ * - `has_location`: true if the message contains a location
+ * - `encryption`: one of `Unencrypted`, `Encrypted`, `NoValidSignature` and `Erroneous`
+ * - `error`, `smtp_response`: error and last response of the server, or null
+ * - `file`, `file_bytes`: path and size of the attached file, path may be null
+ * - `viewtype`, `mimetype`, `width`, `height`, `duration`: see dc_msg_get_viewtype() etc.
+ * - `raw_text`: raw text of the message, truncated to about DC_MAX_GET_INFO_LEN characters
+ * - `rfc724_mid`: the Message-ID
+ * - `server_folder`, `server_uid`: where the message was last seen on the server,
+ *   folder may be null
+ *
+ * @memberof dc_context_t
+ * @param context The context object as created by dc_context_new().
+ * @param msg_id The message id for which information should be returned.
+ * @return JSON object, empty string on errors;
+ *     must be released using dc_str_unref() after usage.
+ */
+char*           dc_get_msg_info_json         (dc_context_t* context, uint32_t msg_id);
+
+
+/**
+ * Download the full message from the server.
+ * This is only needed for messages that were downloaded partially,
+ * see the `download_limit` config option and dc_msg_get_download_state().
+ *
+ * The download is done in the background, the message is updated in place
+ * and #DC_EVENT_MSGS_CHANGED is emitted when done.
+ * If the download fails, the download state is set to #DC_DOWNLOAD_FAILURE.
+ *
+ * @memberof dc_context_t
+ * @param context The context object as created by dc_context_new().
+ * @param msg_id The message ID to download.
+ * @return 1=download scheduled, 0=error, eg. the message is already downloaded.
+ */
+int             dc_download_full_msg         (dc_context_t* context, uint32_t msg_id);
+
+
+/**
+ * Respond to an invitation of type #DC_MSG_CALENDAR.
+ *
+ * The response is sent to the organizer of the event
+ * as iCalendar reply, together with a short text in the chat with the organizer.
+ * The response is remembered in the invitation, see dc_msg_get_invite_response().
+ * Invitations can be responded to multiple times, eg. to decline after accepting.
+ *
+ * @memberof dc_context_t
+ * @param context The context object as created by dc_context_new().
+ * @param msg_id The ID of the invitation.
+ * @param rsvp One of DC_RSVP_ACCEPT, DC_RSVP_DECLINE or DC_RSVP_TENTATIVE.
+ * @return The ID of the message carrying the response, 0 on errors,
+ *     eg. if the message is no invitation or has no organizer.
+ */
+uint32_t        dc_respond_to_invite         (dc_context_t* context, uint32_t msg_id, int rsvp);
+
+
 /**
  * Get the raw mime-headers of the given message.
  * Raw headers are saved for incoming messages
@@ -1345,20 +1999,69 @@ char*           dc_get_msg_info              (dc_context_t* context, uint32_t ms
  *     eg. because of save_mime_headers is not set
  *     or the message is not incoming.
  */
-char*           dc_get_mime_headers          (dc_context_t* context, uint32_t msg_id);
+char*           dc_get_mime_headers          (dc_context_t* context, uint32_t msg_id);
+
+
+/**
+ * Get the HTML body of the given message.
+ * The HTML is saved for incoming messages if the config option `save_html` is set,
+ * dc_msg_has_html() returns 1 then;
+ * scripts, styles, forms, event handlers and URLs with unsafe schemes are removed,
+ * however, the HTML may still reference remote content.
+ *
+ * @memberof dc_context_t
+ * @param context The context object as created by dc_context_new().
+ * @param msg_id The message id.
+ * @return HTML as a string, must be released using dc_str_unref() after usage.
+ *     Returns NULL if there is no HTML saved for the given message.
+ */
+char*           dc_get_msg_html              (dc_context_t* context, uint32_t msg_id);
+
+
+/**
+ * Delete messages. The messages are deleted on the current device and
+ * on the IMAP server.
+ *
+ * @memberof dc_context_t
+ * @param context The context object as created by dc_context_new()
+ * @param msg_ids an array of uint32_t containing all message IDs that should be deleted
+ * @param msg_cnt The number of messages IDs in the msg_ids array
+ * @return None.
+ */
+void            dc_delete_msgs               (dc_context_t* context, const uint32_t* msg_ids, int msg_cnt);
+
+
+/**
+ * Fetch older messages from the server.
+ * The time window set by the config option `sync_since` is extended
+ * to messages received since the given time,
+ * older messages are fetched in the background then.
+ *
+ * If the window already includes the given time, nothing happens.
+ *
+ * @memberof dc_context_t
+ * @param context The context object as created by dc_context_new()
+ * @param timestamp Unix timestamp; messages received since this time are fetched.
+ * @return 1=success, 0=error
+ */
+int             dc_extend_sync_window        (dc_context_t* context, int64_t timestamp);
 
 
 /**
- * Delete messages. The messages are deleted on the current device and
- * on the IMAP server.
+ * Report messages as spam.
+ * The messages are moved to the spam folder on the IMAP server
+ * and their senders are blocked.
+ *
+ * Messages of known contacts that the provider moved to the spam folder
+ * are moved back automatically if the config option `spam_watch` is enabled.
  *
  * @memberof dc_context_t
  * @param context The context object as created by dc_context_new()
- * @param msg_ids an array of uint32_t containing all message IDs that should be deleted
+ * @param msg_ids an array of uint32_t containing all message IDs that should be reported
  * @param msg_cnt The number of messages IDs in the msg_ids array
- * @return None.
+ * @return 1=success, 0=error
  */
-void            dc_delete_msgs               (dc_context_t* context, const uint32_t* msg_ids, int msg_cnt);
+int             dc_report_spam               (dc_context_t* context, const uint32_t* msg_ids, int msg_cnt);
 
 /*
  * Empty IMAP server folder: delete all messages.
@@ -1385,6 +2088,31 @@ void            dc_empty_server              (dc_context_t* context, uint32_t fl
 void            dc_forward_msgs              (dc_context_t* context, const uint32_t* msg_ids, int msg_cnt, uint32_t chat_id);
 
 
+/**
+ * Forward messages to another chat as one batch.
+ *
+ * The messages are forwarded in the order they were sent,
+ * independent of the order in msg_ids.
+ * Attachments are not copied, the forwarded messages use the same files.
+ *
+ * By default, the original authors are not revealed, see dc_msg_is_forwarded().
+ * If `attribute` is set, the names of the original authors are sent along
+ * and can be shown by the recipients using dc_msg_get_forwarded_from().
+ *
+ * @memberof dc_context_t
+ * @param context The context object as created by dc_context_new()
+ * @param msg_ids An array of uint32_t containing all message IDs that should be forwarded
+ * @param msg_cnt The number of messages IDs in the msg_ids array
+ * @param chat_id The destination chat ID.
+ * @param attribute 1=send the names of the original authors along, 0=do not name the authors.
+ * @return Array of the IDs of the new messages in the order they were forwarded,
+ *     eg. to scroll to the first one.
+ *     Must be freed using dc_array_unref() when done.
+ *     NULL on errors.
+ */
+dc_array_t*     dc_forward_msgs_ex           (dc_context_t* context, const uint32_t* msg_ids, int msg_cnt, uint32_t chat_id, int attribute);
+
+
 /**
  * Mark all messages sent by the given contact
  * as _noticed_.  See also dc_marknoticed_chat() and
@@ -1420,6 +2148,9 @@ void            dc_markseen_msgs             (dc_context_t* context, const uint3
  * Starred messages are collected in a virtual chat that can be shown using
  * dc_get_chat_msgs() using the chat_id DC_CHAT_ID_STARRED.
  *
+ * The star is synced with the `\Flagged` flag on the IMAP server,
+ * so messages flagged in other mail clients are shown as starred, too.
+ *
  * @memberof dc_context_t
  * @param context The context object as created by dc_context_new()
  * @param msg_ids An array of uint32_t message IDs defining the messages to star or unstar
@@ -1506,6 +2237,12 @@ uint32_t        dc_create_contact            (dc_context_t* context, const char*
 #define         DC_GCL_ADD_SELF              0x02
 
 
+#define         DC_HOUSEKEEPING_BLOBS        0x01
+#define         DC_HOUSEKEEPING_MDNS         0x02
+#define         DC_HOUSEKEEPING_TOMBSTONES   0x04
+#define         DC_HOUSEKEEPING_VACUUM       0x08
+
+
 /**
  * Add a number of contacts.
  *
@@ -1572,6 +2309,38 @@ int             dc_get_blocked_cnt           (dc_context_t* context);
 dc_array_t*     dc_get_blocked_contacts      (dc_context_t* context);
 
 
+/**
+ * Get verified contacts.
+ *
+ * To check how a contact was verified, use dc_contact_get_verification_method().
+ *
+ * @memberof dc_context_t
+ * @param context The context object as created by dc_context_new().
+ * @return An array containing all verified contact IDs, SELF is not included.
+ *     Must be dc_array_unref()'d after usage.
+ */
+dc_array_t*     dc_get_verified_contacts     (dc_context_t* context);
+
+
+/**
+ * Revoke the verification of a contact.
+ *
+ * The verification is removed, the contact is no longer regarded as verified,
+ * eg. it cannot be added to verified groups.
+ * The contact can be verified again by a QR code scan
+ * or by an introduction in a verified group.
+ * The revocation is recorded in the verification history of the contact.
+ *
+ * Sends #DC_EVENT_CONTACTS_CHANGED if the contact was verified before.
+ *
+ * @memberof dc_context_t
+ * @param context The context object as created by dc_context_new().
+ * @param contact_id The ID of the contact to revoke the verification of.
+ * @return 1=the verification was revoked, 0=the contact was not verified or on errors.
+ */
+int             dc_revoke_verification       (dc_context_t* context, uint32_t contact_id);
+
+
 /**
  * Block or unblock a contact.
  * May result in a #DC_EVENT_CONTACTS_CHANGED event.
@@ -1634,6 +2403,7 @@ dc_contact_t*   dc_get_contact               (dc_context_t* context, uint32_t co
 #define         DC_IMEX_IMPORT_SELF_KEYS      2 // param1 is a directory where the keys are searched in and read from
 #define         DC_IMEX_EXPORT_BACKUP        11 // param1 is a directory where the backup is written to
 #define         DC_IMEX_IMPORT_BACKUP        12 // param1 is the file with the backup to import
+#define         DC_IMEX_EXPORT_INCREMENTAL_BACKUP 13 // param1 is a directory where the backup is written to
 
 
 /**
@@ -1649,6 +2419,13 @@ dc_contact_t*   dc_get_contact               (dc_context_t* context, uint32_t co
  * - **DC_IMEX_IMPORT_BACKUP** (12) - `param1` is the file (not: directory) to import. The file is normally
  *   created by DC_IMEX_EXPORT_BACKUP and detected by dc_imex_has_backup(). Importing a backup
  *   is only possible as long as the context is not configured or used in another way.
+ *   If the file is an incremental backup, the backups it is based on have to be in the same directory.
+ *
+ * - **DC_IMEX_EXPORT_INCREMENTAL_BACKUP** (13) - Export an incremental backup to the directory given as `param1`.
+ *   Only files added or changed since the last backup are included
+ *   if the last backup is still in the directory, otherwise a full backup is created.
+ *   The database is always included completely.
+ *   Incremental backups are imported using DC_IMEX_IMPORT_BACKUP.
  *
  * - **DC_IMEX_EXPORT_SELF_KEYS** (1) - Export all private keys and all public keys of the user to the
  *   directory given as `param1`.  The default key is written to the files `public-key-default.asc`
@@ -1804,6 +2581,125 @@ char*           dc_initiate_key_transfer     (dc_context_t* context);
 int             dc_continue_key_transfer     (dc_context_t* context, uint32_t msg_id, const char* setup_code);
 
 
+/**
+ * Generate a new key and use it as the default key.
+ *
+ * The new key is sent in the Autocrypt header of all messages sent afterwards,
+ * so that contacts pick it up and use it for encryption.
+ * The previous keys are kept and are still used to decrypt messages encrypted to them.
+ * Contacts that have verified the previous key have to verify the new key again.
+ *
+ * Key generation may take some time, so this function should not be called from the UI thread.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @return The fingerprint of the new key as hex string, NULL on errors.
+ *     If set, the string must be released using dc_str_unref() after usage.
+ */
+char*           dc_rotate_key                (dc_context_t* context);
+
+
+/**
+ * Use one of the own keys as the default key again,
+ * eg. to undo dc_rotate_key().
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param fingerprint Fingerprint of the key as returned by dc_get_keys_json(),
+ *     spaces and other separators are ignored.
+ * @return 1=success, 0=error, eg. there is no own key with the given fingerprint.
+ */
+int             dc_set_default_key           (dc_context_t* context, const char* fingerprint);
+
+
+/**
+ * Get the own keys in json format.
+ *
+ * The returned json string contains an array with the default key first,
+ * followed by the previous keys, newest first.
+ * Each key is an object with the following key/values:
+ * - `fingerprint`: the fingerprint of the key as hex string
+ * - `addr`: the address the key was created for
+ * - `created`: unix timestamp of when the key was created or imported
+ * - `is_default`: true for the key used for new messages
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @return a utf8-encoded json string. Must be freed using dc_str_unref().  NULL is never returned.
+ */
+char*           dc_get_keys_json             (dc_context_t* context);
+
+
+/**
+ * Upload the own public key to the keyserver configured by the `keyserver` option,
+ * see dc_set_config().  Call this again to refresh the key on the keyserver,
+ * eg. after dc_rotate_key().
+ *
+ * keys.openpgp.org, the default keyserver,
+ * sends an email to the own address before the address is published along with the key.
+ * Other users can then find the key if they enabled `keyserver_lookup`.
+ *
+ * This function makes a network request and should not be called from the UI thread.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @return 1=success, 0=error, eg. there is no network.
+ */
+int             dc_publish_key               (dc_context_t* context);
+
+
+/**
+ * Offer the account to a second device.
+ *
+ * The returned QR code should be shown to the user
+ * and scanned on the second device, which calls dc_receive_from_second_device() then.
+ * Both devices have to be in the same network.
+ *
+ * The function returns immediately, the transfer is done in the background
+ * as an ongoing process, the progress is reported by #DC_EVENT_IMEX_PROGRESS:
+ * 1000 when the second device has received the account, 0 on errors.
+ * If no second device connects within 10 minutes, the transfer is canceled;
+ * to cancel it before, call dc_stop_ongoing_process().
+ * Meanwhile, no other ongoing process can be started.
+ *
+ * The data are encrypted with a one-time secret contained in the QR code.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param include_backup 1=transfer a backup with all chats and contacts,
+ *     IO must be stopped using dc_stop_io() then,
+ *     0=transfer only the login credentials and the secret key,
+ *     the second device has to call dc_configure() then.
+ * @return The QR code to be scanned by the second device,
+ *     NULL on errors, eg. if the account is not configured
+ *     or if a backup should be transferred while IO is running.
+ *     Must be released using dc_str_unref() after usage.
+ */
+char*           dc_provide_second_device     (dc_context_t* context, int include_backup);
+
+
+/**
+ * Receive an account from another device.
+ *
+ * The QR code is created on the other device using dc_provide_second_device(),
+ * dc_check_qr() returns DC_QR_SECOND_DEVICE for these QR codes.
+ * The account of this context must not be configured.
+ *
+ * The function blocks until the account is received,
+ * the progress is reported by #DC_EVENT_IMEX_PROGRESS.
+ * If a backup was transferred, it is imported and the account is ready to use;
+ * if the database of the other device is encrypted,
+ * the same passphrase has to be used on this device.
+ * If only the credentials were transferred, call dc_configure() afterwards.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param qr The scanned QR code.
+ * @return 1=the account was received, 0=errors.
+ */
+int             dc_receive_from_second_device (dc_context_t* context, const char* qr);
+
+
 /**
  * Signal an ongoing process to stop.
  *
@@ -1832,10 +2728,12 @@ void            dc_stop_ongoing_process      (dc_context_t* context);
 #define         DC_QR_FPR_MISMATCH           220 // id=contact
 #define         DC_QR_FPR_WITHOUT_ADDR       230 // test1=formatted fingerprint
 #define         DC_QR_ACCOUNT                250 // text1=domain
+#define         DC_QR_SECOND_DEVICE          260 // text1=address of the providing device
 #define         DC_QR_ADDR                   320 // id=contact
 #define         DC_QR_TEXT                   330 // text1=text
 #define         DC_QR_URL                    332 // text1=URL
 #define         DC_QR_ERROR                  400 // text1=error string
+#define         DC_QR_LOGIN                  520 // text1=email address
 
 /**
  * Check a scanned QR code.
@@ -1850,10 +2748,14 @@ void            dc_stop_ongoing_process      (dc_context_t* context);
  * - DC_QR_FPR_MISMATCH with dc_lot_t::id=Contact ID
  * - DC_QR_FPR_WITHOUT_ADDR with dc_lot_t::test1=Formatted fingerprint
  * - DC_QR_ACCOUNT allows creation of an account, dc_lot_t::text1=domain
+ * - DC_QR_SECOND_DEVICE allows receiving an account from another device
+ *   using dc_receive_from_second_device(), dc_lot_t::text1=address of the other device
  * - DC_QR_ADDR with dc_lot_t::id=Contact ID
  * - DC_QR_TEXT with dc_lot_t::text1=Text
  * - DC_QR_URL with dc_lot_t::text1=URL
  * - DC_QR_ERROR with dc_lot_t::text1=Error string
+ * - DC_QR_LOGIN allows login to an existing account using dc_set_config_from_qr()
+ *   or dc_configure_from_qr(), dc_lot_t::text1=email address
  *
  * @memberof dc_context_t
  * @param context The context object.
@@ -2058,6 +2960,135 @@ int         dc_set_location                 (dc_context_t* context, double latit
 dc_array_t* dc_get_locations                (dc_context_t* context, uint32_t chat_id, uint32_t contact_id, int64_t timestamp_begin, int64_t timestamp_end);
 
 
+/**
+ * Get the path of a contact as JSON, eg. to draw it on a map.
+ *
+ * Other than dc_get_locations(), only positions of the contact are returned,
+ * sorted ascending by time, and locations received in several chats are returned only once.
+ * The result is a JSON array of objects with the fields
+ * `latitude`, `longitude`, `accuracy`, `timestamp`, `msg_id` (0 if there is no message bound to the location)
+ * and `speed`, the speed since the previous point in meters per second, null for the first point.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id Chat-id to get the path for, 0 to get the path independently of the chat.
+ * @param contact_id Contact-id to get the path for, must not be 0.
+ * @param timestamp_begin Start of timespan to return, 0 for "start from the beginning".
+ * @param timestamp_end End of timespan to return, 0 for "all up to now".
+ * @return JSON array, an empty array on errors.
+ *     The returned string must be released using dc_str_unref().
+ */
+char*       dc_get_location_path_json       (dc_context_t* context, uint32_t chat_id, uint32_t contact_id, int64_t timestamp_begin, int64_t timestamp_end);
+
+
+/**
+ * Get the last known position of every member of a chat sharing locations.
+ *
+ * The array contains one location per contact, the newest first,
+ * use the dc_array_get_*() functions as for dc_get_locations() to get the details.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id Chat-id to get the positions for.
+ * @return Array of locations, must be freed using dc_array_unref().
+ */
+dc_array_t* dc_get_current_positions        (dc_context_t* context, uint32_t chat_id);
+
+
+/**
+ * Get the locations inside a rectangular area, eg. the viewport of the map.
+ *
+ * Works as dc_get_locations() but only returns locations
+ * between the given latitudes and longitudes.
+ * The area may cross the antimeridian, in this case `west` is greater than `east`.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id Chat-id to get location information for, 0 to get locations independently of the chat.
+ * @param contact_id Contact-id to get location information for, 0 to get locations independently of the contact.
+ * @param south Southern border of the area.
+ * @param west Western border of the area.
+ * @param north Northern border of the area.
+ * @param east Eastern border of the area.
+ * @param timestamp_begin Start of timespan to return, 0 for "start from the beginning".
+ * @param timestamp_end End of timespan to return, 0 for "all up to now".
+ * @return Array of locations, must be freed using dc_array_unref().
+ */
+dc_array_t* dc_get_locations_in_box         (dc_context_t* context, uint32_t chat_id, uint32_t contact_id, double south, double west, double north, double east, int64_t timestamp_begin, int64_t timestamp_end);
+
+
+/**
+ * Get the locations inside a rectangular area clustered for a zoom level.
+ *
+ * Lets map frontends show many locations without loading all of them,
+ * nearby locations are merged into one cluster on a grid of a quarter map tile
+ * of the given zoom level, with zoom level 0 showing the whole world on one tile.
+ *
+ * The result is a JSON array of objects with the fields
+ * `latitude` and `longitude`, the center of the cluster,
+ * `count`, the number of clustered locations,
+ * `location_id`, the id of the location for clusters of a single location, 0 otherwise,
+ * and `timestamp`, the timestamp of the newest clustered location.
+ * The biggest clusters come first.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id Chat-id to get the clusters for, 0 for all chats.
+ * @param south Southern border of the area.
+ * @param west Western border of the area.
+ * @param north Northern border of the area.
+ * @param east Eastern border of the area.
+ * @param zoom Zoom level, 0 to 20.
+ * @param timestamp_begin Start of timespan to return, 0 for "start from the beginning".
+ * @param timestamp_end End of timespan to return, 0 for "all up to now".
+ * @return JSON array, an empty array on errors.
+ *     The returned string must be released using dc_str_unref().
+ */
+char*       dc_get_location_clusters_json   (dc_context_t* context, uint32_t chat_id, double south, double west, double north, double east, int zoom, int64_t timestamp_begin, int64_t timestamp_end);
+
+
+/**
+ * Get the named points of interest sent to a chat, the newest first.
+ *
+ * Points of interest are created by dc_msg_set_poi(),
+ * other than dc_get_locations(), live locations are not returned.
+ * Use dc_array_get_poi_label(), dc_array_get_poi_radius() and dc_array_get_poi_color()
+ * in addition to the other dc_array_get_*() functions to get the details.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id Chat-id to get the points of interest for.
+ * @return Array of locations, must be freed using dc_array_unref().
+ */
+dc_array_t* dc_get_pois                     (dc_context_t* context, uint32_t chat_id);
+
+
+#define DC_LOCATION_EXPORT_GPX 1
+#define DC_LOCATION_EXPORT_KML 2
+
+
+/**
+ * Export shared locations as GPX or KML file,
+ * eg. to archive them or to view them in other apps.
+ *
+ * The locations are selected as by dc_get_locations().
+ * Positions are exported as one track per contact,
+ * locations marked by the user as waypoints.
+ * Each point contains the timestamp, the accuracy and the ID of the linked message, if any.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id Chat-id to export the locations for, 0 for all chats.
+ * @param contact_id Contact-id to export the locations for, 0 for all contacts.
+ * @param timestamp_begin Start of timespan to export, 0 for "start from the beginning".
+ * @param timestamp_end End of timespan to export, 0 for "all up to now".
+ * @param format DC_LOCATION_EXPORT_GPX or DC_LOCATION_EXPORT_KML.
+ * @return The file content as a string, NULL on errors.
+ *     The returned string must be released using dc_str_unref().
+ */
+char*       dc_export_locations             (dc_context_t* context, uint32_t chat_id, uint32_t contact_id, int64_t timestamp_begin, int64_t timestamp_end, int format);
+
+
 /**
  * Delete all locations on the current device.
  * Locations already sent cannot be deleted.
@@ -2232,6 +3263,42 @@ uint32_t         dc_array_get_msg_id         (const dc_array_t* array, size_t in
 char*            dc_array_get_marker         (const dc_array_t* array, size_t index);
 
 
+/**
+ * Return the label of the point of interest at the given index,
+ * see dc_get_pois().
+ *
+ * @memberof dc_array_t
+ * @param array The array object.
+ * @param index Index of the item. Must be between 0 and dc_array_get_cnt()-1.
+ * @return Label of the point of interest.
+ *     NULL if the item is no point of interest.
+ *     The returned value must be released using dc_str_unref() after usage.
+ */
+char*            dc_array_get_poi_label      (const dc_array_t* array, size_t index);
+
+
+/**
+ * Return the radius of the point of interest at the given index.
+ *
+ * @memberof dc_array_t
+ * @param array The array object.
+ * @param index Index of the item. Must be between 0 and dc_array_get_cnt()-1.
+ * @return Radius in meters, 0 if no radius is set.
+ */
+double           dc_array_get_poi_radius     (const dc_array_t* array, size_t index);
+
+
+/**
+ * Return the marker color of the point of interest at the given index.
+ *
+ * @memberof dc_array_t
+ * @param array The array object.
+ * @param index Index of the item. Must be between 0 and dc_array_get_cnt()-1.
+ * @return Color as 0xRRGGBB, -1 if no color is set.
+ */
+int              dc_array_get_poi_color      (const dc_array_t* array, size_t index);
+
+
 /**
  * Return the independent-state of the location at the given index.
  * Independent locations do not belong to the track of the user.
@@ -2437,6 +3504,8 @@ char*            dc_chat_get_info_json       (dc_context_t* context, size_t chat
 #define         DC_CHAT_TYPE_SINGLE          100
 #define         DC_CHAT_TYPE_GROUP           120
 #define         DC_CHAT_TYPE_VERIFIED_GROUP  130
+#define         DC_CHAT_TYPE_MAILINGLIST     140
+#define         DC_CHAT_TYPE_BROADCAST       160
 
 
 /**
@@ -2482,6 +3551,16 @@ uint32_t        dc_chat_get_id               (const dc_chat_t* chat);
  * - DC_CHAT_TYPE_VERIFIED_GROUP  (130) - a verified group chat. In verified groups,
  *   all members are verified and encryption is always active and cannot be disabled.
  *
+ * - DC_CHAT_TYPE_MAILINGLIST  (140) - a mailing list, identified by the List-Id header.
+ *   chats_contacts is empty, the sender of every message is shown.
+ *   Messages can only be sent if the list has a posting address,
+ *   see dc_chat_can_send().
+ *
+ * - DC_CHAT_TYPE_BROADCAST  (160) - a broadcast list, chats_contacts contain all
+ *   recipients but not DC_CONTACT_ID_SELF.  Messages are sent to all recipients
+ *   without revealing the other recipients, the recipients see the messages
+ *   in the one-to-one chat with the sender and replies arrive there as well.
+ *
  * @memberof dc_chat_t
  * @param chat The chat object.
  * @return Chat type.
@@ -2645,6 +3724,16 @@ int             dc_chat_is_sending_locations (const dc_chat_t* chat);
 int             dc_chat_is_muted (const dc_chat_t* chat);
 
 
+/**
+ * Get the read receipt policy of the chat (can be changed by dc_set_chat_mdn_policy()).
+ *
+ * @memberof dc_chat_t
+ * @param chat The chat object.
+ * @return One of DC_MDN_POLICY_GLOBAL, DC_MDN_POLICY_ALWAYS or DC_MDN_POLICY_NEVER.
+ */
+int             dc_chat_get_mdn_policy (const dc_chat_t* chat);
+
+
 /**
  * Get the exact state of the mute of a chat
  *
@@ -2680,6 +3769,22 @@ int64_t          dc_chat_get_remaining_mute_duration (const dc_chat_t* chat);
 #define         DC_STATE_OUT_DELIVERED       26 // to check if a mail was sent, use dc_msg_is_sent()
 #define         DC_STATE_OUT_MDN_RCVD        28
 
+#define         DC_SEND_ERROR_OTHER              0
+#define         DC_SEND_ERROR_SMTP_REJECTED      1
+#define         DC_SEND_ERROR_RECIPIENT_REFUSED  2
+#define         DC_SEND_ERROR_MESSAGE_TOO_LARGE  3
+#define         DC_SEND_ERROR_RATE_LIMITED       4
+#define         DC_SEND_ERROR_ENCRYPTION_FAILED  5
+#define         DC_SEND_ERROR_RENDERING_FAILED   6
+
+#define         DC_SMIME_SIGNED                  1
+#define         DC_SMIME_ENCRYPTED               2
+
+#define         DC_DOWNLOAD_DONE             0
+#define         DC_DOWNLOAD_AVAILABLE        10
+#define         DC_DOWNLOAD_FAILURE          20
+#define         DC_DOWNLOAD_IN_PROGRESS      1000
+
 
 #define         DC_MAX_GET_TEXT_LEN          30000 // approx. max. length returned by dc_msg_get_text()
 #define         DC_MAX_GET_INFO_LEN          100000 // approx. max. length returned by dc_get_msg_info()
@@ -2796,6 +3901,44 @@ int             dc_msg_get_viewtype           (const dc_msg_t* msg);
 int             dc_msg_get_state              (const dc_msg_t* msg);
 
 
+/**
+ * Get the reason why sending a message failed.
+ *
+ * Use this function to react on failures programmatically,
+ * eg. to offer removing a recipient or to suggest sending a smaller file;
+ * a description for the user is contained in dc_get_msg_info().
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return -1 if the message is not in the state DC_STATE_OUT_FAILED, otherwise one of:
+ *     - DC_SEND_ERROR_SMTP_REJECTED (1) - The server rejected the message, eg. as spam or by policy.
+ *     - DC_SEND_ERROR_RECIPIENT_REFUSED (2) - The server refused a recipient, eg. as the address does not exist.
+ *     - DC_SEND_ERROR_MESSAGE_TOO_LARGE (3) - The message exceeds the size limit of the server.
+ *     - DC_SEND_ERROR_RATE_LIMITED (4) - The server refuses to send more messages for now.
+ *     - DC_SEND_ERROR_ENCRYPTION_FAILED (5) - The message could not be end-to-end encrypted though this was required.
+ *     - DC_SEND_ERROR_RENDERING_FAILED (6) - The message could not be built, eg. as the attachment is missing.
+ *     - DC_SEND_ERROR_OTHER (0) - Any other error.
+ */
+int             dc_msg_get_send_error         (const dc_msg_t* msg);
+
+
+/**
+ * Get the time at which sending a message is tried again.
+ *
+ * Sending a message that failed temporarily, eg. because of network problems,
+ * is retried with increasing delays.
+ * UIs may use this function to show eg. "retrying in 2 minutes" for messages in the state #DC_STATE_OUT_PENDING.
+ * Use dc_get_msg() to get an updated message object after #DC_EVENT_MSGS_CHANGED.
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return The time of the next try as a unix timestamp in seconds.
+ *     0 if the message is not waiting for a retry,
+ *     eg. if it was not tried yet, was sent or failed permanently.
+ */
+int64_t         dc_msg_get_next_retry_timestamp (const dc_msg_t* msg);
+
+
 /**
  * Get message sending time.
  * The sending time is returned as a unix timestamp in seconds.
@@ -2845,6 +3988,27 @@ int64_t          dc_msg_get_received_timestamp (const dc_msg_t* msg);
 int64_t          dc_msg_get_sort_timestamp     (const dc_msg_t* msg);
 
 
+/**
+ * Get the ephemeral timer of the message, see dc_set_chat_ephemeral_timer().
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return The timer in seconds, 0 if the message is not ephemeral.
+ */
+uint32_t         dc_msg_get_ephemeral_timer    (const dc_msg_t* msg);
+
+
+/**
+ * Get the time the ephemeral message is deleted at.
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return Time of deletion, 0 if the timer has not started yet
+ *     or the message is not ephemeral.
+ */
+int64_t          dc_msg_get_ephemeral_timestamp (const dc_msg_t* msg);
+
+
 /**
  * Get the text of the message.
  * If there is no text associated with the message, an empty string is returned.
@@ -2874,6 +4038,9 @@ char*           dc_msg_get_text               (const dc_msg_t* msg);
  * Typically files are associated with images, videos, audios, documents.
  * Plain text messages do not have a file.
  *
+ * If attachments are encrypted at rest,
+ * the path of a temporary decrypted copy is returned.
+ *
  * @memberof dc_msg_t
  * @param msg The message object.
  * @return Full path, file name and extension of the file associated with the message.
@@ -2883,30 +4050,72 @@ char*           dc_msg_get_text               (const dc_msg_t* msg);
 char*           dc_msg_get_file               (const dc_msg_t* msg);
 
 
+/**
+ * Get the poster frame of a video message.
+ * The UI can show this image as a preview before the video is played.
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return Full path of the thumbnail image.
+ *     If the message has no thumbnail, an empty string is returned.
+ *     NULL is never returned and the returned value must be released using dc_str_unref().
+ */
+char*           dc_msg_get_thumbnail          (const dc_msg_t* msg);
+
+
 /**
  * Get base file name without path. The base file name includes the extension; the path
  * is not returned. To get the full path, use dc_msg_get_file().
  *
  * @memberof dc_msg_t
  * @param msg The message object.
- * @return Base file name plus extension without part.  If there is no file
- *     associated with the message, an empty string is returned.  The returned
- *     value must be released using dc_str_unref().
+ * @return Base file name plus extension without part.  If there is no file
+ *     associated with the message, an empty string is returned.  The returned
+ *     value must be released using dc_str_unref().
+ */
+char*           dc_msg_get_filename           (const dc_msg_t* msg);
+
+
+/**
+ * Get mime type of the file.  If there is not file, an empty string is returned.
+ * If there is no associated mime type with the file, the function guesses on; if
+ * in doubt, `application/octet-stream` is returned. NULL is never returned.
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return String containing the mime type.
+ *     Must be released using dc_str_unref() after usage. NULL is never returned.
+ */
+char*           dc_msg_get_filemime           (const dc_msg_t* msg);
+
+
+/**
+ * Get the calendar event of a message of type #DC_MSG_CALENDAR.
+ *
+ * The event is returned as JSON object with the fields
+ * `method` (eg. `REQUEST` for invitations, `CANCEL` for cancellations),
+ * `uid`, `sequence`, `summary` (the title), `description`, `location`,
+ * `start` and `end` as UTC timestamps, `all_day`,
+ * `organizer` (the address of the organizer) and `organizer_name`.
+ * `description`, `location`, `end`, `organizer` and `organizer_name` may be null.
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return JSON object, NULL if the message contains no event.
+ *     Must be released using dc_str_unref() after usage.
  */
-char*           dc_msg_get_filename           (const dc_msg_t* msg);
+char*           dc_msg_get_calendar_event_json (const dc_msg_t* msg);
 
 
 /**
- * Get mime type of the file.  If there is not file, an empty string is returned.
- * If there is no associated mime type with the file, the function guesses on; if
- * in doubt, `application/octet-stream` is returned. NULL is never returned.
+ * Get the response sent to an invitation using dc_respond_to_invite().
  *
  * @memberof dc_msg_t
  * @param msg The message object.
- * @return String containing the mime type.
- *     Must be released using dc_str_unref() after usage. NULL is never returned.
+ * @return DC_RSVP_ACCEPT, DC_RSVP_DECLINE, DC_RSVP_TENTATIVE
+ *     or 0 if the invitation was not responded to yet.
  */
-char*           dc_msg_get_filemime           (const dc_msg_t* msg);
+int             dc_msg_get_invite_response    (const dc_msg_t* msg);
 
 
 /**
@@ -2980,6 +4189,22 @@ int             dc_msg_get_duration           (const dc_msg_t* msg);
 int             dc_msg_get_showpadlock        (const dc_msg_t* msg);
 
 
+/**
+ * Check if the message was signed or encrypted using S/MIME.
+ * S/MIME is used for messages that are not end-to-end encrypted with OpenPGP
+ * if an S/MIME certificate is configured, see the `smime_certificate` option of dc_set_config().
+ * The core must be built with the `smime` feature to decrypt messages and to verify signatures.
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return A combination of the following flags, 0 if S/MIME was not used:
+ *     - DC_SMIME_SIGNED (1) - The message has a valid signature by the sender
+ *       or was signed by ourself.
+ *     - DC_SMIME_ENCRYPTED (2) - The message was encrypted.
+ */
+int             dc_msg_get_smime_state        (const dc_msg_t* msg);
+
+
 /**
  * Get a summary for a message.
  *
@@ -3036,6 +4261,18 @@ char*           dc_msg_get_summarytext        (const dc_msg_t* msg, int approx_c
 int             dc_msg_has_deviating_timestamp(const dc_msg_t* msg);
 
 
+/**
+ * Check if the HTML body of a message is available.
+ * The UI may then offer to show the full message
+ * using the HTML returned by dc_get_msg_html().
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return 1=HTML body is available, 0=Only the text of the message is available.
+ */
+int             dc_msg_has_html               (const dc_msg_t* msg);
+
+
 /**
  * Check if a message has a location bound to it.
  * These messages are also returned by dc_get_locations()
@@ -3076,6 +4313,23 @@ int             dc_msg_is_sent                (const dc_msg_t* msg);
 int             dc_msg_is_starred             (const dc_msg_t* msg);
 
 
+/**
+ * Get the download state of a message.
+ *
+ * - #DC_DOWNLOAD_DONE: The message is downloaded completely.
+ * - #DC_DOWNLOAD_AVAILABLE: Only a placeholder is shown,
+ *   the full message can be downloaded using dc_download_full_msg().
+ * - #DC_DOWNLOAD_IN_PROGRESS: The full message is being downloaded.
+ * - #DC_DOWNLOAD_FAILURE: Downloading failed, the download may be retried
+ *   using dc_download_full_msg().
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return One of the DC_DOWNLOAD_* constants.
+ */
+int             dc_msg_get_download_state     (const dc_msg_t* msg);
+
+
 /**
  * Check if the message is a forwarded message.
  *
@@ -3087,6 +4341,7 @@ int             dc_msg_is_starred             (const dc_msg_t* msg);
  * original author (in a typical GUI, you select the messages text and click on
  * "forwared"; you won't expect other data to be send to the new recipient,
  * esp. as the new recipient may not be in any relationship to the original author)
+ * unless the forwarding user explicitly chose so, see dc_forward_msgs_ex().
  *
  * @memberof dc_msg_t
  * @param msg The message object.
@@ -3095,6 +4350,22 @@ int             dc_msg_is_starred             (const dc_msg_t* msg);
 int             dc_msg_is_forwarded           (const dc_msg_t* msg);
 
 
+/**
+ * Get the name of the original author of a forwarded message.
+ *
+ * The name is only available if the forwarding user chose to attribute
+ * the message, see dc_forward_msgs_ex().
+ * The UI may show it eg. as "Forwarded from <name>".
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return The name of the original author,
+ *     NULL if the message is not forwarded or the author is not named.
+ *     Must be released using dc_str_unref() after usage.
+ */
+char*           dc_msg_get_forwarded_from     (const dc_msg_t* msg);
+
+
 /**
  * Check if the message is an informational message, created by the
  * device or by another users. Such messages are not "typed" by the user but
@@ -3205,6 +4476,21 @@ void            dc_msg_set_file               (dc_msg_t* msg, const char* file,
 void            dc_msg_set_dimension          (dc_msg_t* msg, int width, int height);
 
 
+/**
+ * Set a poster frame for a message of type #DC_MSG_VIDEO.
+ * The core cannot extract frames from videos,
+ * so the UI should create the image, typically a JPEG file.
+ * The thumbnail is sent together with the video
+ * and returned by dc_msg_get_thumbnail() on both sides.
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @param file Full path of the image file.
+ * @return None.
+ */
+void            dc_msg_set_thumbnail          (dc_msg_t* msg, const char* file);
+
+
 /**
  * Set the duration associated with message object.
  * Typically this is the duration of an audio or video associated using dc_msg_set_file().
@@ -3238,6 +4524,25 @@ void            dc_msg_set_duration           (dc_msg_t* msg, int duration);
 void            dc_msg_set_location           (dc_msg_t* msg, double latitude, double longitude);
 
 
+/**
+ * Bind a named point of interest to the message.
+ *
+ * Works as dc_msg_set_location(), however, the location is shown
+ * with a label and, optionally, as an area with a radius and in a given color.
+ * The points of interest of a chat are returned by dc_get_pois().
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @param latitude North-south position of the location.
+ * @param longitude East-west position of the location.
+ * @param label Label of the point, must not be empty.
+ * @param radius Radius of the area in meters, 0 for none.
+ * @param color Marker color as 0xRRGGBB, -1 for the default color.
+ * @return None.
+ */
+void            dc_msg_set_poi                (dc_msg_t* msg, double latitude, double longitude, const char* label, double radius, int color);
+
+
 /**
  * Late filing information to a message.
  * In contrast to the dc_msg_set_*() functions, this function really stores the information in the database.
@@ -3289,6 +4594,14 @@ void            dc_msg_latefiling_mediasize   (dc_msg_t* msg, int width, int hei
 #define         DC_CONTACT_ID_DEVICE         5 // messages "update info" in the device-chat
 #define         DC_CONTACT_ID_LAST_SPECIAL   9
 
+#define         DC_VERIFICATION_SECUREJOIN         1
+#define         DC_VERIFICATION_GROUP_INTRODUCTION 2
+
+#define         DC_KEY_SOURCE_AUTOCRYPT            1
+#define         DC_KEY_SOURCE_WKD                  2
+#define         DC_KEY_SOURCE_KEYSERVER            3
+#define         DC_KEY_SOURCE_GOSSIP               4
+
 
 /**
  * Free a contact object.
@@ -3410,6 +4723,32 @@ char*           dc_contact_get_profile_image (const dc_contact_t* contact);
 uint32_t        dc_contact_get_color         (const dc_contact_t* contact);
 
 
+/**
+ * Get the time the contact was last seen,
+ * this is the sending time of the last message received from the contact.
+ *
+ * @memberof dc_contact_t
+ * @param contact The contact object.
+ * @return Last-seen timestamp, 0 if no message was received from the contact yet.
+ */
+int64_t         dc_contact_get_last_seen     (const dc_contact_t* contact);
+
+
+/**
+ * Get the contact's status.
+ *
+ * The status is the footer of the messages sent by the contact,
+ * set by each remote user on their own using dc_set_config(context, "selfstatus", status).
+ * The status of the last message received from the contact is used.
+ *
+ * @memberof dc_contact_t
+ * @param contact The contact object.
+ * @return The status, empty string if the contact did not send a status yet.
+ *     Must be released using dc_str_unref() after usage.
+ */
+char*           dc_contact_get_status        (const dc_contact_t* contact);
+
+
 /**
  * Check if a contact is blocked.
  *
@@ -3436,6 +4775,81 @@ int             dc_contact_is_blocked        (const dc_contact_t* contact);
 int             dc_contact_is_verified       (dc_contact_t* contact);
 
 
+/**
+ * Get how the contact was verified.
+ *
+ * @memberof dc_contact_t
+ * @param contact The contact object.
+ * @return One of
+ *     - DC_VERIFICATION_SECUREJOIN (1) - The fingerprint was verified by a QR code scan.
+ *     - DC_VERIFICATION_GROUP_INTRODUCTION (2) - The contact was introduced by a verified member
+ *       of a verified group, see dc_contact_get_verifier_addr().
+ *     - 0 - The contact is not verified
+ *       or the verification took place before the core recorded verifications.
+ */
+int             dc_contact_get_verification_method (dc_contact_t* contact);
+
+
+/**
+ * Get where the key used for encrypting messages to the contact comes from.
+ *
+ * @memberof dc_contact_t
+ * @param contact The contact object.
+ * @return One of
+ *     - DC_KEY_SOURCE_AUTOCRYPT (1) - The key was received in a message of the contact.
+ *     - DC_KEY_SOURCE_WKD (2) - The key was found in the Web Key Directory of the domain
+ *       of the contact, see the `wkd_lookup` option of dc_set_config().
+ *     - DC_KEY_SOURCE_KEYSERVER (3) - The key was found on the keyserver,
+ *       see the `keyserver_lookup` option of dc_set_config().
+ *     - DC_KEY_SOURCE_GOSSIP (4) - The key was gossiped by another member of a group,
+ *       see the `gossip_accept` option of dc_set_config().
+ *     - 0 - No key of the contact is known.
+ */
+int             dc_contact_get_key_source    (dc_contact_t* contact);
+
+
+/**
+ * Get the keys of the contact and where they come from in json format.
+ *
+ * A contact may have a key received in its own messages
+ * and a key gossiped by other group members or found in a key directory;
+ * the key received from the contact is used for encryption if it exists.
+ * The returned json string contains an array of objects with the following key/values:
+ * - `source`: one of the DC_KEY_SOURCE_* constants, see dc_contact_get_key_source()
+ * - `fingerprint`: the fingerprint of the key as hex string
+ * - `url`: the URL the key was fetched from, empty for keys received in messages
+ * - `timestamp`: unix timestamp of when the key was received
+ *
+ * @memberof dc_contact_t
+ * @param contact The contact object.
+ * @return a utf8-encoded json string. Must be freed using dc_str_unref().  NULL is never returned.
+ */
+char*           dc_contact_get_key_sources_json (dc_contact_t* contact);
+
+
+/**
+ * Get the time the contact was verified.
+ *
+ * @memberof dc_contact_t
+ * @param contact The contact object.
+ * @return Verification timestamp, 0 if unknown or if the contact is not verified.
+ */
+int64_t         dc_contact_get_verification_timestamp (dc_contact_t* contact);
+
+
+/**
+ * Get the address of the contact that introduced the verified contact.
+ *
+ * Only set if dc_contact_get_verification_method() returns DC_VERIFICATION_GROUP_INTRODUCTION.
+ *
+ * @memberof dc_contact_t
+ * @param contact The contact object.
+ * @return The address of the introducing contact, NULL if there is none.
+ *     Must be released using dc_str_unref() after usage.
+ */
+char*           dc_contact_get_verifier_addr (dc_contact_t* contact);
+
+
 /**
  * @class dc_provider_t
  *
@@ -3458,6 +4872,23 @@ int             dc_contact_is_verified       (dc_contact_t* contact);
 dc_provider_t*  dc_provider_new_from_email            (const dc_context_t* context, const char* email);
 
 
+/**
+ * Create a provider struct for the given domain.
+ *
+ * Other than dc_provider_new_from_email(), this function can be used
+ * before the user entered the complete email address,
+ * eg. to show hints as soon as the domain is typed.
+ *
+ * @memberof dc_provider_t
+ * @param context The context object as created by dc_context_new().
+ * @param domain The domain to get the provider info for, eg. `example.org`.
+ * @return a dc_provider_t struct which can be used with the dc_provider_get_*
+ *     accessor functions.  If no provider info is found, NULL will be
+ *     returned.
+ */
+dc_provider_t*  dc_provider_new_from_domain           (const dc_context_t* context, const char* domain);
+
+
 /**
  * URL of the overview page.
  *
@@ -3502,6 +4933,19 @@ char*           dc_provider_get_before_login_hint     (const dc_provider_t* prov
 int             dc_provider_get_status                (const dc_provider_t* provider);
 
 
+/**
+ * Whether the provider supports logging in using OAuth2.
+ *
+ * If so, the ui may offer the OAuth2 login flow using dc_get_oauth2_url()
+ * instead of asking for a password.
+ *
+ * @memberof dc_provider_t
+ * @param provider The dc_provider_t struct.
+ * @return 1=OAuth2 is supported, 0=OAuth2 is not supported.
+ */
+int             dc_provider_get_oauth2_supported      (const dc_provider_t* provider);
+
+
 /**
  * Free the provider info struct.
  *
@@ -3698,6 +5142,71 @@ int64_t          dc_lot_get_timestamp     (const dc_lot_t* lot);
  */
 #define DC_MSG_FILE      60
 
+
+/**
+ * Message containing an in-chat app,
+ * a zip archive of HTML, JavaScript and other resources
+ * with `index.html` as entry point.
+ * The archive is set via dc_msg_set_file()
+ * and retrieved via dc_msg_get_file().
+ */
+#define DC_MSG_APP       80
+
+
+/**
+ * Message containing a poll.
+ * The question is retrieved via dc_msg_get_text().
+ */
+#define DC_MSG_POLL      90
+
+
+/**
+ * Message containing a calendar event, typically an invitation.
+ * The title of the event is retrieved via dc_msg_get_text(),
+ * the details via dc_msg_get_calendar_event_json()
+ * and the `.ics` file via dc_msg_get_file().
+ * Invitations can be responded to using dc_respond_to_invite().
+ */
+#define DC_MSG_CALENDAR  100
+
+
+/**
+ * @}
+ */
+
+
+/**
+ * @defgroup DC_RSVP DC_RSVP
+ *
+ * Responses to invitations, see dc_respond_to_invite().
+ *
+ * @addtogroup DC_RSVP
+ * @{
+ */
+
+#define DC_RSVP_ACCEPT    1
+#define DC_RSVP_DECLINE   2
+#define DC_RSVP_TENTATIVE 3
+
+/**
+ * @}
+ */
+
+
+/**
+ * @defgroup DC_CONNECTIVITY DC_CONNECTIVITY
+ *
+ * Connection states returned by dc_get_connectivity().
+ *
+ * @addtogroup DC_CONNECTIVITY
+ * @{
+ */
+
+#define DC_CONNECTIVITY_NOT_CONNECTED 1000
+#define DC_CONNECTIVITY_CONNECTING    2000
+#define DC_CONNECTIVITY_WORKING       3000
+#define DC_CONNECTIVITY_CONNECTED     4000
+
 /**
  * @}
  */
@@ -4081,6 +5590,20 @@ void dc_event_unref(dc_event_t* event);
 #define DC_EVENT_ERROR_SELF_NOT_IN_GROUP  410
 
 
+/**
+ * The OAuth2 access token cannot be refreshed anymore,
+ * eg. because the user revoked the access or changed the password.
+ *
+ * Instead of failing to login silently over and over,
+ * the UI should ask the user to authorize again using dc_get_oauth2_url()
+ * and configure the account with the new code.
+ *
+ * @param data1 0
+ * @param data2 (char*) The address the OAuth2 authorization is for.
+ */
+#define DC_EVENT_ERROR_OAUTH2_REAUTH_REQUIRED 420
+
+
 /**
  * Messages or chats changed.  One or more messages or chats changed for various
  * reasons in the database:
@@ -4100,6 +5623,9 @@ void dc_event_unref(dc_event_t* event);
  *
  * There is no extra #DC_EVENT_MSGS_CHANGED event send together with this event.
  *
+ * The event is also sent for muted chats,
+ * use dc_chat_is_muted() to check if a notification should be shown.
+ *
  * @param data1 (int) chat_id
  * @param data2 (int) msg_id
  */
@@ -4137,9 +5663,64 @@ void dc_event_unref(dc_event_t* event);
 
 
 /**
- * Chat changed.  The name or the image of a chat group was changed or members were added or removed.
+ * Reactions to a message changed,
+ * a contact added, replaced or removed the reaction.
+ *
+ * @param data1 (int) chat_id
+ * @param data2 (int) msg_id
+ */
+#define DC_EVENT_REACTIONS_CHANGED        2016
+
+
+/**
+ * The state of an in-chat app changed,
+ * a state update was sent or received.
+ *
+ * @param data1 (int) chat_id
+ * @param data2 (int) msg_id of the app instance
+ */
+#define DC_EVENT_APP_UPDATE               2017
+
+
+/**
+ * The votes of a poll changed,
+ * a contact voted or removed the vote.
+ *
+ * @param data1 (int) chat_id
+ * @param data2 (int) msg_id of the poll
+ */
+#define DC_EVENT_POLL_CHANGED             2018
+
+
+/**
+ * A contact started typing in a chat.
+ *
+ * Only emitted if the config option `typing_indicators` is enabled.
+ * If no further event is received, the UI should stop showing the typing indicator
+ * after 15 seconds.
+ *
+ * @param data1 (int) chat_id the contact is typing in
+ * @param data2 (int) contact_id
+ */
+#define DC_EVENT_CONTACT_TYPING           2019
+
+
+/**
+ * A contact stopped typing in a chat without sending a message.
+ *
+ * Only emitted if the config option `typing_indicators` is enabled.
+ * The UI should stop showing the typing indicator started by #DC_EVENT_CONTACT_TYPING.
+ *
+ * @param data1 (int) chat_id the contact stopped typing in
+ * @param data2 (int) contact_id
+ */
+#define DC_EVENT_CONTACT_STOPPED_TYPING   2022
+
+
+/**
+ * Chat changed.  The name of a chat group was changed or members were added or removed.
  * Or the verify state of a chat has changed.
- * See dc_set_chat_name(), dc_set_chat_profile_image(), dc_add_contact_to_chat()
+ * See dc_set_chat_name(), dc_add_contact_to_chat()
  * and dc_remove_contact_from_chat().
  *
  * @param data1 (int) chat_id
@@ -4148,6 +5729,17 @@ void dc_event_unref(dc_event_t* event);
 #define DC_EVENT_CHAT_MODIFIED            2020
 
 
+/**
+ * The image of a chat group was set, changed or removed,
+ * see dc_set_chat_profile_image().
+ * Nothing else of the chat changed, so the UI only needs to refresh the image.
+ *
+ * @param data1 (int) chat_id
+ * @param data2 0
+ */
+#define DC_EVENT_CHAT_IMAGE_CHANGED       2021
+
+
 /**
  * Contact(s) created, renamed, verified, blocked or deleted.
  *
@@ -4178,6 +5770,19 @@ void dc_event_unref(dc_event_t* event);
 #define DC_EVENT_CONFIGURE_PROGRESS       2041
 
 
+/**
+ * Inform about the stage of the configuration started by dc_configure().
+ *
+ * Sent in addition to #DC_EVENT_CONFIGURE_PROGRESS whenever a stage starts
+ * and whenever other server settings are tried.
+ *
+ * @param data1 (int) The stage as one of the @ref DC_CONFIGURE_STAGE constants.
+ * @param data2 (char*) What is tried, eg. the URL of the autoconfig file
+ *     or the server, port and security as `user@imap.example.org:993 SSL`.
+ */
+#define DC_EVENT_CONFIGURE_STAGE          2042
+
+
 /**
  * Inform about the import/export progress started by dc_imex().
  *
@@ -4231,6 +5836,25 @@ void dc_event_unref(dc_event_t* event);
  */
 #define DC_EVENT_SECUREJOIN_JOINER_PROGRESS       2061
 
+
+/**
+ * Inform about the progress of housekeeping, eg. started by dc_run_housekeeping().
+ *
+ * @param data1 (int) 0=error, 1-999=progress in permille, 1000=success and done
+ * @param data2 0
+ */
+#define DC_EVENT_HOUSEKEEPING_PROGRESS    2070
+
+
+/**
+ * The state of the connections to the server changed.
+ * Use dc_get_connectivity() or dc_get_connectivity_json() to get the new state.
+ *
+ * @param data1 0
+ * @param data2 0
+ */
+#define DC_EVENT_CONNECTIVITY_CHANGED     2100
+
 /**
  * @}
  */
@@ -4258,11 +5882,29 @@ void dc_event_unref(dc_event_t* event);
 #define DC_SHOW_EMAILS_ALL               2
 
 
+/*
+ * Values for dc_get|set_config("mvbox_policy")
+ */
+#define DC_MVBOX_POLICY_OFF              0
+#define DC_MVBOX_POLICY_WATCH            1
+#define DC_MVBOX_POLICY_MOVE_CHATS       2
+#define DC_MVBOX_POLICY_MOVE_ALL         3
+
+
+/*
+ * Values for dc_set_chat_mdn_policy()
+ */
+#define DC_MDN_POLICY_GLOBAL             0
+#define DC_MDN_POLICY_ALWAYS             1
+#define DC_MDN_POLICY_NEVER              2
+
+
 /*
  * Values for dc_get|set_config("media_quality")
  */
 #define DC_MEDIA_QUALITY_BALANCED 0
 #define DC_MEDIA_QUALITY_WORSE    1
+#define DC_MEDIA_QUALITY_ORIGINAL 2
 
 
 /*
@@ -4273,6 +5915,22 @@ void dc_event_unref(dc_event_t* event);
 #define DC_KEY_GEN_ED25519 2
 
 
+/*
+ * Values for dc_get|set_config("gossip_policy")
+ */
+#define DC_GOSSIP_POLICY_ALWAYS        0
+#define DC_GOSSIP_POLICY_VERIFIED_ONLY 1
+#define DC_GOSSIP_POLICY_NEVER         2
+
+
+/*
+ * Values for dc_get|set_config("gossip_accept")
+ */
+#define DC_GOSSIP_ACCEPT_ALWAYS        0
+#define DC_GOSSIP_ACCEPT_UNKNOWN_PEERS 1
+#define DC_GOSSIP_ACCEPT_NEVER         2
+
+
 /**
  * @defgroup DC_PROVIDER_STATUS DC_PROVIDER_STATUS
  *
@@ -4428,7 +6086,19 @@ void dc_event_unref(dc_event_t* event);
 #define DC_STR_WELCOME_MESSAGE            71
 #define DC_STR_UNKNOWN_SENDER_FOR_CHAT    72
 #define DC_STR_SUBJECT_FOR_NEW_CONTACT    73
-#define DC_STR_COUNT                      73
+#define DC_STR_EPHEMERAL_TIMER_DISABLED   74
+#define DC_STR_EPHEMERAL_TIMER_ENABLED    75
+#define DC_STR_POLL                       76
+#define DC_STR_BROADCAST_LIST             77
+#define DC_STR_MSGADMINADDED              78
+#define DC_STR_MSGADMINREMOVED            79
+#define DC_STR_PARTIAL_DOWNLOAD_MSG_BODY  80
+#define DC_STR_INVITATION                 81
+#define DC_STR_INVITE_ACCEPTED            82
+#define DC_STR_INVITE_DECLINED            83
+#define DC_STR_INVITE_TENTATIVE           84
+#define DC_STR_MSG_RESCUED_FROM_SPAM      85
+#define DC_STR_COUNT                      85
 
 /*
  * @}
@@ -24,9 +24,12 @@ use async_std::task::{block_on, spawn};
 use num_traits::{FromPrimitive, ToPrimitive};
 
 use deltachat::chat::{ChatId, ChatVisibility, MuteDuration};
-use deltachat::constants::DC_MSG_ID_LAST_SPECIAL;
+use deltachat::constants::{
+    MdnPolicy, DC_HOUSEKEEPING_BLOBS, DC_HOUSEKEEPING_MDNS, DC_HOUSEKEEPING_TOMBSTONES,
+    DC_HOUSEKEEPING_VACUUM, DC_MSG_ID_LAST_SPECIAL,
+};
 use deltachat::contact::{Contact, Origin};
-use deltachat::context::Context;
+use deltachat::context::{Context, HousekeepingOptions};
 use deltachat::key::DcKey;
 use deltachat::message::MsgId;
 use deltachat::stock::StockMessage;
@@ -264,7 +267,27 @@ pub unsafe extern "C" fn dc_configure(context: *mut dc_context_t) {
 
     let ctx = &*context;
 
-    spawn(async move { ctx.configure().await.log_err(ctx, "Configure failed") });
+    spawn(async move {
+        record_err(ctx, ctx.configure().await)
+            .await
+            .log_err(ctx, "Configure failed")
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_configure_from_qr(context: *mut dc_context_t, qr: *const libc::c_char) {
+    if context.is_null() || qr.is_null() {
+        eprintln!("ignoring careless call to dc_configure_from_qr()");
+        return;
+    }
+    let qr = to_string_lossy(qr);
+    let ctx = &*context;
+
+    spawn(async move {
+        record_err(ctx, ctx.configure_from_qr(&qr).await)
+            .await
+            .log_err(ctx, "Configure from QR code failed")
+    });
 }
 
 #[no_mangle]
@@ -278,6 +301,17 @@ pub unsafe extern "C" fn dc_is_configured(context: *mut dc_context_t) -> libc::c
     block_on(async move { ctx.is_configured().await as libc::c_int })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_last_error_kind(context: *mut dc_context_t) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_last_error_kind()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move { ctx.get_last_error_kind().await as libc::c_int })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_start_io(context: *mut dc_context_t) {
     if context.is_null() {
@@ -343,23 +377,33 @@ pub unsafe extern "C" fn dc_event_get_data1_int(event: *mut dc_event_t) -> libc:
         | Event::Warning(_)
         | Event::Error(_)
         | Event::ErrorNetwork(_)
-        | Event::ErrorSelfNotInGroup(_) => 0,
+        | Event::ErrorSelfNotInGroup(_)
+        | Event::ErrorOauth2ReauthRequired(_) => 0,
         Event::MsgsChanged { chat_id, .. }
         | Event::IncomingMsg { chat_id, .. }
         | Event::MsgDelivered { chat_id, .. }
         | Event::MsgFailed { chat_id, .. }
         | Event::MsgRead { chat_id, .. }
-        | Event::ChatModified(chat_id) => chat_id.to_u32() as libc::c_int,
+        | Event::ReactionsChanged { chat_id, .. }
+        | Event::AppUpdate { chat_id, .. }
+        | Event::PollChanged { chat_id, .. }
+        | Event::ChatModified(chat_id)
+        | Event::ChatImageChanged(chat_id) => chat_id.to_u32() as libc::c_int,
         Event::ContactsChanged(id) | Event::LocationChanged(id) => {
             let id = id.unwrap_or_default();
             id as libc::c_int
         }
-        Event::ConfigureProgress(progress) | Event::ImexProgress(progress) => {
-            *progress as libc::c_int
-        }
+        Event::ConfigureProgress(progress)
+        | Event::ImexProgress(progress)
+        | Event::HousekeepingProgress(progress) => *progress as libc::c_int,
+        Event::ConnectivityChanged => 0,
+        Event::ConfigureStage { stage, .. } => *stage as libc::c_int,
         Event::ImexFileWritten(_) => 0,
         Event::SecurejoinInviterProgress { contact_id, .. }
         | Event::SecurejoinJoinerProgress { contact_id, .. } => *contact_id as libc::c_int,
+        Event::ContactTyping { chat_id, .. } | Event::ContactStoppedTyping { chat_id, .. } => {
+            chat_id.to_u32() as libc::c_int
+        }
     }
 }
 
@@ -386,19 +430,29 @@ pub unsafe extern "C" fn dc_event_get_data2_int(event: *mut dc_event_t) -> libc:
         | Event::Error(_)
         | Event::ErrorNetwork(_)
         | Event::ErrorSelfNotInGroup(_)
+        | Event::ErrorOauth2ReauthRequired(_)
         | Event::ContactsChanged(_)
         | Event::LocationChanged(_)
         | Event::ConfigureProgress(_)
+        | Event::ConfigureStage { .. }
         | Event::ImexProgress(_)
         | Event::ImexFileWritten(_)
-        | Event::ChatModified(_) => 0,
+        | Event::HousekeepingProgress(_)
+        | Event::ConnectivityChanged
+        | Event::ChatModified(_)
+        | Event::ChatImageChanged(_) => 0,
         Event::MsgsChanged { msg_id, .. }
         | Event::IncomingMsg { msg_id, .. }
         | Event::MsgDelivered { msg_id, .. }
         | Event::MsgFailed { msg_id, .. }
-        | Event::MsgRead { msg_id, .. } => msg_id.to_u32() as libc::c_int,
+        | Event::MsgRead { msg_id, .. }
+        | Event::ReactionsChanged { msg_id, .. }
+        | Event::AppUpdate { msg_id, .. }
+        | Event::PollChanged { msg_id, .. } => msg_id.to_u32() as libc::c_int,
         Event::SecurejoinInviterProgress { progress, .. }
         | Event::SecurejoinJoinerProgress { progress, .. } => *progress as libc::c_int,
+        Event::ContactTyping { contact_id, .. }
+        | Event::ContactStoppedTyping { contact_id, .. } => *contact_id as libc::c_int,
     }
 }
 
@@ -424,7 +478,8 @@ pub unsafe extern "C" fn dc_event_get_data2_str(event: *mut dc_event_t) -> *mut
         | Event::Warning(msg)
         | Event::Error(msg)
         | Event::ErrorNetwork(msg)
-        | Event::ErrorSelfNotInGroup(msg) => {
+        | Event::ErrorSelfNotInGroup(msg)
+        | Event::ErrorOauth2ReauthRequired(msg) => {
             let data2 = msg.to_c_string().unwrap_or_default();
             data2.into_raw()
         }
@@ -433,17 +488,29 @@ pub unsafe extern "C" fn dc_event_get_data2_str(event: *mut dc_event_t) -> *mut
         | Event::MsgDelivered { .. }
         | Event::MsgFailed { .. }
         | Event::MsgRead { .. }
+        | Event::ReactionsChanged { .. }
+        | Event::AppUpdate { .. }
+        | Event::PollChanged { .. }
         | Event::ChatModified(_)
+        | Event::ChatImageChanged(_)
         | Event::ContactsChanged(_)
         | Event::LocationChanged(_)
         | Event::ConfigureProgress(_)
         | Event::ImexProgress(_)
+        | Event::HousekeepingProgress(_)
+        | Event::ConnectivityChanged
         | Event::SecurejoinInviterProgress { .. }
-        | Event::SecurejoinJoinerProgress { .. } => ptr::null_mut(),
+        | Event::SecurejoinJoinerProgress { .. }
+        | Event::ContactTyping { .. }
+        | Event::ContactStoppedTyping { .. } => ptr::null_mut(),
         Event::ImexFileWritten(file) => {
             let data2 = file.to_c_string().unwrap_or_default();
             data2.into_raw()
         }
+        Event::ConfigureStage { comment, .. } => {
+            let data2 = comment.to_c_string().unwrap_or_default();
+            data2.into_raw()
+        }
     }
 }
 
@@ -462,6 +529,26 @@ pub unsafe extern "C" fn dc_get_event_emitter(
     Box::into_raw(Box::new(ctx.get_event_emitter()))
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_event_filter(
+    context: *mut dc_context_t,
+    event_ids: *const libc::c_int,
+    count: libc::c_int,
+) {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_event_filter()");
+        return;
+    }
+    let ctx = &*context;
+    let filter = if event_ids.is_null() {
+        None
+    } else {
+        let event_ids = std::slice::from_raw_parts(event_ids, count.max(0) as usize);
+        Some(event_ids.iter().cloned().collect())
+    };
+    ctx.set_event_filter(filter);
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_event_emitter_unref(emitter: *mut dc_event_emitter_t) {
     if emitter.is_null() {
@@ -509,6 +596,169 @@ pub unsafe extern "C" fn dc_maybe_network(context: *mut dc_context_t) {
     block_on(async move { ctx.maybe_network().await })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_connectivity(context: *mut dc_context_t) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_connectivity()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move { ctx.get_connectivity_summary().await as libc::c_int })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_connectivity_json(context: *mut dc_context_t) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_connectivity_json()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        serde_json::to_string(&ctx.get_connectivity().await)
+            .unwrap_or_else(|_| "[]".to_string())
+            .strdup()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_jobs_json(context: *mut dc_context_t) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_jobs_json()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        let jobs = job::list(&ctx)
+            .await
+            .unwrap_or_log_default(&ctx, "Failed to list jobs");
+        serde_json::to_string(&jobs)
+            .unwrap_or_else(|_| "[]".to_string())
+            .strdup()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_cancel_job(context: *mut dc_context_t, job_id: u32) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_cancel_job()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        job::cancel(&ctx, job_id)
+            .await
+            .log_err(ctx, "Failed to cancel job")
+            .is_ok() as libc::c_int
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_outgoing_queue_json(
+    context: *mut dc_context_t,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_outgoing_queue_json()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        let queue = ctx
+            .get_outgoing_queue()
+            .await
+            .unwrap_or_log_default(&ctx, "Failed to get outgoing queue");
+        serde_json::to_string(&queue)
+            .unwrap_or_else(|_| "[]".to_string())
+            .strdup()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_cancel_send(context: *mut dc_context_t, msg_id: u32) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_cancel_send()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        job::cancel_send(&ctx, MsgId::new(msg_id))
+            .await
+            .log_err(ctx, "Failed to cancel sending")
+            .is_ok() as libc::c_int
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_push_device_token(
+    context: *mut dc_context_t,
+    token: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || token.is_null() {
+        eprintln!("ignoring careless call to dc_set_push_device_token()");
+        return 0;
+    }
+    let ctx = &*context;
+    let token = to_string_lossy(token);
+
+    block_on(async move {
+        ctx.set_push_device_token(&token)
+            .await
+            .map(|_| 1)
+            .unwrap_or_log_default(&ctx, "Failed to set push device token")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_fetch_on_wakeup(context: *mut dc_context_t) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_fetch_on_wakeup()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        push::fetch_on_wakeup(&ctx)
+            .await
+            .map(|_| 1)
+            .unwrap_or_log_default(&ctx, "Failed to fetch on wakeup")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_run_housekeeping(
+    context: *mut dc_context_t,
+    flags: libc::c_int,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_run_housekeeping()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+    let options = HousekeepingOptions {
+        remove_unused_blobs: flags & DC_HOUSEKEEPING_BLOBS != 0,
+        remove_orphaned_mdns: flags & DC_HOUSEKEEPING_MDNS != 0,
+        prune_tombstones: flags & DC_HOUSEKEEPING_TOMBSTONES != 0,
+        vacuum: flags & DC_HOUSEKEEPING_VACUUM != 0,
+    };
+
+    block_on(async move {
+        match ctx.run_housekeeping(options).await {
+            Ok(report) => serde_json::to_string(&report)
+                .unwrap_or_log_default(&ctx, "dc_run_housekeeping() failed to serialise to json")
+                .strdup(),
+            Err(err) => {
+                error!(&ctx, "dc_run_housekeeping() failed: {}", err);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_preconfigure_keypair(
     context: *mut dc_context_t,
@@ -571,6 +821,73 @@ pub unsafe extern "C" fn dc_get_chatlist(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_chatlist_page(
+    context: *mut dc_context_t,
+    flags: libc::c_int,
+    query_str: *const libc::c_char,
+    query_id: u32,
+    offset: libc::c_int,
+    limit: libc::c_int,
+) -> *mut dc_chatlist_t {
+    if context.is_null() || offset < 0 {
+        eprintln!("ignoring careless call to dc_get_chatlist_page()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+    let qs = to_opt_string_lossy(query_str);
+
+    let qi = if query_id == 0 { None } else { Some(query_id) };
+    let limit = if limit > 0 {
+        Some(limit as usize)
+    } else {
+        None
+    };
+
+    block_on(async move {
+        match chatlist::Chatlist::try_load_page(
+            &ctx,
+            flags as usize,
+            qs.as_ref().map(|x| x.as_str()),
+            qi,
+            offset as usize,
+            limit,
+        )
+        .await
+        {
+            Ok(list) => {
+                let ffi_list = ChatlistWrapper { context, list };
+                Box::into_raw(Box::new(ffi_list))
+            }
+            Err(_) => ptr::null_mut(),
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_chatlist_cnt(
+    context: *mut dc_context_t,
+    flags: libc::c_int,
+    query_str: *const libc::c_char,
+    query_id: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_chatlist_cnt()");
+        return -1;
+    }
+    let ctx = &*context;
+    let qs = to_opt_string_lossy(query_str);
+
+    let qi = if query_id == 0 { None } else { Some(query_id) };
+
+    block_on(async move {
+        chatlist::Chatlist::count(&ctx, flags as usize, qs.as_ref().map(|x| x.as_str()), qi)
+            .await
+            .map(|count| count as libc::c_int)
+            .unwrap_or(-1)
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_create_chat_by_msg_id(context: *mut dc_context_t, msg_id: u32) -> u32 {
     if context.is_null() {
@@ -642,7 +959,8 @@ pub unsafe extern "C" fn dc_prepare_msg(
     let ffi_msg: &mut MessageWrapper = &mut *msg;
 
     block_on(async move {
-        chat::prepare_msg(&ctx, ChatId::new(chat_id), &mut ffi_msg.message)
+        let res = chat::prepare_msg(&ctx, ChatId::new(chat_id), &mut ffi_msg.message).await;
+        record_err(&ctx, res)
             .await
             .unwrap_or_log_default(&ctx, "Failed to prepare message")
     })
@@ -663,7 +981,8 @@ pub unsafe extern "C" fn dc_send_msg(
     let ffi_msg = &mut *msg;
 
     block_on(async move {
-        chat::send_msg(&ctx, ChatId::new(chat_id), &mut ffi_msg.message)
+        let res = chat::send_msg(&ctx, ChatId::new(chat_id), &mut ffi_msg.message).await;
+        record_err(&ctx, res)
             .await
             .unwrap_or_log_default(&ctx, "Failed to send message")
     })
@@ -684,7 +1003,8 @@ pub unsafe extern "C" fn dc_send_msg_sync(
     let ffi_msg = &mut *msg;
 
     block_on(async move {
-        chat::send_msg_sync(&ctx, ChatId::new(chat_id), &mut ffi_msg.message)
+        let res = chat::send_msg_sync(&ctx, ChatId::new(chat_id), &mut ffi_msg.message).await;
+        record_err(&ctx, res)
             .await
             .unwrap_or_log_default(&ctx, "Failed to send message")
     })
@@ -705,13 +1025,34 @@ pub unsafe extern "C" fn dc_send_text_msg(
     let text_to_send = to_string_lossy(text_to_send);
 
     block_on(async move {
-        chat::send_text_msg(&ctx, ChatId::new(chat_id), text_to_send)
+        let res = chat::send_text_msg(&ctx, ChatId::new(chat_id), text_to_send).await;
+        record_err(&ctx, res)
             .await
             .map(|msg_id| msg_id.to_u32())
             .unwrap_or_log_default(&ctx, "Failed to send text message")
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_send_typing(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    typing: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_send_typing()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        chat::send_typing(&ctx, ChatId::new(chat_id), typing != 0)
+            .await
+            .unwrap_or_log_default(&ctx, "Failed to send typing notification")
+            as libc::c_int
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_set_draft(
     context: *mut dc_context_t,
@@ -853,34 +1194,117 @@ pub unsafe extern "C" fn dc_get_chat_msgs(
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn dc_get_msg_cnt(context: *mut dc_context_t, chat_id: u32) -> libc::c_int {
-    if context.is_null() {
-        eprintln!("ignoring careless call to dc_get_msg_cnt()");
-        return 0;
+pub unsafe extern "C" fn dc_get_chat_msgs_page(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    before_msg_id: u32,
+    count: libc::c_int,
+    flags: u32,
+) -> *mut dc_array::dc_array_t {
+    if context.is_null() || count < 0 {
+        eprintln!("ignoring careless call to dc_get_chat_msgs_page()");
+        return ptr::null_mut();
     }
     let ctx = &*context;
+    let before_msg_id = if before_msg_id == 0 {
+        None
+    } else {
+        Some(MsgId::new(before_msg_id))
+    };
 
-    block_on(async move { ChatId::new(chat_id).get_msg_cnt(&ctx).await as libc::c_int })
+    block_on(async move {
+        let msg_ids = chat::get_msgs_page(
+            &ctx,
+            ChatId::new(chat_id),
+            before_msg_id,
+            count as usize,
+            flags,
+        )
+        .await
+        .unwrap_or_log_default(ctx, "Cannot get chat messages page");
+        let arr = dc_array_t::from(
+            msg_ids
+                .iter()
+                .map(|msg_id| msg_id.to_u32())
+                .collect::<Vec<u32>>(),
+        );
+        Box::into_raw(Box::new(arr))
+    })
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn dc_get_fresh_msg_cnt(
+pub unsafe extern "C" fn dc_get_chat_msgs_unread_page(
     context: *mut dc_context_t,
     chat_id: u32,
-) -> libc::c_int {
-    if context.is_null() {
-        eprintln!("ignoring careless call to dc_get_fresh_msg_cnt()");
-        return 0;
+    count: libc::c_int,
+    flags: u32,
+) -> *mut dc_array::dc_array_t {
+    if context.is_null() || count < 0 {
+        eprintln!("ignoring careless call to dc_get_chat_msgs_unread_page()");
+        return ptr::null_mut();
     }
     let ctx = &*context;
 
-    block_on(async move { ChatId::new(chat_id).get_fresh_msg_cnt(&ctx).await as libc::c_int })
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn dc_estimate_deletion_cnt(
-    context: *mut dc_context_t,
-    from_server: libc::c_int,
+    block_on(async move {
+        let msg_ids = chat::get_unread_msgs_page(&ctx, ChatId::new(chat_id), count as usize, flags)
+            .await
+            .unwrap_or_log_default(ctx, "Cannot get chat messages page");
+        let arr = dc_array_t::from(
+            msg_ids
+                .iter()
+                .map(|msg_id| msg_id.to_u32())
+                .collect::<Vec<u32>>(),
+        );
+        Box::into_raw(Box::new(arr))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_first_unread_msg(context: *mut dc_context_t, chat_id: u32) -> u32 {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_first_unread_msg()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        chat::get_first_unread_msg(&ctx, ChatId::new(chat_id))
+            .await
+            .unwrap_or_log_default(ctx, "Cannot get first unread message")
+            .map(|msg_id| msg_id.to_u32())
+            .unwrap_or_default()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_msg_cnt(context: *mut dc_context_t, chat_id: u32) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_msg_cnt()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move { ChatId::new(chat_id).get_msg_cnt(&ctx).await as libc::c_int })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_fresh_msg_cnt(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_fresh_msg_cnt()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move { ChatId::new(chat_id).get_fresh_msg_cnt(&ctx).await as libc::c_int })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_estimate_deletion_cnt(
+    context: *mut dc_context_t,
+    from_server: libc::c_int,
     seconds: i64,
 ) -> libc::c_int {
     if context.is_null() || seconds < 0 {
@@ -1289,13 +1713,81 @@ pub unsafe extern "C" fn dc_set_chat_mute_duration(
     };
 
     block_on(async move {
-        chat::set_muted(&ctx, ChatId::new(chat_id), muteDuration)
+        ChatId::new(chat_id)
+            .set_mute_duration(&ctx, muteDuration)
             .await
             .map(|_| 1)
             .unwrap_or_log_default(&ctx, "Failed to set mute duration")
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_chat_ephemeral_timer(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    timer: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_chat_ephemeral_timer()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        ChatId::new(chat_id)
+            .set_ephemeral_timer(&ctx, timer)
+            .await
+            .map(|_| 1)
+            .unwrap_or_log_default(&ctx, "Failed to set ephemeral timer")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_chat_ephemeral_timer(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> u32 {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_chat_ephemeral_timer()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        ChatId::new(chat_id)
+            .get_ephemeral_timer(&ctx)
+            .await
+            .unwrap_or_log_default(&ctx, "Failed to get ephemeral timer")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_chat_mdn_policy(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    policy: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_chat_mdn_policy()");
+        return 0;
+    }
+    let ctx = &*context;
+    let policy = match MdnPolicy::from_i32(policy) {
+        Some(policy) => policy,
+        None => {
+            warn!(ctx, "dc_set_chat_mdn_policy(): Unknown policy {}", policy);
+            return 0;
+        }
+    };
+
+    block_on(async move {
+        chat::set_mdn_policy(&ctx, ChatId::new(chat_id), policy)
+            .await
+            .map(|_| 1)
+            .unwrap_or_log_default(&ctx, "Failed to set read receipt policy")
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_msg_info(
     context: *mut dc_context_t,
@@ -1310,6 +1802,78 @@ pub unsafe extern "C" fn dc_get_msg_info(
     block_on(message::get_msg_info(&ctx, MsgId::new(msg_id))).strdup()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_msg_info_json(
+    context: *mut dc_context_t,
+    msg_id: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_msg_info_json()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        match MsgId::new(msg_id).get_info_struct(&ctx).await {
+            Ok(info) => serde_json::to_string(&info)
+                .unwrap_or_else(|_| "".to_string())
+                .strdup(),
+            Err(err) => {
+                error!(ctx, "Failed to get message info: {}", err);
+                "".strdup()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_download_full_msg(
+    context: *mut dc_context_t,
+    msg_id: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_download_full_msg()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        MsgId::new(msg_id)
+            .download_full(&ctx)
+            .await
+            .map(|_| 1)
+            .unwrap_or_log_default(&ctx, "Failed to download message")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_respond_to_invite(
+    context: *mut dc_context_t,
+    msg_id: u32,
+    rsvp: libc::c_int,
+) -> u32 {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_respond_to_invite()");
+        return 0;
+    }
+    let ctx = &*context;
+    let rsvp = match calendar::Rsvp::from_i32(rsvp) {
+        Some(rsvp) => rsvp,
+        None => {
+            error!(ctx, "Invalid invitation response {}", rsvp);
+            return 0;
+        }
+    };
+
+    block_on(async move {
+        MsgId::new(msg_id)
+            .respond_to_invite(&ctx, rsvp)
+            .await
+            .map(|msg_id| msg_id.to_u32())
+            .unwrap_or_log_default(&ctx, "Failed to respond to invitation")
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_mime_headers(
     context: *mut dc_context_t,
@@ -1329,6 +1893,26 @@ pub unsafe extern "C" fn dc_get_mime_headers(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_msg_html(
+    context: *mut dc_context_t,
+    msg_id: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_msg_html()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        MsgId::new(msg_id)
+            .get_html(&ctx)
+            .await
+            .map(|s| s.strdup())
+            .unwrap_or_else(ptr::null_mut)
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_delete_msgs(
     context: *mut dc_context_t,
@@ -1345,6 +1929,40 @@ pub unsafe extern "C" fn dc_delete_msgs(
     block_on(message::delete_msgs(&ctx, &msg_ids))
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_extend_sync_window(
+    context: *mut dc_context_t,
+    timestamp: i64,
+) -> libc::c_int {
+    if context.is_null() || timestamp <= 0 {
+        eprintln!("ignoring careless call to dc_extend_sync_window()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(ctx.extend_sync_window(timestamp))
+        .log_err(ctx, "Failed to extend sync window")
+        .is_ok() as libc::c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_report_spam(
+    context: *mut dc_context_t,
+    msg_ids: *const u32,
+    msg_cnt: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() || msg_ids.is_null() || msg_cnt <= 0 {
+        eprintln!("ignoring careless call to dc_report_spam()");
+        return 0;
+    }
+    let ctx = &*context;
+    let msg_ids = convert_and_prune_message_ids(msg_ids, msg_cnt);
+
+    block_on(chat::report_spam(&ctx, &msg_ids))
+        .log_err(ctx, "Failed to report spam")
+        .is_ok() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_empty_server(context: *mut dc_context_t, flags: u32) {
     if context.is_null() || flags == 0 {
@@ -1377,7 +1995,41 @@ pub unsafe extern "C" fn dc_forward_msgs(
     block_on(async move {
         chat::forward_msgs(&ctx, &msg_ids[..], ChatId::new(chat_id))
             .await
-            .unwrap_or_log_default(&ctx, "Failed to forward message")
+            .unwrap_or_log_default(&ctx, "Failed to forward message");
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_forward_msgs_ex(
+    context: *mut dc_context_t,
+    msg_ids: *const u32,
+    msg_cnt: libc::c_int,
+    chat_id: u32,
+    attribute: libc::c_int,
+) -> *mut dc_array::dc_array_t {
+    if context.is_null()
+        || msg_ids.is_null()
+        || msg_cnt <= 0
+        || chat_id <= constants::DC_CHAT_ID_LAST_SPECIAL as u32
+    {
+        eprintln!("ignoring careless call to dc_forward_msgs_ex()");
+        return ptr::null_mut();
+    }
+    let msg_ids = convert_and_prune_message_ids(msg_ids, msg_cnt);
+    let ctx = &*context;
+
+    block_on(async move {
+        let new_msg_ids =
+            chat::forward_msgs_ex(&ctx, &msg_ids[..], ChatId::new(chat_id), attribute != 0)
+                .await
+                .unwrap_or_log_default(&ctx, "Failed to forward messages");
+        let arr = dc_array_t::from(
+            new_msg_ids
+                .iter()
+                .map(|msg_id| msg_id.to_u32())
+                .collect::<Vec<u32>>(),
+        );
+        Box::into_raw(Box::new(arr))
     })
 }
 
@@ -1575,6 +2227,41 @@ pub unsafe extern "C" fn dc_get_blocked_contacts(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_verified_contacts(
+    context: *mut dc_context_t,
+) -> *mut dc_array::dc_array_t {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_verified_contacts()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        match Contact::get_all_verified(&ctx).await {
+            Ok(contacts) => Box::into_raw(Box::new(dc_array_t::from(contacts))),
+            Err(_) => ptr::null_mut(),
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_revoke_verification(
+    context: *mut dc_context_t,
+    contact_id: u32,
+) -> libc::c_int {
+    if context.is_null() || contact_id <= constants::DC_CONTACT_ID_LAST_SPECIAL as u32 {
+        eprintln!("ignoring careless call to dc_revoke_verification()");
+        return 0;
+    }
+    let ctx = &*context;
+    block_on(async move {
+        Contact::revoke_verification(&ctx, contact_id)
+            .await
+            .unwrap_or_log_default(&ctx, "Failed to revoke verification") as libc::c_int
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_block_contact(
     context: *mut dc_context_t,
@@ -1697,7 +2384,8 @@ pub unsafe extern "C" fn dc_imex_has_backup(
     let ctx = &*context;
 
     block_on(async move {
-        match imex::has_backup(&ctx, to_string_lossy(dir)).await {
+        // databases opened through the ffi are not encrypted
+        match imex::has_backup(&ctx, to_string_lossy(dir), None).await {
             Ok(res) => res.strdup(),
             Err(err) => {
                 // do not bubble up error to the user,
@@ -1756,6 +2444,148 @@ pub unsafe extern "C" fn dc_continue_key_transfer(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_rotate_key(context: *mut dc_context_t) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_rotate_key()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        match key::rotate_self_keypair(&ctx).await {
+            Ok(fingerprint) => fingerprint.hex().strdup(),
+            Err(err) => {
+                error!(&ctx, "dc_rotate_key(): {}", err);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_default_key(
+    context: *mut dc_context_t,
+    fingerprint: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || fingerprint.is_null() {
+        eprintln!("ignoring careless call to dc_set_default_key()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        let fingerprint = match key::Fingerprint::from_str(&to_string_lossy(fingerprint)) {
+            Ok(fingerprint) => fingerprint,
+            Err(err) => {
+                error!(&ctx, "dc_set_default_key(): bad fingerprint: {}", err);
+                return 0;
+            }
+        };
+        match key::set_default_self_key(&ctx, &fingerprint).await {
+            Ok(()) => 1,
+            Err(err) => {
+                error!(&ctx, "dc_set_default_key(): {}", err);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_publish_key(context: *mut dc_context_t) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_publish_key()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        match keyserver::publish_self_key(&ctx).await {
+            Ok(()) => 1,
+            Err(err) => {
+                error!(&ctx, "dc_publish_key(): {}", err);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_keys_json(context: *mut dc_context_t) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_keys_json()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        let keys = match key::get_self_keys(&ctx).await {
+            Ok(keys) => keys,
+            Err(err) => {
+                error!(&ctx, "dc_get_keys_json(): {}", err);
+                return "".strdup();
+            }
+        };
+        let keys: Vec<_> = keys
+            .into_iter()
+            .map(|key| {
+                serde_json::json!({
+                    "fingerprint": key.fingerprint.hex(),
+                    "addr": key.addr,
+                    "created": key.created,
+                    "is_default": key.is_default,
+                })
+            })
+            .collect();
+        serde_json::Value::Array(keys).to_string().strdup()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_provide_second_device(
+    context: *mut dc_context_t,
+    include_backup: libc::c_int,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_provide_second_device()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        match imex::provide_second_device(&ctx, include_backup != 0).await {
+            Ok(qr) => qr.strdup(),
+            Err(err) => {
+                error!(&ctx, "dc_provide_second_device(): {}", err);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_receive_from_second_device(
+    context: *mut dc_context_t,
+    qr: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || qr.is_null() {
+        eprintln!("ignoring careless call to dc_receive_from_second_device()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        match imex::receive_from_second_device(&ctx, &to_string_lossy(qr)).await {
+            Ok(()) => 1,
+            Err(err) => {
+                error!(&ctx, "dc_receive_from_second_device(): {}", err);
+                0
+            }
+        }
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_stop_ongoing_process(context: *mut dc_context_t) {
     if context.is_null() {
@@ -1892,6 +2722,196 @@ pub unsafe extern "C" fn dc_get_locations(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_location_path_json(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    contact_id: u32,
+    timestamp_begin: i64,
+    timestamp_end: i64,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_location_path_json()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        let path = location::get_path(
+            &ctx,
+            ChatId::new(chat_id),
+            contact_id,
+            timestamp_begin,
+            timestamp_end,
+        )
+        .await
+        .unwrap_or_log_default(&ctx, "Failed to get location path");
+        serde_json::to_string(&path)
+            .unwrap_or_else(|_| "[]".to_string())
+            .strdup()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_locations_in_box(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    contact_id: u32,
+    south: libc::c_double,
+    west: libc::c_double,
+    north: libc::c_double,
+    east: libc::c_double,
+    timestamp_begin: i64,
+    timestamp_end: i64,
+) -> *mut dc_array::dc_array_t {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_locations_in_box()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+    let bbox = location::BoundingBox {
+        south,
+        west,
+        north,
+        east,
+    };
+
+    block_on(async move {
+        let locations = location::get_range_in_box(
+            &ctx,
+            ChatId::new(chat_id),
+            contact_id,
+            &bbox,
+            timestamp_begin,
+            timestamp_end,
+        )
+        .await
+        .unwrap_or_log_default(&ctx, "Failed to get locations in box");
+        Box::into_raw(Box::new(dc_array_t::from(locations)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_location_clusters_json(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    south: libc::c_double,
+    west: libc::c_double,
+    north: libc::c_double,
+    east: libc::c_double,
+    zoom: libc::c_int,
+    timestamp_begin: i64,
+    timestamp_end: i64,
+) -> *mut libc::c_char {
+    if context.is_null() || zoom < 0 {
+        eprintln!("ignoring careless call to dc_get_location_clusters_json()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+    let bbox = location::BoundingBox {
+        south,
+        west,
+        north,
+        east,
+    };
+
+    block_on(async move {
+        let clusters = location::get_clusters(
+            &ctx,
+            ChatId::new(chat_id),
+            &bbox,
+            zoom as u32,
+            timestamp_begin,
+            timestamp_end,
+        )
+        .await
+        .unwrap_or_log_default(&ctx, "Failed to get location clusters");
+        serde_json::to_string(&clusters)
+            .unwrap_or_else(|_| "[]".to_string())
+            .strdup()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_current_positions(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> *mut dc_array::dc_array_t {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_current_positions()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        let positions = location::get_current_positions(&ctx, ChatId::new(chat_id))
+            .await
+            .unwrap_or_log_default(&ctx, "Failed to get current positions");
+        Box::into_raw(Box::new(dc_array_t::from(positions)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_pois(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> *mut dc_array::dc_array_t {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_pois()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        let pois = location::get_pois(&ctx, ChatId::new(chat_id))
+            .await
+            .unwrap_or_log_default(&ctx, "Failed to get points of interest");
+        Box::into_raw(Box::new(dc_array_t::from(pois)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_export_locations(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    contact_id: u32,
+    timestamp_begin: i64,
+    timestamp_end: i64,
+    format: libc::c_int,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_export_locations()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+    let format = match location::ExportFormat::from_i32(format as i32) {
+        Some(format) => format,
+        None => {
+            eprintln!(
+                "ignoring dc_export_locations() with unknown format {}",
+                format
+            );
+            return ptr::null_mut();
+        }
+    };
+
+    block_on(async move {
+        location::export(
+            &ctx,
+            ChatId::new(chat_id),
+            contact_id,
+            (timestamp_begin, timestamp_end),
+            format,
+        )
+        .await
+        .map(|exported| exported.strdup())
+        .unwrap_or_else(|err| {
+            error!(ctx, "Cannot export locations: {}", err);
+            ptr::null_mut()
+        })
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_delete_all_locations(context: *mut dc_context_t) {
     if context.is_null() {
@@ -2006,39 +3026,92 @@ pub unsafe extern "C" fn dc_array_get_contact_id(
     index: libc::size_t,
 ) -> libc::c_uint {
     if array.is_null() {
-        eprintln!("ignoring careless call to dc_array_get_contact_id()");
-        return 0;
+        eprintln!("ignoring careless call to dc_array_get_contact_id()");
+        return 0;
+    }
+
+    (*array).get_location(index).contact_id
+}
+#[no_mangle]
+pub unsafe extern "C" fn dc_array_get_msg_id(
+    array: *const dc_array_t,
+    index: libc::size_t,
+) -> libc::c_uint {
+    if array.is_null() {
+        eprintln!("ignoring careless call to dc_array_get_msg_id()");
+        return 0;
+    }
+
+    (*array).get_location(index).msg_id
+}
+#[no_mangle]
+pub unsafe extern "C" fn dc_array_get_marker(
+    array: *const dc_array_t,
+    index: libc::size_t,
+) -> *mut libc::c_char {
+    if array.is_null() {
+        eprintln!("ignoring careless call to dc_array_get_marker()");
+        return std::ptr::null_mut(); // NULL explicitly defined as "no markers"
+    }
+
+    if let Some(s) = &(*array).get_location(index).marker {
+        s.strdup()
+    } else {
+        std::ptr::null_mut()
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_array_get_poi_label(
+    array: *const dc_array_t,
+    index: libc::size_t,
+) -> *mut libc::c_char {
+    if array.is_null() {
+        eprintln!("ignoring careless call to dc_array_get_poi_label()");
+        return std::ptr::null_mut();
     }
 
-    (*array).get_location(index).contact_id
+    if let Some(poi) = &(*array).get_location(index).poi {
+        poi.label.strdup()
+    } else {
+        std::ptr::null_mut()
+    }
 }
+
 #[no_mangle]
-pub unsafe extern "C" fn dc_array_get_msg_id(
+pub unsafe extern "C" fn dc_array_get_poi_radius(
     array: *const dc_array_t,
     index: libc::size_t,
-) -> libc::c_uint {
+) -> libc::c_double {
     if array.is_null() {
-        eprintln!("ignoring careless call to dc_array_get_msg_id()");
-        return 0;
+        eprintln!("ignoring careless call to dc_array_get_poi_radius()");
+        return 0.0;
     }
 
-    (*array).get_location(index).msg_id
+    (*array)
+        .get_location(index)
+        .poi
+        .as_ref()
+        .and_then(|poi| poi.radius)
+        .unwrap_or_default()
 }
+
 #[no_mangle]
-pub unsafe extern "C" fn dc_array_get_marker(
+pub unsafe extern "C" fn dc_array_get_poi_color(
     array: *const dc_array_t,
     index: libc::size_t,
-) -> *mut libc::c_char {
+) -> libc::c_int {
     if array.is_null() {
-        eprintln!("ignoring careless call to dc_array_get_marker()");
-        return std::ptr::null_mut(); // NULL explicitly defined as "no markers"
+        eprintln!("ignoring careless call to dc_array_get_poi_color()");
+        return -1;
     }
 
-    if let Some(s) = &(*array).get_location(index).marker {
-        s.strdup()
-    } else {
-        std::ptr::null_mut()
-    }
+    (*array)
+        .get_location(index)
+        .poi
+        .as_ref()
+        .and_then(|poi| poi.color)
+        .map_or(-1, |color| color as libc::c_int)
 }
 
 #[no_mangle]
@@ -2365,6 +3438,16 @@ pub unsafe extern "C" fn dc_chat_is_muted(chat: *mut dc_chat_t) -> libc::c_int {
     ffi_chat.chat.is_muted() as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_chat_get_mdn_policy(chat: *mut dc_chat_t) -> libc::c_int {
+    if chat.is_null() {
+        eprintln!("ignoring careless call to dc_chat_get_mdn_policy()");
+        return 0;
+    }
+    let ffi_chat = &*chat;
+    ffi_chat.chat.get_mdn_policy() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_chat_get_remaining_mute_duration(chat: *mut dc_chat_t) -> i64 {
     if chat.is_null() {
@@ -2520,6 +3603,31 @@ pub unsafe extern "C" fn dc_msg_get_state(msg: *mut dc_msg_t) -> libc::c_int {
     ffi_msg.message.get_state() as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_send_error(msg: *mut dc_msg_t) -> libc::c_int {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_send_error()");
+        return -1;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg
+        .message
+        .get_send_error()
+        .map(|send_error| send_error as libc::c_int)
+        .unwrap_or(-1)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_next_retry_timestamp(msg: *mut dc_msg_t) -> i64 {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_next_retry_timestamp()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    let ctx = &*ffi_msg.context;
+    block_on(ffi_msg.message.get_next_retry_timestamp(ctx)).unwrap_or_default()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_get_timestamp(msg: *mut dc_msg_t) -> i64 {
     if msg.is_null() {
@@ -2550,6 +3658,26 @@ pub unsafe extern "C" fn dc_msg_get_sort_timestamp(msg: *mut dc_msg_t) -> i64 {
     ffi_msg.message.get_sort_timestamp()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_ephemeral_timer(msg: *mut dc_msg_t) -> u32 {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_ephemeral_timer()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg.message.get_ephemeral_timer()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_ephemeral_timestamp(msg: *mut dc_msg_t) -> i64 {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_ephemeral_timestamp()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg.message.get_ephemeral_timestamp()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_get_text(msg: *mut dc_msg_t) -> *mut libc::c_char {
     if msg.is_null() {
@@ -2568,9 +3696,20 @@ pub unsafe extern "C" fn dc_msg_get_file(msg: *mut dc_msg_t) -> *mut libc::c_cha
     }
     let ffi_msg = &*msg;
     let ctx = &*ffi_msg.context;
-    ffi_msg
-        .message
-        .get_file(ctx)
+    block_on(ffi_msg.message.get_decrypted_file(ctx))
+        .map(|p| p.to_string_lossy().strdup())
+        .unwrap_or_else(|| "".strdup())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_thumbnail(msg: *mut dc_msg_t) -> *mut libc::c_char {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_thumbnail()");
+        return "".strdup();
+    }
+    let ffi_msg = &*msg;
+    let ctx = &*ffi_msg.context;
+    block_on(ffi_msg.message.get_thumbnail(ctx))
         .map(|p| p.to_string_lossy().strdup())
         .unwrap_or_else(|| "".strdup())
 }
@@ -2599,6 +3738,34 @@ pub unsafe extern "C" fn dc_msg_get_filemime(msg: *mut dc_msg_t) -> *mut libc::c
     }
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_calendar_event_json(msg: *mut dc_msg_t) -> *mut libc::c_char {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_calendar_event_json()");
+        return ptr::null_mut();
+    }
+    let ffi_msg = &*msg;
+    match ffi_msg.message.get_calendar_event() {
+        Some(event) => serde_json::to_string(&event)
+            .map(|json| json.strdup())
+            .unwrap_or_else(|_| ptr::null_mut()),
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_invite_response(msg: *mut dc_msg_t) -> libc::c_int {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_invite_response()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg
+        .message
+        .get_invite_response()
+        .map_or(0, |rsvp| rsvp as libc::c_int)
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_get_filebytes(msg: *mut dc_msg_t) -> u64 {
     if msg.is_null() {
@@ -2651,6 +3818,16 @@ pub unsafe extern "C" fn dc_msg_get_showpadlock(msg: *mut dc_msg_t) -> libc::c_i
     ffi_msg.message.get_showpadlock() as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_smime_state(msg: *mut dc_msg_t) -> libc::c_int {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_smime_state()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg.message.get_smime_state().bits() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_get_summary(
     msg: *mut dc_msg_t,
@@ -2715,6 +3892,16 @@ pub unsafe extern "C" fn dc_msg_has_location(msg: *mut dc_msg_t) -> libc::c_int
     ffi_msg.message.has_location() as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_has_html(msg: *mut dc_msg_t) -> libc::c_int {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_has_html()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg.message.has_html() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_is_sent(msg: *mut dc_msg_t) -> libc::c_int {
     if msg.is_null() {
@@ -2735,6 +3922,16 @@ pub unsafe extern "C" fn dc_msg_is_starred(msg: *mut dc_msg_t) -> libc::c_int {
     ffi_msg.message.is_starred().into()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_download_state(msg: *mut dc_msg_t) -> libc::c_int {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_download_state()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg.message.download_state() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_is_forwarded(msg: *mut dc_msg_t) -> libc::c_int {
     if msg.is_null() {
@@ -2745,6 +3942,19 @@ pub unsafe extern "C" fn dc_msg_is_forwarded(msg: *mut dc_msg_t) -> libc::c_int
     ffi_msg.message.is_forwarded().into()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_forwarded_from(msg: *mut dc_msg_t) -> *mut libc::c_char {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_forwarded_from()");
+        return ptr::null_mut();
+    }
+    let ffi_msg = &*msg;
+    match ffi_msg.message.get_forwarded_from() {
+        Some(author) => author.strdup(),
+        None => ptr::null_mut(),
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_is_info(msg: *mut dc_msg_t) -> libc::c_int {
     if msg.is_null() {
@@ -2816,6 +4026,16 @@ pub unsafe extern "C" fn dc_msg_set_file(
     )
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_set_thumbnail(msg: *mut dc_msg_t, file: *const libc::c_char) {
+    if msg.is_null() || file.is_null() {
+        eprintln!("ignoring careless call to dc_msg_set_thumbnail()");
+        return;
+    }
+    let ffi_msg = &mut *msg;
+    ffi_msg.message.set_thumbnail(to_string_lossy(file))
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_set_dimension(
     msg: *mut dc_msg_t,
@@ -2854,6 +4074,29 @@ pub unsafe extern "C" fn dc_msg_set_location(
     ffi_msg.message.set_location(latitude, longitude)
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_set_poi(
+    msg: *mut dc_msg_t,
+    latitude: libc::c_double,
+    longitude: libc::c_double,
+    label: *const libc::c_char,
+    radius: libc::c_double,
+    color: libc::c_int,
+) {
+    if msg.is_null() || label.is_null() {
+        eprintln!("ignoring careless call to dc_msg_set_poi()");
+        return;
+    }
+    let ffi_msg = &mut *msg;
+    ffi_msg.message.set_poi(
+        latitude,
+        longitude,
+        to_string_lossy(label),
+        if radius > 0.0 { Some(radius) } else { None },
+        if color >= 0 { Some(color as u32) } else { None },
+    )
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_latefiling_mediasize(
     msg: *mut dc_msg_t,
@@ -2998,6 +4241,26 @@ pub unsafe extern "C" fn dc_contact_get_color(contact: *mut dc_contact_t) -> u32
     ffi_contact.contact.get_color()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_contact_get_last_seen(contact: *mut dc_contact_t) -> i64 {
+    if contact.is_null() {
+        eprintln!("ignoring careless call to dc_contact_get_last_seen()");
+        return 0;
+    }
+    let ffi_contact = &*contact;
+    ffi_contact.contact.last_seen()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_contact_get_status(contact: *mut dc_contact_t) -> *mut libc::c_char {
+    if contact.is_null() {
+        eprintln!("ignoring careless call to dc_contact_get_status()");
+        return "".strdup();
+    }
+    let ffi_contact = &*contact;
+    ffi_contact.contact.get_status().strdup()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_contact_is_blocked(contact: *mut dc_contact_t) -> libc::c_int {
     if contact.is_null() {
@@ -3020,6 +4283,124 @@ pub unsafe extern "C" fn dc_contact_is_verified(contact: *mut dc_contact_t) -> l
     block_on(async move { ffi_contact.contact.is_verified(&ctx).await as libc::c_int })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_contact_get_verification_method(
+    contact: *mut dc_contact_t,
+) -> libc::c_int {
+    if contact.is_null() {
+        eprintln!("ignoring careless call to dc_contact_get_verification_method()");
+        return 0;
+    }
+    let ffi_contact = &*contact;
+    let ctx = &*ffi_contact.context;
+
+    block_on(async move {
+        ffi_contact
+            .contact
+            .get_verification(&ctx)
+            .await
+            .unwrap_or_log_default(&ctx, "Failed to get verification")
+            .map(|verification| verification.event as libc::c_int)
+            .unwrap_or_default()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_contact_get_key_source(contact: *mut dc_contact_t) -> libc::c_int {
+    if contact.is_null() {
+        eprintln!("ignoring careless call to dc_contact_get_key_source()");
+        return 0;
+    }
+    let ffi_contact = &*contact;
+    let ctx = &*ffi_contact.context;
+
+    block_on(async move {
+        ffi_contact
+            .contact
+            .get_key_source(&ctx)
+            .await
+            .unwrap_or_log_default(&ctx, "Failed to get key source")
+            .map(|record| record.source as libc::c_int)
+            .unwrap_or_default()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_contact_get_key_sources_json(
+    contact: *mut dc_contact_t,
+) -> *mut libc::c_char {
+    if contact.is_null() {
+        eprintln!("ignoring careless call to dc_contact_get_key_sources_json()");
+        return "".strdup();
+    }
+    let ffi_contact = &*contact;
+    let ctx = &*ffi_contact.context;
+
+    block_on(async move {
+        let records: Vec<_> = ffi_contact
+            .contact
+            .get_key_sources(&ctx)
+            .await
+            .unwrap_or_log_default(&ctx, "Failed to get key sources")
+            .into_iter()
+            .map(|record| {
+                serde_json::json!({
+                    "source": record.source as i32,
+                    "fingerprint": record.fingerprint.hex(),
+                    "url": record.url,
+                    "timestamp": record.timestamp,
+                })
+            })
+            .collect();
+        serde_json::Value::Array(records).to_string().strdup()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_contact_get_verification_timestamp(contact: *mut dc_contact_t) -> i64 {
+    if contact.is_null() {
+        eprintln!("ignoring careless call to dc_contact_get_verification_timestamp()");
+        return 0;
+    }
+    let ffi_contact = &*contact;
+    let ctx = &*ffi_contact.context;
+
+    block_on(async move {
+        ffi_contact
+            .contact
+            .get_verification(&ctx)
+            .await
+            .unwrap_or_log_default(&ctx, "Failed to get verification")
+            .map(|verification| verification.timestamp)
+            .unwrap_or_default()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_contact_get_verifier_addr(
+    contact: *mut dc_contact_t,
+) -> *mut libc::c_char {
+    if contact.is_null() {
+        eprintln!("ignoring careless call to dc_contact_get_verifier_addr()");
+        return ptr::null_mut();
+    }
+    let ffi_contact = &*contact;
+    let ctx = &*ffi_contact.context;
+
+    block_on(async move {
+        match ffi_contact
+            .contact
+            .get_verification(&ctx)
+            .await
+            .unwrap_or_log_default(&ctx, "Failed to get verification")
+            .and_then(|verification| verification.verifier)
+        {
+            Some(addr) => addr.strdup(),
+            None => ptr::null_mut(),
+        }
+    })
+}
+
 // dc_lot_t
 
 #[no_mangle]
@@ -3137,6 +4518,18 @@ impl<T: Default, E: std::fmt::Display> ResultExt<T, E> for Result<T, E> {
     }
 }
 
+/// Records the kind of an [Err] in the context for [dc_get_last_error_kind].
+async fn record_err<T, E: Into<error::Error>>(
+    context: &Context,
+    res: Result<T, E>,
+) -> Result<T, error::Error> {
+    let res = res.map_err(Into::into);
+    if let Err(err) = &res {
+        context.set_last_error(err).await;
+    }
+    res
+}
+
 trait ResultNullableExt<T> {
     fn into_raw(self) -> *mut T;
 }
@@ -3182,6 +4575,22 @@ pub unsafe extern "C" fn dc_provider_new_from_email(
     }
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_provider_new_from_domain(
+    context: *const dc_context_t,
+    domain: *const libc::c_char,
+) -> *const dc_provider_t {
+    if context.is_null() || domain.is_null() {
+        eprintln!("ignoring careless call to dc_provider_new_from_domain()");
+        return ptr::null();
+    }
+    let domain = to_string_lossy(domain);
+    match provider::get_info(domain.as_str()) {
+        Some(provider) => provider,
+        None => ptr::null_mut(),
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_provider_get_overview_page(
     provider: *const dc_provider_t,
@@ -3216,6 +4625,18 @@ pub unsafe extern "C" fn dc_provider_get_status(provider: *const dc_provider_t)
     provider.status as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_provider_get_oauth2_supported(
+    provider: *const dc_provider_t,
+) -> libc::c_int {
+    if provider.is_null() {
+        eprintln!("ignoring careless call to dc_provider_get_oauth2_supported()");
+        return 0;
+    }
+    let provider = &*provider;
+    provider.oauth2_authorizer.is_some() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_provider_unref(provider: *mut dc_provider_t) {
     if provider.is_null() {
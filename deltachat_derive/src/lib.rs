@@ -8,11 +8,32 @@ use quote::quote;
 // data.  If this assumption is violated, compiler error will point to
 // generated code, which is not very user-friendly.
 
-#[proc_macro_derive(ToSql)]
+/// Stores the enum as integer or, with `#[sql(repr = "text")]`,
+/// as the kebab-case name of the variant.
+#[proc_macro_derive(ToSql, attributes(sql))]
 pub fn to_sql_derive(input: TokenStream) -> TokenStream {
     let ast: syn::DeriveInput = syn::parse(input).unwrap();
     let name = &ast.ident;
 
+    let text_variants = match text_variants(&ast) {
+        Ok(text_variants) => text_variants,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    if let Some((idents, values)) = text_variants {
+        let idents = &idents;
+        let gen = quote! {
+            impl rusqlite::types::ToSql for #name {
+                fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput> {
+                    let value = match self {
+                        #(#name::#idents => #values,)*
+                    };
+                    std::result::Result::Ok(rusqlite::types::ToSqlOutput::from(value))
+                }
+            }
+        };
+        return gen.into();
+    }
+
     let gen = quote! {
         impl rusqlite::types::ToSql for #name {
             fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput> {
@@ -26,11 +47,63 @@ pub fn to_sql_derive(input: TokenStream) -> TokenStream {
     gen.into()
 }
 
-#[proc_macro_derive(FromSql)]
+/// Reads the enum stored by the `ToSql` derive.
+///
+/// Unknown integers are read as the default value,
+/// unknown text is an error.  For text, a test checking that all variants
+/// survive the round trip is generated, it requires `Debug` and `PartialEq`.
+#[proc_macro_derive(FromSql, attributes(sql))]
 pub fn from_sql_derive(input: TokenStream) -> TokenStream {
     let ast: syn::DeriveInput = syn::parse(input).unwrap();
     let name = &ast.ident;
 
+    let text_variants = match text_variants(&ast) {
+        Ok(text_variants) => text_variants,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    if let Some((idents, values)) = text_variants {
+        let idents = &idents;
+        let test_mod = syn::Ident::new(
+            &format!("sql_text_tests_{}", to_snake_case(&name.to_string())),
+            name.span(),
+        );
+        let gen = quote! {
+            impl rusqlite::types::FromSql for #name {
+                fn column_result(col: rusqlite::types::ValueRef) -> rusqlite::types::FromSqlResult<Self> {
+                    match col.as_str()? {
+                        #(#values => Ok(#name::#idents),)*
+                        _ => Err(rusqlite::types::FromSqlError::InvalidType),
+                    }
+                }
+            }
+
+            #[cfg(test)]
+            mod #test_mod {
+                use super::*;
+
+                #[test]
+                fn test_sql_text_round_trip() {
+                    let conn = rusqlite::Connection::open_in_memory().unwrap();
+                    for value in &[#(#name::#idents),*] {
+                        let params = [value as &dyn rusqlite::types::ToSql];
+                        let read: #name = conn
+                            .query_row("SELECT ?", &params, |row| row.get(0))
+                            .unwrap();
+                        assert_eq!(&read, value);
+                        let type_name: String = conn
+                            .query_row("SELECT typeof(?)", &params, |row| row.get(0))
+                            .unwrap();
+                        assert_eq!(type_name, "text");
+                    }
+                    let read: rusqlite::Result<#name> =
+                        conn.query_row("SELECT 'unknown'", rusqlite::NO_PARAMS, |row| row.get(0));
+                    assert!(read.is_err());
+                }
+            }
+        };
+        return gen.into();
+    }
+
     let gen = quote! {
         impl rusqlite::types::FromSql for #name {
             fn column_result(col: rusqlite::types::ValueRef) -> rusqlite::types::FromSqlResult<Self> {
@@ -41,3 +114,295 @@ pub fn from_sql_derive(input: TokenStream) -> TokenStream {
     };
     gen.into()
 }
+
+/// Returns the variants and their kebab-case names
+/// if the enum is stored as text by `#[sql(repr = "text")]`.
+fn text_variants(ast: &syn::DeriveInput) -> syn::Result<Option<(Vec<&syn::Ident>, Vec<String>)>> {
+    let mut text = false;
+    for attr in ast.attrs.iter().filter(|attr| attr.path.is_ident("sql")) {
+        match attr.parse_meta()? {
+            syn::Meta::List(list) => {
+                for nested in list.nested.iter() {
+                    match nested {
+                        syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                            path,
+                            lit: syn::Lit::Str(value),
+                            ..
+                        })) if path.is_ident("repr") => match value.value().as_str() {
+                            "text" => text = true,
+                            "integer" => text = false,
+                            _ => {
+                                return Err(syn::Error::new_spanned(
+                                    value,
+                                    "repr must be \"text\" or \"integer\"",
+                                ))
+                            }
+                        },
+                        _ => {
+                            return Err(syn::Error::new_spanned(
+                                nested,
+                                "expected `repr = \"...\"`",
+                            ))
+                        }
+                    }
+                }
+            }
+            meta => return Err(syn::Error::new_spanned(meta, "expected #[sql(...)]")),
+        }
+    }
+    if !text {
+        return Ok(None);
+    }
+
+    let variants = match &ast.data {
+        syn::Data::Enum(data) => &data.variants,
+        _ => return Err(syn::Error::new_spanned(ast, "expected an enum")),
+    };
+    let mut idents = Vec::new();
+    let mut values = Vec::new();
+    for variant in variants {
+        if !variant.fields.is_empty() {
+            return Err(syn::Error::new_spanned(
+                variant,
+                "variants stored as text must not have fields",
+            ));
+        }
+        idents.push(&variant.ident);
+        values.push(to_snake_case(&variant.ident.to_string()).replace('_', "-"));
+    }
+    Ok(Some((idents, values)))
+}
+
+/// Derives the key mapping of the configuration enum.
+///
+/// Keys are the variant names in snake_case unless set by `#[config(key = "...")]`,
+/// defaults are set by `#[config(default = "...")]` and the type of the value by
+/// `#[config(kind = "bool")]`, `"int"` or `"path"`; values are strings by default.
+/// Defaults that do not match the type and duplicate keys are rejected at compile time.
+///
+/// Generates `Display`, `AsRef<str>` and `FromStr` as well as `as_str()`,
+/// `default_value()`, `kind()`, `is_valid_value()` and `iter()`.
+/// Assumes that `ConfigKind` is in scope and `strum` is available.
+#[proc_macro_derive(ConfigKey, attributes(config))]
+pub fn config_key_derive(input: TokenStream) -> TokenStream {
+    let ast: syn::DeriveInput = syn::parse(input).unwrap();
+    match config_key_impl(&ast) {
+        Ok(gen) => gen,
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn config_key_impl(ast: &syn::DeriveInput) -> syn::Result<TokenStream> {
+    let name = &ast.ident;
+    let variants = match &ast.data {
+        syn::Data::Enum(data) => &data.variants,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                ast,
+                "ConfigKey can only be derived for enums",
+            ))
+        }
+    };
+
+    let mut idents = Vec::new();
+    let mut keys = Vec::new();
+    let mut defaults = Vec::new();
+    let mut kinds = Vec::new();
+    for variant in variants {
+        if !variant.fields.is_empty() {
+            return Err(syn::Error::new_spanned(
+                variant,
+                "ConfigKey variants must not have fields",
+            ));
+        }
+
+        let mut key = to_snake_case(&variant.ident.to_string());
+        let mut default: Option<syn::LitStr> = None;
+        let mut kind = "string".to_string();
+        for attr in variant
+            .attrs
+            .iter()
+            .filter(|attr| attr.path.is_ident("config"))
+        {
+            let list = match attr.parse_meta()? {
+                syn::Meta::List(list) => list,
+                meta => return Err(syn::Error::new_spanned(meta, "expected #[config(...)]")),
+            };
+            for nested in list.nested.iter() {
+                let (path, value) = match nested {
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                        path,
+                        lit: syn::Lit::Str(value),
+                        ..
+                    })) => (path, value),
+                    _ => {
+                        return Err(syn::Error::new_spanned(
+                            nested,
+                            "expected `name = \"value\"`",
+                        ))
+                    }
+                };
+                if path.is_ident("key") {
+                    key = value.value();
+                } else if path.is_ident("default") {
+                    default = Some(value.clone());
+                } else if path.is_ident("kind") {
+                    kind = value.value();
+                    if !["string", "bool", "int", "path"].contains(&kind.as_str()) {
+                        return Err(syn::Error::new_spanned(
+                            value,
+                            "kind must be one of \"string\", \"bool\", \"int\" and \"path\"",
+                        ));
+                    }
+                } else {
+                    return Err(syn::Error::new_spanned(path, "unknown config attribute"));
+                }
+            }
+        }
+
+        if let Some(ref default) = default {
+            let value = default.value();
+            let valid = match kind.as_str() {
+                "bool" => value == "0" || value == "1",
+                "int" => value.parse::<i64>().is_ok(),
+                _ => true,
+            };
+            if !valid {
+                return Err(syn::Error::new_spanned(
+                    default,
+                    format!("default {:?} is not a valid {} value", value, kind),
+                ));
+            }
+        }
+        if keys.contains(&key) {
+            return Err(syn::Error::new_spanned(
+                variant,
+                format!("duplicate config key {:?}", key),
+            ));
+        }
+
+        idents.push(&variant.ident);
+        keys.push(key);
+        defaults.push(match default {
+            Some(default) => quote! { Some(#default) },
+            None => quote! { None },
+        });
+        kinds.push(match kind.as_str() {
+            "bool" => quote! { ConfigKind::Bool },
+            "int" => quote! { ConfigKind::Int },
+            "path" => quote! { ConfigKind::Path },
+            _ => quote! { ConfigKind::String },
+        });
+    }
+
+    let idents = &idents;
+    let gen = quote! {
+        impl #name {
+            /// Returns the key under which the value is stored.
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    #(#name::#idents => #keys,)*
+                }
+            }
+
+            /// Returns the default value, if any.
+            pub fn default_value(self) -> Option<&'static str> {
+                match self {
+                    #(#name::#idents => #defaults,)*
+                }
+            }
+
+            /// Returns the type of the value.
+            pub fn kind(self) -> ConfigKind {
+                match self {
+                    #(#name::#idents => #kinds,)*
+                }
+            }
+
+            /// Returns true if `value` may be set for the key.
+            ///
+            /// Empty values are accepted for integers, meaning "automatic".
+            pub fn is_valid_value(self, value: &str) -> bool {
+                match self.kind() {
+                    ConfigKind::Bool => value == "0" || value == "1",
+                    ConfigKind::Int => value.is_empty() || value.trim().parse::<i64>().is_ok(),
+                    ConfigKind::Path => !value.is_empty(),
+                    ConfigKind::String => true,
+                }
+            }
+
+            /// Returns an iterator over all keys.
+            pub fn iter() -> impl Iterator<Item = #name> {
+                [#(#name::#idents),*].to_vec().into_iter()
+            }
+        }
+
+        impl std::fmt::Display for #name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl AsRef<str> for #name {
+            fn as_ref(&self) -> &str {
+                self.as_str()
+            }
+        }
+
+        impl std::str::FromStr for #name {
+            type Err = strum::ParseError;
+
+            fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+                match s {
+                    #(#keys => Ok(#name::#idents),)*
+                    _ => Err(strum::ParseError::VariantNotFound),
+                }
+            }
+        }
+    };
+    Ok(gen.into())
+}
+
+/// Converts a variant name to snake_case the way `strum` does,
+/// so that keys stored by earlier versions keep working.
+fn to_snake_case(s: &str) -> String {
+    #[derive(PartialEq, Clone, Copy)]
+    enum Mode {
+        Boundary,
+        Lowercase,
+        Uppercase,
+    }
+
+    let mut words: Vec<String> = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    let mut init = 0;
+    let mut mode = Mode::Boundary;
+    while let Some((i, c)) = chars.next() {
+        if let Some(&(next_i, next)) = chars.peek() {
+            let next_mode = if c.is_lowercase() {
+                Mode::Lowercase
+            } else if c.is_uppercase() {
+                Mode::Uppercase
+            } else {
+                mode
+            };
+
+            if next_mode == Mode::Lowercase && next.is_uppercase() {
+                // boundary after the current character, eg. `mailPw`
+                words.push(s[init..next_i].to_lowercase());
+                init = next_i;
+                mode = Mode::Boundary;
+            } else if mode == Mode::Uppercase && c.is_uppercase() && next.is_lowercase() {
+                // boundary before the current character, eg. `E2EEEnabled`
+                words.push(s[init..i].to_lowercase());
+                init = i;
+                mode = Mode::Boundary;
+            } else {
+                mode = next_mode;
+            }
+        } else {
+            words.push(s[init..].to_lowercase());
+        }
+    }
+    words.join("_")
+}
@@ -337,6 +337,7 @@ pub async fn cmdline(context: Context, line: &str, chat_id: &mut ChatId) -> Resu
                  disconnect\n\
                  maybenetwork\n\
                  housekeeping\n\
+                 rebuildsummaries\n\
                  help imex (Import/Export)\n\
                  ==============================Chat commands==\n\
                  listchats [<query>]\n\
@@ -427,7 +428,7 @@ pub async fn cmdline(context: Context, line: &str, chat_id: &mut ChatId) -> Resu
             continue_key_transfer(&context, MsgId::new(arg1.parse()?), &arg2).await?;
         }
         "has-backup" => {
-            has_backup(&context, blobdir).await?;
+            has_backup(&context, blobdir, None).await?;
         }
         "export-backup" => {
             imex(&context, ImexMode::ExportBackup, Some(blobdir)).await?;
@@ -486,6 +487,10 @@ pub async fn cmdline(context: Context, line: &str, chat_id: &mut ChatId) -> Resu
         "housekeeping" => {
             sql::housekeeping(&context).await;
         }
+        "rebuildsummaries" => {
+            let count = rebuild_summaries(&context).await?;
+            println!("{} chatlist summaries rebuilt.", count);
+        }
         "listchats" | "listarchived" | "chats" => {
             let listflags = if arg0 == "listarchived" { 0x01 } else { 0 };
             let chatlist = Chatlist::try_load(
@@ -62,6 +62,9 @@ fn receive_event(event: Event) {
         Event::ErrorSelfNotInGroup(msg) => {
             error!("[SELF_NOT_IN_GROUP] {}", msg);
         }
+        Event::ErrorOauth2ReauthRequired(addr) => {
+            error!("[OAUTH2_REAUTH_REQUIRED] {}", addr);
+        }
         Event::MsgsChanged { chat_id, msg_id } => {
             info!(
                 "{}",
@@ -92,6 +95,12 @@ fn receive_event(event: Event) {
                 yellow.paint(format!("Received IMEX_PROGRESS({} ‰)", progress))
             );
         }
+        Event::HousekeepingProgress(progress) => {
+            info!(
+                "{}",
+                yellow.paint(format!("Received HOUSEKEEPING_PROGRESS({} ‰)", progress))
+            );
+        }
         Event::ImexFileWritten(file) => {
             info!(
                 "{}",
@@ -146,7 +155,7 @@ const IMEX_COMMANDS: [&str; 12] = [
     "stop",
 ];
 
-const DB_COMMANDS: [&str; 9] = [
+const DB_COMMANDS: [&str; 10] = [
     "info",
     "set",
     "get",
@@ -156,6 +165,7 @@ const DB_COMMANDS: [&str; 9] = [
     "disconnect",
     "maybenetwork",
     "housekeeping",
+    "rebuildsummaries",
 ];
 
 const CHAT_COMMANDS: [&str; 26] = [
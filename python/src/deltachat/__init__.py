@@ -74,8 +74,7 @@ def run_cmdline(argv=None, account_plugins=None):
         )
         ac.set_config("addr", args.email)
         ac.set_config("mail_pw", args.password)
-        ac.set_config("mvbox_move", "0")
-        ac.set_config("mvbox_watch", "0")
+        ac.set_config("mvbox_policy", "0")
         ac.set_config("sentbox_watch", "0")
         ac.configure()
         ac.wait_configure_finish()
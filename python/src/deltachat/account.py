@@ -4,6 +4,7 @@ from __future__ import print_function
 from contextlib import contextmanager
 from email.utils import parseaddr
 from threading import Event
+import json
 import os
 from array import array
 from . import const
@@ -457,6 +458,36 @@ class Account(object):
             raise RuntimeError("could not send out autocrypt setup message")
         return from_dc_charpointer(res)
 
+    def rotate_key(self):
+        """ generate a new key and use it as default key.
+
+        Previous keys are kept to decrypt messages encrypted to them.
+
+        :returns: fingerprint of the new key as hex string.
+        """
+        res = lib.dc_rotate_key(self._dc_context)
+        if res == ffi.NULL:
+            raise ValueError("could not generate a new key")
+        return from_dc_charpointer(res)
+
+    def set_default_key(self, fingerprint):
+        """ use the own key with the given fingerprint as default key. """
+        if not lib.dc_set_default_key(self._dc_context, as_dc_charpointer(fingerprint)):
+            raise ValueError("no own key with fingerprint {}".format(fingerprint))
+
+    def publish_key(self):
+        """ upload the own public key to the configured keyserver. """
+        if not lib.dc_publish_key(self._dc_context):
+            raise ValueError("could not publish key")
+
+    def get_keys(self):
+        """ return the own keys, the default key first.
+
+        :returns: list of dicts with the keys ``fingerprint``, ``addr``,
+            ``created`` and ``is_default``.
+        """
+        return json.loads(from_dc_charpointer(lib.dc_get_keys_json(self._dc_context)))
+
     def get_setup_contact_qr(self):
         """ get/create Setup-Contact QR Code as ascii-string.
 
@@ -578,6 +609,17 @@ class Account(object):
         self.add_account_plugin(self._configtracker)
         lib.dc_configure(self._dc_context)
 
+    def configure_from_qr(self, qr):
+        """ configure the account from a DCACCOUNT: or dclogin: QR code.
+
+        Use :meth:`wait_configure_finish` to wait for the result.
+        """
+        assert not self.is_configured()
+        assert not hasattr(self, "_configtracker")
+        self._configtracker = ConfigureTracker(self)
+        self.add_account_plugin(self._configtracker)
+        lib.dc_configure_from_qr(self._dc_context, as_dc_charpointer(qr))
+
     def wait_configure_finish(self):
         try:
             self._configtracker.wait_finish()
@@ -139,6 +139,23 @@ class Chat(object):
         """
         return bool(lib.dc_chat_get_remaining_mute_duration(self.id))
 
+    def set_ephemeral_timer(self, timer):
+        """ set the ephemeral timer of this chat.
+
+        :param timer: seconds after which messages are deleted, 0 disables the timer.
+        :returns: None
+        """
+        ret = lib.dc_set_chat_ephemeral_timer(self.account._dc_context, self.id, timer)
+        if not bool(ret):
+            raise ValueError("Failed to set ephemeral timer")
+
+    def get_ephemeral_timer(self):
+        """ return the ephemeral timer of this chat.
+
+        :returns: seconds after which messages are deleted, 0 if disabled.
+        """
+        return lib.dc_get_chat_ephemeral_timer(self.account._dc_context, self.id)
+
     def get_type(self):
         """ (deprecated) return type of this chat.
 
@@ -308,6 +325,21 @@ class Chat(object):
         )
         return list(iter_array(dc_array, lambda x: Message.from_db(self.account, x)))
 
+    def get_messages_page(self, count, before=None):
+        """ return a window of at most `count` messages in this chat.
+
+        :param count: maximum number of messages to return.
+        :param before: if given, return the messages before this message,
+            otherwise return the newest messages.
+        :returns: list of :class:`deltachat.message.Message` objects, oldest first.
+        """
+        before_id = before.id if before is not None else 0
+        dc_array = ffi.gc(
+            lib.dc_get_chat_msgs_page(self.account._dc_context, self.id, before_id, count, 0),
+            lib.dc_array_unref
+        )
+        return list(iter_array(dc_array, lambda x: Message.from_db(self.account, x)))
+
     def count_fresh_messages(self):
         """ return number of fresh messages in this chat.
 
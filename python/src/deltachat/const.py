@@ -14,16 +14,30 @@ DC_GCL_ADD_ALLDONE_HINT = 0x04
 DC_GCL_FOR_FORWARDING = 0x08
 DC_GCL_VERIFIED_ONLY = 0x01
 DC_GCL_ADD_SELF = 0x02
+DC_CONFIGURE_STAGE_AUTOCONFIG = 100
+DC_CONFIGURE_STAGE_IMAP_PROBE = 200
+DC_CONFIGURE_STAGE_SMTP_PROBE = 300
+DC_CONFIGURE_STAGE_LOGIN_CHECK = 400
+DC_HOUSEKEEPING_BLOBS = 0x01
+DC_HOUSEKEEPING_MDNS = 0x02
+DC_HOUSEKEEPING_TOMBSTONES = 0x04
+DC_HOUSEKEEPING_VACUUM = 0x08
+DC_CONNECTIVITY_NOT_CONNECTED = 1000
+DC_CONNECTIVITY_CONNECTING = 2000
+DC_CONNECTIVITY_WORKING = 3000
+DC_CONNECTIVITY_CONNECTED = 4000
 DC_QR_ASK_VERIFYCONTACT = 200
 DC_QR_ASK_VERIFYGROUP = 202
 DC_QR_FPR_OK = 210
 DC_QR_FPR_MISMATCH = 220
 DC_QR_FPR_WITHOUT_ADDR = 230
 DC_QR_ACCOUNT = 250
+DC_QR_SECOND_DEVICE = 260
 DC_QR_ADDR = 320
 DC_QR_TEXT = 330
 DC_QR_URL = 332
 DC_QR_ERROR = 400
+DC_QR_LOGIN = 520
 DC_CHAT_ID_DEADDROP = 1
 DC_CHAT_ID_TRASH = 3
 DC_CHAT_ID_MSGS_IN_CREATION = 4
@@ -35,6 +49,8 @@ DC_CHAT_TYPE_UNDEFINED = 0
 DC_CHAT_TYPE_SINGLE = 100
 DC_CHAT_TYPE_GROUP = 120
 DC_CHAT_TYPE_VERIFIED_GROUP = 130
+DC_CHAT_TYPE_MAILINGLIST = 140
+DC_CHAT_TYPE_BROADCAST = 160
 DC_MSG_ID_MARKER1 = 1
 DC_MSG_ID_DAYMARKER = 9
 DC_MSG_ID_LAST_SPECIAL = 9
@@ -48,10 +64,25 @@ DC_STATE_OUT_PENDING = 20
 DC_STATE_OUT_FAILED = 24
 DC_STATE_OUT_DELIVERED = 26
 DC_STATE_OUT_MDN_RCVD = 28
+DC_SEND_ERROR_OTHER = 0
+DC_SEND_ERROR_SMTP_REJECTED = 1
+DC_SEND_ERROR_RECIPIENT_REFUSED = 2
+DC_SEND_ERROR_MESSAGE_TOO_LARGE = 3
+DC_SEND_ERROR_RATE_LIMITED = 4
+DC_SEND_ERROR_ENCRYPTION_FAILED = 5
+DC_SEND_ERROR_RENDERING_FAILED = 6
+DC_SMIME_SIGNED = 1
+DC_SMIME_ENCRYPTED = 2
 DC_CONTACT_ID_SELF = 1
 DC_CONTACT_ID_INFO = 2
 DC_CONTACT_ID_DEVICE = 5
 DC_CONTACT_ID_LAST_SPECIAL = 9
+DC_VERIFICATION_SECUREJOIN = 1
+DC_VERIFICATION_GROUP_INTRODUCTION = 2
+DC_KEY_SOURCE_AUTOCRYPT = 1
+DC_KEY_SOURCE_WKD = 2
+DC_KEY_SOURCE_KEYSERVER = 3
+DC_KEY_SOURCE_GOSSIP = 4
 DC_MSG_TEXT = 10
 DC_MSG_IMAGE = 20
 DC_MSG_GIF = 21
@@ -60,6 +91,9 @@ DC_MSG_AUDIO = 40
 DC_MSG_VOICE = 41
 DC_MSG_VIDEO = 50
 DC_MSG_FILE = 60
+DC_MSG_APP = 80
+DC_MSG_POLL = 90
+DC_MSG_CALENDAR = 100
 DC_LP_AUTH_OAUTH2 = 0x2
 DC_LP_AUTH_NORMAL = 0x4
 DC_LP_IMAP_SOCKET_STARTTLS = 0x100
@@ -86,19 +120,29 @@ DC_EVENT_WARNING = 300
 DC_EVENT_ERROR = 400
 DC_EVENT_ERROR_NETWORK = 401
 DC_EVENT_ERROR_SELF_NOT_IN_GROUP = 410
+DC_EVENT_ERROR_OAUTH2_REAUTH_REQUIRED = 420
 DC_EVENT_MSGS_CHANGED = 2000
 DC_EVENT_INCOMING_MSG = 2005
 DC_EVENT_MSG_DELIVERED = 2010
 DC_EVENT_MSG_FAILED = 2012
 DC_EVENT_MSG_READ = 2015
+DC_EVENT_REACTIONS_CHANGED = 2016
+DC_EVENT_APP_UPDATE = 2017
+DC_EVENT_POLL_CHANGED = 2018
+DC_EVENT_CONTACT_TYPING = 2019
+DC_EVENT_CONTACT_STOPPED_TYPING = 2022
 DC_EVENT_CHAT_MODIFIED = 2020
+DC_EVENT_CHAT_IMAGE_CHANGED = 2021
 DC_EVENT_CONTACTS_CHANGED = 2030
 DC_EVENT_LOCATION_CHANGED = 2035
 DC_EVENT_CONFIGURE_PROGRESS = 2041
+DC_EVENT_CONFIGURE_STAGE = 2042
 DC_EVENT_IMEX_PROGRESS = 2051
 DC_EVENT_IMEX_FILE_WRITTEN = 2052
 DC_EVENT_SECUREJOIN_INVITER_PROGRESS = 2060
 DC_EVENT_SECUREJOIN_JOINER_PROGRESS = 2061
+DC_EVENT_HOUSEKEEPING_PROGRESS = 2070
+DC_EVENT_CONNECTIVITY_CHANGED = 2100
 DC_EVENT_FILE_COPIED = 2055
 DC_EVENT_IS_OFFLINE = 2081
 DC_EVENT_GET_STRING = 2091
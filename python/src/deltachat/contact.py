@@ -1,5 +1,7 @@
 """ Contact object. """
 
+from datetime import datetime
+
 from . import props
 from .cutil import from_dc_charpointer
 from .capi import lib, ffi
@@ -55,6 +57,23 @@ class Contact(object):
         """ Return True if the contact is verified. """
         return lib.dc_contact_is_verified(self._dc_contact)
 
+    @props.with_doc
+    def last_seen(self):
+        """UTC time when the last message from this contact was sent.
+
+        :returns: naive datetime.datetime() object or None if no message was received yet.
+        """
+        ts = lib.dc_contact_get_last_seen(self._dc_contact)
+        if ts:
+            return datetime.utcfromtimestamp(ts)
+
+    def get_status(self):
+        """Get contact status.
+
+        :returns: the footer of the last message received from the contact, empty if none.
+        """
+        return from_dc_charpointer(lib.dc_contact_get_status(self._dc_contact))
+
     def get_profile_image(self):
         """Get contact profile image.
 
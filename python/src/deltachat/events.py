@@ -214,6 +214,6 @@ class EventThread(threading.Thread):
         elif name == "DC_EVENT_MSG_DELIVERED":
             msg = account.get_message_by_id(ffi_event.data2)
             yield "ac_message_delivered", dict(message=msg)
-        elif name == "DC_EVENT_CHAT_MODIFIED":
+        elif name in ("DC_EVENT_CHAT_MODIFIED", "DC_EVENT_CHAT_IMAGE_CHANGED"):
             chat = account.get_chat_by_id(ffi_event.data1)
             yield "ac_chat_modified", dict(chat=chat)
@@ -154,6 +154,16 @@ class Message(object):
         if ts:
             return datetime.utcfromtimestamp(ts)
 
+    @props.with_doc
+    def time_next_retry(self):
+        """UTC time when sending the message is tried again.
+
+        :returns: naive datetime.datetime() object or None if the message is not waiting for a retry.
+        """
+        ts = lib.dc_msg_get_next_retry_timestamp(self._dc_msg)
+        if ts:
+            return datetime.utcfromtimestamp(ts)
+
     def get_mime_headers(self):
         """ return mime-header object for an incoming message.
 
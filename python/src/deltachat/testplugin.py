@@ -299,8 +299,12 @@ def acfactory(pytestconfig, tmpdir, request, session_liveconfig, data):
             ac, configdict = self.get_online_config(
                 pre_generated_key=pre_generated_key, quiet=quiet)
             configdict.update(config)
-            configdict["mvbox_watch"] = str(int(mvbox))
-            configdict["mvbox_move"] = str(int(move))
+            if move:
+                configdict["mvbox_policy"] = str(const.DC_MVBOX_POLICY_MOVE_CHATS)
+            elif mvbox:
+                configdict["mvbox_policy"] = str(const.DC_MVBOX_POLICY_WATCH)
+            else:
+                configdict["mvbox_policy"] = str(const.DC_MVBOX_POLICY_OFF)
             configdict["sentbox_watch"] = str(int(sentbox))
             ac.update_config(configdict)
             ac.configure()
@@ -345,8 +349,7 @@ def acfactory(pytestconfig, tmpdir, request, session_liveconfig, data):
             ac.update_config(dict(
                 addr=account.get_config("addr"),
                 mail_pw=account.get_config("mail_pw"),
-                mvbox_watch=account.get_config("mvbox_watch"),
-                mvbox_move=account.get_config("mvbox_move"),
+                mvbox_policy=account.get_config("mvbox_policy"),
                 sentbox_watch=account.get_config("sentbox_watch"),
             ))
             ac.configure()
@@ -0,0 +1,404 @@
+//! # Account manager
+//!
+//! Manages several accounts, each with its own [Context], below a common
+//! base directory.  The accounts and the selected account are stored in
+//! `accounts.json` in the base directory, every account lives in its own
+//! subdirectory.
+
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::task::Poll;
+
+use async_std::fs;
+use async_std::path::PathBuf;
+use async_std::stream::Stream;
+use async_std::sync::{channel, Receiver, RwLock, Sender};
+use async_std::task;
+use serde::{Deserialize, Serialize};
+
+use crate::context::Context;
+use crate::error::{ensure, format_err, Result};
+use crate::events::{Event, EventEmitter};
+
+const CONFIG_NAME: &str = "accounts.json";
+const DB_NAME: &str = "dc.db";
+
+/// Account manager, owning the contexts of all accounts.
+#[derive(Debug)]
+pub struct Accounts {
+    dir: PathBuf,
+    os_name: String,
+    config: RwLock<AccountsConfig>,
+    accounts: RwLock<BTreeMap<u32, Context>>,
+    event_sender: Sender<AccountEvent>,
+    event_receiver: Receiver<AccountEvent>,
+}
+
+/// An event emitted by the context of an account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountEvent {
+    pub account_id: u32,
+    pub event: Event,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AccountsConfig {
+    /// ID of the selected account, 0 if there is none.
+    selected_account: u32,
+    next_id: u32,
+    accounts: Vec<AccountConfig>,
+}
+
+impl Default for AccountsConfig {
+    fn default() -> Self {
+        AccountsConfig {
+            selected_account: 0,
+            next_id: 1,
+            accounts: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AccountConfig {
+    id: u32,
+    /// Directory of the account, relative to the base directory.
+    dir: String,
+}
+
+impl Accounts {
+    /// Loads the accounts stored in `dir`.
+    ///
+    /// The directory is created if it does not exist yet.
+    pub async fn new(os_name: String, dir: PathBuf) -> Result<Accounts> {
+        let config_file = dir.join(CONFIG_NAME);
+        let config = if config_file.exists().await {
+            let data = fs::read(&config_file).await?;
+            serde_json::from_slice(&data)
+                .map_err(|err| format_err!("Invalid {}: {}", config_file.display(), err))?
+        } else {
+            fs::create_dir_all(&dir).await?;
+            AccountsConfig::default()
+        };
+
+        let (event_sender, event_receiver) = channel(1_000);
+        let accounts = Accounts {
+            dir,
+            os_name,
+            config: RwLock::new(config.clone()),
+            accounts: RwLock::new(BTreeMap::new()),
+            event_sender,
+            event_receiver,
+        };
+        for account in &config.accounts {
+            let ctx = accounts.open_context(account).await?;
+            accounts.accounts.write().await.insert(account.id, ctx);
+        }
+        accounts.save_config(&config).await?;
+
+        Ok(accounts)
+    }
+
+    /// Returns the context of an account.
+    pub async fn get_account(&self, id: u32) -> Option<Context> {
+        self.accounts.read().await.get(&id).cloned()
+    }
+
+    /// Returns the context of the selected account.
+    pub async fn get_selected_account(&self) -> Option<Context> {
+        let id = self.config.read().await.selected_account;
+        self.get_account(id).await
+    }
+
+    /// Selects an account, the selection is persisted.
+    pub async fn select_account(&self, id: u32) -> Result<()> {
+        ensure!(
+            self.accounts.read().await.contains_key(&id),
+            "Account {} does not exist",
+            id
+        );
+        let mut config = self.config.write().await;
+        config.selected_account = id;
+        self.save_config(&config).await
+    }
+
+    /// Adds a new, unconfigured account and selects it.
+    ///
+    /// Returns the ID of the new account.
+    pub async fn add_account(&self) -> Result<u32> {
+        let mut config = self.config.write().await;
+        let account = AccountConfig {
+            id: config.next_id,
+            dir: format!("account-{}", config.next_id),
+        };
+        fs::create_dir_all(self.dir.join(&account.dir)).await?;
+        let ctx = self.open_context(&account).await?;
+
+        let id = account.id;
+        config.next_id += 1;
+        config.selected_account = id;
+        config.accounts.push(account);
+        self.save_config(&config).await?;
+        self.accounts.write().await.insert(id, ctx);
+
+        Ok(id)
+    }
+
+    /// Removes an account and deletes all its data.
+    ///
+    /// If the account was selected, the account with the lowest ID
+    /// is selected instead.
+    pub async fn remove_account(&self, id: u32) -> Result<()> {
+        let ctx = self
+            .accounts
+            .write()
+            .await
+            .remove(&id)
+            .ok_or_else(|| format_err!("Account {} does not exist", id))?;
+        ctx.stop_io().await;
+        ctx.sql.close().await;
+        drop(ctx);
+
+        let mut config = self.config.write().await;
+        if let Some(index) = config.accounts.iter().position(|account| account.id == id) {
+            let account = config.accounts.remove(index);
+            fs::remove_dir_all(self.dir.join(&account.dir)).await?;
+        }
+        if config.selected_account == id {
+            config.selected_account = self
+                .accounts
+                .read()
+                .await
+                .keys()
+                .next()
+                .copied()
+                .unwrap_or_default();
+        }
+        self.save_config(&config).await
+    }
+
+    /// Returns the IDs of all accounts.
+    pub async fn get_all(&self) -> Vec<u32> {
+        self.accounts.read().await.keys().copied().collect()
+    }
+
+    /// Starts the IO of all accounts.
+    pub async fn start_io(&self) {
+        for ctx in self.accounts.read().await.values() {
+            ctx.start_io().await;
+        }
+    }
+
+    /// Stops the IO of all accounts.
+    pub async fn stop_io(&self) {
+        for ctx in self.accounts.read().await.values() {
+            ctx.stop_io().await;
+        }
+    }
+
+    /// Returns an emitter for the events of all accounts.
+    ///
+    /// Events are tagged with the ID of the account emitting them.
+    /// Events of an account are only delivered to this emitter,
+    /// emitters of the single contexts should not be used together with it.
+    pub fn get_event_emitter(&self) -> AccountsEventEmitter {
+        AccountsEventEmitter(self.event_receiver.clone())
+    }
+
+    async fn open_context(&self, account: &AccountConfig) -> Result<Context> {
+        let dbfile = self.dir.join(&account.dir).join(DB_NAME);
+        let ctx = Context::new(self.os_name.clone(), dbfile).await?;
+        task::spawn(forward_events(
+            account.id,
+            ctx.get_event_emitter(),
+            self.event_sender.clone(),
+        ));
+        Ok(ctx)
+    }
+
+    async fn save_config(&self, config: &AccountsConfig) -> Result<()> {
+        let data = serde_json::to_vec_pretty(config)?;
+        let tmp_file = self.dir.join(format!("{}.tmp", CONFIG_NAME));
+        fs::write(&tmp_file, data).await?;
+        fs::rename(&tmp_file, self.dir.join(CONFIG_NAME)).await?;
+        Ok(())
+    }
+}
+
+/// Forwards the events of an account until its context is dropped.
+///
+/// Forwarding never waits for the consumer, so a full buffer does not
+/// hold up the events of the context.  Events not fitting into the
+/// buffer are dropped, a warning about them is forwarded once
+/// there is room again.
+async fn forward_events(account_id: u32, emitter: EventEmitter, sender: Sender<AccountEvent>) {
+    let mut dropped = 0;
+    while let Some(event) = emitter.recv().await {
+        if dropped > 0 {
+            let warning = Event::Warning(format!(
+                "{} events of account {} dropped, the event buffer was full",
+                dropped, account_id
+            ));
+            if sender
+                .try_send(AccountEvent {
+                    account_id,
+                    event: warning,
+                })
+                .is_ok()
+            {
+                dropped = 0;
+            }
+        }
+        if sender.try_send(AccountEvent { account_id, event }).is_err() {
+            dropped += 1;
+        }
+    }
+}
+
+/// Emitter for the events of all accounts, see [Accounts::get_event_emitter].
+#[derive(Debug, Clone)]
+pub struct AccountsEventEmitter(Receiver<AccountEvent>);
+
+impl AccountsEventEmitter {
+    /// Blocking recv of an event, see [AccountsEventEmitter::recv].
+    pub fn recv_sync(&self) -> Option<AccountEvent> {
+        async_std::task::block_on(self.recv())
+    }
+
+    /// Async recv of an event.
+    ///
+    /// Returns `None` once the `Accounts` and the contexts of all
+    /// accounts have been dropped; contexts returned by
+    /// [Accounts::get_account] keep their events flowing.
+    pub async fn recv(&self) -> Option<AccountEvent> {
+        self.0.recv().await.ok()
+    }
+}
+
+impl Stream for AccountsEventEmitter {
+    type Item = AccountEvent;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<AccountEvent>> {
+        Pin::new(&mut self.0).poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use crate::config::Config;
+
+    #[async_std::test]
+    async fn test_accounts_add_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let p: PathBuf = dir.path().join("accounts").into();
+
+        let accounts = Accounts::new("my_os".into(), p.clone()).await.unwrap();
+        assert!(accounts.get_all().await.is_empty());
+        assert!(accounts.get_selected_account().await.is_none());
+
+        let id1 = accounts.add_account().await.unwrap();
+        let id2 = accounts.add_account().await.unwrap();
+        assert_ne!(id1, id2);
+        assert_eq!(accounts.get_all().await, vec![id1, id2]);
+
+        // the added account is selected
+        let ctx = accounts.get_selected_account().await.unwrap();
+        ctx.set_config(Config::Addr, Some("two@example.org"))
+            .await
+            .unwrap();
+        let ctx = accounts.get_account(id2).await.unwrap();
+        assert_eq!(
+            ctx.get_config(Config::Addr).await,
+            Some("two@example.org".to_string())
+        );
+
+        accounts.select_account(id1).await.unwrap();
+        assert!(accounts.select_account(12345).await.is_err());
+        drop(ctx);
+        drop(accounts);
+
+        // accounts and the selection are persisted
+        let accounts = Accounts::new("my_os".into(), p.clone()).await.unwrap();
+        assert_eq!(accounts.get_all().await, vec![id1, id2]);
+        let ctx = accounts.get_selected_account().await.unwrap();
+        assert_eq!(ctx.get_config(Config::Addr).await, None);
+        let ctx = accounts.get_account(id2).await.unwrap();
+        assert_eq!(
+            ctx.get_config(Config::Addr).await,
+            Some("two@example.org".to_string())
+        );
+        drop(ctx);
+
+        accounts.remove_account(id1).await.unwrap();
+        assert_eq!(accounts.get_all().await, vec![id2]);
+        assert!(accounts.get_account(id1).await.is_none());
+        assert!(!p.join("account-1").exists().await);
+        let ctx = accounts.get_selected_account().await.unwrap();
+        assert_eq!(
+            ctx.get_config(Config::Addr).await,
+            Some("two@example.org".to_string())
+        );
+        assert!(accounts.remove_account(id1).await.is_err());
+
+        // IDs are not reused
+        let id3 = accounts.add_account().await.unwrap();
+        assert!(id3 > id2);
+    }
+
+    #[async_std::test]
+    async fn test_accounts_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let p: PathBuf = dir.path().join("accounts").into();
+        let accounts = Accounts::new("my_os".into(), p).await.unwrap();
+        let emitter = accounts.get_event_emitter();
+
+        let id1 = accounts.add_account().await.unwrap();
+        let id2 = accounts.add_account().await.unwrap();
+        for id in &[id1, id2] {
+            let ctx = accounts.get_account(*id).await.unwrap();
+            ctx.emit_event(Event::Info(format!("hello from {}", id)));
+        }
+
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            let event = async_std::future::timeout(Duration::from_secs(10), emitter.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if let Event::Info(ref msg) = event.event {
+                if msg.starts_with("hello from") {
+                    assert_eq!(msg, &format!("hello from {}", event.account_id));
+                    received.push(event.account_id);
+                }
+            }
+        }
+        received.sort();
+        assert_eq!(received, vec![id1, id2]);
+    }
+
+    #[async_std::test]
+    async fn test_forward_events_full_buffer() {
+        let events = crate::events::Events::new(10);
+        let emitter = events.get_emitter();
+        let (sender, receiver) = channel(2);
+        for i in 0..4 {
+            events.emit(Event::Info(format!("event {}", i)));
+        }
+        drop(events);
+
+        // forwarding does not wait for events to be taken
+        async_std::future::timeout(Duration::from_secs(10), forward_events(1, emitter, sender))
+            .await
+            .unwrap();
+        assert_eq!(receiver.len(), 2);
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.event, Event::Info("event 0".to_string()));
+    }
+}
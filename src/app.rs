@@ -0,0 +1,596 @@
+//! # In-chat apps
+//!
+//! An app is a zip archive of HTML, JavaScript and other resources sent
+//! as a message of type [Viewtype::App]; the UI renders it in a sandbox
+//! with `index.html` as entry point.  Optional metadata is read from
+//! `manifest.json` in the archive.
+//!
+//! Every message carrying an app is an app instance.  Instances share
+//! their state by sending small JSON updates as hidden chat messages
+//! referring to the instance by `In-Reply-To:` and `Chat-App-Update:`.
+//! The updates of all chat members are collected per instance and are
+//! handed to the app in the order they arrived.
+
+use std::io::Read;
+
+use async_std::path::Path;
+use byteorder::{ByteOrder, LittleEndian};
+use flate2::read::DeflateDecoder;
+use serde::{Deserialize, Serialize};
+
+use crate::chat::{self, ChatId};
+use crate::constants::*;
+use crate::context::Context;
+use crate::dc_tools::dc_read_file;
+use crate::error::{bail, ensure, format_err, Result};
+use crate::events::Event;
+use crate::headerdef::HeaderDef;
+use crate::message::{self, Message, MsgId};
+use crate::mimeparser::{parse_message_id, MimeMessage, SystemMessage};
+use crate::param::*;
+
+/// Entry point of an app, required in every archive.
+pub const INDEX_FILE: &str = "index.html";
+
+/// Optional metadata of an app.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Maximum size of a single state update in bytes.
+const MAX_UPDATE_SIZE: usize = 100_000;
+
+/// Maximum size of a file read from an app, protects against zip bombs.
+const MAX_FILE_SIZE: usize = 50 * 1024 * 1024;
+
+const ZIP_LOCAL_SIGNATURE: u32 = 0x0403_4b50;
+const ZIP_CENTRAL_SIGNATURE: u32 = 0x0201_4b50;
+const ZIP_EOCD_SIGNATURE: u32 = 0x0605_4b50;
+
+/// Metadata of an app as read from `manifest.json`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AppManifest {
+    /// Name of the app, defaults to the name of the archive.
+    #[serde(default)]
+    pub name: String,
+
+    /// URL of the source code of the app, if any.
+    #[serde(default)]
+    pub source_code_url: Option<String>,
+}
+
+/// A state update of an app instance.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AppUpdate {
+    /// Serial of the update, increasing with every update.
+    pub serial: u32,
+
+    /// The contact that sent the update.
+    pub contact_id: u32,
+
+    /// The update as sent by the app.
+    pub payload: serde_json::Value,
+}
+
+/// Returns whether `bundle` is a zip archive containing an `index.html`.
+pub fn is_valid_bundle(bundle: &[u8]) -> bool {
+    match read_zip_entry(bundle, INDEX_FILE) {
+        Ok(index) => index.is_some(),
+        Err(_) => false,
+    }
+}
+
+/// Returns the manifest of the app attached to `msg`.
+///
+/// A missing or broken `manifest.json` results in a default manifest
+/// named after the attached file.
+pub async fn get_app_manifest(context: &Context, msg: &Message) -> Result<AppManifest> {
+    let bundle = read_bundle(context, msg).await?;
+    let mut manifest = match read_zip_entry(&bundle, MANIFEST_FILE)? {
+        Some(data) => serde_json::from_slice(&data).unwrap_or_else(|err| {
+            warn!(
+                context,
+                "Invalid {} in app {}: {}", MANIFEST_FILE, msg.id, err
+            );
+            AppManifest::default()
+        }),
+        None => AppManifest::default(),
+    };
+    if manifest.name.is_empty() {
+        manifest.name = msg
+            .param
+            .get(Param::File)
+            .and_then(|file| Path::new(file).file_stem())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+    }
+    Ok(manifest)
+}
+
+/// Returns the content of the file `name` from the app attached to `msg`,
+/// eg. [INDEX_FILE].
+pub async fn get_app_file(context: &Context, msg: &Message, name: &str) -> Result<Vec<u8>> {
+    let bundle = read_bundle(context, msg).await?;
+    read_zip_entry(&bundle, name.trim_start_matches('/'))?
+        .ok_or_else(|| format_err!("File {} not found in app {}", name, msg.id))
+}
+
+async fn read_bundle(context: &Context, msg: &Message) -> Result<Vec<u8>> {
+    ensure!(
+        msg.viewtype == Viewtype::App,
+        "Message {} is no app",
+        msg.id
+    );
+    let path = msg
+        .param
+        .get_path(Param::File, context)?
+        .ok_or_else(|| format_err!("App {} has no file", msg.id))?;
+    dc_read_file(context, path).await
+}
+
+/// Sends a state update to all instances of the app `msg_id`.
+///
+/// `update` must be valid JSON, it is added to the updates of the own
+/// instance immediately.  Returns the ID of the hidden message carrying
+/// the update.
+pub async fn send_app_update(
+    context: &Context,
+    msg_id: MsgId,
+    update: impl AsRef<str>,
+) -> Result<MsgId> {
+    let update = update.as_ref();
+    ensure!(
+        update.len() <= MAX_UPDATE_SIZE,
+        "App update exceeds {} bytes",
+        MAX_UPDATE_SIZE
+    );
+    let payload: serde_json::Value =
+        serde_json::from_str(update).map_err(|err| format_err!("Invalid app update: {}", err))?;
+
+    let msg = Message::load_from_db(context, msg_id).await?;
+    ensure!(
+        msg.viewtype == Viewtype::App,
+        "Message {} is no app",
+        msg_id
+    );
+    ensure!(
+        !msg.chat_id.is_special(),
+        "Cannot update app {} in special chat",
+        msg_id
+    );
+    ensure!(
+        !msg.rfc724_mid.is_empty(),
+        "Cannot update app {} without Message-ID",
+        msg_id
+    );
+
+    add_app_update(context, msg.chat_id, msg_id, DC_CONTACT_ID_SELF, &payload).await?;
+
+    let mut update_msg = Message::new(Viewtype::Text);
+    update_msg.text = Some(payload.to_string());
+    update_msg.hidden = true;
+    update_msg.param.set_cmd(SystemMessage::AppUpdate);
+    update_msg.param.set(Param::Arg, &msg.rfc724_mid);
+    Ok(chat::send_msg(context, msg.chat_id, &mut update_msg).await?)
+}
+
+/// Returns the state updates of the app `msg_id` with a serial larger
+/// than `last_known_serial`, oldest first.
+///
+/// Pass 0 to get all updates.
+pub async fn get_app_updates(
+    context: &Context,
+    msg_id: MsgId,
+    last_known_serial: u32,
+) -> Result<Vec<AppUpdate>> {
+    let updates = context
+        .sql
+        .query_map(
+            "SELECT id, contact_id, payload FROM app_updates
+              WHERE msg_id=? AND id>?
+              ORDER BY id;",
+            paramsv![msg_id, last_known_serial],
+            |row| {
+                Ok((
+                    row.get::<_, u32>(0)?,
+                    row.get::<_, u32>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            },
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    Ok(updates
+        .into_iter()
+        .filter_map(|(serial, contact_id, payload)| {
+            let payload = serde_json::from_str(&payload).ok()?;
+            Some(AppUpdate {
+                serial,
+                contact_id,
+                payload,
+            })
+        })
+        .collect())
+}
+
+async fn add_app_update(
+    context: &Context,
+    chat_id: ChatId,
+    msg_id: MsgId,
+    contact_id: u32,
+    payload: &serde_json::Value,
+) -> Result<()> {
+    context
+        .sql
+        .execute(
+            "INSERT INTO app_updates (msg_id, contact_id, payload) VALUES (?,?,?);",
+            paramsv![msg_id, contact_id as i32, payload.to_string()],
+        )
+        .await?;
+    context.emit_event(Event::AppUpdate { chat_id, msg_id });
+    Ok(())
+}
+
+/// Applies a state update received from `from_id`.
+///
+/// Updates of unknown apps and updates from contacts that are not members
+/// of the chat of the app are ignored.
+pub(crate) async fn receive_app_update(
+    context: &Context,
+    mime_parser: &MimeMessage,
+    from_id: u32,
+) -> Result<()> {
+    let rfc724_mid = match mime_parser.get(HeaderDef::ChatAppUpdate) {
+        Some(value) => parse_message_id(value)?,
+        None => bail!("App update without Chat-App-Update header"),
+    };
+    let update = mime_parser
+        .parts
+        .first()
+        .map(|part| part.msg.as_str())
+        .unwrap_or_default();
+    ensure!(
+        update.len() <= MAX_UPDATE_SIZE,
+        "App update exceeds {} bytes",
+        MAX_UPDATE_SIZE
+    );
+    let payload: serde_json::Value =
+        serde_json::from_str(update).map_err(|err| format_err!("Invalid app update: {}", err))?;
+
+    match message::rfc724_mid_exists(context, &rfc724_mid).await? {
+        Some((_, _, msg_id)) => {
+            let msg = Message::load_from_db(context, msg_id).await?;
+            ensure!(
+                msg.viewtype == Viewtype::App,
+                "Message {} is no app",
+                msg_id
+            );
+            ensure!(
+                chat::is_contact_in_chat(context, msg.chat_id, from_id).await,
+                "Update of app {} from contact {} not in chat",
+                msg_id,
+                from_id
+            );
+            add_app_update(context, msg.chat_id, msg_id, from_id, &payload).await
+        }
+        None => {
+            info!(context, "Ignoring update of unknown app {}", rfc724_mid);
+            Ok(())
+        }
+    }
+}
+
+/// Reads the file `name` from a zip archive.
+///
+/// Returns `None` if there is no such file.  Only stored and deflated
+/// files are supported, which covers what common tools create.
+fn read_zip_entry(archive: &[u8], name: &str) -> Result<Option<Vec<u8>>> {
+    // the end of central directory record is followed by a comment
+    // of at most 64 KiB
+    let eocd = (0..=archive.len().saturating_sub(22))
+        .rev()
+        .take(0x1_0000 + 1)
+        .find(|&pos| read_u32(archive, pos).ok() == Some(ZIP_EOCD_SIGNATURE))
+        .ok_or_else(|| format_err!("Not a zip archive"))?;
+    let entries = read_u16(archive, eocd + 10)?;
+    let mut pos = read_u32(archive, eocd + 16)? as usize;
+
+    for _ in 0..entries {
+        ensure!(
+            read_u32(archive, pos)? == ZIP_CENTRAL_SIGNATURE,
+            "Invalid zip central directory"
+        );
+        let method = read_u16(archive, pos + 10)?;
+        let compressed_size = read_u32(archive, pos + 20)? as usize;
+        let size = read_u32(archive, pos + 24)? as usize;
+        let name_len = read_u16(archive, pos + 28)? as usize;
+        let extra_len = read_u16(archive, pos + 30)? as usize;
+        let comment_len = read_u16(archive, pos + 32)? as usize;
+        let offset = read_u32(archive, pos + 42)? as usize;
+        let entry_name = archive
+            .get(pos + 46..pos + 46 + name_len)
+            .ok_or_else(|| format_err!("Truncated zip archive"))?;
+
+        if entry_name == name.as_bytes() {
+            ensure!(
+                read_u32(archive, offset)? == ZIP_LOCAL_SIGNATURE,
+                "Invalid zip file header"
+            );
+            ensure!(size <= MAX_FILE_SIZE, "File {} in zip is too large", name);
+            let start = offset
+                + 30
+                + read_u16(archive, offset + 26)? as usize
+                + read_u16(archive, offset + 28)? as usize;
+            let data = archive
+                .get(start..start + compressed_size)
+                .ok_or_else(|| format_err!("Truncated zip archive"))?;
+            let content = match method {
+                0 => data.to_vec(),
+                8 => {
+                    let mut content = Vec::new();
+                    DeflateDecoder::new(data)
+                        .take(size as u64)
+                        .read_to_end(&mut content)?;
+                    content
+                }
+                _ => bail!("Unsupported zip compression method {}", method),
+            };
+            ensure!(content.len() == size, "Corrupted file {} in zip", name);
+            return Ok(Some(content));
+        }
+        pos += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(None)
+}
+
+fn read_u16(buf: &[u8], pos: usize) -> Result<u16> {
+    buf.get(pos..pos + 2)
+        .map(LittleEndian::read_u16)
+        .ok_or_else(|| format_err!("Truncated zip archive"))
+}
+
+fn read_u32(buf: &[u8], pos: usize) -> Result<u32> {
+    buf.get(pos..pos + 4)
+        .map(LittleEndian::read_u32)
+        .ok_or_else(|| format_err!("Truncated zip archive"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use byteorder::WriteBytesExt;
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+
+    use crate::chat::get_chat_msgs;
+    use crate::contact::Contact;
+    use crate::dc_receive_imf::dc_receive_imf;
+    use crate::test_utils::*;
+
+    /// Creates a zip archive of deflated files, checksums are not set.
+    fn create_zip(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let mut central = Vec::new();
+        for (name, content) in files {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(content.as_bytes()).unwrap();
+            let data = encoder.finish().unwrap();
+
+            let offset = archive.len() as u32;
+            archive
+                .write_u32::<LittleEndian>(ZIP_LOCAL_SIGNATURE)
+                .unwrap();
+            archive.extend_from_slice(&[20, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            archive
+                .write_u32::<LittleEndian>(data.len() as u32)
+                .unwrap();
+            archive
+                .write_u32::<LittleEndian>(content.len() as u32)
+                .unwrap();
+            archive
+                .write_u16::<LittleEndian>(name.len() as u16)
+                .unwrap();
+            archive.write_u16::<LittleEndian>(0).unwrap();
+            archive.extend_from_slice(name.as_bytes());
+            archive.extend_from_slice(&data);
+
+            central
+                .write_u32::<LittleEndian>(ZIP_CENTRAL_SIGNATURE)
+                .unwrap();
+            central.extend_from_slice(&[20, 0, 20, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            central
+                .write_u32::<LittleEndian>(data.len() as u32)
+                .unwrap();
+            central
+                .write_u32::<LittleEndian>(content.len() as u32)
+                .unwrap();
+            central
+                .write_u16::<LittleEndian>(name.len() as u16)
+                .unwrap();
+            central.extend_from_slice(&[0; 12]);
+            central.write_u32::<LittleEndian>(offset).unwrap();
+            central.extend_from_slice(name.as_bytes());
+        }
+        let central_offset = archive.len() as u32;
+        archive.extend_from_slice(&central);
+        archive
+            .write_u32::<LittleEndian>(ZIP_EOCD_SIGNATURE)
+            .unwrap();
+        archive.extend_from_slice(&[0; 4]);
+        archive
+            .write_u16::<LittleEndian>(files.len() as u16)
+            .unwrap();
+        archive
+            .write_u16::<LittleEndian>(files.len() as u16)
+            .unwrap();
+        archive
+            .write_u32::<LittleEndian>(central.len() as u32)
+            .unwrap();
+        archive.write_u32::<LittleEndian>(central_offset).unwrap();
+        archive.write_u16::<LittleEndian>(0).unwrap();
+        archive
+    }
+
+    async fn send_app(t: &TestContext, chat_id: ChatId, files: &[(&str, &str)]) -> MsgId {
+        let file = t.ctx.get_blobdir().join("chess.zip");
+        async_std::fs::write(&file, create_zip(files))
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::App);
+        msg.set_file(file.to_str().unwrap(), None);
+        chat::send_msg(&t.ctx, chat_id, &mut msg).await.unwrap()
+    }
+
+    async fn create_chat(t: &TestContext) -> (u32, ChatId) {
+        configure_alice_keypair(&t.ctx).await;
+        let bob = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob).await.unwrap();
+        (bob, chat_id)
+    }
+
+    #[test]
+    fn test_read_zip_entry() {
+        let archive = create_zip(&[("index.html", "<html></html>"), ("js/app.js", "alert(1);")]);
+        assert_eq!(
+            read_zip_entry(&archive, "index.html").unwrap().unwrap(),
+            b"<html></html>"
+        );
+        assert_eq!(
+            read_zip_entry(&archive, "js/app.js").unwrap().unwrap(),
+            b"alert(1);"
+        );
+        assert_eq!(read_zip_entry(&archive, "app.js").unwrap(), None);
+        assert!(is_valid_bundle(&archive));
+
+        assert!(!is_valid_bundle(&create_zip(&[("app.js", "")])));
+        assert!(!is_valid_bundle(b"index.html"));
+        assert!(read_zip_entry(&archive[..archive.len() - 30], "index.html").is_err());
+        assert!(read_zip_entry(b"", "index.html").is_err());
+    }
+
+    #[async_std::test]
+    async fn test_send_app() {
+        let t = dummy_context().await;
+        let (_, chat_id) = create_chat(&t).await;
+
+        let msg_id = send_app(&t, chat_id, &[("index.html", "<html></html>")]).await;
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_viewtype(), Viewtype::App);
+        assert_eq!(msg.get_filemime(), Some("application/zip".to_string()));
+        assert_eq!(
+            msg.get_app_manifest(&t.ctx).await.unwrap(),
+            AppManifest {
+                name: "chess".to_string(),
+                source_code_url: None
+            }
+        );
+        assert_eq!(
+            msg.get_app_file(&t.ctx, "/index.html").await.unwrap(),
+            b"<html></html>"
+        );
+        assert!(msg.get_app_file(&t.ctx, "app.js").await.is_err());
+
+        let msg_id = send_app(
+            &t,
+            chat_id,
+            &[
+                ("index.html", "<html></html>"),
+                ("manifest.json", r#"{"name": "Chess"}"#),
+            ],
+        )
+        .await;
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_app_manifest(&t.ctx).await.unwrap().name, "Chess");
+
+        // an app needs an index.html
+        let file = t.ctx.get_blobdir().join("broken.zip");
+        async_std::fs::write(&file, create_zip(&[("app.js", "")]))
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::App);
+        msg.set_file(file.to_str().unwrap(), None);
+        assert!(chat::send_msg(&t.ctx, chat_id, &mut msg).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn test_send_app_update() {
+        let t = dummy_context().await;
+        let (_, chat_id) = create_chat(&t).await;
+        let msg_id = send_app(&t, chat_id, &[("index.html", "<html></html>")]).await;
+
+        assert!(send_app_update(&t.ctx, msg_id, "{broken").await.is_err());
+        let update_msg_id = send_app_update(&t.ctx, msg_id, r#"{"move": "e2e4"}"#)
+            .await
+            .unwrap();
+        send_app_update(&t.ctx, msg_id, r#"{"move": "e7e5"}"#)
+            .await
+            .unwrap();
+
+        let updates = get_app_updates(&t.ctx, msg_id, 0).await.unwrap();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].contact_id, DC_CONTACT_ID_SELF);
+        assert_eq!(updates[0].payload, serde_json::json!({"move": "e2e4"}));
+        assert_eq!(updates[1].payload, serde_json::json!({"move": "e7e5"}));
+        let newer = get_app_updates(&t.ctx, msg_id, updates[0].serial)
+            .await
+            .unwrap();
+        assert_eq!(newer, vec![updates[1].clone()]);
+        assert_eq!(get_chat_msgs(&t.ctx, chat_id, 0, None).await, vec![msg_id]);
+
+        let update_msg = Message::load_from_db(&t.ctx, update_msg_id).await.unwrap();
+        assert!(update_msg.hidden);
+        assert_eq!(update_msg.param.get_cmd(), SystemMessage::AppUpdate);
+        let in_reply_to: String = t
+            .ctx
+            .sql
+            .query_get_value(
+                &t.ctx,
+                "SELECT mime_in_reply_to FROM msgs WHERE id=?;",
+                paramsv![update_msg_id],
+            )
+            .await
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(in_reply_to, msg.rfc724_mid);
+
+        // only apps can be updated
+        let text_msg_id = chat::send_text_msg(&t.ctx, chat_id, "hi".to_string())
+            .await
+            .unwrap();
+        assert!(send_app_update(&t.ctx, text_msg_id, "{}").await.is_err());
+    }
+
+    #[async_std::test]
+    async fn test_receive_app_update() {
+        let t = dummy_context().await;
+        let (bob, chat_id) = create_chat(&t).await;
+        let msg_id = send_app(&t, chat_id, &[("index.html", "<html></html>")]).await;
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+
+        let raw = format!(
+            "From: bob@example.net\n\
+             To: alice@example.com\n\
+             Subject: Chat: app update\n\
+             Message-ID: <update1@example.net>\n\
+             In-Reply-To: <{mid}>\n\
+             Chat-Version: 1.0\n\
+             Chat-App-Update: <{mid}>\n\
+             Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+             \n\
+             {{\"move\":\"e2e4\"}}\n",
+            mid = msg.rfc724_mid
+        );
+        dc_receive_imf(&t.ctx, raw.as_bytes(), "INBOX", 1, false)
+            .await
+            .unwrap();
+
+        let updates = get_app_updates(&t.ctx, msg_id, 0).await.unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].contact_id, bob);
+        assert_eq!(updates[0].payload, serde_json::json!({"move": "e2e4"}));
+        assert_eq!(get_chat_msgs(&t.ctx, chat_id, 0, None).await, vec![msg_id]);
+    }
+}
@@ -14,7 +14,9 @@ use thiserror::Error;
 use crate::config::Config;
 use crate::constants::*;
 use crate::context::Context;
+use crate::dc_tools::dc_delete_file;
 use crate::events::Event;
+use crate::heif;
 use crate::message;
 
 /// Represents a file in the blob directory.
@@ -358,6 +360,54 @@ impl<'a> BlobObject<'a> {
         true
     }
 
+    /// Returns true if both blobs exist and have the same content,
+    /// used to avoid storing identical avatars several times.
+    pub async fn has_same_content(&self, other: &BlobObject<'_>) -> bool {
+        if self.name == other.name {
+            return true;
+        }
+        match (
+            fs::read(self.to_abs_path()).await,
+            fs::read(other.to_abs_path()).await,
+        ) {
+            (Ok(data), Ok(other_data)) => data == other_data,
+            _ => false,
+        }
+    }
+
+    /// Downscales a received avatar and compares it to the `current` avatar.
+    ///
+    /// Avatars are sent along with many messages, so if the received avatar
+    /// does not differ from the current one, its blob is deleted and `None`
+    /// is returned.
+    pub(crate) async fn from_received_avatar(
+        context: &'a Context,
+        name: &str,
+        current: Option<&str>,
+    ) -> Option<BlobObject<'a>> {
+        let blob = match BlobObject::from_name(context, name.to_string()) {
+            Ok(blob) => blob,
+            Err(err) => {
+                warn!(context, "Invalid avatar {}: {}", name, err);
+                return None;
+            }
+        };
+        if let Err(err) = blob.recode_to_avatar_size(context) {
+            warn!(context, "Cannot downscale avatar: {}", err);
+        }
+        if let Some(current) = current {
+            if let Ok(current) = BlobObject::from_name(context, current.to_string()) {
+                if current.has_same_content(&blob).await {
+                    if current.name != blob.name {
+                        dc_delete_file(context, blob.to_abs_path()).await;
+                    }
+                    return None;
+                }
+            }
+        }
+        Some(blob)
+    }
+
     pub fn recode_to_avatar_size(&self, context: &Context) -> Result<(), BlobError> {
         let blob_abs = self.to_abs_path();
         let img = image::open(&blob_abs).map_err(|err| BlobError::RecodeFailure {
@@ -381,6 +431,11 @@ impl<'a> BlobObject<'a> {
         Ok(())
     }
 
+    /// Downscales a JPEG image to the size given by the `media_quality` config
+    /// and re-encodes it at the respective quality.
+    ///
+    /// Images already small enough and images sent
+    /// with [MediaQuality::Original] are not changed.
     pub async fn recode_to_image_size(&self, context: &Context) -> Result<(), BlobError> {
         let blob_abs = self.to_abs_path();
         if message::guess_msgtype_from_suffix(Path::new(&blob_abs))
@@ -389,34 +444,85 @@ impl<'a> BlobObject<'a> {
             return Ok(());
         }
 
+        let (img_wh, jpeg_quality) =
+            match MediaQuality::from_i32(context.get_config_int(Config::MediaQuality).await)
+                .unwrap_or_default()
+            {
+                MediaQuality::Balanced => (BALANCED_IMAGE_SIZE, BALANCED_IMAGE_QUALITY),
+                MediaQuality::Worse => (WORSE_IMAGE_SIZE, WORSE_IMAGE_QUALITY),
+                MediaQuality::Original => return Ok(()),
+            };
+
         let img = image::open(&blob_abs).map_err(|err| BlobError::RecodeFailure {
             blobdir: context.get_blobdir().to_path_buf(),
             blobname: blob_abs.to_str().unwrap_or_default().to_string(),
             cause: err,
         })?;
 
-        let img_wh = if MediaQuality::from_i32(context.get_config_int(Config::MediaQuality).await)
-            .unwrap_or_default()
-            == MediaQuality::Balanced
-        {
-            BALANCED_IMAGE_SIZE
-        } else {
-            WORSE_IMAGE_SIZE
-        };
-
         if img.width() <= img_wh && img.height() <= img_wh {
             return Ok(());
         }
 
         let img = img.thumbnail(img_wh, img_wh);
 
-        img.save(&blob_abs).map_err(|err| BlobError::WriteFailure {
+        let mut encoded = Vec::new();
+        img.write_to(&mut encoded, image::ImageOutputFormat::JPEG(jpeg_quality))
+            .map_err(|err| BlobError::RecodeFailure {
+                blobdir: context.get_blobdir().to_path_buf(),
+                blobname: blob_abs.to_str().unwrap_or_default().to_string(),
+                cause: err,
+            })?;
+        fs::write(&blob_abs, &encoded)
+            .await
+            .map_err(|err| BlobError::WriteFailure {
+                blobdir: context.get_blobdir().to_path_buf(),
+                blobname: blob_abs.to_str().unwrap_or_default().to_string(),
+                cause: err,
+            })?;
+
+        Ok(())
+    }
+
+    /// Returns true if the blob is a HEIF or AVIF image.
+    pub fn is_heif(&self) -> bool {
+        match self.suffix() {
+            Some("heic") | Some("heif") | Some("avif") => true,
+            _ => false,
+        }
+    }
+
+    /// Converts a HEIF or AVIF image to a new JPEG blob.
+    ///
+    /// Most recipients cannot display HEIF images, the original blob is
+    /// kept untouched.  Fails unless built with the `heif` feature.
+    pub async fn recode_heif_to_jpeg(
+        &self,
+        context: &'a Context,
+    ) -> Result<BlobObject<'a>, BlobError> {
+        let blob_abs = self.to_abs_path();
+        let recode_failure = |cause| BlobError::RecodeFailure {
             blobdir: context.get_blobdir().to_path_buf(),
             blobname: blob_abs.to_str().unwrap_or_default().to_string(),
-            cause: err,
-        })?;
+            cause,
+        };
 
-        Ok(())
+        let buf = fs::read(&blob_abs)
+            .await
+            .map_err(|err| recode_failure(image::ImageError::IoError(err)))?;
+        let img = heif::decode(&buf).map_err(recode_failure)?;
+        let mut encoded = Vec::new();
+        img.write_to(
+            &mut encoded,
+            image::ImageOutputFormat::JPEG(BALANCED_IMAGE_QUALITY),
+        )
+        .map_err(recode_failure)?;
+
+        let stem = self
+            .as_file_name()
+            .splitn(2, '.')
+            .next()
+            .unwrap_or_default();
+        BlobObject::create(context, format!("{}.jpg", stem), &encoded).await
     }
 }
 
@@ -470,6 +576,19 @@ mod tests {
 
     use crate::test_utils::*;
 
+    #[async_std::test]
+    async fn test_has_same_content() {
+        let t = dummy_context().await;
+        let foo = BlobObject::create(&t.ctx, "foo", b"hello").await.unwrap();
+        let foo2 = BlobObject::create(&t.ctx, "foo", b"hello").await.unwrap();
+        let bar = BlobObject::create(&t.ctx, "bar", b"world").await.unwrap();
+        assert_ne!(foo.as_name(), foo2.as_name());
+        assert!(foo.has_same_content(&foo2).await);
+        assert!(!foo.has_same_content(&bar).await);
+        let missing = BlobObject::from_name(&t.ctx, "missing".to_string()).unwrap();
+        assert!(!missing.has_same_content(&foo).await);
+    }
+
     #[async_std::test]
     async fn test_create() {
         let t = dummy_context().await;
@@ -611,6 +730,36 @@ mod tests {
         let data = fs::read(blob.to_abs_path()).await.unwrap();
         assert_eq!(data, b"boo");
     }
+    #[async_std::test]
+    async fn test_recode_to_image_size() {
+        let t = dummy_context().await;
+        let img = image::DynamicImage::new_rgb8(2000, 1000);
+        let path = t.ctx.get_blobdir().join("image.jpg");
+        img.save(&path).unwrap();
+
+        let blob = BlobObject::from_path(&t.ctx, &path).unwrap();
+        t.ctx
+            .set_config(Config::MediaQuality, Some("2"))
+            .await
+            .unwrap();
+        blob.recode_to_image_size(&t.ctx).await.unwrap();
+        assert_eq!(image::open(&path).unwrap().dimensions(), (2000, 1000));
+
+        t.ctx
+            .set_config(Config::MediaQuality, Some("0"))
+            .await
+            .unwrap();
+        blob.recode_to_image_size(&t.ctx).await.unwrap();
+        assert_eq!(image::open(&path).unwrap().dimensions(), (1280, 640));
+
+        t.ctx
+            .set_config(Config::MediaQuality, Some("1"))
+            .await
+            .unwrap();
+        blob.recode_to_image_size(&t.ctx).await.unwrap();
+        assert_eq!(image::open(&path).unwrap().dimensions(), (640, 320));
+    }
+
     #[async_std::test]
     async fn test_create_from_name_long() {
         let t = dummy_context().await;
@@ -0,0 +1,385 @@
+//! # At-rest encryption of attachments
+//!
+//! If [Config::EncryptBlobs] is enabled and the database is encrypted, the
+//! files attached to messages are stored as OpenPGP messages symmetrically
+//! encrypted with the database passphrase and prefixed by [MAGIC].
+//! `dc_read_file()` decrypts them transparently,
+//! `Message::get_decrypted_file()` returns a decrypted copy in
+//! [DECRYPTED_DIR].  Copies not used anymore are removed by housekeeping,
+//! all copies are removed when the context is opened or dropped.
+//!
+//! Avatars and other blobs not attached to messages are not encrypted.
+
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
+use async_std::fs;
+use async_std::io::ReadExt;
+use async_std::path::{Path, PathBuf};
+use async_std::prelude::*;
+
+use crate::config::Config;
+use crate::constants::DC_CHAT_ID_TRASH;
+use crate::context::Context;
+use crate::dc_tools::*;
+use crate::error::{ensure, format_err, Result};
+use crate::param::{Param, Params};
+use crate::pgp;
+
+/// Prefix of encrypted blobs.
+const MAGIC: &[u8] = b"DCBLOBENC1\n";
+
+/// Directory in the blobdir containing decrypted copies of blobs.
+pub(crate) const DECRYPTED_DIR: &str = ".decrypted";
+
+/// Decrypted copies not used for this time are removed by housekeeping.
+const DECRYPTED_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Params of a message referring to encrypted blobs.
+const MSG_BLOB_PARAMS: [Param; 2] = [Param::File, Param::Thumbnail];
+
+pub(crate) fn is_encrypted(buf: &[u8]) -> bool {
+    buf.starts_with(MAGIC)
+}
+
+async fn is_encrypted_file(path: &Path) -> bool {
+    let file = match fs::File::open(path).await {
+        Ok(file) => file,
+        Err(_) => return false,
+    };
+    let mut buf = Vec::new();
+    match file.take(MAGIC.len() as u64).read_to_end(&mut buf).await {
+        Ok(_) => is_encrypted(&buf),
+        Err(_) => false,
+    }
+}
+
+async fn encrypt(passphrase: &str, plain: &[u8]) -> Result<Vec<u8>> {
+    let mut buf = MAGIC.to_vec();
+    buf.extend(pgp::symm_encrypt_bytes(passphrase, plain).await?);
+    Ok(buf)
+}
+
+async fn decrypt(passphrase: &str, buf: &[u8]) -> Result<Vec<u8>> {
+    ensure!(is_encrypted(buf), "Blob is not encrypted");
+    pgp::symm_decrypt_bytes(passphrase, &buf[MAGIC.len()..]).await
+}
+
+/// Returns the passphrase to encrypt blobs with if blob encryption is enabled.
+async fn encryption_passphrase(context: &Context) -> Option<String> {
+    if context.get_config_bool(Config::EncryptBlobs).await {
+        context.sql.passphrase().await
+    } else {
+        None
+    }
+}
+
+/// Decrypts the content of an encrypted blob, other data is returned as is.
+pub(crate) async fn decrypt_if_encrypted(context: &Context, buf: Vec<u8>) -> Result<Vec<u8>> {
+    if !is_encrypted(&buf) {
+        return Ok(buf);
+    }
+    let passphrase = context
+        .sql
+        .passphrase()
+        .await
+        .ok_or_else(|| format_err!("Cannot decrypt blob, the database has no passphrase"))?;
+    decrypt(&passphrase, &buf).await
+}
+
+/// Encrypts the data of a new blob if blob encryption is enabled.
+pub(crate) async fn encrypt_if_enabled(context: &Context, buf: Vec<u8>) -> Result<Vec<u8>> {
+    match encryption_passphrase(context).await {
+        Some(passphrase) => encrypt(&passphrase, &buf).await,
+        None => Ok(buf),
+    }
+}
+
+/// Encrypts the files of a message if blob encryption is enabled.
+///
+/// Files already encrypted are not changed.
+pub(crate) async fn encrypt_msg_blobs(context: &Context, param: &Params) -> Result<()> {
+    let passphrase = match encryption_passphrase(context).await {
+        Some(passphrase) => passphrase,
+        None => return Ok(()),
+    };
+    for key in MSG_BLOB_PARAMS.iter() {
+        if let Some(file) = param.get(*key) {
+            if file.starts_with("$BLOBDIR/") {
+                let path = dc_get_abs_path(context, file);
+                recrypt_file(&path, Some(&passphrase), Some(&passphrase)).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the file using a temporary file, so that a blob is never left
+/// half-written.
+async fn replace_file(path: &Path, buf: &[u8]) -> Result<()> {
+    let mut tmp = path.as_os_str().to_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    fs::write(&tmp, buf).await?;
+    fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+/// Re-encrypts a file from the `old` to the `new` passphrase,
+/// `None` meaning unencrypted.
+///
+/// Returns true if the file was changed.
+async fn recrypt_file(path: &Path, old: Option<&str>, new: Option<&str>) -> Result<bool> {
+    if old == new && is_encrypted_file(path).await {
+        return Ok(false);
+    }
+    let buf = fs::read(path).await?;
+    let plain = if is_encrypted(&buf) {
+        let old = old.ok_or_else(|| {
+            format_err!(
+                "{} is encrypted, but there is no passphrase",
+                path.display()
+            )
+        })?;
+        decrypt(old, &buf).await?
+    } else if new.is_none() {
+        return Ok(false);
+    } else {
+        buf
+    };
+    let buf = match new {
+        Some(new) => encrypt(new, &plain).await?,
+        None => plain,
+    };
+    replace_file(path, &buf).await?;
+    Ok(true)
+}
+
+/// Returns the paths of all blobs attached to messages.
+async fn msg_blobs(context: &Context) -> Result<Vec<PathBuf>> {
+    let params = context
+        .sql
+        .query_map(
+            "SELECT param FROM msgs WHERE chat_id!=?;",
+            paramsv![DC_CHAT_ID_TRASH],
+            |row| row.get::<_, String>(0),
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+
+    // forwarded messages share their files
+    let mut files = HashSet::new();
+    for param in params {
+        let param: Params = param.parse().unwrap_or_default();
+        for key in MSG_BLOB_PARAMS.iter() {
+            if let Some(file) = param.get(*key) {
+                if file.starts_with("$BLOBDIR/") {
+                    files.insert(file.to_string());
+                }
+            }
+        }
+    }
+
+    let mut paths = Vec::with_capacity(files.len());
+    for file in files {
+        let path = dc_get_abs_path(context, file);
+        if path.is_file().await {
+            paths.push(path);
+        }
+    }
+    Ok(paths)
+}
+
+/// Re-encrypts all encrypted blobs after the database passphrase changed.
+///
+/// With `new` set to `None`, the blobs are decrypted.
+pub(crate) async fn rekey_blobs(
+    context: &Context,
+    old: Option<&str>,
+    new: Option<&str>,
+) -> Result<()> {
+    if old == new {
+        return Ok(());
+    }
+    let mut failed = 0;
+    for path in msg_blobs(context).await? {
+        if !is_encrypted_file(&path).await {
+            continue;
+        }
+        if let Err(err) = recrypt_file(&path, old, new).await {
+            warn!(context, "Cannot re-key {}: {}", path.display(), err);
+            failed += 1;
+        }
+    }
+    ensure!(failed == 0, "Cannot re-key {} attachments", failed);
+    Ok(())
+}
+
+/// Returns a path to the plaintext of the file.
+///
+/// Unencrypted files are returned as is, encrypted ones are decrypted to
+/// [DECRYPTED_DIR] unless an up-to-date copy exists already.
+pub(crate) async fn decrypted_path(context: &Context, path: PathBuf) -> Result<PathBuf> {
+    if !is_encrypted_file(&path).await {
+        return Ok(path);
+    }
+    let name = path
+        .file_name()
+        .ok_or_else(|| format_err!("{} has no file name", path.display()))?;
+    let dir = context.get_blobdir().join(DECRYPTED_DIR);
+    let decrypted = dir.join(name);
+
+    if let (Ok(meta), Ok(decrypted_meta)) =
+        (fs::metadata(&path).await, fs::metadata(&decrypted).await)
+    {
+        if let (Ok(modified), Ok(decrypted_modified)) = (meta.modified(), decrypted_meta.modified())
+        {
+            if decrypted_modified >= modified {
+                return Ok(decrypted);
+            }
+        }
+    }
+
+    fs::create_dir_all(&dir).await?;
+    let plain = decrypt_if_encrypted(context, fs::read(&path).await?).await?;
+    replace_file(&decrypted, &plain).await?;
+    Ok(decrypted)
+}
+
+/// Removes all decrypted copies in `blobdir`.
+///
+/// This is synchronous as it is also called when the context is dropped.
+pub(crate) fn remove_decrypted(blobdir: &Path) {
+    std::fs::remove_dir_all(blobdir.join(DECRYPTED_DIR)).ok();
+}
+
+/// Removes decrypted copies not used for [DECRYPTED_MAX_AGE].
+pub(crate) async fn prune_decrypted(context: &Context) {
+    let dir = context.get_blobdir().join(DECRYPTED_DIR);
+    let mut entries = match fs::read_dir(&dir).await {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    let keep_newer_than = SystemTime::now() - DECRYPTED_MAX_AGE;
+    while let Some(Ok(entry)) = entries.next().await {
+        let recently_used = match entry.metadata().await {
+            Ok(meta) => meta
+                .accessed()
+                .or_else(|_| meta.modified())
+                .map(|time| time > keep_newer_than)
+                .unwrap_or_default(),
+            Err(_) => false,
+        };
+        if !recently_used {
+            dc_delete_file(context, entry.path()).await;
+        }
+    }
+}
+
+impl Context {
+    /// Encrypts the attachments of all messages and enables
+    /// [Config::EncryptBlobs].
+    ///
+    /// The database must be encrypted, the attachments are encrypted with
+    /// its passphrase.  Returns the number of encrypted files.
+    pub async fn encrypt_blobs(&self) -> Result<usize> {
+        let passphrase =
+            self.sql.passphrase().await.ok_or_else(|| {
+                format_err!("Blobs can only be encrypted with an encrypted database")
+            })?;
+        self.set_config(Config::EncryptBlobs, Some("1")).await?;
+
+        let mut count = 0;
+        let mut failed = 0;
+        for path in msg_blobs(self).await? {
+            match recrypt_file(&path, Some(&passphrase), Some(&passphrase)).await {
+                Ok(true) => count += 1,
+                Ok(false) => {}
+                Err(err) => {
+                    warn!(self, "Cannot encrypt {}: {}", path.display(), err);
+                    failed += 1;
+                }
+            }
+        }
+        ensure!(failed == 0, "Cannot encrypt {} attachments", failed);
+        info!(self, "Encrypted {} attachments.", count);
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::*;
+
+    #[async_std::test]
+    async fn test_encrypt_decrypt() {
+        let encrypted = encrypt("foo", b"hello").await.unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(decrypt("foo", &encrypted).await.unwrap(), b"hello");
+        assert!(decrypt("bar", &encrypted).await.is_err());
+        assert!(decrypt("foo", b"hello").await.is_err());
+    }
+
+    #[async_std::test]
+    async fn test_recrypt_file() {
+        let t = dummy_context().await;
+        let path = t.ctx.get_blobdir().join("foo.txt");
+        fs::write(&path, b"hello").await.unwrap();
+
+        // nothing to do without passphrases
+        assert!(!recrypt_file(&path, None, None).await.unwrap());
+
+        assert!(recrypt_file(&path, Some("foo"), Some("foo")).await.unwrap());
+        assert!(is_encrypted_file(&path).await);
+        // already encrypted
+        assert!(!recrypt_file(&path, Some("foo"), Some("foo")).await.unwrap());
+
+        assert!(recrypt_file(&path, Some("foo"), Some("bar")).await.unwrap());
+        let buf = fs::read(&path).await.unwrap();
+        assert_eq!(decrypt("bar", &buf).await.unwrap(), b"hello");
+        assert!(recrypt_file(&path, Some("foo"), None).await.is_err());
+
+        assert!(recrypt_file(&path, Some("bar"), None).await.unwrap());
+        assert_eq!(fs::read(&path).await.unwrap(), b"hello");
+    }
+
+    #[async_std::test]
+    async fn test_unencrypted_blobs() {
+        let t = dummy_context().await;
+        assert_eq!(
+            decrypt_if_encrypted(&t.ctx, b"hello".to_vec())
+                .await
+                .unwrap(),
+            b"hello"
+        );
+        assert_eq!(
+            encrypt_if_enabled(&t.ctx, b"hello".to_vec()).await.unwrap(),
+            b"hello"
+        );
+        let encrypted = encrypt("foo", b"hello").await.unwrap();
+        assert!(decrypt_if_encrypted(&t.ctx, encrypted).await.is_err());
+
+        let path = t.ctx.get_blobdir().join("foo.txt");
+        fs::write(&path, b"hello").await.unwrap();
+        assert_eq!(decrypted_path(&t.ctx, path.clone()).await.unwrap(), path);
+
+        // encryption needs an encrypted database
+        assert!(t.ctx.encrypt_blobs().await.is_err());
+    }
+
+    #[async_std::test]
+    async fn test_remove_decrypted() {
+        let t = dummy_context().await;
+        let dir = t.ctx.get_blobdir().join(DECRYPTED_DIR);
+        fs::create_dir_all(&dir).await.unwrap();
+        fs::write(dir.join("foo.txt"), b"hello").await.unwrap();
+        remove_decrypted(t.ctx.get_blobdir());
+        assert!(!dir.exists().await);
+        assert!(t.ctx.get_blobdir().exists().await);
+    }
+}
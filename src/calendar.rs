@@ -0,0 +1,652 @@
+//! # Calendar invitations
+//!
+//! Invitations are received as `text/calendar` parts (iMIP, RFC 6047)
+//! containing an iCalendar (RFC 5545) `VEVENT`.  They are shown as messages
+//! of type [Viewtype::Calendar], the parsed event is stored in the params
+//! and the `.ics` file is kept as attachment so that it can be imported
+//! into calendar apps.  Responses to an invitation are sent as iTIP
+//! (RFC 5546) `REPLY` to the organizer of the event.
+
+use serde::{Deserialize, Serialize};
+
+use crate::chat;
+use crate::config::Config;
+use crate::constants::*;
+use crate::contact::{addr_cmp, Contact, Origin};
+use crate::context::Context;
+use crate::dc_tools::dc_gm2local_offset;
+use crate::error::{bail, ensure, format_err, Result};
+use crate::events::Event;
+use crate::message::{Message, MsgId};
+use crate::param::*;
+use crate::stock::StockMessage;
+
+/// An event parsed from an iCalendar object.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalendarEvent {
+    /// iTIP method, eg. `REQUEST` for invitations or `CANCEL` for cancellations.
+    pub method: String,
+    pub uid: String,
+    pub sequence: u32,
+
+    /// Title of the event.
+    pub summary: String,
+    pub description: Option<String>,
+    pub location: Option<String>,
+
+    /// Start of the event as UTC timestamp.
+    pub start: i64,
+
+    /// End of the event as UTC timestamp, if known.
+    pub end: Option<i64>,
+
+    /// True if the event takes whole days, `start` is midnight UTC of the first day then.
+    pub all_day: bool,
+
+    /// Address of the organizer.
+    pub organizer: Option<String>,
+    pub organizer_name: Option<String>,
+}
+
+/// Response to an invitation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, Serialize, Deserialize)]
+#[repr(i32)]
+#[serde(rename_all = "snake_case")]
+pub enum Rsvp {
+    Accept = 1,
+    Decline = 2,
+    Tentative = 3,
+}
+
+impl Rsvp {
+    fn partstat(self) -> &'static str {
+        match self {
+            Rsvp::Accept => "ACCEPTED",
+            Rsvp::Decline => "DECLINED",
+            Rsvp::Tentative => "TENTATIVE",
+        }
+    }
+
+    fn stock_message(self) -> StockMessage {
+        match self {
+            Rsvp::Accept => StockMessage::InviteAccepted,
+            Rsvp::Decline => StockMessage::InviteDeclined,
+            Rsvp::Tentative => StockMessage::InviteTentative,
+        }
+    }
+}
+
+/// A content line of an iCalendar object.
+#[derive(Debug, PartialEq, Eq)]
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Joins folded lines, see RFC 5545, 3.1.
+fn unfold(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.split('\n') {
+        let line = line.trim_end_matches('\r');
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(&line[1..]);
+            }
+        } else if !line.is_empty() {
+            lines.push(line.to_string());
+        }
+    }
+    lines
+}
+
+/// Splits `text` at `separator`, ignoring separators inside double quotes.
+fn split_unquoted(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        } else if c == separator && !quoted {
+            parts.push(&text[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+fn parse_property(line: &str) -> Option<Property> {
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(i, c)| {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if c == ':' && !quoted {
+            Some(i)
+        } else {
+            None
+        }
+    })?;
+    let mut head = split_unquoted(&line[..colon], ';').into_iter();
+    let name = head.next()?.trim().to_uppercase();
+    let params = head
+        .filter_map(|param| {
+            let pos = param.find('=')?;
+            Some((
+                param[..pos].trim().to_uppercase(),
+                param[pos + 1..].trim().trim_matches('"').to_string(),
+            ))
+        })
+        .collect();
+    Some(Property {
+        name,
+        params,
+        value: line[colon + 1..].to_string(),
+    })
+}
+
+fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => unescaped.push('\n'),
+                Some(c) => unescaped.push(c),
+                None => {}
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    unescaped
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Parses a DATE or DATE-TIME value, returns the UTC timestamp and
+/// whether the value is a date.
+///
+/// Times with a `TZID` or without time zone are interpreted as local time
+/// as there is no time zone database available.
+fn parse_datetime(property: &Property) -> Option<(i64, bool)> {
+    let value = property.value.trim();
+    if property.param("VALUE") == Some("DATE") || value.len() == 8 {
+        let date = chrono::NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        Some((date.and_hms(0, 0, 0).timestamp(), true))
+    } else if value.ends_with('Z') {
+        let datetime = chrono::NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ").ok()?;
+        Some((datetime.timestamp(), false))
+    } else {
+        let datetime = chrono::NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+        Some((datetime.timestamp() - dc_gm2local_offset(), false))
+    }
+}
+
+/// Parses a duration such as `PT1H30M` or `P1D` to seconds.
+fn parse_duration(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (sign, value) = if value.starts_with('-') {
+        (-1, &value[1..])
+    } else if value.starts_with('+') {
+        (1, &value[1..])
+    } else {
+        (1, value)
+    };
+    if !value.starts_with('P') {
+        return None;
+    }
+    let mut seconds = 0;
+    let mut number = String::new();
+    for c in value[1..].chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => {}
+            'W' | 'D' | 'H' | 'M' | 'S' => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                seconds += n * match c {
+                    'W' => 7 * 86400,
+                    'D' => 86400,
+                    'H' => 3600,
+                    'M' => 60,
+                    _ => 1,
+                };
+            }
+            _ => return None,
+        }
+    }
+    Some(sign * seconds)
+}
+
+fn strip_mailto(value: &str) -> String {
+    let value = value.trim();
+    match value.get(..7) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("mailto:") => value[7..].to_string(),
+        _ => value.to_string(),
+    }
+}
+
+/// Parses the first event of an iCalendar object.
+pub(crate) fn parse_ics(ics: &str) -> Result<CalendarEvent> {
+    let mut event = CalendarEvent::default();
+    let mut components = Vec::new();
+    let mut has_event = false;
+    let mut has_start = false;
+    let mut duration = None;
+
+    for line in unfold(ics) {
+        let property = match parse_property(&line) {
+            Some(property) => property,
+            None => continue,
+        };
+        match property.name.as_str() {
+            "BEGIN" => {
+                let component = property.value.trim().to_uppercase();
+                if component == "VEVENT" && components.len() == 1 && has_event {
+                    // only the first event is used
+                    break;
+                }
+                components.push(component);
+                continue;
+            }
+            "END" => {
+                if components.pop().as_deref() == Some("VEVENT") && components.len() == 1 {
+                    has_event = true;
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let component = components.iter().map(|c| c.as_str()).collect::<Vec<_>>();
+        match (component.as_slice(), property.name.as_str()) {
+            (["VCALENDAR"], "METHOD") => event.method = property.value.trim().to_uppercase(),
+            (["VCALENDAR", "VEVENT"], name) => match name {
+                "UID" => event.uid = property.value.trim().to_string(),
+                "SEQUENCE" => event.sequence = property.value.trim().parse().unwrap_or_default(),
+                "SUMMARY" => event.summary = unescape_text(&property.value),
+                "DESCRIPTION" => event.description = Some(unescape_text(&property.value)),
+                "LOCATION" => event.location = Some(unescape_text(&property.value)),
+                "DTSTART" => {
+                    let (start, all_day) = parse_datetime(&property)
+                        .ok_or_else(|| format_err!("Invalid DTSTART {:?}", property.value))?;
+                    event.start = start;
+                    event.all_day = all_day;
+                    has_start = true;
+                }
+                "DTEND" => event.end = parse_datetime(&property).map(|(end, _)| end),
+                "DURATION" => duration = parse_duration(&property.value),
+                "ORGANIZER" => {
+                    event.organizer = Some(strip_mailto(&property.value));
+                    event.organizer_name = property.param("CN").map(|name| name.to_string());
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    ensure!(has_event, "No VEVENT found");
+    ensure!(!event.uid.is_empty(), "Event without UID");
+    ensure!(has_start, "Event without DTSTART");
+    if event.end.is_none() {
+        event.end = duration.map(|duration| event.start + duration);
+    }
+    if event.method.is_empty() {
+        event.method = "PUBLISH".to_string();
+    }
+    Ok(event)
+}
+
+/// Folds a content line to lines of at most 75 octets, see RFC 5545, 3.1.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + line.len() / 70 * 3);
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            len = 1;
+        }
+        folded.push(c);
+        len += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+/// Quotes a parameter value if needed, double quotes are not allowed in values.
+fn param_value(value: &str) -> String {
+    let value = value.replace('"', "");
+    if value.contains(|c| c == ':' || c == ';' || c == ',') {
+        format!("\"{}\"", value)
+    } else {
+        value
+    }
+}
+
+fn format_datetime(timestamp: i64, all_day: bool) -> String {
+    let datetime = chrono::NaiveDateTime::from_timestamp(timestamp, 0);
+    if all_day {
+        format!(";VALUE=DATE:{}", datetime.format("%Y%m%d"))
+    } else {
+        format!(":{}", datetime.format("%Y%m%dT%H%M%SZ"))
+    }
+}
+
+/// Builds the iTIP `REPLY` of the attendee `addr` to `event`.
+pub(crate) fn build_reply(
+    event: &CalendarEvent,
+    rsvp: Rsvp,
+    addr: &str,
+    name: &str,
+    timestamp: i64,
+) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Delta Chat//Delta Chat Core//EN".to_string(),
+        "METHOD:REPLY".to_string(),
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}", event.uid),
+        format!("SEQUENCE:{}", event.sequence),
+        format!("DTSTAMP{}", format_datetime(timestamp, false)),
+        format!("DTSTART{}", format_datetime(event.start, event.all_day)),
+    ];
+    if let Some(end) = event.end {
+        lines.push(format!("DTEND{}", format_datetime(end, event.all_day)));
+    }
+    lines.push(format!("SUMMARY:{}", escape_text(&event.summary)));
+    if let Some(organizer) = &event.organizer {
+        match &event.organizer_name {
+            Some(organizer_name) => lines.push(format!(
+                "ORGANIZER;CN={}:mailto:{}",
+                param_value(organizer_name),
+                organizer
+            )),
+            None => lines.push(format!("ORGANIZER:mailto:{}", organizer)),
+        }
+    }
+    let cn = if name.is_empty() {
+        String::new()
+    } else {
+        format!(";CN={}", param_value(name))
+    };
+    lines.push(format!(
+        "ATTENDEE;PARTSTAT={}{}:mailto:{}",
+        rsvp.partstat(),
+        cn,
+        addr
+    ));
+    lines.push("END:VEVENT".to_string());
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| fold(line)).collect()
+}
+
+/// Returns the event stored in the params of a message.
+pub(crate) fn get_calendar_event(param: &Params) -> Option<CalendarEvent> {
+    param
+        .get(Param::CalendarEvent)
+        .and_then(|event| serde_json::from_str(event).ok())
+}
+
+impl MsgId {
+    /// Responds to the invitation `self` of type [Viewtype::Calendar].
+    ///
+    /// The response is sent as iTIP `REPLY` to the organizer of the event,
+    /// in the chat with the organizer, and remembered in the invitation,
+    /// see [Message::get_invite_response].
+    /// Returns the ID of the message carrying the response.
+    pub async fn respond_to_invite(self, context: &Context, rsvp: Rsvp) -> Result<MsgId> {
+        let mut msg = Message::load_from_db(context, self).await?;
+        ensure!(
+            msg.viewtype == Viewtype::Calendar,
+            "Message {} is no invitation",
+            self
+        );
+        let event = get_calendar_event(&msg.param)
+            .ok_or_else(|| format_err!("Invitation {} without event", self))?;
+        ensure!(
+            event.method == "REQUEST",
+            "Cannot respond to {} message {}",
+            event.method,
+            self
+        );
+        let organizer = match &event.organizer {
+            Some(organizer) => organizer,
+            None => bail!("Invitation {} without organizer", self),
+        };
+        let self_addr = context
+            .get_config(Config::ConfiguredAddr)
+            .await
+            .unwrap_or_default();
+        ensure!(
+            !addr_cmp(organizer, &self_addr),
+            "Cannot respond to own invitation {}",
+            self
+        );
+
+        let from = Contact::load_from_db(context, msg.from_id).await?;
+        let contact_id = if addr_cmp(organizer, from.get_addr()) {
+            msg.from_id
+        } else {
+            let name = event.organizer_name.as_deref().unwrap_or_default();
+            Contact::add_or_lookup(context, name, organizer, Origin::IncomingUnknownFrom)
+                .await?
+                .0
+        };
+        let chat_id = chat::create_by_contact_id(context, contact_id).await?;
+
+        let mut reply = Message::new(Viewtype::Text);
+        reply.text = Some(
+            context
+                .stock_string_repl_str(rsvp.stock_message(), &event.summary)
+                .await,
+        );
+        reply
+            .param
+            .set(Param::CalendarEvent, serde_json::to_string(&event)?);
+        reply.param.set_int(Param::CalendarRsvp, rsvp as i32);
+        let reply_id = chat::send_msg(context, chat_id, &mut reply).await?;
+
+        msg.param.set_int(Param::CalendarRsvp, rsvp as i32);
+        msg.save_param_to_disk(context).await;
+        context.emit_event(Event::MsgsChanged {
+            chat_id: msg.chat_id,
+            msg_id: self,
+        });
+        Ok(reply_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::dc_receive_imf::dc_receive_imf;
+    use crate::message;
+    use crate::mimefactory::MimeFactory;
+    use crate::mimeparser::MimeMessage;
+    use crate::test_utils::*;
+
+    const INVITE: &str = "BEGIN:VCALENDAR\r\n\
+        PRODID:-//Google Inc//Google Calendar 70.9054//EN\r\n\
+        VERSION:2.0\r\n\
+        METHOD:REQUEST\r\n\
+        BEGIN:VTIMEZONE\r\n\
+        TZID:Europe/Berlin\r\n\
+        END:VTIMEZONE\r\n\
+        BEGIN:VEVENT\r\n\
+        DTSTART:20200612T100000Z\r\n\
+        DURATION:PT1H30M\r\n\
+        DTSTAMP:20200601T080000Z\r\n\
+        ORGANIZER;CN=\"Bob, the organizer\":mailto:bob@example.net\r\n\
+        UID:abc123@example.net\r\n\
+        ATTENDEE;PARTSTAT=NEEDS-ACTION;CN=alice@example.com:mailto:alice@example.com\r\n\
+        SEQUENCE:2\r\n\
+        SUMMARY:Project meeting\\, part 2\r\n\
+        DESCRIPTION:Let's discuss\\nthe next steps.\r\n\
+        LOCATION:Room 1\r\n\
+        BEGIN:VALARM\r\n\
+        DESCRIPTION:Reminder\r\n\
+        END:VALARM\r\n\
+        END:VEVENT\r\n\
+        END:VCALENDAR\r\n";
+
+    #[test]
+    fn test_parse_ics() {
+        let event = parse_ics(INVITE).unwrap();
+        assert_eq!(event.method, "REQUEST");
+        assert_eq!(event.uid, "abc123@example.net");
+        assert_eq!(event.sequence, 2);
+        assert_eq!(event.summary, "Project meeting, part 2");
+        assert_eq!(
+            event.description.as_deref(),
+            Some("Let's discuss\nthe next steps.")
+        );
+        assert_eq!(event.location.as_deref(), Some("Room 1"));
+        assert_eq!(event.start, 1591956000);
+        assert_eq!(event.end, Some(1591956000 + 5400));
+        assert!(!event.all_day);
+        assert_eq!(event.organizer.as_deref(), Some("bob@example.net"));
+        assert_eq!(event.organizer_name.as_deref(), Some("Bob, the organizer"));
+
+        let event = parse_ics(
+            "BEGIN:VCALENDAR\nBEGIN:VEVENT\nUID:x\nDTSTART;VALUE=DATE:20200612\nSUMMARY:Holi\n day\nEND:VEVENT\nEND:VCALENDAR\n",
+        )
+        .unwrap();
+        assert_eq!(event.method, "PUBLISH");
+        assert_eq!(event.summary, "Holiday");
+        assert!(event.all_day);
+        assert_eq!(event.start, 1591920000);
+        assert_eq!(event.end, None);
+
+        assert!(parse_ics("BEGIN:VCALENDAR\nEND:VCALENDAR\n").is_err());
+        assert!(
+            parse_ics("BEGIN:VCALENDAR\nBEGIN:VEVENT\nUID:x\nEND:VEVENT\nEND:VCALENDAR").is_err()
+        );
+    }
+
+    #[test]
+    fn test_build_reply() {
+        let event = parse_ics(INVITE).unwrap();
+        let reply = build_reply(
+            &event,
+            Rsvp::Tentative,
+            "alice@example.com",
+            "Alice",
+            1591000000,
+        );
+        assert!(reply.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(reply.contains("\r\nMETHOD:REPLY\r\n"));
+        assert!(reply.contains("\r\nUID:abc123@example.net\r\n"));
+        assert!(reply.contains("\r\nSEQUENCE:2\r\n"));
+        assert!(reply.contains("\r\nDTSTART:20200612T100000Z\r\n"));
+        assert!(reply.contains("\r\nSUMMARY:Project meeting\\, part 2\r\n"));
+        assert!(
+            reply.contains("\r\nATTENDEE;PARTSTAT=TENTATIVE;CN=Alice:mailto:alice@example.com\r\n")
+        );
+        assert!(reply.lines().all(|line| line.len() <= 76));
+
+        let parsed = parse_ics(&reply).unwrap();
+        assert_eq!(parsed.method, "REPLY");
+        assert_eq!(parsed.uid, event.uid);
+        assert_eq!(parsed.organizer_name, event.organizer_name);
+
+        let long = "a".repeat(100);
+        assert_eq!(unfold(&fold(&long)), vec![long]);
+    }
+
+    #[async_std::test]
+    async fn test_respond_to_invite() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        t.ctx
+            .set_config(Config::ShowEmails, Some("2"))
+            .await
+            .unwrap();
+        let raw = format!(
+            "From: Bob <bob@example.net>\n\
+             To: alice@example.com\n\
+             Subject: Invitation: Project meeting\n\
+             Message-ID: <invite@example.net>\n\
+             Date: Mon, 1 Jun 2020 08:00:00 +0000\n\
+             MIME-Version: 1.0\n\
+             Content-Type: multipart/alternative; boundary=\"b\"\n\
+             \n\
+             --b\n\
+             Content-Type: text/plain; charset=utf-8\n\
+             \n\
+             You have been invited.\n\
+             --b\n\
+             Content-Type: text/calendar; charset=utf-8; method=REQUEST\n\
+             \n\
+             {}\
+             --b--\n",
+            INVITE
+        );
+        dc_receive_imf(&t.ctx, raw.as_bytes(), "INBOX", 1, false)
+            .await
+            .unwrap();
+        let msg_id = message::rfc724_mid_exists(&t.ctx, "invite@example.net")
+            .await
+            .unwrap()
+            .unwrap()
+            .2;
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_viewtype(), Viewtype::Calendar);
+        assert_eq!(msg.get_text().as_deref(), Some("Project meeting, part 2"));
+        assert_eq!(msg.get_calendar_event().unwrap().uid, "abc123@example.net");
+        assert!(msg.get_file(&t.ctx).is_some());
+        assert_eq!(msg.get_invite_response(), None);
+
+        let reply_id = msg_id
+            .respond_to_invite(&t.ctx, Rsvp::Accept)
+            .await
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_invite_response(), Some(Rsvp::Accept));
+        assert!(msg_id
+            .respond_to_invite(&t.ctx, Rsvp::Decline)
+            .await
+            .is_ok());
+
+        let reply = Message::load_from_db(&t.ctx, reply_id).await.unwrap();
+        assert_eq!(
+            reply.get_text().as_deref(),
+            Some("Accepted: Project meeting, part 2")
+        );
+        let rendered = MimeFactory::from_msg(&t.ctx, &reply, false)
+            .await
+            .unwrap()
+            .render()
+            .await
+            .unwrap();
+        let rendered = String::from_utf8_lossy(&rendered.message);
+        assert!(rendered.contains("text/calendar; method=REPLY"));
+
+        // the reply is not shown as invitation by the organizer
+        let parsed = MimeMessage::from_bytes(&t.ctx, rendered.as_bytes())
+            .await
+            .unwrap();
+        assert_eq!(parsed.parts.len(), 1);
+        assert_eq!(parsed.parts[0].typ, Viewtype::Text);
+    }
+}
@@ -4,25 +4,35 @@ use std::convert::TryFrom;
 use std::time::{Duration, SystemTime};
 
 use async_std::path::{Path, PathBuf};
+use async_std::prelude::*;
 use itertools::Itertools;
 use num_traits::FromPrimitive;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
+use crate::app;
 use crate::blob::{BlobError, BlobObject};
+use crate::blob_crypt;
 use crate::chatlist::*;
 use crate::config::*;
 use crate::constants::*;
 use crate::contact::*;
 use crate::context::Context;
+use crate::dc_receive_imf::dc_receive_imf;
 use crate::dc_tools::*;
+use crate::ephemeral;
 use crate::error::{bail, ensure, format_err, Error};
 use crate::events::Event;
+use crate::headerdef::HeaderDef;
 use crate::job::{self, Action};
 use crate::message::{self, InvalidMsgId, Message, MessageState, MsgId};
-use crate::mimeparser::SystemMessage;
+use crate::mimefactory;
+use crate::mimeparser::{MimeMessage, SystemMessage};
 use crate::param::*;
+use crate::poll;
 use crate::sql;
 use crate::stock::StockMessage;
+use crate::sync;
 
 /// Chat ID, including reserved IDs.
 ///
@@ -136,10 +146,21 @@ impl ChatId {
     }
 
     /// Archives or unarchives a chat.
+    ///
+    /// If enabled by [Config::SyncMsgs], the change is sent to the other devices.
     pub async fn set_visibility(
         self,
         context: &Context,
         visibility: ChatVisibility,
+    ) -> Result<(), Error> {
+        self.set_visibility_ex(context, visibility, true).await
+    }
+
+    pub(crate) async fn set_visibility_ex(
+        self,
+        context: &Context,
+        visibility: ChatVisibility,
+        sync: bool,
     ) -> Result<(), Error> {
         ensure!(
             !self.is_special(),
@@ -157,6 +178,16 @@ impl ChatId {
                 .await?;
         }
 
+        let was_archived = context
+            .sql
+            .query_get_value::<ChatVisibility>(
+                context,
+                "SELECT archived FROM chats WHERE id=?;",
+                paramsv![self],
+            )
+            .await
+            == Some(ChatVisibility::Archived);
+
         context
             .sql
             .execute(
@@ -165,14 +196,122 @@ impl ChatId {
             )
             .await?;
 
+        let is_archived = visibility == ChatVisibility::Archived;
+        if is_archived != was_archived {
+            move_chat_msgs(context, self, is_archived).await?;
+        }
+
         context.emit_event(Event::MsgsChanged {
             msg_id: MsgId::new(0),
             chat_id: ChatId::new(0),
         });
 
+        if sync {
+            let chat_ref = sync::chat_ref(context, self).await.ok().flatten();
+            sync::sync_chat(context, chat_ref, |chat| sync::SyncItem::Visibility {
+                chat,
+                visibility,
+            })
+            .await;
+        }
+        Ok(())
+    }
+
+    /// Pins a chat to the top of the chatlist.
+    ///
+    /// Archived chats are unarchived by pinning them.
+    pub async fn pin(self, context: &Context) -> Result<(), Error> {
+        self.set_visibility(context, ChatVisibility::Pinned).await
+    }
+
+    /// Unpins a chat, the chat is sorted by its last message again.
+    ///
+    /// Chats that are not pinned are not changed.
+    pub async fn unpin(self, context: &Context) -> Result<(), Error> {
+        ensure!(
+            !self.is_special(),
+            "bad chat_id, can not be special chat: {}",
+            self
+        );
+        let changed = context
+            .sql
+            .execute(
+                "UPDATE chats SET archived=? WHERE id=? AND archived=?;",
+                paramsv![ChatVisibility::Normal, self, ChatVisibility::Pinned],
+            )
+            .await?;
+        if changed > 0 {
+            context.emit_event(Event::MsgsChanged {
+                msg_id: MsgId::new(0),
+                chat_id: ChatId::new(0),
+            });
+            let chat_ref = sync::chat_ref(context, self).await.ok().flatten();
+            sync::sync_chat(context, chat_ref, |chat| sync::SyncItem::Visibility {
+                chat,
+                visibility: ChatVisibility::Normal,
+            })
+            .await;
+        }
+        Ok(())
+    }
+
+    /// Mutes or unmutes a chat.
+    ///
+    /// Messages in muted chats are not counted by `Context::get_fresh_msgs`
+    /// and their `Event::IncomingMsg` has the `muted` flag set.
+    pub async fn set_mute_duration(
+        self,
+        context: &Context,
+        duration: MuteDuration,
+    ) -> Result<(), Error> {
+        self.set_mute_duration_ex(context, duration, true).await
+    }
+
+    pub(crate) async fn set_mute_duration_ex(
+        self,
+        context: &Context,
+        duration: MuteDuration,
+        sync: bool,
+    ) -> Result<(), Error> {
+        ensure!(!self.is_special(), "Invalid chat ID");
+        if context
+            .sql
+            .execute(
+                "UPDATE chats SET muted_until=? WHERE id=?;",
+                paramsv![duration, self],
+            )
+            .await
+            .is_ok()
+        {
+            context.emit_event(Event::ChatModified(self));
+        } else {
+            bail!("Failed to set mute duration, chat might not exist -");
+        }
+        if sync {
+            let chat_ref = sync::chat_ref(context, self).await.ok().flatten();
+            sync::sync_chat(context, chat_ref, |chat| sync::SyncItem::Mute {
+                chat,
+                duration,
+            })
+            .await;
+        }
         Ok(())
     }
 
+    /// Returns true if the chat is currently muted.
+    pub async fn is_muted(self, context: &Context) -> bool {
+        context
+            .sql
+            .query_get_value::<MuteDuration>(
+                context,
+                "SELECT muted_until FROM chats WHERE id=?;",
+                paramsv![self],
+            )
+            .await
+            .map(|duration| duration.is_muted())
+            .unwrap_or_default()
+    }
+
     // note that unarchive() is not the same as set_visibility(Normal) -
     // eg. unarchive() does not modify pinned chats and does not send events.
     pub async fn unarchive(self, context: &Context) -> Result<(), Error> {
@@ -187,7 +326,13 @@ impl ChatId {
     }
 
     /// Deletes a chat.
+    ///
+    /// If enabled by [Config::SyncMsgs], the chat is also deleted on the other devices.
     pub async fn delete(self, context: &Context) -> Result<(), Error> {
+        self.delete_ex(context, true).await
+    }
+
+    pub(crate) async fn delete_ex(self, context: &Context, sync: bool) -> Result<(), Error> {
         ensure!(
             !self.is_special(),
             "bad chat_id, can not be a special chat: {}",
@@ -196,6 +341,12 @@ impl ChatId {
         /* Up to 2017-11-02 deleting a group also implied leaving it, see above why we have changed this. */
 
         let _chat = Chat::load_from_db(context, self).await?;
+        // the chat cannot be referred to once it is deleted
+        let chat_ref = if sync {
+            sync::chat_ref(context, self).await?
+        } else {
+            None
+        };
         context
             .sql
             .execute(
@@ -203,6 +354,13 @@ impl ChatId {
                 paramsv![self],
             )
             .await?;
+        context
+            .sql
+            .execute(
+                "DELETE FROM msg_recipient_status WHERE msg_id IN (SELECT id FROM msgs WHERE chat_id=?);",
+                paramsv![self],
+            )
+            .await?;
 
         context
             .sql
@@ -231,6 +389,7 @@ impl ChatId {
         let j = job::Job::new(Action::Housekeeping, 0, Params::new(), 10);
         job::add(context, j).await;
 
+        sync::sync_chat(context, chat_ref, |chat| sync::SyncItem::Delete { chat }).await;
         Ok(())
     }
 
@@ -257,9 +416,12 @@ impl ChatId {
 
     // similar to as dc_set_draft() but does not emit an event
     async fn set_draft_raw(self, context: &Context, msg: &mut Message) -> bool {
-        let deleted = self.maybe_delete_draft(context).await;
+        let (deleted, old_blobs) = self.take_draft(context).await;
         let set = self.do_set_draft(context, msg).await.is_ok();
 
+        // the new draft may use the attachment of the old one
+        delete_unreferenced_blobs(context, &old_blobs).await;
+
         // Can't inline. Both functions above must be called, no shortcut!
         deleted || set
     }
@@ -289,13 +451,33 @@ impl ChatId {
     }
 
     /// Delete draft message in specified chat, if there is one.
+    /// Attachments not used elsewhere are deleted as well.
     ///
     /// Returns `true`, if message was deleted, `false` otherwise.
-    async fn maybe_delete_draft(self, context: &Context) -> bool {
-        match self.get_draft_msg_id(context).await {
-            Some(msg_id) => msg_id.delete_from_db(context).await.is_ok(),
-            None => false,
-        }
+    pub(crate) async fn maybe_delete_draft(self, context: &Context) -> bool {
+        let (deleted, blobs) = self.take_draft(context).await;
+        delete_unreferenced_blobs(context, &blobs).await;
+        deleted
+    }
+
+    /// Deletes the draft message from the database, keeping its attachments.
+    ///
+    /// Returns `true` if a draft was deleted and the blob names of the attachments.
+    async fn take_draft(self, context: &Context) -> (bool, Vec<String>) {
+        let msg_id = match self.get_draft_msg_id(context).await {
+            Some(msg_id) => msg_id,
+            None => return (false, Vec::new()),
+        };
+        let blobs = match Message::load_from_db(context, msg_id).await {
+            Ok(msg) => [Param::File, Param::Thumbnail]
+                .iter()
+                .filter_map(|key| msg.param.get(*key))
+                .filter(|name| name.starts_with("$BLOBDIR/"))
+                .map(|name| name.to_string())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        (msg_id.delete_from_db(context).await.is_ok(), blobs)
     }
 
     /// Set provided message as draft message for specified chat.
@@ -304,7 +486,7 @@ impl ChatId {
     async fn do_set_draft(self, context: &Context, msg: &mut Message) -> Result<(), Error> {
         match msg.viewtype {
             Viewtype::Unknown => bail!("Can not set draft of unknown type."),
-            Viewtype::Text => match msg.text.as_ref() {
+            Viewtype::Text | Viewtype::Poll => match msg.text.as_ref() {
                 Some(text) => {
                     if text.is_empty() {
                         bail!("No text in draft");
@@ -313,12 +495,21 @@ impl ChatId {
                 None => bail!("No text in draft"),
             },
             _ => {
+                // attachments are copied to the blobdir right away
+                // so that the draft survives if the original file is removed
                 let blob = msg
                     .param
                     .get_blob(Param::File, context, !msg.is_increation())
                     .await?
                     .ok_or_else(|| format_err!("No file stored in params"))?;
                 msg.param.set(Param::File, blob.as_name());
+                if let Some(thumbnail) = msg
+                    .param
+                    .get_blob(Param::Thumbnail, context, !msg.is_increation())
+                    .await?
+                {
+                    msg.param.set(Param::Thumbnail, thumbnail.as_name());
+                }
             }
         }
         context
@@ -355,19 +546,17 @@ impl ChatId {
     }
 
     pub async fn get_fresh_msg_cnt(self, context: &Context) -> usize {
+        // the counter is maintained by triggers on the msgs table
         context
             .sql
             .query_get_value::<i32>(
                 context,
-                "SELECT COUNT(*)
-                FROM msgs
-                WHERE state=10
-                AND hidden=0
-                AND chat_id=?;",
+                "SELECT unread_count FROM chats WHERE id=?;",
                 paramsv![self],
             )
             .await
-            .unwrap_or_default() as usize
+            .unwrap_or_default()
+            .max(0) as usize
     }
 
     pub(crate) async fn get_param(self, context: &Context) -> Result<Params, Error> {
@@ -535,6 +724,7 @@ pub struct Chat {
     pub param: Params,
     is_sending_locations: bool,
     pub mute_duration: MuteDuration,
+    unread_count: usize,
 }
 
 impl Chat {
@@ -544,7 +734,7 @@ impl Chat {
             .sql
             .query_row(
                 "SELECT c.type, c.name, c.grpid, c.param, c.archived,
-                    c.blocked, c.locations_send_until, c.muted_until
+                    c.blocked, c.locations_send_until, c.muted_until, c.unread_count
              FROM chats c
              WHERE c.id=?;",
                 paramsv![chat_id],
@@ -559,6 +749,7 @@ impl Chat {
                         blocked: row.get::<_, Option<_>>(5)?.unwrap_or_default(),
                         is_sending_locations: row.get(6)?,
                         mute_duration: row.get(7)?,
+                        unread_count: row.get::<_, i64>(8)?.max(0) as usize,
                     };
                     Ok(c)
                 },
@@ -618,7 +809,9 @@ impl Chat {
 
     /// Returns true if user can send messages to this chat.
     pub fn can_send(&self) -> bool {
-        !self.id.is_special() && !self.is_device_talk()
+        !self.id.is_special()
+            && !self.is_device_talk()
+            && (self.typ != Chattype::Mailinglist || self.param.exists(Param::ListPost))
     }
 
     pub async fn update_param(&mut self, context: &Context) -> Result<(), Error> {
@@ -735,12 +928,23 @@ impl Chat {
         self.is_sending_locations
     }
 
+    /// Returns the number of fresh messages in the chat when it was loaded.
+    ///
+    /// This is the same as [ChatId::get_fresh_msg_cnt] without an extra query.
+    pub fn get_unread_count(&self) -> usize {
+        self.unread_count
+    }
+
     pub fn is_muted(&self) -> bool {
-        match self.mute_duration {
-            MuteDuration::NotMuted => false,
-            MuteDuration::Forever => true,
-            MuteDuration::Until(when) => when > SystemTime::now(),
-        }
+        self.mute_duration.is_muted()
+    }
+
+    /// Returns the read receipt policy set for the chat.
+    pub fn get_mdn_policy(&self) -> MdnPolicy {
+        self.param
+            .get_int(Param::MdnPolicy)
+            .and_then(MdnPolicy::from_i32)
+            .unwrap_or_default()
     }
 
     async fn prepare_msg_raw(
@@ -757,7 +961,9 @@ impl Chat {
 
         if !(self.typ == Chattype::Single
             || self.typ == Chattype::Group
-            || self.typ == Chattype::VerifiedGroup)
+            || self.typ == Chattype::VerifiedGroup
+            || self.typ == Chattype::Mailinglist
+            || self.typ == Chattype::Broadcast)
         {
             error!(context, "Cannot send to chat type #{}.", self.typ,);
             bail!("Cannot set to chat type #{}", self.typ);
@@ -905,21 +1111,38 @@ impl Chat {
                 }
             }
 
+            // reactions, app updates, votes and replies refer to the reacted,
+            // updated, voted or quoted message instead of the last one
+            if msg.param.get_cmd() == SystemMessage::Reaction
+                || msg.param.get_cmd() == SystemMessage::AppUpdate
+                || msg.param.get_cmd() == SystemMessage::PollVote
+            {
+                if let Some(rfc724_mid) = msg.param.get(Param::Arg) {
+                    new_in_reply_to = rfc724_mid.to_string();
+                }
+            } else if let Some(rfc724_mid) = msg.param.get(Param::QuotedMsgId) {
+                new_in_reply_to = rfc724_mid.to_string();
+            }
+
             // add independent location to database
 
+            let poi = msg.get_poi();
             if msg.param.exists(Param::SetLatitude)
                 && context
                     .sql
                     .execute(
                         "INSERT INTO locations \
-                     (timestamp,from_id,chat_id, latitude,longitude,independent)\
-                     VALUES (?,?,?, ?,?,1);", // 1=DC_CONTACT_ID_SELF
+                     (timestamp,from_id,chat_id, latitude,longitude,independent, label,radius,color)\
+                     VALUES (?,?,?, ?,?,1, ?,?,?);", // 1=DC_CONTACT_ID_SELF
                         paramsv![
                             timestamp,
                             DC_CONTACT_ID_SELF,
                             self.id,
                             msg.param.get_float(Param::SetLatitude).unwrap_or_default(),
                             msg.param.get_float(Param::SetLongitude).unwrap_or_default(),
+                            poi.as_ref().map(|poi| poi.label.clone()),
+                            poi.as_ref().and_then(|poi| poi.radius),
+                            poi.as_ref().and_then(|poi| poi.color),
                         ],
                     )
                     .await
@@ -938,10 +1161,18 @@ impl Chat {
                     .await?;
             }
 
+            // the timer of ephemeral messages starts when they are sent
+            msg.ephemeral_timer = self.id.get_ephemeral_timer(context).await?;
+            msg.ephemeral_timestamp = if msg.ephemeral_timer != 0 {
+                timestamp + msg.ephemeral_timer as i64
+            } else {
+                0
+            };
+
             // add message to the database
 
             if context.sql.execute(
-                        "INSERT INTO msgs (rfc724_mid, chat_id, from_id, to_id, timestamp, type, state, txt, param, hidden, mime_in_reply_to, mime_references, location_id) VALUES (?,?,?,?,?, ?,?,?,?,?, ?,?,?);",
+                        "INSERT INTO msgs (rfc724_mid, chat_id, from_id, to_id, timestamp, type, state, txt, param, hidden, mime_in_reply_to, mime_references, location_id, ephemeral_timer, ephemeral_timestamp) VALUES (?,?,?,?,?, ?,?,?,?,?, ?,?,?,?,?);",
                         paramsv![
                             new_rfc724_mid,
                             self.id,
@@ -956,6 +1187,8 @@ impl Chat {
                             new_in_reply_to,
                             new_references,
                             location_id as i32,
+                            msg.ephemeral_timer,
+                            msg.ephemeral_timestamp,
                         ]
                     ).await.is_ok() {
                         msg_id = context.sql.get_rowid(
@@ -964,6 +1197,9 @@ impl Chat {
                             "rfc724_mid",
                             new_rfc724_mid,
                         ).await?;
+                        if msg.ephemeral_timestamp != 0 {
+                            ephemeral::schedule_job(context).await;
+                        }
                     } else {
                         error!(
                             context,
@@ -979,6 +1215,79 @@ impl Chat {
     }
 }
 
+/// Schedules moving the messages of a chat to the archive folder
+/// on the server or, if the chat was unarchived, back.
+///
+/// Does nothing unless `Config::ArchiveMove` is enabled
+/// and an archive folder was detected.
+async fn move_chat_msgs(context: &Context, chat_id: ChatId, archived: bool) -> Result<(), Error> {
+    if !context.get_config_bool(Config::ArchiveMove).await {
+        return Ok(());
+    }
+    let archive_folder = match context.get_config(Config::ConfiguredArchiveFolder).await {
+        Some(folder) => folder,
+        None => return Ok(()),
+    };
+    let dest_folder = if archived {
+        archive_folder.clone()
+    } else {
+        context.get_home_folder().await
+    };
+
+    let msg_ids = context
+        .sql
+        .query_map(
+            if archived {
+                "SELECT id FROM msgs WHERE chat_id=? AND server_uid!=0 AND server_folder!=?;"
+            } else {
+                "SELECT id FROM msgs WHERE chat_id=? AND server_uid!=0 AND server_folder=?;"
+            },
+            paramsv![chat_id, archive_folder],
+            |row| row.get::<_, MsgId>(0),
+            |ids| ids.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    for msg_id in msg_ids {
+        let mut param = Params::new();
+        param.set(Param::Arg, &dest_folder);
+        job::add(
+            context,
+            job::Job::new(Action::MoveMsg, msg_id.to_u32(), param, 0),
+        )
+        .await;
+    }
+    Ok(())
+}
+
+/// Reports messages as spam.
+///
+/// The messages are moved to the spam folder on the server
+/// and their senders are blocked.
+pub async fn report_spam(context: &Context, msg_ids: &[MsgId]) -> Result<(), Error> {
+    let spam_folder = context.get_config(Config::ConfiguredSpamFolder).await;
+    if spam_folder.is_none() {
+        warn!(context, "No spam folder, reported messages are not moved.");
+    }
+    for msg_id in msg_ids {
+        let msg = Message::load_from_db(context, *msg_id).await?;
+        if let Some(ref spam_folder) = spam_folder {
+            if msg.server_uid != 0 {
+                let mut param = Params::new();
+                param.set(Param::Arg, spam_folder);
+                job::add(
+                    context,
+                    job::Job::new(Action::MoveMsg, msg_id.to_u32(), param, 0),
+                )
+                .await;
+            }
+        }
+        if msg.from_id > DC_CONTACT_ID_LAST_SPECIAL {
+            Contact::block(context, msg.from_id).await;
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug, Copy, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub enum ChatVisibility {
     Normal,
@@ -1307,15 +1616,49 @@ pub async fn get_by_contact_id(context: &Context, contact_id: u32) -> Result<Cha
     Ok(chat_id)
 }
 
+/// Error returned by [send_msg], [prepare_msg] and friends.
+///
+/// All variants but [SendMsgError::Other] are reasons for refusing to send a message.
+#[derive(Debug, thiserror::Error)]
+pub enum SendMsgError {
+    #[error("Cannot send messages to special chat {0}")]
+    SpecialChat(ChatId),
+
+    #[error("Cannot send to {0}")]
+    CannotSend(ChatId),
+
+    #[error("Attachment missing for message of type #{0}")]
+    AttachmentMissing(Viewtype),
+
+    #[error("Attachment {0} is not a valid app")]
+    InvalidApp(String),
+
+    #[error("Cannot send messages of type #{0}.")]
+    UnsupportedViewtype(Viewtype),
+
+    /// Sending failed for another reason, eg. a database error.
+    #[error(transparent)]
+    Other(Error),
+}
+
+impl From<Error> for SendMsgError {
+    fn from(err: Error) -> Self {
+        // keep the variant of errors passed through as `Error`
+        match err.downcast::<SendMsgError>() {
+            Ok(err) => err,
+            Err(err) => SendMsgError::Other(err),
+        }
+    }
+}
+
 pub async fn prepare_msg(
     context: &Context,
     chat_id: ChatId,
     msg: &mut Message,
-) -> Result<MsgId, Error> {
-    ensure!(
-        !chat_id.is_special(),
-        "Cannot prepare message for special chat"
-    );
+) -> Result<MsgId, SendMsgError> {
+    if chat_id.is_special() {
+        return Err(SendMsgError::SpecialChat(chat_id));
+    }
 
     msg.state = MessageState::OutPreparing;
     let msg_id = prepare_msg_common(context, chat_id, msg).await?;
@@ -1338,29 +1681,72 @@ pub(crate) fn msgtype_has_file(msgtype: Viewtype) -> bool {
         Viewtype::Voice => true,
         Viewtype::Video => true,
         Viewtype::File => true,
+        Viewtype::App => true,
+        Viewtype::Poll => false,
+        Viewtype::Calendar => true,
     }
 }
 
 async fn prepare_msg_blob(context: &Context, msg: &mut Message) -> Result<(), Error> {
     if msg.viewtype == Viewtype::Text {
         // the caller should check if the message text is empty
+    } else if msg.viewtype == Viewtype::Poll {
+        poll::check_poll(msg)?;
     } else if msgtype_has_file(msg.viewtype) {
-        let blob = msg
+        let mut blob = msg
             .param
             .get_blob(Param::File, context, !msg.is_increation())
             .await?
-            .ok_or_else(|| {
-                format_err!("Attachment missing for message of type #{}", msg.viewtype)
-            })?;
-
+            .ok_or_else(|| SendMsgError::AttachmentMissing(msg.viewtype))?;
+
+        // images sent as files are not recoded so that users can send originals
+        let mut keep_viewtype = false;
+        if msg.viewtype == Viewtype::Image && blob.is_heif() {
+            match blob.recode_heif_to_jpeg(context).await {
+                Ok(jpeg) => blob = jpeg,
+                Err(e) => {
+                    // most clients cannot show HEIF images inline
+                    warn!(
+                        context,
+                        "Cannot convert HEIF image to JPEG, sending as file: {:?}", e
+                    );
+                    msg.viewtype = Viewtype::File;
+                    keep_viewtype = true;
+                }
+            }
+        }
         if msg.viewtype == Viewtype::Image {
             if let Err(e) = blob.recode_to_image_size(context).await {
                 warn!(context, "Cannot recode image, using original data: {:?}", e);
             }
         }
+        if msg.viewtype == Viewtype::Video {
+            if let Some(thumbnail) = msg
+                .param
+                .get_blob(Param::Thumbnail, context, !msg.is_increation())
+                .await?
+            {
+                if let Err(e) = thumbnail.recode_to_image_size(context).await {
+                    warn!(
+                        context,
+                        "Cannot recode thumbnail, using original data: {:?}", e
+                    );
+                }
+                msg.param.set(Param::Thumbnail, thumbnail.as_name());
+            }
+        }
+        if msg.viewtype == Viewtype::App {
+            let bundle = dc_read_file(context, blob.to_abs_path()).await?;
+            if !app::is_valid_bundle(&bundle) {
+                return Err(SendMsgError::InvalidApp(blob.as_name().to_string()).into());
+            }
+            if !msg.param.exists(Param::MimeType) {
+                msg.param.set(Param::MimeType, "application/zip");
+            }
+        }
         msg.param.set(Param::File, blob.as_name());
 
-        if msg.viewtype == Viewtype::File || msg.viewtype == Viewtype::Image {
+        if (msg.viewtype == Viewtype::File || msg.viewtype == Viewtype::Image) && !keep_viewtype {
             // Correct the type, take care not to correct already very special
             // formats as GIF or VOICE.
             //
@@ -1384,8 +1770,12 @@ async fn prepare_msg_blob(context: &Context, msg: &mut Message) -> Result<(), Er
             blob.to_abs_path().display(),
             msg.viewtype
         );
+        // files in creation are encrypted when sent
+        if !msg.is_increation() {
+            blob_crypt::encrypt_msg_blobs(context, &msg.param).await?;
+        }
     } else {
-        bail!("Cannot send messages of type #{}.", msg.viewtype);
+        return Err(SendMsgError::UnsupportedViewtype(msg.viewtype).into());
     }
     Ok(())
 }
@@ -1400,7 +1790,9 @@ async fn prepare_msg_common(
     chat_id.unarchive(context).await?;
 
     let mut chat = Chat::load_from_db(context, chat_id).await?;
-    ensure!(chat.can_send(), "cannot send to {}", chat_id);
+    if !chat.can_send() {
+        return Err(SendMsgError::CannotSend(chat_id).into());
+    }
 
     // The OutPreparing state is set by dc_prepare_msg() before it
     // calls this function and the message is left in the OutPreparing
@@ -1448,7 +1840,7 @@ pub async fn send_msg(
     context: &Context,
     chat_id: ChatId,
     msg: &mut Message,
-) -> Result<MsgId, Error> {
+) -> Result<MsgId, SendMsgError> {
     if chat_id.is_unset() {
         let forwards = msg.param.get(Param::PrepForwards);
         if let Some(forwards) = forwards {
@@ -1466,10 +1858,10 @@ pub async fn send_msg(
             msg.param.remove(Param::PrepForwards);
             msg.save_param_to_disk(context).await;
         }
-        return send_msg_inner(context, chat_id, msg).await;
+        return Ok(send_msg_inner(context, chat_id, msg).await?);
     }
 
-    send_msg_inner(context, chat_id, msg).await
+    Ok(send_msg_inner(context, chat_id, msg).await?)
 }
 
 /// Tries to send a message synchronously.
@@ -1481,7 +1873,7 @@ pub async fn send_msg_sync(
     context: &Context,
     chat_id: ChatId,
     msg: &mut Message,
-) -> Result<MsgId, Error> {
+) -> Result<MsgId, SendMsgError> {
     if context.is_io_running().await {
         return send_msg(context, chat_id, msg).await;
     }
@@ -1502,9 +1894,7 @@ pub async fn send_msg_sync(
             }
             _ => {
                 job.save(context).await?;
-                Err(format_err!(
-                    "failed to send message, queued for later sending"
-                ))
+                Err(format_err!("failed to send message, queued for later sending").into())
             }
         }
     } else {
@@ -1563,16 +1953,181 @@ pub async fn send_text_msg(
     context: &Context,
     chat_id: ChatId,
     text_to_send: String,
-) -> Result<MsgId, Error> {
+) -> Result<MsgId, SendMsgError> {
+    if chat_id.is_special() {
+        return Err(SendMsgError::SpecialChat(chat_id));
+    }
+
+    let mut msg = Message::new(Viewtype::Text);
+    msg.text = Some(text_to_send);
+    send_msg(context, chat_id, &mut msg).await
+}
+
+/// Seconds after which a typing contact is assumed to have stopped typing.
+pub const TYPING_TIMEOUT: i64 = 15;
+
+/// Typing notifications are resent at most every this many seconds.
+const TYPING_RESEND_INTERVAL: i64 = 5;
+
+/// Typing notifications are only sent to chats with messages in this period.
+const TYPING_ACTIVITY_WINDOW: i64 = 60 * 60;
+
+/// Notifies the other members of a chat that the user started or stopped typing.
+///
+/// Typing notifications are small chat messages that are not stored in the
+/// database.  They are only sent if enabled by [Config::TypingIndicators] and
+/// only to end-to-end encrypted chats with recent messages.  `typing=true` is
+/// resent at most every few seconds, so this function can be called on every
+/// keystroke; call it with `typing=false` once the draft is sent or cleared.
+///
+/// Returns true if a notification was sent.
+pub async fn send_typing(context: &Context, chat_id: ChatId, typing: bool) -> Result<bool, Error> {
     ensure!(
         !chat_id.is_special(),
         "bad chat_id, can not be a special chat: {}",
         chat_id
     );
+    if !context.get_config_bool(Config::TypingIndicators).await {
+        return Ok(false);
+    }
+
+    let now = time();
+    let last_sent = context.typing_sent.read().await.get(&chat_id).cloned();
+    if typing {
+        if let Some(last_sent) = last_sent {
+            if now < last_sent + TYPING_RESEND_INTERVAL {
+                return Ok(false);
+            }
+        }
+
+        let chat = Chat::load_from_db(context, chat_id).await?;
+        if chat.is_self_talk()
+            || chat.is_device_talk()
+            || chat.is_unpromoted()
+            || chat.typ == Chattype::Mailinglist
+            || chat.typ == Chattype::Broadcast
+        {
+            return Ok(false);
+        }
+        let last_msg_timestamp: i64 = context
+            .sql
+            .query_get_value(
+                context,
+                "SELECT MAX(timestamp) FROM msgs WHERE chat_id=? AND hidden=0;",
+                paramsv![chat_id],
+            )
+            .await
+            .unwrap_or_default();
+        if last_msg_timestamp < now - TYPING_ACTIVITY_WINDOW {
+            return Ok(false);
+        }
+    } else if last_sent.is_none() {
+        // the members were not told that we are typing
+        return Ok(false);
+    }
 
     let mut msg = Message::new(Viewtype::Text);
-    msg.text = Some(text_to_send);
-    send_msg(context, chat_id, &mut msg).await
+    msg.chat_id = chat_id;
+    msg.param.set_cmd(SystemMessage::Typing);
+    msg.param.set_int(Param::Arg, typing as i32);
+    // a notification sent later on would be wrong
+    let sent = job::send_unstored_msg(context, &mut msg, Some(TYPING_TIMEOUT)).await?;
+
+    let mut typing_sent = context.typing_sent.write().await;
+    if typing && sent {
+        typing_sent.insert(chat_id, now);
+    } else {
+        typing_sent.remove(&chat_id);
+    }
+    Ok(sent)
+}
+
+/// Handles a typing notification received from `from_id` sent at `sent_timestamp`.
+///
+/// Notifications from contacts that are not members of the chat are ignored,
+/// as are notifications older than [TYPING_TIMEOUT], eg. when fetching messages
+/// after being offline, and all notifications if typing indicators are disabled.
+pub(crate) async fn receive_typing(
+    context: &Context,
+    mime_parser: &MimeMessage,
+    from_id: u32,
+    sent_timestamp: i64,
+) -> Result<(), Error> {
+    if !context.get_config_bool(Config::TypingIndicators).await {
+        return Ok(());
+    }
+    ensure!(
+        sent_timestamp >= time() - TYPING_TIMEOUT,
+        "Typing notification sent at {} is outdated",
+        sent_timestamp
+    );
+    let typing = mime_parser
+        .get(HeaderDef::ChatTyping)
+        .map(|typing| typing.trim() == "1")
+        .unwrap_or_default();
+    let (chat_id, blocked) = match mime_parser.get(HeaderDef::ChatGroupId) {
+        Some(grpid) => {
+            let (chat_id, _, blocked) = get_chat_id_by_grpid(context, grpid).await?;
+            (chat_id, blocked)
+        }
+        None => lookup_by_contact_id(context, from_id).await?,
+    };
+    ensure!(
+        blocked == Blocked::Not && is_contact_in_chat(context, chat_id, from_id).await,
+        "Contact {} cannot type in {}",
+        from_id,
+        chat_id
+    );
+
+    if typing {
+        context.emit_event(Event::ContactTyping {
+            chat_id,
+            contact_id: from_id,
+        });
+    } else {
+        context.emit_event(Event::ContactStoppedTyping {
+            chat_id,
+            contact_id: from_id,
+        });
+    }
+    Ok(())
+}
+
+/// Edits the text of an outgoing message.
+///
+/// The new text is stored locally and a hidden correction message
+/// referring to the original message is sent to the chat members.
+/// The text before the first edit is kept, see [Message::get_original_text].
+pub async fn send_edit(context: &Context, msg_id: MsgId, new_text: String) -> Result<MsgId, Error> {
+    let mut msg = Message::load_from_db(context, msg_id).await?;
+    ensure!(
+        !msg.chat_id.is_special(),
+        "bad chat_id, can not be a special chat: {}",
+        msg.chat_id
+    );
+    ensure!(
+        msg.from_id == DC_CONTACT_ID_SELF,
+        "Only outgoing messages can be edited"
+    );
+    ensure!(
+        msg.state != MessageState::OutDraft && msg.state != MessageState::OutPreparing,
+        "Message {} is not sent",
+        msg_id
+    );
+    ensure!(
+        !msg.rfc724_mid.is_empty(),
+        "Message {} has no Message-ID",
+        msg_id
+    );
+
+    msg.apply_edit(context, new_text.clone()).await?;
+
+    let mut edit_msg = Message::new(Viewtype::Text);
+    edit_msg.text = Some(new_text);
+    edit_msg.hidden = true;
+    edit_msg.param.set_cmd(SystemMessage::MessageEdit);
+    edit_msg.param.set(Param::Arg, &msg.rfc724_mid);
+    Ok(send_msg(context, msg.chat_id, &mut edit_msg).await?)
 }
 
 pub async fn get_chat_msgs(
@@ -1593,6 +2148,9 @@ pub async fn get_chat_msgs(
         }
     }
 
+    // expired ephemeral messages are deleted by a job,
+    // until then they are not shown
+    let now = time();
     let process_row =
         |row: &rusqlite::Row| Ok((row.get::<_, MsgId>("id")?, row.get::<_, i64>("timestamp")?));
     let process_rows = |rows: rusqlite::MappedRows<_>| {
@@ -1636,8 +2194,9 @@ pub async fn get_chat_msgs(
                 AND chats.blocked=2
                 AND contacts.blocked=0
                 AND m.msgrmsg>=?
+                AND (m.ephemeral_timestamp=0 OR m.ephemeral_timestamp>?)
               ORDER BY m.timestamp,m.id;",
-                paramsv![if show_emails == ShowEmails::All { 0 } else { 1 }],
+                paramsv![if show_emails == ShowEmails::All { 0 } else { 1 }, now],
                 process_row,
                 process_rows,
             )
@@ -1653,8 +2212,9 @@ pub async fn get_chat_msgs(
               WHERE m.starred=1
                 AND m.hidden=0
                 AND ct.blocked=0
+                AND (m.ephemeral_timestamp=0 OR m.ephemeral_timestamp>?)
               ORDER BY m.timestamp,m.id;",
-                paramsv![],
+                paramsv![now],
                 process_row,
                 process_rows,
             )
@@ -1667,8 +2227,9 @@ pub async fn get_chat_msgs(
                FROM msgs m
               WHERE m.chat_id=?
                 AND m.hidden=0
+                AND (m.ephemeral_timestamp=0 OR m.ephemeral_timestamp>?)
               ORDER BY m.timestamp, m.id;",
-                paramsv![chat_id],
+                paramsv![chat_id, now],
                 process_row,
                 process_rows,
             )
@@ -1683,6 +2244,293 @@ pub async fn get_chat_msgs(
     }
 }
 
+/// Loads the messages of a chat older than `before`, newest last.
+///
+/// At most `count` messages are returned, preceded by the message just before
+/// the window if there is one, so day markers can be computed per window.
+async fn load_msgs_window(
+    context: &Context,
+    chat_id: ChatId,
+    before: Option<(i64, MsgId)>,
+    count: usize,
+) -> Result<Vec<(MsgId, i64)>, Error> {
+    let (timestamp, id) = before.unwrap_or((i64::MAX, MsgId::new(u32::MAX)));
+    let mut rows = context
+        .sql
+        .query_map(
+            "SELECT id, timestamp FROM msgs
+              WHERE chat_id=?1
+                AND hidden=0
+                AND (ephemeral_timestamp=0 OR ephemeral_timestamp>?5)
+                AND (timestamp<?2 OR (timestamp=?2 AND id<?3))
+              ORDER BY timestamp DESC, id DESC
+              LIMIT ?4;",
+            paramsv![chat_id, timestamp, id, count as i64 + 1, time()],
+            |row| Ok((row.get::<_, MsgId>(0)?, row.get::<_, i64>(1)?)),
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    rows.reverse();
+    Ok(rows)
+}
+
+/// Loads `count` messages of a chat starting with the message at `from`, oldest first.
+async fn load_msgs_from(
+    context: &Context,
+    chat_id: ChatId,
+    from: (i64, MsgId),
+    count: usize,
+) -> Result<Vec<(MsgId, i64)>, Error> {
+    let (timestamp, id) = from;
+    let rows = context
+        .sql
+        .query_map(
+            "SELECT id, timestamp FROM msgs
+              WHERE chat_id=?1
+                AND hidden=0
+                AND (ephemeral_timestamp=0 OR ephemeral_timestamp>?5)
+                AND (timestamp>?2 OR (timestamp=?2 AND id>=?3))
+              ORDER BY timestamp, id
+              LIMIT ?4;",
+            paramsv![chat_id, timestamp, id, count as i64, time()],
+            |row| Ok((row.get::<_, MsgId>(0)?, row.get::<_, i64>(1)?)),
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    Ok(rows)
+}
+
+async fn get_msg_timestamp(context: &Context, msg_id: MsgId) -> Result<(i64, MsgId), Error> {
+    ensure!(!msg_id.is_special(), "Invalid message ID {}", msg_id);
+    let timestamp = context
+        .sql
+        .query_get_value_result::<i64>("SELECT timestamp FROM msgs WHERE id=?;", paramsv![msg_id])
+        .await?
+        .ok_or_else(|| format_err!("Message {} does not exist", msg_id))?;
+    Ok((timestamp, msg_id))
+}
+
+/// Adds markers to a window of messages as [get_chat_msgs] does.
+///
+/// `rows` is the window, oldest first, `prev` the message before the
+/// window, it is only used to decide if the window starts with a new day.
+fn add_msg_markers(
+    rows: Vec<(MsgId, i64)>,
+    prev: Option<(MsgId, i64)>,
+    flags: u32,
+    marker1before: Option<MsgId>,
+) -> Vec<MsgId> {
+    let cnv_to_local = dc_gm2local_offset();
+    let day = |timestamp: i64| (timestamp + cnv_to_local) / 86400;
+    let mut last_day = prev.map(|(_, timestamp)| day(timestamp));
+    let mut ret = Vec::with_capacity(rows.len());
+    for (msg_id, timestamp) in rows {
+        if marker1before == Some(msg_id) {
+            ret.push(MsgId::new(DC_MSG_ID_MARKER1));
+        }
+        if (flags & DC_GCM_ADDDAYMARKER) != 0 && last_day != Some(day(timestamp)) {
+            ret.push(MsgId::new(DC_MSG_ID_DAYMARKER));
+            last_day = Some(day(timestamp));
+        }
+        ret.push(msg_id);
+    }
+    ret
+}
+
+/// Returns a window of the messages of a chat.
+///
+/// Returns the `count` messages sorted before `before_msg_id`, or the newest
+/// messages if `before_msg_id` is `None`, oldest first as [get_chat_msgs].
+/// UIs can load older messages on scrolling by passing the first message
+/// of the window as `before_msg_id`.  If `flags` contains
+/// `DC_GCM_ADDDAYMARKER`, day markers are added, the window starts with a
+/// day marker only if the day differs from the message before the window.
+/// Special chats are not supported.
+pub async fn get_msgs_page(
+    context: &Context,
+    chat_id: ChatId,
+    before_msg_id: Option<MsgId>,
+    count: usize,
+    flags: u32,
+) -> Result<Vec<MsgId>, Error> {
+    ensure!(!chat_id.is_special(), "Invalid chat ID {}", chat_id);
+    let before = match before_msg_id {
+        Some(msg_id) => Some(get_msg_timestamp(context, msg_id).await?),
+        None => None,
+    };
+    let mut rows = load_msgs_window(context, chat_id, before, count).await?;
+    let prev = if rows.len() > count {
+        Some(rows.remove(0))
+    } else {
+        None
+    };
+    Ok(add_msg_markers(rows, prev, flags, None))
+}
+
+/// Returns the oldest fresh message of a chat, `None` if there is none.
+pub async fn get_first_unread_msg(
+    context: &Context,
+    chat_id: ChatId,
+) -> Result<Option<MsgId>, Error> {
+    let msg_id = context
+        .sql
+        .query_get_value_result(
+            "SELECT id FROM msgs
+              WHERE chat_id=?
+                AND hidden=0
+                AND state=?
+                AND (ephemeral_timestamp=0 OR ephemeral_timestamp>?)
+              ORDER BY timestamp, id
+              LIMIT 1;",
+            paramsv![chat_id, MessageState::InFresh, time()],
+        )
+        .await?;
+    Ok(msg_id)
+}
+
+/// Returns a window of the messages of a chat to jump to the first unread message.
+///
+/// The window contains up to `count` messages around the first fresh message,
+/// which is preceded by the marker `DC_MSG_ID_MARKER1`.  If there are no fresh
+/// messages, the newest messages are returned as by [get_msgs_page].  Older and
+/// newer messages can then be loaded using [get_msgs_page] and
+/// [message::get_chat_msgs_after].
+pub async fn get_unread_msgs_page(
+    context: &Context,
+    chat_id: ChatId,
+    count: usize,
+    flags: u32,
+) -> Result<Vec<MsgId>, Error> {
+    ensure!(!chat_id.is_special(), "Invalid chat ID {}", chat_id);
+    let first_unread = match get_first_unread_msg(context, chat_id).await? {
+        Some(msg_id) => get_msg_timestamp(context, msg_id).await?,
+        None => return get_msgs_page(context, chat_id, None, count, flags).await,
+    };
+
+    let mut rows = load_msgs_from(context, chat_id, first_unread, count - count / 2).await?;
+    let older_count = count - rows.len();
+    let mut older = load_msgs_window(context, chat_id, Some(first_unread), older_count).await?;
+    let prev = if older.len() > older_count {
+        Some(older.remove(0))
+    } else {
+        None
+    };
+    older.append(&mut rows);
+    Ok(add_msg_markers(older, prev, flags, Some(first_unread.1)))
+}
+
+/// A message found by [search_in_chat].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChatSearchMatch {
+    pub msg_id: MsgId,
+
+    /// Byte ranges `(start, end)` of the matches in the message text, in
+    /// text order.  May be empty if the full-text index matched a spelling
+    /// variant, eg. a word without diacritics.
+    pub offsets: Vec<(usize, usize)>,
+}
+
+/// Splits a search query into lowercased words as the full-text index does.
+fn search_words(query: &str) -> Vec<Vec<char>> {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase().chars().collect())
+        .collect()
+}
+
+/// Returns the length in bytes of the prefix of `text` matching `word` case-insensitively.
+fn prefix_match_len(text: &str, word: &[char]) -> Option<usize> {
+    let mut word = word.iter().peekable();
+    for (pos, c) in text.char_indices() {
+        for lower in c.to_lowercase() {
+            match word.next() {
+                Some(expected) if *expected == lower => {}
+                Some(_) => return None,
+                None => break,
+            }
+        }
+        if word.peek().is_none() {
+            return Some(pos + c.len_utf8());
+        }
+    }
+    None
+}
+
+/// Returns the byte ranges of the words in `text` starting with one of `words`.
+fn match_offsets(text: &str, words: &[Vec<char>]) -> Vec<(usize, usize)> {
+    let mut offsets = Vec::new();
+    let mut prev_alphanumeric = false;
+    for (start, c) in text.char_indices() {
+        let word_start = c.is_alphanumeric() && !prev_alphanumeric;
+        prev_alphanumeric = c.is_alphanumeric();
+        if !word_start {
+            continue;
+        }
+        if let Some(len) = words
+            .iter()
+            .filter_map(|word| prefix_match_len(&text[start..], word))
+            .max()
+        {
+            offsets.push((start, start + len));
+        }
+    }
+    offsets
+}
+
+/// Searches the messages of a chat and returns where the query matches.
+///
+/// Messages are found using the full-text index as [message::search_msgs] does
+/// and sorted like the chat.  Every found message comes with the byte offsets
+/// of the words starting with one of the query words, so UIs can highlight
+/// the matches and navigate between them.
+pub async fn search_in_chat(
+    context: &Context,
+    chat_id: ChatId,
+    query: &str,
+) -> Result<Vec<ChatSearchMatch>, Error> {
+    ensure!(!chat_id.is_special(), "Invalid chat ID {}", chat_id);
+    let fts_query = match message::fts_query(query) {
+        Some(fts_query) => fts_query,
+        None => return Ok(Vec::new()),
+    };
+    let words = search_words(query);
+    let found = context
+        .sql
+        .query_map(
+            "SELECT m.id, m.txt
+               FROM msgs_search
+              INNER JOIN msgs m ON m.id=msgs_search.rowid
+               LEFT JOIN contacts ct ON m.from_id=ct.id
+              WHERE msgs_search MATCH ?
+                AND m.chat_id=?
+                AND m.hidden=0
+                AND ct.blocked=0
+              ORDER BY m.timestamp, m.id;",
+            paramsv![fts_query, chat_id],
+            |row| {
+                let msg_id: MsgId = row.get(0)?;
+                let text: String = row.get::<_, Option<String>>(1)?.unwrap_or_default();
+                Ok(ChatSearchMatch {
+                    msg_id,
+                    offsets: match_offsets(&text, &words),
+                })
+            },
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    Ok(found)
+}
+
 pub async fn marknoticed_chat(context: &Context, chat_id: ChatId) -> Result<(), Error> {
     if !context
         .sql
@@ -1950,6 +2798,8 @@ pub async fn create_group_chat(
 
     let chat_id = ChatId::new(row_id);
     if add_to_chat_contacts_table(context, chat_id, DC_CONTACT_ID_SELF).await {
+        // the creator of a group is its first admin
+        set_admin_flag(context, chat_id, DC_CONTACT_ID_SELF, true).await;
         let mut draft_msg = Message::new(Viewtype::Text);
         draft_msg.set_text(Some(draft_txt));
         chat_id.set_draft_raw(context, &mut draft_msg).await;
@@ -1963,6 +2813,38 @@ pub async fn create_group_chat(
     Ok(chat_id)
 }
 
+/// Creates a broadcast list.
+///
+/// Messages sent to a broadcast list are sent to all members at once
+/// without revealing the other members.  The members see the messages in
+/// the 1:1 chat with the sender, so their replies end up in the 1:1 chats
+/// as well.  Members are managed by [add_contact_to_chat] and
+/// [remove_contact_from_chat], they are not notified about that.
+pub async fn create_broadcast_list(context: &Context) -> Result<ChatId, Error> {
+    let chat_name = context.stock_str(StockMessage::BroadcastList).await;
+    let grpid = dc_create_id();
+
+    context
+        .sql
+        .execute(
+            "INSERT INTO chats (type, name, grpid, param, created_timestamp) VALUES(?, ?, ?, '', ?);",
+            paramsv![Chattype::Broadcast, chat_name.to_string(), grpid, time()],
+        )
+        .await?;
+
+    let row_id = context
+        .sql
+        .get_rowid(context, "chats", "grpid", grpid)
+        .await?;
+
+    context.emit_event(Event::MsgsChanged {
+        msg_id: MsgId::new(0),
+        chat_id: ChatId::new(0),
+    });
+
+    Ok(ChatId::new(row_id))
+}
+
 /// add a contact to the chats_contact table
 pub(crate) async fn add_to_chat_contacts_table(
     context: &Context,
@@ -2015,6 +2897,145 @@ pub(crate) async fn remove_from_chat_contacts_table(
     }
 }
 
+/// Returns the contact IDs of the admins of a group.
+pub async fn get_group_admins(context: &Context, chat_id: ChatId) -> Vec<u32> {
+    context
+        .sql
+        .query_map(
+            "SELECT contact_id FROM chats_contacts WHERE chat_id=? AND is_admin=1;",
+            paramsv![chat_id],
+            |row| row.get::<_, u32>(0),
+            |ids| ids.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await
+        .unwrap_or_default()
+}
+
+/// Returns true if the contact is an admin of the group.
+pub async fn is_group_admin(context: &Context, chat_id: ChatId, contact_id: u32) -> bool {
+    context
+        .sql
+        .exists(
+            "SELECT contact_id FROM chats_contacts WHERE chat_id=? AND contact_id=? AND is_admin=1;",
+            paramsv![chat_id, contact_id as i32],
+        )
+        .await
+        .unwrap_or_default()
+}
+
+/// Returns true if the contact may change name, image and members of the group.
+///
+/// Groups without any admin, e.g. groups created by older versions, can
+/// be changed by all members.
+pub(crate) async fn may_administrate(context: &Context, chat_id: ChatId, contact_id: u32) -> bool {
+    get_group_admins(context, chat_id).await.is_empty()
+        || is_group_admin(context, chat_id, contact_id).await
+}
+
+/// set or unset the admin flag of a contact in the chats_contact table
+pub(crate) async fn set_admin_flag(
+    context: &Context,
+    chat_id: ChatId,
+    contact_id: u32,
+    is_admin: bool,
+) -> bool {
+    match context
+        .sql
+        .execute(
+            "UPDATE chats_contacts SET is_admin=? WHERE chat_id=? AND contact_id=?",
+            paramsv![is_admin as i32, chat_id, contact_id as i32],
+        )
+        .await
+    {
+        Ok(_) => true,
+        Err(err) => {
+            error!(
+                context,
+                "could not set admin flag of {} in chat {}: {}", contact_id, chat_id, err
+            );
+
+            false
+        }
+    }
+}
+
+/// Makes a group member an admin or revokes the admin role.
+///
+/// Only admins may rename the group, change its image and add or remove
+/// members.  The change is sent to the other members if the group is
+/// promoted already.
+pub async fn set_group_admin(
+    context: &Context,
+    chat_id: ChatId,
+    contact_id: u32,
+    is_admin: bool,
+) -> Result<(), Error> {
+    ensure!(!chat_id.is_special(), "Invalid chat ID");
+    ensure!(
+        real_group_exists(context, chat_id).await,
+        "{} is not a group where one can set admins",
+        chat_id
+    );
+    ensure!(
+        is_contact_in_chat(context, chat_id, DC_CONTACT_ID_SELF).await,
+        "Cannot set group admin; self not in group"
+    );
+    ensure!(
+        may_administrate(context, chat_id, DC_CONTACT_ID_SELF).await,
+        "Cannot set group admin; only group admins may do this"
+    );
+    ensure!(
+        is_contact_in_chat(context, chat_id, contact_id).await,
+        "Cannot set group admin; contact {} not in group",
+        contact_id
+    );
+    if is_group_admin(context, chat_id, contact_id).await == is_admin {
+        return Ok(());
+    }
+    if !set_admin_flag(context, chat_id, contact_id, is_admin).await {
+        bail!("Failed to set group admin");
+    }
+
+    let chat = Chat::load_from_db(context, chat_id).await?;
+    if chat.is_promoted() {
+        let addr = if contact_id == DC_CONTACT_ID_SELF {
+            context
+                .get_config(Config::ConfiguredAddr)
+                .await
+                .unwrap_or_default()
+        } else {
+            Contact::get_by_id(context, contact_id)
+                .await?
+                .get_addr()
+                .to_string()
+        };
+        let mut msg = Message::new(Viewtype::Text);
+        msg.text = Some(
+            context
+                .stock_system_msg(
+                    if is_admin {
+                        StockMessage::MsgAdminAdded
+                    } else {
+                        StockMessage::MsgAdminRemoved
+                    },
+                    &addr,
+                    "",
+                    DC_CONTACT_ID_SELF,
+                )
+                .await,
+        );
+        msg.param.set_cmd(if is_admin {
+            SystemMessage::GroupAdminAdded
+        } else {
+            SystemMessage::GroupAdminRemoved
+        });
+        msg.param.set(Param::Arg, addr);
+        msg.id = send_msg(context, chat_id, &mut msg).await?;
+    }
+    context.emit_event(Event::ChatModified(chat_id));
+    Ok(())
+}
+
 /// Adds a contact to the chat.
 pub async fn add_contact_to_chat(context: &Context, chat_id: ChatId, contact_id: u32) -> bool {
     match add_contact_to_chat_ex(context, chat_id, contact_id, false).await {
@@ -2040,6 +3061,21 @@ pub(crate) async fn add_contact_to_chat_ex(
 
     /*this also makes sure, not contacts are added to special or normal chats*/
     let mut chat = Chat::load_from_db(context, chat_id).await?;
+    if chat.typ == Chattype::Broadcast {
+        // members of broadcast lists only see a 1:1 chat, there is nobody to notify
+        ensure!(
+            Contact::real_exists_by_id(context, contact_id).await,
+            "invalid contact_id {} for adding to broadcast list",
+            contact_id
+        );
+        if !is_contact_in_chat(context, chat_id, contact_id).await
+            && !add_to_chat_contacts_table(context, chat_id, contact_id).await
+        {
+            return Ok(false);
+        }
+        context.emit_event(Event::ChatModified(chat_id));
+        return Ok(true);
+    }
     ensure!(
         real_group_exists(context, chat_id).await,
         "{} is not a group where one can add members",
@@ -2059,6 +3095,10 @@ pub(crate) async fn add_contact_to_chat_ex(
         );
         bail!("can not add contact because our account is not part of it");
     }
+    ensure!(
+        may_administrate(context, chat_id, DC_CONTACT_ID_SELF).await,
+        "Cannot add contact to group; only group admins may do this"
+    );
     if from_handshake && chat.param.get_int(Param::Unpromoted).unwrap_or_default() == 1 {
         chat.param.remove(Param::Unpromoted);
         chat.update_param(context).await?;
@@ -2225,6 +3265,17 @@ pub enum MuteDuration {
     Until(SystemTime),
 }
 
+impl MuteDuration {
+    /// Returns true if the duration did not expire yet.
+    pub fn is_muted(&self) -> bool {
+        match self {
+            MuteDuration::NotMuted => false,
+            MuteDuration::Forever => true,
+            MuteDuration::Until(when) => *when > SystemTime::now(),
+        }
+    }
+}
+
 impl rusqlite::types::ToSql for MuteDuration {
     fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput> {
         let duration: i64 = match &self {
@@ -2260,35 +3311,49 @@ impl rusqlite::types::FromSql for MuteDuration {
     }
 }
 
-pub async fn set_muted(
+/// Sets whether read receipts are sent and requested in a chat,
+/// overriding the global `Config::MdnsEnabled` option.
+pub async fn set_mdn_policy(
     context: &Context,
     chat_id: ChatId,
-    duration: MuteDuration,
+    policy: MdnPolicy,
 ) -> Result<(), Error> {
     ensure!(!chat_id.is_special(), "Invalid chat ID");
-    if context
-        .sql
-        .execute(
-            "UPDATE chats SET muted_until=? WHERE id=?;",
-            paramsv![duration, chat_id],
-        )
-        .await
-        .is_ok()
-    {
-        context.emit_event(Event::ChatModified(chat_id));
-    } else {
-        bail!("Failed to set mute duration, chat might not exist -");
-    }
+    let mut chat = Chat::load_from_db(context, chat_id).await?;
+    chat.param.set_int(Param::MdnPolicy, policy as i32);
+    chat.update_param(context).await?;
+    context.emit_event(Event::ChatModified(chat_id));
     Ok(())
 }
 
-pub async fn remove_contact_from_chat(
+/// Returns true if read receipts are sent and requested in the chat.
+pub(crate) async fn mdns_enabled(context: &Context, chat_id: ChatId) -> bool {
+    let policy = match Chat::load_from_db(context, chat_id).await {
+        Ok(chat) => chat.get_mdn_policy(),
+        Err(_) => MdnPolicy::Global,
+    };
+    match policy {
+        MdnPolicy::Global => context.get_config_bool(Config::MdnsEnabled).await,
+        MdnPolicy::Always => true,
+        MdnPolicy::Never => false,
+    }
+}
+
+pub async fn set_muted(
     context: &Context,
     chat_id: ChatId,
-    contact_id: u32,
+    duration: MuteDuration,
 ) -> Result<(), Error> {
-    ensure!(
-        !chat_id.is_special(),
+    chat_id.set_mute_duration(context, duration).await
+}
+
+pub async fn remove_contact_from_chat(
+    context: &Context,
+    chat_id: ChatId,
+    contact_id: u32,
+) -> Result<(), Error> {
+    ensure!(
+        !chat_id.is_special(),
         "bad chat_id, can not be special chat: {}",
         chat_id
     );
@@ -2303,7 +3368,10 @@ pub async fn remove_contact_from_chat(
     /* we do not check if "contact_id" exists but just delete all records with the id from chats_contacts */
     /* this allows to delete pending references to deleted contacts.  Of course, this should _not_ happen. */
     if let Ok(chat) = Chat::load_from_db(context, chat_id).await {
-        if real_group_exists(context, chat_id).await {
+        if chat.typ == Chattype::Broadcast {
+            success = remove_from_chat_contacts_table(context, chat_id, contact_id).await;
+            context.emit_event(Event::ChatModified(chat_id));
+        } else if real_group_exists(context, chat_id).await {
             if !is_contact_in_chat(context, chat_id, DC_CONTACT_ID_SELF).await {
                 emit_event!(
                     context,
@@ -2312,6 +3380,12 @@ pub async fn remove_contact_from_chat(
                     )
                 );
             } else {
+                // leaving a group is always possible
+                ensure!(
+                    contact_id == DC_CONTACT_ID_SELF
+                        || may_administrate(context, chat_id, DC_CONTACT_ID_SELF).await,
+                    "Cannot remove contact from group; only group admins may do this"
+                );
                 if let Ok(contact) = Contact::get_by_id(context, contact_id).await {
                     if chat.is_promoted() {
                         msg.viewtype = Viewtype::Text;
@@ -2417,6 +3491,8 @@ pub async fn set_chat_name(
                 context,
                 Event::ErrorSelfNotInGroup("Cannot set chat name; self not in group".into())
             );
+        } else if !may_administrate(context, chat_id, DC_CONTACT_ID_SELF).await {
+            bail!("Cannot set chat name; only group admins may do this");
         } else {
             /* we should respect this - whatever we send to the group, it gets discarded anyway! */
             if context
@@ -2487,6 +3563,10 @@ pub async fn set_chat_profile_image(
         );
         bail!("Failed to set profile image");
     }
+    ensure!(
+        may_administrate(context, chat_id, DC_CONTACT_ID_SELF).await,
+        "Cannot set chat profile image; only group admins may do this"
+    );
     let mut msg = Message::new(Viewtype::Text);
     msg.param
         .set_int(Param::Cmd, SystemMessage::GroupImageChanged as i32);
@@ -2499,16 +3579,23 @@ pub async fn set_chat_profile_image(
                 .await,
         );
     } else {
-        let image_blob = match BlobObject::from_path(context, Path::new(new_image.as_ref())) {
-            Ok(blob) => Ok(blob),
-            Err(err) => match err {
-                BlobError::WrongBlobdir { .. } => {
-                    BlobObject::create_and_copy(context, Path::new(new_image.as_ref())).await
-                }
-                _ => Err(err),
-            },
-        }?;
+        let (image_blob, copied) =
+            match BlobObject::from_path(context, Path::new(new_image.as_ref())) {
+                Ok(blob) => (blob, false),
+                Err(BlobError::WrongBlobdir { .. }) => (
+                    BlobObject::create_and_copy(context, Path::new(new_image.as_ref())).await?,
+                    true,
+                ),
+                Err(err) => return Err(err.into()),
+            };
         image_blob.recode_to_avatar_size(context)?;
+        if is_current_profile_image(context, &chat, &image_blob).await {
+            info!(context, "Chat {} already has this profile image", chat_id);
+            if copied {
+                dc_delete_file(context, image_blob.to_abs_path()).await;
+            }
+            return Ok(());
+        }
         chat.param.set(Param::ProfileImage, image_blob.as_name());
         msg.param.set(Param::Arg, image_blob.as_name());
         msg.text = Some(
@@ -2528,15 +3615,72 @@ pub async fn set_chat_profile_image(
             }
         );
     }
-    emit_event!(context, Event::ChatModified(chat_id));
+    emit_event!(context, Event::ChatImageChanged(chat_id));
     Ok(())
 }
 
+/// Deletes the given blobs if they are not referenced by any message or chat,
+/// used for the attachments of discarded drafts.
+async fn delete_unreferenced_blobs(context: &Context, blobs: &[String]) {
+    for name in blobs {
+        let pattern = format!("%={}%", name);
+        let references = context
+            .sql
+            .query_get_value::<i32>(
+                context,
+                "SELECT (SELECT COUNT(*) FROM msgs WHERE param LIKE ?)
+                      + (SELECT COUNT(*) FROM chats WHERE param LIKE ?);",
+                paramsv![pattern, pattern],
+            )
+            .await;
+        if references == Some(0) {
+            if let Ok(blob) = BlobObject::from_name(context, name.clone()) {
+                dc_delete_file(context, blob.to_abs_path()).await;
+            }
+        }
+    }
+}
+
+/// Returns true if `image` has the same content as the current profile image of `chat`.
+pub(crate) async fn is_current_profile_image(
+    context: &Context,
+    chat: &Chat,
+    image: &BlobObject<'_>,
+) -> bool {
+    match chat
+        .param
+        .get(Param::ProfileImage)
+        .map(|name| BlobObject::from_name(context, name.to_string()))
+    {
+        Some(Ok(current)) => current.has_same_content(image).await,
+        _ => false,
+    }
+}
+
+/// Forwards messages to a chat, see [forward_msgs_ex].
 pub async fn forward_msgs(
     context: &Context,
     msg_ids: &[MsgId],
     chat_id: ChatId,
-) -> Result<(), Error> {
+) -> Result<Vec<MsgId>, Error> {
+    forward_msgs_ex(context, msg_ids, chat_id, false).await
+}
+
+/// Forwards messages to a chat as one batch.
+///
+/// The messages are forwarded in the order they were sent, independent
+/// of the order of `msg_ids`.  Attachments are not copied, the forwarded
+/// messages refer to the same blobs.  If `attribute` is set, the name of the
+/// original author is sent along, see [Message::get_forwarded_from].
+///
+/// Returns the IDs of the new messages in the order they were forwarded.
+/// Messages that cannot be loaded are skipped.
+pub async fn forward_msgs_ex(
+    context: &Context,
+    msg_ids: &[MsgId],
+    chat_id: ChatId,
+    attribute: bool,
+) -> Result<Vec<MsgId>, Error> {
     ensure!(!msg_ids.is_empty(), "empty msgs_ids: nothing to forward");
     ensure!(!chat_id.is_special(), "can not forward to special chat");
 
@@ -2546,7 +3690,9 @@ pub async fn forward_msgs(
 
     chat_id.unarchive(context).await?;
     if let Ok(mut chat) = Chat::load_from_db(context, chat_id).await {
-        ensure!(chat.can_send(), "cannot send to {}", chat_id);
+        if !chat.can_send() {
+            return Err(SendMsgError::CannotSend(chat_id).into());
+        }
         curr_timestamp = dc_create_smeared_timestamps(context, msg_ids.len()).await;
         let ids = context
             .sql
@@ -2563,13 +3709,35 @@ pub async fn forward_msgs(
 
         for id in ids {
             let src_msg_id: MsgId = id;
-            let msg = Message::load_from_db(context, src_msg_id).await;
-            if msg.is_err() {
-                break;
-            }
-            let mut msg = msg.unwrap();
+            let mut msg = match Message::load_from_db(context, src_msg_id).await {
+                Ok(msg) => msg,
+                Err(err) => {
+                    warn!(context, "Cannot forward message {}: {}", src_msg_id, err);
+                    continue;
+                }
+            };
             let original_param = msg.param.clone();
 
+            // messages forwarded again keep the original author
+            if attribute && !msg.param.exists(Param::ForwardedFrom) {
+                let author = if msg.from_id == DC_CONTACT_ID_SELF {
+                    match context.get_config(Config::Displayname).await {
+                        Some(name) if !name.is_empty() => Some(name),
+                        _ => context.get_config(Config::ConfiguredAddr).await,
+                    }
+                } else {
+                    Contact::get_by_id(context, msg.from_id)
+                        .await
+                        .ok()
+                        .map(|contact| contact.get_display_name().to_string())
+                };
+                if let Some(author) = author {
+                    msg.param.set(Param::ForwardedFrom, author);
+                }
+            } else if !attribute {
+                msg.param.remove(Param::ForwardedFrom);
+            }
+
             // we tested a sort of broadcast
             // by not marking own forwarded messages as such,
             // however, this turned out to be to confusing and unclear.
@@ -2617,7 +3785,7 @@ pub async fn forward_msgs(
             msg_id: *msg_id,
         });
     }
-    Ok(())
+    Ok(created_msgs)
 }
 
 pub(crate) async fn get_chat_contact_cnt(context: &Context, chat_id: ChatId) -> usize {
@@ -2735,7 +3903,12 @@ pub async fn add_device_msg(
     }
 
     if !msg_id.is_unset() {
-        context.emit_event(Event::IncomingMsg { chat_id, msg_id });
+        let muted = chat_id.is_muted(context).await;
+        context.emit_event(Event::IncomingMsg {
+            chat_id,
+            msg_id,
+            muted,
+        });
     }
 
     Ok(msg_id)
@@ -2778,6 +3951,135 @@ pub(crate) async fn delete_and_reset_all_device_msgs(context: &Context) -> Resul
     Ok(())
 }
 
+/// Imports a message from an RFC 822 file, eg. an `.eml` file,
+/// using the same pipeline as for received messages.
+///
+/// If `chat_id` is not special, the message is moved to this chat,
+/// otherwise the chat is chosen as for received messages.
+/// Returns the ID of the imported message, or of the existing message
+/// if a message with the same Message-ID exists already.
+pub async fn import_eml(context: &Context, chat_id: ChatId, eml: &[u8]) -> Result<MsgId, Error> {
+    if !chat_id.is_special() {
+        Chat::load_from_db(context, chat_id).await?;
+    }
+    let parsed = MimeMessage::from_bytes(context, eml).await?;
+    let (rfc724_mid, eml) = match parsed.get_rfc724_mid() {
+        Some(rfc724_mid) => (rfc724_mid, eml.to_vec()),
+        None => {
+            // the message is looked up by its Message-ID after receiving it,
+            // deriving it from the content avoids duplicates on repeated imports.
+            let rfc724_mid = format!("{:x}@imported", Sha256::digest(eml));
+            let mut with_mid = format!("Message-ID: <{}>\r\n", rfc724_mid).into_bytes();
+            with_mid.extend_from_slice(eml);
+            (rfc724_mid, with_mid)
+        }
+    };
+
+    dc_receive_imf(context, &eml, "", 0, true).await?;
+    let (_, _, msg_id) = message::rfc724_mid_exists(context, &rfc724_mid)
+        .await?
+        .ok_or_else(|| format_err!("Cannot import message {}", rfc724_mid))?;
+    let msg = Message::load_from_db(context, msg_id).await?;
+    ensure!(
+        msg.chat_id != ChatId::new(DC_CHAT_ID_TRASH),
+        "Message {} is not shown in any chat",
+        rfc724_mid
+    );
+
+    if !chat_id.is_special() && msg.chat_id != chat_id {
+        context
+            .sql
+            .execute(
+                "UPDATE msgs SET chat_id=? WHERE rfc724_mid=?;",
+                paramsv![chat_id, rfc724_mid],
+            )
+            .await?;
+        context.emit_event(Event::MsgsChanged {
+            chat_id: msg.chat_id,
+            msg_id: MsgId::new(0),
+        });
+        context.emit_event(Event::MsgsChanged { chat_id, msg_id });
+    }
+    Ok(msg_id)
+}
+
+/// Writes all messages of a chat into an mbox file at `path`.
+///
+/// The messages are written unencrypted, including their attachments,
+/// so that the chat can be archived or opened in other mail clients.
+/// Info messages are skipped.
+pub async fn export_mbox(
+    context: &Context,
+    chat_id: ChatId,
+    path: impl AsRef<Path>,
+) -> Result<(), Error> {
+    ensure!(
+        !chat_id.is_special(),
+        "Cannot export special chat {}",
+        chat_id
+    );
+    let self_addr = context
+        .get_config(Config::ConfiguredAddr)
+        .await
+        .unwrap_or_default();
+
+    let mut file = async_std::fs::File::create(path.as_ref()).await?;
+    for msg_id in get_chat_msgs(context, chat_id, 0, None).await {
+        let msg = Message::load_from_db(context, msg_id).await?;
+        if msg.is_info() {
+            continue;
+        }
+        let from_addr = if msg.from_id == DC_CONTACT_ID_SELF {
+            self_addr.clone()
+        } else {
+            Contact::load_from_db(context, msg.from_id)
+                .await?
+                .get_addr()
+                .to_string()
+        };
+        let timestamp = if msg.timestamp_sent > 0 {
+            msg.timestamp_sent
+        } else {
+            msg.timestamp_sort
+        };
+        let eml = mimefactory::render_eml(context, &msg).await?;
+        file.write_all(&mbox_entry(&from_addr, timestamp, &eml))
+            .await?;
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+/// Formats a message as mbox entry in the "mboxrd" format,
+/// see <https://tools.ietf.org/html/rfc4155>.
+///
+/// Lines of the message starting with any number of `>` followed by
+/// `From ` are quoted by another `>`, lines end with LF.
+fn mbox_entry(from_addr: &str, timestamp: i64, eml: &[u8]) -> Vec<u8> {
+    let date = chrono::NaiveDateTime::from_timestamp(timestamp, 0).format("%a %b %e %H:%M:%S %Y");
+    let mut entry = format!(
+        "From {} {}\n",
+        if from_addr.is_empty() {
+            "MAILER-DAEMON"
+        } else {
+            from_addr
+        },
+        date
+    )
+    .into_bytes();
+
+    let eml = String::from_utf8_lossy(eml);
+    for line in eml.lines() {
+        if line.trim_start_matches('>').starts_with("From ") {
+            entry.push(b'>');
+        }
+        entry.extend_from_slice(line.as_bytes());
+        entry.push(b'\n');
+    }
+    entry.push(b'\n');
+    entry
+}
+
 /// Adds an informational message to chat.
 ///
 /// For example, it can be a message showing that a member was added to a group.
@@ -2896,6 +4198,69 @@ mod tests {
         assert_eq!(msg_text, draft_text);
     }
 
+    #[cfg(not(feature = "heif"))]
+    #[async_std::test]
+    async fn test_heif_image_sent_as_file() {
+        let t = dummy_context().await;
+        t.ctx
+            .set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+        let chat_id = create_by_contact_id(&t.ctx, DC_CONTACT_ID_SELF)
+            .await
+            .unwrap();
+        let heic = b"\0\0\0\x18ftypheic\0\0\0\0mif1heic";
+        let blob = BlobObject::create(&t.ctx, "photo.heic", heic)
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::Image);
+        msg.set_file(blob.as_name(), None);
+        let msg_id = prepare_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_viewtype(), Viewtype::File);
+        assert_eq!(msg.get_filemime(), Some("image/heic".to_string()));
+    }
+
+    #[async_std::test]
+    async fn test_draft_with_attachment() {
+        let t = dummy_context().await;
+        let chat_id = create_by_contact_id(&t.ctx, DC_CONTACT_ID_SELF)
+            .await
+            .unwrap();
+        let file = t.dir.path().join("hello.txt");
+        async_std::fs::write(&file, b"hello").await.unwrap();
+        let mut quoted = Message::new(Viewtype::Text);
+        quoted.set_text(Some("quoted".to_string()));
+
+        let mut msg = Message::new(Viewtype::File);
+        msg.set_file(file.to_str().unwrap(), None);
+        msg.set_text(Some("see attachment".to_string()));
+        msg.set_quote(&quoted);
+        msg.set_location(52.5, 13.4);
+        chat_id.set_draft(&t.ctx, Some(&mut msg)).await;
+        async_std::fs::remove_file(&file).await.unwrap();
+
+        let draft = chat_id.get_draft(&t.ctx).await.unwrap().unwrap();
+        assert_eq!(draft.get_viewtype(), Viewtype::File);
+        assert_eq!(draft.get_text(), Some("see attachment".to_string()));
+        assert_eq!(draft.quoted_text(), Some("quoted".to_string()));
+        assert_eq!(draft.param.get_float(Param::SetLatitude), Some(52.5));
+        let blob = draft.get_file(&t.ctx).unwrap();
+        assert!(blob.starts_with(t.ctx.get_blobdir()));
+        assert_eq!(async_std::fs::read(&blob).await.unwrap(), b"hello");
+
+        // setting the loaded draft again keeps the attachment
+        let mut draft = draft;
+        chat_id.set_draft(&t.ctx, Some(&mut draft)).await;
+        assert!(blob.exists().await);
+
+        // discarding the draft deletes the attachment
+        chat_id.set_draft(&t.ctx, None).await;
+        assert!(chat_id.get_draft(&t.ctx).await.unwrap().is_none());
+        assert!(!blob.exists().await);
+    }
+
     #[async_std::test]
     async fn test_add_contact_to_chat_ex_add_self() {
         // Adding self to a contact should succeed, even though it's pointless.
@@ -2945,6 +4310,105 @@ mod tests {
         assert_eq!(chat.name, t.ctx.stock_str(StockMessage::DeadDrop).await);
     }
 
+    #[async_std::test]
+    async fn test_import_and_export_eml() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        let eml = b"From: Bob <bob@example.org>\n\
+                    To: alice@example.com\n\
+                    Chat-Version: 1.0\n\
+                    Subject: foo\n\
+                    Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                    Content-Type: text/plain\n\
+                    \n\
+                    hello from an eml file\n";
+        let msg_id = import_eml(&t.ctx, ChatId::new(0), eml).await.unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_text().unwrap(), "hello from an eml file");
+
+        // importing again does not duplicate the message, but moves it to the given chat
+        let chat_id = create_group_chat(&t.ctx, VerifiedStatus::Unverified, "grp")
+            .await
+            .unwrap();
+        assert_eq!(import_eml(&t.ctx, chat_id, eml).await.unwrap(), msg_id);
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.chat_id, chat_id);
+
+        // without saved mime headers, the message is reconstructed
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("msg.eml");
+        msg_id.export_eml(&t.ctx, &path).await.unwrap();
+        let exported = String::from_utf8(std::fs::read(&path).unwrap()).unwrap();
+        assert!(exported.contains("bob@example.org"));
+        assert!(exported.contains("Subject: grp"));
+        assert!(exported.contains("hello from an eml file"));
+
+        // with saved mime headers, the original is exported
+        t.ctx
+            .set_config(Config::SaveMimeHeaders, Some("1"))
+            .await
+            .unwrap();
+        let eml = b"From: Bob <bob@example.org>\n\
+                    To: alice@example.com\n\
+                    Chat-Version: 1.0\n\
+                    Message-ID: <original@example.org>\n\
+                    Date: Sun, 22 Mar 2020 22:38:57 +0000\n\
+                    \n\
+                    original\n";
+        let msg_id = import_eml(&t.ctx, chat_id, eml).await.unwrap();
+        msg_id.export_eml(&t.ctx, &path).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), eml.to_vec());
+    }
+
+    #[test]
+    fn test_mbox_entry() {
+        let entry = mbox_entry(
+            "bob@example.org",
+            1584916677,
+            b"Subject: foo\r\n\r\nFrom the start\r\n>From quoted\r\nnot From\r\n",
+        );
+        assert_eq!(
+            String::from_utf8(entry).unwrap(),
+            "From bob@example.org Sun Mar 22 22:37:57 2020\n\
+             Subject: foo\n\
+             \n\
+             >From the start\n\
+             >>From quoted\n\
+             not From\n\
+             \n"
+        );
+    }
+
+    #[async_std::test]
+    async fn test_export_mbox() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        let contact_id = Contact::create(&t.ctx, "Bob", "bob@example.org")
+            .await
+            .unwrap();
+        let chat_id = create_by_contact_id(&t.ctx, contact_id).await.unwrap();
+        send_text_msg(&t.ctx, chat_id, "first message".to_string())
+            .await
+            .unwrap();
+        send_text_msg(&t.ctx, chat_id, "From the second message".to_string())
+            .await
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chat.mbox");
+        export_mbox(&t.ctx, chat_id, &path).await.unwrap();
+        let mbox = String::from_utf8(std::fs::read(&path).unwrap()).unwrap();
+        assert!(mbox.starts_with("From alice@example.com "));
+        assert_eq!(mbox.matches("\nFrom alice@example.com ").count(), 1);
+        assert!(mbox.contains("first message"));
+        assert!(mbox.contains("\n>From the second message"));
+        assert!(mbox.contains("bob@example.org"));
+
+        assert!(export_mbox(&t.ctx, ChatId::new(DC_CHAT_ID_DEADDROP), &path)
+            .await
+            .is_err());
+    }
+
     #[async_std::test]
     async fn test_add_device_msg_unlabelled() {
         let t = test_context().await;
@@ -3118,6 +4582,56 @@ mod tests {
             .is_err());
     }
 
+    #[async_std::test]
+    async fn test_forward_msgs() {
+        let t = test_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        t.ctx
+            .set_config(Config::Displayname, Some("Alice"))
+            .await
+            .unwrap();
+        let bob = Contact::create(&t.ctx, "Bob", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = create_by_contact_id(&t.ctx, bob).await.unwrap();
+        let msg_id1 = send_text_msg(&t.ctx, chat_id, "first".to_string())
+            .await
+            .unwrap();
+        let blob = BlobObject::create(&t.ctx, "hello.txt", b"hello")
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::File);
+        msg.set_file(blob.as_name(), None);
+        let msg_id2 = send_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+
+        // the messages are forwarded in the order they were sent
+        let ids = forward_msgs_ex(&t.ctx, &[msg_id2, msg_id1], chat_id, true)
+            .await
+            .unwrap();
+        assert_eq!(ids.len(), 2);
+        let fwd1 = Message::load_from_db(&t.ctx, ids[0]).await.unwrap();
+        assert_eq!(fwd1.get_text(), Some("first".to_string()));
+        assert!(fwd1.is_forwarded());
+        assert_eq!(fwd1.get_forwarded_from(), Some("Alice".to_string()));
+        let fwd2 = Message::load_from_db(&t.ctx, ids[1]).await.unwrap();
+        assert_eq!(fwd2.param.get(Param::File), Some(blob.as_name()));
+
+        let rendered = mimefactory::MimeFactory::from_msg(&t.ctx, &fwd1, false)
+            .await
+            .unwrap()
+            .render()
+            .await
+            .unwrap();
+        let payload = String::from_utf8_lossy(&rendered.message);
+        assert!(payload.contains("From: Alice\r\n"));
+
+        // without attribution, the author is not revealed
+        let ids = forward_msgs(&t.ctx, &ids[..1], chat_id).await.unwrap();
+        let fwd = Message::load_from_db(&t.ctx, ids[0]).await.unwrap();
+        assert!(fwd.is_forwarded());
+        assert_eq!(fwd.get_forwarded_from(), None);
+    }
+
     #[async_std::test]
     async fn test_delete_and_reset_all_device_msgs() {
         let t = test_context().await;
@@ -3258,6 +4772,68 @@ mod tests {
         assert_eq!(chatlist_len(&t.ctx, DC_GCL_ARCHIVED_ONLY).await, 1);
     }
 
+    async fn count_move_jobs(ctx: &Context, msg_id: MsgId) -> usize {
+        job::list(ctx)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|job| job.action == Action::MoveMsg && job.foreign_id == msg_id.to_u32())
+            .count()
+    }
+
+    #[async_std::test]
+    async fn test_archive_move() {
+        let t = dummy_context().await;
+        let mut msg = Message::new(Viewtype::Text);
+        msg.text = Some("foo".to_string());
+        let msg_id = add_device_msg(&t.ctx, None, Some(&mut msg)).await.unwrap();
+        let msg = message::Message::load_from_db(&t.ctx, msg_id)
+            .await
+            .unwrap();
+        message::update_server_uid(&t.ctx, &msg.rfc724_mid, "INBOX", 5).await;
+
+        // not moved without an archive folder or if disabled
+        msg.chat_id
+            .set_visibility(&t.ctx, ChatVisibility::Archived)
+            .await
+            .unwrap();
+        assert_eq!(count_move_jobs(&t.ctx, msg_id).await, 0);
+        t.ctx
+            .set_config(Config::ConfiguredArchiveFolder, Some("Archive"))
+            .await
+            .unwrap();
+        msg.chat_id
+            .set_visibility(&t.ctx, ChatVisibility::Normal)
+            .await
+            .unwrap();
+        assert_eq!(count_move_jobs(&t.ctx, msg_id).await, 0);
+
+        t.ctx
+            .set_config(Config::ArchiveMove, Some("1"))
+            .await
+            .unwrap();
+        msg.chat_id
+            .set_visibility(&t.ctx, ChatVisibility::Archived)
+            .await
+            .unwrap();
+        assert_eq!(count_move_jobs(&t.ctx, msg_id).await, 1);
+
+        // archiving again does not move anything
+        msg.chat_id
+            .set_visibility(&t.ctx, ChatVisibility::Archived)
+            .await
+            .unwrap();
+        assert_eq!(count_move_jobs(&t.ctx, msg_id).await, 1);
+
+        // messages in the archive folder are moved back on unarchiving
+        message::update_server_uid(&t.ctx, &msg.rfc724_mid, "Archive", 7).await;
+        msg.chat_id
+            .set_visibility(&t.ctx, ChatVisibility::Pinned)
+            .await
+            .unwrap();
+        assert_eq!(count_move_jobs(&t.ctx, msg_id).await, 2);
+    }
+
     async fn get_chats_from_chat_list(ctx: &Context, listflags: usize) -> Vec<ChatId> {
         let chatlist = Chatlist::try_load(ctx, listflags, None, None)
             .await
@@ -3395,6 +4971,149 @@ mod tests {
         assert!(!shall_attach_selfavatar(&t.ctx, chat_id).await.unwrap());
     }
 
+    #[async_std::test]
+    async fn test_set_chat_profile_image() {
+        use image::GenericImageView;
+
+        let t = dummy_context().await;
+        let chat_id = create_group_chat(&t.ctx, VerifiedStatus::Unverified, "foo")
+            .await
+            .unwrap();
+        let file = t.dir.path().join("avatar.jpg");
+        let bytes = include_bytes!("../test-data/image/avatar1000x1000.jpg");
+        async_std::fs::write(&file, bytes).await.unwrap();
+
+        set_chat_profile_image(&t.ctx, chat_id, file.to_str().unwrap())
+            .await
+            .unwrap();
+        let chat = Chat::load_from_db(&t.ctx, chat_id).await.unwrap();
+        let image = chat.get_profile_image(&t.ctx).await.unwrap();
+        let img = image::open(&image).unwrap();
+        assert!(img.width() <= AVATAR_SIZE && img.height() <= AVATAR_SIZE);
+
+        // setting the same image again does not create another blob
+        set_chat_profile_image(&t.ctx, chat_id, file.to_str().unwrap())
+            .await
+            .unwrap();
+        let chat = Chat::load_from_db(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(chat.get_profile_image(&t.ctx).await.unwrap(), image);
+        let mut blobs = async_std::fs::read_dir(t.ctx.get_blobdir()).await.unwrap();
+        let mut count = 0;
+        while let Some(entry) = blobs.next().await {
+            entry.unwrap();
+            count += 1;
+        }
+        assert_eq!(count, 1);
+
+        set_chat_profile_image(&t.ctx, chat_id, "").await.unwrap();
+        let chat = Chat::load_from_db(&t.ctx, chat_id).await.unwrap();
+        assert!(chat.get_profile_image(&t.ctx).await.is_none());
+    }
+
+    #[async_std::test]
+    async fn test_muted_chat_fresh_msgs() {
+        let t = dummy_context().await;
+        let mut msg = Message::new(Viewtype::Text);
+        msg.text = Some("message text".to_string());
+        let msg_id = add_device_msg(&t.ctx, None, Some(&mut msg)).await.unwrap();
+        let chat_id = Message::load_from_db(&t.ctx, msg_id).await.unwrap().chat_id;
+        assert_eq!(t.ctx.get_fresh_msgs().await, vec![msg_id]);
+        assert!(!chat_id.is_muted(&t.ctx).await);
+
+        chat_id
+            .set_mute_duration(&t.ctx, MuteDuration::Forever)
+            .await
+            .unwrap();
+        assert!(chat_id.is_muted(&t.ctx).await);
+        assert!(t.ctx.get_fresh_msgs().await.is_empty());
+        assert_eq!(chat_id.get_fresh_msg_cnt(&t.ctx).await, 1);
+
+        chat_id
+            .set_mute_duration(
+                &t.ctx,
+                MuteDuration::Until(SystemTime::now() - Duration::from_secs(3600)),
+            )
+            .await
+            .unwrap();
+        assert!(!chat_id.is_muted(&t.ctx).await);
+        assert_eq!(t.ctx.get_fresh_msgs().await, vec![msg_id]);
+    }
+
+    #[async_std::test]
+    async fn test_unread_count() {
+        let t = dummy_context().await;
+        let mut msg_ids = Vec::new();
+        for _ in 0..3 {
+            let mut msg = Message::new(Viewtype::Text);
+            msg.text = Some("message text".to_string());
+            msg_ids.push(add_device_msg(&t.ctx, None, Some(&mut msg)).await.unwrap());
+        }
+        let chat_id = Message::load_from_db(&t.ctx, msg_ids[0])
+            .await
+            .unwrap()
+            .chat_id;
+        assert_eq!(chat_id.get_fresh_msg_cnt(&t.ctx).await, 3);
+        let chat = Chat::load_from_db(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(chat.get_unread_count(), 3);
+
+        message::markseen_msgs(&t.ctx, vec![msg_ids[0]]).await;
+        assert_eq!(chat_id.get_fresh_msg_cnt(&t.ctx).await, 2);
+        message::delete_msgs(&t.ctx, &[msg_ids[1]]).await;
+        assert_eq!(chat_id.get_fresh_msg_cnt(&t.ctx).await, 1);
+
+        // housekeeping repairs counters that went out of sync
+        t.ctx
+            .sql
+            .execute(
+                "UPDATE chats SET unread_count=5 WHERE id=?;",
+                paramsv![chat_id],
+            )
+            .await
+            .unwrap();
+        let report = t.ctx.run_housekeeping(Default::default()).await.unwrap();
+        assert_eq!(report.repaired_counters, 1);
+        assert_eq!(chat_id.get_fresh_msg_cnt(&t.ctx).await, 1);
+    }
+
+    #[async_std::test]
+    async fn test_mdn_policy() {
+        let t = dummy_context().await;
+        let chat_id = create_group_chat(&t.ctx, VerifiedStatus::Unverified, "foo")
+            .await
+            .unwrap();
+        let chat = Chat::load_from_db(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(chat.get_mdn_policy(), MdnPolicy::Global);
+        assert!(mdns_enabled(&t.ctx, chat_id).await);
+
+        t.ctx
+            .set_config(Config::MdnsEnabled, Some("0"))
+            .await
+            .unwrap();
+        assert!(!mdns_enabled(&t.ctx, chat_id).await);
+
+        set_mdn_policy(&t.ctx, chat_id, MdnPolicy::Always)
+            .await
+            .unwrap();
+        let chat = Chat::load_from_db(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(chat.get_mdn_policy(), MdnPolicy::Always);
+        assert!(mdns_enabled(&t.ctx, chat_id).await);
+
+        t.ctx
+            .set_config(Config::MdnsEnabled, Some("1"))
+            .await
+            .unwrap();
+        set_mdn_policy(&t.ctx, chat_id, MdnPolicy::Never)
+            .await
+            .unwrap();
+        assert!(!mdns_enabled(&t.ctx, chat_id).await);
+
+        assert!(
+            set_mdn_policy(&t.ctx, ChatId::new(DC_CHAT_ID_TRASH), MdnPolicy::Never)
+                .await
+                .is_err()
+        );
+    }
+
     #[async_std::test]
     async fn test_set_mute_duration() {
         let t = dummy_context().await;
@@ -3476,4 +5195,367 @@ mod tests {
         chat_id.set_draft(&t.ctx, Some(&mut msg)).await;
         assert!(!chat_id.parent_is_encrypted(&t.ctx).await.unwrap());
     }
+
+    #[async_std::test]
+    async fn test_send_edit() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        let bob = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = create_by_contact_id(&t.ctx, bob).await.unwrap();
+        let msg_id = send_text_msg(&t.ctx, chat_id, "helo".to_string())
+            .await
+            .unwrap();
+
+        let edit_id = send_edit(&t.ctx, msg_id, "hello".to_string())
+            .await
+            .unwrap();
+        send_edit(&t.ctx, msg_id, "hello!".to_string())
+            .await
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert!(msg.is_edited());
+        assert_eq!(msg.get_text().unwrap(), "hello!");
+        assert_eq!(msg.get_original_text().unwrap(), "helo");
+        assert_eq!(get_chat_msgs(&t.ctx, chat_id, 0, None).await, vec![msg_id]);
+
+        let edit_msg = Message::load_from_db(&t.ctx, edit_id).await.unwrap();
+        assert!(edit_msg.hidden);
+        assert_eq!(edit_msg.param.get_cmd(), SystemMessage::MessageEdit);
+        assert_eq!(
+            edit_msg.param.get(Param::Arg),
+            Some(msg.rfc724_mid.as_str())
+        );
+
+        // drafts cannot be edited
+        let mut draft = Message::new(Viewtype::Text);
+        draft.set_text(Some("draft".to_string()));
+        chat_id.set_draft(&t.ctx, Some(&mut draft)).await;
+        let draft_id = chat_id.get_draft_msg_id(&t.ctx).await.unwrap();
+        assert!(send_edit(&t.ctx, draft_id, "x".to_string()).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn test_send_typing() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        let bob = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = create_by_contact_id(&t.ctx, bob).await.unwrap();
+
+        // disabled by default
+        assert!(!send_typing(&t.ctx, chat_id, true).await.unwrap());
+
+        // no recent activity in the chat
+        t.ctx
+            .set_config(Config::TypingIndicators, Some("1"))
+            .await
+            .unwrap();
+        assert!(!send_typing(&t.ctx, chat_id, true).await.unwrap());
+
+        // bob has no key, typing notifications are never sent unencrypted
+        send_text_msg(&t.ctx, chat_id, "hi".to_string())
+            .await
+            .unwrap();
+        assert!(!send_typing(&t.ctx, chat_id, true).await.unwrap());
+        assert!(!send_typing(&t.ctx, chat_id, false).await.unwrap());
+        assert_eq!(get_chat_msgs(&t.ctx, chat_id, 0, None).await.len(), 1);
+    }
+
+    #[async_std::test]
+    async fn test_broadcast_list() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        let bob = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let claire = Contact::create(&t.ctx, "", "claire@example.net")
+            .await
+            .unwrap();
+
+        let chat_id = create_broadcast_list(&t.ctx).await.unwrap();
+        let chat = Chat::load_from_db(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(chat.get_type(), Chattype::Broadcast);
+        assert_eq!(chat.get_name(), "Broadcast List");
+        assert!(get_chat_contacts(&t.ctx, chat_id).await.is_empty());
+
+        assert!(add_contact_to_chat(&t.ctx, chat_id, bob).await);
+        assert!(add_contact_to_chat(&t.ctx, chat_id, claire).await);
+        assert!(!add_contact_to_chat(&t.ctx, chat_id, DC_CONTACT_ID_SELF).await);
+        assert_eq!(get_chat_contacts(&t.ctx, chat_id).await.len(), 2);
+
+        // members are managed silently
+        let msg_id = send_text_msg(&t.ctx, chat_id, "hi all".to_string())
+            .await
+            .unwrap();
+        assert_eq!(get_chat_msgs(&t.ctx, chat_id, 0, None).await, vec![msg_id]);
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert!(!msg.rfc724_mid.starts_with("Gr."));
+
+        remove_contact_from_chat(&t.ctx, chat_id, claire)
+            .await
+            .unwrap();
+        assert_eq!(get_chat_contacts(&t.ctx, chat_id).await, vec![bob]);
+        assert_eq!(get_chat_msgs(&t.ctx, chat_id, 0, None).await, vec![msg_id]);
+    }
+
+    #[async_std::test]
+    async fn test_group_admins() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        let bob = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let claire = Contact::create(&t.ctx, "", "claire@example.net")
+            .await
+            .unwrap();
+
+        let chat_id = create_group_chat(&t.ctx, VerifiedStatus::Unverified, "foo")
+            .await
+            .unwrap();
+        assert_eq!(
+            get_group_admins(&t.ctx, chat_id).await,
+            vec![DC_CONTACT_ID_SELF]
+        );
+        assert!(add_contact_to_chat(&t.ctx, chat_id, bob).await);
+        assert!(!is_group_admin(&t.ctx, chat_id, bob).await);
+
+        set_group_admin(&t.ctx, chat_id, bob, true).await.unwrap();
+        assert!(is_group_admin(&t.ctx, chat_id, bob).await);
+        assert!(set_group_admin(&t.ctx, chat_id, claire, true)
+            .await
+            .is_err());
+
+        // without admin role, the group cannot be changed anymore
+        set_group_admin(&t.ctx, chat_id, DC_CONTACT_ID_SELF, false)
+            .await
+            .unwrap();
+        assert_eq!(get_group_admins(&t.ctx, chat_id).await, vec![bob]);
+        assert!(set_chat_name(&t.ctx, chat_id, "bar").await.is_err());
+        assert!(set_chat_profile_image(&t.ctx, chat_id, "").await.is_err());
+        assert!(!add_contact_to_chat(&t.ctx, chat_id, claire).await);
+        assert!(remove_contact_from_chat(&t.ctx, chat_id, bob)
+            .await
+            .is_err());
+        assert!(set_group_admin(&t.ctx, chat_id, DC_CONTACT_ID_SELF, true)
+            .await
+            .is_err());
+        assert_eq!(
+            Chat::load_from_db(&t.ctx, chat_id).await.unwrap().name,
+            "foo"
+        );
+
+        // leaving is still possible
+        remove_contact_from_chat(&t.ctx, chat_id, DC_CONTACT_ID_SELF)
+            .await
+            .unwrap();
+        assert_eq!(get_chat_contacts(&t.ctx, chat_id).await, vec![bob]);
+    }
+
+    #[async_std::test]
+    async fn test_get_msgs_page() {
+        let t = dummy_context().await;
+        let mut msg_ids = Vec::new();
+        for i in 0..5 {
+            let mut msg = Message::new(Viewtype::Text);
+            msg.text = Some(format!("message {}", i));
+            msg_ids.push(add_device_msg(&t.ctx, None, Some(&mut msg)).await.unwrap());
+        }
+        let chat_id = Message::load_from_db(&t.ctx, msg_ids[0])
+            .await
+            .unwrap()
+            .chat_id;
+        // two messages per day, the first message of the second window is on a new day
+        for (i, msg_id) in msg_ids.iter().enumerate() {
+            t.ctx
+                .sql
+                .execute(
+                    "UPDATE msgs SET timestamp=? WHERE id=?;",
+                    paramsv![86400 * 10 + 86400 * (i as i64 / 2), *msg_id],
+                )
+                .await
+                .unwrap();
+        }
+        let day = MsgId::new(DC_MSG_ID_DAYMARKER);
+
+        let page = get_msgs_page(&t.ctx, chat_id, None, 3, 0).await.unwrap();
+        assert_eq!(page, msg_ids[2..].to_vec());
+        let page = get_msgs_page(&t.ctx, chat_id, Some(msg_ids[2]), 3, 0)
+            .await
+            .unwrap();
+        assert_eq!(page, msg_ids[..2].to_vec());
+        let page = get_msgs_page(&t.ctx, chat_id, Some(msg_ids[0]), 3, 0)
+            .await
+            .unwrap();
+        assert!(page.is_empty());
+
+        let page = get_msgs_page(&t.ctx, chat_id, None, 2, DC_GCM_ADDDAYMARKER)
+            .await
+            .unwrap();
+        assert_eq!(page, vec![msg_ids[3], day, msg_ids[4]]);
+        let page = get_msgs_page(&t.ctx, chat_id, Some(msg_ids[3]), 2, DC_GCM_ADDDAYMARKER)
+            .await
+            .unwrap();
+        assert_eq!(page, vec![msg_ids[1], day, msg_ids[2]]);
+        assert!(
+            get_msgs_page(&t.ctx, ChatId::new(DC_CHAT_ID_STARRED), None, 2, 0)
+                .await
+                .is_err()
+        );
+
+        // expired messages are not returned
+        let group_id = create_group_chat(&t.ctx, VerifiedStatus::Unverified, "group")
+            .await
+            .unwrap();
+        add_info_msg(&t.ctx, group_id, "old").await;
+        add_info_msg(&t.ctx, group_id, "new").await;
+        let page = get_msgs_page(&t.ctx, group_id, None, 3, 0).await.unwrap();
+        assert_eq!(page.len(), 2);
+        t.ctx
+            .sql
+            .execute(
+                "UPDATE msgs SET ephemeral_timestamp=? WHERE id=?;",
+                paramsv![time() - 1, page[0]],
+            )
+            .await
+            .unwrap();
+        let expired_page = get_msgs_page(&t.ctx, group_id, None, 3, 0).await.unwrap();
+        assert_eq!(expired_page, vec![page[1]]);
+
+        // jump to the first unread message
+        assert_eq!(
+            get_first_unread_msg(&t.ctx, chat_id).await.unwrap(),
+            Some(msg_ids[0])
+        );
+        t.ctx
+            .sql
+            .execute(
+                "UPDATE msgs SET state=? WHERE id<=?;",
+                paramsv![MessageState::InSeen, msg_ids[2]],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            get_first_unread_msg(&t.ctx, chat_id).await.unwrap(),
+            Some(msg_ids[3])
+        );
+        let marker1 = MsgId::new(DC_MSG_ID_MARKER1);
+        let page = get_unread_msgs_page(&t.ctx, chat_id, 4, 0).await.unwrap();
+        assert_eq!(
+            page,
+            vec![msg_ids[1], msg_ids[2], marker1, msg_ids[3], msg_ids[4]]
+        );
+
+        t.ctx
+            .sql
+            .execute(
+                "UPDATE msgs SET state=? WHERE chat_id=?;",
+                paramsv![MessageState::InSeen, chat_id],
+            )
+            .await
+            .unwrap();
+        assert_eq!(get_first_unread_msg(&t.ctx, chat_id).await.unwrap(), None);
+        let page = get_unread_msgs_page(&t.ctx, chat_id, 2, 0).await.unwrap();
+        assert_eq!(page, msg_ids[3..].to_vec());
+    }
+
+    #[test]
+    fn test_match_offsets() {
+        let words = search_words("Äpf, ban");
+        assert_eq!(
+            match_offsets("äpfel und Bananen, ÄPFEL", &words),
+            vec![(0, 4), (11, 14), (20, 24)]
+        );
+        assert!(match_offsets("Apfelbanane", &words).is_empty());
+        assert!(match_offsets("", &words).is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_search_in_chat() {
+        let t = dummy_context().await;
+        t.ctx
+            .set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+        let chat_id = create_by_contact_id(&t.ctx, DC_CONTACT_ID_SELF)
+            .await
+            .unwrap();
+        let mut ids = Vec::new();
+        for text in &[
+            "Apples and more apples",
+            "no fruits here",
+            "pineapple, apple",
+        ] {
+            let mut msg = Message::new(Viewtype::Text);
+            msg.set_text(Some(text.to_string()));
+            ids.push(prepare_msg(&t.ctx, chat_id, &mut msg).await.unwrap());
+        }
+
+        let found = search_in_chat(&t.ctx, chat_id, "appl").await.unwrap();
+        assert_eq!(
+            found,
+            vec![
+                ChatSearchMatch {
+                    msg_id: ids[0],
+                    offsets: vec![(0, 4), (16, 20)]
+                },
+                ChatSearchMatch {
+                    msg_id: ids[2],
+                    offsets: vec![(11, 15)]
+                }
+            ]
+        );
+        assert!(search_in_chat(&t.ctx, chat_id, " ")
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(
+            search_in_chat(&t.ctx, ChatId::new(DC_CHAT_ID_TRASH), "appl")
+                .await
+                .is_err()
+        );
+    }
+
+    #[async_std::test]
+    async fn test_report_spam() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        let contact_id = Contact::create(&t.ctx, "", "spammer@example.net")
+            .await
+            .unwrap();
+        dc_receive_imf(
+            &t.ctx,
+            b"From: spammer@example.net\n\
+                 To: alice@example.com\n\
+                 Subject: foo\n\
+                 Message-ID: <spam-1@example.net>\n\
+                 Chat-Version: 1.0\n\
+                 Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                 \n\
+                 buy now\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        let (_, _, msg_id) = message::rfc724_mid_exists(&t.ctx, "spam-1@example.net")
+            .await
+            .unwrap()
+            .unwrap();
+        t.ctx
+            .set_config(Config::ConfiguredSpamFolder, Some("Junk"))
+            .await
+            .unwrap();
+
+        report_spam(&t.ctx, &[msg_id]).await.unwrap();
+        assert!(Contact::is_blocked_load(&t.ctx, contact_id).await);
+        assert!(job::list(&t.ctx)
+            .await
+            .unwrap()
+            .iter()
+            .any(|job| job.action == Action::MoveMsg && job.foreign_id == msg_id.to_u32()));
+    }
 }
@@ -38,6 +38,8 @@ use crate::stock::StockMessage;
 pub struct Chatlist {
     /// Stores pairs of `chat_id, message_id`
     ids: Vec<(ChatId, MsgId)>,
+    /// Summaries loaded along with the list, `None` for special chats.
+    summaries: Vec<Option<Lot>>,
 }
 
 impl Chatlist {
@@ -91,6 +93,24 @@ impl Chatlist {
         listflags: usize,
         query: Option<&str>,
         query_contact_id: Option<u32>,
+    ) -> Result<Self> {
+        Self::try_load_page(context, listflags, query, query_contact_id, 0, None).await
+    }
+
+    /// Loads a part of the chatlist.
+    ///
+    /// Returns the items of the list returned by [Chatlist::try_load]
+    /// starting at the index `offset`, at most `limit` items or all
+    /// remaining items if `limit` is `None`.  Special chats are returned
+    /// at the same positions as in the whole list, so UIs can load the
+    /// list page by page; [Chatlist::count] returns the total number of items.
+    pub async fn try_load_page(
+        context: &Context,
+        listflags: usize,
+        query: Option<&str>,
+        query_contact_id: Option<u32>,
+        offset: usize,
+        limit: Option<usize>,
     ) -> Result<Self> {
         let flag_archived_only = 0 != listflags & DC_GCL_ARCHIVED_ONLY;
         let flag_for_forwarding = 0 != listflags & DC_GCL_FOR_FORWARDING;
@@ -102,13 +122,52 @@ impl Chatlist {
         if let Err(err) = delete_device_expired_messages(context).await {
             warn!(context, "Failed to hide expired messages: {}", err);
         }
+        if let Err(err) = update_summaries(context).await {
+            warn!(context, "Failed to update chatlist summaries: {}", err);
+        }
 
-        let mut add_archived_link_item = false;
+        let normal_list = query_contact_id.is_none() && !flag_archived_only && query.is_none();
+        let add_specials = normal_list && !flag_no_specials;
+
+        // the deaddrop is shown on top of the normal chatlist
+        let mut prefix = Vec::new();
+        if add_specials && !flag_for_forwarding {
+            if let Some(last_deaddrop_fresh_msg_id) = get_last_deaddrop_fresh_msg(context).await {
+                prefix.push((
+                    ChatId::new(DC_CHAT_ID_DEADDROP),
+                    last_deaddrop_fresh_msg_id,
+                    None,
+                ));
+            }
+        }
+        let prefix_len = prefix.len();
+        let mut items: Vec<(ChatId, MsgId, Option<Lot>)> = prefix
+            .into_iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .collect();
+
+        // range of the chats loaded from the database, -1 means no limit for SQLite
+        let sql_offset = offset.saturating_sub(prefix_len);
+        let sql_limit = limit.map(|limit| limit - items.len());
+        let sql_limit_param = sql_limit.map_or(-1, |limit| limit as i64);
+        let sql_offset_param = sql_offset as i64;
 
         let process_row = |row: &rusqlite::Row| {
             let chat_id: ChatId = row.get(0)?;
-            let msg_id: MsgId = row.get(1).unwrap_or_default();
-            Ok((chat_id, msg_id))
+            let msg_id: Option<MsgId> = row.get(1)?;
+            let summary = if msg_id.is_some() {
+                let mut summary = Lot::new();
+                summary.text1 = row.get(2)?;
+                summary.text1_meaning = row.get(3)?;
+                summary.text2 = row.get(4)?;
+                summary.timestamp = row.get(5)?;
+                summary.state = row.get(6)?;
+                Some(summary)
+            } else {
+                None
+            };
+            Ok((chat_id, msg_id.unwrap_or_default(), summary))
         };
 
         let process_rows = |rows: rusqlite::MappedRows<_>| {
@@ -125,12 +184,11 @@ impl Chatlist {
             ChatId::new(0)
         };
 
-        // select with left join and minimum:
+        // select the chats along with their summaries:
         //
-        // - the inner select must use `hidden` and _not_ `m.hidden`
-        //   which would refer the outer select and take a lot of time
-        // - `GROUP BY` is needed several messages may have the same
-        //   timestamp
+        // - the summaries of all chats were built by update_summaries() above,
+        //   `sort_timestamp` is the timestamp of the last message
+        //   and NULL for chats without messages
         // - the list starts with the newest chats
         //
         // nb: the query currently shows messages from blocked
@@ -140,24 +198,19 @@ impl Chatlist {
         // tg do the same) for the deaddrop, however, they should
         // really be hidden, however, _currently_ the deaddrop is not
         // shown at all permanent in the chatlist.
-        let mut ids = if let Some(query_contact_id) = query_contact_id {
+        let rows = if let Some(query_contact_id) = query_contact_id {
             // show chats shared with a given contact
             context.sql.query_map(
-                "SELECT c.id, m.id
+                "SELECT c.id, s.msg_id, s.text1, s.text1_meaning, s.text2, s.timestamp, s.state
                  FROM chats c
-                 LEFT JOIN msgs m
-                        ON c.id=m.chat_id
-                       AND m.timestamp=(
-                               SELECT MAX(timestamp)
-                                 FROM msgs
-                                WHERE chat_id=c.id
-                                  AND (hidden=0 OR state=?1))
+                 LEFT JOIN chat_summaries s
+                        ON s.chat_id=c.id
                  WHERE c.id>9
                    AND c.blocked=0
-                   AND c.id IN(SELECT chat_id FROM chats_contacts WHERE contact_id=?2)
-                 GROUP BY c.id
-                 ORDER BY c.archived=?3 DESC, IFNULL(m.timestamp,c.created_timestamp) DESC, m.id DESC;",
-                paramsv![MessageState::OutDraft, query_contact_id as i32, ChatVisibility::Pinned],
+                   AND c.id IN(SELECT chat_id FROM chats_contacts WHERE contact_id=?1)
+                 ORDER BY c.archived=?2 DESC, IFNULL(s.sort_timestamp,c.created_timestamp) DESC, s.msg_id DESC
+                 LIMIT ?3 OFFSET ?4;",
+                paramsv![query_contact_id as i32, ChatVisibility::Pinned, sql_limit_param, sql_offset_param],
                 process_row,
                 process_rows,
             ).await?
@@ -169,21 +222,16 @@ impl Chatlist {
             context
                 .sql
                 .query_map(
-                    "SELECT c.id, m.id
+                    "SELECT c.id, s.msg_id, s.text1, s.text1_meaning, s.text2, s.timestamp, s.state
                  FROM chats c
-                 LEFT JOIN msgs m
-                        ON c.id=m.chat_id
-                       AND m.timestamp=(
-                               SELECT MAX(timestamp)
-                                 FROM msgs
-                                WHERE chat_id=c.id
-                                  AND (hidden=0 OR state=?))
+                 LEFT JOIN chat_summaries s
+                        ON s.chat_id=c.id
                  WHERE c.id>9
                    AND c.blocked=0
                    AND c.archived=1
-                 GROUP BY c.id
-                 ORDER BY IFNULL(m.timestamp,c.created_timestamp) DESC, m.id DESC;",
-                    paramsv![MessageState::OutDraft],
+                 ORDER BY IFNULL(s.sort_timestamp,c.created_timestamp) DESC, s.msg_id DESC
+                 LIMIT ?1 OFFSET ?2;",
+                    paramsv![sql_limit_param, sql_offset_param],
                     process_row,
                     process_rows,
                 )
@@ -202,21 +250,16 @@ impl Chatlist {
             context
                 .sql
                 .query_map(
-                    "SELECT c.id, m.id
+                    "SELECT c.id, s.msg_id, s.text1, s.text1_meaning, s.text2, s.timestamp, s.state
                  FROM chats c
-                 LEFT JOIN msgs m
-                        ON c.id=m.chat_id
-                       AND m.timestamp=(
-                               SELECT MAX(timestamp)
-                                 FROM msgs
-                                WHERE chat_id=c.id
-                                  AND (hidden=0 OR state=?1))
-                 WHERE c.id>9 AND c.id!=?2
+                 LEFT JOIN chat_summaries s
+                        ON s.chat_id=c.id
+                 WHERE c.id>9 AND c.id!=?1
                    AND c.blocked=0
-                   AND c.name LIKE ?3
-                 GROUP BY c.id
-                 ORDER BY IFNULL(m.timestamp,c.created_timestamp) DESC, m.id DESC;",
-                    paramsv![MessageState::OutDraft, skip_id, str_like_cmd],
+                   AND c.name LIKE ?2
+                 ORDER BY c.archived=?3 DESC, IFNULL(s.sort_timestamp,c.created_timestamp) DESC, s.msg_id DESC
+                 LIMIT ?4 OFFSET ?5;",
+                    paramsv![skip_id, str_like_cmd, ChatVisibility::Pinned, sql_limit_param, sql_offset_param],
                     process_row,
                     process_rows,
                 )
@@ -231,48 +274,130 @@ impl Chatlist {
             } else {
                 ChatId::new(0)
             };
-            let mut ids = context.sql.query_map(
-                "SELECT c.id, m.id
+            context.sql.query_map(
+                "SELECT c.id, s.msg_id, s.text1, s.text1_meaning, s.text2, s.timestamp, s.state
                  FROM chats c
-                 LEFT JOIN msgs m
-                        ON c.id=m.chat_id
-                       AND m.timestamp=(
-                               SELECT MAX(timestamp)
-                                 FROM msgs
-                                WHERE chat_id=c.id
-                                  AND (hidden=0 OR state=?1))
-                 WHERE c.id>9 AND c.id!=?2
+                 LEFT JOIN chat_summaries s
+                        ON s.chat_id=c.id
+                 WHERE c.id>9 AND c.id!=?1
                    AND c.blocked=0
-                   AND NOT c.archived=?3
-                 GROUP BY c.id
-                 ORDER BY c.id=?4 DESC, c.archived=?5 DESC, IFNULL(m.timestamp,c.created_timestamp) DESC, m.id DESC;",
-                paramsv![MessageState::OutDraft, skip_id, ChatVisibility::Archived, sort_id_up, ChatVisibility::Pinned],
+                   AND NOT c.archived=?2
+                 ORDER BY c.id=?3 DESC, c.archived=?4 DESC, IFNULL(s.sort_timestamp,c.created_timestamp) DESC, s.msg_id DESC
+                 LIMIT ?5 OFFSET ?6;",
+                paramsv![skip_id, ChatVisibility::Archived, sort_id_up, ChatVisibility::Pinned, sql_limit_param, sql_offset_param],
                 process_row,
                 process_rows,
-            ).await?;
+            ).await?
+        };
+        let rows_len = rows.len();
+        items.extend(rows);
+
+        // the archive link and the all-done hint are shown below the chats
+        let reached_end = sql_limit.map_or(true, |limit| rows_len < limit);
+        if add_specials && reached_end && dc_get_archived_cnt(context).await > 0 {
+            let chat_cnt = if rows_len == 0 && sql_offset > 0 {
+                count_unarchived_chats(context, skip_id).await?
+            } else {
+                sql_offset + rows_len
+            };
+            let mut suffix = Vec::new();
+            if prefix_len + chat_cnt == 0 && flag_add_alldone_hint {
+                suffix.push((ChatId::new(DC_CHAT_ID_ALLDONE_HINT), MsgId::new(0), None));
+            }
+            suffix.push((ChatId::new(DC_CHAT_ID_ARCHIVED_LINK), MsgId::new(0), None));
+
+            let remaining = limit.map_or(usize::MAX, |limit| limit - items.len());
+            items.extend(
+                suffix
+                    .into_iter()
+                    .skip(offset.saturating_sub(prefix_len + chat_cnt))
+                    .take(remaining),
+            );
+        }
+
+        let (ids, summaries) = items
+            .into_iter()
+            .map(|(chat_id, msg_id, summary)| ((chat_id, msg_id), summary))
+            .unzip();
+        Ok(Chatlist { ids, summaries })
+    }
+
+    /// Returns the number of items of the list returned by [Chatlist::try_load]
+    /// without loading the list.
+    pub async fn count(
+        context: &Context,
+        listflags: usize,
+        query: Option<&str>,
+        query_contact_id: Option<u32>,
+    ) -> Result<usize> {
+        let flag_archived_only = 0 != listflags & DC_GCL_ARCHIVED_ONLY;
+        let flag_for_forwarding = 0 != listflags & DC_GCL_FOR_FORWARDING;
+        let flag_no_specials = 0 != listflags & DC_GCL_NO_SPECIALS;
+        let flag_add_alldone_hint = 0 != listflags & DC_GCL_ADD_ALLDONE_HINT;
+
+        let skip_id = if flag_for_forwarding {
+            chat::lookup_by_contact_id(context, DC_CONTACT_ID_DEVICE)
+                .await
+                .unwrap_or_default()
+                .0
+        } else {
+            ChatId::new(0)
+        };
+
+        let count: i64 = if let Some(query_contact_id) = query_contact_id {
+            context
+                .sql
+                .query_row(
+                    "SELECT COUNT(*) FROM chats c
+                      WHERE c.id>9
+                        AND c.blocked=0
+                        AND c.id IN(SELECT chat_id FROM chats_contacts WHERE contact_id=?);",
+                    paramsv![query_contact_id as i32],
+                    |row| row.get(0),
+                )
+                .await?
+        } else if flag_archived_only {
+            context
+                .sql
+                .query_row(
+                    "SELECT COUNT(*) FROM chats c WHERE c.id>9 AND c.blocked=0 AND c.archived=1;",
+                    paramsv![],
+                    |row| row.get(0),
+                )
+                .await?
+        } else if let Some(query) = query {
+            let query = query.trim().to_string();
+            ensure!(!query.is_empty(), "missing query");
+            if let Err(err) = update_special_chat_names(context).await {
+                warn!(context, "cannot update special chat names: {:?}", err)
+            }
+            context
+                .sql
+                .query_row(
+                    "SELECT COUNT(*) FROM chats c
+                      WHERE c.id>9 AND c.id!=?
+                        AND c.blocked=0
+                        AND c.name LIKE ?;",
+                    paramsv![skip_id, format!("%{}%", query)],
+                    |row| row.get(0),
+                )
+                .await?
+        } else {
+            let mut count = count_unarchived_chats(context, skip_id).await?;
             if !flag_no_specials {
-                if let Some(last_deaddrop_fresh_msg_id) = get_last_deaddrop_fresh_msg(context).await
-                {
-                    if !flag_for_forwarding {
-                        ids.insert(
-                            0,
-                            (ChatId::new(DC_CHAT_ID_DEADDROP), last_deaddrop_fresh_msg_id),
-                        );
+                if !flag_for_forwarding && get_last_deaddrop_fresh_msg(context).await.is_some() {
+                    count += 1;
+                }
+                if dc_get_archived_cnt(context).await > 0 {
+                    if count == 0 && flag_add_alldone_hint {
+                        count += 1;
                     }
+                    count += 1;
                 }
-                add_archived_link_item = true;
             }
-            ids
+            return Ok(count);
         };
-
-        if add_archived_link_item && dc_get_archived_cnt(context).await > 0 {
-            if ids.is_empty() && flag_add_alldone_hint {
-                ids.push((ChatId::new(DC_CHAT_ID_ALLDONE_HINT), MsgId::new(0)));
-            }
-            ids.push((ChatId::new(DC_CHAT_ID_ARCHIVED_LINK), MsgId::new(0)));
-        }
-
-        Ok(Chatlist { ids })
+        Ok(count as usize)
     }
 
     /// Find out the number of chats.
@@ -320,65 +445,189 @@ impl Chatlist {
     /// - dc_lot_t::state: The state of the message as one of the DC_STATE_* constants (see #dc_msg_get_state()).
     //    0 if not applicable.
     pub async fn get_summary(&self, context: &Context, index: usize, chat: Option<&Chat>) -> Lot {
-        // The summary is created by the chat, not by the last message.
-        // This is because we may want to display drafts here or stuff as
-        // "is typing".
-        // Also, sth. as "No messages" would not work if the summary comes from a message.
-        let mut ret = Lot::new();
-
         let (chat_id, lastmsg_id) = match self.ids.get(index) {
             Some(ids) => ids,
             None => {
+                let mut ret = Lot::new();
                 ret.text2 = Some("ErrBadChatlistIndex".to_string());
                 return ret;
             }
         };
 
-        let chat_loaded: Chat;
-        let chat = if let Some(chat) = chat {
-            chat
-        } else if let Ok(chat) = Chat::load_from_db(context, *chat_id).await {
-            chat_loaded = chat;
-            &chat_loaded
-        } else {
-            return ret;
-        };
-
-        let mut lastcontact = None;
+        if let Some(Some(summary)) = self.summaries.get(index) {
+            return summary.clone();
+        }
 
-        let lastmsg = if let Ok(lastmsg) = Message::load_from_db(context, *lastmsg_id).await {
-            if lastmsg.from_id != DC_CONTACT_ID_SELF
-                && (chat.typ == Chattype::Group || chat.typ == Chattype::VerifiedGroup)
-            {
-                lastcontact = Contact::load_from_db(context, lastmsg.from_id).await.ok();
+        if chat_id.is_special() {
+            build_summary(context, *chat_id, *lastmsg_id, chat).await.0
+        } else {
+            // the summary could not be built when the list was loaded
+            match update_summary(context, *chat_id, chat).await {
+                Ok(summary) => summary,
+                Err(err) => {
+                    warn!(context, "Cannot build chatlist summary: {}", err);
+                    Lot::new()
+                }
             }
+        }
+    }
 
-            Some(lastmsg)
-        } else {
-            None
-        };
+    pub fn get_index_for_id(&self, id: ChatId) -> Option<usize> {
+        self.ids.iter().position(|(chat_id, _)| chat_id == &id)
+    }
+}
 
-        if chat.id.is_archived_link() {
-            ret.text2 = None;
-        } else if lastmsg.is_none() || lastmsg.as_ref().unwrap().from_id == DC_CONTACT_ID_UNDEFINED
+/// Builds the summary of `chat_id` with `lastmsg_id` as the last message.
+///
+/// Returns the summary and the last message, if any.
+async fn build_summary(
+    context: &Context,
+    chat_id: ChatId,
+    lastmsg_id: MsgId,
+    chat: Option<&Chat>,
+) -> (Lot, Option<Message>) {
+    // The summary is created by the chat, not by the last message.
+    // This is because we may want to display drafts here or stuff as
+    // "is typing".
+    // Also, sth. as "No messages" would not work if the summary comes from a message.
+    let mut ret = Lot::new();
+
+    let chat_loaded: Chat;
+    let chat = if let Some(chat) = chat {
+        chat
+    } else if let Ok(chat) = Chat::load_from_db(context, chat_id).await {
+        chat_loaded = chat;
+        &chat_loaded
+    } else {
+        return (ret, None);
+    };
+
+    let mut lastcontact = None;
+
+    let mut lastmsg = if let Ok(lastmsg) = Message::load_from_db(context, lastmsg_id).await {
+        if lastmsg.from_id != DC_CONTACT_ID_SELF
+            && (chat.typ == Chattype::Group
+                || chat.typ == Chattype::VerifiedGroup
+                || chat.typ == Chattype::Mailinglist)
         {
-            ret.text2 = Some(
-                context
-                    .stock_str(StockMessage::NoMessages)
-                    .await
-                    .to_string(),
-            );
-        } else {
-            ret.fill(&mut lastmsg.unwrap(), chat, lastcontact.as_ref(), context)
-                .await;
+            lastcontact = Contact::load_from_db(context, lastmsg.from_id).await.ok();
         }
 
-        ret
+        Some(lastmsg)
+    } else {
+        None
+    };
+
+    if chat.id.is_archived_link() {
+        ret.text2 = None;
+    } else {
+        match lastmsg {
+            Some(ref mut lastmsg) if lastmsg.from_id != DC_CONTACT_ID_UNDEFINED => {
+                ret.fill(lastmsg, chat, lastcontact.as_ref(), context).await;
+            }
+            _ => {
+                ret.text2 = Some(
+                    context
+                        .stock_str(StockMessage::NoMessages)
+                        .await
+                        .to_string(),
+                );
+            }
+        }
     }
 
-    pub fn get_index_for_id(&self, id: ChatId) -> Option<usize> {
-        self.ids.iter().position(|(chat_id, _)| chat_id == &id)
+    (ret, lastmsg)
+}
+
+/// Builds the summary of `chat_id` from its last message
+/// and stores it in the `chat_summaries` table.
+///
+/// The row is removed by triggers as soon as the messages of the chat
+/// or the name of the sender of the last message change.
+async fn update_summary(context: &Context, chat_id: ChatId, chat: Option<&Chat>) -> Result<Lot> {
+    let last_msg_query = "SELECT id FROM msgs
+                           WHERE chat_id=?1
+                             AND (hidden=0 OR state=?2)
+                           ORDER BY timestamp DESC, id DESC
+                           LIMIT 1";
+    let lastmsg_id: MsgId = context
+        .sql
+        .query_get_value_result(last_msg_query, paramsv![chat_id, MessageState::OutDraft])
+        .await?
+        .unwrap_or_default();
+    let (summary, lastmsg) = build_summary(context, chat_id, lastmsg_id, chat).await;
+
+    // the summary is not stored if the chat got a new message meanwhile
+    context
+        .sql
+        .execute(
+            format!(
+                "INSERT OR REPLACE INTO chat_summaries
+                   (chat_id, msg_id, from_id, text1, text1_meaning, text2, timestamp, state, sort_timestamp)
+                 SELECT ?1, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10
+                  WHERE ?3=IFNULL(({}), 0);",
+                last_msg_query
+            ),
+            paramsv![
+                chat_id,
+                MessageState::OutDraft,
+                lastmsg_id,
+                lastmsg.as_ref().map_or(0, |msg| msg.from_id) as i32,
+                summary.text1,
+                summary.text1_meaning,
+                summary.text2,
+                summary.timestamp,
+                summary.state,
+                lastmsg.as_ref().map(|msg| msg.timestamp_sort)
+            ],
+        )
+        .await?;
+    Ok(summary)
+}
+
+/// Builds the summaries missing in the `chat_summaries` table,
+/// eg. because the chats got new messages since the chatlist was loaded last.
+async fn update_summaries(context: &Context) -> Result<usize> {
+    let chat_ids = context
+        .sql
+        .query_map(
+            "SELECT id FROM chats
+              WHERE id>9
+                AND blocked=0
+                AND id NOT IN (SELECT chat_id FROM chat_summaries);",
+            paramsv![],
+            |row| row.get::<_, ChatId>(0),
+            |ids| {
+                ids.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    for chat_id in &chat_ids {
+        update_summary(context, *chat_id, None).await?;
     }
+    Ok(chat_ids.len())
+}
+
+/// Drops all chatlist summaries, eg. because a stock string changed.
+///
+/// The summaries are rebuilt when the chatlist is loaded next.
+pub(crate) async fn clear_summaries(context: &Context) -> Result<()> {
+    context
+        .sql
+        .execute("DELETE FROM chat_summaries;", paramsv![])
+        .await?;
+    Ok(())
+}
+
+/// Rebuilds the summaries of all chats.
+///
+/// The summaries are kept up to date automatically, this is only needed
+/// if the database was modified by other means.  Returns the number of
+/// summaries built.
+pub async fn rebuild_summaries(context: &Context) -> Result<usize> {
+    clear_summaries(context).await?;
+    update_summaries(context).await
 }
 
 /// Returns the number of archived chats
@@ -416,6 +665,22 @@ async fn get_last_deaddrop_fresh_msg(context: &Context) -> Option<MsgId> {
         .await
 }
 
+/// Returns the number of chats in the normal chatlist, without special chats.
+async fn count_unarchived_chats(context: &Context, skip_id: ChatId) -> Result<usize> {
+    let count: i64 = context
+        .sql
+        .query_row(
+            "SELECT COUNT(*) FROM chats c
+              WHERE c.id>9 AND c.id!=?
+                AND c.blocked=0
+                AND NOT c.archived=?;",
+            paramsv![skip_id, ChatVisibility::Archived],
+            |row| row.get(0),
+        )
+        .await?;
+    Ok(count as usize)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -470,6 +735,115 @@ mod tests {
         assert_eq!(chats.len(), 1);
     }
 
+    #[async_std::test]
+    async fn test_try_load_page() {
+        let t = dummy_context().await;
+        let mut chat_ids = Vec::new();
+        for name in &["a chat", "b chat", "c chat", "d chat", "e chat"] {
+            chat_ids.push(
+                create_group_chat(&t.ctx, VerifiedStatus::Unverified, name)
+                    .await
+                    .unwrap(),
+            );
+        }
+        chat_ids[0]
+            .set_visibility(&t.ctx, ChatVisibility::Archived)
+            .await
+            .unwrap();
+
+        for flags in &[0, DC_GCL_NO_SPECIALS, DC_GCL_ARCHIVED_ONLY] {
+            let all = Chatlist::try_load(&t.ctx, *flags, None, None)
+                .await
+                .unwrap();
+            assert_eq!(
+                Chatlist::count(&t.ctx, *flags, None, None).await.unwrap(),
+                all.len()
+            );
+            let mut paged = Vec::new();
+            let mut offset = 0;
+            loop {
+                let page = Chatlist::try_load_page(&t.ctx, *flags, None, None, offset, Some(2))
+                    .await
+                    .unwrap();
+                assert!(page.len() <= 2);
+                if page.is_empty() {
+                    break;
+                }
+                offset += page.len();
+                paged.extend(page.ids);
+            }
+            assert_eq!(paged, all.ids);
+        }
+
+        // the archive link is the last item of the normal chatlist
+        let all = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        assert_eq!(all.len(), 5);
+        assert_eq!(all.get_chat_id(4), ChatId::new(DC_CHAT_ID_ARCHIVED_LINK));
+        let page = Chatlist::try_load_page(&t.ctx, 0, None, None, 4, Some(10))
+            .await
+            .unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page.get_chat_id(0), ChatId::new(DC_CHAT_ID_ARCHIVED_LINK));
+        let page = Chatlist::try_load_page(&t.ctx, 0, None, None, 1, None)
+            .await
+            .unwrap();
+        assert_eq!(page.ids, all.ids[1..].to_vec());
+
+        // all chats archived
+        for chat_id in &chat_ids[1..] {
+            chat_id
+                .set_visibility(&t.ctx, ChatVisibility::Archived)
+                .await
+                .unwrap();
+        }
+        let flags = DC_GCL_ADD_ALLDONE_HINT;
+        let all = Chatlist::try_load(&t.ctx, flags, None, None).await.unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(Chatlist::count(&t.ctx, flags, None, None).await.unwrap(), 2);
+        let page = Chatlist::try_load_page(&t.ctx, flags, None, None, 1, Some(1))
+            .await
+            .unwrap();
+        assert_eq!(page.ids, all.ids[1..].to_vec());
+    }
+
+    #[async_std::test]
+    async fn test_pinned_first() {
+        let t = dummy_context().await;
+        let chat_id1 = create_group_chat(&t.ctx, VerifiedStatus::Unverified, "a chat")
+            .await
+            .unwrap();
+        let chat_id2 = create_group_chat(&t.ctx, VerifiedStatus::Unverified, "b chat")
+            .await
+            .unwrap();
+        let chat_id3 = create_group_chat(&t.ctx, VerifiedStatus::Unverified, "c chat")
+            .await
+            .unwrap();
+
+        chat_id1.pin(&t.ctx).await.unwrap();
+        chat_id2.pin(&t.ctx).await.unwrap();
+        for query in &[None, Some("chat")] {
+            let chats = Chatlist::try_load(&t.ctx, 0, *query, None).await.unwrap();
+            assert_eq!(chats.len(), 3);
+            assert_eq!(chats.get_chat_id(0), chat_id2);
+            assert_eq!(chats.get_chat_id(1), chat_id1);
+            assert_eq!(chats.get_chat_id(2), chat_id3);
+        }
+
+        chat_id2.unpin(&t.ctx).await.unwrap();
+        chat_id3.unpin(&t.ctx).await.unwrap();
+        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        assert_eq!(chats.get_chat_id(0), chat_id1);
+        assert_eq!(chats.get_chat_id(1), chat_id3);
+        assert_eq!(chats.get_chat_id(2), chat_id2);
+        assert_eq!(
+            Chat::load_from_db(&t.ctx, chat_id3)
+                .await
+                .unwrap()
+                .get_visibility(),
+            ChatVisibility::Normal
+        );
+    }
+
     #[async_std::test]
     async fn test_sort_self_talk_up_on_forward() {
         let t = dummy_context().await;
@@ -543,4 +917,74 @@ mod tests {
         let summary = chats.get_summary(&t.ctx, 0, None).await;
         assert_eq!(summary.get_text2().unwrap(), "foo: bar test"); // the linebreak should be removed from summary
     }
+
+    async fn cached_summaries(context: &Context) -> i32 {
+        context
+            .sql
+            .query_get_value(context, "SELECT COUNT(*) FROM chat_summaries;", paramsv![])
+            .await
+            .unwrap_or_default()
+    }
+
+    #[async_std::test]
+    async fn test_summary_cache() {
+        let t = dummy_context().await;
+        let chat_id = create_group_chat(&t.ctx, VerifiedStatus::Unverified, "a chat")
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("foo".to_string()));
+        chat_id.set_draft(&t.ctx, Some(&mut msg)).await;
+
+        // the summaries are built along with the list
+        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        assert_eq!(cached_summaries(&t.ctx).await, 1);
+        let summary = chats.get_summary(&t.ctx, 0, None).await;
+        assert_eq!(summary.get_text1(), Some("Draft"));
+        assert_eq!(summary.get_text2(), Some("foo"));
+
+        // changing the message drops the summary
+        let msg_id = chats.get_msg_id(0).unwrap();
+        t.ctx
+            .sql
+            .execute("UPDATE msgs SET txt='bar' WHERE id=?;", paramsv![msg_id])
+            .await
+            .unwrap();
+        assert_eq!(cached_summaries(&t.ctx).await, 0);
+        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        let summary = chats.get_summary(&t.ctx, 0, None).await;
+        assert_eq!(summary.get_text2(), Some("bar"));
+        assert_eq!(cached_summaries(&t.ctx).await, 1);
+
+        // so does a new message in the chat
+        let chat_id2 = create_group_chat(&t.ctx, VerifiedStatus::Unverified, "b chat")
+            .await
+            .unwrap();
+        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        assert_eq!(cached_summaries(&t.ctx).await, 2);
+        let index = chats.get_index_for_id(chat_id2).unwrap();
+        let summary = chats.get_summary(&t.ctx, index, None).await;
+        assert_eq!(summary.get_text2(), Some("No messages."));
+        add_info_msg(&t.ctx, chat_id2, "baz").await;
+        assert_eq!(cached_summaries(&t.ctx).await, 1);
+        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        assert_eq!(chats.get_chat_id(0), chat_id2);
+        let summary = chats.get_summary(&t.ctx, 0, None).await;
+        assert_eq!(summary.get_text2(), Some("baz"));
+
+        // and changing a stock string used in the summary
+        t.ctx
+            .set_stock_translation(StockMessage::Draft, "Entwurf".to_string())
+            .await
+            .unwrap();
+        assert_eq!(cached_summaries(&t.ctx).await, 0);
+        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        let index = chats.get_index_for_id(chat_id).unwrap();
+        let summary = chats.get_summary(&t.ctx, index, None).await;
+        assert_eq!(summary.get_text1(), Some("Entwurf"));
+
+        let rebuilt = rebuild_summaries(&t.ctx).await.unwrap();
+        assert_eq!(rebuilt, 2);
+        assert_eq!(cached_summaries(&t.ctx).await as usize, rebuilt);
+    }
 }
@@ -1,74 +1,153 @@
 //! # Key-value configuration management
 
-use strum::{EnumProperty, IntoEnumIterator};
-use strum_macros::{AsRefStr, Display, EnumIter, EnumProperty, EnumString};
+use async_std::prelude::*;
+use deltachat_derive::ConfigKey;
 
 use crate::blob::BlobObject;
 use crate::chat::ChatId;
 use crate::constants::DC_VERSION_STR;
 use crate::context::Context;
 use crate::dc_tools::*;
+use crate::error::format_err;
 use crate::events::Event;
+use crate::folder_strategy::FolderStrategy;
 use crate::message::MsgId;
 use crate::mimefactory::RECOMMENDED_FILE_SIZE;
 use crate::{scheduler::InterruptInfo, stock::StockMessage};
 
+/// Type of the value of a configuration key, see `Config::kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigKind {
+    String,
+
+    /// `0` or `1`.
+    Bool,
+
+    /// An integer, empty for automatic values as the port.
+    Int,
+
+    /// Path to a file, copied to the blob directory when set.
+    Path,
+}
+
 /// The available configuration keys.
-#[derive(
-    Debug, Clone, Copy, PartialEq, Eq, Display, EnumString, AsRefStr, EnumIter, EnumProperty,
-)]
-#[strum(serialize_all = "snake_case")]
+///
+/// Keys, defaults and the type of the values are set by `#[config(...)]`,
+/// see `deltachat_derive::ConfigKey`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ConfigKey)]
 pub enum Config {
     Addr,
     MailServer,
     MailUser,
     MailPw,
+    #[config(kind = "int")]
     MailPort,
+    #[config(kind = "int")]
     ImapCertificateChecks,
     SendServer,
     SendUser,
     SendPw,
+    #[config(kind = "int")]
     SendPort,
+    #[config(kind = "int")]
     SmtpCertificateChecks,
+    #[config(kind = "int")]
     ServerFlags,
 
-    #[strum(props(default = "INBOX"))]
+    #[config(default = "INBOX")]
     ImapFolder,
 
     Displayname,
     Selfstatus,
+    #[config(kind = "path")]
     Selfavatar,
 
-    #[strum(props(default = "0"))]
+    #[config(default = "0", kind = "bool")]
     BccSelf,
 
-    #[strum(props(default = "1"))]
+    #[config(default = "1", kind = "bool")]
     E2eeEnabled,
 
-    #[strum(props(default = "1"))]
+    #[config(default = "1", kind = "bool")]
     MdnsEnabled,
 
-    #[strum(props(default = "1"))]
+    #[config(default = "1", kind = "bool")]
     InboxWatch,
 
-    #[strum(props(default = "1"))]
+    #[config(default = "1", kind = "bool")]
     SentboxWatch,
 
-    #[strum(props(default = "1"))]
-    MvboxWatch,
+    /// How chat messages are sorted into the folder set by `MvboxFolder`,
+    /// one of the `constants::MvboxPolicy` values.
+    #[config(default = "2", kind = "int")] // also change MvboxPolicy.default() on changes
+    MvboxPolicy,
+
+    /// Name of the folder chat messages are moved to,
+    /// see `folder_strategy::FolderStrategy`.
+    MvboxFolder,
 
-    #[strum(props(default = "1"))]
-    MvboxMove,
+    /// Move the messages of archived chats to the archive folder
+    /// of the provider and back when the chats are unarchived.
+    #[config(default = "0", kind = "bool")]
+    ArchiveMove,
 
-    #[strum(props(default = "0"))] // also change ShowEmails.default() on changes
+    /// Scan the spam folder for messages of known contacts
+    /// and move them back to the inbox.
+    #[config(default = "0", kind = "bool")]
+    SpamWatch,
+
+    /// Fetch messages received since this time, see `Context::get_sync_since`.
+    ///
+    /// Unset by default, only new messages are fetched then.
+    SyncSince,
+
+    /// Additional folders scanned for messages, one folder per line,
+    /// see `Context::get_watch_folders`.
+    WatchFolders,
+
+    /// Time in seconds an IDLE session may stay without any response from the server.
+    /// Afterwards the folder is polled to check whether IDLE missed new messages.
+    #[config(default = "1380", kind = "int")]
+    IdleTimeout,
+
+    /// Interval in seconds folders are polled at
+    /// if the server does not support IDLE or IDLE does not work.
+    #[config(default = "60", kind = "int")]
+    PollInterval,
+
+    /// Move deleted messages to the trash folder of the provider
+    /// instead of deleting them from the server at once.
+    ///
+    /// If the provider has no trash folder, one is created.
+    #[config(default = "0", kind = "bool")]
+    DeleteToTrash,
+
+    #[config(default = "0", kind = "int")] // also change ShowEmails.default() on changes
     ShowEmails,
 
-    #[strum(props(default = "0"))] // also change MediaQuality.default() on changes
+    #[config(default = "0", kind = "int")] // also change MediaQuality.default() on changes
     MediaQuality,
 
-    #[strum(props(default = "0"))]
+    /// Bitmask of system message kinds shown in chats,
+    /// see `mimeparser::ShowSystemMessages`.
+    ///
+    /// System messages of other kinds are stored hidden when received;
+    /// changing the option does not affect messages already stored.
+    #[config(default = "31", kind = "int")]
+    // also change ShowSystemMessages.default() on changes
+    ShowSystemMessages,
+
+    #[config(default = "0", kind = "int")]
     KeyGenType,
 
+    /// When keys are gossiped in group messages, see `constants::GossipPolicy`.
+    #[config(default = "0", kind = "int")] // also change GossipPolicy.default() on changes
+    GossipPolicy,
+
+    /// Which peerstates received gossip may change, see `constants::GossipAccept`.
+    #[config(default = "0", kind = "int")] // also change GossipAccept.default() on changes
+    GossipAccept,
+
     /// Timer in seconds after which the message is deleted from the
     /// server.
     ///
@@ -77,7 +156,7 @@ pub enum Config {
     ///
     /// Value 1 is treated as "delete at once": messages are deleted
     /// immediately, without moving to DeltaChat folder.
-    #[strum(props(default = "0"))]
+    #[config(default = "0", kind = "int")]
     DeleteServerAfter,
 
     /// Timer in seconds after which the message is deleted from the
@@ -85,37 +164,141 @@ pub enum Config {
     ///
     /// Equals to 0 by default, which means the message is never
     /// deleted.
-    #[strum(props(default = "0"))]
+    #[config(default = "0", kind = "int")]
     DeleteDeviceAfter,
 
+    /// Download limit in bytes.
+    ///
+    /// Messages larger than this are only downloaded partially, showing a
+    /// placeholder, the full message can be downloaded on demand.
+    /// 0 means no limit.
+    #[config(default = "0", kind = "int")]
+    DownloadLimit,
+
+    /// Host of a SOCKS5 proxy, eg. `127.0.0.1` for a local Tor daemon.
+    ///
+    /// If set, IMAP and SMTP connections and HTTP requests are made through the proxy.
+    Socks5Host,
+
+    #[config(default = "1080", kind = "int")]
+    Socks5Port,
+
+    /// Optional username for the SOCKS5 proxy.
+    Socks5User,
+
+    /// Optional password for the SOCKS5 proxy.
+    Socks5Password,
+
+    /// Experimental: URL of a JMAP server, eg. `https://api.fastmail.com`.
+    ///
+    /// If set and the `jmap` feature is enabled, messages are fetched from
+    /// the inbox and sent using JMAP instead of IMAP and SMTP,
+    /// authenticating with the configured IMAP credentials.
+    JmapUrl,
+
+    /// Send typing notifications to end-to-end encrypted chats,
+    /// see `chat::send_typing`.
+    #[config(default = "0", kind = "bool")]
+    TypingIndicators,
+
+    /// Send changes of the seen, archived and muted state, deleted chats
+    /// and edited contacts to the other devices of the user, see the `sync` module.
+    #[config(default = "0", kind = "bool")]
+    SyncMsgs,
+
+    /// Look up keys of recipients without Autocrypt key in the
+    /// Web Key Directory of their domain, see the `wkd` module.
+    #[config(default = "0", kind = "bool")]
+    WkdLookup,
+
+    /// Look up keys of recipients without Autocrypt key on the keyserver,
+    /// see the `keyserver` module.
+    #[config(default = "0", kind = "bool")]
+    KeyserverLookup,
+
+    /// Keyserver used for key lookup and for publishing the own key.
+    #[config(default = "https://keys.openpgp.org")]
+    Keyserver,
+
+    /// Timestamp of the last upload of the own key to the keyserver.
+    #[config(kind = "int")]
+    KeyserverPublished,
+
+    /// Own S/MIME certificate in PEM format, followed by the intermediate
+    /// certificates of the chain, see the `smime` module.
+    SmimeCertificate,
+
+    /// Private key of [Config::SmimeCertificate] in PEM format.
+    SmimeKey,
+
+    /// Certificate authorities trusted for S/MIME in addition to the
+    /// system trust store, in PEM format.
+    SmimeCa,
+
+    /// Store message attachments encrypted with the database passphrase.
+    ///
+    /// Only effective if the database is encrypted, see
+    /// `Context::open_with_passphrase`.  Existing attachments are encrypted
+    /// using `Context::encrypt_blobs`.
+    #[config(default = "0", kind = "bool")]
+    EncryptBlobs,
+
+    /// Number of tries after which a failed job, eg. sending a message,
+    /// is given up.  0 uses the default of the job type.
+    #[config(default = "0", kind = "int")]
+    JobMaxTries,
+
+    /// Maximum delay in seconds between two tries of a failed job.
+    /// 0 uses the default of the job type.
+    #[config(default = "0", kind = "int")]
+    JobMaxBackoff,
+
+    #[config(kind = "bool")]
     SaveMimeHeaders,
+
+    /// Save the sanitized HTML body of incoming messages, see `MsgId::get_html`.
+    #[config(default = "0", kind = "bool")]
+    SaveHtml,
     ConfiguredAddr,
     ConfiguredMailServer,
     ConfiguredMailUser,
     ConfiguredMailPw,
+    #[config(kind = "int")]
     ConfiguredMailPort,
+    #[config(kind = "int")]
     ConfiguredMailSecurity,
+    #[config(kind = "int")]
     ConfiguredImapCertificateChecks,
     ConfiguredSendServer,
     ConfiguredSendUser,
     ConfiguredSendPw,
+    #[config(kind = "int")]
     ConfiguredSendPort,
+    #[config(kind = "int")]
     ConfiguredSmtpCertificateChecks,
+    #[config(kind = "int")]
     ConfiguredServerFlags,
+    #[config(kind = "int")]
     ConfiguredSendSecurity,
+    #[config(kind = "bool")]
     ConfiguredE2EEEnabled,
+    #[config(default = "INBOX")]
     ConfiguredInboxFolder,
     ConfiguredMvboxFolder,
     ConfiguredSentboxFolder,
+    ConfiguredArchiveFolder,
+    ConfiguredTrashFolder,
+    ConfiguredSpamFolder,
+    #[config(kind = "bool")]
     Configured,
 
-    #[strum(serialize = "sys.version")]
+    #[config(key = "sys.version")]
     SysVersion,
 
-    #[strum(serialize = "sys.msgsize_max_recommended")]
+    #[config(key = "sys.msgsize_max_recommended")]
     SysMsgsizeMaxRecommended,
 
-    #[strum(serialize = "sys.config_keys")]
+    #[config(key = "sys.config_keys")]
     SysConfigKeys,
 }
 
@@ -140,8 +323,7 @@ impl Context {
         // Default values
         match key {
             Config::Selfstatus => Some(self.stock_str(StockMessage::StatusLine).await.into_owned()),
-            Config::ConfiguredInboxFolder => Some("INBOX".to_owned()),
-            _ => key.get_str("default").map(|s| s.to_string()),
+            _ => key.default_value().map(|s| s.to_string()),
         }
     }
 
@@ -179,9 +361,92 @@ impl Context {
         }
     }
 
+    /// Gets the additional folders to scan for messages.
+    ///
+    /// Folders that are watched anyway, as INBOX, are skipped.
+    pub async fn get_watch_folders(&self) -> Vec<String> {
+        let value = match self.get_config(Config::WatchFolders).await {
+            Some(value) => value,
+            None => return Vec::new(),
+        };
+        let mut watched = vec!["INBOX".to_string()];
+        for key in &[
+            Config::ConfiguredInboxFolder,
+            Config::ConfiguredMvboxFolder,
+            Config::ConfiguredSentboxFolder,
+        ] {
+            if let Some(folder) = self.get_config(*key).await {
+                watched.push(folder);
+            }
+        }
+
+        let mut folders: Vec<String> = Vec::new();
+        for folder in value.lines().map(|folder| folder.trim()) {
+            if !folder.is_empty()
+                && !watched.iter().any(|watched| watched == folder)
+                && !folders.iter().any(|f| f == folder)
+            {
+                folders.push(folder.to_string());
+            }
+        }
+        folders
+    }
+
+    /// Gets the time since which messages are fetched from the server.
+    ///
+    /// `Config::SyncSince` is either a unix timestamp
+    /// or a number of days followed by `d`, eg. `30d` for the last 30 days.
+    /// `None` means that only new messages are fetched.
+    pub async fn get_sync_since(&self) -> Option<i64> {
+        let value = self.get_config(Config::SyncSince).await?;
+        let value = value.trim();
+        if value.ends_with('d') {
+            let days: i64 = value[..value.len() - 1].trim().parse().ok()?;
+            Some(time() - days.max(0) * 24 * 60 * 60)
+        } else {
+            value.parse().ok().filter(|timestamp| *timestamp > 0)
+        }
+    }
+
+    /// Extends the time window in which messages are fetched
+    /// to messages received since `timestamp`.
+    ///
+    /// Messages received before the current window are fetched
+    /// on the next fetch of each folder; a later `timestamp` does nothing.
+    pub async fn extend_sync_window(&self, timestamp: i64) -> crate::sql::Result<()> {
+        if let Some(since) = self.get_sync_since().await {
+            if since <= timestamp {
+                return Ok(());
+            }
+        }
+        self.set_config(Config::SyncSince, Some(&timestamp.to_string()))
+            .await
+    }
+
+    /// Gets the folder messages normally arrive in,
+    /// the `DeltaChat` folder if messages are moved there, `INBOX` otherwise.
+    pub(crate) async fn get_home_folder(&self) -> String {
+        if FolderStrategy::load(self).await.moves_msgs() {
+            if let Some(folder) = self.get_config(Config::ConfiguredMvboxFolder).await {
+                return folder;
+            }
+        }
+        self.get_config(Config::ConfiguredInboxFolder)
+            .await
+            .unwrap_or_else(|| "INBOX".to_string())
+    }
+
     /// Set the given config key.
     /// If `None` is passed as a value the value is cleared and set to the default if there is one.
+    ///
+    /// Values that do not match the type of the key, see `Config::kind`, are refused.
     pub async fn set_config(&self, key: Config, value: Option<&str>) -> crate::sql::Result<()> {
+        if let Some(value) = value {
+            if !key.is_valid_value(value) {
+                return Err(format_err!("Invalid value {:?} for {}", value, key).into());
+            }
+        }
+
         match key {
             Config::Selfavatar => {
                 self.sql
@@ -212,9 +477,33 @@ impl Context {
                     .await;
                 ret
             }
-            Config::MvboxWatch => {
+            Config::MvboxPolicy | Config::MvboxFolder => {
+                self.sql.set_raw_config(self, key, value).await?;
+                // the inbox loop looks up or creates the folder again
+                self.sql
+                    .set_raw_config(self, Config::ConfiguredMvboxFolder, None)
+                    .await?;
+                self.sql
+                    .set_raw_config_int(self, "folders_configured", 0)
+                    .await?;
+                self.interrupt_inbox(InterruptInfo::new(false, None))
+                    .join(self.interrupt_mvbox(InterruptInfo::new(false, None)))
+                    .await;
+                Ok(())
+            }
+            Config::WatchFolders | Config::SpamWatch => {
+                let ret = self.sql.set_raw_config(self, key, value).await;
+                self.interrupt_watch_folders(InterruptInfo::new(false, None))
+                    .await;
+                ret
+            }
+            Config::SyncSince => {
                 let ret = self.sql.set_raw_config(self, key, value).await;
-                self.interrupt_mvbox(InterruptInfo::new(false, None)).await;
+                self.interrupt_inbox(InterruptInfo::new(false, None))
+                    .join(self.interrupt_mvbox(InterruptInfo::new(false, None)))
+                    .join(self.interrupt_sentbox(InterruptInfo::new(false, None)))
+                    .join(self.interrupt_watch_folders(InterruptInfo::new(false, None)))
+                    .await;
                 ret
             }
             Config::Selfstatus => {
@@ -281,7 +570,67 @@ mod tests {
 
     #[test]
     fn test_default_prop() {
-        assert_eq!(Config::ImapFolder.get_str("default"), Some("INBOX"));
+        assert_eq!(Config::ImapFolder.default_value(), Some("INBOX"));
+        assert_eq!(Config::ConfiguredInboxFolder.default_value(), Some("INBOX"));
+        assert_eq!(Config::MailServer.default_value(), None);
+    }
+
+    #[test]
+    fn test_config_keys() {
+        // keys stored by earlier versions
+        assert_eq!(Config::Socks5Host.as_str(), "socks5_host");
+        assert_eq!(
+            Config::ConfiguredE2EEEnabled.as_str(),
+            "configured_e2ee_enabled"
+        );
+        assert_eq!(
+            Config::ImapCertificateChecks.as_str(),
+            "imap_certificate_checks"
+        );
+        assert_eq!(Config::MailPw.as_ref(), "mail_pw");
+        assert_eq!(Config::SysVersion.as_str(), "sys.version");
+        assert!(Config::from_str("unknown_key").is_err());
+
+        for key in Config::iter() {
+            assert_eq!(Config::from_str(key.as_str()), Ok(key));
+        }
+    }
+
+    #[async_std::test]
+    async fn test_set_config_validation() {
+        let t = dummy_context().await;
+        assert_eq!(Config::MdnsEnabled.kind(), ConfigKind::Bool);
+        assert!(t
+            .ctx
+            .set_config(Config::MdnsEnabled, Some("yes"))
+            .await
+            .is_err());
+        assert!(t.ctx.get_config_bool(Config::MdnsEnabled).await);
+        t.ctx
+            .set_config(Config::MdnsEnabled, Some("0"))
+            .await
+            .unwrap();
+        assert!(!t.ctx.get_config_bool(Config::MdnsEnabled).await);
+
+        assert_eq!(Config::MailPort.kind(), ConfigKind::Int);
+        assert!(t
+            .ctx
+            .set_config(Config::MailPort, Some("imap"))
+            .await
+            .is_err());
+        t.ctx.set_config(Config::MailPort, Some("")).await.unwrap();
+        t.ctx
+            .set_config(Config::MailPort, Some("993"))
+            .await
+            .unwrap();
+        assert_eq!(t.ctx.get_config_int(Config::MailPort).await, 993);
+
+        assert_eq!(Config::Selfavatar.kind(), ConfigKind::Path);
+        assert!(t
+            .ctx
+            .set_config(Config::Selfavatar, Some(""))
+            .await
+            .is_err());
     }
 
     #[async_std::test]
@@ -382,4 +731,55 @@ mod tests {
         let media_quality = constants::MediaQuality::from_i32(media_quality).unwrap_or_default();
         assert_eq!(media_quality, constants::MediaQuality::Worse);
     }
+
+    #[async_std::test]
+    async fn test_get_watch_folders() {
+        let t = dummy_context().await;
+        assert!(t.ctx.get_watch_folders().await.is_empty());
+
+        t.ctx
+            .set_config(Config::ConfiguredMvboxFolder, Some("DeltaChat"))
+            .await
+            .unwrap();
+        t.ctx
+            .set_config(
+                Config::WatchFolders,
+                Some("Lists\n\n INBOX\nDeltaChat\nWork/Chats \nLists\n"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            t.ctx.get_watch_folders().await,
+            vec!["Lists".to_string(), "Work/Chats".to_string()]
+        );
+    }
+
+    #[async_std::test]
+    async fn test_sync_since() {
+        let t = dummy_context().await;
+        assert_eq!(t.ctx.get_sync_since().await, None);
+
+        t.ctx
+            .set_config(Config::SyncSince, Some("30d"))
+            .await
+            .unwrap();
+        let since = t.ctx.get_sync_since().await.unwrap();
+        assert!((since - (time() - 30 * 24 * 60 * 60)).abs() < 10);
+
+        // a later time does not narrow the window
+        t.ctx.extend_sync_window(time()).await.unwrap();
+        assert_eq!(
+            t.ctx.get_config(Config::SyncSince).await,
+            Some("30d".to_string())
+        );
+
+        t.ctx.extend_sync_window(1_500_000_000).await.unwrap();
+        assert_eq!(t.ctx.get_sync_since().await, Some(1_500_000_000));
+
+        t.ctx
+            .set_config(Config::SyncSince, Some("garbage"))
+            .await
+            .unwrap();
+        assert_eq!(t.ctx.get_sync_since().await, None);
+    }
 }
@@ -12,12 +12,13 @@ use crate::config::Config;
 use crate::constants::*;
 use crate::context::Context;
 use crate::dc_tools::*;
+use crate::events::Event;
 use crate::imap::Imap;
 use crate::login_param::{CertificateChecks, LoginParam};
 use crate::message::Message;
 use crate::oauth2::*;
 use crate::smtp::Smtp;
-use crate::{chat, e2ee, provider};
+use crate::{chat, e2ee, provider, qr};
 
 use auto_mozilla::moz_autoconfigure;
 use auto_outlook::outlk_autodiscover;
@@ -32,6 +33,38 @@ macro_rules! progress {
     };
 }
 
+/// Emits `Event::ConfigureStage`.
+fn stage(context: &Context, stage: ConfigureStage, comment: impl Into<String>) {
+    let comment = comment.into();
+    info!(context, "Configure stage {}: {}", stage, comment);
+    context.emit_event(Event::ConfigureStage { stage, comment });
+}
+
+/// Describes the server settings tried, eg. `user@imap.example.org:993 SSL`.
+fn describe_server(user: &str, server: &str, port: i32, socket: &str) -> String {
+    format!("{}@{}:{} {}", user, server, port, socket)
+}
+
+fn imap_socket_name(server_flags: i32) -> &'static str {
+    if server_flags & DC_LP_IMAP_SOCKET_STARTTLS != 0 {
+        "STARTTLS"
+    } else if server_flags & DC_LP_IMAP_SOCKET_PLAIN != 0 {
+        "plain"
+    } else {
+        "SSL"
+    }
+}
+
+fn smtp_socket_name(server_flags: i32) -> &'static str {
+    if server_flags & DC_LP_SMTP_SOCKET_STARTTLS as i32 != 0 {
+        "STARTTLS"
+    } else if server_flags & DC_LP_SMTP_SOCKET_PLAIN as i32 != 0 {
+        "plain"
+    } else {
+        "SSL"
+    }
+}
+
 impl Context {
     /// Checks if the context is already configured.
     pub async fn is_configured(&self) -> bool {
@@ -39,30 +72,48 @@ impl Context {
     }
 
     /// Configures this account with the currently set parameters.
-    pub async fn configure(&self) -> Result<()> {
+    pub async fn configure(&self) -> std::result::Result<(), Error> {
         use futures::future::FutureExt;
 
-        ensure!(
-            !self.scheduler.read().await.is_running(),
-            "cannot configure, already running"
-        );
-        ensure!(
-            self.sql.is_open().await,
-            "cannot configure, database not opened."
-        );
+        if self.scheduler.read().await.is_running() {
+            return Err(Error::AlreadyRunning);
+        }
+        if !self.sql.is_open().await {
+            return Err(format_err!("cannot configure, database not opened.").into());
+        }
         let cancel_channel = self.alloc_ongoing().await?;
 
         let res = self
             .inner_configure()
             .race(cancel_channel.recv().map(|_| {
+                info!(self, "Configure canceled");
                 progress!(self, 0);
-                Ok(())
+                Err(format_err!("Configure canceled"))
             }))
             .await;
 
         self.free_ongoing().await;
 
-        res
+        Ok(res?)
+    }
+
+    /// Configures an account from a `DCACCOUNT:` or `dclogin:` QR code.
+    ///
+    /// The credentials are taken from the QR code or requested from the
+    /// account creation endpoint it contains, see
+    /// [crate::qr::set_config_from_qr], and used for [Context::configure] then.  Progress is reported by
+    /// `Event::ConfigureProgress` for both steps.
+    pub async fn configure_from_qr(&self, qr: &str) -> std::result::Result<(), Error> {
+        if self.scheduler.read().await.is_running() {
+            return Err(Error::AlreadyRunning);
+        }
+        progress!(self, 1);
+        if let Err(err) = qr::set_config_from_qr(self, qr).await {
+            error!(self, "Configure Failed: {}", err);
+            progress!(self, 0);
+            return Err(err.into());
+        }
+        self.configure().await
     }
 
     async fn inner_configure(&self) -> Result<()> {
@@ -70,9 +121,15 @@ impl Context {
 
         let was_configured_before = self.is_configured().await;
         let mut param = LoginParam::from_database(self, "").await;
-        let success = configure(self, &mut param).await;
+        let mut success = configure(self, &mut param).await;
 
         if let Some(provider) = provider::get_provider_info(&param.addr) {
+            // the login hint often explains why logging in failed, eg. a missing app password
+            if provider.status != provider::Status::OK && !provider.before_login_hint.is_empty() {
+                success =
+                    success.map_err(|err| format_err!("{}\n\n{}", err, provider.before_login_hint));
+            }
+
             if !was_configured_before {
                 if let Some(config_defaults) = &provider.config_defaults {
                     for def in config_defaults.iter() {
@@ -155,6 +212,7 @@ async fn configure(ctx: &Context, param: &mut LoginParam) -> Result<()> {
     {
         // no advanced parameters entered by the user: query provider-database or do Autoconfig
         keep_flags = param.server_flags & DC_LP_AUTH_OAUTH2;
+        stage(ctx, ConfigureStage::Autoconfig, "provider database");
         if let Some(new_param) = get_offline_autoconfig(ctx, &param) {
             // got parameters from our provider-database, skip Autoconfig, preserve the OAuth2 setting
             param_autoconfig = Some(new_param);
@@ -269,10 +327,8 @@ async fn configure(ctx: &Context, param: &mut LoginParam) -> Result<()> {
     try_smtp_connections(ctx, param, param_autoconfig.is_some()).await?;
     progress!(ctx, 900);
 
-    let create_mvbox = ctx.get_config_bool(Config::MvboxWatch).await
-        || ctx.get_config_bool(Config::MvboxMove).await;
-
-    imap.configure_folders(ctx, create_mvbox).await?;
+    stage(ctx, ConfigureStage::LoginCheck, param.mail_server.as_str());
+    imap.configure_folders(ctx).await?;
 
     imap.select_with_uidvalidity(ctx, "INBOX")
         .await
@@ -371,6 +427,7 @@ async fn get_autoconfig(
 
     let mut progress = 300;
     for source in &sources {
+        stage(ctx, ConfigureStage::Autoconfig, source.url.as_str());
         let res = source.fetch(ctx, param).await;
         progress!(ctx, progress);
         progress += 10;
@@ -521,6 +578,16 @@ async fn try_imap_one_param(context: &Context, param: &LoginParam, imap: &mut Im
         param.imap_certificate_checks
     );
     info!(context, "Trying: {}", inf);
+    stage(
+        context,
+        ConfigureStage::ImapProbe,
+        describe_server(
+            &param.mail_user,
+            &param.mail_server,
+            param.mail_port,
+            imap_socket_name(param.server_flags),
+        ),
+    );
 
     if imap.connect(context, &param).await {
         info!(context, "success: {}", inf);
@@ -580,6 +647,16 @@ async fn try_smtp_one_param(context: &Context, param: &LoginParam, smtp: &mut Sm
         param.send_user, param.send_server, param.send_port, param.server_flags
     );
     info!(context, "Trying: {}", inf);
+    stage(
+        context,
+        ConfigureStage::SmtpProbe,
+        describe_server(
+            &param.send_user,
+            &param.send_server,
+            param.send_port,
+            smtp_socket_name(param.server_flags),
+        ),
+    );
 
     if let Err(err) = smtp.connect(context, &param).await {
         bail!("could not connect: {}", err);
@@ -610,6 +687,23 @@ pub enum Error {
 
     #[error("Number of redirection is exceeded")]
     RedirectionError,
+
+    #[error("Cannot configure, already running")]
+    AlreadyRunning,
+
+    /// Configuring failed for another reason, eg. the server refused the login.
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        // keep the variant of errors passed through as `anyhow::Error`
+        match err.downcast::<Error>() {
+            Ok(err) => err,
+            Err(err) => Error::Other(err),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -633,6 +727,28 @@ mod tests {
         assert!(t.ctx.configure().await.is_err());
     }
 
+    #[test]
+    fn test_describe_server() {
+        assert_eq!(
+            describe_server(
+                "alice",
+                "imap.example.org",
+                993,
+                imap_socket_name(DC_LP_IMAP_SOCKET_SSL)
+            ),
+            "alice@imap.example.org:993 SSL"
+        );
+        assert_eq!(
+            imap_socket_name(DC_LP_IMAP_SOCKET_STARTTLS | DC_LP_SMTP_SOCKET_PLAIN as i32),
+            "STARTTLS"
+        );
+        assert_eq!(
+            smtp_socket_name(DC_LP_IMAP_SOCKET_STARTTLS | DC_LP_SMTP_SOCKET_PLAIN as i32),
+            "plain"
+        );
+        assert_eq!(smtp_socket_name(0), "SSL");
+    }
+
     #[async_std::test]
     async fn test_get_offline_autoconfig() {
         let context = dummy_context().await.ctx;
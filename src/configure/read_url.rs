@@ -1,15 +1,25 @@
+use http_types::{Method, Request, Url};
+
 use crate::context::Context;
+use crate::socks::send_http;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("URL request error")]
-    GetError(surf::Error),
+    GetError(http_types::Error),
 }
 
 pub async fn read_url(context: &Context, url: &str) -> Result<String, Error> {
     info!(context, "Requesting URL {}", url);
 
-    match surf::get(url).recv_string().await {
+    let request = async {
+        let url = Url::parse(url)?;
+        send_http(context, Request::new(Method::Get, url))
+            .await?
+            .body_string()
+            .await
+    };
+    match request.await {
         Ok(res) => Ok(res),
         Err(err) => {
             info!(context, "Can\'t read URL {}", url);
@@ -0,0 +1,204 @@
+//! # Connectivity
+//!
+//! State of the connections to the server, reported by the IO loops,
+//! so that UIs can show eg. "Updating…" or "Connected" based on real data.
+//! Changes are announced by [Event::ConnectivityChanged].
+
+use std::collections::BTreeMap;
+
+use async_std::sync::RwLock;
+use serde::Serialize;
+
+use crate::context::Context;
+use crate::dc_tools::time;
+use crate::events::Event;
+
+/// Connection the state is reported for.
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum ConnectionKind {
+    Inbox,
+    Mvbox,
+    Sentbox,
+    Smtp,
+}
+
+const CONNECTION_KINDS: [ConnectionKind; 4] = [
+    ConnectionKind::Inbox,
+    ConnectionKind::Mvbox,
+    ConnectionKind::Sentbox,
+    ConnectionKind::Smtp,
+];
+
+/// State of a connection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state")]
+pub enum ConnectivityState {
+    /// IO is not running or the folder is not watched.
+    NotConnected,
+
+    Connecting,
+
+    /// Connected and busy, eg. fetching or sending messages.
+    Working,
+
+    /// Connected and waiting for new messages.
+    ///
+    /// SMTP connects on demand, it is reported as connected
+    /// as long as the last message was sent successfully.
+    Connected,
+
+    /// The last try failed, it is retried at `next_retry` if known.
+    Interrupted {
+        error: String,
+        next_retry: Option<i64>,
+    },
+}
+
+impl ConnectivityState {
+    /// Returns the state as one of the `DC_CONNECTIVITY_*` constants.
+    pub fn to_int(&self) -> i32 {
+        match self {
+            ConnectivityState::NotConnected | ConnectivityState::Interrupted { .. } => 1000,
+            ConnectivityState::Connecting => 2000,
+            ConnectivityState::Working => 3000,
+            ConnectivityState::Connected => 4000,
+        }
+    }
+}
+
+/// State of one connection, see [Context::get_connectivity].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Connectivity {
+    pub connection: ConnectionKind,
+
+    #[serde(flatten)]
+    pub state: ConnectivityState,
+
+    /// Time the state was entered, 0 if the connection was never used.
+    pub since: i64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ConnectivityStore(RwLock<BTreeMap<ConnectionKind, Connectivity>>);
+
+impl Context {
+    /// Sets the state of a connection, emitting [Event::ConnectivityChanged] on changes.
+    pub(crate) async fn set_connectivity(
+        &self,
+        connection: ConnectionKind,
+        state: ConnectivityState,
+    ) {
+        {
+            let mut store = self.connectivity.0.write().await;
+            if let Some(connectivity) = store.get(&connection) {
+                if connectivity.state == state {
+                    return;
+                }
+            }
+            store.insert(
+                connection,
+                Connectivity {
+                    connection,
+                    state,
+                    since: time(),
+                },
+            );
+        }
+        self.emit_event(Event::ConnectivityChanged);
+    }
+
+    /// Resets all connections to [ConnectivityState::NotConnected] after IO is stopped.
+    pub(crate) async fn reset_connectivity(&self) {
+        let was_empty = {
+            let mut store = self.connectivity.0.write().await;
+            let was_empty = store.is_empty();
+            store.clear();
+            was_empty
+        };
+        if !was_empty {
+            self.emit_event(Event::ConnectivityChanged);
+        }
+    }
+
+    /// Returns the state of the IMAP and SMTP connections.
+    pub async fn get_connectivity(&self) -> Vec<Connectivity> {
+        let store = self.connectivity.0.read().await;
+        CONNECTION_KINDS
+            .iter()
+            .map(|connection| {
+                store.get(connection).cloned().unwrap_or(Connectivity {
+                    connection: *connection,
+                    state: ConnectivityState::NotConnected,
+                    since: 0,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the overall state as one of the `DC_CONNECTIVITY_*` constants.
+    ///
+    /// This is the state of the inbox,
+    /// or `DC_CONNECTIVITY_NOT_CONNECTED` if sending messages fails.
+    pub async fn get_connectivity_summary(&self) -> i32 {
+        let store = self.connectivity.0.read().await;
+        if let Some(Connectivity {
+            state: ConnectivityState::Interrupted { .. },
+            ..
+        }) = store.get(&ConnectionKind::Smtp)
+        {
+            return ConnectivityState::NotConnected.to_int();
+        }
+        store
+            .get(&ConnectionKind::Inbox)
+            .map(|connectivity| connectivity.state.to_int())
+            .unwrap_or_else(|| ConnectivityState::NotConnected.to_int())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[async_std::test]
+    async fn test_connectivity() {
+        let t = dummy_context().await;
+        let ctx = &t.ctx;
+        assert_eq!(ctx.get_connectivity_summary().await, 1000);
+        assert!(ctx
+            .get_connectivity()
+            .await
+            .iter()
+            .all(|c| c.state == ConnectivityState::NotConnected));
+
+        ctx.set_connectivity(ConnectionKind::Inbox, ConnectivityState::Connecting)
+            .await;
+        assert_eq!(ctx.get_connectivity_summary().await, 2000);
+        ctx.set_connectivity(ConnectionKind::Inbox, ConnectivityState::Connected)
+            .await;
+        assert_eq!(ctx.get_connectivity_summary().await, 4000);
+
+        ctx.set_connectivity(
+            ConnectionKind::Smtp,
+            ConnectivityState::Interrupted {
+                error: "connection refused".to_string(),
+                next_retry: Some(1000),
+            },
+        )
+        .await;
+        assert_eq!(ctx.get_connectivity_summary().await, 1000);
+        let connectivity = ctx.get_connectivity().await;
+        assert_eq!(connectivity.len(), 4);
+        assert_eq!(connectivity[0].connection, ConnectionKind::Inbox);
+        assert_eq!(connectivity[0].state, ConnectivityState::Connected);
+        assert!(connectivity[0].since > 0);
+        let json = serde_json::to_value(&connectivity[3]).unwrap();
+        assert_eq!(json["connection"], "Smtp");
+        assert_eq!(json["state"], "Interrupted");
+        assert_eq!(json["error"], "connection refused");
+        assert_eq!(json["next_retry"], 1000);
+
+        ctx.reset_connectivity().await;
+        assert_eq!(ctx.get_connectivity_summary().await, 1000);
+    }
+}
@@ -57,11 +57,30 @@ impl Default for ShowEmails {
     }
 }
 
+/// Whether read receipts are sent and requested in a chat.
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql)]
+#[repr(u8)]
+pub enum MdnPolicy {
+    /// Follow the global `Config::MdnsEnabled` option.
+    Global = 0,
+    Always = 1,
+    Never = 2,
+}
+
+impl Default for MdnPolicy {
+    fn default() -> Self {
+        MdnPolicy::Global
+    }
+}
+
 #[derive(Debug, Display, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql)]
 #[repr(u8)]
 pub enum MediaQuality {
     Balanced = 0,
     Worse = 1,
+
+    /// Images are sent as they are.
+    Original = 2,
 }
 
 impl Default for MediaQuality {
@@ -70,6 +89,30 @@ impl Default for MediaQuality {
     }
 }
 
+/// How chat messages are sorted into the folder set by `Config::MvboxFolder`,
+/// see `folder_strategy::FolderStrategy`.
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql)]
+#[repr(u8)]
+pub enum MvboxPolicy {
+    /// The folder is neither created nor watched, chat messages stay in the inbox.
+    Off = 0,
+
+    /// The folder is created and watched, but no messages are moved there.
+    Watch = 1,
+
+    /// Chat messages are moved to the folder, classic emails stay in the inbox.
+    MoveChats = 2,
+
+    /// Chat messages and classic emails are moved to the folder.
+    MoveAll = 3,
+}
+
+impl Default for MvboxPolicy {
+    fn default() -> Self {
+        MvboxPolicy::MoveChats // also change Config.MvboxPolicy props(default) on changes
+    }
+}
+
 #[derive(Debug, Display, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql)]
 #[repr(u8)]
 pub enum KeyGenType {
@@ -84,6 +127,62 @@ impl Default for KeyGenType {
     }
 }
 
+/// When keys of group members are sent in Autocrypt-Gossip headers.
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql)]
+#[repr(u8)]
+pub enum GossipPolicy {
+    /// Gossip in all encrypted messages to groups.
+    Always = 0,
+
+    /// Gossip only in verified groups.
+    VerifiedOnly = 1,
+
+    Never = 2,
+}
+
+impl Default for GossipPolicy {
+    fn default() -> Self {
+        GossipPolicy::Always // also change Config.GossipPolicy props(default) on changes
+    }
+}
+
+/// Which peerstates received Autocrypt-Gossip headers may change.
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql)]
+#[repr(u8)]
+pub enum GossipAccept {
+    Always = 0,
+
+    /// Gossip is only used for peers without a known key.
+    UnknownPeers = 1,
+
+    /// Gossip is ignored.  Members introduced to verified groups
+    /// cannot be verified then.
+    Never = 2,
+}
+
+impl Default for GossipAccept {
+    fn default() -> Self {
+        GossipAccept::Always // also change Config.GossipAccept props(default) on changes
+    }
+}
+
+/// Stage of the configuration, see `Event::ConfigureStage`.
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, Serialize)]
+#[repr(i32)]
+pub enum ConfigureStage {
+    /// The server settings are looked up in the provider database or by autoconfig.
+    Autoconfig = 100,
+
+    /// Logging in to an IMAP server is tried.
+    ImapProbe = 200,
+
+    /// Logging in to an SMTP server is tried.
+    SmtpProbe = 300,
+
+    /// The IMAP login is checked by setting up the folders and selecting INBOX.
+    LoginCheck = 400,
+}
+
 pub const DC_HANDSHAKE_CONTINUE_NORMAL_PROCESSING: i32 = 0x01;
 pub const DC_HANDSHAKE_STOP_NORMAL_PROCESSING: i32 = 0x02;
 pub const DC_HANDSHAKE_ADD_DELETE_JOB: i32 = 0x04;
@@ -100,6 +199,12 @@ pub const DC_GCM_ADDDAYMARKER: u32 = 0x01;
 pub const DC_GCL_VERIFIED_ONLY: usize = 0x01;
 pub const DC_GCL_ADD_SELF: usize = 0x02;
 
+// flags for dc_run_housekeeping(), see `context::HousekeepingOptions`
+pub const DC_HOUSEKEEPING_BLOBS: i32 = 0x01;
+pub const DC_HOUSEKEEPING_MDNS: i32 = 0x02;
+pub const DC_HOUSEKEEPING_TOMBSTONES: i32 = 0x04;
+pub const DC_HOUSEKEEPING_VACUUM: i32 = 0x08;
+
 // unchanged user avatars are resent to the recipients every some days
 pub const DC_RESEND_USER_AVATAR_DAYS: i64 = 14;
 
@@ -139,6 +244,8 @@ pub enum Chattype {
     Single = 100,
     Group = 120,
     VerifiedGroup = 130,
+    Mailinglist = 140,
+    Broadcast = 160,
 }
 
 impl Default for Chattype {
@@ -231,8 +338,12 @@ pub const AVATAR_SIZE: u32 = 192;
 pub const BALANCED_IMAGE_SIZE: u32 = 1280;
 pub const WORSE_IMAGE_SIZE: u32 = 640;
 
+// JPEG quality of recoded images
+pub const BALANCED_IMAGE_QUALITY: u8 = 85;
+pub const WORSE_IMAGE_QUALITY: u8 = 60;
+
 // this value can be increased if the folder configuration is changed and must be redone on next program start
-pub const DC_FOLDERS_CONFIGURED_VERSION: i32 = 3;
+pub const DC_FOLDERS_CONFIGURED_VERSION: i32 = 6;
 
 #[derive(
     Debug,
@@ -296,6 +407,22 @@ pub enum Viewtype {
     /// The file is set via dc_msg_set_file()
     /// and retrieved via dc_msg_get_file().
     File = 60,
+
+    /// Message containing an in-chat app,
+    /// a zip archive of HTML, JavaScript and other resources
+    /// with `index.html` as entry point.
+    /// The archive is set via dc_msg_set_file()
+    /// and retrieved via dc_msg_get_file().
+    App = 80,
+
+    /// Message containing a poll,
+    /// the question is the text of the message.
+    Poll = 90,
+
+    /// Message containing a calendar event, typically an invitation,
+    /// the title of the event is the text of the message,
+    /// the `.ics` file is retrieved via dc_msg_get_file().
+    Calendar = 100,
 }
 
 impl Default for Viewtype {
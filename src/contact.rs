@@ -7,6 +7,7 @@ use lazy_static::lazy_static;
 use regex::Regex;
 
 use crate::aheader::EncryptPreference;
+use crate::blob::BlobObject;
 use crate::chat::ChatId;
 use crate::config::Config;
 use crate::constants::*;
@@ -21,6 +22,7 @@ use crate::mimeparser::AvatarAction;
 use crate::param::*;
 use crate::peerstate::*;
 use crate::stock::StockMessage;
+use crate::sync;
 
 /// An object representing a single contact in memory.
 ///
@@ -66,6 +68,12 @@ pub struct Contact {
 
     /// Parameters as Param::ProfileImage
     pub param: Params,
+
+    /// Timestamp of the last message received from the contact, 0 if unknown.
+    last_seen: i64,
+
+    /// Status of the contact, taken from the footer of the last message received.
+    status: String,
 }
 
 /// Possible origins of a contact.
@@ -164,7 +172,8 @@ impl Contact {
         let mut res = context
             .sql
             .query_row(
-                "SELECT c.name, c.addr, c.origin, c.blocked, c.authname, c.param
+                "SELECT c.name, c.addr, c.origin, c.blocked, c.authname, c.param, c.last_seen,
+                    c.status
                FROM contacts c
               WHERE c.id=?;",
                 paramsv![contact_id as i32],
@@ -177,6 +186,8 @@ impl Contact {
                         blocked: row.get::<_, Option<i32>>(3)?.unwrap_or_default() != 0,
                         origin: row.get(2)?,
                         param: row.get::<_, String>(5)?.parse().unwrap_or_default(),
+                        last_seen: row.get::<_, Option<i64>>(6)?.unwrap_or_default(),
+                        status: row.get::<_, Option<String>>(7)?.unwrap_or_default(),
                     };
                     Ok(contact)
                 },
@@ -188,6 +199,10 @@ impl Contact {
                 .get_config(Config::ConfiguredAddr)
                 .await
                 .unwrap_or_default();
+            res.status = context
+                .get_config(Config::Selfstatus)
+                .await
+                .unwrap_or_default();
         } else if contact_id == DC_CONTACT_ID_DEVICE {
             res.name = context
                 .stock_str(StockMessage::DeviceMessages)
@@ -213,12 +228,12 @@ impl Contact {
 
     /// Block the given contact.
     pub async fn block(context: &Context, id: u32) {
-        set_block_contact(context, id, true).await;
+        set_block_contact(context, id, true, true).await;
     }
 
     /// Unblock the given contact.
     pub async fn unblock(context: &Context, id: u32) {
-        set_block_contact(context, id, false).await;
+        set_block_contact(context, id, false, true).await;
     }
 
     /// Add a single contact as a result of an _explicit_ user action.
@@ -244,6 +259,16 @@ impl Contact {
 
         let (contact_id, sth_modified) =
             Contact::add_or_lookup(context, name, addr, Origin::ManuallyCreated).await?;
+        if contact_id > DC_CONTACT_ID_LAST_SPECIAL {
+            context
+                .sql
+                .execute(
+                    "UPDATE contacts SET name_timestamp=? WHERE id=?;",
+                    paramsv![time(), contact_id as i32],
+                )
+                .await?;
+            sync::sync_contact(context, contact_id).await;
+        }
         let blocked = Contact::is_blocked_load(context, contact_id).await;
         context.emit_event(Event::ContactsChanged(
             if sth_modified == Modifier::Created {
@@ -823,6 +848,74 @@ impl Contact {
         Ok(())
     }
 
+    /// Returns the timestamp of the last message received from the contact.
+    ///
+    /// 0 if no message was received from the contact yet.
+    pub fn last_seen(&self) -> i64 {
+        self.last_seen
+    }
+
+    /// Updates the last-seen timestamp of the contact,
+    /// older timestamps than the stored one are ignored.
+    pub(crate) async fn update_last_seen(
+        context: &Context,
+        contact_id: u32,
+        timestamp: i64,
+    ) -> Result<()> {
+        context
+            .sql
+            .execute(
+                "UPDATE contacts SET last_seen=? WHERE id=? AND last_seen<?;",
+                paramsv![timestamp, contact_id as i32, timestamp],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the status of the contact, the footer of the messages sent by the contact.
+    ///
+    /// For the self contact, this is the `selfstatus` config option.
+    pub fn get_status(&self) -> &str {
+        &self.status
+    }
+
+    /// Updates the status of the contact to the footer of a message sent at `timestamp`,
+    /// footers of messages older than the current status are ignored.
+    pub(crate) async fn update_status(
+        context: &Context,
+        contact_id: u32,
+        status: &str,
+        timestamp: i64,
+    ) -> Result<()> {
+        let (old_status, old_timestamp) = context
+            .sql
+            .query_row(
+                "SELECT status, status_timestamp FROM contacts WHERE id=?;",
+                paramsv![contact_id as i32],
+                |row| {
+                    Ok((
+                        row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+                        row.get::<_, Option<i64>>(1)?.unwrap_or_default(),
+                    ))
+                },
+            )
+            .await?;
+        if timestamp < old_timestamp {
+            return Ok(());
+        }
+        context
+            .sql
+            .execute(
+                "UPDATE contacts SET status=?, status_timestamp=? WHERE id=?;",
+                paramsv![status, timestamp, contact_id as i32],
+            )
+            .await?;
+        if status != old_status {
+            context.emit_event(Event::ContactsChanged(Some(contact_id)));
+        }
+        Ok(())
+    }
+
     /// Get the ID of the contact.
     pub fn get_id(&self) -> u32 {
         self.id
@@ -949,6 +1042,89 @@ impl Contact {
         VerifiedStatus::Unverified
     }
 
+    /// Returns the IDs of all verified contacts, not including SELF.
+    pub async fn get_all_verified(context: &Context) -> Result<Vec<u32>> {
+        Contact::get_all(context, DC_GCL_VERIFIED_ONLY as u32, None::<String>).await
+    }
+
+    /// Returns how and when the current key of the contact was verified.
+    ///
+    /// `None` if the contact is not verified or was verified
+    /// before the verification history was recorded.
+    pub async fn get_verification(&self, context: &Context) -> Result<Option<VerificationRecord>> {
+        let peerstate = match Peerstate::from_addr(context, &self.addr).await {
+            Some(peerstate) => peerstate,
+            None => return Ok(None),
+        };
+        let fingerprint = match peerstate.verified_key_fingerprint {
+            Some(ref fingerprint) => fingerprint,
+            None => return Ok(None),
+        };
+        let records = peerstate.get_verification_records().await?;
+        Ok(records.into_iter().rev().find(|record| {
+            record.event != VerificationEvent::Revoked && &record.fingerprint == fingerprint
+        }))
+    }
+
+    /// Returns where the key used for encryption to the contact comes from.
+    ///
+    /// `None` if no key of the contact is known.
+    pub async fn get_key_source(&self, context: &Context) -> Result<Option<KeySourceRecord>> {
+        match Peerstate::from_addr(context, &self.addr).await {
+            Some(peerstate) => Ok(peerstate.get_key_source().await?),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns where the keys of the contact come from,
+    /// the key received directly from the contact first.
+    pub async fn get_key_sources(&self, context: &Context) -> Result<Vec<KeySourceRecord>> {
+        match Peerstate::from_addr(context, &self.addr).await {
+            Some(peerstate) => Ok(peerstate.get_key_sources().await?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Returns all verifications and revocations of the contact, oldest first.
+    pub async fn get_verification_history(
+        &self,
+        context: &Context,
+    ) -> Result<Vec<VerificationRecord>> {
+        match Peerstate::from_addr(context, &self.addr).await {
+            Some(peerstate) => Ok(peerstate.get_verification_records().await?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Revokes the verification of a contact.
+    ///
+    /// The contact can be verified again by a QR code scan
+    /// or by an introduction in a verified group.
+    /// Returns `true` if the contact was verified before.
+    pub async fn revoke_verification(context: &Context, contact_id: u32) -> Result<bool> {
+        ensure!(
+            contact_id > DC_CONTACT_ID_LAST_SPECIAL,
+            "Cannot revoke verification of special contact {}",
+            contact_id
+        );
+        let contact = Contact::load_from_db(context, contact_id).await?;
+        let mut peerstate = match Peerstate::from_addr(context, &contact.addr).await {
+            Some(peerstate) => peerstate,
+            None => return Ok(false),
+        };
+        let fingerprint = match peerstate.revoke_verification() {
+            Some(fingerprint) => fingerprint,
+            None => return Ok(false),
+        };
+        peerstate.save_to_db(&context.sql, false).await?;
+        peerstate
+            .add_verification_record(VerificationEvent::Revoked, &fingerprint, None)
+            .await?;
+        info!(context, "Verification of {} revoked.", contact.addr);
+        context.emit_event(Event::ContactsChanged(Some(contact_id)));
+        Ok(true)
+    }
+
     pub async fn addr_equals_contact(
         context: &Context,
         addr: impl AsRef<str>,
@@ -1053,7 +1229,13 @@ fn sanitize_name_and_addr(name: impl AsRef<str>, addr: impl AsRef<str>) -> (Stri
     }
 }
 
-async fn set_block_contact(context: &Context, contact_id: u32, new_blocking: bool) {
+/// Blocks or unblocks a contact, `sync` sends the change to the other devices.
+pub(crate) async fn set_block_contact(
+    context: &Context,
+    contact_id: u32,
+    new_blocking: bool,
+    sync: bool,
+) {
     if contact_id <= DC_CONTACT_ID_LAST_SPECIAL {
         return;
     }
@@ -1063,12 +1245,15 @@ async fn set_block_contact(context: &Context, contact_id: u32, new_blocking: boo
             && context
                 .sql
                 .execute(
-                    "UPDATE contacts SET blocked=? WHERE id=?;",
-                    paramsv![new_blocking as i32, contact_id as i32],
+                    "UPDATE contacts SET blocked=?, blocked_timestamp=? WHERE id=?;",
+                    paramsv![new_blocking as i32, time(), contact_id as i32],
                 )
                 .await
                 .is_ok()
         {
+            if sync {
+                sync::sync_contact(context, contact_id).await;
+            }
             // also (un)block all chats with _only_ this contact - we do not delete them to allow a
             // non-destructive blocking->unblocking.
             // (Maybe, beside normal chats (type=100) we should also block group chats with only this user.
@@ -1085,31 +1270,297 @@ async fn set_block_contact(context: &Context, contact_id: u32, new_blocking: boo
     }
 }
 
+/// Sets the profile image of a contact, received avatars are downscaled.
+///
+/// Returns false if the image did not change,
+/// in this case no [Event::ContactsChanged] is emitted.
 pub(crate) async fn set_profile_image(
     context: &Context,
     contact_id: u32,
     profile_image: &AvatarAction,
-) -> Result<()> {
+) -> Result<bool> {
     // the given profile image is expected to be already in the blob directory
     // as profile images can be set only by receiving messages, this should be always the case, however.
     let mut contact = Contact::load_from_db(context, contact_id).await?;
     let changed = match profile_image {
         AvatarAction::Change(profile_image) => {
-            contact.param.set(Param::ProfileImage, profile_image);
-            true
+            let current = contact
+                .param
+                .get(Param::ProfileImage)
+                .map(|s| s.to_string());
+            match BlobObject::from_received_avatar(context, profile_image, current.as_deref()).await
+            {
+                Some(blob) => {
+                    contact.param.set(Param::ProfileImage, blob.as_name());
+                    true
+                }
+                None => false,
+            }
         }
         AvatarAction::Delete => {
+            let had_image = contact.param.exists(Param::ProfileImage);
             contact.param.remove(Param::ProfileImage);
-            true
+            had_image
         }
     };
     if changed {
         contact.update_param(context).await?;
         context.emit_event(Event::ContactsChanged(Some(contact_id)));
     }
+    Ok(changed)
+}
+
+/// Returns a vCard containing the given contacts.
+///
+/// Each contact is exported with its name, its address and its profile
+/// image.  The vCard can be imported again using [import_vcard].
+pub async fn make_vcard(context: &Context, contact_ids: &[u32]) -> Result<String> {
+    let mut vcard = String::new();
+    for &contact_id in contact_ids {
+        let contact = Contact::get_by_id(context, contact_id).await?;
+        let name = if contact_id == DC_CONTACT_ID_SELF {
+            context
+                .get_config(Config::Displayname)
+                .await
+                .unwrap_or_default()
+        } else {
+            contact.get_name().to_string()
+        };
+
+        vcard += "BEGIN:VCARD\r\nVERSION:4.0\r\n";
+        vcard += &fold_vcard_line(&format!(
+            "FN:{}",
+            escape_vcard_value(if name.is_empty() {
+                contact.get_addr()
+            } else {
+                name.as_str()
+            })
+        ));
+        vcard += &fold_vcard_line(&format!("EMAIL:{}", escape_vcard_value(contact.get_addr())));
+        if let Some(path) = contact.get_profile_image(context).await {
+            match async_std::fs::read(&path).await {
+                Ok(data) => {
+                    let mime = if data.starts_with(b"\x89PNG") {
+                        "image/png"
+                    } else {
+                        "image/jpeg"
+                    };
+                    vcard += &fold_vcard_line(&format!(
+                        "PHOTO:data:{};base64,{}",
+                        mime,
+                        base64::encode(&data)
+                    ));
+                }
+                Err(err) => warn!(context, "cannot read profile image {:?}: {}", path, err),
+            }
+        }
+        vcard += "END:VCARD\r\n";
+    }
+    Ok(vcard)
+}
+
+/// Adds the contacts of a vCard to the contact list.
+///
+/// For each card, the first valid e-mail address is used together with
+/// the name.  A contained photo is used as profile image unless the
+/// contact has sent a profile image on its own already.  Cards without
+/// a valid address are skipped.
+///
+/// Returns the IDs of the imported contacts.
+pub async fn import_vcard(context: &Context, vcard: &str) -> Result<Vec<u32>> {
+    let mut contact_ids = Vec::new();
+    for card in parse_vcard(vcard) {
+        let addr = match card.addr {
+            Some(addr) => addr,
+            None => continue,
+        };
+        // exporters use the address as name if there is no name
+        let name = if addr_cmp(&card.name, &addr) {
+            ""
+        } else {
+            card.name.as_str()
+        };
+        let contact_id = match Contact::create(context, name, &addr).await {
+            Ok(contact_id) => contact_id,
+            Err(err) => {
+                warn!(context, "cannot import {} from vcard: {}", addr, err);
+                continue;
+            }
+        };
+        if let Some(photo) = card.photo {
+            if let Err(err) = set_vcard_photo(context, contact_id, &photo).await {
+                warn!(context, "cannot import photo of {}: {}", addr, err);
+            }
+        }
+        contact_ids.push(contact_id);
+    }
+    Ok(contact_ids)
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct VcardContact {
+    name: String,
+    addr: Option<String>,
+    photo: Option<Vec<u8>>,
+}
+
+fn parse_vcard(vcard: &str) -> Vec<VcardContact> {
+    // unfold lines, continuation lines start with a space or a tab
+    let mut lines: Vec<String> = Vec::new();
+    for line in vcard.lines() {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(&line[1..]);
+            }
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+
+    let mut cards = Vec::new();
+    let mut card: Option<VcardContact> = None;
+    for line in &lines {
+        let (property, params, value) = match split_vcard_line(line) {
+            Some(res) => res,
+            None => continue,
+        };
+        match property.as_str() {
+            "BEGIN" if value.eq_ignore_ascii_case("VCARD") => card = Some(VcardContact::default()),
+            "END" if value.eq_ignore_ascii_case("VCARD") => cards.extend(card.take()),
+            _ => {}
+        }
+        let card = match card.as_mut() {
+            Some(card) => card,
+            None => continue,
+        };
+        match property.as_str() {
+            "FN" => card.name = unescape_vcard_value(value),
+            "N" if card.name.is_empty() => {
+                // family; given; additional; prefixes; suffixes
+                let parts: Vec<String> = value.splitn(3, ';').map(unescape_vcard_value).collect();
+                card.name = format!(
+                    "{} {}",
+                    parts.get(1).map(String::as_str).unwrap_or_default(),
+                    parts[0]
+                )
+                .trim()
+                .to_string();
+            }
+            "EMAIL" if card.addr.is_none() => {
+                let addr = unescape_vcard_value(value);
+                if may_be_valid_addr(&addr) {
+                    card.addr = Some(addr);
+                }
+            }
+            "PHOTO" => card.photo = decode_vcard_photo(&params, value),
+            _ => {}
+        }
+    }
+    cards
+}
+
+/// Splits a vCard line into the uppercased property name, the lowercased
+/// parameters and the raw value.
+fn split_vcard_line(line: &str) -> Option<(String, Vec<String>, &str)> {
+    let mut in_quotes = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            in_quotes = !in_quotes;
+            None
+        }
+        ':' if !in_quotes => Some(i),
+        _ => None,
+    })?;
+    let mut params = line[..colon].split(';');
+    let property = params.next()?;
+    // strip group prefixes as in `item1.EMAIL`
+    let property = property.rsplit('.').next().unwrap_or_default();
+    Some((
+        property.to_uppercase(),
+        params.map(|param| param.to_lowercase()).collect(),
+        &line[colon + 1..],
+    ))
+}
+
+fn decode_vcard_photo(params: &[String], value: &str) -> Option<Vec<u8>> {
+    let data = if value.starts_with("data:") {
+        let pos = value.find(";base64,")?;
+        &value[pos + 8..]
+    } else if params
+        .iter()
+        .any(|param| param == "encoding=b" || param == "encoding=base64")
+    {
+        value
+    } else {
+        return None;
+    };
+    let data: String = data.split_whitespace().collect();
+    base64::decode(&data).ok()
+}
+
+async fn set_vcard_photo(context: &Context, contact_id: u32, data: &[u8]) -> Result<()> {
+    let contact = Contact::load_from_db(context, contact_id).await?;
+    if contact_id == DC_CONTACT_ID_SELF || contact.param.exists(Param::ProfileImage) {
+        // images set by the contact itself are preferred
+        return Ok(());
+    }
+    let name = if data.starts_with(b"\x89PNG") {
+        "avatar.png"
+    } else {
+        "avatar.jpg"
+    };
+    let blob = BlobObject::create(context, name, data).await?;
+    blob.recode_to_avatar_size(context)?;
+    set_profile_image(
+        context,
+        contact_id,
+        &AvatarAction::Change(blob.as_name().to_string()),
+    )
+    .await?;
     Ok(())
 }
 
+fn escape_vcard_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace('\n', "\\n")
+}
+
+fn unescape_vcard_value(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => res.push('\n'),
+                Some(c) => res.push(c),
+                None => {}
+            }
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
+
+/// Folds a vCard line to lines of at most 75 octets as required by RFC 6350.
+fn fold_vcard_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + line.len() / 25 + 2);
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            folded += "\r\n ";
+            len = 1;
+        }
+        folded.push(c);
+        len += c.len_utf8();
+    }
+    folded += "\r\n";
+    folded
+}
+
 /// Normalize a name.
 ///
 /// - Remove quotes (come from some bad MUA implementations)
@@ -1292,6 +1743,51 @@ mod tests {
         assert_eq!(contacts.len(), 0);
     }
 
+    #[async_std::test]
+    async fn test_revoke_verification() {
+        let t = dummy_context().await;
+        let bob_id = Contact::create(&t.ctx, "bob", "bob@example.net")
+            .await
+            .unwrap();
+        assert!(Contact::get_all_verified(&t.ctx).await.unwrap().is_empty());
+
+        let pub_key = alice_keypair().public;
+        let fingerprint = pub_key.fingerprint();
+        let mut peerstate = Peerstate::new(&t.ctx, "bob@example.net".to_string());
+        peerstate.public_key = Some(pub_key);
+        peerstate.recalc_fingerprint();
+        assert!(peerstate.set_verified(
+            PeerstateKeyType::PublicKey,
+            &fingerprint,
+            PeerstateVerifiedStatus::BidirectVerified
+        ));
+        peerstate.save_to_db(&t.ctx.sql, true).await.unwrap();
+        peerstate
+            .add_verification_record(VerificationEvent::SecureJoin, &fingerprint, None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Contact::get_all_verified(&t.ctx).await.unwrap(),
+            vec![bob_id]
+        );
+        let bob = Contact::load_from_db(&t.ctx, bob_id).await.unwrap();
+        let verification = bob.get_verification(&t.ctx).await.unwrap().unwrap();
+        assert_eq!(verification.event, VerificationEvent::SecureJoin);
+        assert_eq!(verification.fingerprint, fingerprint);
+
+        assert!(Contact::revoke_verification(&t.ctx, bob_id).await.unwrap());
+        assert!(!Contact::revoke_verification(&t.ctx, bob_id).await.unwrap());
+        assert_eq!(bob.is_verified(&t.ctx).await, VerifiedStatus::Unverified);
+        assert!(Contact::get_all_verified(&t.ctx).await.unwrap().is_empty());
+        assert!(bob.get_verification(&t.ctx).await.unwrap().is_none());
+
+        let history = bob.get_verification_history(&t.ctx).await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].event, VerificationEvent::Revoked);
+        assert_eq!(history[1].fingerprint, fingerprint);
+    }
+
     #[async_std::test]
     async fn test_is_self_addr() -> Result<()> {
         let t = test_context().await;
@@ -1617,4 +2113,120 @@ mod tests {
             .await
             .is_err());
     }
+
+    #[test]
+    fn test_parse_vcard() {
+        let cards = parse_vcard(
+            "BEGIN:VCARD\r\n\
+             VERSION:3.0\r\n\
+             N:Doe;John;;;\r\n\
+             item1.EMAIL;TYPE=INTERNET:john@exam\r\n \
+             ple.org\r\n\
+             EMAIL:other@example.org\r\n\
+             END:VCARD\r\n\
+             BEGIN:VCARD\r\n\
+             VERSION:4.0\r\n\
+             FN:Doe\\, Jane\r\n\
+             EMAIL:not an address\r\n\
+             EMAIL:jane@example.org\r\n\
+             PHOTO;ENCODING=b;TYPE=JPEG:aGVs\r\n bG8=\r\n\
+             END:VCARD\r\n\
+             BEGIN:VCARD\r\n\
+             FN:Nobody\r\n\
+             END:VCARD\r\n",
+        );
+        assert_eq!(
+            cards,
+            vec![
+                VcardContact {
+                    name: "John Doe".to_string(),
+                    addr: Some("john@example.org".to_string()),
+                    photo: None,
+                },
+                VcardContact {
+                    name: "Doe, Jane".to_string(),
+                    addr: Some("jane@example.org".to_string()),
+                    photo: Some(b"hello".to_vec()),
+                },
+                VcardContact {
+                    name: "Nobody".to_string(),
+                    addr: None,
+                    photo: None,
+                },
+            ]
+        );
+    }
+
+    #[async_std::test]
+    async fn test_set_profile_image() {
+        let t = dummy_context().await;
+        let bob_id = Contact::create(&t.ctx, "Bob", "bob@example.net")
+            .await
+            .unwrap();
+        let data = include_bytes!("../test-data/image/avatar900x900.png");
+        let avatar = BlobObject::create(&t.ctx, "avatar.png", data)
+            .await
+            .unwrap();
+        let action = AvatarAction::Change(avatar.as_name().to_string());
+        assert!(set_profile_image(&t.ctx, bob_id, &action).await.unwrap());
+        let bob = Contact::load_from_db(&t.ctx, bob_id).await.unwrap();
+        assert_eq!(bob.param.get(Param::ProfileImage), Some(avatar.as_name()));
+
+        // the same avatar received again is not stored twice
+        let copy = BlobObject::create(&t.ctx, "avatar.png", data)
+            .await
+            .unwrap();
+        let action = AvatarAction::Change(copy.as_name().to_string());
+        assert!(!set_profile_image(&t.ctx, bob_id, &action).await.unwrap());
+        assert!(!copy.to_abs_path().exists().await);
+
+        assert!(set_profile_image(&t.ctx, bob_id, &AvatarAction::Delete)
+            .await
+            .unwrap());
+        assert!(!set_profile_image(&t.ctx, bob_id, &AvatarAction::Delete)
+            .await
+            .unwrap());
+    }
+
+    #[async_std::test]
+    async fn test_vcard_roundtrip() {
+        let alice = dummy_context().await;
+        let bob_id = Contact::create(&alice.ctx, "Bob; the builder", "bob@example.net")
+            .await
+            .unwrap();
+        let claire_id = Contact::create(&alice.ctx, "", "claire@example.net")
+            .await
+            .unwrap();
+        let avatar = BlobObject::create(
+            &alice.ctx,
+            "avatar.png",
+            include_bytes!("../test-data/image/avatar64x64.png"),
+        )
+        .await
+        .unwrap();
+        set_profile_image(
+            &alice.ctx,
+            bob_id,
+            &AvatarAction::Change(avatar.as_name().to_string()),
+        )
+        .await
+        .unwrap();
+
+        let vcard = make_vcard(&alice.ctx, &[bob_id, claire_id]).await.unwrap();
+        assert!(vcard.starts_with("BEGIN:VCARD\r\n"));
+        assert!(vcard.contains("FN:Bob\\; the builder\r\n"));
+        assert!(vcard.lines().all(|line| line.len() <= 75));
+
+        let t = dummy_context().await;
+        let contact_ids = import_vcard(&t.ctx, &vcard).await.unwrap();
+        assert_eq!(contact_ids.len(), 2);
+        let bob = Contact::load_from_db(&t.ctx, contact_ids[0]).await.unwrap();
+        assert_eq!(bob.get_name(), "Bob; the builder");
+        assert_eq!(bob.get_addr(), "bob@example.net");
+        assert!(bob.get_profile_image(&t.ctx).await.is_some());
+        let claire = Contact::load_from_db(&t.ctx, contact_ids[1]).await.unwrap();
+        assert_eq!(claire.get_name(), "");
+        assert_eq!(claire.get_addr(), "claire@example.net");
+        assert!(claire.get_profile_image(&t.ctx).await.is_none());
+    }
 }
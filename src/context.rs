@@ -1,27 +1,32 @@
 //! Context module
 
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::ffi::OsString;
 use std::ops::Deref;
 
 use async_std::path::{Path, PathBuf};
 use async_std::sync::{channel, Arc, Mutex, Receiver, RwLock, Sender};
+use serde::Serialize;
 
+use crate::blob_crypt;
 use crate::chat::*;
 use crate::config::Config;
+use crate::connectivity::ConnectivityStore;
 use crate::constants::*;
 use crate::contact::*;
-use crate::dc_tools::duration_to_str;
+use crate::dc_tools::{duration_to_str, time};
 use crate::error::*;
-use crate::events::{Event, EventEmitter, Events};
+use crate::events::{Event, EventEmitter, Events, EVENT_BUFFER_SIZE, EVENT_FLUSH_TIMEOUT};
+use crate::folder_strategy::FolderStrategy;
 use crate::job::{self, Action};
 use crate::key::{DcKey, SignedPublicKey};
 use crate::login_param::LoginParam;
 use crate::lot::Lot;
-use crate::message::{self, Message, MessengerMessage, MsgId};
+use crate::message::{self, Message, MsgId};
 use crate::param::Params;
 use crate::scheduler::Scheduler;
 use crate::sql::Sql;
+use crate::transport::TransportCache;
 use std::time::SystemTime;
 
 #[derive(Clone, Debug)]
@@ -52,11 +57,29 @@ pub struct InnerContext {
     pub(crate) generating_key_mutex: Mutex<()>,
     /// Mutex to enforce only a single running oauth2 is running.
     pub(crate) oauth2_mutex: Mutex<()>,
+    /// Mutex held by the SMTP loop while it loads and runs a job, see [job::cancel_send].
+    pub(crate) smtp_job_mutex: Mutex<()>,
     pub(crate) translated_stockstrings: RwLock<HashMap<usize, String>>,
     pub(crate) events: Events,
+    /// Chats with a pending typing notification and the time it was sent.
+    pub(crate) typing_sent: RwLock<HashMap<ChatId, i64>>,
+    /// Recent runs of rate limited jobs, see `job::Action::rate_limit`.
+    pub(crate) job_runs: RwLock<HashMap<Action, VecDeque<i64>>>,
 
     pub(crate) scheduler: RwLock<Scheduler>,
 
+    /// The transport replacing IMAP and SMTP, if configured.
+    pub(crate) transport: Mutex<TransportCache>,
+
+    /// State of the connections reported by the IO loops.
+    pub(crate) connectivity: ConnectivityStore,
+
+    /// Kind of the last error recorded by [Context::set_last_error].
+    last_error_kind: RwLock<ErrorKind>,
+
+    /// The database was opened read-only, see [ContextBuilder::read_only].
+    read_only: bool,
+
     creation_time: SystemTime,
 }
 
@@ -82,19 +105,79 @@ pub fn get_info() -> BTreeMap<&'static str, String> {
     res
 }
 
+/// Steps run by [Context::run_housekeeping].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HousekeepingOptions {
+    /// Delete files in the blobdir not referenced anymore.
+    pub remove_unused_blobs: bool,
+
+    /// Delete read receipts and delivery states of messages that do not exist anymore.
+    pub remove_orphaned_mdns: bool,
+
+    /// Delete the tombstones of deleted messages.
+    pub prune_tombstones: bool,
+
+    /// Rebuild the database file to return unused space to the file system.
+    ///
+    /// This may take a while for large databases and is not done by the
+    /// automatic housekeeping.
+    pub vacuum: bool,
+}
+
+impl Default for HousekeepingOptions {
+    fn default() -> Self {
+        HousekeepingOptions {
+            remove_unused_blobs: true,
+            remove_orphaned_mdns: true,
+            prune_tombstones: true,
+            vacuum: false,
+        }
+    }
+}
+
+/// What was cleaned up by [Context::run_housekeeping].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct HousekeepingReport {
+    /// Number of deleted files.
+    pub removed_blobs: usize,
+
+    /// Bytes freed by deleting files and vacuuming the database.
+    pub freed_bytes: u64,
+
+    /// Number of deleted database rows.
+    pub removed_rows: usize,
+
+    /// Number of chats with wrong unread counters that were recalculated.
+    pub repaired_counters: usize,
+}
+
 impl Context {
     /// Creates new context.
     pub async fn new(os_name: String, dbfile: PathBuf) -> Result<Context> {
-        // pretty_env_logger::try_init_timed().ok();
+        ContextBuilder::new(dbfile).os_name(os_name).open().await
+    }
 
-        let mut blob_fname = OsString::new();
-        blob_fname.push(dbfile.file_name().unwrap_or_default());
-        blob_fname.push("-blobs");
-        let blobdir = dbfile.with_file_name(blob_fname);
-        if !blobdir.exists().await {
-            async_std::fs::create_dir_all(&blobdir).await?;
-        }
-        Context::with_blobdir(os_name, dbfile, blobdir).await
+    /// Returns a builder to open a context with further options.
+    pub fn builder(dbfile: impl Into<PathBuf>) -> ContextBuilder {
+        ContextBuilder::new(dbfile)
+    }
+
+    /// Creates a new context with a database encrypted by SQLCipher.
+    ///
+    /// SQLCipher derives the encryption key from `passphrase`.  Opening fails
+    /// if the passphrase is wrong or if the library was built without
+    /// the `sqlcipher` feature.  Blobs are only encrypted if
+    /// [Config::EncryptBlobs] is enabled.
+    pub async fn open_with_passphrase(
+        os_name: String,
+        dbfile: PathBuf,
+        passphrase: String,
+    ) -> Result<Context> {
+        ContextBuilder::new(dbfile)
+            .os_name(os_name)
+            .passphrase(passphrase)
+            .open()
+            .await
     }
 
     pub async fn with_blobdir(
@@ -102,41 +185,69 @@ impl Context {
         dbfile: PathBuf,
         blobdir: PathBuf,
     ) -> Result<Context> {
-        ensure!(
-            blobdir.is_dir().await,
-            "Blobdir does not exist: {}",
-            blobdir.display()
-        );
+        ContextBuilder::new(dbfile)
+            .os_name(os_name)
+            .blobdir(blobdir)
+            .open()
+            .await
+    }
 
-        let inner = InnerContext {
-            blobdir,
-            dbfile,
-            os_name: Some(os_name),
-            running_state: RwLock::new(Default::default()),
-            sql: Sql::new(),
-            bob: RwLock::new(Default::default()),
-            last_smeared_timestamp: RwLock::new(0),
-            generating_key_mutex: Mutex::new(()),
-            oauth2_mutex: Mutex::new(()),
-            translated_stockstrings: RwLock::new(HashMap::new()),
-            events: Events::default(),
-            scheduler: RwLock::new(Scheduler::Stopped),
-            creation_time: std::time::SystemTime::now(),
-        };
+    /// Returns true if the database was opened read-only.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
 
-        let ctx = Context {
-            inner: Arc::new(inner),
-        };
+    /// Changes the passphrase of the database, `None` decrypts it.
+    ///
+    /// An unencrypted database is encrypted with the new passphrase.
+    /// IO must be stopped while the database is re-keyed.  Attachments
+    /// encrypted at rest are re-encrypted with the new passphrase.
+    pub async fn change_passphrase(&self, passphrase: Option<String>) -> Result<()> {
         ensure!(
-            ctx.sql.open(&ctx, &ctx.dbfile, false).await,
-            "Failed opening sqlite database"
+            !self.is_io_running().await,
+            "Cannot change passphrase while IO is running"
         );
+        let passphrase = passphrase.filter(|passphrase| !passphrase.is_empty());
+        let old_passphrase = self.sql.passphrase().await;
+        self.sql
+            .change_passphrase(self, &self.dbfile, passphrase.clone())
+            .await?;
+        blob_crypt::rekey_blobs(self, old_passphrase.as_deref(), passphrase.as_deref()).await
+    }
 
-        Ok(ctx)
+    /// Runs housekeeping now.
+    ///
+    /// Housekeeping also runs automatically after messages or chats are
+    /// deleted, using the default options.  Progress is reported using
+    /// [Event::HousekeepingProgress].
+    pub async fn run_housekeeping(
+        &self,
+        options: HousekeepingOptions,
+    ) -> Result<HousekeepingReport> {
+        crate::sql::housekeeping(self, options).await
+    }
+
+    /// Records the kind of an error returned to the user.
+    ///
+    /// Used by bindings that cannot return typed errors, eg. the FFI.
+    pub async fn set_last_error(&self, err: &Error) {
+        *self.last_error_kind.write().await = ErrorKind::of(err);
+    }
+
+    /// Returns the kind of the error last recorded by [Context::set_last_error].
+    ///
+    /// Successful calls do not reset the kind, so it is only meaningful
+    /// directly after a call failed.
+    pub async fn get_last_error_kind(&self) -> ErrorKind {
+        *self.last_error_kind.read().await
     }
 
     /// Starts the IO scheduler.
     pub async fn start_io(&self) {
+        if self.read_only {
+            warn!(self, "cannot start IO, database opened read-only");
+            return;
+        }
         info!(self, "starting IO");
         if self.is_io_running().await {
             info!(self, "IO is already running");
@@ -155,6 +266,12 @@ impl Context {
     }
 
     /// Stops the IO scheduler.
+    ///
+    /// Jobs and fetches in progress are finished so that their database
+    /// writes are complete, IDLE is aborted.  Returns when all connections
+    /// are closed and the emitted events are taken by the [EventEmitter]
+    /// or a short timeout passed; the context can be suspended then
+    /// and IO started again using [Context::start_io].
     pub async fn stop_io(&self) {
         info!(self, "stopping IO");
         if !self.is_io_running().await {
@@ -163,6 +280,9 @@ impl Context {
         }
 
         self.inner.stop_io().await;
+        self.reset_connectivity().await;
+        info!(self, "IO stopped");
+        self.events.flush(EVENT_FLUSH_TIMEOUT).await;
     }
 
     /// Returns a reference to the underlying SQL instance.
@@ -188,7 +308,19 @@ impl Context {
         self.events.emit(event);
     }
 
-    /// Get the next queued event.
+    /// Subscribes to the events with the given IDs only, see [Event::as_id].
+    ///
+    /// Other events are dropped before they are queued for the emitter,
+    /// this includes the `Info` and `Warning` log events.
+    /// `None` subscribes to all events again, which is the default.
+    pub fn set_event_filter(&self, event_ids: Option<HashSet<i32>>) {
+        self.events.set_filter(event_ids);
+    }
+
+    /// Returns an emitter for the events of this context.
+    ///
+    /// The emitter is a [Stream](futures::Stream) of events,
+    /// see [EventEmitter] for the buffering semantics.
     pub fn get_event_emitter(&self) -> EventEmitter {
         self.events.get_emitter()
     }
@@ -291,8 +423,8 @@ impl Context {
 
         let inbox_watch = self.get_config_int(Config::InboxWatch).await;
         let sentbox_watch = self.get_config_int(Config::SentboxWatch).await;
-        let mvbox_watch = self.get_config_int(Config::MvboxWatch).await;
-        let mvbox_move = self.get_config_int(Config::MvboxMove).await;
+        let watch_folders = self.get_watch_folders().await;
+        let mvbox_policy = self.get_config_int(Config::MvboxPolicy).await;
         let folders_configured = self
             .sql
             .get_raw_config_int(self, "folders_configured")
@@ -316,6 +448,14 @@ impl Context {
         res.insert("database_dir", self.get_dbfile().display().to_string());
         res.insert("database_version", dbversion.to_string());
         res.insert("journal_mode", journal_mode);
+        let statement_cache = self.sql.statement_cache_stats();
+        res.insert(
+            "statement_cache",
+            format!(
+                "{} hits, {} misses",
+                statement_cache.hits, statement_cache.misses
+            ),
+        );
         res.insert("blobdir", self.get_blobdir().display().to_string());
         res.insert("display_name", displayname.unwrap_or_else(|| unset.into()));
         res.insert(
@@ -329,8 +469,8 @@ impl Context {
         res.insert("used_account_settings", l2.to_string());
         res.insert("inbox_watch", inbox_watch.to_string());
         res.insert("sentbox_watch", sentbox_watch.to_string());
-        res.insert("mvbox_watch", mvbox_watch.to_string());
-        res.insert("mvbox_move", mvbox_move.to_string());
+        res.insert("watch_folders", watch_folders.join(","));
+        res.insert("mvbox_policy", mvbox_policy.to_string());
         res.insert("folders_configured", folders_configured.to_string());
         res.insert("configured_sentbox_folder", configured_sentbox_folder);
         res.insert("configured_mvbox_folder", configured_mvbox_folder);
@@ -369,9 +509,10 @@ impl Context {
                     "   AND m.chat_id>?",
                     "   AND ct.blocked=0",
                     "   AND (c.blocked=0 OR c.blocked=?)",
+                    "   AND NOT(c.muted_until=-1 OR c.muted_until>?)",
                     " ORDER BY m.timestamp DESC,m.id DESC;"
                 ),
-                paramsv![10, 9, if 0 != show_deaddrop { 2 } else { 0 }],
+                paramsv![10, 9, if 0 != show_deaddrop { 2 } else { 0 }, time()],
                 |row| row.get::<_, MsgId>(0),
                 |rows| {
                     let mut ret = Vec::new();
@@ -385,48 +526,98 @@ impl Context {
             .unwrap_or_default()
     }
 
+    /// Searches messages containing the given query.
+    ///
+    /// The search uses the full-text index of message texts, every word of
+    /// the query has to match the beginning of a word in the message.
+    /// If the index finds nothing, messages containing the query anywhere,
+    /// eg. inside a word, are returned.
+    /// Messages from contacts whose name starts with the query are found as well.
+    /// Results of a global search (unset `chat_id`) are sorted newest first,
+    /// results of a search in a chat are sorted like the chat.
+    /// Use [message::search_msgs] for results sorted by relevance.
+    ///
+    /// [message::search_msgs]: crate::message::search_msgs
     #[allow(non_snake_case)]
     pub async fn search_msgs(&self, chat_id: ChatId, query: impl AsRef<str>) -> Vec<MsgId> {
         let real_query = query.as_ref().trim();
-        if real_query.is_empty() {
-            return Vec::new();
-        }
+        let fts_query = match message::fts_query(real_query) {
+            Some(fts_query) => fts_query,
+            None => return Vec::new(),
+        };
         let strLikeInText = format!("%{}%", real_query);
         let strLikeBeg = format!("{}%", real_query);
 
+        let ret = self
+            .search_msgs_matching(
+                chat_id,
+                "m.id IN (SELECT rowid FROM msgs_search WHERE msgs_search MATCH ?)",
+                fts_query,
+                &strLikeBeg,
+            )
+            .await;
+        if !ret.is_empty() {
+            return ret;
+        }
+        self.search_msgs_matching(chat_id, "m.txt LIKE ?", strLikeInText, &strLikeBeg)
+            .await
+    }
+
+    /// Runs the query of [Context::search_msgs] with the given condition on the text.
+    #[allow(non_snake_case)]
+    async fn search_msgs_matching(
+        &self,
+        chat_id: ChatId,
+        text_cond: &str,
+        text_arg: String,
+        strLikeBeg: &str,
+    ) -> Vec<MsgId> {
         let query = if !chat_id.is_unset() {
-            concat!(
-                "SELECT m.id AS id, m.timestamp AS timestamp",
-                " FROM msgs m",
-                " LEFT JOIN contacts ct",
-                "        ON m.from_id=ct.id",
-                " WHERE m.chat_id=?",
-                "   AND m.hidden=0",
-                "   AND ct.blocked=0",
-                "   AND (txt LIKE ? OR ct.name LIKE ?)",
-                " ORDER BY m.timestamp,m.id;"
+            format!(
+                concat!(
+                    "SELECT m.id AS id, m.timestamp AS timestamp",
+                    " FROM msgs m",
+                    " LEFT JOIN contacts ct",
+                    "        ON m.from_id=ct.id",
+                    " WHERE m.chat_id=?",
+                    "   AND m.hidden=0",
+                    "   AND ct.blocked=0",
+                    "   AND (m.ephemeral_timestamp=0 OR m.ephemeral_timestamp>?)",
+                    "   AND ({} OR ct.name LIKE ?)",
+                    " ORDER BY m.timestamp,m.id;"
+                ),
+                text_cond
             )
         } else {
-            concat!(
-                "SELECT m.id AS id, m.timestamp AS timestamp",
-                " FROM msgs m",
-                " LEFT JOIN contacts ct",
-                "        ON m.from_id=ct.id",
-                " LEFT JOIN chats c",
-                "        ON m.chat_id=c.id",
-                " WHERE m.chat_id>9",
-                "   AND m.hidden=0",
-                "   AND (c.blocked=0 OR c.blocked=?)",
-                "   AND ct.blocked=0",
-                "   AND (m.txt LIKE ? OR ct.name LIKE ?)",
-                " ORDER BY m.timestamp DESC,m.id DESC;"
+            format!(
+                concat!(
+                    "SELECT m.id AS id, m.timestamp AS timestamp",
+                    " FROM msgs m",
+                    " LEFT JOIN contacts ct",
+                    "        ON m.from_id=ct.id",
+                    " LEFT JOIN chats c",
+                    "        ON m.chat_id=c.id",
+                    " WHERE m.chat_id>?",
+                    "   AND m.hidden=0",
+                    "   AND c.blocked=0",
+                    "   AND ct.blocked=0",
+                    "   AND (m.ephemeral_timestamp=0 OR m.ephemeral_timestamp>?)",
+                    "   AND ({} OR ct.name LIKE ?)",
+                    " ORDER BY m.timestamp DESC,m.id DESC;"
+                ),
+                text_cond
             )
         };
+        let chat_id = if chat_id.is_unset() {
+            DC_CHAT_ID_LAST_SPECIAL
+        } else {
+            chat_id.to_u32()
+        };
 
         self.sql
             .query_map(
                 query,
-                paramsv![chat_id, strLikeInText, strLikeBeg],
+                paramsv![chat_id, time(), text_arg, strLikeBeg],
                 |row| row.get::<_, MsgId>("id"),
                 |rows| {
                     let mut ret = Vec::new();
@@ -456,7 +647,8 @@ impl Context {
     }
 
     pub async fn do_heuristics_moves(&self, folder: &str, msg_id: MsgId) {
-        if !self.get_config_bool(Config::MvboxMove).await {
+        let strategy = FolderStrategy::load(self).await;
+        if !strategy.moves_msgs() {
             return;
         }
 
@@ -470,15 +662,12 @@ impl Context {
                 return;
             }
 
-            match msg.is_dc_message {
-                MessengerMessage::No => {}
-                MessengerMessage::Yes | MessengerMessage::Reply => {
-                    job::add(
-                        self,
-                        job::Job::new(Action::MoveMsg, msg.id.to_u32(), Params::new(), 0),
-                    )
-                    .await;
-                }
+            if strategy.should_move(msg.is_dc_message) {
+                job::add(
+                    self,
+                    job::Job::new(Action::MoveMsg, msg.id.to_u32(), Params::new(), 0),
+                )
+                .await;
             }
         }
     }
@@ -502,6 +691,14 @@ impl InnerContext {
     }
 }
 
+impl Drop for InnerContext {
+    fn drop(&mut self) {
+        if !self.read_only {
+            blob_crypt::remove_decrypted(&self.blobdir);
+        }
+    }
+}
+
 impl Default for RunningState {
     fn default() -> Self {
         RunningState {
@@ -523,6 +720,163 @@ pub fn get_version_str() -> &'static str {
     &DC_VERSION_STR
 }
 
+/// Creates the blobdir next to the database file if it does not exist.
+/// Returns the blobdir used if none is given, next to the database file.
+fn default_blobdir(dbfile: &Path) -> PathBuf {
+    let mut blob_fname = OsString::new();
+    blob_fname.push(dbfile.file_name().unwrap_or_default());
+    blob_fname.push("-blobs");
+    dbfile.with_file_name(blob_fname)
+}
+
+/// Options for opening a [Context], see [Context::builder].
+///
+/// The combination of options is checked by [ContextBuilder::validate]
+/// before anything is created on disk.
+#[derive(Debug, Clone)]
+pub struct ContextBuilder {
+    dbfile: PathBuf,
+    blobdir: Option<PathBuf>,
+    passphrase: Option<String>,
+    os_name: Option<String>,
+    event_buffer_size: usize,
+    read_only: bool,
+}
+
+impl ContextBuilder {
+    /// Creates a builder for the database at `dbfile`.
+    pub fn new(dbfile: impl Into<PathBuf>) -> Self {
+        ContextBuilder {
+            dbfile: dbfile.into(),
+            blobdir: None,
+            passphrase: None,
+            os_name: None,
+            event_buffer_size: EVENT_BUFFER_SIZE,
+            read_only: false,
+        }
+    }
+
+    /// Sets the directory for attachments.
+    ///
+    /// The directory must exist.  By default, the directory
+    /// `<dbfile>-blobs` next to the database is used and created if needed.
+    pub fn blobdir(mut self, blobdir: impl Into<PathBuf>) -> Self {
+        self.blobdir = Some(blobdir.into());
+        self
+    }
+
+    /// Opens the database encrypted by SQLCipher, see [Context::open_with_passphrase].
+    pub fn passphrase(mut self, passphrase: impl Into<String>) -> Self {
+        self.passphrase = Some(passphrase.into());
+        self
+    }
+
+    /// Sets the name of the operating system sent in the `X-Mailer` header.
+    pub fn os_name(mut self, os_name: impl Into<String>) -> Self {
+        self.os_name = Some(os_name.into());
+        self
+    }
+
+    /// Sets the number of events buffered for the [EventEmitter],
+    /// further events are dropped until the emitter catches up.
+    pub fn event_buffer_size(mut self, event_buffer_size: usize) -> Self {
+        self.event_buffer_size = event_buffer_size;
+        self
+    }
+
+    /// Opens an existing database read-only, eg. to inspect a backup.
+    ///
+    /// Nothing is created or migrated and IO cannot be started.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Checks the combination of options without touching the disk.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.dbfile.file_name().is_some(),
+            "Invalid database file: {}",
+            self.dbfile.display()
+        );
+        ensure!(
+            self.event_buffer_size > 0,
+            "Event buffer size must not be 0"
+        );
+        if let Some(ref passphrase) = self.passphrase {
+            ensure!(!passphrase.is_empty(), "Empty database passphrase");
+            ensure!(
+                cfg!(feature = "sqlcipher"),
+                "Database encryption is not supported by this build"
+            );
+        }
+        Ok(())
+    }
+
+    /// Opens the context.
+    pub async fn open(self) -> Result<Context> {
+        self.validate()?;
+
+        let create_blobdir = self.blobdir.is_none() && !self.read_only;
+        let blobdir = match self.blobdir {
+            Some(blobdir) => blobdir,
+            None => default_blobdir(&self.dbfile),
+        };
+        if self.read_only {
+            ensure!(
+                self.dbfile.is_file().await,
+                "Database does not exist: {}",
+                self.dbfile.display()
+            );
+        } else if create_blobdir && !blobdir.exists().await {
+            async_std::fs::create_dir_all(&blobdir).await?;
+        }
+        ensure!(
+            blobdir.is_dir().await,
+            "Blobdir does not exist: {}",
+            blobdir.display()
+        );
+
+        if !self.read_only {
+            // decrypted copies left behind if the app was killed
+            blob_crypt::remove_decrypted(&blobdir);
+        }
+
+        let inner = InnerContext {
+            blobdir,
+            dbfile: self.dbfile,
+            os_name: self.os_name,
+            running_state: RwLock::new(Default::default()),
+            sql: Sql::with_passphrase(self.passphrase),
+            bob: RwLock::new(Default::default()),
+            last_smeared_timestamp: RwLock::new(0),
+            generating_key_mutex: Mutex::new(()),
+            oauth2_mutex: Mutex::new(()),
+            smtp_job_mutex: Mutex::new(()),
+            translated_stockstrings: RwLock::new(HashMap::new()),
+            events: Events::new(self.event_buffer_size),
+            typing_sent: RwLock::new(HashMap::new()),
+            job_runs: RwLock::new(HashMap::new()),
+            scheduler: RwLock::new(Scheduler::Stopped),
+            transport: Mutex::new(Default::default()),
+            connectivity: Default::default(),
+            last_error_kind: RwLock::new(ErrorKind::Other),
+            read_only: self.read_only,
+            creation_time: std::time::SystemTime::now(),
+        };
+
+        let ctx = Context {
+            inner: Arc::new(inner),
+        };
+        ensure!(
+            ctx.sql.open(&ctx, &ctx.dbfile, ctx.read_only).await,
+            "Failed opening sqlite database"
+        );
+
+        Ok(ctx)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -554,6 +908,176 @@ mod tests {
         assert!(blobdir.is_dir());
     }
 
+    #[async_std::test]
+    async fn test_start_stop_io() {
+        let t = dummy_context().await;
+        for _ in 0..2 {
+            t.ctx.start_io().await;
+            assert!(t.ctx.is_io_running().await);
+
+            // idling loops are interrupted, not aborted after the timeout
+            let start = std::time::Instant::now();
+            t.ctx.stop_io().await;
+            assert!(!t.ctx.is_io_running().await);
+            assert!(start.elapsed() < std::time::Duration::from_secs(10));
+        }
+        t.ctx.stop_io().await;
+    }
+
+    #[async_std::test]
+    async fn test_context_builder() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dbfile = tmp.path().join("db.sqlite");
+
+        // invalid combinations are refused before anything is created
+        assert!(Context::builder(dbfile.clone())
+            .event_buffer_size(0)
+            .open()
+            .await
+            .is_err());
+        assert!(Context::builder(dbfile.clone())
+            .passphrase("")
+            .validate()
+            .is_err());
+        assert!(Context::builder(dbfile.clone())
+            .read_only(true)
+            .open()
+            .await
+            .is_err());
+        assert!(!dbfile.exists());
+        assert!(!tmp.path().join("db.sqlite-blobs").exists());
+
+        let blobdir = tmp.path().join("attachments");
+        assert!(Context::builder(dbfile.clone())
+            .blobdir(blobdir.clone())
+            .open()
+            .await
+            .is_err());
+        std::fs::create_dir(&blobdir).unwrap();
+        let ctx = Context::builder(dbfile.clone())
+            .blobdir(blobdir.clone())
+            .os_name("FakeOS")
+            .event_buffer_size(10)
+            .open()
+            .await
+            .unwrap();
+        assert_eq!(ctx.get_blobdir().to_str(), blobdir.to_str());
+        assert!(!ctx.is_read_only());
+        ctx.set_config(Config::Addr, Some("alice@example.org"))
+            .await
+            .unwrap();
+        drop(ctx);
+
+        let ctx = Context::builder(dbfile.clone())
+            .blobdir(blobdir.clone())
+            .read_only(true)
+            .open()
+            .await
+            .unwrap();
+        assert!(ctx.is_read_only());
+        assert_eq!(
+            ctx.get_config(Config::Addr).await,
+            Some("alice@example.org".to_string())
+        );
+        assert!(ctx
+            .set_config(Config::Addr, Some("bob@example.org"))
+            .await
+            .is_err());
+        ctx.start_io().await;
+        assert!(!ctx.is_io_running().await);
+    }
+
+    #[async_std::test]
+    async fn test_open_with_passphrase() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dbfile = tmp.path().join("db.sqlite");
+        let res =
+            Context::open_with_passphrase("FakeOS".into(), dbfile.clone().into(), "foo".into())
+                .await;
+        if !cfg!(feature = "sqlcipher") {
+            // a plain SQLite would ignore the passphrase
+            assert!(res.is_err());
+            return;
+        }
+
+        let ctx = res.unwrap();
+        assert!(ctx.sql.is_encrypted().await);
+        ctx.set_config(Config::Addr, Some("alice@example.org"))
+            .await
+            .unwrap();
+        drop(ctx);
+
+        assert!(Context::new("FakeOS".into(), dbfile.clone().into())
+            .await
+            .is_err());
+        assert!(Context::open_with_passphrase(
+            "FakeOS".into(),
+            dbfile.clone().into(),
+            "bar".into()
+        )
+        .await
+        .is_err());
+        let ctx =
+            Context::open_with_passphrase("FakeOS".into(), dbfile.clone().into(), "foo".into())
+                .await
+                .unwrap();
+        assert_eq!(
+            ctx.get_config(Config::Addr).await,
+            Some("alice@example.org".to_string())
+        );
+    }
+
+    #[async_std::test]
+    async fn test_change_passphrase() {
+        if !cfg!(feature = "sqlcipher") {
+            return;
+        }
+        let tmp = tempfile::tempdir().unwrap();
+        let dbfile = tmp.path().join("db.sqlite");
+        let ctx = Context::new("FakeOS".into(), dbfile.clone().into())
+            .await
+            .unwrap();
+        ctx.set_config(Config::Addr, Some("alice@example.org"))
+            .await
+            .unwrap();
+
+        // encrypt
+        ctx.change_passphrase(Some("foo".into())).await.unwrap();
+        assert!(ctx.sql.is_encrypted().await);
+        assert_eq!(
+            ctx.get_config(Config::Addr).await,
+            Some("alice@example.org".to_string())
+        );
+        drop(ctx);
+        assert!(Context::new("FakeOS".into(), dbfile.clone().into())
+            .await
+            .is_err());
+
+        // re-key
+        let ctx =
+            Context::open_with_passphrase("FakeOS".into(), dbfile.clone().into(), "foo".into())
+                .await
+                .unwrap();
+        ctx.change_passphrase(Some("bar".into())).await.unwrap();
+        drop(ctx);
+        let ctx =
+            Context::open_with_passphrase("FakeOS".into(), dbfile.clone().into(), "bar".into())
+                .await
+                .unwrap();
+
+        // decrypt
+        ctx.change_passphrase(None).await.unwrap();
+        assert!(!ctx.sql.is_encrypted().await);
+        drop(ctx);
+        let ctx = Context::new("FakeOS".into(), dbfile.clone().into())
+            .await
+            .unwrap();
+        assert_eq!(
+            ctx.get_config(Config::Addr).await,
+            Some("alice@example.org".to_string())
+        );
+    }
+
     #[async_std::test]
     async fn test_wrong_blogdir() {
         let tmp = tempfile::tempdir().unwrap();
@@ -607,6 +1131,38 @@ mod tests {
         assert!(info.get("database_dir").is_some());
     }
 
+    #[async_std::test]
+    async fn test_run_housekeeping() {
+        let t = dummy_context().await;
+        t.ctx
+            .sql
+            .execute(
+                "INSERT INTO msgs_mdns (msg_id, contact_id) VALUES (4711, 10);",
+                paramsv![],
+            )
+            .await
+            .unwrap();
+        // recently created files are kept as they may belong to a message being created
+        let unused = t.ctx.get_blobdir().join("unused.txt");
+        async_std::fs::write(&unused, b"hello").await.unwrap();
+
+        let report = t
+            .ctx
+            .run_housekeeping(HousekeepingOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(report.removed_blobs, 0);
+        assert_eq!(report.removed_rows, 1);
+        assert!(unused.exists());
+
+        let report = t
+            .ctx
+            .run_housekeeping(HousekeepingOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(report, HousekeepingReport::default());
+    }
+
     #[test]
     fn test_get_info_no_context() {
         let info = get_info();
@@ -4,23 +4,26 @@ use sha2::{Digest, Sha256};
 
 use mailparse::SingleInfo;
 
+use crate::blob::BlobObject;
+use crate::blob_crypt;
 use crate::chat::{self, Chat, ChatId};
 use crate::config::Config;
 use crate::constants::*;
 use crate::contact::*;
 use crate::context::Context;
 use crate::dc_tools::*;
+use crate::download::DownloadState;
 use crate::error::{bail, ensure, format_err, Result};
 use crate::events::Event;
 use crate::headerdef::HeaderDef;
 use crate::job::{self, Action};
-use crate::message::{self, MessageState, MessengerMessage, MsgId};
+use crate::message::{self, Message, MessageState, MessengerMessage, MsgId};
 use crate::mimeparser::*;
 use crate::param::*;
 use crate::peerstate::*;
 use crate::securejoin::{self, handle_securejoin_handshake, observe_securejoin_on_other_device};
 use crate::stock::StockMessage;
-use crate::{contact, location};
+use crate::{app, contact, ephemeral, location, poll, reaction, sync};
 
 // IndexSet is like HashSet but maintains order of insertion
 type ContactIds = indexmap::IndexSet<u32>;
@@ -28,7 +31,7 @@ type ContactIds = indexmap::IndexSet<u32>;
 #[derive(Debug, PartialEq, Eq)]
 enum CreateEvent {
     MsgsChanged,
-    IncomingMsg,
+    IncomingMsg { muted: bool },
 }
 
 /// Receive a message and add it to the database.
@@ -42,6 +45,22 @@ pub async fn dc_receive_imf(
     server_folder: impl AsRef<str>,
     server_uid: u32,
     seen: bool,
+) -> Result<()> {
+    dc_receive_imf_inner(context, imf_raw, server_folder, server_uid, seen, None).await
+}
+
+/// Like [dc_receive_imf], but `is_partial_download` may be set to the size
+/// of the full message if only the header was downloaded.
+///
+/// A placeholder message is stored then, it is replaced when
+/// the full message is received later.
+pub(crate) async fn dc_receive_imf_inner(
+    context: &Context,
+    imf_raw: &[u8],
+    server_folder: impl AsRef<str>,
+    server_uid: u32,
+    seen: bool,
+    is_partial_download: Option<u32>,
 ) -> Result<()> {
     info!(
         context,
@@ -59,13 +78,14 @@ pub async fn dc_receive_imf(
         println!("{}", String::from_utf8_lossy(imf_raw));
     }
 
-    let mut mime_parser = match MimeMessage::from_bytes(context, imf_raw).await {
-        Err(err) => {
-            warn!(context, "dc_receive_imf: can't parse MIME: {}", err);
-            return Ok(());
-        }
-        Ok(mime_parser) => mime_parser,
-    };
+    let mut mime_parser =
+        match MimeMessage::from_bytes_with_partial(context, imf_raw, is_partial_download).await {
+            Err(err) => {
+                warn!(context, "dc_receive_imf: can't parse MIME: {}", err);
+                return Ok(());
+            }
+            Ok(mime_parser) => mime_parser,
+        };
 
     // we can not add even an empty record if we have no info whatsoever
     if !mime_parser.has_headers() {
@@ -92,7 +112,11 @@ pub async fn dc_receive_imf(
             for (chat_id, msg_id) in created_db_entries {
                 let event = match create_event_to_send {
                     CreateEvent::MsgsChanged => Event::MsgsChanged { msg_id, chat_id },
-                    CreateEvent::IncomingMsg => Event::IncomingMsg { msg_id, chat_id },
+                    CreateEvent::IncomingMsg { muted } => Event::IncomingMsg {
+                        msg_id,
+                        chat_id,
+                        muted: *muted,
+                    },
                 };
                 context.emit_event(event);
             }
@@ -114,6 +138,66 @@ pub async fn dc_receive_imf(
 
     let incoming = from_id != DC_CONTACT_ID_SELF;
 
+    if from_id > DC_CONTACT_ID_LAST_SPECIAL {
+        // messages may be delayed, but never count a date in the future
+        let now = time();
+        let last_seen = if sent_timestamp > 0 && sent_timestamp < now {
+            sent_timestamp
+        } else {
+            now
+        };
+        if let Err(err) = Contact::update_last_seen(context, from_id, last_seen).await {
+            warn!(
+                context,
+                "Cannot update last seen of contact {}: {}", from_id, err
+            );
+        }
+
+        // footers of mailing lists are not written by the sender;
+        // Delta Chat adds the status to all normal chat messages,
+        // so a chat message without footer means the status was removed
+        let status = match &mime_parser.footer {
+            Some(footer) => Some(footer.as_str()),
+            None if mime_parser.has_chat_version()
+                && mime_parser.is_system_message == SystemMessage::Unknown
+                && mime_parser.reports.is_empty() =>
+            {
+                Some("")
+            }
+            None => None,
+        };
+        if let Some(status) = status {
+            if mime_parser.get(HeaderDef::ListId).is_none() {
+                if let Err(err) = Contact::update_status(context, from_id, status, last_seen).await
+                {
+                    warn!(
+                        context,
+                        "Cannot update status of contact {}: {}", from_id, err
+                    );
+                }
+            }
+        }
+    }
+
+    // typing notifications are not stored, they are removed from the server right away
+    if mime_parser.is_system_message == SystemMessage::Typing {
+        if incoming && !from_id_blocked {
+            if let Err(err) =
+                chat::receive_typing(context, &mime_parser, from_id, sent_timestamp).await
+            {
+                info!(context, "Ignoring typing notification: {}", err);
+            }
+        }
+        job::add_unstored_msg_deletion(
+            context,
+            &mime_parser.get_rfc724_mid().unwrap_or_default(),
+            server_folder.as_ref(),
+            server_uid,
+        )
+        .await;
+        return Ok(());
+    }
+
     let mut to_ids = ContactIds::new();
 
     to_ids.extend(
@@ -168,6 +252,7 @@ pub async fn dc_receive_imf(
             &mut insert_msg_id,
             &mut created_db_entries,
             &mut create_event_to_send,
+            is_partial_download,
         )
         .await
         {
@@ -196,9 +281,10 @@ pub async fn dc_receive_imf(
 
     if let Some(avatar_action) = &mime_parser.user_avatar {
         match contact::set_profile_image(&context, from_id, avatar_action).await {
-            Ok(()) => {
+            Ok(true) => {
                 context.emit_event(Event::ChatModified(chat_id));
             }
+            Ok(false) => {}
             Err(err) => {
                 warn!(context, "reveive_imf cannot update profile image: {}", err);
             }
@@ -209,7 +295,9 @@ pub async fn dc_receive_imf(
     let delete_server_after = context.get_config_delete_server_after().await;
 
     if !created_db_entries.is_empty() {
-        if needs_delete_job || delete_server_after == Some(0) {
+        // partially downloaded messages are kept on the server
+        // until they are downloaded completely.
+        if needs_delete_job || (delete_server_after == Some(0) && is_partial_download.is_none()) {
             for db_entry in &created_db_entries {
                 job::add(
                     context,
@@ -310,6 +398,7 @@ async fn add_parts(
     insert_msg_id: &mut MsgId,
     created_db_entries: &mut Vec<(ChatId, MsgId)>,
     create_event_to_send: &mut Option<CreateEvent>,
+    is_partial_download: Option<u32>,
 ) -> Result<()> {
     let mut state: MessageState;
     let mut chat_id_blocked = Blocked::Not;
@@ -322,16 +411,32 @@ async fn add_parts(
     // check, if the mail is already in our database - if so, just update the folder/uid
     // (if the mail was moved around) and finish. (we may get a mail twice eg. if it is
     // moved between folders. make sure, this check is done eg. before securejoin-processing) */
-    if let Some((old_server_folder, old_server_uid, _)) =
+    // partially downloaded messages are replaced by the full message instead.
+    let mut replace_msg = None;
+    if let Some((old_server_folder, old_server_uid, old_msg_id)) =
         message::rfc724_mid_exists(context, &rfc724_mid).await?
     {
-        if old_server_folder != server_folder.as_ref() || old_server_uid != server_uid {
-            message::update_server_uid(context, &rfc724_mid, server_folder.as_ref(), server_uid)
+        let old_msg = Message::load_from_db(context, old_msg_id).await?;
+        if is_partial_download.is_none() && old_msg.download_state() != DownloadState::Done {
+            info!(
+                context,
+                "Message {} was downloaded partially, replacing it.", old_msg_id
+            );
+            replace_msg = Some(old_msg);
+        } else {
+            if old_server_folder != server_folder.as_ref() || old_server_uid != server_uid {
+                message::update_server_uid(
+                    context,
+                    &rfc724_mid,
+                    server_folder.as_ref(),
+                    server_uid,
+                )
                 .await;
-        }
+            }
 
-        warn!(context, "Message already in DB");
-        return Ok(());
+            warn!(context, "Message already in DB");
+            return Ok(());
+        }
     }
 
     let mut msgrmsg = if mime_parser.has_chat_version() {
@@ -345,8 +450,11 @@ async fn add_parts(
     let mut allow_creation = true;
     let show_emails =
         ShowEmails::from_i32(context.get_config_int(Config::ShowEmails).await).unwrap_or_default();
+    // mailing lists get their own chat regardless of show_emails
+    let is_mailinglist = mime_parser.get(HeaderDef::ListId).is_some();
     if mime_parser.is_system_message != SystemMessage::AutocryptSetupMessage
         && msgrmsg == MessengerMessage::No
+        && !is_mailinglist
     {
         // this message is a classic email not a chat-message nor a reply to one
         match show_emails {
@@ -365,6 +473,11 @@ async fn add_parts(
     // (of course, the user can add other chats manually later)
     let to_id: u32;
 
+    // sync messages between own devices are applied below and not shown in any chat
+    if mime_parser.is_system_message == SystemMessage::MultiDeviceSync {
+        *chat_id = ChatId::new(DC_CHAT_ID_TRASH);
+    }
+
     if incoming {
         state = if seen {
             MessageState::InSeen
@@ -446,7 +559,12 @@ async fn add_parts(
 
         if chat_id.is_unset() {
             // check if the message belongs to a mailing list
-            if mime_parser.is_mailinglist_message() {
+            if let Some(list_id) = mime_parser.get(HeaderDef::ListId) {
+                let (new_chat_id, new_chat_id_blocked) =
+                    create_or_lookup_mailinglist(context, list_id, mime_parser).await;
+                *chat_id = new_chat_id;
+                chat_id_blocked = new_chat_id_blocked;
+            } else if mime_parser.is_mailinglist_message() {
                 *chat_id = ChatId::new(DC_CHAT_ID_TRASH);
                 info!(context, "Message belongs to a mailing list and is ignored.",);
             }
@@ -502,6 +620,7 @@ async fn add_parts(
             && !incoming_origin.is_known()
             && msgrmsg == MessengerMessage::No
             && show_emails != ShowEmails::All
+            && !is_mailinglist
         {
             state = MessageState::InNoticed;
         }
@@ -534,6 +653,16 @@ async fn add_parts(
             }
         }
 
+        if chat_id.is_unset() {
+            // own messages delivered by a mailing list
+            if let Some(list_id) = mime_parser.get(HeaderDef::ListId) {
+                let (new_chat_id, new_chat_id_blocked) =
+                    create_or_lookup_mailinglist(context, list_id, mime_parser).await;
+                *chat_id = new_chat_id;
+                chat_id_blocked = new_chat_id_blocked;
+            }
+        }
+
         if !to_ids.is_empty() {
             if chat_id.is_unset() {
                 let (new_chat_id, new_chat_id_blocked) = create_or_lookup_group(
@@ -616,6 +745,25 @@ async fn add_parts(
     // unarchive chat
     chat_id.unarchive(context).await?;
 
+    // messages expire with the timer they were sent with,
+    // chat members are informed about timer changes
+    let ephemeral_timer = ephemeral::timer_from_headers(mime_parser);
+    if mime_parser.is_system_message == SystemMessage::EphemeralTimerChanged
+        && !chat_id.is_special()
+        && (from_id == DC_CONTACT_ID_SELF
+            || chat::is_contact_in_chat(context, *chat_id, from_id).await)
+    {
+        if let Err(err) = chat_id
+            .inner_set_ephemeral_timer(context, ephemeral_timer)
+            .await
+        {
+            warn!(context, "Cannot set ephemeral timer: {}", err);
+        }
+        let better_msg =
+            ephemeral::stock_ephemeral_timer_changed(context, ephemeral_timer, from_id).await;
+        set_better_msg(mime_parser, &better_msg);
+    }
+
     // if the mime-headers should be saved, find out its size
     // (the mime-header ends with an empty line)
     let save_mime_headers = context.get_config_bool(Config::SaveMimeHeaders).await;
@@ -645,10 +793,68 @@ async fn add_parts(
         None
     };
     let sent_timestamp = *sent_timestamp;
-    let is_hidden = *hidden;
+    let mut is_hidden = *hidden;
     let chat_id = *chat_id;
     let is_mdn = !mime_parser.reports.is_empty();
 
+    if is_system_message == SystemMessage::Reaction
+        || is_system_message == SystemMessage::MessageEdit
+        || is_system_message == SystemMessage::AppUpdate
+        || is_system_message == SystemMessage::PollVote
+    {
+        is_hidden = true;
+        if state == MessageState::InFresh {
+            state = MessageState::InNoticed;
+        }
+    } else if let Some(flag) = is_system_message.show_flag() {
+        let shown = ShowSystemMessages::from_bits_truncate(
+            context.get_config_int(Config::ShowSystemMessages).await,
+        );
+        if !shown.contains(flag) {
+            is_hidden = true;
+            if state == MessageState::InFresh {
+                state = MessageState::InNoticed;
+            }
+        }
+    }
+
+    // the full message keeps the ID and the seen state of the placeholder
+    let replace_msg_id = if let Some(old_msg) = replace_msg {
+        if state == MessageState::InFresh {
+            state = old_msg.state;
+        }
+        Some(old_msg.id)
+    } else {
+        None
+    };
+    let download_state = if is_partial_download.is_some() {
+        DownloadState::Available
+    } else {
+        DownloadState::Done
+    };
+
+    // the timer of incoming messages starts when they are seen
+    let ephemeral_timestamp = if ephemeral_timer != 0
+        && (from_id == DC_CONTACT_ID_SELF || state == MessageState::InSeen)
+    {
+        time() + ephemeral_timer as i64
+    } else {
+        0
+    };
+
+    for part in &parts {
+        if let Err(err) = blob_crypt::encrypt_msg_blobs(context, &part.param).await {
+            warn!(context, "Cannot encrypt attachment: {}", err);
+        }
+    }
+
+    // the HTML is stored with the first text part only
+    let mut html = if context.get_config_bool(Config::SaveHtml).await {
+        mime_parser.html.take()
+    } else {
+        None
+    };
+
     // TODO: can this clone be avoided?
     let rfc724_mid = rfc724_mid.to_string();
 
@@ -657,15 +863,22 @@ async fn add_parts(
         .with_conn(move |mut conn| {
             let mut ids = Vec::with_capacity(parts.len());
             let mut is_hidden = is_hidden;
+            let mut replace_msg_id = replace_msg_id;
+
+            if let Some(replace_msg_id) = replace_msg_id {
+                conn.execute("DELETE FROM msgs WHERE id=?;", paramsv![replace_msg_id])?;
+            }
 
             for part in &mut parts {
                 let mut txt_raw = "".to_string();
+                let mut part_html = None;
                 let mut stmt = conn.prepare_cached(
                     "INSERT INTO msgs \
-         (rfc724_mid, server_folder, server_uid, chat_id, from_id, to_id, timestamp, \
+         (id, rfc724_mid, server_folder, server_uid, chat_id, from_id, to_id, timestamp, \
          timestamp_sent, timestamp_rcvd, type, state, msgrmsg,  txt, txt_raw, param, \
-         bytes, hidden, mime_headers,  mime_in_reply_to, mime_references) \
-         VALUES (?,?,?,?,?,?, ?,?,?,?,?,?, ?,?,?,?,?,?, ?,?);",
+         bytes, hidden, mime_headers,  mime_in_reply_to, mime_references, download_state, \
+         mime_html, ephemeral_timer, ephemeral_timestamp) \
+         VALUES (?,?,?,?,?,?,?, ?,?,?,?,?,?, ?,?,?,?,?,?, ?,?,?,?, ?,?);",
                 )?;
 
                 let is_location_kml = location_kml_is
@@ -682,12 +895,19 @@ async fn add_parts(
                 if part.typ == Viewtype::Text {
                     let msg_raw = part.msg_raw.as_ref().cloned().unwrap_or_default();
                     txt_raw = format!("{}\n\n{}", subject, msg_raw);
+                    part_html = html.take();
+                    if part_html.is_some() {
+                        part.param.set_int(Param::HasHtml, 1);
+                    }
                 }
                 if is_system_message != SystemMessage::Unknown {
                     part.param.set_int(Param::Cmd, is_system_message as i32);
                 }
 
+                // the first part takes the place of the replaced message
+                let part_msg_id = replace_msg_id.take();
                 stmt.execute(paramsv![
+                    part_msg_id,
                     rfc724_mid,
                     server_folder,
                     server_uid as i32,
@@ -709,6 +929,10 @@ async fn add_parts(
                     mime_headers,
                     mime_in_reply_to,
                     mime_references,
+                    download_state,
+                    part_html.unwrap_or_default(),
+                    ephemeral_timer,
+                    ephemeral_timestamp,
                 ])?;
 
                 drop(stmt);
@@ -728,6 +952,9 @@ async fn add_parts(
     }
 
     *hidden = is_hidden;
+    if ephemeral_timestamp != 0 {
+        ephemeral::schedule_job(context).await;
+    }
     created_db_entries.extend(ids.iter().map(|id| (chat_id, *id)));
     mime_parser.parts = new_parts;
 
@@ -736,6 +963,30 @@ async fn add_parts(
         "Message has {} parts and is assigned to chat #{}.", icnt, chat_id,
     );
 
+    if is_system_message == SystemMessage::Reaction {
+        if let Err(err) =
+            reaction::receive_reaction(context, mime_parser, from_id, sent_timestamp).await
+        {
+            warn!(context, "Cannot apply reaction: {}", err);
+        }
+    } else if is_system_message == SystemMessage::MessageEdit {
+        if let Err(err) = apply_edit(context, mime_parser, from_id).await {
+            warn!(context, "Cannot apply edit: {}", err);
+        }
+    } else if is_system_message == SystemMessage::AppUpdate {
+        if let Err(err) = app::receive_app_update(context, mime_parser, from_id).await {
+            warn!(context, "Cannot apply app update: {}", err);
+        }
+    } else if is_system_message == SystemMessage::PollVote {
+        if let Err(err) = poll::receive_poll_vote(context, mime_parser, from_id).await {
+            warn!(context, "Cannot apply vote: {}", err);
+        }
+    } else if is_system_message == SystemMessage::MultiDeviceSync {
+        if let Err(err) = sync::receive_sync_items(context, mime_parser, from_id).await {
+            warn!(context, "Cannot apply sync message: {}", err);
+        }
+    }
+
     // check event to send
     if chat_id.is_trash() || *hidden {
         *create_event_to_send = None;
@@ -745,9 +996,14 @@ async fn add_parts(
         } else if Blocked::Not != chat_id_blocked {
             *create_event_to_send = Some(CreateEvent::MsgsChanged);
         } else {
-            *create_event_to_send = Some(CreateEvent::IncomingMsg);
+            let muted = chat_id.is_muted(context).await;
+            *create_event_to_send = Some(CreateEvent::IncomingMsg { muted });
         }
     }
+    if replace_msg_id.is_some() && create_event_to_send.is_some() {
+        // the placeholder was already notified
+        *create_event_to_send = Some(CreateEvent::MsgsChanged);
+    }
 
     async fn update_last_subject(
         context: &Context,
@@ -777,6 +1033,38 @@ async fn add_parts(
     Ok(())
 }
 
+/// Applies a received edit to the message it refers to.
+///
+/// Edits of unknown messages and edits not sent by the author
+/// of the original message are ignored.
+async fn apply_edit(context: &Context, mime_parser: &MimeMessage, from_id: u32) -> Result<()> {
+    let rfc724_mid = match mime_parser.get(HeaderDef::ChatEdit) {
+        Some(value) => parse_message_id(value)?,
+        None => bail!("Edit without Chat-Edit header"),
+    };
+    let text = mime_parser
+        .parts
+        .first()
+        .map(|part| part.msg.clone())
+        .unwrap_or_default();
+
+    match message::rfc724_mid_exists(context, &rfc724_mid).await? {
+        Some((_, _, msg_id)) => {
+            let mut msg = Message::load_from_db(context, msg_id).await?;
+            ensure!(
+                msg.from_id == from_id,
+                "Edit of message {} not sent by its author",
+                msg_id
+            );
+            msg.apply_edit(context, text).await
+        }
+        None => {
+            info!(context, "Ignoring edit of unknown message {}", rfc724_mid);
+            Ok(())
+        }
+    }
+}
+
 async fn save_locations(
     context: &Context,
     mime_parser: &MimeMessage,
@@ -1013,6 +1301,20 @@ async fn create_or_lookup_group(
                             )
                             .await
                     }
+                } else if value == "group-admin-changed" {
+                    if let Some(addr) = mime_parser.get(HeaderDef::ChatGroupAdminAdded).cloned() {
+                        mime_parser.is_system_message = SystemMessage::GroupAdminAdded;
+                        better_msg = context
+                            .stock_system_msg(StockMessage::MsgAdminAdded, &addr, "", from_id)
+                            .await;
+                    } else if let Some(addr) =
+                        mime_parser.get(HeaderDef::ChatGroupAdminRemoved).cloned()
+                    {
+                        mime_parser.is_system_message = SystemMessage::GroupAdminRemoved;
+                        better_msg = context
+                            .stock_system_msg(StockMessage::MsgAdminRemoved, &addr, "", from_id)
+                            .await;
+                    }
                 }
             }
         }
@@ -1128,10 +1430,26 @@ async fn create_or_lookup_group(
 
     // XXX insert code in a different PR :)
 
-    // execute group commands
+    // execute group commands;
+    // changes by contacts not allowed to administrate the group are ignored,
+    // however, everyone may leave the group
+    let may_administrate = chat::may_administrate(context, chat_id, from_id).await;
+    if !may_administrate
+        && (X_MrAddToGrp.is_some()
+            || X_MrGrpNameChanged
+            || mime_parser.group_avatar.is_some()
+            || removed_id > 0 && removed_id != from_id)
+    {
+        warn!(
+            context,
+            "contact {} is not allowed to change group {}", from_id, chat_id
+        );
+    }
     if X_MrAddToGrp.is_some() {
-        recreate_member_list = true;
-    } else if X_MrGrpNameChanged {
+        if may_administrate {
+            recreate_member_list = true;
+        }
+    } else if X_MrGrpNameChanged && may_administrate {
         if let Some(ref grpname) = grpname {
             if grpname.len() < 200 {
                 info!(context, "updating grpname for chat {}", chat_id);
@@ -1149,19 +1467,27 @@ async fn create_or_lookup_group(
             }
         }
     }
-    if let Some(avatar_action) = &mime_parser.group_avatar {
+    if let Some(avatar_action) = mime_parser
+        .group_avatar
+        .as_ref()
+        .filter(|_| may_administrate)
+    {
         info!(context, "group-avatar change for {}", chat_id);
         if let Ok(mut chat) = Chat::load_from_db(context, chat_id).await {
-            match avatar_action {
+            let changed = match avatar_action {
                 AvatarAction::Change(profile_image) => {
-                    chat.param.set(Param::ProfileImage, profile_image);
+                    set_group_image(context, &mut chat, profile_image).await
                 }
                 AvatarAction::Delete => {
+                    let had_image = chat.param.exists(Param::ProfileImage);
                     chat.param.remove(Param::ProfileImage);
+                    had_image
                 }
             };
-            chat.update_param(context).await?;
-            send_EVENT_CHAT_MODIFIED = true;
+            if changed {
+                chat.update_param(context).await?;
+                context.emit_event(Event::ChatImageChanged(chat_id));
+            }
         }
     }
 
@@ -1197,17 +1523,79 @@ async fn create_or_lookup_group(
             }
         }
         send_EVENT_CHAT_MODIFIED = true;
-    } else if removed_id > 0 {
+    } else if removed_id > 0 && (may_administrate || removed_id == from_id) {
         chat::remove_from_chat_contacts_table(context, chat_id, removed_id).await;
         send_EVENT_CHAT_MODIFIED = true;
     }
 
+    // update admins after the member list is complete
+    if apply_admin_changes(context, mime_parser, chat_id, from_id).await {
+        send_EVENT_CHAT_MODIFIED = true;
+    }
+
     if send_EVENT_CHAT_MODIFIED {
         context.emit_event(Event::ChatModified(chat_id));
     }
     Ok((chat_id, chat_id_blocked))
 }
 
+/// Sets a received group image, returns false if the image did not change.
+async fn set_group_image(context: &Context, chat: &mut Chat, profile_image: &str) -> bool {
+    let current = chat.param.get(Param::ProfileImage).map(|s| s.to_string());
+    match BlobObject::from_received_avatar(context, profile_image, current.as_deref()).await {
+        Some(image_blob) => {
+            chat.param.set(Param::ProfileImage, image_blob.as_name());
+            true
+        }
+        None => false,
+    }
+}
+
+/// Applies the `Chat-Group-Admin-Added` and `Chat-Group-Admin-Removed`
+/// headers to the members of a group.
+///
+/// The changes are ignored if the sender is not allowed to change the
+/// admins.  Returns true if the admins were updated.
+async fn apply_admin_changes(
+    context: &Context,
+    mime_parser: &MimeMessage,
+    chat_id: ChatId,
+    from_id: u32,
+) -> bool {
+    let added = mime_parser.get(HeaderDef::ChatGroupAdminAdded);
+    let removed = mime_parser.get(HeaderDef::ChatGroupAdminRemoved);
+    if added.is_none() && removed.is_none() {
+        return false;
+    }
+    if !chat::may_administrate(context, chat_id, from_id).await {
+        warn!(
+            context,
+            "contact {} is not allowed to change the admins of {}", from_id, chat_id
+        );
+        return false;
+    }
+
+    let changes = added
+        .into_iter()
+        .map(|addrs| (addrs, true))
+        .chain(removed.into_iter().map(|addrs| (addrs, false)));
+    for (addrs, is_admin) in changes {
+        for addr in addrs
+            .split(',')
+            .map(str::trim)
+            .filter(|addr| !addr.is_empty())
+        {
+            let contact_id = Contact::lookup_id_by_addr(context, addr, Origin::Unknown).await;
+            if contact_id == 0 {
+                warn!(context, "admin {:?} has no contact_id", addr);
+            } else {
+                chat::set_admin_flag(context, chat_id, contact_id, is_admin).await;
+            }
+        }
+    }
+    true
+}
+
 /// try extract a grpid from a message-id list header value
 fn extract_grpid(mime_parser: &MimeMessage, headerdef: HeaderDef) -> Option<&str> {
     let header = mime_parser.get(headerdef)?;
@@ -1218,6 +1606,119 @@ fn extract_grpid(mime_parser: &MimeMessage, headerdef: HeaderDef) -> Option<&str
     parts.filter_map(dc_extract_grpid_from_rfc724_mid).next()
 }
 
+/// Creates or looks up the chat of the mailing list announced by `list_id_header`.
+///
+/// New mailing lists end up in the deaddrop until the user accepts them.
+/// The `List-Post:` address is remembered to answer to the list.
+async fn create_or_lookup_mailinglist(
+    context: &Context,
+    list_id_header: &str,
+    mime_parser: &MimeMessage,
+) -> (ChatId, Blocked) {
+    let (listname, listid) = parse_list_id(list_id_header);
+
+    // only mailing lists are looked up, a List-Id equal to the ID
+    // of a group must not add messages to the group.
+    let existing = context
+        .sql
+        .query_row_optional(
+            "SELECT id, blocked FROM chats WHERE grpid=? AND type=?;",
+            paramsv![listid, Chattype::Mailinglist],
+            |row| {
+                let chat_id = row.get::<_, ChatId>(0)?;
+                let blocked = row.get::<_, Option<Blocked>>(1)?.unwrap_or_default();
+                Ok((chat_id, blocked))
+            },
+        )
+        .await;
+    let (chat_id, blocked) = match existing {
+        Ok(Some((chat_id, blocked))) => (chat_id, blocked),
+        Err(err) => {
+            warn!(context, "Cannot look up mailing list {}: {}", listid, err);
+            return (ChatId::new(0), Blocked::Not);
+        }
+        Ok(None) => {
+            if let Err(err) = context
+                .sql
+                .execute(
+                    "INSERT INTO chats (type, name, grpid, blocked, created_timestamp) VALUES(?, ?, ?, ?, ?);",
+                    paramsv![Chattype::Mailinglist, listname, listid, Blocked::Deaddrop, time()],
+                )
+                .await
+            {
+                warn!(
+                    context,
+                    "Failed to create mailing list '{}' for listid={}: {}", listname, listid, err
+                );
+                return (ChatId::new(0), Blocked::Not);
+            }
+            let chat_id = ChatId::new(
+                context
+                    .sql
+                    .get_rowid(context, "chats", "grpid", &listid)
+                    .await
+                    .unwrap_or_default(),
+            );
+            info!(
+                context,
+                "Created mailing list '{}' listid={} as {}", listname, listid, chat_id
+            );
+            (chat_id, Blocked::Deaddrop)
+        }
+    };
+
+    if let Some(list_post) = mime_parser
+        .get(HeaderDef::ListPost)
+        .and_then(|value| parse_list_post(value))
+    {
+        if let Ok(mut chat) = Chat::load_from_db(context, chat_id).await {
+            if chat.param.get(Param::ListPost) != Some(list_post.as_str()) {
+                chat.param.set(Param::ListPost, list_post);
+                if let Err(err) = chat.update_param(context).await {
+                    warn!(context, "Cannot update List-Post of {}: {}", chat_id, err);
+                }
+            }
+        }
+    }
+
+    (chat_id, blocked)
+}
+
+/// Splits a `List-Id:` header as `Name <list.example.org>` into name and ID.
+///
+/// The ID is used as name if there is no name.
+fn parse_list_id(value: &str) -> (String, String) {
+    let value = value.trim();
+    match (value.rfind('<'), value.rfind('>')) {
+        (Some(start), Some(end)) if start < end => {
+            let listid = value[start + 1..end].trim().to_string();
+            let listname = value[..start].trim().trim_matches('"').trim();
+            if listname.is_empty() {
+                (listid.clone(), listid)
+            } else {
+                (listname.to_string(), listid)
+            }
+        }
+        _ => (value.to_string(), value.to_string()),
+    }
+}
+
+/// Returns the address of a `List-Post:` header as `<mailto:list@example.org>`.
+///
+/// Returns `None` if posting is not allowed or not possible by email.
+fn parse_list_post(value: &str) -> Option<String> {
+    let start = value.to_ascii_lowercase().find("mailto:")? + "mailto:".len();
+    let addr: String = value[start..]
+        .chars()
+        .take_while(|c| *c != '>' && *c != '?' && !c.is_whitespace())
+        .collect();
+    if may_be_valid_addr(&addr) {
+        Some(addr)
+    } else {
+        None
+    }
+}
+
 /// Handle groups for received messages, return chat_id/Blocked status on success
 async fn create_or_lookup_adhoc_group(
     context: &Context,
@@ -1598,6 +2099,13 @@ async fn check_verified_properties(
                             PeerstateVerifiedStatus::BidirectVerified,
                         );
                         peerstate.save_to_db(&context.sql, false).await?;
+                        peerstate
+                            .add_verification_record(
+                                VerificationEvent::GroupIntroduction,
+                                &fp,
+                                Some(contact.get_addr()),
+                            )
+                            .await?;
                         is_verified = true;
                     }
                 }
@@ -1779,7 +2287,7 @@ mod tests {
     use super::*;
     use crate::chat::ChatVisibility;
     use crate::chatlist::Chatlist;
-    use crate::message::Message;
+    use crate::mimefactory::MimeFactory;
     use crate::test_utils::{dummy_context, TestContext};
 
     #[test]
@@ -2264,27 +2772,20 @@ mod tests {
     }
 
     #[async_std::test]
-    async fn test_escaped_recipients() {
+    async fn test_contact_last_seen() {
         let t = configured_offline_context().await;
-        Contact::create(&t.ctx, "foobar", "foobar@example.com")
+        let contact_id = Contact::create(&t.ctx, "", "bob@example.net")
             .await
             .unwrap();
-
-        let carl_contact_id =
-            Contact::add_or_lookup(&t.ctx, "Carl", "carl@host.tld", Origin::IncomingUnknownFrom)
-                .await
-                .unwrap()
-                .0;
+        let contact = Contact::load_from_db(&t.ctx, contact_id).await.unwrap();
+        assert_eq!(contact.last_seen(), 0);
 
         dc_receive_imf(
             &t.ctx,
-            b"From: Foobar <foobar@example.com>\n\
-                 To: =?UTF-8?B?0JjQvNGPLCDQpNCw0LzQuNC70LjRjw==?= alice@example.org\n\
-                 Cc: =?utf-8?q?=3Ch2=3E?= <carl@host.tld>\n\
+            b"From: bob@example.net\n\
+                 To: alice@example.org\n\
                  Subject: foo\n\
-                 Message-ID: <asdklfjjaweofi@example.org>\n\
-                 Chat-Version: 1.0\n\
-                 Chat-Disposition-Notification-To: <foobar@example.com>\n\
+                 Message-ID: <last-seen-1@example.net>\n\
                  Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
                  \n\
                  hello\n",
@@ -2294,31 +2795,156 @@ mod tests {
         )
         .await
         .unwrap();
-        assert_eq!(
-            Contact::load_from_db(&t.ctx, carl_contact_id)
-                .await
-                .unwrap()
-                .get_name(),
-            "h2"
-        );
+        let contact = Contact::load_from_db(&t.ctx, contact_id).await.unwrap();
+        assert_eq!(contact.last_seen(), 1_584_916_677);
 
-        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
-        let msg = Message::load_from_db(&t.ctx, chats.get_msg_id(0).unwrap())
-            .await
-            .unwrap();
-        assert_eq!(msg.is_dc_message, MessengerMessage::Yes);
-        assert_eq!(msg.text.unwrap(), "hello");
-        assert_eq!(msg.param.get_int(Param::WantsMdn).unwrap(), 1);
+        // an older message does not move the timestamp back
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.net\n\
+                 To: alice@example.org\n\
+                 Subject: foo\n\
+                 Message-ID: <last-seen-2@example.net>\n\
+                 Date: Sat, 21 Mar 2020 22:37:57 +0000\n\
+                 \n\
+                 hello again\n",
+            "INBOX",
+            2,
+            false,
+        )
+        .await
+        .unwrap();
+        let contact = Contact::load_from_db(&t.ctx, contact_id).await.unwrap();
+        assert_eq!(contact.last_seen(), 1_584_916_677);
     }
 
     #[async_std::test]
-    async fn test_cc_to_contact() {
+    async fn test_contact_status() {
         let t = configured_offline_context().await;
-        Contact::create(&t.ctx, "foobar", "foobar@example.com")
+        let contact_id = Contact::create(&t.ctx, "", "bob@example.net")
             .await
             .unwrap();
 
-        let carl_contact_id = Contact::add_or_lookup(
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.net\n\
+                 To: alice@example.org\n\
+                 Subject: foo\n\
+                 Message-ID: <status-1@example.net>\n\
+                 Chat-Version: 1.0\n\
+                 Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                 \n\
+                 hello\n\
+                 -- \n\
+                 Bob's status\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        let contact = Contact::load_from_db(&t.ctx, contact_id).await.unwrap();
+        assert_eq!(contact.get_status(), "Bob's status");
+
+        // the footer of an older message does not change the status
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.net\n\
+                 To: alice@example.org\n\
+                 Subject: foo\n\
+                 Message-ID: <status-2@example.net>\n\
+                 Chat-Version: 1.0\n\
+                 Date: Sat, 21 Mar 2020 22:37:57 +0000\n\
+                 \n\
+                 hello again\n\
+                 -- \n\
+                 Bob's old status\n",
+            "INBOX",
+            2,
+            false,
+        )
+        .await
+        .unwrap();
+        let contact = Contact::load_from_db(&t.ctx, contact_id).await.unwrap();
+        assert_eq!(contact.get_status(), "Bob's status");
+
+        // a newer chat message without footer removes the status
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.net\n\
+                 To: alice@example.org\n\
+                 Subject: foo\n\
+                 Message-ID: <status-3@example.net>\n\
+                 Chat-Version: 1.0\n\
+                 Date: Mon, 23 Mar 2020 22:37:57 +0000\n\
+                 \n\
+                 hello without status\n",
+            "INBOX",
+            3,
+            false,
+        )
+        .await
+        .unwrap();
+        let contact = Contact::load_from_db(&t.ctx, contact_id).await.unwrap();
+        assert_eq!(contact.get_status(), "");
+    }
+
+    #[async_std::test]
+    async fn test_escaped_recipients() {
+        let t = configured_offline_context().await;
+        Contact::create(&t.ctx, "foobar", "foobar@example.com")
+            .await
+            .unwrap();
+
+        let carl_contact_id =
+            Contact::add_or_lookup(&t.ctx, "Carl", "carl@host.tld", Origin::IncomingUnknownFrom)
+                .await
+                .unwrap()
+                .0;
+
+        dc_receive_imf(
+            &t.ctx,
+            b"From: Foobar <foobar@example.com>\n\
+                 To: =?UTF-8?B?0JjQvNGPLCDQpNCw0LzQuNC70LjRjw==?= alice@example.org\n\
+                 Cc: =?utf-8?q?=3Ch2=3E?= <carl@host.tld>\n\
+                 Subject: foo\n\
+                 Message-ID: <asdklfjjaweofi@example.org>\n\
+                 Chat-Version: 1.0\n\
+                 Chat-Disposition-Notification-To: <foobar@example.com>\n\
+                 Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                 \n\
+                 hello\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            Contact::load_from_db(&t.ctx, carl_contact_id)
+                .await
+                .unwrap()
+                .get_name(),
+            "h2"
+        );
+
+        let chats = Chatlist::try_load(&t.ctx, 0, None, None).await.unwrap();
+        let msg = Message::load_from_db(&t.ctx, chats.get_msg_id(0).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(msg.is_dc_message, MessengerMessage::Yes);
+        assert_eq!(msg.text.unwrap(), "hello");
+        assert_eq!(msg.param.get_int(Param::WantsMdn).unwrap(), 1);
+    }
+
+    #[async_std::test]
+    async fn test_cc_to_contact() {
+        let t = configured_offline_context().await;
+        Contact::create(&t.ctx, "foobar", "foobar@example.com")
+            .await
+            .unwrap();
+
+        let carl_contact_id = Contact::add_or_lookup(
             &t.ctx,
             "garabage",
             "carl@host.tld",
@@ -2354,4 +2980,648 @@ mod tests {
             "Carl"
         );
     }
+
+    async fn receive_member_added(t: &TestContext) -> ChatId {
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.org\n\
+              To: alice@example.org, claire@example.org\n\
+              Subject: foo\n\
+              Message-ID: <Gr.abcde12345.1@example.org>\n\
+              Chat-Version: 1.0\n\
+              Chat-Group-ID: abcde12345\n\
+              Chat-Group-Name: foo\n\
+              Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+              \n\
+              hello\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.org\n\
+              To: alice@example.org, claire@example.org, dave@example.org\n\
+              Subject: foo\n\
+              Message-ID: <Gr.abcde12345.2@example.org>\n\
+              Chat-Version: 1.0\n\
+              Chat-Group-ID: abcde12345\n\
+              Chat-Group-Name: foo\n\
+              Chat-Group-Member-Added: dave@example.org\n\
+              Date: Sun, 22 Mar 2020 22:38:57 +0000\n\
+              \n\
+              dave added\n",
+            "INBOX",
+            2,
+            false,
+        )
+        .await
+        .unwrap();
+        let (chat_id, _, _) = chat::get_chat_id_by_grpid(&t.ctx, "abcde12345")
+            .await
+            .unwrap();
+        chat_id
+    }
+
+    #[async_std::test]
+    async fn test_show_system_messages_default() {
+        let t = configured_offline_context().await;
+        let chat_id = receive_member_added(&t).await;
+
+        let msgs = chat::get_chat_msgs(&t.ctx, chat_id, 0, None).await;
+        assert_eq!(msgs.len(), 2);
+        let msg = Message::load_from_db(&t.ctx, msgs[1]).await.unwrap();
+        assert!(msg.is_info());
+        assert!(!msg.hidden);
+    }
+
+    #[async_std::test]
+    async fn test_show_system_messages_member_added_hidden() {
+        let t = configured_offline_context().await;
+        let shown = ShowSystemMessages::all() - ShowSystemMessages::MEMBER_ADDED;
+        t.ctx
+            .set_config(Config::ShowSystemMessages, Some(&shown.bits().to_string()))
+            .await
+            .unwrap();
+        let chat_id = receive_member_added(&t).await;
+
+        let msgs = chat::get_chat_msgs(&t.ctx, chat_id, 0, None).await;
+        assert_eq!(msgs.len(), 1);
+        let msg = Message::load_from_db(&t.ctx, msgs[0]).await.unwrap();
+        assert!(!msg.is_info());
+
+        // the member is added nevertheless
+        let contacts = chat::get_chat_contacts(&t.ctx, chat_id).await;
+        assert_eq!(contacts.len(), 4);
+
+        // re-enabling does not reveal messages received before
+        t.ctx
+            .set_config(Config::ShowSystemMessages, None)
+            .await
+            .unwrap();
+        let msgs = chat::get_chat_msgs(&t.ctx, chat_id, 0, None).await;
+        assert_eq!(msgs.len(), 1);
+    }
+
+    #[async_std::test]
+    async fn test_receive_edit() {
+        let t = configured_offline_context().await;
+        dc_receive_imf(&t.ctx, MSGRMSG, "INBOX", 1, false)
+            .await
+            .unwrap();
+        let msg_id = message::rfc724_mid_exists(&t.ctx, "Mr.1111@example.org")
+            .await
+            .unwrap()
+            .unwrap()
+            .2;
+
+        // edits by other contacts are ignored
+        dc_receive_imf(
+            &t.ctx,
+            b"From: Claire <claire@example.org>\n\
+              To: alice@example.org\n\
+              Chat-Version: 1.0\n\
+              Subject: Chat: hello\n\
+              Message-ID: <Mr.2222@example.org>\n\
+              Chat-Edit: <Mr.1111@example.org>\n\
+              Date: Sun, 22 Mar 2020 22:38:55 +0000\n\
+              \n\
+              spoofed\n",
+            "INBOX",
+            2,
+            false,
+        )
+        .await
+        .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert!(!msg.is_edited());
+        assert_eq!(msg.get_text().unwrap(), "hello");
+
+        dc_receive_imf(
+            &t.ctx,
+            b"From: Bob <bob@example.org>\n\
+              To: alice@example.org\n\
+              Chat-Version: 1.0\n\
+              Subject: Chat: hello\n\
+              Message-ID: <Mr.3333@example.org>\n\
+              Chat-Edit: <Mr.1111@example.org>\n\
+              Date: Sun, 22 Mar 2020 22:39:55 +0000\n\
+              \n\
+              hello, world\n",
+            "INBOX",
+            3,
+            false,
+        )
+        .await
+        .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert!(msg.is_edited());
+        assert_eq!(msg.get_text().unwrap(), "hello, world");
+        assert_eq!(msg.get_original_text().unwrap(), "hello");
+        assert_eq!(
+            chat::get_chat_msgs(&t.ctx, msg.chat_id, 0, None).await,
+            vec![msg_id]
+        );
+    }
+
+    #[async_std::test]
+    async fn test_receive_edit_multiline() {
+        let t = configured_offline_context().await;
+        dc_receive_imf(
+            &t.ctx,
+            b"From: Bob <bob@example.org>\n\
+              To: alice@example.org\n\
+              Chat-Version: 1.0\n\
+              Subject: Chat: hello\n\
+              Message-ID: <Mr.1111@example.org>\n\
+              Date: Sun, 22 Mar 2020 22:37:55 +0000\n\
+              \n\
+              hello\n\
+              f=/etc/passwd\n\
+              x=1\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        dc_receive_imf(
+            &t.ctx,
+            b"From: Bob <bob@example.org>\n\
+              To: alice@example.org\n\
+              Chat-Version: 1.0\n\
+              Subject: Chat: hello\n\
+              Message-ID: <Mr.2222@example.org>\n\
+              Chat-Edit: <Mr.1111@example.org>\n\
+              Date: Sun, 22 Mar 2020 22:38:55 +0000\n\
+              \n\
+              hello\n\
+              w=100\n",
+            "INBOX",
+            2,
+            false,
+        )
+        .await
+        .unwrap();
+
+        let (_, _, msg_id) = message::rfc724_mid_exists(&t.ctx, "Mr.1111@example.org")
+            .await
+            .unwrap()
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert!(msg.is_edited());
+        assert_eq!(msg.get_text().unwrap(), "hello\nw=100");
+        assert_eq!(
+            msg.get_original_text().unwrap(),
+            "hello\nf=/etc/passwd\nx=1"
+        );
+        assert!(msg.param.get(Param::File).is_none());
+        assert!(msg.param.get(Param::Width).is_none());
+    }
+
+    #[async_std::test]
+    async fn test_receive_quote() {
+        let t = configured_offline_context().await;
+        dc_receive_imf(&t.ctx, MSGRMSG, "INBOX", 1, false)
+            .await
+            .unwrap();
+        dc_receive_imf(
+            &t.ctx,
+            b"From: Bob <bob@example.org>\n\
+              To: alice@example.org\n\
+              Chat-Version: 1.0\n\
+              Subject: Chat: hello\n\
+              Message-ID: <Mr.2222@example.org>\n\
+              In-Reply-To: <Mr.1111@example.org>\n\
+              Date: Sun, 22 Mar 2020 22:38:55 +0000\n\
+              \n\
+              > hello\n\
+              \n\
+              hi there\n",
+            "INBOX",
+            2,
+            false,
+        )
+        .await
+        .unwrap();
+
+        let (_, _, quoted_id) = message::rfc724_mid_exists(&t.ctx, "Mr.1111@example.org")
+            .await
+            .unwrap()
+            .unwrap();
+        let (_, _, msg_id) = message::rfc724_mid_exists(&t.ctx, "Mr.2222@example.org")
+            .await
+            .unwrap()
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_text().unwrap(), "hi there");
+        assert_eq!(msg.quoted_text().unwrap(), "hello");
+        let quoted = msg.quoted_message(&t.ctx).await.unwrap().unwrap();
+        assert_eq!(quoted.id, quoted_id);
+
+        let msg = Message::load_from_db(&t.ctx, quoted_id).await.unwrap();
+        assert!(msg.quoted_text().is_none());
+        assert!(msg.quoted_message(&t.ctx).await.unwrap().is_none());
+    }
+
+    #[async_std::test]
+    async fn test_receive_multiline_quote() {
+        let t = configured_offline_context().await;
+        dc_receive_imf(
+            &t.ctx,
+            b"From: Bob <bob@example.org>\n\
+              To: alice@example.org\n\
+              Chat-Version: 1.0\n\
+              Subject: Chat: hello\n\
+              Message-ID: <Mr.2222@example.org>\n\
+              In-Reply-To: <Mr.1111@example.org>\n\
+              Date: Sun, 22 Mar 2020 22:38:55 +0000\n\
+              \n\
+              > hello\n\
+              > f=/etc/passwd\n\
+              > x=1\n\
+              \n\
+              hi there\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+
+        let (_, _, msg_id) = message::rfc724_mid_exists(&t.ctx, "Mr.2222@example.org")
+            .await
+            .unwrap()
+            .unwrap();
+        let mut msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.quoted_text().unwrap(), "hello\nf=/etc/passwd\nx=1");
+        assert!(msg.param.get(Param::File).is_none());
+        assert_eq!(msg.get_viewtype(), Viewtype::Text);
+
+        // the quote survives saving the params again
+        msg.save_param_to_disk(&t.ctx).await;
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.quoted_text().unwrap(), "hello\nf=/etc/passwd\nx=1");
+        assert!(msg.param.get(Param::File).is_none());
+    }
+
+    #[async_std::test]
+    async fn test_receive_html() {
+        let t = configured_offline_context().await;
+        t.ctx
+            .set_config(
+                Config::ShowEmails,
+                Some(&(ShowEmails::All as i32).to_string()),
+            )
+            .await
+            .unwrap();
+        t.ctx.set_config(Config::SaveHtml, Some("1")).await.unwrap();
+        dc_receive_imf(
+            &t.ctx,
+            b"From: Newsletter <news@example.org>\n\
+              To: alice@example.org\n\
+              Subject: News\n\
+              Message-ID: <news1@example.org>\n\
+              Date: Sun, 22 Mar 2020 22:37:55 +0000\n\
+              Content-Type: multipart/alternative; boundary=\"==BREAK==\"\n\
+              \n\
+              --==BREAK==\n\
+              Content-Type: text/plain; charset=utf-8\n\
+              \n\
+              plain news\n\
+              --==BREAK==\n\
+              Content-Type: text/html; charset=utf-8\n\
+              \n\
+              <p onclick=\"x()\">html <b>news</b><script>x()</script></p>\n\
+              --==BREAK==--\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+
+        let (_, _, msg_id) = message::rfc724_mid_exists(&t.ctx, "news1@example.org")
+            .await
+            .unwrap()
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_text().unwrap(), "plain news");
+        assert!(msg.has_html());
+        assert_eq!(
+            msg_id.get_html(&t.ctx).await.unwrap().trim(),
+            "<p>html <b>news</b></p>"
+        );
+
+        dc_receive_imf(&t.ctx, MSGRMSG, "INBOX", 2, false)
+            .await
+            .unwrap();
+        let (_, _, msg_id) = message::rfc724_mid_exists(&t.ctx, "Mr.1111@example.org")
+            .await
+            .unwrap()
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert!(!msg.has_html());
+        assert_eq!(msg_id.get_html(&t.ctx).await, None);
+
+        // HTML is not saved by default
+        t.ctx.set_config(Config::SaveHtml, None).await.unwrap();
+        dc_receive_imf(
+            &t.ctx,
+            b"From: Newsletter <news@example.org>\n\
+              To: alice@example.org\n\
+              Subject: News\n\
+              Message-ID: <news2@example.org>\n\
+              Date: Sun, 22 Mar 2020 22:39:55 +0000\n\
+              Content-Type: text/html; charset=utf-8\n\
+              \n\
+              <p>more <b>news</b></p>\n",
+            "INBOX",
+            3,
+            false,
+        )
+        .await
+        .unwrap();
+        let (_, _, msg_id) = message::rfc724_mid_exists(&t.ctx, "news2@example.org")
+            .await
+            .unwrap()
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_text().unwrap(), "more *news*");
+        assert!(!msg.has_html());
+    }
+
+    #[async_std::test]
+    async fn test_group_admins() {
+        let t = configured_offline_context().await;
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.org\n\
+              To: alice@example.org, claire@example.org\n\
+              Subject: foo\n\
+              Message-ID: <Gr.abcde12345.1@example.org>\n\
+              Chat-Version: 1.0\n\
+              Chat-Group-ID: abcde12345\n\
+              Chat-Group-Name: foo\n\
+              Chat-Group-Member-Added: alice@example.org\n\
+              Chat-Group-Admin-Added: bob@example.org\n\
+              Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+              \n\
+              alice added\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        let (chat_id, _, _) = chat::get_chat_id_by_grpid(&t.ctx, "abcde12345")
+            .await
+            .unwrap();
+        let bob = Contact::lookup_id_by_addr(&t.ctx, "bob@example.org", Origin::Unknown).await;
+        let claire =
+            Contact::lookup_id_by_addr(&t.ctx, "claire@example.org", Origin::Unknown).await;
+        assert_eq!(chat::get_group_admins(&t.ctx, chat_id).await, vec![bob]);
+
+        // claire is no admin, her changes are ignored
+        dc_receive_imf(
+            &t.ctx,
+            b"From: claire@example.org\n\
+              To: alice@example.org, bob@example.org\n\
+              Subject: foo\n\
+              Message-ID: <Gr.abcde12345.2@example.org>\n\
+              Chat-Version: 1.0\n\
+              Chat-Group-ID: abcde12345\n\
+              Chat-Group-Name: foo\n\
+              Chat-Content: group-admin-changed\n\
+              Chat-Group-Admin-Added: claire@example.org\n\
+              Date: Sun, 22 Mar 2020 22:38:57 +0000\n\
+              \n\
+              claire is now admin\n",
+            "INBOX",
+            2,
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(!chat::is_group_admin(&t.ctx, chat_id, claire).await);
+
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.org\n\
+              To: alice@example.org, claire@example.org\n\
+              Subject: foo\n\
+              Message-ID: <Gr.abcde12345.3@example.org>\n\
+              Chat-Version: 1.0\n\
+              Chat-Group-ID: abcde12345\n\
+              Chat-Group-Name: foo\n\
+              Chat-Content: group-admin-changed\n\
+              Chat-Group-Admin-Added: alice@example.org\n\
+              Date: Sun, 22 Mar 2020 22:39:57 +0000\n\
+              \n\
+              alice is now admin\n",
+            "INBOX",
+            3,
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(chat::is_group_admin(&t.ctx, chat_id, DC_CONTACT_ID_SELF).await);
+        let msgs = chat::get_chat_msgs(&t.ctx, chat_id, 0, None).await;
+        let msg = Message::load_from_db(&t.ctx, *msgs.last().unwrap())
+            .await
+            .unwrap();
+        assert!(msg.is_info());
+        assert_eq!(msg.param.get_cmd(), SystemMessage::GroupAdminAdded);
+
+        // claire may neither rename the group nor remove members, but she may leave
+        for (uid, header) in [
+            (4, "Chat-Group-Name-Changed: foo\nChat-Group-Name: bar"),
+            (5, "Chat-Group-Member-Removed: bob@example.org"),
+            (6, "Chat-Group-Member-Added: dave@example.org"),
+        ]
+        .iter()
+        {
+            let raw = format!(
+                "From: claire@example.org\n\
+                 To: alice@example.org, bob@example.org\n\
+                 Subject: foo\n\
+                 Message-ID: <Gr.abcde12345.{uid}@example.org>\n\
+                 Chat-Version: 1.0\n\
+                 Chat-Group-ID: abcde12345\n\
+                 {header}\n\
+                 Date: Sun, 22 Mar 2020 22:4{uid}:57 +0000\n\
+                 \n\
+                 change\n",
+                uid = uid,
+                header = header
+            );
+            dc_receive_imf(&t.ctx, raw.as_bytes(), "INBOX", *uid, false)
+                .await
+                .unwrap();
+        }
+        let chat = Chat::load_from_db(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(chat.get_name(), "foo");
+        let contacts = chat::get_chat_contacts(&t.ctx, chat_id).await;
+        assert_eq!(contacts.len(), 3);
+        assert!(contacts.contains(&bob));
+
+        dc_receive_imf(
+            &t.ctx,
+            b"From: claire@example.org\n\
+              To: alice@example.org, bob@example.org\n\
+              Subject: foo\n\
+              Message-ID: <Gr.abcde12345.7@example.org>\n\
+              Chat-Version: 1.0\n\
+              Chat-Group-ID: abcde12345\n\
+              Chat-Group-Member-Removed: claire@example.org\n\
+              Date: Sun, 22 Mar 2020 22:47:57 +0000\n\
+              \n\
+              bye\n",
+            "INBOX",
+            7,
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(!chat::is_contact_in_chat(&t.ctx, chat_id, claire).await);
+    }
+
+    #[test]
+    fn test_parse_list_headers() {
+        assert_eq!(
+            parse_list_id("Delta Chat <delta.codespeak.net>"),
+            ("Delta Chat".to_string(), "delta.codespeak.net".to_string())
+        );
+        assert_eq!(
+            parse_list_id("\"Delta Chat\" <delta.codespeak.net>"),
+            ("Delta Chat".to_string(), "delta.codespeak.net".to_string())
+        );
+        assert_eq!(
+            parse_list_id("<delta.codespeak.net>"),
+            (
+                "delta.codespeak.net".to_string(),
+                "delta.codespeak.net".to_string()
+            )
+        );
+
+        assert_eq!(
+            parse_list_post("<mailto:delta@codespeak.net>"),
+            Some("delta@codespeak.net".to_string())
+        );
+        assert_eq!(
+            parse_list_post("<MAILTO:delta@codespeak.net?subject=help>"),
+            Some("delta@codespeak.net".to_string())
+        );
+        assert_eq!(parse_list_post("NO"), None);
+        assert_eq!(parse_list_post("<https://example.org/post>"), None);
+    }
+
+    #[async_std::test]
+    async fn test_mailing_list() {
+        let t = configured_offline_context().await;
+        assert_eq!(t.ctx.get_config_int(Config::ShowEmails).await, 0);
+
+        for (uid, from) in &[(1, "bob@example.net"), (2, "claire@example.net")] {
+            let raw = format!(
+                "From: {from}\n\
+                 To: delta@codespeak.net\n\
+                 Subject: [delta] hello\n\
+                 Message-ID: <list{uid}@example.net>\n\
+                 List-Id: Delta Chat <delta.codespeak.net>\n\
+                 List-Post: <mailto:delta@codespeak.net>\n\
+                 Precedence: list\n\
+                 Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                 \n\
+                 hello from {from}\n",
+                from = from,
+                uid = uid
+            );
+            dc_receive_imf(&t.ctx, raw.as_bytes(), "INBOX", *uid, false)
+                .await
+                .unwrap();
+        }
+
+        let (_, _, msg_id) = message::rfc724_mid_exists(&t.ctx, "list1@example.net")
+            .await
+            .unwrap()
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        let chat = Chat::load_from_db(&t.ctx, msg.chat_id).await.unwrap();
+        assert_eq!(chat.get_type(), Chattype::Mailinglist);
+        assert_eq!(chat.get_name(), "Delta Chat");
+        let (_, _, blocked) = chat::get_chat_id_by_grpid(&t.ctx, "delta.codespeak.net")
+            .await
+            .unwrap();
+        assert_eq!(blocked, Blocked::Deaddrop);
+        assert!(chat.can_send());
+        assert_eq!(msg.get_state(), MessageState::InFresh);
+
+        // both senders end up in the same chat and are shown
+        let msgs = chat::get_chat_msgs(&t.ctx, chat.id, 0, None).await;
+        assert_eq!(msgs.len(), 2);
+        let mut msg2 = Message::load_from_db(&t.ctx, msgs[1]).await.unwrap();
+        assert_ne!(msg.from_id, msg2.from_id);
+        let summary = msg2.get_summary(&t.ctx, Some(&chat)).await;
+        assert!(summary.get_text1().is_some());
+
+        // accepting the list, answers go to the posting address
+        let chat_id = chat::create_by_msg_id(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(chat_id, chat.id);
+        let mut reply = Message::new(Viewtype::Text);
+        reply.set_text(Some("hi list".to_string()));
+        chat::prepare_msg(&t.ctx, chat_id, &mut reply)
+            .await
+            .unwrap();
+        let mf = MimeFactory::from_msg(&t.ctx, &reply, false).await.unwrap();
+        assert_eq!(mf.recipients(), vec!["delta@codespeak.net".to_string()]);
+    }
+
+    #[async_std::test]
+    async fn test_mailing_list_not_in_group() {
+        let t = configured_offline_context().await;
+        let group_id = chat::create_group_chat(&t.ctx, VerifiedStatus::Unverified, "group")
+            .await
+            .unwrap();
+        t.ctx
+            .sql
+            .execute(
+                "UPDATE chats SET grpid=? WHERE id=?;",
+                paramsv!["delta.codespeak.net", group_id],
+            )
+            .await
+            .unwrap();
+
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.net\n\
+                 To: delta@codespeak.net\n\
+                 Subject: [delta] hello\n\
+                 Message-ID: <list@example.net>\n\
+                 List-Id: Delta Chat <delta.codespeak.net>\n\
+                 Precedence: list\n\
+                 Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                 \n\
+                 hello\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+
+        // a List-Id equal to the ID of a group creates a mailing list
+        let (_, _, msg_id) = message::rfc724_mid_exists(&t.ctx, "list@example.net")
+            .await
+            .unwrap()
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_ne!(msg.chat_id, group_id);
+        let chat = Chat::load_from_db(&t.ctx, msg.chat_id).await.unwrap();
+        assert_eq!(chat.get_type(), Chattype::Mailinglist);
+        assert!(chat::get_chat_msgs(&t.ctx, group_id, 0, None)
+            .await
+            .is_empty());
+    }
 }
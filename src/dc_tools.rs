@@ -12,9 +12,11 @@ use async_std::{fs, io};
 use chrono::{Local, TimeZone};
 use rand::{thread_rng, Rng};
 
+use crate::blob_crypt;
 use crate::context::Context;
-use crate::error::{bail, Error};
+use crate::error::{bail, format_err, Error};
 use crate::events::Event;
+use crate::heif;
 
 pub(crate) fn dc_exactly_one_bit_set(v: i32) -> bool {
     0 != v && 0 == v & (v - 1)
@@ -240,6 +242,10 @@ pub fn dc_get_filesuffix_lc(path_filename: impl AsRef<str>) -> Option<String> {
 
 /// Returns the `(width, height)` of the given image buffer.
 pub fn dc_get_filemeta(buf: &[u8]) -> Result<(u32, u32), Error> {
+    if heif::is_heif(buf) {
+        return heif::get_dimensions(buf)
+            .ok_or_else(|| format_err!("Cannot read dimensions of HEIF image"));
+    }
     let meta = image_meta::load_from_buf(buf)?;
 
     Ok((meta.dimensions.width, meta.dimensions.height))
@@ -392,11 +398,12 @@ pub(crate) async fn dc_write_file(
     })
 }
 
+/// Reads a file, attachments encrypted at rest are decrypted.
 pub async fn dc_read_file<P: AsRef<Path>>(context: &Context, path: P) -> Result<Vec<u8>, Error> {
     let path_abs = dc_get_abs_path(context, &path);
 
     match fs::read(&path_abs).await {
-        Ok(bytes) => Ok(bytes),
+        Ok(bytes) => blob_crypt::decrypt_if_encrypted(context, bytes).await,
         Err(err) => {
             warn!(
                 context,
@@ -1,36 +1,99 @@
 //! De-HTML
 //!
-//! A module to remove HTML tags from the email text
+//! A module to remove HTML tags from the email text.
+//!
+//! The structure of the HTML is kept as far as it is meaningful in plain
+//! text: lists are converted to bullets, data tables to aligned columns,
+//! quotes to lines prefixed by `>` and links to "text (url)".  Layout
+//! tables, as used by most newsletters, are converted to paragraphs.
+
+use quick_xml::events::attributes::Attribute;
+use quick_xml::events::{BytesStart, Event};
+
+/// Elements that start a new paragraph.
+const BLOCK_ELEMENTS: [&str; 22] = [
+    "p",
+    "div",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "nav",
+    "main",
+    "aside",
+    "address",
+    "center",
+    "dl",
+    "figure",
+    "figcaption",
+    "form",
+    "fieldset",
+];
+
+/// Elements that never have content or an end tag.
+const VOID_ELEMENTS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "wbr",
+];
 
-use lazy_static::lazy_static;
-use quick_xml::events::{BytesEnd, BytesStart, BytesText};
+type Reader<'a> = quick_xml::Reader<&'a [u8]>;
 
-lazy_static! {
-    static ref LINE_RE: regex::Regex = regex::Regex::new(r"(\r?\n)+").unwrap();
+#[derive(Debug)]
+enum FrameKind {
+    /// The whole document, always the first frame.
+    Document,
+
+    /// Content that is not shown, eg. scripts or hidden preheaders.
+    Skip,
+    Pre,
+    Quote,
+    List {
+        ordered: bool,
+        next: u32,
+    },
+    Item {
+        marker: String,
+    },
+    Table {
+        rows: Vec<Vec<String>>,
+    },
+    Row {
+        cells: Vec<String>,
+    },
+    Cell,
+    Link {
+        href: String,
+    },
 }
 
-struct Dehtml {
-    strbuilder: String,
-    add_text: AddText,
-    last_href: Option<String>,
+/// An open element whose content is converted as a whole when it is closed.
+#[derive(Debug)]
+struct Frame {
+    tag: String,
+    kind: FrameKind,
+    text: String,
 }
 
-#[derive(Debug, PartialEq)]
-enum AddText {
-    No,
-    YesRemoveLineEnds,
-    YesPreserveLineEnds,
+struct Dehtml {
+    frames: Vec<Frame>,
 }
 
-// dehtml() returns way too many newlines; however, an optimisation on this issue is not needed as
-// the newlines are typically removed in further processing by the caller
+/// Converts HTML to plain text.
 pub fn dehtml(buf: &str) -> String {
     let buf = buf.trim();
 
     let mut dehtml = Dehtml {
-        strbuilder: String::with_capacity(buf.len()),
-        add_text: AddText::YesRemoveLineEnds,
-        last_href: None,
+        frames: vec![Frame {
+            tag: String::new(),
+            kind: FrameKind::Document,
+            text: String::with_capacity(buf.len()),
+        }],
     };
 
     let mut reader = quick_xml::Reader::from_str(buf);
@@ -40,12 +103,21 @@ pub fn dehtml(buf: &str) -> String {
 
     loop {
         match reader.read_event(&mut buf) {
-            Ok(quick_xml::events::Event::Start(ref e)) => {
-                dehtml_starttag_cb(e, &mut dehtml, &reader)
+            Ok(Event::Start(ref e)) => dehtml.start_tag(e, &reader),
+            Ok(Event::Empty(ref e)) => {
+                // `<br/>` or `<p/>`, the latter is handled as `<p></p>`
+                dehtml.start_tag(e, &reader);
+                let tag = tag_name(e.name());
+                if !VOID_ELEMENTS.contains(&tag.as_str()) {
+                    dehtml.end_tag(&tag);
+                }
+            }
+            Ok(Event::End(ref e)) => dehtml.end_tag(&tag_name(e.name())),
+            Ok(Event::Text(ref e)) => {
+                let text = escaper::decode_html_buf_sloppy(e.escaped()).unwrap_or_default();
+                dehtml.add_text(&text);
             }
-            Ok(quick_xml::events::Event::End(ref e)) => dehtml_endtag_cb(e, &mut dehtml),
-            Ok(quick_xml::events::Event::Text(ref e)) => dehtml_text_cb(e, &mut dehtml),
-            Ok(quick_xml::events::Event::CData(ref e)) => dehtml_cdata_cb(e, &mut dehtml),
+            Ok(Event::CData(ref e)) => dehtml.add_text(&String::from_utf8_lossy(e.escaped())),
             Err(e) => {
                 eprintln!(
                     "Parse html error: Error at position {}: {:?}",
@@ -53,116 +125,687 @@ pub fn dehtml(buf: &str) -> String {
                     e
                 );
             }
-            Ok(quick_xml::events::Event::Eof) => break,
+            Ok(Event::Eof) => break,
             _ => (),
         }
         buf.clear();
     }
 
-    dehtml.strbuilder
+    dehtml.finish()
 }
 
-fn dehtml_text_cb(event: &BytesText, dehtml: &mut Dehtml) {
-    if dehtml.add_text == AddText::YesPreserveLineEnds
-        || dehtml.add_text == AddText::YesRemoveLineEnds
-    {
-        let last_added = escaper::decode_html_buf_sloppy(event.escaped()).unwrap_or_default();
+impl Dehtml {
+    fn text_mut(&mut self) -> &mut String {
+        // the document frame is never removed
+        &mut self.frames.last_mut().unwrap().text
+    }
 
-        if dehtml.add_text == AddText::YesRemoveLineEnds {
-            dehtml.strbuilder += LINE_RE.replace_all(&last_added, "\r").as_ref();
-        } else {
-            dehtml.strbuilder += &last_added;
-        }
+    fn is_skipping(&self) -> bool {
+        self.frames
+            .iter()
+            .any(|frame| matches!(frame.kind, FrameKind::Skip))
     }
-}
 
-fn dehtml_cdata_cb(event: &BytesText, dehtml: &mut Dehtml) {
-    if dehtml.add_text == AddText::YesPreserveLineEnds
-        || dehtml.add_text == AddText::YesRemoveLineEnds
-    {
-        let last_added = escaper::decode_html_buf_sloppy(event.escaped()).unwrap_or_default();
+    fn is_pre(&self) -> bool {
+        self.frames
+            .iter()
+            .any(|frame| matches!(frame.kind, FrameKind::Pre))
+    }
 
-        if dehtml.add_text == AddText::YesRemoveLineEnds {
-            dehtml.strbuilder += LINE_RE.replace_all(&last_added, "\r").as_ref();
-        } else {
-            dehtml.strbuilder += &last_added;
+    fn push_frame(&mut self, tag: &str, kind: FrameKind) {
+        self.frames.push(Frame {
+            tag: tag.to_string(),
+            kind,
+            text: String::new(),
+        });
+    }
+
+    /// Returns the index of the innermost open element with one of the given tags,
+    /// not looking beyond the innermost element with one of the `barriers` tags.
+    fn find_frame(&self, tags: &[&str], barriers: &[&str]) -> Option<usize> {
+        for (index, frame) in self.frames.iter().enumerate().skip(1).rev() {
+            if tags.contains(&frame.tag.as_str()) {
+                return Some(index);
+            }
+            if barriers.contains(&frame.tag.as_str()) {
+                return None;
+            }
         }
+        None
     }
-}
 
-fn dehtml_endtag_cb(event: &BytesEnd, dehtml: &mut Dehtml) {
-    let tag = String::from_utf8_lossy(event.name()).trim().to_lowercase();
+    /// Closes the element at `index` and all elements opened after it.
+    fn close_frames(&mut self, index: usize) {
+        while self.frames.len() > index.max(1) {
+            let frame = self.frames.pop().unwrap();
+            self.close_frame(frame);
+        }
+    }
 
-    match tag.as_str() {
-        "p" | "div" | "table" | "td" | "style" | "script" | "title" | "pre" => {
-            dehtml.strbuilder += "\n\n";
-            dehtml.add_text = AddText::YesRemoveLineEnds;
+    fn close_frame(&mut self, frame: Frame) {
+        let text = frame.text.trim();
+        match frame.kind {
+            FrameKind::Document | FrameKind::Skip => {}
+            FrameKind::Pre => {
+                let text = frame.text.trim_matches('\n').to_string();
+                push_block(self.text_mut(), &text);
+            }
+            FrameKind::Quote => {
+                let quoted = text
+                    .lines()
+                    .map(|line| {
+                        if line.is_empty() {
+                            ">".to_string()
+                        } else {
+                            format!("> {}", line)
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                push_block(self.text_mut(), &quoted);
+            }
+            FrameKind::List { .. } => {
+                let parent = self.frames.last_mut().unwrap();
+                if let FrameKind::Item { .. } = parent.kind {
+                    // nested lists continue the item
+                    new_line(&mut parent.text);
+                    parent.text.push_str(text);
+                    parent.text.push('\n');
+                } else {
+                    push_block(&mut parent.text, text);
+                }
+            }
+            FrameKind::Item { marker } => {
+                if text.is_empty() {
+                    return;
+                }
+                let indent = " ".repeat(marker.chars().count());
+                let item = text
+                    .lines()
+                    .enumerate()
+                    .map(|(i, line)| {
+                        if i == 0 {
+                            format!("{}{}", marker, line)
+                        } else if line.is_empty() {
+                            String::new()
+                        } else {
+                            format!("{}{}", indent, line)
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let out = self.text_mut();
+                new_line(out);
+                out.push_str(&item);
+                out.push('\n');
+            }
+            FrameKind::Table { rows } => {
+                let table = render_table(rows);
+                push_block(self.text_mut(), &table);
+            }
+            FrameKind::Row { cells } => {
+                let parent = self.frames.last_mut().unwrap();
+                match parent.kind {
+                    FrameKind::Table { ref mut rows } => rows.push(cells),
+                    _ => push_block(&mut parent.text, &cells.join("  ")),
+                }
+            }
+            FrameKind::Cell => {
+                let parent = self.frames.last_mut().unwrap();
+                match parent.kind {
+                    FrameKind::Row { ref mut cells } => cells.push(text.to_string()),
+                    FrameKind::Table { ref mut rows } => rows.push(vec![text.to_string()]),
+                    _ => push_block(&mut parent.text, text),
+                }
+            }
+            FrameKind::Link { href } => {
+                let link = format_link(text, &href);
+                add_inline(self.text_mut(), &link);
+            }
         }
-        "a" => {
-            if let Some(ref last_href) = dehtml.last_href.take() {
-                dehtml.strbuilder += "](";
-                dehtml.strbuilder += last_href;
-                dehtml.strbuilder += ")";
+    }
+
+    fn start_tag(&mut self, event: &BytesStart, reader: &Reader) {
+        let tag = tag_name(event.name());
+        let is_void = VOID_ELEMENTS.contains(&tag.as_str());
+
+        if self.is_skipping() {
+            // nested elements are tracked to find the end of the skipped one
+            if !is_void {
+                self.push_frame(&tag, FrameKind::Skip);
             }
+            return;
         }
-        "b" | "strong" => {
-            dehtml.strbuilder += "*";
+        if !is_void && is_hidden(event, reader) {
+            self.push_frame(&tag, FrameKind::Skip);
+            return;
         }
-        "i" | "em" => {
-            dehtml.strbuilder += "_";
+
+        match tag.as_str() {
+            "style" | "script" | "title" | "head" => self.push_frame(&tag, FrameKind::Skip),
+            "br" => line_break(self.text_mut()),
+            "hr" => paragraph(self.text_mut()),
+            "dt" | "dd" => new_line(self.text_mut()),
+            "pre" => self.push_frame(&tag, FrameKind::Pre),
+            "blockquote" => self.push_frame(&tag, FrameKind::Quote),
+            "ul" | "ol" => {
+                let next = get_attr(event, reader, "start")
+                    .and_then(|start| start.trim().parse().ok())
+                    .unwrap_or(1);
+                self.push_frame(
+                    &tag,
+                    FrameKind::List {
+                        ordered: tag == "ol",
+                        next,
+                    },
+                );
+            }
+            "li" => {
+                if let Some(index) = self.find_frame(&["li"], &["ul", "ol"]) {
+                    self.close_frames(index);
+                }
+                let marker = match self.find_frame(&["ul", "ol"], &[]) {
+                    Some(index) => match self.frames[index].kind {
+                        FrameKind::List {
+                            ordered: true,
+                            ref mut next,
+                        } => {
+                            *next += 1;
+                            format!("{}. ", *next - 1)
+                        }
+                        _ => "• ".to_string(),
+                    },
+                    None => "• ".to_string(),
+                };
+                self.push_frame(&tag, FrameKind::Item { marker });
+            }
+            "table" => self.push_frame(&tag, FrameKind::Table { rows: Vec::new() }),
+            "tr" => {
+                if let Some(index) = self.find_frame(&["tr"], &["table"]) {
+                    self.close_frames(index);
+                }
+                self.push_frame(&tag, FrameKind::Row { cells: Vec::new() });
+            }
+            "td" | "th" => {
+                if let Some(index) = self.find_frame(&["td", "th"], &["tr", "table"]) {
+                    self.close_frames(index);
+                }
+                self.push_frame(&tag, FrameKind::Cell);
+            }
+            "a" => {
+                if let Some(href) = get_attr(event, reader, "href") {
+                    self.push_frame(&tag, FrameKind::Link { href });
+                }
+            }
+            "img" => {
+                if !is_tracking_pixel(event, reader) {
+                    if let Some(alt) = get_attr(event, reader, "alt") {
+                        self.add_text(&alt);
+                    }
+                }
+            }
+            "b" | "strong" => self.text_mut().push('*'),
+            "i" | "em" => self.text_mut().push('_'),
+            _ => {
+                if BLOCK_ELEMENTS.contains(&tag.as_str()) {
+                    paragraph(self.text_mut());
+                }
+            }
         }
-        _ => {}
     }
-}
 
-fn dehtml_starttag_cb<B: std::io::BufRead>(
-    event: &BytesStart,
-    dehtml: &mut Dehtml,
-    reader: &quick_xml::Reader<B>,
-) {
-    let tag = String::from_utf8_lossy(event.name()).trim().to_lowercase();
+    fn end_tag(&mut self, tag: &str) {
+        if !self.is_skipping() {
+            match tag {
+                "b" | "strong" => self.text_mut().push('*'),
+                "i" | "em" => self.text_mut().push('_'),
+                "dt" | "dd" => new_line(self.text_mut()),
+                _ => {
+                    if BLOCK_ELEMENTS.contains(&tag) {
+                        paragraph(self.text_mut());
+                    }
+                }
+            }
+        }
+        if let Some(index) = self.find_frame(&[tag], &[]) {
+            self.close_frames(index);
+        }
+    }
 
-    match tag.as_str() {
-        "p" | "div" | "table" | "td" => {
-            dehtml.strbuilder += "\n\n";
-            dehtml.add_text = AddText::YesRemoveLineEnds;
+    fn add_text(&mut self, text: &str) {
+        if self.is_skipping() {
+            return;
         }
-        "br" => {
-            dehtml.strbuilder += "\n";
-            dehtml.add_text = AddText::YesRemoveLineEnds;
+        if self.is_pre() {
+            let text = text.replace("\r\n", "\n").replace('\r', "\n");
+            self.text_mut().push_str(&text);
+            return;
         }
-        "style" | "script" | "title" => {
-            dehtml.add_text = AddText::No;
+        add_inline(self.text_mut(), text);
+    }
+
+    fn finish(mut self) -> String {
+        self.close_frames(1);
+        let text = self.frames.pop().unwrap().text;
+        text.trim().to_string()
+    }
+}
+
+/// Appends text, collapsing whitespace as browsers do.
+fn add_inline(out: &mut String, text: &str) {
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !out.is_empty() && !out.ends_with(' ') && !out.ends_with('\n') {
+                out.push(' ');
+            }
+        } else {
+            out.push(c);
         }
-        "pre" => {
-            dehtml.strbuilder += "\n\n";
-            dehtml.add_text = AddText::YesPreserveLineEnds;
+    }
+}
+
+fn trim_trailing_spaces(out: &mut String) {
+    let len = out.trim_end_matches(' ').len();
+    out.truncate(len);
+}
+
+fn line_break(out: &mut String) {
+    trim_trailing_spaces(out);
+    out.push('\n');
+}
+
+fn new_line(out: &mut String) {
+    trim_trailing_spaces(out);
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+fn paragraph(out: &mut String) {
+    trim_trailing_spaces(out);
+    if out.is_empty() {
+        return;
+    }
+    while !out.ends_with("\n\n") {
+        out.push('\n');
+    }
+}
+
+fn push_block(out: &mut String, block: &str) {
+    if block.trim().is_empty() {
+        return;
+    }
+    paragraph(out);
+    out.push_str(block);
+    paragraph(out);
+}
+
+fn tag_name(name: &[u8]) -> String {
+    String::from_utf8_lossy(name).trim().to_lowercase()
+}
+
+fn get_attr(event: &BytesStart, reader: &Reader, name: &str) -> Option<String> {
+    event
+        .html_attributes()
+        .filter_map(|attr| attr.ok())
+        .find(|attr| String::from_utf8_lossy(attr.key).trim().to_lowercase() == name)
+        .and_then(|attr| attr.unescape_and_decode_value(reader).ok())
+}
+
+fn get_style(event: &BytesStart, reader: &Reader) -> String {
+    get_attr(event, reader, "style")
+        .unwrap_or_default()
+        .to_lowercase()
+        .replace(' ', "")
+}
+
+/// Returns true for elements not shown by browsers,
+/// eg. the preheader of newsletters shown only in the message list.
+fn is_hidden(event: &BytesStart, reader: &Reader) -> bool {
+    get_attr(event, reader, "hidden").is_some() || get_style(event, reader).contains("display:none")
+}
+
+/// Returns true for images used to track whether a message is read.
+fn is_tracking_pixel(event: &BytesStart, reader: &Reader) -> bool {
+    let is_tiny = |name: &str| {
+        get_attr(event, reader, name)
+            .and_then(|value| value.trim().trim_end_matches("px").parse::<u32>().ok())
+            .map(|value| value <= 1)
+            .unwrap_or_default()
+    };
+    let style = get_style(event, reader);
+    is_tiny("width")
+        || is_tiny("height")
+        || style.contains("display:none")
+        || style.contains("width:1px")
+        || style.contains("height:1px")
+}
+
+/// Formats a link as "text (url)", omitting the URL if it is the same as the text.
+fn format_link(text: &str, href: &str) -> String {
+    fn normalize(url: &str) -> String {
+        let url = url.trim().to_lowercase();
+        let url = url.trim_end_matches('/');
+        for scheme in &["https://", "http://", "mailto:"] {
+            if url.starts_with(scheme) {
+                return url[scheme.len()..].to_string();
+            }
         }
-        "a" => {
-            if let Some(href) = event
-                .html_attributes()
-                .filter_map(|attr| attr.ok())
-                .find(|attr| String::from_utf8_lossy(attr.key).trim().to_lowercase() == "href")
-            {
-                let href = href
-                    .unescape_and_decode_value(reader)
-                    .unwrap_or_default()
-                    .to_lowercase();
+        url.to_string()
+    }
+
+    let href = href.trim();
+    if href.is_empty() || href.starts_with('#') || normalize(text) == normalize(href) {
+        text.to_string()
+    } else if text.is_empty() {
+        // eg. a linked image without alternative text
+        String::new()
+    } else {
+        format!("{} ({})", text, href)
+    }
+}
+
+/// Renders a data table as aligned columns and a layout table as paragraphs.
+fn render_table(rows: Vec<Vec<String>>) -> String {
+    let rows: Vec<Vec<String>> = rows
+        .into_iter()
+        .filter(|row| row.iter().any(|cell| !cell.is_empty()))
+        .collect();
+    let columns = rows.iter().map(|row| row.len()).max().unwrap_or_default();
+    let is_layout = columns < 2
+        || rows
+            .iter()
+            .any(|row| row.iter().any(|cell| cell.contains('\n')));
+
+    if is_layout {
+        return rows
+            .into_iter()
+            .flatten()
+            .filter(|cell| !cell.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+    }
 
-                if !href.is_empty() {
-                    dehtml.last_href = Some(href);
-                    dehtml.strbuilder += "[";
+    let mut widths = vec![0; columns];
+    for row in &rows {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            let mut line = String::new();
+            for (i, cell) in row.iter().enumerate() {
+                line.push_str(cell);
+                if i + 1 < row.len() {
+                    let padding = widths[i] - cell.chars().count() + 2;
+                    line.push_str(&" ".repeat(padding));
+                }
+            }
+            line.trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Elements kept by [sanitize_html].
+///
+/// Other elements are removed, their content is kept unless they are
+/// one of [DROPPED_ELEMENTS].
+const SAFE_ELEMENTS: [&str; 71] = [
+    "a",
+    "abbr",
+    "address",
+    "article",
+    "aside",
+    "b",
+    "bdi",
+    "bdo",
+    "big",
+    "blockquote",
+    "body",
+    "br",
+    "caption",
+    "center",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "dd",
+    "del",
+    "details",
+    "dfn",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "font",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "main",
+    "mark",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "s",
+    "samp",
+    "section",
+    "small",
+    "span",
+    "strike",
+    "strong",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "u",
+    "ul",
+];
+
+/// Elements removed together with their content by [sanitize_html].
+///
+/// Besides scripts and embedded objects, these are elements whose content
+/// is not parsed as HTML by browsers, eg. SVG with its animations.
+const DROPPED_ELEMENTS: [&str; 16] = [
+    "applet", "form", "frameset", "iframe", "math", "noembed", "noframes", "noscript", "object",
+    "script", "select", "style", "svg", "template", "textarea", "title",
+];
+
+/// Attributes kept by [sanitize_html].
+const SAFE_ATTRIBUTES: [&str; 27] = [
+    "align",
+    "alt",
+    "background",
+    "bgcolor",
+    "border",
+    "cellpadding",
+    "cellspacing",
+    "cite",
+    "class",
+    "color",
+    "cols",
+    "colspan",
+    "datetime",
+    "dir",
+    "face",
+    "height",
+    "href",
+    "lang",
+    "rows",
+    "rowspan",
+    "size",
+    "span",
+    "src",
+    "start",
+    "style",
+    "title",
+    "width",
+];
+
+/// Attributes containing URLs, see [is_safe_url].
+const URL_ATTRIBUTES: [&str; 4] = ["background", "cite", "href", "src"];
+
+/// URL schemes allowed by [sanitize_html], URLs without scheme are allowed as well.
+const SAFE_URL_SCHEMES: [&str; 4] = ["http", "https", "mailto", "cid"];
+
+/// CSS loading resources or running code, see [is_safe_style].
+const UNSAFE_CSS: [&str; 7] = [
+    "url(",
+    "image(",
+    "image-set(",
+    "expression",
+    "behavior",
+    "binding",
+    "/*",
+];
+
+/// Keeps only known safe elements and attributes of HTML,
+/// so that it can be shown to the user.
+///
+/// Comments, processing instructions and CDATA sections are removed
+/// as browsers end them differently than the XML parser.
+///
+/// Returns `None` if the HTML cannot be parsed.
+pub fn sanitize_html(html: &str) -> Option<String> {
+    let mut reader = quick_xml::Reader::from_str(html);
+    reader.check_end_names(false);
+    let mut writer = quick_xml::Writer::new(Vec::with_capacity(html.len()));
+
+    // name and nesting depth of the dropped element whose content is skipped
+    let mut skip: Option<(String, usize)> = None;
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event(&mut buf) {
+            Err(_) => return None,
+            Ok(Event::Eof) => break,
+            Ok(event) => {
+                if let Some((name, depth)) = skip.as_mut() {
+                    match event {
+                        Event::Start(ref e) if tag_name(e.name()) == *name => *depth += 1,
+                        Event::End(ref e) if tag_name(e.name()) == *name => *depth -= 1,
+                        _ => {}
+                    }
+                    if *depth == 0 {
+                        skip = None;
+                    }
+                } else {
+                    match event {
+                        Event::Start(ref e) if is_dropped_element(e.name()) => {
+                            skip = Some((tag_name(e.name()), 1));
+                        }
+                        Event::Start(ref e) if is_safe_element(e.name()) => {
+                            writer.write_event(Event::Start(sanitize_element(e))).ok()?;
+                        }
+                        Event::Empty(ref e) if is_safe_element(e.name()) => {
+                            writer.write_event(Event::Empty(sanitize_element(e))).ok()?;
+                        }
+                        Event::End(e) if is_safe_element(e.name()) => {
+                            writer.write_event(Event::End(e)).ok()?;
+                        }
+                        Event::Text(e) => {
+                            writer.write_event(Event::Text(e)).ok()?;
+                        }
+                        _ => {}
+                    }
                 }
             }
         }
-        "b" | "strong" => {
-            dehtml.strbuilder += "*";
-        }
-        "i" | "em" => {
-            dehtml.strbuilder += "_";
+        buf.clear();
+    }
+    String::from_utf8(writer.into_inner()).ok()
+}
+
+fn is_safe_element(name: &[u8]) -> bool {
+    SAFE_ELEMENTS.contains(&tag_name(name).as_str())
+}
+
+fn is_dropped_element(name: &[u8]) -> bool {
+    DROPPED_ELEMENTS.contains(&tag_name(name).as_str())
+}
+
+/// Copies an element with its safe attributes only.
+///
+/// Values are written decoded and escaped again, so that a quote in a
+/// value cannot end the attribute.
+fn sanitize_element(event: &BytesStart) -> BytesStart<'static> {
+    let mut element = BytesStart::owned(event.name().to_vec(), event.name().len());
+    for attr in event.html_attributes().filter_map(|attr| attr.ok()) {
+        if let Some((key, value)) = safe_attribute(&attr) {
+            element.push_attribute((key.as_str(), value.as_str()));
         }
-        _ => {}
     }
+    element
+}
+
+/// Returns the lowercased name and the decoded value of a safe attribute.
+fn safe_attribute(attr: &Attribute) -> Option<(String, String)> {
+    let key = String::from_utf8_lossy(attr.key).trim().to_lowercase();
+    if !SAFE_ATTRIBUTES.contains(&key.as_str()) {
+        return None;
+    }
+    // values are checked as the browser sees them, after decoding entities;
+    // values that cannot be decoded are dropped
+    let value = String::from_utf8_lossy(&attr.unescaped_value().ok()?).to_string();
+    let lowercase = value.to_lowercase();
+    let is_safe = if URL_ATTRIBUTES.contains(&key.as_str()) {
+        is_safe_url(&lowercase)
+    } else if key == "style" {
+        is_safe_style(&lowercase)
+    } else {
+        true
+    };
+    if is_safe {
+        Some((key, value))
+    } else {
+        None
+    }
+}
+
+/// Returns true if the URL has no scheme or one of [SAFE_URL_SCHEMES].
+fn is_safe_url(url: &str) -> bool {
+    // browsers ignore whitespace and control characters in the scheme
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect();
+    match url.find(|c| c == ':' || c == '/' || c == '?' || c == '#') {
+        Some(pos) if url[pos..].starts_with(':') => SAFE_URL_SCHEMES.contains(&&url[..pos]),
+        _ => true,
+    }
+}
+
+/// Returns true if the CSS contains none of [UNSAFE_CSS].
+///
+/// CSS escapes are rejected as well, eg. `u\72l(` is the same as `url(`.
+fn is_safe_style(style: &str) -> bool {
+    !style.contains('\\')
+        && !UNSAFE_CSS
+            .iter()
+            .any(|unsafe_css| style.contains(unsafe_css))
 }
 
 #[cfg(test)]
@@ -174,7 +817,7 @@ mod tests {
         let cases = vec![
             (
                 "<a href='https://example.com'> Foo </a>",
-                "[ Foo ](https://example.com)",
+                "Foo (https://example.com)",
             ),
             ("<img href='/foo.png'>", ""),
             ("<b> bar </b>", "* bar *"),
@@ -194,7 +837,7 @@ mod tests {
         let html = "\r\r\nline1<br>\r\n\r\n\r\rline2\n\r";
         let plain = dehtml(html);
 
-        assert_eq!(plain, "line1\n\r\r\rline2");
+        assert_eq!(plain, "line1\nline2");
     }
 
     #[test]
@@ -202,7 +845,7 @@ mod tests {
         let html = "<a href=url>text</a";
         let plain = dehtml(html);
 
-        assert_eq!(plain, "[text](url)");
+        assert_eq!(plain, "text (url)");
     }
 
     #[test]
@@ -234,7 +877,7 @@ mod tests {
         <html>
         <head>
         <title>Hi</title>
-        <meta http-equiv='Content-Type' content='text/html; charset=iso-8859-1'>						
+        <meta http-equiv='Content-Type' content='text/html; charset=iso-8859-1'>
         </head>
         <body>
         lots of text
@@ -244,4 +887,170 @@ mod tests {
         let txt = dehtml(input);
         assert_eq!(txt.trim(), "lots of text");
     }
+
+    #[test]
+    fn test_dehtml_structure() {
+        let cases = vec![
+            ("<p>one</p><p>two</p>", "one\n\ntwo"),
+            ("<ul><li>one</li><li>two</li></ul>", "• one\n• two"),
+            ("<ol start=\"3\"><li>three<li>four</ol>", "3. three\n4. four"),
+            (
+                "<ul><li>one<ul><li>nested</li></ul></li></ul>",
+                "• one\n  • nested",
+            ),
+            (
+                "<blockquote><p>quoted</p><p>text</p></blockquote>reply",
+                "> quoted\n>\n> text\n\nreply",
+            ),
+            (
+                "<table><tr><td>a</td><td>1</td></tr><tr><td>bcd</td><td>23</td></tr></table>",
+                "a    1\nbcd  23",
+            ),
+            (
+                "<table><tr><td><p>layout</p></td></tr><tr><td>table</td></tr></table>",
+                "layout\n\ntable",
+            ),
+            (
+                "<a href=\"https://example.org/\">example.org</a>",
+                "example.org",
+            ),
+            (
+                "<a href=\"mailto:bob@example.org\">bob@example.org</a>",
+                "bob@example.org",
+            ),
+            ("<pre>  a\n  b</pre>", "a\n  b"),
+            ("hi<div style=\"display: none\">preheader</div>", "hi"),
+            (
+                "<img src=\"x.gif\" alt=\"Logo\"><img src=\"p.gif\" width=\"1\" height=\"1\" alt=\"pixel\">",
+                "Logo",
+            ),
+        ];
+        for (input, output) in cases {
+            assert_eq!(dehtml(input), output, "for {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_dehtml_newsletter() {
+        let html = include_str!("../test-data/html/newsletter.html");
+        assert_eq!(
+            dehtml(html),
+            "Example Project (https://example.org/?utm_source=newsletter)\n\
+             \n\
+             Weekly Digest\n\
+             \n\
+             Hello,\n\
+             here is what happened this week:\n\
+             \n\
+             • Version 1.2 is *released*.\n\
+             • The events page (https://example.org/events) was updated.\n\
+             • New translations:\n\
+             \x20 • German\n\
+             \x20 • French\n\
+             \n\
+             Read the full post (https://example.org/blog/weekly) on example.org.\n\
+             \n\
+             You receive this mail because you subscribed. \
+             Unsubscribe (https://example.org/unsubscribe?id=1234)"
+        );
+    }
+
+    #[test]
+    fn test_dehtml_mailing_list_reply() {
+        let html = include_str!("../test-data/html/mailing_list_reply.html");
+        assert_eq!(
+            dehtml(html),
+            "I measured both versions:\n\
+             \n\
+             Version   Time    Memory\n\
+             1.1       12.5 s  210 MB\n\
+             1.2-beta  9 s     180 MB\n\
+             \n\
+             Steps to reproduce:\n\
+             \n\
+             1. Import the backup\n\
+             2. Open the largest chat\n\
+             3. Scroll to the top\n\
+             \n\
+             On 01.05.20 10:00, Bob wrote:\n\
+             \n\
+             > Is the new version faster?\n\
+             >\n\
+             > > It feels slow\n\
+             > > on my phone.\n\
+             \n\
+             $ time ./run\n\
+             real    0m9.012s"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_html() {
+        let cases = vec![
+            ("<p>hello <b>world</b></p>", "<p>hello <b>world</b></p>"),
+            (
+                "<p onclick='alert(1)' class=\"x\">hi<script>alert(1)</script></p>",
+                "<p class=\"x\">hi</p>",
+            ),
+            (
+                "<a href='javascript:alert(1)'>a</a><a href=\"https://example.org\">b</a>",
+                "<a>a</a><a href=\"https://example.org\">b</a>",
+            ),
+            (
+                "<div><iframe src=\"x\"><p>inner</p></iframe>after</div>",
+                "<div>after</div>",
+            ),
+            (
+                "<img src=\"cid:1\" onerror=\"x()\"/>&amp;",
+                "<img src=\"cid:1\"/>&amp;",
+            ),
+            (
+                "<a href=\"&#106;avascript:alert(1)\">a</a><a href=\"java&#x09;script:x()\">b</a>",
+                "<a>a</a><a>b</a>",
+            ),
+            (
+                "<a href=\"data:text/html,x\">a</a><a href=\"/path?x=a:b\">b</a><a href=\"MAILTO:bob@example.org\">c</a>",
+                "<a>a</a><a href=\"/path?x=a:b\">b</a><a href=\"MAILTO:bob@example.org\">c</a>",
+            ),
+            (
+                "<head><meta http-equiv=\"refresh\" content=\"0\"><link rel=\"stylesheet\" href=\"x\"><style>p { color: red }</style></head><p>text</p>",
+                "<head></head><p>text</p>",
+            ),
+            (
+                "<form action=\"https://example.org\"><input name=\"pw\"></form><p style=\"background: url(x)\">after</p>",
+                "<p>after</p>",
+            ),
+            (
+                "<svg><animate attributeName=\"href\" values=\"javascript:alert(1)\"/><a href=\"#\">x</a></svg>after",
+                "after",
+            ),
+            (
+                "<svg><svg></svg><set attributeName=\"href\" to=\"javascript:alert(1)\"/></svg>after",
+                "after",
+            ),
+            (
+                "<animate attributeName=\"href\" values=\"javascript:alert(1)\"/><set to=\"javascript:alert(1)\">x</set>",
+                "x",
+            ),
+            (
+                "<p style=\"background: u\\72l(https://example.org/t.gif)\">a</p><p style=\"background: &#92;75rl(x)\">b</p><p style=\"width: expr/**/ession(x())\">c</p><p style=\"color: red\">d</p>",
+                "<p>a</p><p>b</p><p>c</p><p style=\"color: red\">d</p>",
+            ),
+            (
+                "<p title='x\" onclick=\"alert(1)'>a</p>",
+                "<p title=\"x&quot; onclick=&quot;alert(1)\">a</p>",
+            ),
+            (
+                "<!-- --!><img src=x onerror=alert(1)> --><![CDATA[><img src=x onerror=alert(1)>]]>after",
+                "after",
+            ),
+            (
+                "<marquee>a</marquee><custom onclick=\"x()\">b</custom><a name=\"c\" target=\"_top\">c</a>",
+                "ab<a>c</a>",
+            ),
+        ];
+        for (input, output) in cases {
+            assert_eq!(sanitize_html(input).unwrap(), output, "for {:?}", input);
+        }
+    }
 }
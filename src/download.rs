@@ -0,0 +1,249 @@
+//! # Download on demand
+//!
+//! Messages larger than the configured `download_limit` are not
+//! downloaded completely; only the header is fetched and a placeholder
+//! message with [DownloadState::Available] is stored.  The user may then
+//! request the full message using [MsgId::download_full], the placeholder
+//! is replaced in place by the downloaded message, keeping its ID.
+
+use deltachat_derive::{FromSql, ToSql};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::context::Context;
+use crate::error::{bail, format_err, Result};
+use crate::events::Event;
+use crate::imap::{Imap, ImapActionResult};
+use crate::job::{self, Action, Job, Status};
+use crate::message::{Message, MsgId};
+use crate::param::Params;
+
+/// Download limits smaller than this are raised to this value.
+///
+/// Messages below this size are typically text-only chat messages
+/// and are always downloaded completely.
+pub(crate) const MIN_DOWNLOAD_LIMIT: u32 = 32768;
+
+/// Download state of a message.
+#[derive(
+    Debug,
+    Display,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    FromPrimitive,
+    ToPrimitive,
+    FromSql,
+    ToSql,
+    Serialize,
+    Deserialize,
+)]
+#[repr(i32)]
+pub enum DownloadState {
+    /// The message is downloaded completely.
+    Done = 0,
+
+    /// Only the header is downloaded, the full message can be
+    /// downloaded using [MsgId::download_full].
+    Available = 10,
+
+    /// Downloading the full message failed, eg. because the message
+    /// was deleted from the server in between.
+    Failure = 20,
+
+    /// The full message is being downloaded.
+    InProgress = 1000,
+}
+
+impl Default for DownloadState {
+    fn default() -> Self {
+        DownloadState::Done
+    }
+}
+
+impl Context {
+    /// Returns the download limit in bytes, `None` if all messages are
+    /// downloaded completely.
+    pub(crate) async fn download_limit(&self) -> Option<u32> {
+        match self.get_config_int(Config::DownloadLimit).await {
+            limit if limit <= 0 => None,
+            limit => Some(std::cmp::max(MIN_DOWNLOAD_LIMIT, limit as u32)),
+        }
+    }
+}
+
+impl MsgId {
+    /// Schedules the full download of a partially downloaded message.
+    ///
+    /// Once downloaded, the message is updated in place
+    /// and `DC_EVENT_MSGS_CHANGED` is emitted.
+    pub async fn download_full(self, context: &Context) -> Result<()> {
+        let msg = Message::load_from_db(context, self).await?;
+        match msg.download_state() {
+            DownloadState::Done => bail!("Message {} is already downloaded", self),
+            DownloadState::InProgress => bail!("Download of message {} already started", self),
+            DownloadState::Available | DownloadState::Failure => {
+                self.update_download_state(context, DownloadState::InProgress)
+                    .await?;
+                job::add(
+                    context,
+                    Job::new(Action::DownloadMsg, self.to_u32(), Params::new(), 0),
+                )
+                .await;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn update_download_state(
+        self,
+        context: &Context,
+        download_state: DownloadState,
+    ) -> Result<()> {
+        let msg = Message::load_from_db(context, self).await?;
+        context
+            .sql
+            .execute(
+                "UPDATE msgs SET download_state=? WHERE id=?;",
+                paramsv![download_state, self],
+            )
+            .await?;
+        context.emit_event(Event::MsgsChanged {
+            chat_id: msg.chat_id,
+            msg_id: self,
+        });
+        Ok(())
+    }
+}
+
+impl Job {
+    /// Downloads the full message, replacing the placeholder.
+    pub(crate) async fn download_msg(&self, context: &Context, imap: &mut Imap) -> Status {
+        if let Err(err) = imap.connect_configured(context).await {
+            warn!(context, "could not connect: {:?}", err);
+            return Status::RetryLater;
+        }
+
+        let msg_id = MsgId::new(self.foreign_id);
+        let msg = job_try!(Message::load_from_db(context, msg_id).await);
+        let server_folder = msg.server_folder.unwrap_or_default();
+        match imap
+            .fetch_single_msg(context, &server_folder, msg.server_uid)
+            .await
+        {
+            ImapActionResult::RetryLater => Status::RetryLater,
+            ImapActionResult::Success => Status::Finished(Ok(())),
+            ImapActionResult::Failed | ImapActionResult::AlreadyDone => {
+                job_try!(
+                    msg_id
+                        .update_download_state(context, DownloadState::Failure)
+                        .await
+                );
+                Status::Finished(Err(format_err!(
+                    "Cannot download message {} from {}/{}",
+                    msg_id,
+                    server_folder,
+                    msg.server_uid
+                )))
+            }
+        }
+    }
+}
+
+/// Returns a human readable size as "123 bytes", "12 KiB" or "1.2 MiB".
+pub(crate) fn format_size(bytes: u32) -> String {
+    const KIB: u32 = 1024;
+    const MIB: u32 = 1024 * KIB;
+    if bytes < KIB {
+        format!("{} bytes", bytes)
+    } else if bytes < MIB {
+        format!("{} KiB", bytes / KIB)
+    } else {
+        format!("{:.1} MiB", f64::from(bytes) / f64::from(MIB))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::chat::{self, ChatId};
+    use crate::constants::Viewtype;
+    use crate::dc_receive_imf::{dc_receive_imf, dc_receive_imf_inner};
+    use crate::message;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 bytes");
+        assert_eq!(format_size(1023), "1023 bytes");
+        assert_eq!(format_size(40_000), "39 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024 + 300_000), "5.3 MiB");
+    }
+
+    #[async_std::test]
+    async fn test_download_limit() {
+        let t = dummy_context().await;
+        assert_eq!(t.ctx.download_limit().await, None);
+
+        t.ctx
+            .set_config(Config::DownloadLimit, Some("200000"))
+            .await
+            .unwrap();
+        assert_eq!(t.ctx.download_limit().await, Some(200000));
+
+        t.ctx
+            .set_config(Config::DownloadLimit, Some("20000"))
+            .await
+            .unwrap();
+        assert_eq!(t.ctx.download_limit().await, Some(MIN_DOWNLOAD_LIMIT));
+    }
+
+    #[async_std::test]
+    async fn test_partial_download() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+
+        let header = b"From: Bob <bob@example.org>\n\
+                       To: alice@example.com\n\
+                       Chat-Version: 1.0\n\
+                       Subject: foo\n\
+                       Message-ID: <Mr.12345678901@example.org>\n\
+                       Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                       Content-Type: text/plain\n\
+                       \n";
+        dc_receive_imf_inner(&t.ctx, header, "INBOX", 1, false, Some(100_000))
+            .await
+            .unwrap();
+
+        let (_, _, msg_id) = message::rfc724_mid_exists(&t.ctx, "Mr.12345678901@example.org")
+            .await
+            .unwrap()
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.download_state(), DownloadState::Available);
+        assert_eq!(msg.get_viewtype(), Viewtype::Text);
+        assert_eq!(msg.get_text().unwrap(), "[97 KiB message]");
+
+        msg_id.download_full(&t.ctx).await.unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.download_state(), DownloadState::InProgress);
+        assert!(msg_id.download_full(&t.ctx).await.is_err());
+
+        // receiving the full message replaces the placeholder
+        let mut full = header.to_vec();
+        full.extend_from_slice(b"hello, this is the full message\n");
+        dc_receive_imf(&t.ctx, &full, "INBOX", 1, false)
+            .await
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.download_state(), DownloadState::Done);
+        assert_eq!(msg.get_text().unwrap(), "hello, this is the full message");
+        assert!(msg_id.download_full(&t.ctx).await.is_err());
+
+        let chat_id = msg.get_chat_id();
+        assert_eq!(chat::get_chat_msgs(&t.ctx, chat_id, 0, None).await.len(), 1);
+        assert_ne!(chat_id, ChatId::new(0));
+    }
+}
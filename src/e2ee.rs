@@ -150,7 +150,8 @@ pub async fn try_decrypt(
     }
 
     /* possibly perform decryption */
-    let private_keyring: Keyring<SignedSecretKey> = Keyring::new_self(context).await?;
+    let mut private_keyring: Keyring<SignedSecretKey> = Keyring::new_self(context).await?;
+    private_keyring.load_previous(context).await?;
     let mut public_keyring_for_validate: Keyring<SignedPublicKey> = Keyring::new();
     let mut signatures = HashSet::default();
 
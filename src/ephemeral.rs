@@ -0,0 +1,347 @@
+//! # Ephemeral messages
+//!
+//! A chat can have an ephemeral timer, see [ChatId::set_ephemeral_timer].
+//! Messages of such a chat carry the timer in the `Chat-Ephemeral-Timer:`
+//! header and are deleted locally and on the server when their timer
+//! expired.  The timer of outgoing messages starts when they are sent,
+//! the timer of incoming messages when they are seen.
+//!
+//! Timer changes are sent to the other chat members as system messages
+//! with `Chat-Content: ephemeral-timer-changed` and are shown as info
+//! messages.  Expired messages are deleted by the `DeleteExpiredMsgs` job
+//! which is scheduled for the message expiring next.
+
+use crate::chat::{self, Chat, ChatId};
+use crate::constants::*;
+use crate::context::Context;
+use crate::dc_tools::time;
+use crate::error::{ensure, Result};
+use crate::events::Event;
+use crate::headerdef::HeaderDef;
+use crate::job::{self, Action, Job};
+use crate::message::{self, Message, MsgId};
+use crate::mimeparser::{MimeMessage, SystemMessage};
+use crate::param::Params;
+use crate::stock::StockMessage;
+
+impl ChatId {
+    /// Returns the ephemeral timer of the chat in seconds, 0 if it is disabled.
+    pub async fn get_ephemeral_timer(self, context: &Context) -> Result<u32> {
+        let timer = context
+            .sql
+            .query_get_value_result(
+                "SELECT ephemeral_timer FROM chats WHERE id=?;",
+                paramsv![self],
+            )
+            .await?;
+        Ok(timer.unwrap_or_default())
+    }
+
+    /// Sets the ephemeral timer of the chat in seconds, 0 disables it.
+    ///
+    /// The new timer applies to messages sent afterwards, it is sent to
+    /// the other chat members and added to the chat as info message.
+    pub async fn set_ephemeral_timer(self, context: &Context, timer: u32) -> Result<()> {
+        let chat = Chat::load_from_db(context, self).await?;
+        ensure!(
+            chat.can_send(),
+            "Cannot set ephemeral timer in chat {}",
+            self
+        );
+        if timer == self.get_ephemeral_timer(context).await? {
+            return Ok(());
+        }
+        self.inner_set_ephemeral_timer(context, timer).await?;
+
+        let mut msg = Message::new(Viewtype::Text);
+        msg.text = Some(stock_ephemeral_timer_changed(context, timer, DC_CONTACT_ID_SELF).await);
+        msg.param.set_cmd(SystemMessage::EphemeralTimerChanged);
+        chat::send_msg(context, self, &mut msg).await?;
+        Ok(())
+    }
+
+    /// Stores the ephemeral timer of the chat without notifying the chat members.
+    pub(crate) async fn inner_set_ephemeral_timer(
+        self,
+        context: &Context,
+        timer: u32,
+    ) -> Result<()> {
+        ensure!(!self.is_special(), "Invalid chat ID");
+        context
+            .sql
+            .execute(
+                "UPDATE chats SET ephemeral_timer=? WHERE id=?;",
+                paramsv![timer, self],
+            )
+            .await?;
+        context.emit_event(Event::ChatModified(self));
+        Ok(())
+    }
+}
+
+/// Returns the info message text for a changed ephemeral timer.
+pub(crate) async fn stock_ephemeral_timer_changed(
+    context: &Context,
+    timer: u32,
+    from_id: u32,
+) -> String {
+    if timer == 0 {
+        context
+            .stock_system_msg(StockMessage::MsgEphemeralTimerDisabled, "", "", from_id)
+            .await
+    } else {
+        context
+            .stock_system_msg(
+                StockMessage::MsgEphemeralTimerEnabled,
+                timer.to_string(),
+                "",
+                from_id,
+            )
+            .await
+    }
+}
+
+/// Returns the ephemeral timer a message was sent with, 0 if none.
+pub(crate) fn timer_from_headers(mime_parser: &MimeMessage) -> u32 {
+    mime_parser
+        .get(HeaderDef::ChatEphemeralTimer)
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or_default()
+}
+
+/// Starts the ephemeral timers of the given messages if not yet started.
+pub(crate) async fn start_timers(context: &Context, msg_ids: &[MsgId]) -> Result<()> {
+    if msg_ids.is_empty() {
+        return Ok(());
+    }
+    let now = time();
+    let msg_ids = msg_ids.to_vec();
+    let started = context
+        .sql
+        .with_conn(move |mut conn| {
+            let tx = conn.transaction()?;
+            let mut started = 0;
+            {
+                let mut stmt = tx.prepare(
+                    "UPDATE msgs SET ephemeral_timestamp=?+ephemeral_timer
+                      WHERE id=? AND ephemeral_timer>0 AND ephemeral_timestamp=0;",
+                )?;
+                for msg_id in msg_ids {
+                    started += stmt.execute(paramsv![now, msg_id])?;
+                }
+            }
+            tx.commit()?;
+            Ok(started)
+        })
+        .await?;
+    if started > 0 {
+        schedule_job(context).await;
+    }
+    Ok(())
+}
+
+/// Schedules the `DeleteExpiredMsgs` job for the message expiring next.
+pub(crate) async fn schedule_job(context: &Context) {
+    let next = context
+        .sql
+        .query_get_value_result::<Option<i64>>(
+            "SELECT MIN(ephemeral_timestamp) FROM msgs
+              WHERE ephemeral_timestamp>0 AND chat_id!=?;",
+            paramsv![DC_CHAT_ID_TRASH],
+        )
+        .await;
+    job::kill_action(context, Action::DeleteExpiredMsgs).await;
+    match next {
+        Ok(Some(Some(timestamp))) => {
+            let delay = std::cmp::max(timestamp - time(), 0);
+            job::add(
+                context,
+                Job::new(Action::DeleteExpiredMsgs, 0, Params::new(), delay),
+            )
+            .await;
+        }
+        Ok(_) => {}
+        Err(err) => warn!(
+            context,
+            "Cannot schedule deleting ephemeral messages: {}", err
+        ),
+    }
+}
+
+/// Deletes messages with an expired ephemeral timer locally and on the server.
+///
+/// Returns true if any message was deleted.
+pub(crate) async fn delete_expired_messages(context: &Context) -> Result<bool> {
+    let msg_ids = context
+        .sql
+        .query_map(
+            "SELECT id FROM msgs
+              WHERE ephemeral_timestamp>0 AND ephemeral_timestamp<=? AND chat_id!=?;",
+            paramsv![time(), DC_CHAT_ID_TRASH],
+            |row| row.get::<_, MsgId>(0),
+            |ids| {
+                ids.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    if msg_ids.is_empty() {
+        return Ok(false);
+    }
+    info!(context, "Deleting {} expired messages", msg_ids.len());
+    message::delete_msgs(context, &msg_ids).await;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::chat::get_chat_msgs;
+    use crate::contact::Contact;
+    use crate::dc_receive_imf::dc_receive_imf;
+    use crate::mimefactory::MimeFactory;
+    use crate::test_utils::*;
+
+    #[async_std::test]
+    async fn test_set_ephemeral_timer() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        let bob = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob).await.unwrap();
+        assert_eq!(chat_id.get_ephemeral_timer(&t.ctx).await.unwrap(), 0);
+
+        chat_id.set_ephemeral_timer(&t.ctx, 60).await.unwrap();
+        assert_eq!(chat_id.get_ephemeral_timer(&t.ctx).await.unwrap(), 60);
+        let msgs = get_chat_msgs(&t.ctx, chat_id, 0, None).await;
+        assert_eq!(msgs.len(), 1);
+        let info = Message::load_from_db(&t.ctx, msgs[0]).await.unwrap();
+        assert_eq!(info.param.get_cmd(), SystemMessage::EphemeralTimerChanged);
+        assert_eq!(info.get_ephemeral_timer(), 60);
+
+        let msg_id = chat::send_text_msg(&t.ctx, chat_id, "hi".to_string())
+            .await
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_ephemeral_timer(), 60);
+        assert!(msg.get_ephemeral_timestamp() > time());
+        let rendered = MimeFactory::from_msg(&t.ctx, &msg, false)
+            .await
+            .unwrap()
+            .render()
+            .await
+            .unwrap();
+        let parsed = MimeMessage::from_bytes(&t.ctx, &rendered.message)
+            .await
+            .unwrap();
+        assert_eq!(timer_from_headers(&parsed), 60);
+
+        // setting the same timer again does not send anything
+        chat_id.set_ephemeral_timer(&t.ctx, 60).await.unwrap();
+        assert_eq!(get_chat_msgs(&t.ctx, chat_id, 0, None).await.len(), 2);
+
+        assert!(ChatId::new(DC_CHAT_ID_TRASH)
+            .set_ephemeral_timer(&t.ctx, 60)
+            .await
+            .is_err());
+    }
+
+    #[async_std::test]
+    async fn test_receive_ephemeral_timer() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        let bob = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob).await.unwrap();
+
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.net\n\
+              To: alice@example.com\n\
+              Subject: Chat: timer\n\
+              Message-ID: <timer@example.net>\n\
+              Chat-Version: 1.0\n\
+              Chat-Content: ephemeral-timer-changed\n\
+              Chat-Ephemeral-Timer: 120\n\
+              Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+              \n\
+              timer changed\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(chat_id.get_ephemeral_timer(&t.ctx).await.unwrap(), 120);
+
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.net\n\
+              To: alice@example.com\n\
+              Subject: Chat: hi\n\
+              Message-ID: <hi@example.net>\n\
+              Chat-Version: 1.0\n\
+              Chat-Ephemeral-Timer: 120\n\
+              Date: Sun, 22 Mar 2020 22:38:57 +0000\n\
+              \n\
+              hi\n",
+            "INBOX",
+            2,
+            false,
+        )
+        .await
+        .unwrap();
+        let msgs = get_chat_msgs(&t.ctx, chat_id, 0, None).await;
+        assert_eq!(msgs.len(), 2);
+        let info = Message::load_from_db(&t.ctx, msgs[0]).await.unwrap();
+        assert_eq!(
+            info.get_text().unwrap(),
+            stock_ephemeral_timer_changed(&t.ctx, 120, bob).await
+        );
+
+        // the timer of incoming messages starts when they are seen
+        let msg = Message::load_from_db(&t.ctx, msgs[1]).await.unwrap();
+        assert_eq!(msg.get_ephemeral_timer(), 120);
+        assert_eq!(msg.get_ephemeral_timestamp(), 0);
+        message::markseen_msgs(&t.ctx, vec![msg.id]).await;
+        let msg = Message::load_from_db(&t.ctx, msg.id).await.unwrap();
+        assert!(msg.get_ephemeral_timestamp() > time());
+        assert!(job::action_exists(&t.ctx, Action::DeleteExpiredMsgs).await);
+    }
+
+    #[async_std::test]
+    async fn test_delete_expired_messages() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        let bob = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob).await.unwrap();
+        chat_id.inner_set_ephemeral_timer(&t.ctx, 60).await.unwrap();
+        let msg_id = chat::send_text_msg(&t.ctx, chat_id, "hi".to_string())
+            .await
+            .unwrap();
+        assert!(!delete_expired_messages(&t.ctx).await.unwrap());
+
+        t.ctx
+            .sql
+            .execute(
+                "UPDATE msgs SET ephemeral_timestamp=? WHERE id=?;",
+                paramsv![time() - 1, msg_id],
+            )
+            .await
+            .unwrap();
+        // expired messages are not shown even if the job did not run yet
+        assert!(get_chat_msgs(&t.ctx, chat_id, 0, None).await.is_empty());
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.chat_id, chat_id);
+
+        assert!(delete_expired_messages(&t.ctx).await.unwrap());
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.chat_id, ChatId::new(DC_CHAT_ID_TRASH));
+        assert!(!delete_expired_messages(&t.ctx).await.unwrap());
+    }
+}
@@ -1,7 +1,72 @@
 //! # Error handling
+//!
+//! The modules callers most often want to react on return typed errors:
+//! [SqlError], [ImapError], [SmtpError], [ConfigureError] from
+//! [crate::context::Context::configure] and [SendMsgError] from
+//! [crate::chat::send_msg] and friends.  Other functions return the
+//! catch-all [Error], which all typed errors convert into;  the typed
+//! error can be recovered using `err.downcast_ref::<SendMsgError>()` etc.
+//! or classified using [ErrorKind::of].
+
+use serde::Serialize;
 
 pub use anyhow::{bail, ensure, format_err, Error, Result};
 
+pub use crate::chat::SendMsgError;
+pub use crate::configure::Error as ConfigureError;
+pub use crate::imap::Error as ImapError;
+pub use crate::smtp::Error as SmtpError;
+pub use crate::sql::Error as SqlError;
+
+/// Kind of an [Error], determined by the typed error it was created from.
+///
+/// The values are the `DC_ERROR_KIND_*` constants of the FFI.
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Serialize)]
+#[repr(i32)]
+pub enum ErrorKind {
+    /// The error is not one of the typed errors.
+    Other = 0,
+
+    Sql = 1,
+    Imap = 2,
+    Smtp = 3,
+    Configure = 4,
+    SendMsg = 5,
+}
+
+impl Default for ErrorKind {
+    fn default() -> Self {
+        ErrorKind::Other
+    }
+}
+
+impl ErrorKind {
+    /// Returns the kind of the outermost typed error in the chain of causes.
+    ///
+    /// Errors wrapped into the `Other` variant of a typed error
+    /// are classified by their own chain of causes.
+    pub fn of(err: &Error) -> Self {
+        for cause in err.chain() {
+            if let Some(SendMsgError::Other(err)) = cause.downcast_ref() {
+                return ErrorKind::of(err);
+            } else if let Some(ConfigureError::Other(err)) = cause.downcast_ref() {
+                return ErrorKind::of(err);
+            } else if cause.is::<SqlError>() {
+                return ErrorKind::Sql;
+            } else if cause.is::<ImapError>() {
+                return ErrorKind::Imap;
+            } else if cause.is::<SmtpError>() {
+                return ErrorKind::Smtp;
+            } else if cause.is::<ConfigureError>() {
+                return ErrorKind::Configure;
+            } else if cause.is::<SendMsgError>() {
+                return ErrorKind::SendMsg;
+            }
+        }
+        ErrorKind::Other
+    }
+}
+
 #[macro_export]
 macro_rules! ensure_eq {
     ($left:expr, $right:expr) => ({
@@ -31,3 +96,33 @@ macro_rules! ensure_eq {
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_kind() {
+        use crate::chat::ChatId;
+
+        let err: Error = SendMsgError::CannotSend(ChatId::new(10)).into();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::SendMsg);
+        assert!(err.downcast_ref::<SendMsgError>().is_some());
+
+        let err = err.context("failed to forward");
+        assert_eq!(ErrorKind::of(&err), ErrorKind::SendMsg);
+
+        let err: Error = SqlError::SqlNoConnection.into();
+        assert_eq!(ErrorKind::of(&err), ErrorKind::Sql);
+
+        let err: SendMsgError = err.into();
+        assert!(matches!(err, SendMsgError::Other(_)));
+        assert_eq!(ErrorKind::of(&err.into()), ErrorKind::Sql);
+
+        let err: Error = SendMsgError::SpecialChat(ChatId::new(1)).into();
+        let err: SendMsgError = err.into();
+        assert!(matches!(err, SendMsgError::SpecialChat(_)));
+
+        assert_eq!(ErrorKind::of(&format_err!("oops")), ErrorKind::Other);
+    }
+}
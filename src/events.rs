@@ -1,28 +1,76 @@
 //! # Events specification
 
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::sync::RwLock;
+use std::task::{self, Poll};
+use std::time::{Duration, Instant};
+
 use async_std::path::PathBuf;
+use async_std::stream::Stream;
 use async_std::sync::{channel, Receiver, Sender, TrySendError};
+use serde::{Serialize, Serializer};
 use strum::EnumProperty;
 
 use crate::chat::ChatId;
+use crate::constants::ConfigureStage;
 use crate::message::MsgId;
 
+/// Maximum number of events buffered for the emitters.
+pub const EVENT_BUFFER_SIZE: usize = 1_000;
+
+/// Time given to the emitters to take the queued events when IO is stopped.
+pub(crate) const EVENT_FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
 #[derive(Debug)]
 pub struct Events {
     receiver: Receiver<Event>,
     sender: Sender<Event>,
+
+    /// IDs of the events to queue, `None` to queue all events.
+    filter: RwLock<Option<HashSet<i32>>>,
 }
 
 impl Default for Events {
     fn default() -> Self {
-        let (sender, receiver) = channel(1_000);
-
-        Self { receiver, sender }
+        Self::new(EVENT_BUFFER_SIZE)
     }
 }
 
 impl Events {
+    /// Waits until the queued events are taken by the emitters, at most `timeout`.
+    pub(crate) async fn flush(&self, timeout: Duration) {
+        let start = Instant::now();
+        while !self.receiver.is_empty() && start.elapsed() < timeout {
+            async_std::task::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Creates the event channel buffering up to `buffer_size` events.
+    pub(crate) fn new(buffer_size: usize) -> Self {
+        let (sender, receiver) = channel(buffer_size);
+
+        Self {
+            receiver,
+            sender,
+            filter: RwLock::new(None),
+        }
+    }
+
+    /// Emits an event without blocking.
+    ///
+    /// If the buffer is full, the oldest event is dropped.
+    /// Events not matching the filter are dropped right away.
     pub fn emit(&self, event: Event) {
+        if let Some(ref ids) = *self.filter.read().unwrap() {
+            if !ids.contains(&event.as_id()) {
+                return;
+            }
+        }
+        self.queue(event);
+    }
+
+    fn queue(&self, event: Event) {
         match self.sender.try_send(event) {
             Ok(()) => {}
             Err(TrySendError::Full(event)) => {
@@ -30,7 +78,7 @@ impl Events {
                 let _ = self.receiver.try_recv();
 
                 // try again
-                self.emit(event);
+                self.queue(event);
             }
             Err(TrySendError::Disconnected(_)) => {
                 unreachable!("unable to emit event, channel disconnected");
@@ -42,8 +90,28 @@ impl Events {
     pub fn get_emitter(&self) -> EventEmitter {
         EventEmitter(self.receiver.clone())
     }
+
+    /// Sets the IDs of the events to queue, see [Event::as_id].
+    ///
+    /// `None` queues all events.
+    pub fn set_filter(&self, ids: Option<HashSet<i32>>) {
+        *self.filter.write().unwrap() = ids;
+    }
 }
 
+/// Receives the events of a context, see [Context::get_event_emitter].
+///
+/// The emitter can be used by calling [EventEmitter::recv] or as a
+/// [Stream], eg. `while let Some(event) = emitter.next().await { ... }`.
+///
+/// Events are buffered up to [EVENT_BUFFER_SIZE] events.  Emitting events
+/// never blocks the context; if the consumer does not keep up and the buffer
+/// is full, the oldest events are dropped to make room for new ones.
+///
+/// All clones of an emitter share the same buffer, so each event is
+/// delivered to only one of them.
+///
+/// [Context::get_event_emitter]: crate::context::Context::get_event_emitter
 #[derive(Debug, Clone)]
 pub struct EventEmitter(Receiver<Event>);
 
@@ -60,6 +128,15 @@ impl EventEmitter {
     }
 }
 
+impl Stream for EventEmitter {
+    type Item = Event;
+
+    /// Returns the next event, `None` once the context has been dropped.
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Event>> {
+        Pin::new(&mut self.0).poll_next(cx)
+    }
+}
+
 impl Event {
     /// Returns the corresponding Event id.
     pub fn as_id(&self) -> i32 {
@@ -70,7 +147,10 @@ impl Event {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, EnumProperty)]
+/// Events are serialized as `{"type": "<variant>", "data": <fields>}`,
+/// eg. for the JSON-RPC API.
+#[derive(Debug, Clone, PartialEq, Eq, EnumProperty, Serialize)]
+#[serde(tag = "type", content = "data")]
 pub enum Event {
     /// The library-user may write an informational string to the log.
     /// Passed to the callback given to dc_context_new().
@@ -155,6 +235,16 @@ pub enum Event {
     #[strum(props(id = "410"))]
     ErrorSelfNotInGroup(String),
 
+    /// The OAuth2 access token cannot be refreshed anymore,
+    /// eg. because the user revoked the access or changed the password.
+    ///
+    /// The user has to authorize again using dc_get_oauth2_url()
+    /// and configure the account with the new code.
+    ///
+    /// @param data2 (String) The address the OAuth2 authorization is for.
+    #[strum(props(id = "420"))]
+    ErrorOauth2ReauthRequired(String),
+
     /// Messages or chats changed.  One or more messages or chats changed for various
     /// reasons in the database:
     /// - Messages sent, received or removed
@@ -167,8 +257,14 @@ pub enum Event {
     /// when receiving this message.
     ///
     /// There is no extra #DC_EVENT_MSGS_CHANGED event send together with this event.
+    ///
+    /// `muted` is set if the chat is muted, UIs should not notify then.
     #[strum(props(id = "2005"))]
-    IncomingMsg { chat_id: ChatId, msg_id: MsgId },
+    IncomingMsg {
+        chat_id: ChatId,
+        msg_id: MsgId,
+        muted: bool,
+    },
 
     /// A single message is sent successfully. State changed from  DC_STATE_OUT_PENDING to
     /// DC_STATE_OUT_DELIVERED, see dc_msg_get_state().
@@ -185,13 +281,42 @@ pub enum Event {
     #[strum(props(id = "2015"))]
     MsgRead { chat_id: ChatId, msg_id: MsgId },
 
-    /// Chat changed.  The name or the image of a chat group was changed or members were added or removed.
+    /// Reactions to a message changed, see reaction::get_reactions().
+    #[strum(props(id = "2016"))]
+    ReactionsChanged { chat_id: ChatId, msg_id: MsgId },
+
+    /// The state of an in-chat app changed, see app::get_app_updates().
+    /// `msg_id` is the ID of the app instance.
+    #[strum(props(id = "2017"))]
+    AppUpdate { chat_id: ChatId, msg_id: MsgId },
+
+    /// The votes of a poll changed, see poll::get_poll_state().
+    #[strum(props(id = "2018"))]
+    PollChanged { chat_id: ChatId, msg_id: MsgId },
+
+    /// A contact started typing in a chat, see chat::send_typing().
+    ///
+    /// The UI should stop showing the contact as typing after
+    /// `chat::TYPING_TIMEOUT` seconds without a new event.
+    #[strum(props(id = "2019"))]
+    ContactTyping { chat_id: ChatId, contact_id: u32 },
+
+    /// A contact stopped typing in a chat without sending a message.
+    #[strum(props(id = "2022"))]
+    ContactStoppedTyping { chat_id: ChatId, contact_id: u32 },
+
+    /// Chat changed.  The name of a chat group was changed or members were added or removed.
     /// Or the verify state of a chat has changed.
-    /// See dc_set_chat_name(), dc_set_chat_profile_image(), dc_add_contact_to_chat()
+    /// See dc_set_chat_name(), dc_add_contact_to_chat()
     /// and dc_remove_contact_from_chat().
     #[strum(props(id = "2020"))]
     ChatModified(ChatId),
 
+    /// The image of a chat group was set, changed or removed,
+    /// see dc_set_chat_profile_image().  Nothing else of the chat changed.
+    #[strum(props(id = "2021"))]
+    ChatImageChanged(ChatId),
+
     /// Contact(s) created, renamed, blocked or deleted.
     ///
     /// @param data1 (int) If set, this is the contact_id of an added contact that should be selected.
@@ -212,6 +337,20 @@ pub enum Event {
     #[strum(props(id = "2041"))]
     ConfigureProgress(usize),
 
+    /// Inform about the stage of the configuration started by configure().
+    ///
+    /// Sent in addition to [Event::ConfigureProgress] whenever a stage starts
+    /// and whenever other server settings are tried.
+    ///
+    /// @param data1 (int) The stage as one of the DC_CONFIGURE_STAGE_* constants.
+    /// @param data2 (char*) What is tried, eg. the URL of the autoconfig file
+    ///     or the server, port and security as `user@imap.example.org:993 SSL`.
+    #[strum(props(id = "2042"))]
+    ConfigureStage {
+        stage: ConfigureStage,
+        comment: String,
+    },
+
     /// Inform about the import/export progress started by imex().
     ///
     /// @param data1 (usize) 0=error, 1-999=progress in permille, 1000=success and done
@@ -227,7 +366,7 @@ pub enum Event {
     ///
     /// @param data2 0
     #[strum(props(id = "2052"))]
-    ImexFileWritten(PathBuf),
+    ImexFileWritten(#[serde(serialize_with = "serialize_path")] PathBuf),
 
     /// Progress information of a secure-join handshake from the view of the inviter
     /// (Alice, the person who shows the QR code).
@@ -254,4 +393,79 @@ pub enum Event {
     ///     (Bob has verified alice and waits until Alice does the same for him)
     #[strum(props(id = "2061"))]
     SecurejoinJoinerProgress { contact_id: u32, progress: usize },
+
+    /// Inform about the progress of housekeeping, eg. started by run_housekeeping().
+    ///
+    /// @param data1 (usize) 0=error, 1-999=progress in permille, 1000=success and done
+    /// @param data2 0
+    #[strum(props(id = "2070"))]
+    HousekeepingProgress(usize),
+
+    /// The state of the connections to the server changed,
+    /// use dc_get_connectivity() to get the new state.
+    ///
+    /// @param data1 0
+    /// @param data2 0
+    #[strum(props(id = "2100"))]
+    ConnectivityChanged,
+}
+
+#[allow(clippy::ptr_arg)]
+fn serialize_path<S: Serializer>(path: &PathBuf, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use async_std::prelude::*;
+
+    #[async_std::test]
+    async fn test_emitter_stream() {
+        let events = Events::default();
+        let mut emitter = events.get_emitter();
+
+        events.emit(Event::Info("first".to_string()));
+        events.emit(Event::Info("second".to_string()));
+        assert_eq!(emitter.next().await, Some(Event::Info("first".to_string())));
+        assert_eq!(
+            emitter.next().await,
+            Some(Event::Info("second".to_string()))
+        );
+    }
+
+    #[async_std::test]
+    async fn test_filter() {
+        let events = Events::default();
+        let mut emitter = events.get_emitter();
+
+        let incoming_msg = Event::IncomingMsg {
+            chat_id: ChatId::new(10),
+            msg_id: MsgId::new(11),
+            muted: false,
+        };
+        events.set_filter(Some([incoming_msg.as_id()].iter().cloned().collect()));
+        events.emit(Event::Info("dropped".to_string()));
+        events.emit(incoming_msg.clone());
+        assert_eq!(emitter.next().await, Some(incoming_msg));
+
+        events.set_filter(None);
+        events.emit(Event::Info("queued".to_string()));
+        assert_eq!(
+            emitter.next().await,
+            Some(Event::Info("queued".to_string()))
+        );
+    }
+
+    #[async_std::test]
+    async fn test_emitter_drops_oldest() {
+        let events = Events::default();
+        let mut emitter = events.get_emitter();
+
+        for i in 0..EVENT_BUFFER_SIZE + 10 {
+            events.emit(Event::Info(i.to_string()));
+        }
+        assert_eq!(emitter.next().await, Some(Event::Info("10".to_string())));
+    }
 }
@@ -0,0 +1,160 @@
+//! # Folder strategy
+//!
+//! Describes how chat messages are sorted into folders on the server:
+//! which folder they are moved to and, depending on the [MvboxPolicy],
+//! whether the folder is created and watched
+//! and whether classic emails are moved as well.
+//!
+//! The strategy is stored in the configuration of each account,
+//! providers may set other defaults, see `provider::Provider::config_defaults`.
+
+use num_traits::FromPrimitive;
+
+use crate::config::Config;
+use crate::constants::MvboxPolicy;
+use crate::context::Context;
+use crate::error::{ensure, Result};
+use crate::message::MessengerMessage;
+
+/// Name of the folder chat messages are moved to by default.
+pub const DEFAULT_MVBOX_FOLDER: &str = "DeltaChat";
+
+/// How chat messages are sorted into folders on the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FolderStrategy {
+    /// Name of the folder chat messages are moved to.
+    ///
+    /// If the folder cannot be created at the top level,
+    /// it is created below INBOX.
+    pub folder: String,
+
+    /// Whether the folder is used and which messages are moved there.
+    pub policy: MvboxPolicy,
+}
+
+impl FolderStrategy {
+    /// Loads the strategy of the account.
+    pub async fn load(context: &Context) -> Self {
+        let folder = context
+            .get_config(Config::MvboxFolder)
+            .await
+            .filter(|folder| !folder.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_MVBOX_FOLDER.to_string());
+        let policy = MvboxPolicy::from_i32(context.get_config_int(Config::MvboxPolicy).await)
+            .unwrap_or_default();
+        FolderStrategy { folder, policy }
+    }
+
+    /// Saves the strategy for the account.
+    ///
+    /// The folders are configured again on the next connection.
+    pub async fn save(&self, context: &Context) -> Result<()> {
+        ensure!(
+            !self.folder.trim().is_empty(),
+            "folder name must not be empty"
+        );
+        context
+            .set_config(Config::MvboxFolder, Some(self.folder.trim()))
+            .await?;
+        context
+            .set_config(Config::MvboxPolicy, Some(&(self.policy as i32).to_string()))
+            .await?;
+        Ok(())
+    }
+
+    /// Returns true if the folder is used at all.
+    pub(crate) fn uses_folder(&self) -> bool {
+        self.policy != MvboxPolicy::Off
+    }
+
+    /// Returns true if messages of any kind are moved to the folder.
+    pub(crate) fn moves_msgs(&self) -> bool {
+        match self.policy {
+            MvboxPolicy::Off | MvboxPolicy::Watch => false,
+            MvboxPolicy::MoveChats | MvboxPolicy::MoveAll => true,
+        }
+    }
+
+    /// Returns true if a message of the given kind should be moved to the folder.
+    pub(crate) fn should_move(&self, is_dc_message: MessengerMessage) -> bool {
+        match is_dc_message {
+            MessengerMessage::Yes | MessengerMessage::Reply => self.moves_msgs(),
+            MessengerMessage::No => self.policy == MvboxPolicy::MoveAll,
+        }
+    }
+
+    /// Returns the name of the folder below INBOX
+    /// used if the folder cannot be created at the top level.
+    pub(crate) fn fallback_folder(&self, delimiter: &str) -> String {
+        format!("INBOX{}{}", delimiter, self.folder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[async_std::test]
+    async fn test_folder_strategy() {
+        let t = dummy_context().await;
+        let strategy = FolderStrategy::load(&t.ctx).await;
+        assert_eq!(
+            strategy,
+            FolderStrategy {
+                folder: "DeltaChat".to_string(),
+                policy: MvboxPolicy::MoveChats,
+            }
+        );
+        assert!(strategy.uses_folder());
+        assert!(strategy.should_move(MessengerMessage::Reply));
+        assert!(!strategy.should_move(MessengerMessage::No));
+        assert_eq!(strategy.fallback_folder("."), "INBOX.DeltaChat");
+
+        let strategy = FolderStrategy {
+            folder: "Chats".to_string(),
+            policy: MvboxPolicy::Watch,
+        };
+        strategy.save(&t.ctx).await.unwrap();
+        assert_eq!(FolderStrategy::load(&t.ctx).await, strategy);
+        assert!(strategy.uses_folder());
+        assert!(!strategy.should_move(MessengerMessage::Yes));
+
+        let strategy = FolderStrategy {
+            folder: "Chats".to_string(),
+            policy: MvboxPolicy::MoveAll,
+        };
+        assert!(strategy.should_move(MessengerMessage::No));
+
+        let mut strategy = strategy;
+        strategy.folder = " ".to_string();
+        assert!(strategy.save(&t.ctx).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn test_folder_change_resets_configured_folder() {
+        let t = dummy_context().await;
+        t.ctx
+            .set_config(Config::ConfiguredMvboxFolder, Some("DeltaChat"))
+            .await
+            .unwrap();
+        t.ctx
+            .sql
+            .set_raw_config_int(&t.ctx, "folders_configured", 1)
+            .await
+            .unwrap();
+
+        t.ctx
+            .set_config(Config::MvboxFolder, Some("Chats"))
+            .await
+            .unwrap();
+        assert_eq!(t.ctx.get_config(Config::ConfiguredMvboxFolder).await, None);
+        assert_eq!(
+            t.ctx
+                .sql
+                .get_raw_config_int(&t.ctx, "folders_configured")
+                .await,
+            Some(0)
+        );
+    }
+}
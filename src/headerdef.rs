@@ -17,7 +17,14 @@ pub enum HeaderDef {
     /// Delta Chat extension for message IDs in combined MDNs
     AdditionalMessageIds,
 
+    /// Per-recipient fields of delivery status notifications, RFC 3464
+    FinalRecipient,
+    Action,
+    Status,
+    DiagnosticCode,
+
     ListId,
+    ListPost,
     References,
     InReplyTo,
     Precedence,
@@ -31,9 +38,20 @@ pub enum HeaderDef {
     ChatVoiceMessage,
     ChatGroupMemberRemoved,
     ChatGroupMemberAdded,
+    ChatGroupAdminAdded,
+    ChatGroupAdminRemoved,
     ChatContent,
     ChatDuration,
+    ChatVideoThumbnail,
     ChatDispositionNotificationTo,
+    ChatReaction,
+    ChatEdit,
+    ChatAppUpdate,
+    ChatPollOptions,
+    ChatPollVote,
+    ChatTyping,
+    ChatSync,
+    ChatEphemeralTimer,
     Autocrypt,
     AutocryptSetupMessage,
     SecureJoin,
@@ -0,0 +1,177 @@
+//! # HEIF and AVIF images
+//!
+//! iPhones store photos as HEIC, which is a HEIF image with HEVC coding;
+//! AVIF uses the same container with AV1 coding.  Many clients cannot show
+//! these images, so outgoing images are converted to JPEG if the crate is
+//! built with the `heif` feature, which links against libheif.
+//!
+//! The dimensions are read from the container without decoding the image,
+//! see ISO/IEC 23008-12.
+
+/// Brands in the `ftyp` box identifying HEIF and AVIF images.
+const BRANDS: [&[u8]; 10] = [
+    b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1", b"avif", b"avis",
+];
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    let bytes = buf.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Returns true if the buffer contains a HEIF or AVIF image.
+pub(crate) fn is_heif(buf: &[u8]) -> bool {
+    if buf.get(4..8) != Some(&b"ftyp"[..]) {
+        return false;
+    }
+    let size = read_u32(buf, 0).unwrap_or_default() as usize;
+    let major_brand = &buf[8..buf.len().min(12)];
+    let compatible_brands = buf.get(16..size.min(buf.len())).unwrap_or_default();
+    std::iter::once(major_brand)
+        .chain(compatible_brands.chunks(4))
+        .any(|brand| BRANDS.contains(&brand))
+}
+
+/// Returns the top-level box of the given type.
+fn find_box<'a>(buf: &'a [u8], box_type: &[u8]) -> Option<&'a [u8]> {
+    let mut offset = 0;
+    while offset + 8 <= buf.len() {
+        let size = match read_u32(buf, offset)? as usize {
+            // the box extends to the end of the file
+            0 => buf.len() - offset,
+            // 64-bit size following the type
+            1 => {
+                let high = read_u32(buf, offset + 8)? as usize;
+                let low = read_u32(buf, offset + 12)? as usize;
+                if high != 0 {
+                    return None;
+                }
+                low
+            }
+            size => size,
+        };
+        if size < 8 {
+            return None;
+        }
+        if &buf[offset + 4..offset + 8] == box_type {
+            return buf.get(offset..offset + size);
+        }
+        offset += size;
+    }
+    None
+}
+
+/// Returns the `(width, height)` of a HEIF or AVIF image.
+///
+/// The `meta` box contains an `ispe` property with the size of each image
+/// item; photos are often stored as grid of tiles, so the largest size is
+/// the one of the whole image.
+pub(crate) fn get_dimensions(buf: &[u8]) -> Option<(u32, u32)> {
+    let meta = find_box(buf, b"meta")?;
+    let mut dimensions: Option<(u32, u32)> = None;
+    let mut offset = 4;
+    while offset + 16 <= meta.len() {
+        if &meta[offset..offset + 4] == b"ispe" {
+            // the box type is followed by version and flags
+            let width = read_u32(meta, offset + 8)?;
+            let height = read_u32(meta, offset + 12)?;
+            let area = u64::from(width) * u64::from(height);
+            if dimensions
+                .map(|(w, h)| u64::from(w) * u64::from(h) < area)
+                .unwrap_or(true)
+            {
+                dimensions = Some((width, height));
+            }
+            offset += 16;
+        } else {
+            offset += 1;
+        }
+    }
+    dimensions
+}
+
+/// Decodes a HEIF or AVIF image.
+#[cfg(feature = "heif")]
+pub(crate) fn decode(buf: &[u8]) -> image::ImageResult<image::DynamicImage> {
+    use libheif_rs::{ColorSpace, HeifContext, RgbChroma};
+
+    let format_error = |err: libheif_rs::HeifError| image::ImageError::FormatError(err.to_string());
+    let context = HeifContext::read_from_bytes(buf).map_err(format_error)?;
+    let handle = context.primary_image_handle().map_err(format_error)?;
+    let decoded = handle
+        .decode(ColorSpace::Rgb(RgbChroma::Rgb), false)
+        .map_err(format_error)?;
+    let planes = decoded.planes();
+    let plane = planes.interleaved.ok_or_else(|| {
+        image::ImageError::FormatError("HEIF image without interleaved plane".to_string())
+    })?;
+
+    // rows may be padded to the stride
+    let row_len = plane.width as usize * 3;
+    let mut rgb = Vec::with_capacity(row_len * plane.height as usize);
+    for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        rgb.extend_from_slice(&row[..row_len]);
+    }
+    let img = image::RgbImage::from_raw(plane.width, plane.height, rgb)
+        .ok_or(image::ImageError::DimensionError)?;
+    Ok(image::DynamicImage::ImageRgb8(img))
+}
+
+/// Decodes a HEIF or AVIF image, not supported without the `heif` feature.
+#[cfg(not(feature = "heif"))]
+pub(crate) fn decode(_buf: &[u8]) -> image::ImageResult<image::DynamicImage> {
+    Err(image::ImageError::UnsupportedError(
+        "HEIF images are not supported by this build".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the header of a HEIC photo of 4032x3024 pixels stored as 512x512 tiles.
+    fn heic_header() -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&24u32.to_be_bytes());
+        buf.extend_from_slice(b"ftypheic\0\0\0\0mif1heic");
+
+        let ispe = |width: u32, height: u32| {
+            let mut ispe = Vec::new();
+            ispe.extend_from_slice(&20u32.to_be_bytes());
+            ispe.extend_from_slice(b"ispe\0\0\0\0");
+            ispe.extend_from_slice(&width.to_be_bytes());
+            ispe.extend_from_slice(&height.to_be_bytes());
+            ispe
+        };
+        let mut meta = Vec::new();
+        meta.extend_from_slice(b"meta\0\0\0\0");
+        meta.extend(ispe(512, 512));
+        meta.extend(ispe(4032, 3024));
+        buf.extend_from_slice(&(meta.len() as u32 + 4).to_be_bytes());
+        buf.extend(meta);
+
+        buf.extend_from_slice(&16u32.to_be_bytes());
+        buf.extend_from_slice(b"mdat\x01\x02\x03\x04\x05\x06\x07\x08");
+        buf
+    }
+
+    #[test]
+    fn test_is_heif() {
+        assert!(is_heif(&heic_header()));
+        assert!(is_heif(b"\0\0\0\x1cftypavif\0\0\0\0avifmif1miaf"));
+        assert!(!is_heif(b"\0\0\0\x18ftypisom\0\0\x02\0isomiso2"));
+        assert!(!is_heif(b"\xff\xd8\xff\xe0\0\x10JFIF"));
+        assert!(!is_heif(b""));
+    }
+
+    #[test]
+    fn test_get_dimensions() {
+        assert_eq!(get_dimensions(&heic_header()), Some((4032, 3024)));
+        assert_eq!(get_dimensions(b"\0\0\0\x10ftypheic\0\0\0\0"), None);
+    }
+
+    #[cfg(not(feature = "heif"))]
+    #[test]
+    fn test_decode_unsupported() {
+        assert!(decode(&heic_header()).is_err());
+    }
+}
@@ -4,10 +4,10 @@ use async_imap::{
     error::{Error as ImapError, Result as ImapResult},
     Client as ImapClient,
 };
-use async_std::net::{self, TcpStream};
 
 use super::session::Session;
 use crate::login_param::{dc_build_tls, CertificateChecks};
+use crate::socks::{connect_tcp, Socks5Config};
 
 use super::session::SessionStream;
 
@@ -75,15 +75,15 @@ impl Client {
         Ok(Session { inner: session })
     }
 
-    pub async fn connect_secure<A: net::ToSocketAddrs, S: AsRef<str>>(
-        addr: A,
-        domain: S,
+    pub async fn connect_secure(
+        domain: &str,
+        port: u16,
+        socks5: Option<&Socks5Config>,
         certificate_checks: CertificateChecks,
     ) -> ImapResult<Self> {
-        let stream = TcpStream::connect(addr).await?;
+        let stream = connect_tcp(socks5, domain, port).await?;
         let tls = dc_build_tls(certificate_checks);
-        let tls_stream: Box<dyn SessionStream> =
-            Box::new(tls.connect(domain.as_ref(), stream).await?);
+        let tls_stream: Box<dyn SessionStream> = Box::new(tls.connect(domain, stream).await?);
         let mut client = ImapClient::new(tls_stream);
         if std::env::var(crate::DCC_IMAP_DEBUG).is_ok() {
             client.debug = true;
@@ -100,8 +100,12 @@ impl Client {
         })
     }
 
-    pub async fn connect_insecure<A: net::ToSocketAddrs>(addr: A) -> ImapResult<Self> {
-        let stream: Box<dyn SessionStream> = Box::new(TcpStream::connect(addr).await?);
+    pub async fn connect_insecure(
+        domain: &str,
+        port: u16,
+        socks5: Option<&Socks5Config>,
+    ) -> ImapResult<Self> {
+        let stream: Box<dyn SessionStream> = Box::new(connect_tcp(socks5, domain, port).await?);
 
         let mut client = ImapClient::new(stream);
         if std::env::var(crate::DCC_IMAP_DEBUG).is_ok() {
@@ -0,0 +1,325 @@
+//! # CONDSTORE/QRESYNC flag synchronization
+//!
+//! With CONDSTORE, see <https://tools.ietf.org/html/rfc7162>, the server
+//! increases the mod-sequence of a folder on every flag change.  Remembering
+//! the HIGHESTMODSEQ of a folder allows to fetch only the flags changed
+//! since the last synchronization instead of all flags.  With QRESYNC,
+//! the server additionally reports messages expunged in between as VANISHED.
+
+use super::{Error, Imap, Result};
+
+use crate::chat::ChatId;
+use crate::context::Context;
+use crate::events::Event;
+use crate::message::{MessageState, MsgId};
+
+/// Flag changes reported by a `CHANGEDSINCE` fetch.
+#[derive(Debug, Default, PartialEq)]
+struct FlagChanges {
+    /// UIDs of messages having the `\Seen` flag set.
+    seen: Vec<u32>,
+
+    /// UIDs of messages having the `\Flagged` flag set.
+    flagged: Vec<u32>,
+
+    /// UIDs of changed messages not having the `\Flagged` flag set.
+    unflagged: Vec<u32>,
+
+    /// UID ranges of expunged messages, only reported with QRESYNC.
+    vanished: Vec<(u32, u32)>,
+}
+
+impl Imap {
+    pub fn can_condstore(&self) -> bool {
+        self.config.can_condstore
+    }
+
+    /// Enables QRESYNC for the session, if the server supports it.
+    ///
+    /// QRESYNC has to be enabled once after login before it can be used.
+    pub(super) async fn enable_qresync(&mut self, context: &Context) {
+        if !self.config.can_qresync {
+            return;
+        }
+        if let Some(ref mut session) = self.session {
+            if let Err(err) = session.run_command_and_check_ok("ENABLE QRESYNC").await {
+                warn!(context, "failed to enable QRESYNC: {}", err);
+                self.config.can_qresync = false;
+            }
+        }
+    }
+
+    /// Synchronizes the `\Seen` and `\Flagged` flags and, with QRESYNC,
+    /// the expunges of the given folder that happened since the last synchronization.
+    ///
+    /// The first synchronization of a folder only remembers the current
+    /// HIGHESTMODSEQ.
+    pub(crate) async fn sync_flag_changes(
+        &mut self,
+        context: &Context,
+        folder: &str,
+    ) -> Result<()> {
+        if !self.can_condstore() {
+            return Ok(());
+        }
+        self.select_folder(context, Some(folder)).await?;
+
+        let uid_validity = self
+            .config
+            .selected_mailbox
+            .as_ref()
+            .and_then(|mailbox| mailbox.uid_validity)
+            .ok_or_else(|| Error::NoMailbox(folder.to_string()))?;
+        let can_qresync = self.config.can_qresync;
+        let session = self.session.as_mut().ok_or(Error::NoConnection)?;
+
+        let response = session
+            .run_command_and_read_response(format!(
+                "STATUS {} (HIGHESTMODSEQ)",
+                quote_folder_name(folder)
+            ))
+            .await?;
+        let highest_modseq = parse_highest_modseq(&String::from_utf8_lossy(&response))
+            .ok_or_else(|| Error::Other(format!("No HIGHESTMODSEQ for folder {:?}", folder)))?;
+
+        let (last_uid_validity, last_modseq) = get_modseq(context, folder).await;
+        if last_uid_validity != uid_validity || last_modseq == 0 {
+            info!(
+                context,
+                "CONDSTORE: starting sync of \"{}\" at modseq {}", folder, highest_modseq
+            );
+            set_modseq(context, folder, uid_validity, highest_modseq).await?;
+            return Ok(());
+        }
+        if highest_modseq == last_modseq {
+            // nothing changed
+            return Ok(());
+        }
+
+        let modifiers = if can_qresync {
+            format!("(CHANGEDSINCE {} VANISHED)", last_modseq)
+        } else {
+            format!("(CHANGEDSINCE {})", last_modseq)
+        };
+        let response = session
+            .run_command_and_read_response(format!("UID FETCH 1:* (FLAGS) {}", modifiers))
+            .await?;
+        let changes = parse_flag_changes(&String::from_utf8_lossy(&response));
+        info!(
+            context,
+            "CONDSTORE: \"{}\" changed from modseq {} to {}: {} seen, {} flagged, {} vanished ranges",
+            folder,
+            last_modseq,
+            highest_modseq,
+            changes.seen.len(),
+            changes.flagged.len(),
+            changes.vanished.len()
+        );
+
+        apply_flag_changes(context, folder, &changes).await?;
+        set_modseq(context, folder, uid_validity, highest_modseq).await?;
+        Ok(())
+    }
+}
+
+/// Returns the (UIDVALIDITY, HIGHESTMODSEQ) of the last sync of a folder.
+async fn get_modseq(context: &Context, folder: &str) -> (u32, u64) {
+    context
+        .sql
+        .query_row(
+            "SELECT uid_validity, modseq FROM imap_sync WHERE folder=?;",
+            paramsv![folder],
+            |row| {
+                let uid_validity: i64 = row.get(0)?;
+                let modseq: i64 = row.get(1)?;
+                Ok((uid_validity as u32, modseq as u64))
+            },
+        )
+        .await
+        .unwrap_or_default()
+}
+
+async fn set_modseq(context: &Context, folder: &str, uid_validity: u32, modseq: u64) -> Result<()> {
+    context
+        .sql
+        .execute(
+            "INSERT OR REPLACE INTO imap_sync (folder, uid_validity, modseq) VALUES (?,?,?);",
+            paramsv![folder, uid_validity as i64, modseq as i64],
+        )
+        .await?;
+    Ok(())
+}
+
+async fn apply_flag_changes(context: &Context, folder: &str, changes: &FlagChanges) -> Result<()> {
+    let mut updated = 0;
+    for uid in &changes.seen {
+        updated += context
+            .sql
+            .execute(
+                "UPDATE msgs SET state=? \
+                 WHERE server_folder=? AND server_uid=? AND (state=? OR state=?);",
+                paramsv![
+                    MessageState::InSeen,
+                    folder,
+                    *uid as i64,
+                    MessageState::InFresh,
+                    MessageState::InNoticed
+                ],
+            )
+            .await?;
+    }
+    updated += set_starred(context, folder, &changes.flagged, true).await?;
+    updated += set_starred(context, folder, &changes.unflagged, false).await?;
+    for (start, end) in &changes.vanished {
+        // server_uid=0 marks messages already deleted on the server.
+        context
+            .sql
+            .execute(
+                "UPDATE msgs SET server_uid=0 \
+                 WHERE server_folder=? AND server_uid>=? AND server_uid<=?;",
+                paramsv![folder, *start as i64, *end as i64],
+            )
+            .await?;
+    }
+    if updated > 0 {
+        context.emit_event(Event::MsgsChanged {
+            msg_id: MsgId::new(0),
+            chat_id: ChatId::new(0),
+        });
+    }
+    Ok(())
+}
+
+/// Stars or unstars the messages with the given UIDs in `folder`
+/// to reflect the `\Flagged` flag set by other mail clients.
+///
+/// No job is created to set the flag on the server again.
+/// Returns the number of messages changed.
+pub(super) async fn set_starred(
+    context: &Context,
+    folder: &str,
+    uids: &[u32],
+    starred: bool,
+) -> Result<usize> {
+    let mut updated = 0;
+    for uid in uids {
+        updated += context
+            .sql
+            .execute(
+                "UPDATE msgs SET starred=? WHERE server_folder=? AND server_uid=? AND starred!=?;",
+                paramsv![starred as i32, folder, *uid as i64, starred as i32],
+            )
+            .await?;
+    }
+    Ok(updated)
+}
+
+fn quote_folder_name(folder: &str) -> String {
+    format!("\"{}\"", folder.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Parses the value of HIGHESTMODSEQ from a STATUS response,
+/// eg. `* STATUS "INBOX" (HIGHESTMODSEQ 7011231777)`.
+fn parse_highest_modseq(response: &str) -> Option<u64> {
+    let pos = response.find("HIGHESTMODSEQ ")?;
+    response[pos + "HIGHESTMODSEQ ".len()..]
+        .split(|c: char| !c.is_ascii_digit())
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Parses the untagged FETCH and VANISHED responses of a `CHANGEDSINCE` fetch.
+fn parse_flag_changes(response: &str) -> FlagChanges {
+    let mut changes = FlagChanges::default();
+    for line in response.lines() {
+        let line = line.trim();
+        if line.starts_with("* VANISHED ") {
+            let set = line["* VANISHED ".len()..]
+                .trim_start_matches("(EARLIER)")
+                .trim();
+            changes.vanished.extend(parse_uid_set(set));
+        } else if line.starts_with("* ") && line.contains(" FETCH (") {
+            let uid = match find_number_after(line, "UID ") {
+                Some(uid) => uid,
+                None => continue,
+            };
+            let flags = line
+                .find("FLAGS (")
+                .map(|pos| &line[pos + "FLAGS (".len()..])
+                .and_then(|rest| rest.split(')').next())
+                .unwrap_or_default();
+            if flags.split_whitespace().any(|flag| flag == "\\Seen") {
+                changes.seen.push(uid);
+            }
+            if flags.split_whitespace().any(|flag| flag == "\\Flagged") {
+                changes.flagged.push(uid);
+            } else {
+                changes.unflagged.push(uid);
+            }
+        }
+    }
+    changes
+}
+
+fn find_number_after(line: &str, prefix: &str) -> Option<u32> {
+    let pos = line.find(prefix)?;
+    line[pos + prefix.len()..]
+        .split(|c: char| !c.is_ascii_digit())
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Parses an IMAP UID set like `41,43:116` into inclusive ranges.
+fn parse_uid_set(set: &str) -> Vec<(u32, u32)> {
+    set.split(',')
+        .filter_map(|range| {
+            let mut bounds = range.trim().splitn(2, ':');
+            let start: u32 = bounds.next()?.parse().ok()?;
+            let end: u32 = match bounds.next() {
+                Some(end) => end.parse().ok()?,
+                None => start,
+            };
+            Some((start.min(end), start.max(end)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_highest_modseq() {
+        assert_eq!(
+            parse_highest_modseq("* STATUS \"INBOX\" (HIGHESTMODSEQ 7011231777)\r\n"),
+            Some(7011231777)
+        );
+        assert_eq!(parse_highest_modseq("* STATUS \"INBOX\" ()\r\n"), None);
+    }
+
+    #[test]
+    fn test_parse_flag_changes() {
+        let response = "* VANISHED (EARLIER) 41,43:116,120\r\n\
+                        * 1 FETCH (UID 4 MODSEQ (65402) FLAGS (\\Seen))\r\n\
+                        * 2 FETCH (UID 6 MODSEQ (75403) FLAGS (\\Deleted \\Flagged))\r\n\
+                        * 4 FETCH (FLAGS (\\Answered \\Seen) UID 8 MODSEQ (29738))\r\n";
+        assert_eq!(
+            parse_flag_changes(response),
+            FlagChanges {
+                seen: vec![4, 8],
+                flagged: vec![6],
+                unflagged: vec![4, 8],
+                vanished: vec![(41, 41), (43, 116), (120, 120)],
+            }
+        );
+        assert_eq!(parse_flag_changes(""), FlagChanges::default());
+    }
+
+    #[test]
+    fn test_quote_folder_name() {
+        assert_eq!(quote_folder_name("INBOX"), "\"INBOX\"");
+        assert_eq!(quote_folder_name("a\"b\\c"), "\"a\\\"b\\\\c\"");
+    }
+}
@@ -4,6 +4,8 @@ use async_imap::extensions::idle::IdleResponse;
 use async_std::prelude::*;
 use std::time::{Duration, SystemTime};
 
+use crate::config::Config;
+use crate::dc_tools::time;
 use crate::{context::Context, scheduler::InterruptInfo};
 
 use super::select_folder;
@@ -11,6 +13,28 @@ use super::session::Session;
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// Number of dead IDLE sessions after which the connection falls back to polling.
+const MAX_IDLE_FAILURES: u32 = 2;
+
+/// Lower bound of the configured IDLE timeout and poll interval, in seconds.
+const MIN_IDLE_TIMEOUT: i64 = 60;
+const MIN_POLL_INTERVAL: u64 = 10;
+
+/// Seconds between the polls of two connections, see `poll_delay`.
+const POLL_STAGGER: u64 = 2;
+
+/// Returns the time from `now` until the next poll of the connection in `slot`.
+///
+/// Polls are aligned to multiples of the interval, so that the folders are polled
+/// within a few seconds and the radio is woken up only once,
+/// offset by the slot so the connections are not opened at the same instant.
+fn poll_delay(now: u64, interval: u64, slot: u32) -> Duration {
+    let interval = interval.max(MIN_POLL_INTERVAL);
+    let offset = u64::from(slot) * POLL_STAGGER % interval;
+    let last = now.saturating_sub(offset) / interval * interval + offset;
+    Duration::from_secs(last + interval - now)
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("IMAP IDLE protocol failed to init/complete")]
@@ -34,6 +58,34 @@ impl Imap {
         self.config.can_idle
     }
 
+    /// Returns true if IDLE should be used,
+    /// false if the server does not support it or IDLE sessions of the current connection
+    /// were found dead repeatedly.
+    /// The folder is polled then, see `Imap::fake_idle`.
+    pub fn use_idle(&self) -> bool {
+        self.can_idle() && self.idle_failures < MAX_IDLE_FAILURES
+    }
+
+    /// Sets the slot of the connection to stagger its polls with other connections.
+    pub(crate) fn set_poll_slot(&mut self, slot: u32) {
+        self.poll_slot = slot;
+    }
+
+    /// Forgets the dead IDLE sessions, so IDLE is tried again.
+    pub(crate) fn reset_idle_failures(&mut self) {
+        self.idle_failures = 0;
+    }
+
+    fn idle_failed(&mut self, context: &Context) {
+        self.idle_failures += 1;
+        if self.idle_failures == MAX_IDLE_FAILURES {
+            warn!(
+                context,
+                "IDLE does not seem to work on this server, falling back to polling"
+            );
+        }
+    }
+
     pub async fn idle(
         &mut self,
         context: &Context,
@@ -49,8 +101,13 @@ impl Imap {
         self.select_folder(context, watch_folder.clone()).await?;
 
         let session = self.session.take();
-        let timeout = Duration::from_secs(23 * 60);
+        let timeout = context
+            .get_config_int(Config::IdleTimeout)
+            .await
+            .max(MIN_IDLE_TIMEOUT as i32);
+        let timeout = Duration::from_secs(timeout as u64);
         let mut info = Default::default();
+        let mut timed_out = false;
 
         if let Some(session) = session {
             let mut handle = session.idle();
@@ -84,12 +141,13 @@ impl Imap {
                 match fut.await {
                     Ok(Event::IdleResponse(IdleResponse::NewData(_))) => {
                         info!(context, "Idle has NewData");
+                        self.idle_failures = 0;
                     }
-                    // TODO: idle_wait does not distinguish manual interrupts
-                    // from Timeouts if we would know it's a Timeout we could bail
-                    // directly and reconnect .
+                    // The interrupt handle is kept until the wait is over,
+                    // so a timeout means the server did not send anything within the window.
                     Ok(Event::IdleResponse(IdleResponse::Timeout)) => {
-                        info!(context, "Idle-wait timeout or interruption");
+                        info!(context, "Idle-wait timeout");
+                        timed_out = true;
                     }
                     Ok(Event::IdleResponse(IdleResponse::ManualInterrupt)) => {
                         info!(context, "Idle wait was interrupted");
@@ -106,6 +164,8 @@ impl Imap {
 
             // if we can't properly terminate the idle
             // protocol let's break the connection.
+            // This means the connection is lost, not that IDLE is dead,
+            // so it is not counted as an IDLE failure.
             let res = handle
                 .done()
                 .timeout(Duration::from_secs(15))
@@ -127,11 +187,40 @@ impl Imap {
                     return Err(Error::IdleProtocolFailed(err));
                 }
             }
+
+            // Some servers drop IDLE silently, the session then stays quiet
+            // although new messages arrive. Check whether IDLE missed some.
+            if timed_out {
+                if let Some(ref watch_folder) = watch_folder {
+                    match self.fetch_new_messages(context, watch_folder).await {
+                        Ok(true) => {
+                            warn!(context, "IDLE missed new messages in {}", watch_folder);
+                            self.idle_failed(context);
+                        }
+                        Ok(false) => {}
+                        Err(err) => {
+                            warn!(context, "could not check folder after IDLE: {}", err);
+                            self.trigger_reconnect();
+                        }
+                    }
+                }
+            }
         }
 
         Ok(info)
     }
 
+    /// Waits for an interrupt, at most for the given duration.
+    ///
+    /// Used to poll transports replacing IMAP.
+    pub(crate) async fn wait_for_interrupt(&mut self, timeout: Duration) -> InterruptInfo {
+        async_std::future::timeout(timeout, self.idle_interrupt.recv())
+            .await
+            .ok()
+            .and_then(|info| info.ok())
+            .unwrap_or_default()
+    }
+
     pub(crate) async fn fake_idle(
         &mut self,
         context: &Context,
@@ -155,9 +244,8 @@ impl Imap {
             self.skip_next_idle_wait = false;
             info!(context, "fake-idle wait was skipped");
         } else {
-            // check every minute if there are new messages
-            // TODO: grow sleep durations / make them more flexible
-            let mut interval = async_std::stream::interval(Duration::from_secs(60));
+            // check for new messages in the configured interval
+            let interval = context.get_config_int(Config::PollInterval).await.max(0) as u64;
 
             enum Event {
                 Tick,
@@ -167,8 +255,8 @@ impl Imap {
             info =
                 loop {
                     use futures::future::FutureExt;
-                    match interval
-                        .next()
+                    let delay = poll_delay(time() as u64, interval, self.poll_slot);
+                    match async_std::task::sleep(delay)
                         .map(|_| Event::Tick)
                         .race(self.idle_interrupt.recv().map(|probe_network| {
                             Event::Interrupt(probe_network.unwrap_or_default())
@@ -183,7 +271,7 @@ impl Imap {
                                 warn!(context, "fake_idle: could not connect: {}", err);
                                 continue;
                             }
-                            if self.config.can_idle {
+                            if self.use_idle() {
                                 // we only fake-idled because network was gone during IDLE, probably
                                 break InterruptInfo::new(false, None);
                             }
@@ -229,3 +317,27 @@ impl Imap {
         info
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poll_delay() {
+        assert_eq!(poll_delay(125, 60, 0), Duration::from_secs(55));
+        assert_eq!(poll_delay(120, 60, 0), Duration::from_secs(60));
+        assert_eq!(poll_delay(121, 60, 1), Duration::from_secs(1));
+        assert_eq!(poll_delay(125, 60, 1), Duration::from_secs(57));
+        assert_eq!(poll_delay(125, 60, 2), Duration::from_secs(59));
+
+        // the folders are polled within a few seconds
+        let now = 1_600_000_007;
+        let polls: Vec<u64> = (0..3)
+            .map(|slot| now + poll_delay(now, 300, slot).as_secs())
+            .collect();
+        assert_eq!(polls[1] - polls[0], POLL_STAGGER);
+        assert_eq!(polls[2] - polls[0], 2 * POLL_STAGGER);
+
+        assert_eq!(poll_delay(125, 0, 0), Duration::from_secs(5));
+    }
+}
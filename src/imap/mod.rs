@@ -11,25 +11,35 @@ use async_imap::{
 };
 use async_std::prelude::*;
 use async_std::sync::Receiver;
+use async_trait::async_trait;
+use deltachat_derive::{FromSql, ToSql};
 use num_traits::FromPrimitive;
 
+use crate::chat::{self, ChatId};
 use crate::config::*;
 use crate::constants::*;
+use crate::contact::{Contact, Origin};
 use crate::context::Context;
 use crate::dc_receive_imf::{
-    dc_receive_imf, from_field_to_contact_id, is_msgrmsg_rfc724_mid_in_list,
+    dc_receive_imf_inner, from_field_to_contact_id, is_msgrmsg_rfc724_mid_in_list,
 };
 use crate::events::Event;
+use crate::folder_strategy::FolderStrategy;
 use crate::headerdef::{HeaderDef, HeaderDefMap};
 use crate::job::{self, Action};
 use crate::login_param::{CertificateChecks, LoginParam};
+use crate::message::MsgId;
 use crate::message::{self, update_server_uid};
 use crate::mimeparser;
-use crate::oauth2::dc_get_oauth2_access_token;
-use crate::param::Params;
+use crate::oauth2::{self, dc_get_oauth2_access_token};
+use crate::param::{Param, Params};
+use crate::push;
+use crate::socks::Socks5Config;
+use crate::transport::FetchTransport;
 use crate::{scheduler::InterruptInfo, stock::StockMessage};
 
 mod client;
+mod condstore;
 mod idle;
 pub mod select_folder;
 mod session;
@@ -95,7 +105,8 @@ pub enum ImapActionResult {
 /// - Chat-Version to check if a message is a chat message
 /// - Autocrypt-Setup-Message to check if a message is an autocrypt setup message,
 ///   not necessarily sent by Delta Chat.
-const PREFETCH_FLAGS: &str = "(UID BODY.PEEK[HEADER.FIELDS (\
+/// - The size to check if the message exceeds the download limit.
+const PREFETCH_FLAGS: &str = "(UID RFC822.SIZE BODY.PEEK[HEADER.FIELDS (\
                               MESSAGE-ID \
                               FROM \
                               IN-REPLY-TO REFERENCES \
@@ -105,6 +116,7 @@ const PREFETCH_FLAGS: &str = "(UID BODY.PEEK[HEADER.FIELDS (\
 const DELETE_CHECK_FLAGS: &str = "(UID BODY.PEEK[HEADER.FIELDS (MESSAGE-ID)])";
 const JUST_UID: &str = "(UID)";
 const BODY_FLAGS: &str = "(FLAGS BODY.PEEK[])";
+const HEADER_FLAGS: &str = "(FLAGS RFC822.SIZE BODY.PEEK[HEADER])";
 const SELECT_ALL: &str = "1:*";
 
 #[derive(Debug)]
@@ -116,6 +128,13 @@ pub struct Imap {
     interrupt: Option<stop_token::StopSource>,
     skip_next_idle_wait: bool,
     should_reconnect: bool,
+
+    /// Number of IDLE sessions found dead since IDLE last worked,
+    /// see `Imap::use_idle`.
+    idle_failures: u32,
+
+    /// Offset of the polls of this connection, see `idle::poll_delay`.
+    poll_slot: u32,
 }
 
 #[derive(Debug)]
@@ -135,10 +154,15 @@ impl async_imap::Authenticator for OAuth2 {
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// Special use of a folder, stored as `sent-objects` etc.
+#[derive(Debug, PartialEq, FromSql, ToSql)]
+#[sql(repr = "text")]
 enum FolderMeaning {
     Unknown,
     SentObjects,
+    Archive,
+    Trash,
+    Spam,
     Other,
 }
 
@@ -159,6 +183,17 @@ struct ImapConfig {
     /// True if the server has MOVE capability as defined in
     /// https://tools.ietf.org/html/rfc6851
     pub can_move: bool,
+
+    /// True if the server has CONDSTORE capability as defined in
+    /// https://tools.ietf.org/html/rfc7162
+    pub can_condstore: bool,
+
+    /// True if the server has QRESYNC capability, which implies CONDSTORE.
+    pub can_qresync: bool,
+
+    /// True if the server has XDELTAPUSH capability and accepts
+    /// push device tokens, see [crate::push].
+    pub can_push: bool,
 }
 
 impl Default for ImapConfig {
@@ -176,6 +211,9 @@ impl Default for ImapConfig {
             selected_folder_needs_expunge: false,
             can_idle: false,
             can_move: false,
+            can_condstore: false,
+            can_qresync: false,
+            can_push: false,
         }
     }
 }
@@ -190,6 +228,8 @@ impl Imap {
             interrupt: Default::default(),
             skip_next_idle_wait: Default::default(),
             should_reconnect: Default::default(),
+            idle_failures: Default::default(),
+            poll_slot: Default::default(),
         }
     }
 
@@ -218,6 +258,10 @@ impl Imap {
         }
 
         let server_flags = self.config.server_flags as i32;
+        let socks5 = Socks5Config::from_database(context).await;
+        if let Some(ref socks5) = socks5 {
+            info!(context, "Connecting to IMAP server through {}", socks5);
+        }
 
         let connection_res: ImapResult<Client> =
             if (server_flags & (DC_LP_IMAP_SOCKET_STARTTLS | DC_LP_IMAP_SOCKET_PLAIN)) != 0 {
@@ -225,7 +269,7 @@ impl Imap {
                 let imap_server: &str = config.imap_server.as_ref();
                 let imap_port = config.imap_port;
 
-                match Client::connect_insecure((imap_server, imap_port)).await {
+                match Client::connect_insecure(imap_server, imap_port, socks5.as_ref()).await {
                     Ok(client) => {
                         if (server_flags & DC_LP_IMAP_SOCKET_STARTTLS) != 0 {
                             client.secure(imap_server, config.certificate_checks).await
@@ -241,8 +285,9 @@ impl Imap {
                 let imap_port = config.imap_port;
 
                 Client::connect_secure(
-                    (imap_server, imap_port),
                     imap_server,
+                    imap_port,
+                    socks5.as_ref(),
                     config.certificate_checks,
                 )
                 .await
@@ -258,7 +303,7 @@ impl Imap {
                     let addr: &str = config.addr.as_ref();
 
                     if let Some(token) =
-                        dc_get_oauth2_access_token(context, addr, imap_pw, true).await
+                        dc_get_oauth2_access_token(context, addr, imap_pw, false).await
                     {
                         let auth = OAuth2 {
                             user: imap_user.into(),
@@ -298,9 +343,14 @@ impl Imap {
                 // needs to be set here to ensure it is set on reconnects.
                 self.connected = true;
                 self.session = Some(session);
+                self.reset_idle_failures();
                 Ok(())
             }
             Err((err, _)) => {
+                if (self.config.server_flags & DC_LP_AUTH_OAUTH2) != 0 {
+                    // the token may be revoked before it expires, refresh it on the next try.
+                    oauth2::invalidate_access_token(context).await;
+                }
                 let imap_user = self.config.imap_user.to_owned();
                 let message = context
                     .stock_string_repl_str(StockMessage::CannotLogin, &imap_user)
@@ -338,6 +388,9 @@ impl Imap {
 
         cfg.can_idle = false;
         cfg.can_move = false;
+        cfg.can_condstore = false;
+        cfg.can_qresync = false;
+        cfg.can_push = false;
     }
 
     /// Connects to imap account using already-configured parameters.
@@ -353,7 +406,7 @@ impl Imap {
         // the trailing underscore is correct
 
         if self.connect(context, &param).await {
-            self.ensure_configured_folders(context, true).await
+            self.ensure_configured_folders(context).await
         } else {
             Err(Error::ConnectionFailed(format!("{}", param)))
         }
@@ -398,6 +451,9 @@ impl Imap {
                     } else {
                         let can_idle = caps.has_str("IDLE");
                         let can_move = caps.has_str("MOVE");
+                        let can_qresync = caps.has_str("QRESYNC");
+                        let can_condstore = can_qresync || caps.has_str("CONDSTORE");
+                        let can_push = caps.has_str("XDELTAPUSH");
                         let caps_list = caps.iter().fold(String::new(), |s, c| {
                             if let Capability::Atom(x) = c {
                                 s + &format!(" {}", x)
@@ -408,6 +464,9 @@ impl Imap {
 
                         self.config.can_idle = can_idle;
                         self.config.can_move = can_move;
+                        self.config.can_condstore = can_condstore;
+                        self.config.can_qresync = can_qresync;
+                        self.config.can_push = can_push;
                         self.connected = true;
                         emit_event!(
                             context,
@@ -432,6 +491,7 @@ impl Imap {
 
             false
         } else {
+            self.enable_qresync(context).await;
             true
         }
     }
@@ -448,9 +508,41 @@ impl Imap {
         }
         self.setup_handle_if_needed(context).await?;
 
+        if let Err(err) = self.register_push_token(context).await {
+            warn!(context, "failed to register push token: {}", err);
+        }
+
         while self.fetch_new_messages(context, &watch_folder).await? {
             // We fetch until no more new messages are there.
         }
+
+        if let Err(err) = self.sync_flag_changes(context, watch_folder).await {
+            warn!(
+                context,
+                "failed to sync flags of \"{}\": {}", watch_folder, err
+            );
+        }
+        Ok(())
+    }
+
+    /// Uploads the push token of the device if it changed since the last upload.
+    async fn register_push_token(&mut self, context: &Context) -> Result<()> {
+        if !self.config.can_push {
+            return Ok(());
+        }
+        let token = match push::token_to_upload(context).await {
+            Some(token) => token,
+            None => return Ok(()),
+        };
+        let session = self.session.as_mut().ok_or(Error::NoConnection)?;
+        session
+            .run_command_and_check_ok(format!(
+                "SETMETADATA \"INBOX\" (/private/devicetoken \"{}\")",
+                token
+            ))
+            .await?;
+        push::set_token_uploaded(context, &token).await?;
+        info!(context, "push device token registered");
         Ok(())
     }
 
@@ -579,13 +671,23 @@ impl Imap {
         let (uid_validity, last_seen_uid) = self
             .select_with_uidvalidity(context, folder.as_ref())
             .await?;
+        let last_seen_uid = self
+            .apply_sync_since(context, folder.as_ref(), uid_validity, last_seen_uid)
+            .await;
 
         let msgs = self.fetch_after(context, last_seen_uid).await?;
         let read_cnt = msgs.len();
         let folder: &str = folder.as_ref();
+        let download_limit = context.download_limit().await;
+        let is_spam_folder = context
+            .get_config(Config::ConfiguredSpamFolder)
+            .await
+            .as_deref()
+            == Some(folder);
 
         let mut read_errors = 0;
-        let mut uids = Vec::with_capacity(msgs.len());
+        let mut uids_fetch_fully = Vec::with_capacity(msgs.len());
+        let mut uids_fetch_partially = Vec::new();
         let mut new_last_seen_uid = None;
 
         for (current_uid, msg) in msgs.into_iter() {
@@ -610,9 +712,15 @@ impl Imap {
                 show_emails,
             )
             .await
+                && (!is_spam_folder || prefetch_is_known_sender(context, &headers).await)
             {
                 // Trigger download and processing for this message.
-                uids.push(current_uid);
+                match download_limit {
+                    Some(download_limit) if msg.size.unwrap_or_default() > download_limit => {
+                        uids_fetch_partially.push(current_uid);
+                    }
+                    _ => uids_fetch_fully.push(current_uid),
+                }
             } else if read_errors == 0 {
                 // No errors so far, but this was skipped, so mark as last_seen_uid
                 new_last_seen_uid = Some(current_uid);
@@ -620,14 +728,31 @@ impl Imap {
         }
 
         // check passed, go fetch the emails
-        let (new_last_seen_uid_processed, error_cnt) =
-            self.fetch_many_msgs(context, &folder, &uids).await;
-        read_errors += error_cnt;
+        let (largest_uid_fully_fetched, mut failed_uids) = self
+            .fetch_many_msgs(context, &folder, &uids_fetch_fully, false)
+            .await;
+
+        let (largest_uid_partially_fetched, failed_partially) = self
+            .fetch_many_msgs(context, &folder, &uids_fetch_partially, true)
+            .await;
+        failed_uids.extend(failed_partially);
+        read_errors += failed_uids.len();
+
+        if is_spam_folder {
+            let uids = uids_fetch_fully.iter().chain(uids_fetch_partially.iter());
+            rescue_from_spam(context, folder, uids).await;
+        }
+
+        let new_last_seen_uid_processed =
+            largest_uid_fully_fetched.max(largest_uid_partially_fetched);
 
         // determine which last_seen_uid to use to update  to
         let new_last_seen_uid_processed = new_last_seen_uid_processed.unwrap_or_default();
         let new_last_seen_uid = new_last_seen_uid.unwrap_or_default();
-        let last_one = new_last_seen_uid.max(new_last_seen_uid_processed);
+        let last_one = limit_last_seen_uid(
+            new_last_seen_uid.max(new_last_seen_uid_processed),
+            &failed_uids,
+        );
 
         if last_one > last_seen_uid {
             self.set_config_last_seen_uid(context, &folder, uid_validity, last_one)
@@ -646,6 +771,76 @@ impl Imap {
         Ok(read_cnt > 0)
     }
 
+    /// Lowers the last seen UID of the selected folder, so that messages
+    /// received since `Context::get_sync_since` are fetched, too.
+    ///
+    /// The time a folder was searched for is remembered per UIDVALIDITY,
+    /// so the folder is searched again only if the window was extended.
+    /// Returns the last seen UID to use.
+    async fn apply_sync_since(
+        &mut self,
+        context: &Context,
+        folder: &str,
+        uid_validity: u32,
+        last_seen_uid: u32,
+    ) -> u32 {
+        let since = match context.get_sync_since().await {
+            Some(since) => since,
+            None => return last_seen_uid,
+        };
+
+        // the entry has the format `imap.synced_since.<folder>=<uidvalidity>:<since>`
+        let key = format!("imap.synced_since.{}", folder);
+        if let Some(entry) = context.sql.get_raw_config(context, &key).await {
+            let mut parts = entry.split(':');
+            let synced_validity: u32 = parts.next().unwrap_or_default().parse().unwrap_or(0);
+            let synced_since: i64 = parts.next().unwrap_or_default().parse().unwrap_or(0);
+            if synced_validity == uid_validity && synced_since <= since {
+                return last_seen_uid;
+            }
+        }
+
+        let session = match self.session.as_mut() {
+            Some(session) => session,
+            None => return last_seen_uid,
+        };
+        let query = format!("SINCE {}", imap_date(since));
+        let first_uid = match session.uid_search(&query).await {
+            Ok(uids) => uids.into_iter().min(),
+            Err(err) => {
+                warn!(
+                    context,
+                    "Cannot search \"{}\" in {}: {}", query, folder, err
+                );
+                return last_seen_uid;
+            }
+        };
+
+        let mut new_last_seen_uid = last_seen_uid;
+        if let Some(first_uid) = first_uid {
+            new_last_seen_uid = new_last_seen_uid.min(first_uid.saturating_sub(1));
+        }
+        if new_last_seen_uid != last_seen_uid {
+            info!(
+                context,
+                "Fetching messages of \"{}\" since {}, starting after uid {}",
+                folder,
+                since,
+                new_last_seen_uid
+            );
+            self.set_config_last_seen_uid(context, folder, uid_validity, new_last_seen_uid)
+                .await;
+        }
+        if let Err(err) = context
+            .sql
+            .set_raw_config(context, &key, Some(&format!("{}:{}", uid_validity, since)))
+            .await
+        {
+            warn!(context, "Cannot save sync window of {}: {}", folder, err);
+        }
+        new_last_seen_uid
+    }
+
     /// Fetch all uids larger than the passed in. Returns a sorted list of fetch results.
     async fn fetch_after(
         &mut self,
@@ -711,44 +906,70 @@ impl Imap {
             .ok();
     }
 
+    /// Fetches a single message by server UID, eg. to download a message
+    /// completely that was only downloaded partially before.
+    pub async fn fetch_single_msg(
+        &mut self,
+        context: &Context,
+        folder: &str,
+        uid: u32,
+    ) -> ImapActionResult {
+        if let Some(imapresult) = self
+            .prepare_imap_operation_on_msg(context, folder, uid)
+            .await
+        {
+            return imapresult;
+        }
+        // we are connected, and the folder is selected
+        info!(context, "Downloading message {}/{} fully...", folder, uid);
+
+        match self.fetch_many_msgs(context, folder, &[uid], false).await {
+            (Some(_), _) => ImapActionResult::Success,
+            (None, _) if self.should_reconnect() => ImapActionResult::RetryLater,
+            (None, _) => ImapActionResult::Failed,
+        }
+    }
+
     /// Fetches a list of messages by server UID.
     /// The passed in list of uids must be sorted.
     ///
-    /// Returns the last uid fetch successfully and an error count.
+    /// If `fetch_partially` is set, only the header of the messages
+    /// is downloaded and a placeholder is stored for each message.
+    ///
+    /// Returns the largest uid fetched successfully and the uids that failed to be fetched.
     async fn fetch_many_msgs<S: AsRef<str>>(
         &mut self,
         context: &Context,
         folder: S,
         server_uids: &[u32],
-    ) -> (Option<u32>, usize) {
+        fetch_partially: bool,
+    ) -> (Option<u32>, Vec<u32>) {
         if server_uids.is_empty() {
-            return (None, 0);
+            return (None, Vec::new());
         }
 
         if !self.is_connected() {
             warn!(context, "Not connected");
-            return (None, server_uids.len());
+            return (None, server_uids.to_vec());
         }
 
         if self.session.is_none() {
             // we could not get a valid imap session, this should be retried
             self.trigger_reconnect();
             warn!(context, "Could not get IMAP session");
-            return (None, server_uids.len());
+            return (None, server_uids.to_vec());
         }
 
         let session = self.session.as_mut().unwrap();
 
-        let set = if server_uids.len() == 1 {
-            server_uids[0].to_string()
+        let set = build_sequence_set(server_uids);
+        let fetch_flags = if fetch_partially {
+            HEADER_FLAGS
         } else {
-            let first_uid = server_uids[0];
-            let last_uid = server_uids[server_uids.len() - 1];
-            assert!(first_uid < last_uid, "uids must be sorted");
-            format!("{}:{}", first_uid, last_uid)
+            BODY_FLAGS
         };
 
-        let mut msgs = match session.uid_fetch(&set, BODY_FLAGS).await {
+        let mut msgs = match session.uid_fetch(&set, fetch_flags).await {
             Ok(msgs) => msgs,
             Err(err) => {
                 // TODO: maybe differentiate between IO and input/parsing problems
@@ -761,13 +982,13 @@ impl Imap {
                     folder.as_ref(),
                     err
                 );
-                return (None, server_uids.len());
+                return (None, server_uids.to_vec());
             }
         };
 
         let folder = folder.as_ref().to_string();
 
-        let mut read_errors = 0;
+        let mut failed_uids = Vec::new();
         let mut last_uid = None;
         let mut count = 0;
 
@@ -782,7 +1003,12 @@ impl Imap {
             count += 1;
 
             let is_deleted = msg.flags().any(|flag| flag == Flag::Deleted);
-            if is_deleted || msg.body().is_none() {
+            let has_body = if fetch_partially {
+                msg.header().is_some()
+            } else {
+                msg.body().is_some()
+            };
+            if is_deleted || !has_body {
                 // No need to process these.
                 continue;
             }
@@ -793,15 +1019,32 @@ impl Imap {
 
             let task = async_std::task::spawn(async move {
                 // safe, as we checked above that there is a body.
-                let body = msg.body().unwrap();
+                let (body, partial) = if fetch_partially {
+                    (msg.header().unwrap(), Some(msg.size.unwrap_or_default()))
+                } else {
+                    (msg.body().unwrap(), None)
+                };
                 let is_seen = msg.flags().any(|flag| flag == Flag::Seen);
+                let is_flagged = msg.flags().any(|flag| flag == Flag::Flagged);
 
-                match dc_receive_imf(&context, &body, &folder, server_uid, is_seen).await {
-                    Ok(_) => Some(server_uid),
+                let res =
+                    dc_receive_imf_inner(&context, &body, &folder, server_uid, is_seen, partial)
+                        .await;
+                match res {
+                    Ok(_) => {
+                        // messages flagged in other mail clients are shown as starred
+                        if is_flagged {
+                            if let Err(err) =
+                                condstore::set_starred(&context, &folder, &[server_uid], true).await
+                            {
+                                warn!(context, "Cannot star message {}: {}", server_uid, err);
+                            }
+                        }
+                        Ok(server_uid)
+                    }
                     Err(err) => {
                         warn!(context, "dc_receive_imf error: {}", err);
-                        read_errors += 1;
-                        None
+                        Err(server_uid)
                     }
                 }
             });
@@ -810,11 +1053,11 @@ impl Imap {
 
         for task in futures::future::join_all(tasks).await {
             match task {
-                Some(uid) => {
-                    last_uid = Some(uid);
+                Ok(uid) => {
+                    last_uid = last_uid.max(Some(uid));
                 }
-                None => {
-                    read_errors += 1;
+                Err(uid) => {
+                    failed_uids.push(uid);
                 }
             }
         }
@@ -828,7 +1071,7 @@ impl Imap {
             );
         }
 
-        (last_uid, read_errors)
+        (last_uid, failed_uids)
     }
 
     pub async fn can_move(&self) -> bool {
@@ -946,12 +1189,24 @@ impl Imap {
         context: &Context,
         uid_set: &str,
         flag: &str,
+    ) -> bool {
+        self.store_flag_finalized(context, uid_set, flag, true)
+            .await
+    }
+
+    /// Adds or removes a flag, see [Imap::add_flag_finalized] for the result.
+    async fn store_flag_finalized(
+        &mut self,
+        context: &Context,
+        uid_set: &str,
+        flag: &str,
+        add: bool,
     ) -> bool {
         if self.should_reconnect() {
             return false;
         }
         if let Some(ref mut session) = &mut self.session {
-            let query = format!("+FLAGS ({})", flag);
+            let query = format!("{}FLAGS ({})", if add { "+" } else { "-" }, flag);
             match session.uid_store(uid_set, &query).await {
                 Ok(_) => {}
                 Err(err) => {
@@ -1033,6 +1288,67 @@ impl Imap {
         }
     }
 
+    /// Looks up the UID of a message in a folder by its Message-ID.
+    ///
+    /// Used after moving a message to a folder that is not watched,
+    /// so the message can be found there later.
+    pub async fn find_uid(
+        &mut self,
+        context: &Context,
+        folder: &str,
+        rfc724_mid: &str,
+    ) -> Option<u32> {
+        if let Err(err) = self.select_folder(context, Some(folder)).await {
+            warn!(context, "Cannot select folder {}: {:?}", folder, err);
+            return None;
+        }
+        let session = self.session.as_mut()?;
+        let query = format!("HEADER Message-ID \"<{}>\"", rfc724_mid.replace('"', ""));
+        match session.uid_search(&query).await {
+            Ok(uids) => uids.into_iter().max(),
+            Err(err) => {
+                warn!(
+                    context,
+                    "Cannot search {} in {}: {}", rfc724_mid, folder, err
+                );
+                None
+            }
+        }
+    }
+
+    /// Sets or clears the `\Flagged` flag of a message,
+    /// used to sync starred messages with other mail clients.
+    pub async fn set_flagged(
+        &mut self,
+        context: &Context,
+        folder: &str,
+        uid: u32,
+        flagged: bool,
+    ) -> ImapActionResult {
+        if let Some(imapresult) = self
+            .prepare_imap_operation_on_msg(context, folder, uid)
+            .await
+        {
+            return imapresult;
+        }
+        info!(
+            context,
+            "{} message {}/{} as flagged...",
+            if flagged { "Marking" } else { "Unmarking" },
+            folder,
+            uid
+        );
+
+        if self
+            .store_flag_finalized(context, &uid.to_string(), "\\Flagged", flagged)
+            .await
+        {
+            ImapActionResult::Success
+        } else {
+            ImapActionResult::RetryLater
+        }
+    }
+
     pub async fn delete_msg(
         &mut self,
         context: &Context,
@@ -1093,6 +1409,25 @@ impl Imap {
             }
         }
 
+        let delete_to_trash = context.get_config_bool(Config::DeleteToTrash).await;
+        let trash_folder = context.get_config(Config::ConfiguredTrashFolder).await;
+        match get_delete_target(delete_to_trash, folder, trash_folder.as_deref()) {
+            DeleteTarget::Expunge => {}
+            DeleteTarget::CreateTrash => match self.create_trash_folder(context).await {
+                Some(trash_folder) => {
+                    return self
+                        .move_to_trash(context, folder, uid, &trash_folder, message_id)
+                        .await
+                }
+                None => return ImapActionResult::RetryLater,
+            },
+            DeleteTarget::Trash(trash_folder) => {
+                return self
+                    .move_to_trash(context, folder, uid, &trash_folder, message_id)
+                    .await
+            }
+        }
+
         // mark the message for deletion
         if !self.add_flag_finalized(context, uid, "\\Deleted").await {
             warn!(
@@ -1113,11 +1448,193 @@ impl Imap {
         }
     }
 
-    pub async fn ensure_configured_folders(
+    /// Deletes messages from `folder`, given as pairs of UID and Message-ID.
+    ///
+    /// Messages are only deleted if the Message-ID on the server matches,
+    /// messages not on the server anymore are skipped.
+    pub async fn delete_msgs(
+        &mut self,
+        context: &Context,
+        folder: &str,
+        msgs: &[(u32, String)],
+    ) -> ImapActionResult {
+        let mut uids: Vec<u32> = msgs.iter().map(|(uid, _)| *uid).collect();
+        uids.sort_unstable();
+        uids.dedup();
+        let first_uid = match uids.first() {
+            Some(uid) => *uid,
+            None => return ImapActionResult::AlreadyDone,
+        };
+        if let Some(imapresult) = self
+            .prepare_imap_operation_on_msg(context, folder, first_uid)
+            .await
+        {
+            return imapresult;
+        }
+        // we are connected, and the folder is selected
+
+        // double-check that we are deleting the correct message-ids
+        let mut to_delete = Vec::with_capacity(uids.len());
+        if let Some(ref mut session) = &mut self.session {
+            match session
+                .uid_fetch(build_sequence_set(&uids), DELETE_CHECK_FLAGS)
+                .await
+            {
+                Ok(mut fetches) => {
+                    while let Some(fetch) = fetches.next().await {
+                        let fetch = match fetch {
+                            Ok(fetch) => fetch,
+                            Err(err) => {
+                                warn!(context, "Cannot delete on IMAP, {}: {}", folder, err);
+                                return ImapActionResult::RetryLater;
+                            }
+                        };
+                        let uid = match fetch.uid {
+                            Some(uid) => uid,
+                            None => continue,
+                        };
+                        let remote_message_id = get_fetch_headers(&fetch)
+                            .and_then(|headers| prefetch_get_message_id(&headers))
+                            .unwrap_or_default();
+                        match msgs
+                            .iter()
+                            .find(|(msg_uid, message_id)| {
+                                *msg_uid == uid && *message_id == remote_message_id
+                            }) {
+                            Some((uid, message_id)) => to_delete.push((*uid, message_id)),
+                            None => warn!(
+                                context,
+                                "Cannot delete on IMAP, {}/{}: remote message-id '{}' does not match",
+                                folder,
+                                uid,
+                                remote_message_id,
+                            ),
+                        }
+                    }
+                }
+                Err(err) => {
+                    warn!(context, "Cannot delete on IMAP, {}: {}", folder, err);
+                    return ImapActionResult::RetryLater;
+                }
+            }
+        }
+        if to_delete.is_empty() {
+            info!(
+                context,
+                "Cannot delete on IMAP, {}: imap entries gone", folder
+            );
+            return ImapActionResult::AlreadyDone;
+        }
+        to_delete.sort_unstable();
+
+        let delete_to_trash = context.get_config_bool(Config::DeleteToTrash).await;
+        let trash_folder = context.get_config(Config::ConfiguredTrashFolder).await;
+        let trash_folder = match get_delete_target(delete_to_trash, folder, trash_folder.as_deref())
+        {
+            DeleteTarget::Expunge => None,
+            DeleteTarget::CreateTrash => match self.create_trash_folder(context).await {
+                Some(trash_folder) => Some(trash_folder),
+                None => return ImapActionResult::RetryLater,
+            },
+            DeleteTarget::Trash(trash_folder) => Some(trash_folder),
+        };
+        if let Some(trash_folder) = trash_folder {
+            for (uid, message_id) in to_delete {
+                match self
+                    .move_to_trash(context, folder, uid, &trash_folder, message_id)
+                    .await
+                {
+                    ImapActionResult::AlreadyDone | ImapActionResult::Success => {}
+                    res => return res,
+                }
+            }
+            return ImapActionResult::Success;
+        }
+
+        // mark the messages for deletion
+        let uids: Vec<u32> = to_delete.iter().map(|(uid, _)| *uid).collect();
+        let set = build_sequence_set(&uids);
+        if !self
+            .add_flag_finalized_with_set(context, &set, "\\Deleted")
+            .await
+        {
+            warn!(
+                context,
+                "Cannot mark messages {}/{} as \"Deleted\".", folder, set
+            );
+            ImapActionResult::RetryLater
+        } else {
+            for (uid, message_id) in to_delete {
+                emit_event!(
+                    context,
+                    Event::ImapMessageDeleted(format!(
+                        "IMAP Message {}/{} marked as deleted [{}]",
+                        folder, uid, message_id
+                    ))
+                );
+            }
+            self.config.selected_folder_needs_expunge = true;
+            ImapActionResult::Success
+        }
+    }
+
+    async fn move_to_trash(
         &mut self,
         context: &Context,
-        create_mvbox: bool,
-    ) -> Result<()> {
+        folder: &str,
+        uid: u32,
+        trash_folder: &str,
+        message_id: &str,
+    ) -> ImapActionResult {
+        let res = self.mv(context, folder, uid, trash_folder).await;
+        if res == ImapActionResult::Success {
+            emit_event!(
+                context,
+                Event::ImapMessageDeleted(format!(
+                    "IMAP Message {}/{} moved to {} [{}]",
+                    folder, uid, trash_folder, message_id
+                ))
+            );
+        }
+        res
+    }
+
+    /// Creates the trash folder if the provider has none
+    /// and remembers it as the configured trash folder.
+    ///
+    /// Returns `None` if the folder could not be created.
+    async fn create_trash_folder(&mut self, context: &Context) -> Option<String> {
+        let session = self.session.as_mut()?;
+        let trash_folder = "Trash";
+        info!(context, "Creating trash folder \"{}\"...", trash_folder);
+        if let Err(err) = session.create(trash_folder).await {
+            // the folder may exist without being detected,
+            // eg. if it was created by another client in the meantime
+            let exists = match session.list(Some(""), Some(trash_folder)).await {
+                Ok(mut folders) => folders.next().await.is_some(),
+                Err(_) => false,
+            };
+            if !exists {
+                warn!(context, "Cannot create trash folder: {}", err);
+                return None;
+            }
+        }
+        if let Err(err) = session.subscribe(trash_folder).await {
+            warn!(
+                context,
+                "could not subscribe to {:?}: {:?}", trash_folder, err
+            );
+        }
+        if let Err(err) = context
+            .set_config(Config::ConfiguredTrashFolder, Some(trash_folder))
+            .await
+        {
+            warn!(context, "Cannot save trash folder: {}", err);
+        }
+        Some(trash_folder.to_string())
+    }
+
+    pub async fn ensure_configured_folders(&mut self, context: &Context) -> Result<()> {
         let folders_configured = context
             .sql
             .get_raw_config_int(context, "folders_configured")
@@ -1126,10 +1643,10 @@ impl Imap {
             return Ok(());
         }
 
-        self.configure_folders(context, create_mvbox).await
+        self.configure_folders(context).await
     }
 
-    pub async fn configure_folders(&mut self, context: &Context, create_mvbox: bool) -> Result<()> {
+    pub async fn configure_folders(&mut self, context: &Context) -> Result<()> {
         if !self.is_connected() {
             return Err(Error::NoConnection);
         }
@@ -1145,8 +1662,12 @@ impl Imap {
             let mut delimiter = ".".to_string();
             let mut delimiter_is_default = true;
             let mut sentbox_folder = None;
+            let mut archive_folder = None;
+            let mut trash_folder = None;
+            let mut spam_folder = None;
             let mut mvbox_folder = None;
-            let mut fallback_folder = get_fallback_folder(&delimiter);
+            let strategy = FolderStrategy::load(context).await;
+            let mut fallback_folder = strategy.fallback_folder(&delimiter);
 
             while let Some(folder) = folders.next().await {
                 let folder = folder.map_err(|err| Error::Other(err.to_string()))?;
@@ -1156,12 +1677,12 @@ impl Imap {
                 if let Some(d) = folder.delimiter() {
                     if delimiter_is_default && !d.is_empty() && delimiter != d {
                         delimiter = d.to_string();
-                        fallback_folder = get_fallback_folder(&delimiter);
+                        fallback_folder = strategy.fallback_folder(&delimiter);
                         delimiter_is_default = false;
                     }
                 }
 
-                if folder.name() == "DeltaChat" {
+                if folder.name() == strategy.folder {
                     // Always takes precendent
                     mvbox_folder = Some(folder.name().to_string());
                 } else if folder.name() == fallback_folder {
@@ -1169,13 +1690,32 @@ impl Imap {
                     if mvbox_folder.is_none() {
                         mvbox_folder = Some(folder.name().to_string());
                     }
-                } else if let FolderMeaning::SentObjects = get_folder_meaning(&folder) {
-                    // Always takes precedent
-                    sentbox_folder = Some(folder.name().to_string());
-                } else if let FolderMeaning::SentObjects = get_folder_meaning_by_name(&folder) {
-                    // only set iff none has been already set
-                    if sentbox_folder.is_none() {
-                        sentbox_folder = Some(folder.name().to_string());
+                } else {
+                    match get_folder_meaning(&folder) {
+                        // SPECIAL-USE attributes always take precedent
+                        FolderMeaning::SentObjects => {
+                            sentbox_folder = Some(folder.name().to_string())
+                        }
+                        FolderMeaning::Archive => archive_folder = Some(folder.name().to_string()),
+                        FolderMeaning::Trash => trash_folder = Some(folder.name().to_string()),
+                        FolderMeaning::Spam => spam_folder = Some(folder.name().to_string()),
+                        FolderMeaning::Other => {}
+                        FolderMeaning::Unknown => match get_folder_meaning_by_name(&folder) {
+                            // only set iff none has been already set
+                            FolderMeaning::SentObjects if sentbox_folder.is_none() => {
+                                sentbox_folder = Some(folder.name().to_string())
+                            }
+                            FolderMeaning::Archive if archive_folder.is_none() => {
+                                archive_folder = Some(folder.name().to_string())
+                            }
+                            FolderMeaning::Trash if trash_folder.is_none() => {
+                                trash_folder = Some(folder.name().to_string())
+                            }
+                            FolderMeaning::Spam if spam_folder.is_none() => {
+                                spam_folder = Some(folder.name().to_string())
+                            }
+                            _ => {}
+                        },
                     }
                 }
             }
@@ -1183,13 +1723,18 @@ impl Imap {
 
             info!(context, "Using \"{}\" as folder-delimiter.", delimiter);
             info!(context, "sentbox folder is {:?}", sentbox_folder);
+            info!(context, "archive folder is {:?}", archive_folder);
+            info!(context, "trash folder is {:?}", trash_folder);
+            info!(context, "spam folder is {:?}", spam_folder);
 
-            if mvbox_folder.is_none() && create_mvbox {
-                info!(context, "Creating MVBOX-folder \"DeltaChat\"...",);
+            if !strategy.uses_folder() {
+                mvbox_folder = None;
+            } else if mvbox_folder.is_none() {
+                info!(context, "Creating MVBOX-folder \"{}\"...", strategy.folder);
 
-                match session.create("DeltaChat").await {
+                match session.create(&strategy.folder).await {
                     Ok(_) => {
-                        mvbox_folder = Some("DeltaChat".into());
+                        mvbox_folder = Some(strategy.folder.clone());
                         info!(context, "MVBOX-folder created.",);
                     }
                     Err(err) => {
@@ -1225,16 +1770,23 @@ impl Imap {
             context
                 .set_config(Config::ConfiguredInboxFolder, Some("INBOX"))
                 .await?;
-            if let Some(ref mvbox_folder) = mvbox_folder {
-                context
-                    .set_config(Config::ConfiguredMvboxFolder, Some(mvbox_folder))
-                    .await?;
-            }
+            context
+                .set_config(Config::ConfiguredMvboxFolder, mvbox_folder.as_deref())
+                .await?;
             if let Some(ref sentbox_folder) = sentbox_folder {
                 context
                     .set_config(Config::ConfiguredSentboxFolder, Some(sentbox_folder))
                     .await?;
             }
+            context
+                .set_config(Config::ConfiguredArchiveFolder, archive_folder.as_deref())
+                .await?;
+            context
+                .set_config(Config::ConfiguredTrashFolder, trash_folder.as_deref())
+                .await?;
+            context
+                .set_config(Config::ConfiguredSpamFolder, spam_folder.as_deref())
+                .await?;
             context
                 .sql
                 .set_raw_config_int(context, "folders_configured", DC_FOLDERS_CONFIGURED_VERSION)
@@ -1299,6 +1851,22 @@ impl Imap {
     }
 }
 
+#[async_trait]
+impl FetchTransport for Imap {
+    async fn fetch(&mut self, context: &Context) -> crate::error::Result<()> {
+        let watch_folder = context
+            .get_config(Config::ConfiguredInboxFolder)
+            .await
+            .ok_or_else(|| crate::error::format_err!("Can not fetch inbox folder, not set"))?;
+        self.connect_configured(context).await?;
+        if let Err(err) = Imap::fetch(self, context, &watch_folder).await {
+            self.trigger_reconnect();
+            return Err(err.into());
+        }
+        Ok(())
+    }
+}
+
 /// Try to get the folder meaning by the name of the folder only used if the server does not support XLIST.
 // TODO: lots languages missing - maybe there is a list somewhere on other MUAs?
 // however, if we fail to find out the sent-folder,
@@ -1307,24 +1875,76 @@ impl Imap {
 // but sth. different in others - a hard job.
 fn get_folder_meaning_by_name(folder_name: &Name) -> FolderMeaning {
     let sent_names = vec!["sent", "sentmail", "sent objects", "gesendet"];
+    let archive_names = vec!["archive", "archives", "archiv"];
+    let trash_names = vec!["trash", "deleted items", "deleted messages", "papierkorb"];
+    let spam_names = vec!["spam", "junk", "junk e-mail", "junk email"];
     let lower = folder_name.name().to_lowercase();
 
     if sent_names.into_iter().any(|s| s == lower) {
         FolderMeaning::SentObjects
+    } else if archive_names.into_iter().any(|s| s == lower) {
+        FolderMeaning::Archive
+    } else if trash_names.into_iter().any(|s| s == lower) {
+        FolderMeaning::Trash
+    } else if spam_names.into_iter().any(|s| s == lower) {
+        FolderMeaning::Spam
     } else {
         FolderMeaning::Unknown
     }
 }
 
+/// Formats a timestamp as date for IMAP `SEARCH`, eg. `1-Feb-1994`.
+fn imap_date(timestamp: i64) -> String {
+    chrono::NaiveDateTime::from_timestamp(timestamp, 0)
+        .format("%-d-%b-%Y")
+        .to_string()
+}
+
+/// How a message is deleted on the server.
+#[derive(Debug, PartialEq)]
+enum DeleteTarget {
+    /// Mark the message as `\Deleted` and expunge it.
+    Expunge,
+
+    /// Move the message to the given trash folder.
+    Trash(String),
+
+    /// Move the message to a trash folder that does not exist yet.
+    CreateTrash,
+}
+
+fn get_delete_target(
+    delete_to_trash: bool,
+    folder: &str,
+    trash_folder: Option<&str>,
+) -> DeleteTarget {
+    if !delete_to_trash {
+        return DeleteTarget::Expunge;
+    }
+    match trash_folder {
+        // messages deleted from the trash folder are gone for good
+        Some(trash_folder) if trash_folder == folder => DeleteTarget::Expunge,
+        Some(trash_folder) => DeleteTarget::Trash(trash_folder.to_string()),
+        None => DeleteTarget::CreateTrash,
+    }
+}
+
 fn get_folder_meaning(folder_name: &Name) -> FolderMeaning {
-    let special_names = vec!["\\Spam", "\\Trash", "\\Drafts", "\\Junk"];
+    let special_names = vec!["\\Drafts"];
+    let spam_names = vec!["\\Spam", "\\Junk"];
 
     for attr in folder_name.attributes() {
         if let NameAttribute::Custom(ref label) = attr {
             if special_names.iter().any(|s| *s == label) {
                 return FolderMeaning::Other;
+            } else if spam_names.iter().any(|s| *s == label) {
+                return FolderMeaning::Spam;
             } else if label == "\\Sent" {
                 return FolderMeaning::SentObjects;
+            } else if label == "\\Archive" {
+                return FolderMeaning::Archive;
+            } else if label == "\\Trash" {
+                return FolderMeaning::Trash;
             }
         }
     }
@@ -1402,6 +2022,33 @@ async fn precheck_imf(
     }
 }
 
+/// Builds an IMAP sequence set from a sorted list of uids,
+/// eg. `1:3,5,7:8` for `[1, 2, 3, 5, 7, 8]`.
+fn build_sequence_set(uids: &[u32]) -> String {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for &uid in uids {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == uid => *end = uid,
+            Some((_, end)) => {
+                assert!(*end < uid, "uids must be sorted");
+                ranges.push((uid, uid));
+            }
+            None => ranges.push((uid, uid)),
+        }
+    }
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}:{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
 fn get_fetch_headers(prefetch_msg: &Fetch) -> Result<Vec<mailparse::MailHeader>> {
     let header_bytes = match prefetch_msg.header() {
         Some(header_bytes) => header_bytes,
@@ -1467,6 +2114,87 @@ async fn prefetch_should_download(
     Ok(show)
 }
 
+/// Checks if a message is from a known contact that is not blocked,
+/// used to find messages wrongly classified as spam.
+///
+/// No contacts are created for the senders of spam.  Spam often fakes
+/// the own address, so messages from self are not rescued.
+async fn prefetch_is_known_sender(
+    context: &Context,
+    headers: &[mailparse::MailHeader<'_>],
+) -> bool {
+    match mimeparser::get_from(headers).first() {
+        Some(from) => {
+            let contact_id =
+                Contact::lookup_id_by_addr(context, &from.addr, Origin::IncomingReplyTo).await;
+            contact_id > DC_CONTACT_ID_LAST_SPECIAL
+        }
+        None => false,
+    }
+}
+
+/// Moves messages from known contacts found in the spam folder
+/// to the home folder and adds an info message to their chats.
+async fn rescue_from_spam(context: &Context, folder: &str, uids: impl Iterator<Item = &u32>) {
+    let home_folder = context.get_home_folder().await;
+    for uid in uids {
+        let row = context
+            .sql
+            .query_row_optional(
+                "SELECT id, chat_id, from_id FROM msgs WHERE server_folder=? AND server_uid=?;",
+                paramsv![folder, *uid as i64],
+                |row| {
+                    Ok((
+                        row.get::<_, MsgId>(0)?,
+                        row.get::<_, ChatId>(1)?,
+                        row.get::<_, u32>(2)?,
+                    ))
+                },
+            )
+            .await;
+        let (msg_id, chat_id, from_id) = match row {
+            Ok(Some(row)) => row,
+            Ok(None) => continue,
+            Err(err) => {
+                warn!(context, "Cannot load rescued message {}: {}", uid, err);
+                continue;
+            }
+        };
+
+        info!(
+            context,
+            "Rescuing message {}/{} from spam to \"{}\"", folder, uid, home_folder
+        );
+        let mut param = Params::new();
+        param.set(Param::Arg, &home_folder);
+        job::add(
+            context,
+            job::Job::new(Action::MoveMsg, msg_id.to_u32(), param, 0),
+        )
+        .await;
+
+        if !chat_id.is_special() {
+            let name = match Contact::get_by_id(context, from_id).await {
+                Ok(contact) => contact.get_display_name().to_string(),
+                Err(_) => from_id.to_string(),
+            };
+            let text = context
+                .stock_string_repl_str(StockMessage::MsgRescuedFromSpam, name)
+                .await;
+            chat::add_info_msg(context, chat_id, text).await;
+        }
+    }
+}
+
+/// Returns the last seen UID to store after fetching,
+/// just below the smallest UID that failed to be fetched, so that it is fetched again.
+fn limit_last_seen_uid(last_seen_uid: u32, failed_uids: &[u32]) -> u32 {
+    match failed_uids.iter().min() {
+        Some(failed_uid) => last_seen_uid.min(failed_uid.saturating_sub(1)),
+        None => last_seen_uid,
+    }
+}
+
 async fn message_needs_processing(
     context: &Context,
     current_uid: u32,
@@ -1514,6 +2242,45 @@ async fn message_needs_processing(
     true
 }
 
-fn get_fallback_folder(delimiter: &str) -> String {
-    format!("INBOX{}DeltaChat", delimiter)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_last_seen_uid() {
+        assert_eq!(limit_last_seen_uid(10, &[]), 10);
+        assert_eq!(limit_last_seen_uid(10, &[7, 5, 9]), 4);
+        assert_eq!(limit_last_seen_uid(3, &[7]), 3);
+        assert_eq!(limit_last_seen_uid(10, &[0]), 0);
+    }
+
+    #[test]
+    fn test_imap_date() {
+        assert_eq!(imap_date(760_060_800), "1-Feb-1994");
+        assert_eq!(imap_date(1_584_916_677), "22-Mar-2020");
+    }
+
+    #[test]
+    fn test_get_delete_target() {
+        assert_eq!(
+            get_delete_target(false, "INBOX", Some("Trash")),
+            DeleteTarget::Expunge
+        );
+        assert_eq!(
+            get_delete_target(false, "INBOX", None),
+            DeleteTarget::Expunge
+        );
+        assert_eq!(
+            get_delete_target(true, "INBOX", Some("Trash")),
+            DeleteTarget::Trash("Trash".to_string())
+        );
+        assert_eq!(
+            get_delete_target(true, "Trash", Some("Trash")),
+            DeleteTarget::Expunge
+        );
+        assert_eq!(
+            get_delete_target(true, "INBOX", None),
+            DeleteTarget::CreateTrash
+        );
+    }
 }
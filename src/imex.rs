@@ -2,17 +2,23 @@
 
 use std::any::Any;
 use std::cmp::{max, min};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::time::Duration;
 
+use async_std::net::{TcpListener, TcpStream, UdpSocket};
 use async_std::path::{Path, PathBuf};
 use async_std::prelude::*;
 use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use crate::blob::BlobObject;
 use crate::chat;
 use crate::chat::delete_and_reset_all_device_msgs;
 use crate::config::Config;
 use crate::constants::*;
-use crate::context::Context;
+use crate::context::{Context, HousekeepingOptions};
 use crate::dc_tools::*;
 use crate::e2ee;
 use crate::error::*;
@@ -22,8 +28,10 @@ use crate::message::{Message, MsgId};
 use crate::mimeparser::SystemMessage;
 use crate::param::*;
 use crate::pgp;
+use crate::qr;
 use crate::sql::{self, Sql};
 use crate::stock::StockMessage;
+use crate::sync;
 
 #[derive(Debug, Display, Copy, Clone, PartialEq, Eq, FromPrimitive, ToPrimitive)]
 #[repr(i32)]
@@ -50,8 +58,18 @@ pub enum ImexMode {
     /// created by DC_IMEX_EXPORT_BACKUP and detected by dc_imex_has_backup(). Importing a backup
     /// is only possible as long as the context is not configured or used in another way.
     ImportBackup = 12,
+
+    /// Export an incremental backup to the directory given as `param1`.
+    /// Only files added or changed since the last backup are included
+    /// if the last backup is still in the directory, otherwise a full backup is created.
+    /// To import an incremental backup, the backups it is based on
+    /// have to be in the same directory.
+    ExportIncrementalBackup = 13,
 }
 
+/// Incremental backups based on more backups than this are not imported.
+const MAX_BACKUP_LAYERS: usize = 1000;
+
 /// Import/export things.
 ///
 /// What to do is defined by the *what* parameter.
@@ -84,7 +102,14 @@ pub async fn imex(
 }
 
 /// Returns the filename of the backup found (otherwise an error)
-pub async fn has_backup(context: &Context, dir_name: impl AsRef<Path>) -> Result<String> {
+///
+/// `passphrase` is the passphrase the backups are encrypted with,
+/// `None` for unencrypted backups.
+pub async fn has_backup(
+    context: &Context,
+    dir_name: impl AsRef<Path>,
+    passphrase: Option<String>,
+) -> Result<String> {
     let dir_name = dir_name.as_ref();
     let mut dir_iter = async_std::fs::read_dir(dir_name).await?;
     let mut newest_backup_time = 0;
@@ -95,7 +120,7 @@ pub async fn has_backup(context: &Context, dir_name: impl AsRef<Path>) -> Result
             let name = dirent.file_name();
             let name = name.to_string_lossy();
             if name.starts_with("delta-chat") && name.ends_with(".bak") {
-                let sql = Sql::new();
+                let sql = Sql::with_passphrase(passphrase.clone());
                 if sql.open(context, &path, true).await {
                     let curr_backup_time = sql
                         .get_raw_config_int(context, "backup_time")
@@ -272,12 +297,13 @@ pub async fn continue_key_transfer(
         "Message is no Autocrypt Setup Message."
     );
 
-    if let Some(filename) = msg.get_file(context) {
-        let file = dc_open_file_std(context, filename)?;
+    if let Some(filename) = msg.param.get_path(Param::File, context)? {
+        let file = std::io::Cursor::new(dc_read_file(context, filename).await?);
         let sc = normalize_setup_code(setup_code);
         let armored_key = decrypt_setup_file(&sc, file).await?;
         set_self_key(context, &armored_key, true, true).await?;
         maybe_add_bcc_self_device_msg(context).await?;
+        sync::request_contacts(context).await;
 
         Ok(())
     } else {
@@ -373,7 +399,10 @@ async fn imex_inner(
     ensure!(context.sql.is_open().await, "Database not opened.");
 
     let path = param.unwrap();
-    if what == ImexMode::ExportBackup || what == ImexMode::ExportSelfKeys {
+    if what == ImexMode::ExportBackup
+        || what == ImexMode::ExportIncrementalBackup
+        || what == ImexMode::ExportSelfKeys
+    {
         // before we export anything, make sure the private key exists
         if e2ee::ensure_secret_key_exists(context).await.is_err() {
             bail!("Cannot create private key or private key not available.");
@@ -385,8 +414,9 @@ async fn imex_inner(
     let success = match what {
         ImexMode::ExportSelfKeys => export_self_keys(context, path).await,
         ImexMode::ImportSelfKeys => import_self_keys(context, path).await,
-        ImexMode::ExportBackup => export_backup(context, path).await,
+        ImexMode::ExportBackup => export_backup(context, path, false).await,
         ImexMode::ImportBackup => import_backup(context, path).await,
+        ImexMode::ExportIncrementalBackup => export_backup(context, path, true).await,
     };
 
     match success {
@@ -490,11 +520,17 @@ async fn import_backup(context: &Context, backup_to_import: impl AsRef<Path>) ->
     }
 
     if all_files_extracted {
+        import_backup_layers(context, backup_to_import.as_ref()).await?;
+
         // only delete backup_blobs if all files were successfully extracted
         context
             .sql
             .execute("DROP TABLE backup_blobs;", paramsv![])
             .await?;
+        context
+            .sql
+            .set_raw_config(context, "backup_parent", None)
+            .await?;
         context.sql.execute("VACUUM;", paramsv![]).await.ok();
         Ok(())
     } else {
@@ -502,32 +538,183 @@ async fn import_backup(context: &Context, backup_to_import: impl AsRef<Path>) ->
     }
 }
 
+/// Extracts the files of an incremental backup that are contained
+/// in the backups it is based on.
+///
+/// Each incremental backup names the backup it is based on as `backup_parent`,
+/// these backups are expected in the same directory.  Only the files listed in
+/// the manifest of the imported backup are extracted, each in its newest version.
+async fn import_backup_layers(context: &Context, backup: &Path) -> Result<()> {
+    let mut parent = context.sql.get_raw_config(context, "backup_parent").await;
+    if parent.is_none() {
+        return Ok(());
+    }
+    let mut missing: HashSet<String> = context
+        .sql
+        .query_map(
+            "SELECT file_name FROM backup_manifest \
+             WHERE file_name NOT IN (SELECT file_name FROM backup_blobs);",
+            paramsv![],
+            |row| row.get::<_, String>(0),
+            |names| {
+                names
+                    .collect::<std::result::Result<HashSet<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    let dir = backup.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut layers = 0;
+    while let Some(name) = parent {
+        if missing.is_empty() {
+            break;
+        }
+        ensure!(layers < MAX_BACKUP_LAYERS, "Too many incremental backups.");
+        layers += 1;
+
+        let path = dir.join(&name);
+        ensure!(
+            path.exists().await,
+            "Cannot find backup {} the imported backup is based on.",
+            name
+        );
+        info!(context, "Import files from base backup {}.", name);
+        let layer = Sql::with_passphrase(context.sql.passphrase().await);
+        ensure!(
+            layer.open(context, &path, true).await,
+            "Cannot open backup {}.",
+            name
+        );
+        let res = extract_layer_files(context, &layer, &mut missing).await;
+        parent = layer.get_raw_config(context, "backup_parent").await;
+        layer.close().await;
+        res?;
+    }
+
+    ensure!(
+        missing.is_empty(),
+        "{} files not found in the backups the imported backup is based on.",
+        missing.len()
+    );
+    Ok(())
+}
+
+/// Writes the files of a base backup that are still missing to the blobdir.
+async fn extract_layer_files(
+    context: &Context,
+    layer: &Sql,
+    missing: &mut HashSet<String>,
+) -> Result<()> {
+    let names = layer
+        .query_map(
+            "SELECT file_name FROM backup_blobs;",
+            paramsv![],
+            |row| row.get::<_, String>(0),
+            |names| {
+                names
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    for name in names {
+        if context.shall_stop_ongoing().await {
+            bail!("received stop signal");
+        }
+        if !missing.remove(&name) {
+            continue;
+        }
+        let file_blob: Vec<u8> = layer
+            .query_get_value(
+                context,
+                "SELECT file_content FROM backup_blobs WHERE file_name=?;",
+                paramsv![name],
+            )
+            .await
+            .unwrap_or_default();
+        dc_write_file(context, context.get_blobdir().join(&name), &file_blob).await?;
+    }
+    Ok(())
+}
+
 /*******************************************************************************
  * Export backup
  ******************************************************************************/
-/* the FILE_PROGRESS macro calls the callback with the permille of files processed.
-The macro avoids weird values of 0% or 100% while still working. */
-async fn export_backup(context: &Context, dir: impl AsRef<Path>) -> Result<()> {
+async fn export_backup(context: &Context, dir: impl AsRef<Path>, incremental: bool) -> Result<()> {
     // get a fine backup file name (the name includes the date so that multiple backup instances are possible)
-    // FIXME: we should write to a temporary file first and rename it on success. this would guarantee the backup is complete.
-    // let dest_path_filename = dc_get_next_backup_file(context, dir, res);
     let now = time();
+    let dir = dir.as_ref();
     let dest_path_filename = dc_get_next_backup_path(dir, now).await?;
-    let dest_path_string = dest_path_filename.to_string_lossy().to_string();
-
-    sql::housekeeping(context).await;
+    let parent = if incremental {
+        get_backup_parent(context, dir).await
+    } else {
+        None
+    };
+    let known_files = match parent {
+        Some(_) => load_manifest(&context.sql).await?,
+        None => HashMap::new(),
+    };
+    // the backup is written to a temporary file and renamed on success,
+    // so that an interrupted export does not leave an incomplete backup.
+    let temp_path = PartialBackup(dest_path_filename.with_extension("bak.partial"));
 
-    context.sql.execute("VACUUM;", paramsv![]).await.ok();
+    let file_name = dest_path_filename
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
 
-    // we close the database during the copy of the dbfile
-    context.sql.close().await;
     info!(
         context,
         "Backup '{}' to '{}'.",
         context.get_dbfile().display(),
         dest_path_filename.display(),
     );
-    let copied = dc_copy_file(context, context.get_dbfile(), &dest_path_filename).await;
+    let res = write_backup(
+        context,
+        &temp_path.0,
+        &file_name,
+        now,
+        parent.as_deref(),
+        &known_files,
+    )
+    .await;
+    match res {
+        Ok(manifest) => {
+            async_std::fs::rename(&temp_path.0, &dest_path_filename).await?;
+            // remember the files of this backup for the next incremental backup
+            store_manifest(&context.sql, manifest, &file_name).await?;
+            context.emit_event(Event::ImexFileWritten(dest_path_filename));
+            Ok(())
+        }
+        Err(err) => {
+            error!(context, "backup failed: {}", err);
+            Err(err)
+        }
+    }
+}
+
+/// Writes a backup named `name` based on `parent` to `dest`, files contained
+/// unchanged in `known_files` are skipped.  Returns the manifest of the backup.
+async fn write_backup(
+    context: &Context,
+    dest: &Path,
+    name: &str,
+    now: i64,
+    parent: Option<&str>,
+    known_files: &HashMap<String, String>,
+) -> Result<Vec<(String, String)>> {
+    let options = HousekeepingOptions {
+        vacuum: true,
+        ..Default::default()
+    };
+    if let Err(err) = sql::housekeeping(context, options).await {
+        warn!(context, "Housekeeping before backup failed: {}", err);
+    }
+
+    // we close the database during the copy of the dbfile
+    context.sql.close().await;
+    let copied = dc_copy_file(context, context.get_dbfile(), dest).await;
     context
         .sql
         .open(&context, &context.get_dbfile(), false)
@@ -537,35 +724,130 @@ async fn export_backup(context: &Context, dir: impl AsRef<Path>) -> Result<()> {
         bail!(
             "could not copy file from '{}' to '{}'",
             context.get_dbfile().display(),
-            dest_path_string
+            dest.display()
         );
     }
-    let dest_sql = Sql::new();
+    // the backup is a copy of the database, so it has the same passphrase
+    let dest_sql = Sql::with_passphrase(context.sql.passphrase().await);
     ensure!(
-        dest_sql.open(context, &dest_path_filename, false).await,
+        dest_sql.open(context, dest, false).await,
         "could not open exported database {}",
-        dest_path_string
+        dest.display()
     );
-    let res = match add_files_to_export(context, &dest_sql).await {
-        Err(err) => {
-            dc_delete_file(context, &dest_path_filename).await;
-            error!(context, "backup failed: {}", err);
-            Err(err)
-        }
-        Ok(()) => {
-            dest_sql
-                .set_raw_config_int(context, "backup_time", now as i32)
-                .await?;
-            context.emit_event(Event::ImexFileWritten(dest_path_filename));
-            Ok(())
-        }
+    let res = match add_files_to_export(context, &dest_sql, known_files).await {
+        Ok(manifest) => finish_backup(context, &dest_sql, name, now, parent, manifest.clone())
+            .await
+            .map(|_| manifest),
+        Err(err) => Err(err),
     };
     dest_sql.close().await;
+    res
+}
 
-    Ok(res?)
+/// Returns the file name of the last backup if it is still in `dir`,
+/// so that an incremental backup can be based on it.
+async fn get_backup_parent(context: &Context, dir: &Path) -> Option<String> {
+    let name = context.sql.get_raw_config(context, "backup_last").await?;
+    if dir.join(&name).exists().await {
+        Some(name)
+    } else {
+        info!(
+            context,
+            "Last backup {} not found, exporting a full backup.", name
+        );
+        None
+    }
 }
 
-async fn add_files_to_export(context: &Context, sql: &Sql) -> Result<()> {
+/// Stores the backup time, the backup the new backup is based on
+/// and the manifest of all files in the new backup.
+///
+/// The backup is also stored as last backup along with its manifest,
+/// so that an incremental backup exported after restoring it is based
+/// on it and not on the backup that was the last one before.
+async fn finish_backup(
+    context: &Context,
+    dest_sql: &Sql,
+    name: &str,
+    now: i64,
+    parent: Option<&str>,
+    manifest: Vec<(String, String)>,
+) -> Result<()> {
+    dest_sql
+        .set_raw_config_int(context, "backup_time", now as i32)
+        .await?;
+    dest_sql
+        .set_raw_config(context, "backup_parent", parent)
+        .await?;
+    store_manifest(dest_sql, manifest, name).await
+}
+
+/// Returns the file names and hashes of the last backup.
+async fn load_manifest(sql: &Sql) -> Result<HashMap<String, String>> {
+    let manifest = sql
+        .query_map(
+            "SELECT file_name, hash FROM backup_manifest;",
+            paramsv![],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+            |rows| {
+                rows.collect::<std::result::Result<HashMap<_, _>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    Ok(manifest)
+}
+
+/// Stores the manifest of the backup `name` and the backup as last backup.
+///
+/// Both are written in one transaction, so the manifest always belongs
+/// to the backup an incremental backup is based on.
+async fn store_manifest(sql: &Sql, manifest: Vec<(String, String)>, name: &str) -> Result<()> {
+    let name = name.to_string();
+    sql.with_conn(move |mut conn| {
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM config WHERE keyname='backup_last';",
+            paramsv![],
+        )?;
+        tx.execute(
+            "INSERT INTO config (keyname, value) VALUES ('backup_last', ?);",
+            paramsv![name],
+        )?;
+        tx.execute("DELETE FROM backup_manifest;", paramsv![])?;
+        {
+            let mut stmt =
+                tx.prepare("INSERT INTO backup_manifest (file_name, hash) VALUES (?, ?);")?;
+            for (file_name, hash) in &manifest {
+                stmt.execute(paramsv![file_name, hash])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    })
+    .await?;
+    Ok(())
+}
+
+/// Temporary file of a backup being exported, deleted when dropped.
+///
+/// This also cleans up if the export is cancelled by dropping its future.
+/// After a successful export, the file is already renamed.
+struct PartialBackup(PathBuf);
+
+impl Drop for PartialBackup {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
+}
+
+/// Adds the files of the blobdir to the backup, skipping files contained
+/// unchanged in `known_files`.  Returns the names and hashes of all files.
+async fn add_files_to_export(
+    context: &Context,
+    sql: &Sql,
+    known_files: &HashMap<String, String>,
+) -> Result<Vec<(String, String)>> {
     // add all files as blobs to the database copy (this does not require
     // the source to be locked, neigher the destination as it is used only here)
     if !sql.table_exists("backup_blobs").await? {
@@ -583,42 +865,90 @@ async fn add_files_to_export(context: &Context, sql: &Sql) -> Result<()> {
 
     info!(context, "EXPORT: total_files_cnt={}", total_files_cnt);
 
-    sql.with_conn_async(|conn| async move {
-        // scan directory, pass 2: copy files
-        let mut dir_handle = async_std::fs::read_dir(&dir).await?;
-
-        let mut processed_files_cnt = 0;
-        while let Some(entry) = dir_handle.next().await {
-            let entry = entry?;
-            if context.shall_stop_ongoing().await {
-                return Ok(());
-            }
-            processed_files_cnt += 1;
-            let permille = max(min(processed_files_cnt * 1000 / total_files_cnt, 990), 10);
-            context.emit_event(Event::ImexProgress(permille));
-
-            let name_f = entry.file_name();
-            let name = name_f.to_string_lossy();
-            if name.starts_with("delta-chat") && name.ends_with(".bak") {
-                continue;
-            }
-            info!(context, "EXPORT: copying filename={}", name);
-            let curr_path_filename = context.get_blobdir().join(entry.file_name());
-            if let Ok(buf) = dc_read_file(context, &curr_path_filename).await {
-                if buf.is_empty() {
+    let manifest = sql
+        .with_conn_async(|conn| async move {
+            // scan directory, pass 2: copy files
+            let mut dir_handle = async_std::fs::read_dir(&dir).await?;
+            let mut manifest = Vec::new();
+
+            let mut processed_files_cnt = 0;
+            while let Some(entry) = dir_handle.next().await {
+                let entry = entry?;
+                if context.shall_stop_ongoing().await {
+                    return Err(format_err!("received stop signal").into());
+                }
+                processed_files_cnt += 1;
+                let permille = max(min(processed_files_cnt * 1000 / total_files_cnt, 990), 10);
+                context.emit_event(Event::ImexProgress(permille));
+
+                let name_f = entry.file_name();
+                let name = name_f.to_string_lossy();
+                if name.starts_with("delta-chat")
+                    && (name.ends_with(".bak") || name.ends_with(".bak.partial"))
+                {
                     continue;
                 }
-                // bail out if we can't insert
-                let mut stmt = conn.prepare_cached(
-                    "INSERT INTO backup_blobs (file_name, file_content) VALUES (?, ?);",
-                )?;
-                stmt.execute(paramsv![name, buf])?;
+                let curr_path_filename = context.get_blobdir().join(entry.file_name());
+                let hash = match hash_file(&curr_path_filename) {
+                    Ok(Some(hash)) => hash,
+                    // empty or deleted in between
+                    _ => continue,
+                };
+                if known_files.get(&*name) != Some(&hash) {
+                    info!(context, "EXPORT: copying filename={}", name);
+                    // bail out if we can't insert
+                    stream_file_to_export(&conn, &name, &curr_path_filename)?;
+                }
+                manifest.push((name.to_string(), hash));
             }
-        }
-        Ok(())
-    })
-    .await?;
+            Ok(manifest)
+        })
+        .await?;
 
+    Ok(manifest)
+}
+
+/// Returns the SHA-256 of a file as hex string, `None` for empty files.
+fn hash_file(path: impl AsRef<std::path::Path>) -> std::io::Result<Option<String>> {
+    let mut file = std::fs::File::open(path)?;
+    if file.metadata()?.len() == 0 {
+        return Ok(None);
+    }
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(Some(format!("{:x}", hasher.result())))
+}
+
+/// Copies a file into `backup_blobs` in chunks,
+/// without reading the whole file into memory.
+///
+/// Empty files are skipped, they are not restored on import anyway.
+fn stream_file_to_export(
+    conn: &rusqlite::Connection,
+    name: &str,
+    path: impl AsRef<std::path::Path>,
+) -> sql::Result<()> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        // the file may have been deleted in between
+        Err(_) => return Ok(()),
+    };
+    let len = file.metadata()?.len();
+    if len == 0 {
+        return Ok(());
+    }
+    conn.execute(
+        "INSERT INTO backup_blobs (file_name, file_content) VALUES (?, zeroblob(?));",
+        paramsv![name, len as i64],
+    )?;
+    let mut blob = conn.blob_open(
+        rusqlite::DatabaseName::Main,
+        "backup_blobs",
+        "file_content",
+        conn.last_insert_rowid(),
+        false,
+    )?;
+    std::io::copy(&mut file.take(len), &mut blob)?;
     Ok(())
 }
 
@@ -767,6 +1097,388 @@ where
     res
 }
 
+/*******************************************************************************
+ * Second device setup
+ ******************************************************************************/
+
+/// The account is transferred as [SecondDeviceSetup].
+const SECOND_DEVICE_SETUP: u8 = 1;
+
+/// The account is transferred as a backup.
+const SECOND_DEVICE_BACKUP: u8 = 2;
+
+/// Time the QR code of [provide_second_device] stays valid.
+const SECOND_DEVICE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Time a connection may take to authenticate or to be established.
+const SECOND_DEVICE_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Length of the random challenge a connecting second device has to answer.
+const SECOND_DEVICE_CHALLENGE_LEN: usize = 32;
+
+/// The settings transferred to a second device if no backup is sent.
+const SECOND_DEVICE_CONFIG: [Config; 19] = [
+    Config::Addr,
+    Config::MailServer,
+    Config::MailUser,
+    Config::MailPw,
+    Config::MailPort,
+    Config::ImapCertificateChecks,
+    Config::SendServer,
+    Config::SendUser,
+    Config::SendPw,
+    Config::SendPort,
+    Config::SmtpCertificateChecks,
+    Config::ServerFlags,
+    Config::Displayname,
+    Config::Selfstatus,
+    Config::E2eeEnabled,
+    Config::Socks5Host,
+    Config::Socks5Port,
+    Config::Socks5User,
+    Config::Socks5Password,
+];
+
+/// Credentials and key of an account transferred to a second device.
+#[derive(Debug, Serialize, Deserialize)]
+struct SecondDeviceSetup {
+    config: Vec<(String, String)>,
+    secret_key: String,
+}
+
+/// Prepares the transfer of the account to a second device
+/// and returns the QR code to be scanned by the second device.
+///
+/// The transfer runs in the background as an ongoing process until
+/// the second device has received the account, until it is canceled
+/// using `Context::stop_ongoing()` or until it times out after 10 minutes.
+/// The progress is reported by #DC_EVENT_IMEX_PROGRESS,
+/// 1000 if the account was transferred, 0 on errors.
+///
+/// Without `include_backup`, only the credentials and the secret key are
+/// transferred, the second device has to be configured then.
+/// With `include_backup`, a backup with all chats is transferred,
+/// it is held in memory during the transfer.  As for exporting backups,
+/// IO must be stopped using `Context::stop_io()` then.
+///
+/// The data are encrypted with a one-time secret contained in the QR code
+/// and are sent directly to the second device, which has to be in the same network.
+pub async fn provide_second_device(context: &Context, include_backup: bool) -> Result<String> {
+    use futures::future::FutureExt;
+
+    let cancel = context.alloc_ongoing().await?;
+    let (transfer, qr) = match prepare_second_device(context, include_backup).await {
+        Ok(res) => res,
+        Err(err) => {
+            context.free_ongoing().await;
+            return Err(err);
+        }
+    };
+
+    let ctx = context.clone();
+    async_std::task::spawn(async move {
+        let serve = serve_second_device(&ctx, transfer)
+            .race(cancel.recv().map(|_| Err(format_err!("canceled"))));
+        let res = match async_std::future::timeout(SECOND_DEVICE_TIMEOUT, serve).await {
+            Ok(res) => res,
+            Err(_) => Err(format_err!("No second device connected.")),
+        };
+        ctx.free_ongoing().await;
+        match res {
+            Ok(()) => {
+                info!(ctx, "Account transferred to second device.");
+                ctx.emit_event(Event::ImexProgress(1000));
+            }
+            Err(err) => {
+                warn!(ctx, "Cannot transfer account to second device: {}", err);
+                ctx.emit_event(Event::ImexProgress(0));
+            }
+        }
+    });
+    Ok(qr)
+}
+
+/// An account prepared for the transfer to a second device.
+struct SecondDeviceTransfer {
+    listener: TcpListener,
+
+    /// The one-time secret the second device has to prove to know.
+    secret: String,
+
+    /// `SECOND_DEVICE_SETUP` or `SECOND_DEVICE_BACKUP`.
+    kind: u8,
+
+    /// The encrypted account data.
+    data: Vec<u8>,
+}
+
+/// Returns the transfer and the QR code to be scanned by the second device.
+async fn prepare_second_device(
+    context: &Context,
+    include_backup: bool,
+) -> Result<(SecondDeviceTransfer, String)> {
+    ensure!(
+        context.is_configured().await,
+        "Only configured accounts can be transferred."
+    );
+    e2ee::ensure_secret_key_exists(context).await?;
+    context.emit_event(Event::ImexProgress(10));
+
+    let (kind, plain) = if include_backup {
+        // the database is closed while it is copied
+        ensure!(
+            !context.is_io_running().await,
+            "Cannot transfer a backup while IO is running."
+        );
+        let path = PartialBackup(context.get_dbfile().with_extension("second-device.bak"));
+        write_backup(context, &path.0, time(), None, &HashMap::new()).await?;
+        (SECOND_DEVICE_BACKUP, async_std::fs::read(&path.0).await?)
+    } else {
+        (
+            SECOND_DEVICE_SETUP,
+            render_second_device_setup(context).await?,
+        )
+    };
+
+    let secret: String = thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(32)
+        .collect();
+    let data = pgp::symm_encrypt_bytes(&secret, &plain).await?;
+
+    let listener = TcpListener::bind((local_ip().await?, 0)).await?;
+    let qr = qr::render_second_device_qr(&secret, &listener.local_addr()?);
+    context.emit_event(Event::ImexProgress(100));
+    let transfer = SecondDeviceTransfer {
+        listener,
+        secret,
+        kind,
+        data,
+    };
+    Ok((transfer, qr))
+}
+
+/// Returns the IP address of the interface used for outgoing connections.
+async fn local_ip() -> Result<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    // connecting an UDP socket does not send anything, it only selects the interface
+    socket.connect("192.0.2.1:9").await?;
+    Ok(socket.local_addr()?.ip())
+}
+
+/// The second device authenticates by sending this hash of the one-time
+/// secret and the challenge sent to it.
+///
+/// As the challenge is random for each connection, an overheard response
+/// cannot be replayed; the secret itself is never sent over the network.
+fn second_device_auth(secret: &str, challenge: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.input(format!("dc-second-device:{}:", secret).as_bytes());
+    hasher.input(challenge);
+    hasher.result().to_vec()
+}
+
+/// Waits for the second device and sends the account data.
+///
+/// The data are sent as the kind byte, the length as big-endian `u64`
+/// and the encrypted data; the second device acknowledges with a single byte.
+async fn serve_second_device(context: &Context, transfer: SecondDeviceTransfer) -> Result<()> {
+    let (sender, receiver) = async_std::sync::channel(1);
+    let authenticated = async {
+        receiver
+            .recv()
+            .await
+            .map_err(|_| format_err!("No second device authenticated."))
+    };
+    let mut stream = authenticated
+        .race(accept_second_devices(
+            context,
+            &transfer.listener,
+            &transfer.secret,
+            sender,
+        ))
+        .await?;
+    context.emit_event(Event::ImexProgress(500));
+
+    stream.write_all(&[transfer.kind]).await?;
+    stream
+        .write_all(&(transfer.data.len() as u64).to_be_bytes())
+        .await?;
+    stream.write_all(&transfer.data).await?;
+    let mut ack = [0u8; 1];
+    stream.read_exact(&mut ack).await?;
+    Ok(())
+}
+
+/// Accepts connections and sends the first authenticated one to `sender`.
+///
+/// Each connection is authenticated in its own task, so that a client
+/// not knowing the secret cannot block the second device by connecting first.
+async fn accept_second_devices(
+    context: &Context,
+    listener: &TcpListener,
+    secret: &str,
+    sender: async_std::sync::Sender<TcpStream>,
+) -> Result<TcpStream> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        info!(context, "Second device connected from {}.", peer);
+        let ctx = context.clone();
+        let secret = secret.to_string();
+        let sender = sender.clone();
+        async_std::task::spawn(async move {
+            let auth = async_std::io::timeout(
+                SECOND_DEVICE_CONNECT_TIMEOUT,
+                authenticate_second_device(stream, &secret),
+            );
+            match auth.await {
+                Ok(stream) => sender.send(stream).await,
+                Err(err) => warn!(
+                    ctx,
+                    "Rejecting unauthenticated connection from {}: {}", peer, err
+                ),
+            }
+        });
+    }
+}
+
+/// Sends a random challenge and checks the response of the second device.
+async fn authenticate_second_device(
+    mut stream: TcpStream,
+    secret: &str,
+) -> std::io::Result<TcpStream> {
+    let challenge: Vec<u8> = thread_rng()
+        .sample_iter(&rand::distributions::Standard)
+        .take(SECOND_DEVICE_CHALLENGE_LEN)
+        .collect();
+    stream.write_all(&challenge).await?;
+    let expected = second_device_auth(secret, &challenge);
+    let mut auth = vec![0u8; expected.len()];
+    stream.read_exact(&mut auth).await?;
+    if auth != expected {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "wrong response",
+        ));
+    }
+    Ok(stream)
+}
+
+/// Receives an account from the device that rendered the QR code
+/// using [provide_second_device].
+///
+/// The account must not be configured yet.  If only the credentials were
+/// transferred, the account has to be configured afterwards, the received
+/// key is used then.  If a backup was transferred, it is imported and the
+/// account is ready to use;  if the database of the providing device is
+/// encrypted, the database of this device must use the same passphrase.
+///
+/// The progress is reported by #DC_EVENT_IMEX_PROGRESS.
+pub async fn receive_from_second_device(context: &Context, qr: &str) -> Result<()> {
+    use futures::future::FutureExt;
+
+    let cancel = context.alloc_ongoing().await?;
+    let res = do_receive_from_second_device(context, qr)
+        .race(cancel.recv().map(|_| Err(format_err!("canceled"))))
+        .await;
+    context.free_ongoing().await;
+
+    match res {
+        Ok(()) => {
+            info!(context, "Account received from other device.");
+            context.emit_event(Event::ImexProgress(1000));
+            Ok(())
+        }
+        Err(err) => {
+            context.emit_event(Event::ImexProgress(0));
+            bail!("Cannot receive account from other device: {}", err);
+        }
+    }
+}
+
+async fn do_receive_from_second_device(context: &Context, qr: &str) -> Result<()> {
+    ensure!(
+        !context.is_configured().await,
+        "Cannot receive an account into an account in use."
+    );
+    let (secret, addr) = qr::parse_second_device_qr(qr)?;
+    context.emit_event(Event::ImexProgress(10));
+
+    let mut stream =
+        async_std::io::timeout(SECOND_DEVICE_CONNECT_TIMEOUT, TcpStream::connect(addr)).await?;
+    let mut challenge = [0u8; SECOND_DEVICE_CHALLENGE_LEN];
+    stream.read_exact(&mut challenge).await?;
+    stream
+        .write_all(&second_device_auth(&secret, &challenge))
+        .await?;
+
+    let mut kind = [0u8; 1];
+    stream.read_exact(&mut kind).await?;
+    let mut len = [0u8; 8];
+    stream.read_exact(&mut len).await?;
+    let len = u64::from_be_bytes(len);
+    let mut data = Vec::new();
+    (&mut stream).take(len).read_to_end(&mut data).await?;
+    ensure!(
+        data.len() as u64 == len,
+        "Connection closed during transfer."
+    );
+    context.emit_event(Event::ImexProgress(500));
+
+    let plain = pgp::symm_decrypt_bytes(&secret, &data).await?;
+    stream.write_all(&[1]).await?;
+
+    match kind[0] {
+        SECOND_DEVICE_SETUP => import_second_device_setup(context, &plain).await,
+        SECOND_DEVICE_BACKUP => {
+            let path = PartialBackup(context.get_dbfile().with_extension("second-device.bak"));
+            async_std::fs::write(&path.0, &plain).await?;
+            import_backup(context, &path.0).await
+        }
+        kind => bail!("Unknown transfer kind {}", kind),
+    }
+}
+
+async fn render_second_device_setup(context: &Context) -> Result<Vec<u8>> {
+    let mut config = Vec::new();
+    for key in SECOND_DEVICE_CONFIG.iter() {
+        if let Some(value) = context.get_config(*key).await {
+            config.push((key.to_string(), value));
+        }
+    }
+    let setup = SecondDeviceSetup {
+        config,
+        secret_key: SignedSecretKey::load_self(context).await?.to_asc(None),
+    };
+    Ok(serde_json::to_vec(&setup)?)
+}
+
+async fn import_second_device_setup(context: &Context, buf: &[u8]) -> Result<()> {
+    let setup: SecondDeviceSetup = serde_json::from_slice(buf)?;
+    for (key, value) in setup.config.iter() {
+        let key: Config = key.parse()?;
+        ensure!(
+            SECOND_DEVICE_CONFIG.contains(&key),
+            "Unexpected setting {}",
+            key
+        );
+        context.set_config(key, Some(value.as_str())).await?;
+    }
+
+    let addr = context
+        .get_config(Config::Addr)
+        .await
+        .ok_or_else(|| format_err!("Missing address"))?;
+    let (secret, _) = SignedSecretKey::from_asc(&setup.secret_key)?;
+    let keypair = pgp::KeyPair {
+        addr: EmailAddress::new(&addr)?,
+        public: secret.split_public_key()?,
+        secret,
+    };
+    key::store_self_keypair(context, &keypair, key::KeyPairUse::Default).await?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -836,6 +1548,217 @@ mod tests {
         assert_eq!(bytes, key.to_asc(None).into_bytes());
     }
 
+    #[async_std::test]
+    async fn test_export_and_import_backup() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        let blob_content = vec![42u8; 100_000];
+        dc_write_file(&t.ctx, t.ctx.get_blobdir().join("file.bin"), &blob_content)
+            .await
+            .unwrap();
+
+        let backup_dir = tempfile::tempdir().unwrap();
+        imex(&t.ctx, ImexMode::ExportBackup, Some(backup_dir.path()))
+            .await
+            .unwrap();
+        let files: Vec<_> = std::fs::read_dir(backup_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        // the temporary file is renamed to the backup
+        assert_eq!(files.len(), 1);
+        assert!(files[0].ends_with(".bak"));
+
+        let t2 = dummy_context().await;
+        let backup = has_backup(&t2.ctx, backup_dir.path(), None).await.unwrap();
+        imex(&t2.ctx, ImexMode::ImportBackup, Some(backup))
+            .await
+            .unwrap();
+        let restored = dc_read_file(&t2.ctx, t2.ctx.get_blobdir().join("file.bin"))
+            .await
+            .unwrap();
+        assert_eq!(restored, blob_content);
+    }
+
+    #[async_std::test]
+    async fn test_second_device_setup() {
+        let t = dummy_context().await;
+        let addr = configure_alice_keypair(&t.ctx).await;
+        t.ctx.set_config(Config::Addr, Some(&addr)).await.unwrap();
+        t.ctx
+            .set_config(Config::MailPw, Some("secret"))
+            .await
+            .unwrap();
+        let setup = render_second_device_setup(&t.ctx).await.unwrap();
+
+        let t2 = dummy_context().await;
+        import_second_device_setup(&t2.ctx, &setup).await.unwrap();
+        assert_eq!(t2.ctx.get_config(Config::Addr).await, Some(addr.clone()));
+        assert_eq!(
+            t2.ctx.get_config(Config::MailPw).await,
+            Some("secret".to_string())
+        );
+        // the key is used once the account is configured
+        t2.ctx
+            .set_config(Config::ConfiguredAddr, Some(&addr))
+            .await
+            .unwrap();
+        assert_eq!(
+            SignedSecretKey::load_self(&t2.ctx).await.unwrap(),
+            alice_keypair().secret
+        );
+
+        // the QR code is checked before connecting
+        assert!(receive_from_second_device(&t2.ctx, "DCSECONDDEVICE:x")
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_second_device_auth() {
+        let auth = second_device_auth("secret", &[1; SECOND_DEVICE_CHALLENGE_LEN]);
+        assert_eq!(
+            auth,
+            second_device_auth("secret", &[1; SECOND_DEVICE_CHALLENGE_LEN])
+        );
+        assert_ne!(
+            auth,
+            second_device_auth("secret", &[2; SECOND_DEVICE_CHALLENGE_LEN])
+        );
+        assert_ne!(
+            auth,
+            second_device_auth("other", &[1; SECOND_DEVICE_CHALLENGE_LEN])
+        );
+    }
+
+    async fn last_backup(context: &Context, dir: &std::path::Path) -> PathBuf {
+        let name = context
+            .sql
+            .get_raw_config(context, "backup_last")
+            .await
+            .unwrap();
+        PathBuf::from(dir.join(name))
+    }
+
+    #[async_std::test]
+    async fn test_incremental_backup() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        let blobdir = t.ctx.get_blobdir();
+        dc_write_file(&t.ctx, blobdir.join("a.txt"), b"a")
+            .await
+            .unwrap();
+        dc_write_file(&t.ctx, blobdir.join("b.txt"), b"b")
+            .await
+            .unwrap();
+
+        let backup_dir = tempfile::tempdir().unwrap();
+        imex(
+            &t.ctx,
+            ImexMode::ExportIncrementalBackup,
+            Some(backup_dir.path()),
+        )
+        .await
+        .unwrap();
+        let base = last_backup(&t.ctx, backup_dir.path()).await;
+
+        dc_write_file(&t.ctx, blobdir.join("b.txt"), b"bb")
+            .await
+            .unwrap();
+        dc_write_file(&t.ctx, blobdir.join("c.txt"), b"c")
+            .await
+            .unwrap();
+        dc_delete_file(&t.ctx, blobdir.join("a.txt")).await;
+        imex(
+            &t.ctx,
+            ImexMode::ExportIncrementalBackup,
+            Some(backup_dir.path()),
+        )
+        .await
+        .unwrap();
+        let increment = last_backup(&t.ctx, backup_dir.path()).await;
+        assert_ne!(base, increment);
+
+        // the increment only contains the changed files
+        let sql = Sql::new();
+        assert!(sql.open(&t.ctx, &increment, true).await);
+        let mut names = sql
+            .query_map(
+                "SELECT file_name FROM backup_blobs;",
+                paramsv![],
+                |row| row.get::<_, String>(0),
+                |names| {
+                    names
+                        .collect::<std::result::Result<Vec<_>, _>>()
+                        .map_err(Into::into)
+                },
+            )
+            .await
+            .unwrap();
+        names.sort();
+        assert_eq!(names, vec!["b.txt", "c.txt"]);
+        sql.close().await;
+
+        dc_write_file(&t.ctx, blobdir.join("d.txt"), b"d")
+            .await
+            .unwrap();
+        imex(
+            &t.ctx,
+            ImexMode::ExportIncrementalBackup,
+            Some(backup_dir.path()),
+        )
+        .await
+        .unwrap();
+        let latest = last_backup(&t.ctx, backup_dir.path()).await;
+
+        // importing the latest backup layers it over the older ones
+        let t2 = dummy_context().await;
+        imex(&t2.ctx, ImexMode::ImportBackup, Some(&latest))
+            .await
+            .unwrap();
+        let blobdir2 = t2.ctx.get_blobdir();
+        assert!(!blobdir2.join("a.txt").exists().await);
+        for (name, content) in &[("b.txt", "bb"), ("c.txt", "c"), ("d.txt", "d")] {
+            let restored = dc_read_file(&t2.ctx, blobdir2.join(name)).await.unwrap();
+            assert_eq!(restored, content.as_bytes());
+        }
+
+        // an incremental backup after restoring is based on the restored backup
+        dc_write_file(&t2.ctx, blobdir2.join("e.txt"), b"e")
+            .await
+            .unwrap();
+        imex(
+            &t2.ctx,
+            ImexMode::ExportIncrementalBackup,
+            Some(backup_dir.path()),
+        )
+        .await
+        .unwrap();
+        let restored_increment = last_backup(&t2.ctx, backup_dir.path()).await;
+        assert_ne!(restored_increment, latest);
+        let t4 = dummy_context().await;
+        imex(&t4.ctx, ImexMode::ImportBackup, Some(&restored_increment))
+            .await
+            .unwrap();
+        let blobdir4 = t4.ctx.get_blobdir();
+        for (name, content) in &[
+            ("b.txt", "bb"),
+            ("c.txt", "c"),
+            ("d.txt", "d"),
+            ("e.txt", "e"),
+        ] {
+            let restored = dc_read_file(&t4.ctx, blobdir4.join(name)).await.unwrap();
+            assert_eq!(restored, content.as_bytes());
+        }
+
+        // without the backups it is based on, the increment cannot be imported
+        std::fs::remove_file(&increment).unwrap();
+        let t3 = dummy_context().await;
+        assert!(imex(&t3.ctx, ImexMode::ImportBackup, Some(&latest))
+            .await
+            .is_err());
+    }
+
     #[test]
     fn test_normalize_setup_code() {
         let norm = normalize_setup_code("123422343234423452346234723482349234");
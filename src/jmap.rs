@@ -0,0 +1,509 @@
+//! # Experimental JMAP transport
+//!
+//! See <https://jmap.io/spec-mail.html>.  The JMAP session is discovered
+//! via `/.well-known/jmap` of the configured `jmap_url`.  New emails in the
+//! inbox are synced using `Email/changes` and `Email/get` and passed as raw
+//! messages to `dc_receive_imf`; outgoing messages are uploaded, imported
+//! into the Sent mailbox and sent using `EmailSubmission/set`.
+//!
+//! The client is created once and cached in the context, see
+//! [crate::transport].  Received messages are stored with the
+//! `server_folder` [JMAP_FOLDER], so no IMAP jobs are run for them.
+
+use async_trait::async_trait;
+use http_types::{Method, Request, Url};
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::context::Context;
+use crate::dc_receive_imf::dc_receive_imf;
+use crate::error::{bail, ensure, format_err, Result};
+use crate::login_param::LoginParam;
+use crate::socks::send_http;
+use crate::transport::{FetchTransport, SendTransport, JMAP_FOLDER};
+
+const CAPABILITIES: [&str; 3] = [
+    "urn:ietf:params:jmap:core",
+    "urn:ietf:params:jmap:mail",
+    "urn:ietf:params:jmap:submission",
+];
+
+/// Raw config key of the last synced `Email` state.
+const EMAIL_STATE_KEY: &str = "jmap.email_state";
+
+/// Maximum number of redirects followed for a GET request.
+const MAX_REDIRECTS: usize = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Session {
+    api_url: String,
+    download_url: String,
+    upload_url: String,
+    account_id: String,
+}
+
+impl Session {
+    fn from_json(json: &Value) -> Result<Self> {
+        let get = |key: &str| {
+            json[key]
+                .as_str()
+                .map(|s| s.to_string())
+                .ok_or_else(|| format_err!("JMAP session without {}", key))
+        };
+        Ok(Session {
+            api_url: get("apiUrl")?,
+            download_url: get("downloadUrl")?,
+            upload_url: get("uploadUrl")?,
+            account_id: json["primaryAccounts"]["urn:ietf:params:jmap:mail"]
+                .as_str()
+                .map(|s| s.to_string())
+                .ok_or_else(|| format_err!("JMAP session without mail account"))?,
+        })
+    }
+
+    fn download_url(&self, blob_id: &str) -> String {
+        self.download_url
+            .replace("{accountId}", &self.account_id)
+            .replace("{blobId}", blob_id)
+            .replace("{name}", "message.eml")
+            .replace("{type}", "message%2Frfc822")
+    }
+
+    fn upload_url(&self) -> String {
+        self.upload_url.replace("{accountId}", &self.account_id)
+    }
+}
+
+#[derive(DebugStub)]
+pub struct JmapClient {
+    url: String,
+    #[debug_stub = "Authorization"]
+    authorization: String,
+    session: Option<Session>,
+}
+
+impl JmapClient {
+    pub(crate) async fn new(context: &Context, url: String) -> Self {
+        let param = LoginParam::from_database(context, "configured_").await;
+        let credentials = format!("{}:{}", param.mail_user, param.mail_pw);
+        JmapClient {
+            url,
+            authorization: format!("Basic {}", base64::encode(&credentials)),
+            session: None,
+        }
+    }
+
+    /// Sends a request authorized for the configured account.
+    ///
+    /// Redirects of GET requests are followed up to [MAX_REDIRECTS] times,
+    /// credentials are only sent to the origin of the original request.
+    /// Responses without a success status are returned as errors.
+    async fn send(&self, context: &Context, mut req: Request) -> Result<http_types::Response> {
+        let origin = req.url().origin();
+        let mut redirects = 0;
+        loop {
+            let url = req.url().clone();
+            if url.origin() == origin {
+                req.insert_header("Authorization", self.authorization.as_str());
+            }
+            let follow = req.method() == Method::Get;
+            let res = send_http(context, req)
+                .await
+                .map_err(|err| format_err!("JMAP request to {} failed: {}", url, err))?;
+            let status = res.status();
+            if follow && status.is_redirection() {
+                ensure!(
+                    redirects < MAX_REDIRECTS,
+                    "JMAP request to {} redirected too often",
+                    url
+                );
+                redirects += 1;
+                let location = res
+                    .header("Location")
+                    .ok_or_else(|| format_err!("JMAP redirect from {} without Location", url))?;
+                let target = url.join(location.last().as_str())?;
+                info!(context, "JMAP: {} redirected to {}", url, target);
+                req = Request::new(Method::Get, target);
+                continue;
+            }
+            ensure!(
+                status.is_success(),
+                "JMAP request to {} failed: {}",
+                url,
+                status
+            );
+            return Ok(res);
+        }
+    }
+
+    async fn session(&mut self, context: &Context) -> Result<Session> {
+        if let Some(ref session) = self.session {
+            return Ok(session.clone());
+        }
+        let url = format!("{}/.well-known/jmap", self.url.trim_end_matches('/'));
+        info!(context, "JMAP: requesting session from {}", url);
+        let json: Value = self
+            .send(context, Request::new(Method::Get, Url::parse(&url)?))
+            .await?
+            .body_json()
+            .await
+            .map_err(|err| format_err!("JMAP session request failed: {}", err))?;
+        let session = Session::from_json(&json)?;
+        self.session = Some(session.clone());
+        Ok(session)
+    }
+
+    /// Performs the given method calls, returning the arguments of the responses.
+    async fn request(&mut self, context: &Context, method_calls: Value) -> Result<Vec<Value>> {
+        let session = self.session(context).await?;
+        let body = json!({
+            "using": CAPABILITIES,
+            "methodCalls": method_calls,
+        });
+        let mut req = Request::new(Method::Post, Url::parse(&session.api_url)?);
+        req.set_body(body.to_string());
+        req.insert_header("Content-Type", "application/json");
+        let json: Value = self
+            .send(context, req)
+            .await?
+            .body_json()
+            .await
+            .map_err(|err| format_err!("JMAP request failed: {}", err))?;
+
+        let responses = json["methodResponses"]
+            .as_array()
+            .ok_or_else(|| format_err!("JMAP response without methodResponses"))?;
+        let mut res = Vec::with_capacity(responses.len());
+        for response in responses {
+            if response[0] == "error" {
+                bail!("JMAP method error: {}", response[1]);
+            }
+            res.push(response[1].clone());
+        }
+        Ok(res)
+    }
+
+    async fn call(&mut self, context: &Context, method: &str, args: Value) -> Result<Value> {
+        self.request(context, json!([[method, args, "0"]]))
+            .await?
+            .pop()
+            .ok_or_else(|| format_err!("JMAP: no response to {}", method))
+    }
+
+    async fn mailbox_id(&mut self, context: &Context, role: &str) -> Result<String> {
+        let account_id = self.session(context).await?.account_id;
+        let res = self
+            .call(
+                context,
+                "Mailbox/query",
+                json!({"accountId": account_id, "filter": {"role": role}}),
+            )
+            .await?;
+        res["ids"][0]
+            .as_str()
+            .map(|id| id.to_string())
+            .ok_or_else(|| format_err!("JMAP: no {} mailbox", role))
+    }
+
+    /// Returns the identity matching the configured address, or the first one.
+    async fn identity_id(&mut self, context: &Context, addr: &str) -> Result<String> {
+        let account_id = self.session(context).await?.account_id;
+        let res = self
+            .call(context, "Identity/get", json!({ "accountId": account_id }))
+            .await?;
+        let identities = res["list"].as_array().cloned().unwrap_or_default();
+        identities
+            .iter()
+            .find(|identity| {
+                identity["email"]
+                    .as_str()
+                    .map(|email| email.eq_ignore_ascii_case(addr))
+                    .unwrap_or_default()
+            })
+            .or_else(|| identities.first())
+            .and_then(|identity| identity["id"].as_str())
+            .map(|id| id.to_string())
+            .ok_or_else(|| format_err!("JMAP: no identity to send from"))
+    }
+
+    async fn download(&mut self, context: &Context, blob_id: &str) -> Result<Vec<u8>> {
+        let url = self.session(context).await?.download_url(blob_id);
+        self.send(context, Request::new(Method::Get, Url::parse(&url)?))
+            .await?
+            .body_bytes()
+            .await
+            .map_err(|err| format_err!("JMAP download of {} failed: {}", blob_id, err))
+    }
+
+    async fn fetch_new_emails(&mut self, context: &Context) -> Result<()> {
+        let account_id = self.session(context).await?.account_id;
+        let mut state = match context.sql.get_raw_config(context, EMAIL_STATE_KEY).await {
+            Some(state) => state,
+            None => {
+                // Like for IMAP, existing messages are not downloaded on the first sync.
+                let res = self
+                    .call(
+                        context,
+                        "Email/get",
+                        json!({"accountId": account_id, "ids": []}),
+                    )
+                    .await?;
+                let state = res["state"]
+                    .as_str()
+                    .ok_or_else(|| format_err!("JMAP: no Email state"))?;
+                info!(context, "JMAP: starting sync at state {}", state);
+                context
+                    .sql
+                    .set_raw_config(context, EMAIL_STATE_KEY, Some(state))
+                    .await?;
+                return Ok(());
+            }
+        };
+        let inbox_id = self.mailbox_id(context, "inbox").await?;
+
+        loop {
+            let changes = self
+                .call(
+                    context,
+                    "Email/changes",
+                    json!({"accountId": account_id, "sinceState": state}),
+                )
+                .await?;
+            let created = changes["created"].as_array().cloned().unwrap_or_default();
+            if !created.is_empty() {
+                let res = self
+                    .call(
+                        context,
+                        "Email/get",
+                        json!({
+                            "accountId": account_id,
+                            "ids": created,
+                            "properties": ["blobId", "mailboxIds", "keywords"],
+                        }),
+                    )
+                    .await?;
+                for email in res["list"].as_array().cloned().unwrap_or_default() {
+                    if email["mailboxIds"][&inbox_id] != true {
+                        continue;
+                    }
+                    let blob_id = match email["blobId"].as_str() {
+                        Some(blob_id) => blob_id,
+                        None => continue,
+                    };
+                    let raw = self.download(context, blob_id).await?;
+                    let is_seen = email["keywords"]["$seen"] == true;
+                    dc_receive_imf(context, &raw, JMAP_FOLDER, 0, is_seen).await?;
+                }
+            }
+
+            state = changes["newState"]
+                .as_str()
+                .ok_or_else(|| format_err!("JMAP: no new Email state"))?
+                .to_string();
+            context
+                .sql
+                .set_raw_config(context, EMAIL_STATE_KEY, Some(&state))
+                .await?;
+            if changes["hasMoreChanges"] != true {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    async fn submit(
+        &mut self,
+        context: &Context,
+        recipients: &[String],
+        message: &[u8],
+    ) -> Result<()> {
+        let session = self.session(context).await?;
+        let mut req = Request::new(Method::Post, Url::parse(&session.upload_url())?);
+        req.set_body(message.to_vec());
+        req.insert_header("Content-Type", "message/rfc822");
+        let upload: Value = self
+            .send(context, req)
+            .await?
+            .body_json()
+            .await
+            .map_err(|err| format_err!("JMAP upload failed: {}", err))?;
+        let blob_id = upload["blobId"]
+            .as_str()
+            .ok_or_else(|| format_err!("JMAP upload without blobId"))?;
+
+        let addr = context
+            .get_config(Config::ConfiguredAddr)
+            .await
+            .unwrap_or_default();
+        let sent_id = self.mailbox_id(context, "sent").await?;
+        let identity_id = self.identity_id(context, &addr).await?;
+        let rcpt_to: Vec<Value> = recipients
+            .iter()
+            .map(|recipient| json!({ "email": recipient }))
+            .collect();
+
+        let responses = self
+            .request(
+                context,
+                json!([
+                    ["Email/import", {
+                        "accountId": session.account_id,
+                        "emails": {"msg": {
+                            "blobId": blob_id,
+                            "mailboxIds": {sent_id: true},
+                            "keywords": {"$seen": true},
+                        }},
+                    }, "0"],
+                    ["EmailSubmission/set", {
+                        "accountId": session.account_id,
+                        "create": {"submission": {
+                            "emailId": "#msg",
+                            "identityId": identity_id,
+                            "envelope": {
+                                "mailFrom": {"email": addr},
+                                "rcptTo": rcpt_to,
+                            },
+                        }},
+                    }, "1"],
+                ]),
+            )
+            .await?;
+        ensure!(
+            responses.len() == 2 && responses[0]["created"]["msg"].is_object(),
+            "JMAP: cannot import message: {}",
+            responses
+                .get(0)
+                .map(|r| &r["notCreated"])
+                .unwrap_or(&Value::Null)
+        );
+        ensure!(
+            responses[1]["created"]["submission"].is_object(),
+            "JMAP: cannot submit message: {}",
+            responses[1]["notCreated"]
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FetchTransport for JmapClient {
+    async fn fetch(&mut self, context: &Context) -> Result<()> {
+        self.fetch_new_emails(context).await
+    }
+}
+
+#[async_trait]
+impl SendTransport for JmapClient {
+    async fn send(
+        &mut self,
+        context: &Context,
+        recipients: &[String],
+        message: &[u8],
+    ) -> Result<()> {
+        self.submit(context, recipients, message).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use async_std::io::BufReader;
+    use async_std::net::TcpListener;
+    use async_std::prelude::*;
+    use async_std::task;
+
+    use crate::test_utils::dummy_context;
+
+    const SESSION: &str = r#"{
+      "primaryAccounts": {"urn:ietf:params:jmap:mail": "u1234"},
+      "apiUrl": "https://jmap.example.org/api/",
+      "downloadUrl": "https://jmap.example.org/download/{accountId}/{blobId}/{name}",
+      "uploadUrl": "https://jmap.example.org/upload/{accountId}/"
+    }"#;
+
+    /// Starts an HTTP server redirecting `/.well-known/jmap` to `/session`
+    /// and `/loop/.well-known/jmap` to itself, other paths are not found.
+    ///
+    /// Returns the base URL.
+    async fn fake_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        task::spawn(async move {
+            let mut incoming = listener.incoming();
+            while let Some(Ok(stream)) = incoming.next().await {
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).await.unwrap();
+                let mut line = String::new();
+                while line != "\r\n" {
+                    line.clear();
+                    reader.read_line(&mut line).await.unwrap();
+                }
+                let path = request_line.split(' ').nth(1).unwrap_or_default();
+                let res = match path {
+                    "/.well-known/jmap" => {
+                        "HTTP/1.1 301 Moved Permanently\r\nLocation: /session\r\ncontent-length: 0\r\n\r\n"
+                            .to_string()
+                    }
+                    "/loop/.well-known/jmap" => {
+                        "HTTP/1.1 302 Found\r\nLocation: /loop/.well-known/jmap\r\ncontent-length: 0\r\n\r\n"
+                            .to_string()
+                    }
+                    "/session" => format!(
+                        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                        SESSION.len(),
+                        SESSION
+                    ),
+                    _ => "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n".to_string(),
+                };
+                (&stream).write_all(res.as_bytes()).await.unwrap();
+            }
+        });
+        format!("http://127.0.0.1:{}", port)
+    }
+
+    #[async_std::test]
+    async fn test_session_redirects() {
+        let t = dummy_context().await;
+        let url = fake_server().await;
+
+        let mut client = JmapClient::new(&t.ctx, url.clone()).await;
+        let session = client.session(&t.ctx).await.unwrap();
+        assert_eq!(session.account_id, "u1234");
+
+        let mut client = JmapClient::new(&t.ctx, format!("{}/loop", url)).await;
+        assert!(client.session(&t.ctx).await.is_err());
+
+        let mut client = JmapClient::new(&t.ctx, format!("{}/missing", url)).await;
+        assert!(client.session(&t.ctx).await.is_err());
+    }
+
+    #[test]
+    fn test_session_from_json() {
+        let json: Value = serde_json::from_str(
+            r#"{
+              "capabilities": {"urn:ietf:params:jmap:core": {}},
+              "primaryAccounts": {"urn:ietf:params:jmap:mail": "u1234"},
+              "apiUrl": "https://jmap.example.org/api/",
+              "downloadUrl": "https://jmap.example.org/download/{accountId}/{blobId}/{name}?type={type}",
+              "uploadUrl": "https://jmap.example.org/upload/{accountId}/"
+            }"#,
+        )
+        .unwrap();
+        let session = Session::from_json(&json).unwrap();
+        assert_eq!(session.api_url, "https://jmap.example.org/api/");
+        assert_eq!(session.account_id, "u1234");
+        assert_eq!(
+            session.download_url("Gabc"),
+            "https://jmap.example.org/download/u1234/Gabc/message.eml?type=message%2Frfc822"
+        );
+        assert_eq!(
+            session.upload_url(),
+            "https://jmap.example.org/upload/u1234/"
+        );
+
+        let json: Value = serde_json::from_str(r#"{"apiUrl": "https://x/"}"#).unwrap();
+        assert!(Session::from_json(&json).is_err());
+    }
+}
@@ -3,39 +3,45 @@
 //! This module implements a job queue maintained in the SQLite database
 //! and job types.
 
+use std::collections::BTreeMap;
 use std::fmt;
 use std::future::Future;
 
 use deltachat_derive::{FromSql, ToSql};
 use itertools::Itertools;
+use num_traits::FromPrimitive;
 use rand::{thread_rng, Rng};
+use serde::Serialize;
 
 use async_smtp::smtp::response::Category;
 use async_smtp::smtp::response::Code;
 use async_smtp::smtp::response::Detail;
+use async_smtp::smtp::response::Response;
 
 use crate::blob::BlobObject;
+use crate::blob_crypt;
 use crate::chat::{self, ChatId};
 use crate::config::Config;
 use crate::constants::*;
-use crate::contact::Contact;
+use crate::contact::{addr_cmp, Contact};
 use crate::context::Context;
 use crate::dc_tools::*;
+use crate::ephemeral;
 use crate::error::{bail, ensure, format_err, Error, Result};
 use crate::events::Event;
 use crate::imap::*;
+use crate::keyserver;
 use crate::location;
-use crate::login_param::LoginParam;
 use crate::message::MsgId;
-use crate::message::{self, Message, MessageState};
+use crate::message::{self, Message, MessageState, SendError};
 use crate::mimefactory::MimeFactory;
 use crate::param::*;
+use crate::peerstate::Peerstate;
 use crate::smtp::Smtp;
+use crate::transport::{self, SendTransport};
+use crate::wkd;
 use crate::{scheduler::InterruptInfo, sql};
 
-// results in ~3 weeks for the last backoff timespan
-const JOB_RETRIES: u32 = 17;
-
 /// Thread IDs
 #[derive(Debug, Display, Copy, Clone, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql)]
 #[repr(i32)]
@@ -81,11 +87,13 @@ impl Default for Thread {
     Clone,
     PartialEq,
     Eq,
+    Hash,
     PartialOrd,
     FromPrimitive,
     ToPrimitive,
     FromSql,
     ToSql,
+    Serialize,
 )]
 #[repr(i32)]
 pub enum Action {
@@ -95,15 +103,21 @@ pub enum Action {
     Housekeeping = 105, // low priority ...
     EmptyServer = 107,
     OldDeleteMsgOnImap = 110,
+    DeleteExpiredMsgs = 120,
     MarkseenMsgOnImap = 130,
+    SetFlaggedOnImap = 135,
 
     // Moving message is prioritized lower than deletion so we don't
     // bother moving message if it is already scheduled for deletion.
     MoveMsg = 200,
     DeleteMsgOnImap = 210,
 
+    // Downloading is requested by the user and thus prioritized highest.
+    DownloadMsg = 250,
+
     // Jobs in the SMTP-thread, range from DC_SMTP_THREAD..DC_SMTP_THREAD+999
-    MaybeSendLocations = 5005, // low priority ...
+    DiscoverKeys = 5002, // low priority ...
+    MaybeSendLocations = 5005,
     MaybeSendLocationsEnded = 5007,
     SendMdn = 5010,
     SendMsgToSmtp = 5901, // ... high priority
@@ -115,6 +129,167 @@ impl Default for Action {
     }
 }
 
+/// Priority lane of a job.
+///
+/// Pending jobs of a higher lane are always run before jobs of a lower
+/// lane, within a lane jobs are ordered by their action.
+#[derive(
+    Debug,
+    Display,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    FromPrimitive,
+    ToPrimitive,
+    FromSql,
+    ToSql,
+)]
+#[repr(i32)]
+pub enum Priority {
+    /// Work the user does not wait for, eg. housekeeping.
+    Bulk = 0,
+
+    Normal = 1,
+
+    /// Actions requested by the user, eg. sending a message.
+    Interactive = 2,
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::Normal
+    }
+}
+
+/// Limits how often jobs of an action are run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Maximum number of runs within `period`.
+    pub max_runs: usize,
+
+    /// Period in seconds.
+    pub period: i64,
+}
+
+/// How a job is retried if it did not succeed.
+///
+/// Every action has a default policy, the number of tries and the maximum
+/// delay can be overridden by [Config::JobMaxTries] and
+/// [Config::JobMaxBackoff].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of tries after which the job is given up.
+    pub max_tries: u32,
+
+    /// Delay in seconds before the first retry, doubled for every further try.
+    pub base_delay: i64,
+
+    /// Upper bound of the delay in seconds.
+    pub max_delay: i64,
+}
+
+impl RetryPolicy {
+    /// Returns the delay in seconds before the job is tried again
+    /// after `tries` unsuccessful tries.
+    ///
+    /// The delay is chosen randomly from the upper half of the
+    /// exponential backoff so that jobs failed at the same time
+    /// are not retried at the same time.
+    pub fn backoff(&self, tries: u32) -> i64 {
+        let exp = tries.saturating_sub(1).min(30);
+        let delay = self
+            .base_delay
+            .max(1)
+            .saturating_mul(1 << exp)
+            .min(self.max_delay.max(1));
+        let jitter = thread_rng().gen_range(0, delay / 2 + 1);
+        (delay - jitter).max(1)
+    }
+}
+
+impl Action {
+    /// Returns the priority lane of the action.
+    pub fn priority(self) -> Priority {
+        use Action::*;
+
+        match self {
+            SendMsgToSmtp | MarkseenMsgOnImap | DownloadMsg => Priority::Interactive,
+            Housekeeping | EmptyServer | DiscoverKeys => Priority::Bulk,
+            Unknown
+            | OldDeleteMsgOnImap
+            | DeleteExpiredMsgs
+            | SetFlaggedOnImap
+            | MoveMsg
+            | DeleteMsgOnImap
+            | MaybeSendLocations
+            | MaybeSendLocationsEnded
+            | SendMdn => Priority::Normal,
+        }
+    }
+
+    /// Returns how often jobs of the action may run, `None` for no limit.
+    pub fn rate_limit(self) -> Option<RateLimit> {
+        use Action::*;
+
+        match self {
+            // some providers throttle or even block accounts
+            // moving or deleting many messages in a short time
+            OldDeleteMsgOnImap | MoveMsg | DeleteMsgOnImap => Some(RateLimit {
+                max_runs: 60,
+                period: 60,
+            }),
+            SendMdn => Some(RateLimit {
+                max_runs: 20,
+                period: 60,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns the retry policy used if not overridden by the configuration.
+    pub fn default_retry_policy(self) -> RetryPolicy {
+        use Action::*;
+
+        let (max_tries, max_delay) = match self {
+            // a message may be sent after the server recovered,
+            // results in ~2 weeks for the last try
+            SendMsgToSmtp => (17, 24 * 60 * 60),
+
+            // retrying housekeeping, MDNs and locations is pointless
+            // after some time as they are sent again anyway or become outdated
+            Housekeeping | SendMdn | MaybeSendLocations | MaybeSendLocationsEnded => (5, 60 * 60),
+
+            // lookups are rate limited per contact anyway
+            DiscoverKeys => (3, 60 * 60),
+
+            Unknown | EmptyServer | OldDeleteMsgOnImap | DeleteExpiredMsgs | MarkseenMsgOnImap
+            | SetFlaggedOnImap | MoveMsg | DeleteMsgOnImap | DownloadMsg => (17, 24 * 60 * 60),
+        };
+        RetryPolicy {
+            max_tries,
+            base_delay: 60,
+            max_delay,
+        }
+    }
+
+    /// Returns the retry policy of the action including configured overrides.
+    pub async fn retry_policy(self, context: &Context) -> RetryPolicy {
+        let mut policy = self.default_retry_policy();
+        let max_tries = context.get_config_int(Config::JobMaxTries).await;
+        if max_tries > 0 {
+            policy.max_tries = max_tries as u32;
+        }
+        let max_delay = context.get_config_int(Config::JobMaxBackoff).await;
+        if max_delay > 0 {
+            policy.max_delay = i64::from(max_delay);
+        }
+        policy
+    }
+}
+
 impl From<Action> for Thread {
     fn from(action: Action) -> Thread {
         use Action::*;
@@ -124,11 +299,15 @@ impl From<Action> for Thread {
 
             Housekeeping => Thread::Imap,
             OldDeleteMsgOnImap => Thread::Imap,
+            DeleteExpiredMsgs => Thread::Imap,
             DeleteMsgOnImap => Thread::Imap,
             EmptyServer => Thread::Imap,
             MarkseenMsgOnImap => Thread::Imap,
+            SetFlaggedOnImap => Thread::Imap,
             MoveMsg => Thread::Imap,
+            DownloadMsg => Thread::Imap,
 
+            DiscoverKeys => Thread::Smtp,
             MaybeSendLocations => Thread::Smtp,
             MaybeSendLocationsEnded => Thread::Smtp,
             SendMdn => Thread::Smtp,
@@ -175,6 +354,16 @@ impl Job {
         self.desired_timestamp - self.added_timestamp
     }
 
+    /// Returns the time at which the job is retried,
+    /// `None` if the job was not tried yet.
+    pub fn next_retry_timestamp(&self) -> Option<i64> {
+        if self.tries > 0 {
+            Some(self.desired_timestamp)
+        } else {
+            None
+        }
+    }
+
     /// Deletes the job from the database.
     async fn delete(self, context: &Context) -> Result<()> {
         if self.job_id != 0 {
@@ -210,14 +399,16 @@ impl Job {
                 .await?;
         } else {
             context.sql.execute(
-                "INSERT INTO jobs (added_timestamp, thread, action, foreign_id, param, desired_timestamp) VALUES (?,?,?,?,?,?);",
+                "INSERT INTO jobs (added_timestamp, thread, action, foreign_id, param, desired_timestamp, tries, priority) VALUES (?,?,?,?,?,?,?,?);",
                 paramsv![
                     self.added_timestamp,
                     thread,
                     self.action,
                     self.foreign_id,
                     self.param.to_string(),
-                    self.desired_timestamp
+                    self.desired_timestamp,
+                    self.tries as i64,
+                    self.action.priority()
                 ]
             ).await?;
         }
@@ -230,7 +421,6 @@ impl Job {
         context: &Context,
         recipients: Vec<async_smtp::EmailAddress>,
         message: Vec<u8>,
-        job_id: u32,
         smtp: &mut Smtp,
         success_cb: F,
     ) -> Status
@@ -246,48 +436,85 @@ impl Job {
             info!(context, "smtp-sending out mime message:");
             println!("{}", String::from_utf8_lossy(&message));
         }
-        match smtp.send(context, recipients, message, job_id).await {
-            Err(crate::smtp::send::Error::SendError(err)) => {
+        let recipients: Vec<String> = recipients.iter().map(|addr| addr.to_string()).collect();
+        let res = match transport::send_configured(context, &recipients, &message).await {
+            Some(res) => res,
+            None => SendTransport::send(smtp, context, &recipients, &message).await,
+        };
+        match res {
+            Ok(()) => {
+                job_try!(success_cb().await);
+                Status::Finished(Ok(()))
+            }
+            Err(err) => match err.downcast::<crate::smtp::send::Error>() {
+                Ok(err) => self.handle_smtp_error(context, smtp, err).await,
+                Err(err) => {
+                    warn!(context, "failed to send: {}", err);
+                    self.pending_error = Some(err.to_string());
+                    Status::RetryLater
+                }
+            },
+        }
+    }
+
+    /// Handles an error returned by SMTP when sending the job's message.
+    async fn handle_smtp_error(
+        &mut self,
+        context: &Context,
+        smtp: &mut Smtp,
+        err: crate::smtp::send::Error,
+    ) -> Status {
+        match err {
+            crate::smtp::send::Error::SendError(err) => {
                 // Remote error, retry later.
                 warn!(context, "SMTP failed to send: {}", err);
                 self.pending_error = Some(err.to_string());
 
+                if let async_smtp::smtp::error::Error::Permanent(ref response)
+                | async_smtp::smtp::error::Error::Transient(ref response) = err
+                {
+                    if self.action == Action::SendMsgToSmtp && 0 != self.foreign_id {
+                        message::set_msg_smtp_response(
+                            context,
+                            MsgId::new(self.foreign_id),
+                            Some(&smtp_response_to_string(response)),
+                        )
+                        .await
+                        .unwrap_or_else(|err| {
+                            warn!(context, "failed to store SMTP response: {}", err);
+                        });
+                    }
+                }
+
                 let res = match err {
                     async_smtp::smtp::error::Error::Permanent(ref response) => {
-                        match response.code {
-                            // Sometimes servers send a permanent error when actually it is a temporary error
-                            // For documentation see https://tools.ietf.org/html/rfc3463
-
-                            // Code 5.5.0, see https://support.delta.chat/t/every-other-message-gets-stuck/877/2
-                            Code {
-                                category: Category::MailSystem,
-                                detail: Detail::Zero,
-                                ..
-                            } => Status::RetryLater,
-
-                            _ => {
-                                // If we do not retry, add an info message to the chat
-                                // Error 5.7.1 should definitely go here: Yandex sends 5.7.1 with a link when it thinks that the email is SPAM.
-                                match Message::load_from_db(context, MsgId::new(self.foreign_id))
-                                    .await
-                                {
-                                    Ok(message) => {
-                                        chat::add_info_msg(
-                                            context,
-                                            message.chat_id,
-                                            err.to_string(),
-                                        )
+                        if !is_permanent_smtp_error(response) {
+                            Status::RetryLater
+                        } else {
+                            // If we do not retry, add an info message to the chat
+                            // Error 5.7.1 should definitely go here: Yandex sends 5.7.1 with a link when it thinks that the email is SPAM.
+                            match Message::load_from_db(context, MsgId::new(self.foreign_id)).await
+                            {
+                                Ok(message) => {
+                                    chat::add_info_msg(context, message.chat_id, err.to_string())
                                         .await
-                                    }
-                                    Err(e) => warn!(
-                                        context,
-                                        "couldn't load chat_id to inform user about SMTP error: {}",
-                                        e
-                                    ),
-                                };
-
-                                Status::Finished(Err(format_err!("Permanent SMTP error: {}", err)))
+                                }
+                                Err(e) => warn!(
+                                    context,
+                                    "couldn't load chat_id to inform user about SMTP error: {}", e
+                                ),
+                            };
+                            if self.action == Action::SendMsgToSmtp && 0 != self.foreign_id {
+                                message::set_msg_failed(
+                                    context,
+                                    MsgId::new(self.foreign_id),
+                                    send_error_from_smtp_response(response),
+                                    Some(err.to_string()),
+                                )
+                                .await;
                             }
+
+                            Status::Finished(Err(format_err!("Permanent SMTP error: {}", err)))
                         }
                     }
                     async_smtp::smtp::error::Error::Transient(_) => {
@@ -310,33 +537,27 @@ impl Job {
 
                 res
             }
-            Err(crate::smtp::send::Error::EnvelopeError(err)) => {
+            crate::smtp::send::Error::EnvelopeError(err) => {
                 // Local error, job is invalid, do not retry.
                 smtp.disconnect().await;
                 warn!(context, "SMTP job is invalid: {}", err);
                 Status::Finished(Err(err.into()))
             }
-            Err(crate::smtp::send::Error::NoTransport) => {
+            crate::smtp::send::Error::NoTransport => {
                 // Should never happen.
                 // It does not even make sense to disconnect here.
                 error!(context, "SMTP job failed because SMTP has no transport");
                 Status::Finished(Err(format_err!("SMTP has not transport")))
             }
-            Ok(()) => {
-                job_try!(success_cb().await);
-                Status::Finished(Ok(()))
-            }
         }
     }
 
     pub async fn send_msg_to_smtp(&mut self, context: &Context, smtp: &mut Smtp) -> Status {
-        //  SMTP server, if not yet done
-        if !smtp.is_connected().await {
-            let loginparam = LoginParam::from_database(context, "configured_").await;
-            if let Err(err) = smtp.connect(context, &loginparam).await {
-                warn!(context, "SMTP connection failure: {:?}", err);
-                return Status::RetryLater;
+        if self.is_expired() {
+            if let Ok(Some(filename)) = self.param.get_path(Param::File, context) {
+                dc_delete_file(context, filename).await;
             }
+            return Status::Finished(Err(format_err!("Message is outdated")));
         }
 
         let filename = job_try!(job_try!(self
@@ -374,11 +595,21 @@ impl Job {
         };
 
         let foreign_id = self.foreign_id;
-        self.smtp_send(context, recipients_list, body, self.job_id, smtp, || {
+        let recipient_addrs: Vec<String> = recipients_list
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect();
+        self.smtp_send(context, recipients_list, body, smtp, || {
             async move {
                 // smtp success, update db ASAP, then delete smtp file
                 if 0 != foreign_id {
                     set_delivered(context, MsgId::new(foreign_id)).await;
+                    message::set_delivered_to_addrs(
+                        context,
+                        MsgId::new(foreign_id),
+                        &recipient_addrs,
+                    )
+                    .await;
                 }
                 // now also delete the generated file
                 dc_delete_file(context, filename).await;
@@ -388,6 +619,15 @@ impl Job {
         .await
     }
 
+    /// Returns true if the job sends a message that is outdated, see [Param::Expires].
+    fn is_expired(&self) -> bool {
+        self.param
+            .get(Param::Expires)
+            .and_then(|expires| expires.parse::<i64>().ok())
+            .map(|expires| expires < time())
+            .unwrap_or_default()
+    }
+
     /// Get `SendMdn` jobs with foreign_id equal to `contact_id` excluding the `job_id` job.
     async fn get_additional_mdn_jobs(
         &self,
@@ -431,13 +671,23 @@ impl Job {
         Ok((job_ids, rfc724_mids))
     }
 
-    async fn send_mdn(&mut self, context: &Context, smtp: &mut Smtp) -> Status {
-        if !context.get_config_bool(Config::MdnsEnabled).await {
-            // User has disabled MDNs after job scheduling but before
-            // execution.
-            return Status::Finished(Err(format_err!("MDNs are disabled")));
-        }
+    /// Looks up the keys of the recipients stored in the job
+    /// in the Web Key Directory and on the keyserver.
+    async fn discover_keys(&mut self, context: &Context) -> Status {
+        let recipients: Vec<String> = self
+            .param
+            .get(Param::Recipients)
+            .unwrap_or_default()
+            .split('\x1e')
+            .filter(|addr| !addr.is_empty())
+            .map(|addr| addr.to_string())
+            .collect();
+        wkd::import_missing_keys(context, &recipients).await;
+        keyserver::import_missing_keys(context, &recipients).await;
+        Status::Finished(Ok(()))
+    }
 
+    async fn send_mdn(&mut self, context: &Context, smtp: &mut Smtp) -> Status {
         let contact_id = self.foreign_id;
         let contact = job_try!(Contact::load_from_db(context, contact_id).await);
         if contact.is_blocked() {
@@ -453,6 +703,16 @@ impl Job {
             )));
         };
 
+        let chat_id = match Message::load_from_db(context, msg_id).await {
+            Ok(msg) => msg.chat_id,
+            Err(_) => ChatId::new(0),
+        };
+        if !chat::mdns_enabled(context, chat_id).await {
+            // User has disabled MDNs after job scheduling but before
+            // execution.
+            return Status::Finished(Err(format_err!("MDNs are disabled")));
+        }
+
         // Try to aggregate other SendMdn jobs and send a combined MDN.
         let (additional_job_ids, additional_rfc724_mids) = self
             .get_additional_mdn_jobs(context, contact_id)
@@ -478,16 +738,7 @@ impl Job {
             .map_err(|err| format_err!("invalid recipient: {} {:?}", addr, err)));
         let recipients = vec![recipient];
 
-        // connect to SMTP server, if not yet done
-        if !smtp.is_connected().await {
-            let loginparam = LoginParam::from_database(context, "configured_").await;
-            if let Err(err) = smtp.connect(context, &loginparam).await {
-                warn!(context, "SMTP connection failure: {:?}", err);
-                return Status::RetryLater;
-            }
-        }
-
-        self.smtp_send(context, recipients, body, self.job_id, smtp, || {
+        self.smtp_send(context, recipients, body, smtp, || {
             async move {
                 // Remove additional SendMdn jobs we have aggregated into this one.
                 kill_ids(context, &additional_job_ids).await?;
@@ -504,7 +755,12 @@ impl Job {
         }
 
         let msg = job_try!(Message::load_from_db(context, MsgId::new(self.foreign_id)).await);
-        let dest_folder = context.get_config(Config::ConfiguredMvboxFolder).await;
+        // an explicit destination is set when (un)archiving chats, see `chat::move_chat_msgs`
+        let explicit_dest = self.param.get(Param::Arg).map(|folder| folder.to_string());
+        let dest_folder = match explicit_dest {
+            Some(ref folder) => Some(folder.clone()),
+            None => context.get_config(Config::ConfiguredMvboxFolder).await,
+        };
 
         if let Some(dest_folder) = dest_folder {
             let server_folder = msg.server_folder.as_ref().unwrap();
@@ -516,7 +772,18 @@ impl Job {
                 ImapActionResult::RetryLater => Status::RetryLater,
                 ImapActionResult::Success => {
                     // Rust-Imap provides no target uid on mv, so just set it to 0, update again when precheck_imf() is called for the moved message
-                    message::update_server_uid(context, &msg.rfc724_mid, &dest_folder, 0).await;
+                    let mut dest_uid = 0;
+                    if explicit_dest.is_some() {
+                        // the destination may not be watched,
+                        // so the new uid would never be known otherwise
+                        if let Some(uid) =
+                            imap.find_uid(context, &dest_folder, &msg.rfc724_mid).await
+                        {
+                            dest_uid = uid;
+                        }
+                    }
+                    message::update_server_uid(context, &msg.rfc724_mid, &dest_folder, dest_uid)
+                        .await;
                     Status::Finished(Ok(()))
                 }
                 ImapActionResult::Failed => {
@@ -543,9 +810,45 @@ impl Job {
             return Status::RetryLater;
         }
 
-        let msg = job_try!(Message::load_from_db(context, MsgId::new(self.foreign_id)).await);
+        if self.foreign_id == 0 {
+            // the message is not stored in the database, eg. a typing notification,
+            // see `add_unstored_msg_deletion`
+            let mid = self.param.get(Param::Arg).unwrap_or_default().to_string();
+            let server_folder = self.param.get(Param::Arg2).unwrap_or_default().to_string();
+            let server_uid = self.param.get_int(Param::Arg3).unwrap_or_default() as u32;
+            return match imap
+                .delete_msg(context, &mid, &server_folder, server_uid)
+                .await
+            {
+                ImapActionResult::AlreadyDone | ImapActionResult::Success => {
+                    Status::Finished(Ok(()))
+                }
+                ImapActionResult::RetryLater | ImapActionResult::Failed => Status::RetryLater,
+            };
+        }
+
+        // messages deleted together are listed in `Param::Arg4`, see `message::delete_msgs`
+        let msg_ids = match self.param.get(Param::Arg4) {
+            Some(ids) => ids
+                .split_whitespace()
+                .filter_map(|id| id.parse().ok())
+                .map(MsgId::new)
+                .collect(),
+            None => vec![MsgId::new(self.foreign_id)],
+        };
+        let mut msgs = Vec::with_capacity(msg_ids.len());
+        for msg_id in msg_ids {
+            match Message::load_from_db(context, msg_id).await {
+                Ok(msg) => msgs.push(msg),
+                Err(err) => warn!(context, "Cannot load message {} to delete: {}", msg_id, err),
+            }
+        }
+
+        /* eg. device messages have no Message-ID */
+        msgs.retain(|msg| !msg.rfc724_mid.is_empty());
 
-        if !msg.rfc724_mid.is_empty() {
+        let mut server_msgs: BTreeMap<&str, Vec<(u32, String)>> = BTreeMap::new();
+        for msg in &msgs {
             let cnt = message::rfc724_mid_cnt(context, &msg.rfc724_mid).await;
             info!(
                 context,
@@ -553,37 +856,47 @@ impl Job {
                 &msg.rfc724_mid,
                 cnt
             );
-            if cnt > 1 {
+            let deleted_cnt = msgs
+                .iter()
+                .filter(|other| other.rfc724_mid == msg.rfc724_mid)
+                .count();
+            if cnt as usize > deleted_cnt {
                 info!(
                     context,
                     "The message is deleted from the server when all parts are deleted.",
                 );
-            } else {
-                /* if this is the last existing part of the message,
-                we delete the message from the server */
-                let mid = msg.rfc724_mid;
-                let server_folder = msg.server_folder.as_ref().unwrap();
-                let res = if msg.server_uid == 0 {
-                    // Message is already deleted on IMAP server.
-                    ImapActionResult::AlreadyDone
-                } else {
-                    imap.delete_msg(context, &mid, server_folder, msg.server_uid)
-                        .await
-                };
-                match res {
-                    ImapActionResult::AlreadyDone | ImapActionResult::Success => {}
-                    ImapActionResult::RetryLater | ImapActionResult::Failed => {
-                        // If job has failed, for example due to some
-                        // IMAP bug, we postpone it instead of failing
-                        // immediately. This will prevent adding it
-                        // immediately again if user has enabled
-                        // automatic message deletion. Without this,
-                        // we might waste a lot of traffic constantly
-                        // retrying message deletion.
-                        return Status::RetryLater;
+                continue;
+            }
+            // if these are the last existing parts of the message,
+            // we delete the message from the server;
+            // a message without UID is already deleted on the server
+            if let Some(server_folder) = msg.server_folder.as_deref() {
+                if msg.server_uid != 0 {
+                    let uids = server_msgs.entry(server_folder).or_default();
+                    if !uids.iter().any(|(uid, _)| *uid == msg.server_uid) {
+                        uids.push((msg.server_uid, msg.rfc724_mid.clone()));
                     }
                 }
             }
+        }
+
+        for (server_folder, uids) in server_msgs {
+            match imap.delete_msgs(context, server_folder, &uids).await {
+                ImapActionResult::AlreadyDone | ImapActionResult::Success => {}
+                ImapActionResult::RetryLater | ImapActionResult::Failed => {
+                    // If job has failed, for example due to some
+                    // IMAP bug, we postpone it instead of failing
+                    // immediately. This will prevent adding it
+                    // immediately again if user has enabled
+                    // automatic message deletion. Without this,
+                    // we might waste a lot of traffic constantly
+                    // retrying message deletion.
+                    return Status::RetryLater;
+                }
+            }
+        }
+
+        for msg in &msgs {
             if msg.chat_id.is_trash() || msg.hidden {
                 // Messages are stored in trash chat only to keep
                 // their server UID and Message-ID. Once message is
@@ -605,11 +918,8 @@ impl Job {
                 // reaches zero, we will remove the message.
                 job_try!(msg.id.unlink(context).await);
             }
-            Status::Finished(Ok(()))
-        } else {
-            /* eg. device messages have no Message-ID */
-            Status::Finished(Ok(()))
         }
+        Status::Finished(Ok(()))
     }
 
     async fn empty_server(&mut self, context: &Context, imap: &mut Imap) -> Status {
@@ -647,7 +957,7 @@ impl Job {
                 // The job will not be retried so locally
                 // there is no risk of double-sending MDNs.
                 if msg.param.get_bool(Param::WantsMdn).unwrap_or_default()
-                    && context.get_config_bool(Config::MdnsEnabled).await
+                    && chat::mdns_enabled(context, msg.chat_id).await
                 {
                     if let Err(err) = send_mdn(context, &msg).await {
                         warn!(context, "could not send out mdn for {}: {}", msg.id, err);
@@ -658,6 +968,29 @@ impl Job {
             }
         }
     }
+
+    /// Syncs the starred state of a message to the `\Flagged` flag on the server.
+    ///
+    /// The current state is read from the database so that the last change wins
+    /// if a message is starred and unstarred quickly.
+    async fn set_flagged_on_imap(&mut self, context: &Context, imap: &mut Imap) -> Status {
+        let msg = job_try!(Message::load_from_db(context, MsgId::new(self.foreign_id)).await);
+        let folder = match msg.server_folder.as_ref() {
+            Some(folder) if msg.server_uid != 0 => folder,
+            // the message is not on the server (anymore)
+            _ => return Status::Finished(Ok(())),
+        };
+        match imap
+            .set_flagged(context, folder, msg.server_uid, msg.is_starred())
+            .await
+        {
+            ImapActionResult::RetryLater => Status::RetryLater,
+            ImapActionResult::AlreadyDone | ImapActionResult::Success => Status::Finished(Ok(())),
+            ImapActionResult::Failed => {
+                Status::Finished(Err(format_err!("Cannot set flagged state of {}", msg.id)))
+            }
+        }
+    }
 }
 
 /// Delete all pending jobs with the given action.
@@ -692,8 +1025,73 @@ pub async fn action_exists(context: &Context, action: Action) -> bool {
         .unwrap_or_default()
 }
 
+/// Formats an SMTP response as sent by the server, e.g. `550 5.7.1 rejected`.
+fn smtp_response_to_string(response: &Response) -> String {
+    format!("{} {}", response.code, response.message.join(" "))
+        .trim()
+        .to_string()
+}
+
+/// Returns true if sending should not be retried after the server answered
+/// with the permanent error `response`.
+///
+/// Sometimes servers send a permanent error when actually it is a temporary
+/// error, for documentation see https://tools.ietf.org/html/rfc3463
+fn is_permanent_smtp_error(response: &Response) -> bool {
+    match response.code {
+        // Code 5.5.0, see https://support.delta.chat/t/every-other-message-gets-stuck/877/2
+        Code {
+            category: Category::MailSystem,
+            detail: Detail::Zero,
+            ..
+        } => false,
+
+        // eg. 5.4.5 "daily sending quota exceeded", the quota is reset later
+        _ => send_error_from_smtp_response(response) != SendError::RateLimited,
+    }
+}
+
+/// Classifies a permanent error response of the SMTP server.
+///
+/// The enhanced status code, eg. `5.1.1`, is more specific than the basic
+/// reply code, so it is preferred if the server sends one, see RFC 3463.
+fn send_error_from_smtp_response(response: &Response) -> SendError {
+    let enhanced_code = response
+        .message
+        .first()
+        .and_then(|line| line.split_whitespace().next())
+        .and_then(|code| {
+            let mut parts = code.splitn(3, '.').map(|part| part.parse::<u16>().ok());
+            let class = parts.next()??;
+            let subject = parts.next()??;
+            let detail = parts.next()??;
+            if class == 4 || class == 5 {
+                Some((subject, detail))
+            } else {
+                None
+            }
+        });
+
+    match enhanced_code {
+        Some((1, _)) | Some((2, 1)) | Some((2, 2)) => SendError::RecipientRefused,
+        Some((2, 3)) | Some((3, 4)) => SendError::MessageTooLarge,
+        Some((4, 5)) => SendError::RateLimited,
+        Some(_) => SendError::SmtpRejected,
+        None => match response.code.to_string().as_str() {
+            "550" | "551" | "553" => SendError::RecipientRefused,
+            "552" => SendError::MessageTooLarge,
+            _ => SendError::SmtpRejected,
+        },
+    }
+}
+
 async fn set_delivered(context: &Context, msg_id: MsgId) {
     message::update_msg_state(context, msg_id, MessageState::OutDelivered).await;
+    message::set_msg_smtp_response(context, msg_id, None)
+        .await
+        .unwrap_or_else(|err| {
+            warn!(context, "failed to clear SMTP response: {}", err);
+        });
     let chat_id: ChatId = context
         .sql
         .query_get_value(
@@ -706,6 +1104,69 @@ async fn set_delivered(context: &Context, msg_id: MsgId) {
     context.emit_event(Event::MsgDelivered { chat_id, msg_id });
 }
 
+/// Deletes a received message that is not stored in the database from the server.
+pub(crate) async fn add_unstored_msg_deletion(
+    context: &Context,
+    rfc724_mid: &str,
+    server_folder: &str,
+    server_uid: u32,
+) {
+    if server_uid == 0 {
+        return;
+    }
+    let mut param = Params::new();
+    param.set(Param::Arg, rfc724_mid);
+    param.set(Param::Arg2, server_folder);
+    param.set_int(Param::Arg3, server_uid as i32);
+    add(context, Job::new(Action::DeleteMsgOnImap, 0, param, 0)).await;
+}
+
+/// Sends a message that is not stored in the database, eg. a typing notification.
+///
+/// The message is only sent if it can be end-to-end encrypted,
+/// it is not sent to self.  If `lifetime` is set, the message is dropped
+/// if it could not be sent within this many seconds.
+/// Returns true if a send job was added.
+pub(crate) async fn send_unstored_msg(
+    context: &Context,
+    msg: &mut Message,
+    lifetime: Option<i64>,
+) -> Result<bool> {
+    let from = context
+        .get_config(Config::ConfiguredAddr)
+        .await
+        .unwrap_or_default();
+    msg.rfc724_mid = dc_create_outgoing_rfc724_mid(None, &from);
+    msg.timestamp_sort = dc_create_smeared_timestamp(context).await;
+
+    let mimefactory = MimeFactory::from_msg(context, msg, false).await?;
+    let recipients = mimefactory.recipients();
+    if recipients.is_empty() {
+        return Ok(false);
+    }
+    let rendered_msg = mimefactory.render().await?;
+    if !rendered_msg.is_encrypted {
+        info!(
+            context,
+            "Not sending unencrypted {} to {}",
+            msg.param.get_cmd(),
+            msg.chat_id
+        );
+        return Ok(false);
+    }
+
+    let bytes = blob_crypt::encrypt_if_enabled(context, rendered_msg.message).await?;
+    let blob = BlobObject::create(context, &rendered_msg.rfc724_mid, &bytes).await?;
+    let mut param = Params::new();
+    param.set(Param::File, blob.as_name());
+    param.set(Param::Recipients, recipients.join("\x1e"));
+    if let Some(lifetime) = lifetime {
+        param.set(Param::Expires, (time() + lifetime).to_string());
+    }
+    add(context, create(Action::SendMsgToSmtp, 0, param, 0)?).await;
+    Ok(true)
+}
+
 /// Constructs a job for sending a message.
 ///
 /// Returns `None` if no messages need to be sent out.
@@ -714,6 +1175,7 @@ async fn set_delivered(context: &Context, msg_id: MsgId) {
 pub async fn send_msg_job(context: &Context, msg_id: MsgId) -> Result<Option<Job>> {
     let mut msg = Message::load_from_db(context, msg_id).await?;
     msg.try_calc_and_set_dimensions(context).await.ok();
+    blob_crypt::encrypt_msg_blobs(context, &msg.param).await?;
 
     /* create message */
     let needs_encryption = msg.param.get_bool(Param::GuaranteeE2ee).unwrap_or_default();
@@ -729,6 +1191,7 @@ pub async fn send_msg_job(context: &Context, msg_id: MsgId) -> Result<Option<Job
     let mimefactory = MimeFactory::from_msg(context, &msg, attach_selfavatar).await?;
 
     let mut recipients = mimefactory.recipients();
+    add_discover_keys(context, &recipients).await;
 
     let from = context
         .get_config(Config::ConfiguredAddr)
@@ -760,7 +1223,13 @@ pub async fn send_msg_job(context: &Context, msg_id: MsgId) -> Result<Option<Job
     let rendered_msg = match mimefactory.render().await {
         Ok(res) => Ok(res),
         Err(err) => {
-            message::set_msg_failed(context, msg_id, Some(err.to_string())).await;
+            message::set_msg_failed(
+                context,
+                msg_id,
+                SendError::RenderingFailed,
+                Some(err.to_string()),
+            )
+            .await;
             Err(err)
         }
     }?;
@@ -770,6 +1239,7 @@ pub async fn send_msg_job(context: &Context, msg_id: MsgId) -> Result<Option<Job
         message::set_msg_failed(
             context,
             msg_id,
+            SendError::EncryptionFailed,
             Some("End-to-end-encryption unavailable unexpectedly."),
         )
         .await;
@@ -809,10 +1279,15 @@ pub async fn send_msg_job(context: &Context, msg_id: MsgId) -> Result<Option<Job
         msg.save_param_to_disk(context).await;
     }
 
+    if !rendered_msg.smime.is_empty() {
+        msg.param.set_int(Param::Smime, rendered_msg.smime.bits());
+        msg.save_param_to_disk(context).await;
+    }
+
     ensure!(!recipients.is_empty(), "no recipients for smtp job set");
     let mut param = Params::new();
-    let bytes = &rendered_msg.message;
-    let blob = BlobObject::create(context, &rendered_msg.rfc724_mid, bytes).await?;
+    let bytes = blob_crypt::encrypt_if_enabled(context, rendered_msg.message).await?;
+    let blob = BlobObject::create(context, &rendered_msg.rfc724_mid, &bytes).await?;
 
     let recipients = recipients.join("\x1e");
     param.set(Param::File, blob.as_name());
@@ -887,7 +1362,62 @@ impl<'a> Connection<'a> {
     }
 }
 
+/// Returns the number of seconds until a job of `action` may run
+/// without exceeding the rate limit of the action, 0 if it may run now.
+async fn rate_limit_delay(context: &Context, action: Action) -> i64 {
+    let limit = match action.rate_limit() {
+        Some(limit) => limit,
+        None => return 0,
+    };
+    let now = time();
+    let mut job_runs = context.job_runs.write().await;
+    let runs = job_runs.entry(action).or_default();
+    while runs
+        .front()
+        .map_or(false, |timestamp| *timestamp <= now - limit.period)
+    {
+        runs.pop_front();
+    }
+    if runs.len() < limit.max_runs {
+        0
+    } else {
+        runs.front()
+            .map_or(0, |timestamp| timestamp + limit.period - now)
+            .max(1)
+    }
+}
+
+/// Records a run of a job for rate limiting.
+async fn record_run(context: &Context, action: Action) {
+    if action.rate_limit().is_some() {
+        context
+            .job_runs
+            .write()
+            .await
+            .entry(action)
+            .or_default()
+            .push_back(time());
+    }
+}
+
 pub(crate) async fn perform_job(context: &Context, mut connection: Connection<'_>, mut job: Job) {
+    let delay = rate_limit_delay(context, job.action).await;
+    if delay > 0 {
+        info!(
+            context,
+            "{}-job {} is rate limited, postponed by {} seconds", &connection, &job, delay
+        );
+        // jobs not stored in the database are created again when due
+        if job.job_id != 0 {
+            job.desired_timestamp = time() + delay;
+            job.save(context).await.unwrap_or_else(|err| {
+                error!(context, "failed to save job: {}", err);
+            });
+        }
+        return;
+    }
+    record_run(context, job.action).await;
+
     info!(context, "{}-job {} started...", &connection, &job);
 
     let try_res = match perform_job_action(context, &mut job, &mut connection, 0).await {
@@ -898,14 +1428,18 @@ pub(crate) async fn perform_job(context: &Context, mut connection: Connection<'_
     match try_res {
         Status::RetryNow | Status::RetryLater => {
             let tries = job.tries + 1;
+            let policy = job.action.retry_policy(context).await;
 
-            if tries < JOB_RETRIES {
+            if tries < policy.max_tries {
                 info!(
                     context,
                     "{} thread increases job {} tries to {}", &connection, job, tries
                 );
                 job.tries = tries;
-                let time_offset = get_backoff_time_offset(tries);
+                if let Some(ref err) = job.pending_error {
+                    job.param.set(Param::Error, err);
+                }
+                let time_offset = policy.backoff(tries);
                 job.desired_timestamp = time() + time_offset;
                 info!(
                     context,
@@ -924,8 +1458,17 @@ pub(crate) async fn perform_job(context: &Context, mut connection: Connection<'_
                     "{} thread removes job {} as it exhausted {} retries",
                     &connection,
                     job,
-                    JOB_RETRIES
+                    policy.max_tries
                 );
+                if job.action == Action::SendMsgToSmtp && job.foreign_id != 0 {
+                    message::set_msg_failed(
+                        context,
+                        MsgId::new(job.foreign_id),
+                        SendError::Other,
+                        job.pending_error.as_ref(),
+                    )
+                    .await;
+                }
                 job.delete(context).await.unwrap_or_else(|err| {
                     error!(context, "failed to delete job: {}", err);
                 });
@@ -962,10 +1505,19 @@ async fn perform_job_action(
         "{} begin immediate try {} of job {}", &connection, tries, job
     );
 
+    if is_for_transport_msg(context, job).await {
+        info!(
+            context,
+            "Skipping job {}, message was not received using IMAP", job
+        );
+        return Status::Finished(Ok(()));
+    }
+
     let try_res = match job.action {
         Action::Unknown => Status::Finished(Err(format_err!("Unknown job id found"))),
         Action::SendMsgToSmtp => job.send_msg_to_smtp(context, connection.smtp()).await,
         Action::SendMdn => job.send_mdn(context, connection.smtp()).await,
+        Action::DiscoverKeys => job.discover_keys(context).await,
         Action::MaybeSendLocations => location::job_maybe_send_locations(context, job).await,
         Action::MaybeSendLocationsEnded => {
             location::job_maybe_send_locations_ended(context, job).await
@@ -974,9 +1526,20 @@ async fn perform_job_action(
         Action::OldDeleteMsgOnImap => job.delete_msg_on_imap(context, connection.inbox()).await,
         Action::DeleteMsgOnImap => job.delete_msg_on_imap(context, connection.inbox()).await,
         Action::MarkseenMsgOnImap => job.markseen_msg_on_imap(context, connection.inbox()).await,
+        Action::SetFlaggedOnImap => job.set_flagged_on_imap(context, connection.inbox()).await,
         Action::MoveMsg => job.move_msg(context, connection.inbox()).await,
+        Action::DownloadMsg => job.download_msg(context, connection.inbox()).await,
+        Action::DeleteExpiredMsgs => match ephemeral::delete_expired_messages(context).await {
+            Ok(_) => {
+                ephemeral::schedule_job(context).await;
+                Status::Finished(Ok(()))
+            }
+            Err(err) => Status::Finished(Err(err)),
+        },
         Action::Housekeeping => {
-            sql::housekeeping(context).await;
+            if let Err(err) = sql::housekeeping(context, Default::default()).await {
+                warn!(context, "Housekeeping failed: {}", err);
+            }
             Status::Finished(Ok(()))
         }
     };
@@ -989,15 +1552,56 @@ async fn perform_job_action(
     try_res
 }
 
-fn get_backoff_time_offset(tries: u32) -> i64 {
-    let n = 2_i32.pow(tries - 1) * 60;
-    let mut rng = thread_rng();
-    let r: i32 = rng.gen();
-    let mut seconds = r % (n + 1);
-    if seconds < 1 {
-        seconds = 1;
+/// Returns true if the job is an IMAP job for a message received by a
+/// transport replacing IMAP, so the message is on no IMAP server.
+async fn is_for_transport_msg(context: &Context, job: &Job) -> bool {
+    match job.action {
+        Action::OldDeleteMsgOnImap
+        | Action::DeleteMsgOnImap
+        | Action::MarkseenMsgOnImap
+        | Action::SetFlaggedOnImap
+        | Action::MoveMsg
+        | Action::DownloadMsg => {}
+        _ => return false,
     }
-    seconds as i64
+    let server_folder: Option<String> = context
+        .sql
+        .query_get_value(
+            context,
+            "SELECT server_folder FROM msgs WHERE id=?;",
+            paramsv![job.foreign_id as i32],
+        )
+        .await;
+    server_folder.as_deref() == Some(transport::JMAP_FOLDER)
+}
+
+/// Schedules looking up the keys of recipients without peerstate.
+///
+/// The lookup is done by a background job so that sending is not delayed
+/// by slow or unreachable key directories; found keys are used for the
+/// following messages.
+async fn add_discover_keys(context: &Context, recipients: &[String]) {
+    if !context.get_config_bool(Config::WkdLookup).await
+        && !context.get_config_bool(Config::KeyserverLookup).await
+    {
+        return;
+    }
+    let self_addr = context
+        .get_config(Config::ConfiguredAddr)
+        .await
+        .unwrap_or_default();
+    let mut missing = Vec::new();
+    for addr in recipients {
+        if !addr_cmp(addr, &self_addr) && Peerstate::from_addr(context, addr).await.is_none() {
+            missing.push(addr.clone());
+        }
+    }
+    if missing.is_empty() {
+        return;
+    }
+    let mut param = Params::new();
+    param.set(Param::Recipients, missing.join("\x1e"));
+    add(context, Job::new(Action::DiscoverKeys, 0, param, 0)).await;
 }
 
 async fn send_mdn(context: &Context, msg: &Message) -> Result<()> {
@@ -1009,6 +1613,230 @@ async fn send_mdn(context: &Context, msg: &Message) -> Result<()> {
     Ok(())
 }
 
+/// A pending job as returned by [list].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JobInfo {
+    pub job_id: u32,
+    pub action: Action,
+
+    /// The object the job works on, eg. the message ID for sending a message.
+    pub foreign_id: u32,
+
+    /// Number of unsuccessful tries.
+    pub tries: u32,
+
+    /// Time at which the job is run next.
+    pub next_run: i64,
+
+    /// Error of the last unsuccessful try.
+    pub last_error: Option<String>,
+}
+
+/// Returns all pending jobs in the order they are run.
+///
+/// Useful to find out why a message is stuck in the state
+/// [MessageState::OutPending].
+pub async fn list(context: &Context) -> Result<Vec<JobInfo>> {
+    let jobs = context
+        .sql
+        .query_map(
+            "SELECT id, action, foreign_id, param, desired_timestamp, tries
+               FROM jobs
+              ORDER BY priority DESC, desired_timestamp, action DESC, id;",
+            paramsv![],
+            |row| {
+                let param: Params = row.get::<_, String>(3)?.parse().unwrap_or_default();
+                Ok(JobInfo {
+                    job_id: row.get(0)?,
+                    action: Action::from_i32(row.get(1)?).unwrap_or_default(),
+                    foreign_id: row.get(2)?,
+                    next_run: row.get(4)?,
+                    tries: row.get(5)?,
+                    last_error: param.get(Param::Error).map(|err| err.to_string()),
+                })
+            },
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    Ok(jobs)
+}
+
+/// Cancels a pending job, see [list].
+///
+/// If the job sends a message, the message is marked as failed.
+/// A job that is running already is not interrupted.
+pub async fn cancel(context: &Context, job_id: u32) -> Result<()> {
+    let (action, foreign_id) = context
+        .sql
+        .query_row_optional(
+            "SELECT action, foreign_id FROM jobs WHERE id=?;",
+            paramsv![job_id],
+            |row| Ok((row.get::<_, i32>(0)?, row.get::<_, u32>(1)?)),
+        )
+        .await?
+        .ok_or_else(|| format_err!("Job {} does not exist", job_id))?;
+    context
+        .sql
+        .execute("DELETE FROM jobs WHERE id=?;", paramsv![job_id])
+        .await?;
+    info!(context, "Job {} canceled", job_id);
+
+    if Action::from_i32(action) == Some(Action::SendMsgToSmtp) && foreign_id != 0 {
+        message::set_msg_failed(
+            context,
+            MsgId::new(foreign_id),
+            SendError::Other,
+            Some("Sending was canceled"),
+        )
+        .await;
+    }
+    Ok(())
+}
+
+/// A message waiting to be sent, as returned by [Context::get_outgoing_queue].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OutgoingMsg {
+    pub msg_id: MsgId,
+    pub chat_id: ChatId,
+
+    /// Size of the rendered message in bytes.
+    pub size: u64,
+
+    /// Number of unsuccessful tries.
+    pub tries: u32,
+
+    /// Time at which sending is tried next.
+    pub next_run: i64,
+
+    /// Error of the last unsuccessful try, eg. a rejection by the server.
+    pub last_error: Option<String>,
+}
+
+impl Context {
+    /// Returns the messages waiting to be sent in the order they are sent.
+    pub async fn get_outgoing_queue(&self) -> Result<Vec<OutgoingMsg>> {
+        let rows = self
+            .sql
+            .query_map(
+                "SELECT j.foreign_id, m.chat_id, j.param, j.desired_timestamp, j.tries
+                   FROM jobs j
+                   LEFT JOIN msgs m ON m.id=j.foreign_id
+                  WHERE j.action=? AND j.foreign_id!=0
+                  ORDER BY j.priority DESC, j.desired_timestamp, j.id;",
+                paramsv![Action::SendMsgToSmtp],
+                |row| {
+                    let param: Params = row.get::<_, String>(2)?.parse().unwrap_or_default();
+                    Ok((
+                        row.get::<_, MsgId>(0)?,
+                        row.get::<_, Option<ChatId>>(1)?.unwrap_or_default(),
+                        param,
+                        row.get::<_, i64>(3)?,
+                        row.get::<_, u32>(4)?,
+                    ))
+                },
+                |rows| {
+                    rows.collect::<std::result::Result<Vec<_>, _>>()
+                        .map_err(Into::into)
+                },
+            )
+            .await?;
+
+        let mut queue = Vec::with_capacity(rows.len());
+        for (msg_id, chat_id, param, next_run, tries) in rows {
+            let size = match param.get_path(Param::File, self) {
+                Ok(Some(path)) => async_std::fs::metadata(path)
+                    .await
+                    .map(|meta| meta.len())
+                    .unwrap_or_default(),
+                _ => 0,
+            };
+            queue.push(OutgoingMsg {
+                msg_id,
+                chat_id,
+                size,
+                tries,
+                next_run,
+                last_error: param.get(Param::Error).map(|err| err.to_string()),
+            });
+        }
+        Ok(queue)
+    }
+}
+
+/// Cancels sending a message and turns it into the draft of its chat again.
+///
+/// The previous draft of the chat is replaced.  Fails if the message
+/// is not waiting to be sent.  If the message is being sent right now,
+/// waits until the try is finished; a message that was sent successfully
+/// cannot be canceled anymore.
+pub async fn cancel_send(context: &Context, msg_id: MsgId) -> Result<()> {
+    let _job_lock = context.smtp_job_mutex.lock().await;
+    let msg = Message::load_from_db(context, msg_id).await?;
+    ensure!(
+        msg.state == MessageState::OutPending,
+        "Message {} is not waiting to be sent",
+        msg_id
+    );
+    let files = context
+        .sql
+        .query_map(
+            "SELECT param FROM jobs WHERE action=? AND foreign_id=?;",
+            paramsv![Action::SendMsgToSmtp, msg_id],
+            |row| row.get::<_, String>(0),
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    let deleted = context
+        .sql
+        .execute(
+            "DELETE FROM jobs WHERE action=? AND foreign_id=?;",
+            paramsv![Action::SendMsgToSmtp, msg_id],
+        )
+        .await?;
+    ensure!(deleted > 0, "Message {} is not waiting to be sent", msg_id);
+    for param in files {
+        let param: Params = param.parse().unwrap_or_default();
+        if let Ok(Some(path)) = param.get_path(Param::File, context) {
+            dc_delete_file(context, path).await;
+        }
+    }
+
+    msg.chat_id.maybe_delete_draft(context).await;
+    context
+        .sql
+        .execute(
+            "UPDATE msgs SET state=?, hidden=1, timestamp=? WHERE id=?;",
+            paramsv![MessageState::OutDraft, time(), msg_id],
+        )
+        .await?;
+    info!(context, "Sending message {} canceled", msg_id);
+    context.emit_event(Event::MsgsChanged {
+        chat_id: msg.chat_id,
+        msg_id,
+    });
+    Ok(())
+}
+
+/// Makes failed jobs due right away instead of waiting for their backoff,
+/// returns the number of jobs affected.
+pub(crate) async fn reset_backoff(context: &Context) -> Result<usize> {
+    let now = time();
+    let count = context
+        .sql
+        .execute(
+            "UPDATE jobs SET desired_timestamp=? WHERE tries>0 AND desired_timestamp>?;",
+            paramsv![now, now],
+        )
+        .await?;
+    Ok(count)
+}
+
 /// Creates a job.
 pub fn create(action: Action, foreign_id: i32, param: Params, delay_seconds: i64) -> Result<Job> {
     ensure!(
@@ -1028,28 +1856,70 @@ pub async fn add(context: &Context, job: Job) {
     });
 
     if delay_seconds == 0 {
-        match action {
-            Action::Unknown => unreachable!(),
-            Action::Housekeeping
-            | Action::EmptyServer
-            | Action::OldDeleteMsgOnImap
-            | Action::DeleteMsgOnImap
-            | Action::MarkseenMsgOnImap
-            | Action::MoveMsg => {
-                info!(context, "interrupt: imap");
-                context
-                    .interrupt_inbox(InterruptInfo::new(false, None))
-                    .await;
-            }
-            Action::MaybeSendLocations
-            | Action::MaybeSendLocationsEnded
-            | Action::SendMdn
-            | Action::SendMsgToSmtp => {
-                info!(context, "interrupt: smtp");
-                context
-                    .interrupt_smtp(InterruptInfo::new(false, None))
-                    .await;
+        interrupt_thread(context, action.into()).await;
+    }
+}
+
+/// Adds several new jobs in a single transaction.
+///
+/// Other than calling [add] for each job, the threads are interrupted
+/// only once, so this should be used for operations on many messages.
+pub(crate) async fn add_batch(context: &Context, jobs: Vec<Job>) {
+    let mut threads = Vec::new();
+    for job in &jobs {
+        let thread = Thread::from(job.action);
+        if job.delay_seconds() == 0 && !threads.contains(&thread) {
+            threads.push(thread);
+        }
+    }
+
+    let res = context
+        .sql
+        .with_conn(move |mut conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut stmt = tx.prepare(
+                    "INSERT INTO jobs (added_timestamp, thread, action, foreign_id, param, desired_timestamp, priority) VALUES (?,?,?,?,?,?,?);",
+                )?;
+                for job in jobs {
+                    stmt.execute(paramsv![
+                        job.added_timestamp,
+                        Thread::from(job.action),
+                        job.action,
+                        job.foreign_id,
+                        job.param.to_string(),
+                        job.desired_timestamp,
+                        job.action.priority()
+                    ])?;
+                }
             }
+            tx.commit()?;
+            Ok(())
+        })
+        .await;
+    if let Err(err) = res {
+        error!(context, "failed to save jobs: {}", err);
+    }
+
+    for thread in threads {
+        interrupt_thread(context, thread).await;
+    }
+}
+
+async fn interrupt_thread(context: &Context, thread: Thread) {
+    match thread {
+        Thread::Unknown => unreachable!(),
+        Thread::Imap => {
+            info!(context, "interrupt: imap");
+            context
+                .interrupt_inbox(InterruptInfo::new(false, None))
+                .await;
+        }
+        Thread::Smtp => {
+            info!(context, "interrupt: smtp");
+            context
+                .interrupt_smtp(InterruptInfo::new(false, None))
+                .await;
         }
     }
 }
@@ -1078,7 +1948,7 @@ pub(crate) async fn load_next(
 SELECT id, action, foreign_id, param, added_timestamp, desired_timestamp, tries
 FROM jobs
 WHERE thread=? AND foreign_id=?
-ORDER BY action DESC, added_timestamp
+ORDER BY priority DESC, action DESC, added_timestamp
 LIMIT 1;
 "#;
         m = msg_id;
@@ -1090,7 +1960,7 @@ LIMIT 1;
 SELECT id, action, foreign_id, param, added_timestamp, desired_timestamp, tries
 FROM jobs
 WHERE thread=? AND desired_timestamp<=?
-ORDER BY action DESC, added_timestamp
+ORDER BY priority DESC, action DESC, added_timestamp
 LIMIT 1;
 "#;
         params = paramsv![thread_i, t];
@@ -1102,7 +1972,7 @@ LIMIT 1;
 SELECT id, action, foreign_id, param, added_timestamp, desired_timestamp, tries
 FROM jobs
 WHERE thread=? AND tries>0
-ORDER BY desired_timestamp, action DESC
+ORDER BY priority DESC, desired_timestamp, action DESC
 LIMIT 1;
 "#;
         params = paramsv![thread_i];
@@ -1161,8 +2031,12 @@ LIMIT 1;
             None
         }
         Thread::Imap => {
+            let deletion_due = rate_limit_delay(context, Action::DeleteMsgOnImap).await == 0;
             if let Some(job) = job {
-                if job.action < Action::DeleteMsgOnImap {
+                if deletion_due
+                    && (job.action.priority(), job.action)
+                        < (Action::DeleteMsgOnImap.priority(), Action::DeleteMsgOnImap)
+                {
                     load_imap_deletion_job(context)
                         .await
                         .unwrap_or_default()
@@ -1170,8 +2044,10 @@ LIMIT 1;
                 } else {
                     Some(job)
                 }
-            } else {
+            } else if deletion_due {
                 load_imap_deletion_job(context).await.unwrap_or_default()
+            } else {
+                None
             }
         }
         Thread::Smtp => job,
@@ -1244,4 +2120,488 @@ mod tests {
         .await;
         assert!(jobs.is_some());
     }
+
+    #[async_std::test]
+    async fn test_smtp_response_stored_on_failure() {
+        use async_smtp::smtp::error::Error as SmtpError;
+        use async_smtp::smtp::response::Severity;
+
+        let t = dummy_context().await;
+        t.ctx
+            .set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, DC_CONTACT_ID_SELF)
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("hi".to_string()));
+        let msg_id = chat::send_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+        let mut smtp = Smtp::new();
+        let response = |severity, detail, message: &str| {
+            Response::new(
+                Code::new(severity, Category::MailSystem, detail),
+                vec![message.to_string()],
+            )
+        };
+
+        // a transient error is retried, the response is stored nevertheless
+        let mut job = load_next(&t.ctx, Thread::Smtp, &Default::default())
+            .await
+            .unwrap();
+        let err = SmtpError::Transient(response(
+            Severity::TransientNegativeCompletion,
+            Detail::Zero,
+            "4.2.0 try again",
+        ));
+        let status = job
+            .handle_smtp_error(&t.ctx, &mut smtp, crate::smtp::send::Error::SendError(err))
+            .await;
+        assert!(matches!(status, Status::RetryLater));
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_state(), MessageState::OutPending);
+        assert_eq!(
+            msg.get_smtp_response(),
+            Some("450 4.2.0 try again".to_string())
+        );
+
+        // a permanent error fails the message
+        let err = SmtpError::Permanent(response(
+            Severity::PermanentNegativeCompletion,
+            Detail::Four,
+            "5.7.1 rejected",
+        ));
+        let status = job
+            .handle_smtp_error(&t.ctx, &mut smtp, crate::smtp::send::Error::SendError(err))
+            .await;
+        assert!(matches!(status, Status::Finished(Err(_))));
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_state(), MessageState::OutFailed);
+        assert_eq!(msg.get_send_error(), Some(SendError::SmtpRejected));
+        assert_eq!(
+            msg.get_smtp_response(),
+            Some("554 5.7.1 rejected".to_string())
+        );
+        let info = message::get_msg_info(&t.ctx, msg_id).await;
+        assert!(info.contains("SMTP response: 554 5.7.1 rejected"));
+
+        // successful resend clears the response
+        set_delivered(&t.ctx, msg_id).await;
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_smtp_response(), None);
+        assert_eq!(msg.get_send_error(), None);
+    }
+
+    #[test]
+    fn test_send_error_from_smtp_response() {
+        use async_smtp::smtp::response::Severity;
+
+        let response = |detail, message: &str| {
+            Response::new(
+                Code::new(
+                    Severity::PermanentNegativeCompletion,
+                    Category::MailSystem,
+                    detail,
+                ),
+                vec![message.to_string()],
+            )
+        };
+        let classify = |detail, message| send_error_from_smtp_response(&response(detail, message));
+
+        assert_eq!(
+            classify(Detail::Zero, "5.1.1 user unknown"),
+            SendError::RecipientRefused
+        );
+        assert_eq!(
+            classify(Detail::Zero, "5.7.1 spam detected"),
+            SendError::SmtpRejected
+        );
+        assert_eq!(
+            classify(Detail::Four, "5.3.4 message too big"),
+            SendError::MessageTooLarge
+        );
+        assert_eq!(
+            classify(Detail::Zero, "5.4.5 daily sending quota exceeded"),
+            SendError::RateLimited
+        );
+        assert_eq!(
+            classify(Detail::Two, "message exceeds fixed maximum message size"),
+            SendError::MessageTooLarge
+        );
+        assert_eq!(
+            classify(Detail::Zero, "no such user"),
+            SendError::RecipientRefused
+        );
+        assert_eq!(
+            classify(Detail::Four, "transaction failed"),
+            SendError::SmtpRejected
+        );
+    }
+
+    #[test]
+    fn test_is_permanent_smtp_error() {
+        use async_smtp::smtp::response::Severity;
+
+        let response = |category, detail, message: &str| {
+            Response::new(
+                Code::new(Severity::PermanentNegativeCompletion, category, detail),
+                vec![message.to_string()],
+            )
+        };
+        assert!(!is_permanent_smtp_error(&response(
+            Category::MailSystem,
+            Detail::Zero,
+            "5.7.1 rejected"
+        )));
+        assert!(!is_permanent_smtp_error(&response(
+            Category::MailSystem,
+            Detail::One,
+            "5.4.5 daily sending quota exceeded"
+        )));
+        assert!(is_permanent_smtp_error(&response(
+            Category::MailSystem,
+            Detail::Four,
+            "5.3.4 message too big"
+        )));
+        assert!(is_permanent_smtp_error(&response(
+            Category::Information,
+            Detail::Four,
+            "5.7.1 spam detected"
+        )));
+    }
+
+    #[test]
+    fn test_retry_policy_backoff() {
+        let policy = RetryPolicy {
+            max_tries: 17,
+            base_delay: 60,
+            max_delay: 3600,
+        };
+        for _ in 0..100 {
+            let delay = policy.backoff(1);
+            assert!(delay >= 30 && delay <= 60);
+            let delay = policy.backoff(3);
+            assert!(delay >= 120 && delay <= 240);
+            let delay = policy.backoff(16);
+            assert!(delay >= 1800 && delay <= 3600);
+        }
+        assert!(policy.backoff(100) <= 3600);
+    }
+
+    #[async_std::test]
+    async fn test_retry_policy_config() {
+        let t = dummy_context().await;
+        let policy = Action::SendMsgToSmtp.retry_policy(&t.ctx).await;
+        assert_eq!(policy, Action::SendMsgToSmtp.default_retry_policy());
+        assert_eq!(policy.max_tries, 17);
+
+        t.ctx
+            .set_config(Config::JobMaxTries, Some("3"))
+            .await
+            .unwrap();
+        t.ctx
+            .set_config(Config::JobMaxBackoff, Some("600"))
+            .await
+            .unwrap();
+        let policy = Action::SendMdn.retry_policy(&t.ctx).await;
+        assert_eq!(policy.max_tries, 3);
+        assert_eq!(policy.max_delay, 600);
+    }
+
+    #[async_std::test]
+    async fn test_next_retry_timestamp() {
+        let t = dummy_context().await;
+        t.ctx
+            .set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, DC_CONTACT_ID_SELF)
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("hi".to_string()));
+        let msg_id = chat::send_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_next_retry_timestamp(&t.ctx).await, None);
+
+        let mut job = Job::new(Action::SendMsgToSmtp, msg_id.to_u32(), Params::new(), 0);
+        assert_eq!(job.next_retry_timestamp(), None);
+        job.tries = 1;
+        job.desired_timestamp = time() + 120;
+        assert_eq!(job.next_retry_timestamp(), Some(job.desired_timestamp));
+
+        job.clone().save(&t.ctx).await.unwrap();
+        assert_eq!(
+            msg.get_next_retry_timestamp(&t.ctx).await,
+            Some(job.desired_timestamp)
+        );
+    }
+
+    #[async_std::test]
+    async fn test_is_for_transport_msg() {
+        let t = dummy_context().await;
+        t.ctx
+            .set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, DC_CONTACT_ID_SELF)
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("hi".to_string()));
+        let msg_id = chat::send_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+        let job = Job::new(Action::MarkseenMsgOnImap, msg_id.to_u32(), Params::new(), 0);
+        assert!(!is_for_transport_msg(&t.ctx, &job).await);
+
+        t.ctx
+            .sql
+            .execute(
+                "UPDATE msgs SET server_folder=? WHERE id=?;",
+                paramsv![transport::JMAP_FOLDER, msg_id],
+            )
+            .await
+            .unwrap();
+        assert!(is_for_transport_msg(&t.ctx, &job).await);
+        let job = Job::new(Action::SendMdn, msg_id.to_u32(), Params::new(), 0);
+        assert!(!is_for_transport_msg(&t.ctx, &job).await);
+    }
+
+    #[async_std::test]
+    async fn test_maybe_network_resets_backoff() {
+        let t = dummy_context().await;
+        insert_job(&t.ctx, 1).await;
+        insert_job(&t.ctx, 2).await;
+        let later = time() + 3600;
+        t.ctx
+            .sql
+            .execute(
+                "UPDATE jobs SET tries=?, desired_timestamp=? WHERE foreign_id=1;",
+                paramsv![3, later],
+            )
+            .await
+            .unwrap();
+        let job = load_next(
+            &t.ctx,
+            Thread::from(Action::MoveMsg),
+            &InterruptInfo::new(false, None),
+        )
+        .await
+        .unwrap();
+        assert_eq!(job.foreign_id, 2);
+
+        t.ctx.maybe_network().await;
+        let desired_timestamp: i64 = t
+            .ctx
+            .sql
+            .query_get_value(
+                &t.ctx,
+                "SELECT desired_timestamp FROM jobs WHERE foreign_id=1;",
+                paramsv![],
+            )
+            .await
+            .unwrap();
+        assert!(desired_timestamp <= time());
+        assert_eq!(reset_backoff(&t.ctx).await.unwrap(), 0);
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let mut job = Job::new(Action::SendMsgToSmtp, 0, Params::new(), 0);
+        assert!(!job.is_expired());
+        job.param.set(Param::Expires, (time() + 15).to_string());
+        assert!(!job.is_expired());
+        job.param.set(Param::Expires, (time() - 1).to_string());
+        assert!(job.is_expired());
+    }
+
+    #[async_std::test]
+    async fn test_add_discover_keys() {
+        let t = dummy_context().await;
+        t.ctx
+            .set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+        let recipients = vec![
+            "self@example.com".to_string(),
+            "bob@example.net".to_string(),
+        ];
+        add_discover_keys(&t.ctx, &recipients).await;
+        assert!(list(&t.ctx).await.unwrap().is_empty());
+
+        t.ctx
+            .set_config(Config::WkdLookup, Some("1"))
+            .await
+            .unwrap();
+        add_discover_keys(&t.ctx, &recipients).await;
+        let jobs = list(&t.ctx).await.unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].action, Action::DiscoverKeys);
+        let param: Params = t
+            .ctx
+            .sql
+            .query_get_value::<String>(
+                &t.ctx,
+                "SELECT param FROM jobs WHERE id=?;",
+                paramsv![jobs[0].job_id],
+            )
+            .await
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(param.get(Param::Recipients), Some("bob@example.net"));
+    }
+
+    #[async_std::test]
+    async fn test_list_and_cancel() {
+        let t = dummy_context().await;
+        t.ctx
+            .set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, DC_CONTACT_ID_SELF)
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("hi".to_string()));
+        let msg_id = chat::send_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+        for job in list(&t.ctx).await.unwrap() {
+            cancel(&t.ctx, job.job_id).await.unwrap();
+        }
+
+        insert_job(&t.ctx, 1).await;
+        let mut job = Job::new(Action::SendMsgToSmtp, msg_id.to_u32(), Params::new(), 0);
+        job.param.set(Param::Error, "connection refused");
+        job.save(&t.ctx).await.unwrap();
+
+        let jobs = list(&t.ctx).await.unwrap();
+        assert_eq!(jobs.len(), 2);
+        let job = jobs
+            .iter()
+            .find(|job| job.action == Action::SendMsgToSmtp)
+            .unwrap();
+        assert_eq!(job.foreign_id, msg_id.to_u32());
+        assert_eq!(job.tries, 0);
+        assert_eq!(job.last_error, Some("connection refused".to_string()));
+
+        cancel(&t.ctx, job.job_id).await.unwrap();
+        assert!(cancel(&t.ctx, job.job_id).await.is_err());
+        let jobs = list(&t.ctx).await.unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].action, Action::MoveMsg);
+
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_state(), MessageState::OutFailed);
+    }
+
+    #[async_std::test]
+    async fn test_outgoing_queue_and_cancel_send() {
+        let t = dummy_context().await;
+        t.ctx
+            .set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, DC_CONTACT_ID_SELF)
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("hi".to_string()));
+        let msg_id = chat::send_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+
+        let queue = t.ctx.get_outgoing_queue().await.unwrap();
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue[0].msg_id, msg_id);
+        assert_eq!(queue[0].chat_id, chat_id);
+        assert!(queue[0].size > 0);
+        assert_eq!(queue[0].tries, 0);
+        assert_eq!(queue[0].last_error, None);
+
+        cancel_send(&t.ctx, msg_id).await.unwrap();
+        assert!(t.ctx.get_outgoing_queue().await.unwrap().is_empty());
+        let draft = chat_id.get_draft(&t.ctx).await.unwrap().unwrap();
+        assert_eq!(draft.id, msg_id);
+        assert_eq!(draft.get_text(), Some("hi".to_string()));
+
+        // a draft is not waiting to be sent
+        assert!(cancel_send(&t.ctx, msg_id).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn test_cancel_send_waits_for_running_job() {
+        let t = dummy_context().await;
+        t.ctx
+            .set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, DC_CONTACT_ID_SELF)
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("hi".to_string()));
+        let msg_id = chat::send_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+
+        // the smtp loop is sending the message
+        let job_lock = t.ctx.smtp_job_mutex.lock().await;
+        let ctx = t.ctx.clone();
+        let canceled = async_std::task::spawn(async move { cancel_send(&ctx, msg_id).await });
+        async_std::task::sleep(std::time::Duration::from_millis(100)).await;
+        assert_eq!(t.ctx.get_outgoing_queue().await.unwrap().len(), 1);
+
+        message::update_msg_state(&t.ctx, msg_id, MessageState::OutDelivered).await;
+        t.ctx
+            .sql
+            .execute("DELETE FROM jobs WHERE foreign_id=?;", paramsv![msg_id])
+            .await
+            .unwrap();
+        drop(job_lock);
+
+        // a sent message cannot be canceled
+        assert!(canceled.await.is_err());
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_state(), MessageState::OutDelivered);
+    }
+
+    #[async_std::test]
+    async fn test_load_next_job_priority() {
+        let t = dummy_context().await;
+        Job::new(Action::Housekeeping, 0, Params::new(), 0)
+            .save(&t.ctx)
+            .await
+            .unwrap();
+        insert_job(&t.ctx, 1).await;
+        Job::new(Action::MarkseenMsgOnImap, 2, Params::new(), 0)
+            .save(&t.ctx)
+            .await
+            .unwrap();
+
+        let mut actions = Vec::new();
+        while let Some(job) = load_next(&t.ctx, Thread::Imap, &Default::default()).await {
+            actions.push(job.action);
+            job.delete(&t.ctx).await.unwrap();
+        }
+        assert_eq!(
+            actions,
+            vec![
+                Action::MarkseenMsgOnImap,
+                Action::MoveMsg,
+                Action::Housekeeping
+            ]
+        );
+    }
+
+    #[async_std::test]
+    async fn test_rate_limit() {
+        let t = dummy_context().await;
+        let limit = Action::SendMdn.rate_limit().unwrap();
+        for _ in 0..limit.max_runs {
+            assert_eq!(rate_limit_delay(&t.ctx, Action::SendMdn).await, 0);
+            record_run(&t.ctx, Action::SendMdn).await;
+        }
+        let delay = rate_limit_delay(&t.ctx, Action::SendMdn).await;
+        assert!(delay > 0 && delay <= limit.period);
+
+        // other actions are not affected
+        assert_eq!(rate_limit_delay(&t.ctx, Action::MoveMsg).await, 0);
+        record_run(&t.ctx, Action::SendMsgToSmtp).await;
+        assert_eq!(rate_limit_delay(&t.ctx, Action::SendMsgToSmtp).await, 0);
+    }
 }
@@ -0,0 +1,925 @@
+//! # JSON-RPC API
+//!
+//! A [JSON-RPC 2.0] interface to the core for frontends that do not use
+//! the C FFI.  Requests and responses are exchanged as lines of JSON over
+//! any byte stream, eg. stdio or a socket, see [RpcServer::serve].
+//! Events of the context are pushed to the client as `event`
+//! notifications carrying the serialized [Event].
+//!
+//! Parameters are passed by name, eg.
+//! `{"jsonrpc":"2.0","id":1,"method":"send_text_msg","params":{"chat_id":10,"text":"hi"}}`.
+//!
+//! Failed calls are answered with an error of code `-32000`
+//! carrying the [ErrorKind] as `data.kind`, eg. `SendMsg`.
+//!
+//! [JSON-RPC 2.0]: https://www.jsonrpc.org/specification
+//! [Event]: crate::events::Event
+
+mod types;
+
+pub use types::*;
+
+use async_std::io::{self, prelude::*, BufRead, BufReader, Write};
+use async_std::sync::{channel, Receiver, Sender};
+use futures::future::{self, Either};
+use futures::stream::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+use crate::calendar;
+use crate::chat::{self, Chat, ChatId};
+use crate::chatlist::Chatlist;
+use crate::config::Config;
+use crate::contact::{Contact, VerifiedStatus};
+use crate::context::Context;
+use crate::error::{ErrorKind, Result};
+use crate::events::EventEmitter;
+use crate::job;
+use crate::key;
+use crate::location;
+use crate::message::{self, MsgId};
+use crate::reaction;
+use crate::search;
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+/// Error code for errors returned by the core.
+const APPLICATION_ERROR: i32 = -32000;
+
+/// JSON-RPC server for a context.
+#[derive(Debug, Clone)]
+pub struct RpcServer {
+    context: Context,
+}
+
+impl RpcServer {
+    pub fn new(context: Context) -> Self {
+        RpcServer { context }
+    }
+
+    /// Serves requests on stdin, writing responses and events to stdout.
+    pub async fn serve_stdio(&self) -> Result<()> {
+        self.serve(BufReader::new(io::stdin()), io::stdout()).await
+    }
+
+    /// Serves requests read line by line from `reader` until it is closed.
+    ///
+    /// Responses and event notifications are written as lines to `writer`.
+    /// Requests are handled one after another while events are pushed
+    /// concurrently.  The server consumes the events of the context,
+    /// they should not be read from another [EventEmitter] at the same time.
+    pub async fn serve<R, W>(&self, reader: R, writer: W) -> Result<()>
+    where
+        R: BufRead + Unpin,
+        W: Write + Unpin,
+    {
+        let (sender, receiver) = channel(1_000);
+        let process = async move {
+            let res = {
+                let requests = self.read_requests(reader, &sender);
+                let events = push_events(self.context.get_event_emitter(), &sender);
+                futures::pin_mut!(requests, events);
+                match future::select(requests, events).await {
+                    Either::Left((res, _)) => res,
+                    Either::Right(((), _)) => Ok(()),
+                }
+            };
+            // closing the channel stops the writer
+            drop(sender);
+            res
+        };
+
+        let (res, write_res) = future::join(process, write_lines(receiver, writer)).await;
+        res?;
+        write_res
+    }
+
+    /// Handles a single request or a batch of requests serialized as JSON.
+    ///
+    /// Returns the serialized response, `None` if only notifications
+    /// were received.
+    pub async fn handle_request(&self, request: &str) -> Option<String> {
+        let response = match serde_json::from_str::<Value>(request) {
+            Err(err) => Some(to_value(Response::error(
+                Value::Null,
+                RpcError::new(PARSE_ERROR, err.to_string()),
+            ))),
+            Ok(Value::Array(requests)) => {
+                if requests.is_empty() {
+                    Some(to_value(Response::error(
+                        Value::Null,
+                        RpcError::new(INVALID_REQUEST, "Empty batch"),
+                    )))
+                } else {
+                    let mut responses = Vec::new();
+                    for request in requests {
+                        if let Some(response) = self.handle_value(request).await {
+                            responses.push(response);
+                        }
+                    }
+                    if responses.is_empty() {
+                        None
+                    } else {
+                        Some(to_value(responses))
+                    }
+                }
+            }
+            Ok(request) => self.handle_value(request).await.map(to_value),
+        };
+        response.map(|response| response.to_string())
+    }
+
+    async fn read_requests<R: BufRead + Unpin>(
+        &self,
+        reader: R,
+        sender: &Sender<String>,
+    ) -> Result<()> {
+        let mut lines = reader.lines();
+        while let Some(line) = lines.next().await {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle_request(&line).await {
+                sender.send(response).await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_value(&self, request: Value) -> Option<Response> {
+        let request: Request = match serde_json::from_value(request) {
+            Ok(request) => request,
+            Err(err) => {
+                return Some(Response::error(
+                    Value::Null,
+                    RpcError::new(INVALID_REQUEST, err.to_string()),
+                ))
+            }
+        };
+        if request.jsonrpc != "2.0" {
+            return Some(Response::error(
+                request.id.unwrap_or_default(),
+                RpcError::new(INVALID_REQUEST, "Unsupported JSON-RPC version"),
+            ));
+        }
+
+        let result = self.call(&request.method, request.params).await;
+        // notifications are not answered
+        let id = request.id?;
+        Some(match result {
+            Ok(result) => Response::result(id, result),
+            Err(error) => Response::error(id, error),
+        })
+    }
+
+    async fn call(&self, method: &str, params: Value) -> std::result::Result<Value, RpcError> {
+        let context = &self.context;
+        let result = match method {
+            "get_info" => to_value(context.get_info().await),
+            "get_config" => {
+                let params: KeyParams = parse_params(params)?;
+                to_value(context.get_config(parse_key(&params.key)?).await)
+            }
+            "set_config" => {
+                let params: SetConfigParams = parse_params(params)?;
+                context
+                    .set_config(parse_key(&params.key)?, params.value.as_deref())
+                    .await?;
+                Value::Null
+            }
+            "configure" => {
+                context.configure().await?;
+                Value::Null
+            }
+            "is_configured" => to_value(context.is_configured().await),
+            "start_io" => {
+                context.start_io().await;
+                Value::Null
+            }
+            "stop_io" => {
+                context.stop_io().await;
+                Value::Null
+            }
+
+            "get_chatlist" => {
+                let params: ChatlistParams = parse_params(params)?;
+                let chatlist = Chatlist::try_load_page(
+                    context,
+                    params.flags,
+                    params.query.as_deref(),
+                    None,
+                    params.offset,
+                    params.limit,
+                )
+                .await?;
+                let chat_ids: Vec<ChatId> = (0..chatlist.len())
+                    .map(|index| chatlist.get_chat_id(index))
+                    .collect();
+                to_value(chat_ids)
+            }
+            "get_chatlist_count" => {
+                let params: ChatlistParams = parse_params(params)?;
+                to_value(
+                    Chatlist::count(context, params.flags, params.query.as_deref(), None).await?,
+                )
+            }
+            "get_chat" => {
+                let params: ChatIdParams = parse_params(params)?;
+                let chat = Chat::load_from_db(context, params.chat_id).await?;
+                to_value(chat.get_info(context).await?)
+            }
+            "create_chat_by_contact_id" => {
+                let params: ContactIdParams = parse_params(params)?;
+                to_value(chat::create_by_contact_id(context, params.contact_id).await?)
+            }
+            "create_group_chat" => {
+                let params: CreateGroupParams = parse_params(params)?;
+                let verified = if params.verified {
+                    VerifiedStatus::Verified
+                } else {
+                    VerifiedStatus::Unverified
+                };
+                to_value(chat::create_group_chat(context, verified, &params.name).await?)
+            }
+            "get_chat_contacts" => {
+                let params: ChatIdParams = parse_params(params)?;
+                to_value(chat::get_chat_contacts(context, params.chat_id).await)
+            }
+            "add_contact_to_chat" => {
+                let params: ChatContactParams = parse_params(params)?;
+                if !chat::add_contact_to_chat(context, params.chat_id, params.contact_id).await {
+                    return Err(RpcError::new(
+                        APPLICATION_ERROR,
+                        "Cannot add contact to chat",
+                    ));
+                }
+                Value::Null
+            }
+            "remove_contact_from_chat" => {
+                let params: ChatContactParams = parse_params(params)?;
+                chat::remove_contact_from_chat(context, params.chat_id, params.contact_id).await?;
+                Value::Null
+            }
+            "get_chat_msgs" => {
+                let params: ChatIdParams = parse_params(params)?;
+                to_value(chat::get_chat_msgs(context, params.chat_id, 0, None).await)
+            }
+            "marknoticed_chat" => {
+                let params: ChatIdParams = parse_params(params)?;
+                chat::marknoticed_chat(context, params.chat_id).await?;
+                Value::Null
+            }
+
+            "get_message" => {
+                let params: MsgIdParams = parse_params(params)?;
+                to_value(MessageObject::load(context, params.msg_id).await?)
+            }
+            "send_text_msg" => {
+                let params: SendTextParams = parse_params(params)?;
+                to_value(chat::send_text_msg(context, params.chat_id, params.text).await?)
+            }
+            "send_reaction" => {
+                let params: ReactionParams = parse_params(params)?;
+                to_value(reaction::send_reaction(context, params.msg_id, &params.reaction).await?)
+            }
+            "get_reactions" => {
+                let params: MsgIdParams = parse_params(params)?;
+                to_value(ReactionObject::load_all(context, params.msg_id).await?)
+            }
+            "get_read_receipts" => {
+                let params: MsgIdParams = parse_params(params)?;
+                to_value(ReceiptsObject::load(context, params.msg_id).await?)
+            }
+            "get_message_info" => {
+                let params: MsgIdParams = parse_params(params)?;
+                to_value(params.msg_id.get_info_struct(context).await?)
+            }
+            "get_recipient_states" => {
+                let params: MsgIdParams = parse_params(params)?;
+                to_value(params.msg_id.get_recipient_states(context).await?)
+            }
+            "delete_messages" => {
+                let params: MsgIdsParams = parse_params(params)?;
+                message::delete_msgs(context, &params.msg_ids).await;
+                Value::Null
+            }
+            "respond_to_invite" => {
+                let params: InviteResponseParams = parse_params(params)?;
+                to_value(
+                    params
+                        .msg_id
+                        .respond_to_invite(context, params.rsvp)
+                        .await?,
+                )
+            }
+            "forward_messages" => {
+                let params: ForwardParams = parse_params(params)?;
+                to_value(
+                    chat::forward_msgs_ex(
+                        context,
+                        &params.msg_ids,
+                        params.chat_id,
+                        params.attribute,
+                    )
+                    .await?,
+                )
+            }
+            "markseen_msgs" => {
+                let params: MsgIdsParams = parse_params(params)?;
+                message::markseen_msgs(context, params.msg_ids).await;
+                Value::Null
+            }
+            "search_messages" => {
+                let params: SearchParams = parse_params(params)?;
+                to_value(context.search_msgs(params.chat_id, &params.query).await)
+            }
+            "search_in_chat" => {
+                let params: SearchParams = parse_params(params)?;
+                to_value(chat::search_in_chat(context, params.chat_id, &params.query).await?)
+            }
+            "search_messages_advanced" => {
+                let params: SearchParams = parse_params(params)?;
+                to_value(search::search(context, params.chat_id, &params.query).await?)
+            }
+            "get_location_clusters" => {
+                let params: LocationClustersParams = parse_params(params)?;
+                to_value(
+                    location::get_clusters(
+                        context,
+                        params.chat_id,
+                        &params.bbox,
+                        params.zoom,
+                        params.timestamp_from,
+                        params.timestamp_to,
+                    )
+                    .await?,
+                )
+            }
+
+            "get_contacts" => {
+                let params: ContactsParams = parse_params(params)?;
+                to_value(Contact::get_all(context, params.flags, params.query).await?)
+            }
+            "get_contact" => {
+                let params: ContactIdParams = parse_params(params)?;
+                to_value(ContactObject::load(context, params.contact_id).await?)
+            }
+            "create_contact" => {
+                let params: CreateContactParams = parse_params(params)?;
+                to_value(Contact::create(context, &params.name, &params.addr).await?)
+            }
+            "block_contact" => {
+                let params: ContactIdParams = parse_params(params)?;
+                Contact::block(context, params.contact_id).await;
+                Value::Null
+            }
+            "unblock_contact" => {
+                let params: ContactIdParams = parse_params(params)?;
+                Contact::unblock(context, params.contact_id).await;
+                Value::Null
+            }
+            "get_verified_contacts" => to_value(Contact::get_all_verified(context).await?),
+            "revoke_verification" => {
+                let params: ContactIdParams = parse_params(params)?;
+                to_value(Contact::revoke_verification(context, params.contact_id).await?)
+            }
+            "get_jobs" => to_value(job::list(context).await?),
+            "get_connectivity" => to_value(context.get_connectivity().await),
+            "cancel_job" => {
+                let params: JobIdParams = parse_params(params)?;
+                job::cancel(context, params.job_id).await?;
+                Value::Null
+            }
+            "get_outgoing_queue" => to_value(context.get_outgoing_queue().await?),
+            "cancel_send" => {
+                let params: MsgIdParams = parse_params(params)?;
+                job::cancel_send(context, params.msg_id).await?;
+                Value::Null
+            }
+
+            "get_self_keys" => to_value(KeyObject::load_all(context).await?),
+            "rotate_key" => to_value(key::rotate_self_keypair(context).await?.hex()),
+            "set_default_key" => {
+                let params: FingerprintParams = parse_params(params)?;
+                let fingerprint = key::Fingerprint::from_str(&params.fingerprint)
+                    .map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))?;
+                key::set_default_self_key(context, &fingerprint).await?;
+                Value::Null
+            }
+
+            _ => {
+                return Err(RpcError::new(
+                    METHOD_NOT_FOUND,
+                    format!("Unknown method {}", method),
+                ))
+            }
+        };
+        Ok(result)
+    }
+}
+
+async fn push_events(mut emitter: EventEmitter, sender: &Sender<String>) {
+    while let Some(event) = emitter.next().await {
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "event",
+            "params": event,
+        });
+        sender.send(notification.to_string()).await;
+    }
+}
+
+async fn write_lines<W: Write + Unpin>(receiver: Receiver<String>, mut writer: W) -> Result<()> {
+    while let Ok(line) = receiver.recv().await {
+        writer.write_all(line.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+        writer.flush().await?;
+    }
+    Ok(())
+}
+
+fn to_value<T: Serialize>(value: T) -> Value {
+    serde_json::to_value(value).unwrap_or_default()
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> std::result::Result<T, RpcError> {
+    // omitted params are treated like empty ones
+    let params = if params.is_null() {
+        Value::Object(Default::default())
+    } else {
+        params
+    };
+    serde_json::from_value(params).map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))
+}
+
+fn parse_key(key: &str) -> std::result::Result<Config, RpcError> {
+    Config::from_str(key)
+        .map_err(|_| RpcError::new(INVALID_PARAMS, format!("Unknown config key {}", key)))
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    /// Missing for notifications.
+    id: Option<Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+impl Response {
+    fn result(id: Value, result: Value) -> Self {
+        Response {
+            jsonrpc: "2.0",
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    fn error(id: Value, error: RpcError) -> Self {
+        Response {
+            jsonrpc: "2.0",
+            id,
+            result: None,
+            error: Some(error),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct RpcError {
+    code: i32,
+    message: String,
+
+    /// For application errors, `{"kind": ...}` with the [ErrorKind].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i32, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    fn application(kind: ErrorKind, message: impl Into<String>) -> Self {
+        RpcError {
+            code: APPLICATION_ERROR,
+            message: message.into(),
+            data: Some(json!({ "kind": kind })),
+        }
+    }
+}
+
+impl From<crate::error::Error> for RpcError {
+    fn from(err: crate::error::Error) -> Self {
+        RpcError::application(ErrorKind::of(&err), format!("{:#}", err))
+    }
+}
+
+impl From<crate::sql::Error> for RpcError {
+    fn from(err: crate::sql::Error) -> Self {
+        RpcError::application(ErrorKind::Sql, err.to_string())
+    }
+}
+
+impl From<crate::error::ConfigureError> for RpcError {
+    fn from(err: crate::error::ConfigureError) -> Self {
+        RpcError::from(crate::error::Error::from(err))
+    }
+}
+
+impl From<chat::SendMsgError> for RpcError {
+    fn from(err: chat::SendMsgError) -> Self {
+        RpcError::from(crate::error::Error::from(err))
+    }
+}
+
+impl From<crate::key::Error> for RpcError {
+    fn from(err: crate::key::Error) -> Self {
+        RpcError::new(APPLICATION_ERROR, err.to_string())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyParams {
+    key: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetConfigParams {
+    key: String,
+    value: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatlistParams {
+    /// Combination of the `DC_GCL_*` flags.
+    #[serde(default)]
+    flags: usize,
+    query: Option<String>,
+
+    /// Index of the first chat to return, see `Chatlist::try_load_page`.
+    #[serde(default)]
+    offset: usize,
+
+    /// Maximum number of chats to return, all if unset.
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatIdParams {
+    chat_id: ChatId,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContactIdParams {
+    contact_id: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct JobIdParams {
+    job_id: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct FingerprintParams {
+    fingerprint: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatContactParams {
+    chat_id: ChatId,
+    contact_id: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateGroupParams {
+    name: String,
+    #[serde(default)]
+    verified: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct MsgIdParams {
+    msg_id: MsgId,
+}
+
+#[derive(Debug, Deserialize)]
+struct MsgIdsParams {
+    msg_ids: Vec<MsgId>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ForwardParams {
+    msg_ids: Vec<MsgId>,
+    chat_id: ChatId,
+
+    /// Send the names of the original authors along.
+    #[serde(default)]
+    attribute: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct SendTextParams {
+    chat_id: ChatId,
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReactionParams {
+    msg_id: MsgId,
+    reaction: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct InviteResponseParams {
+    msg_id: MsgId,
+    rsvp: calendar::Rsvp,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchParams {
+    /// Unset to search all chats.
+    #[serde(default)]
+    chat_id: ChatId,
+    query: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct LocationClustersParams {
+    /// Unset for all chats.
+    #[serde(default)]
+    chat_id: ChatId,
+    bbox: location::BoundingBox,
+    zoom: u32,
+    #[serde(default)]
+    timestamp_from: i64,
+    /// 0 for up to now.
+    #[serde(default)]
+    timestamp_to: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContactsParams {
+    /// Combination of the `DC_GCL_*` flags.
+    #[serde(default)]
+    flags: u32,
+    query: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateContactParams {
+    #[serde(default)]
+    name: String,
+    addr: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::constants::{DC_CHAT_ID_TRASH, DC_CONTACT_ID_SELF};
+    use crate::test_utils::*;
+
+    async fn call(server: &RpcServer, request: Value) -> Value {
+        let response = server.handle_request(&request.to_string()).await.unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    #[async_std::test]
+    async fn test_jsonrpc_config() {
+        let t = dummy_context().await;
+        let server = RpcServer::new(t.ctx.clone());
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 1, "method": "set_config",
+                   "params": {"key": "displayname", "value": "Alice"}}),
+        )
+        .await;
+        assert_eq!(response, json!({"jsonrpc": "2.0", "id": 1, "result": null}));
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": "two", "method": "get_config",
+                   "params": {"key": "displayname"}}),
+        )
+        .await;
+        assert_eq!(
+            response,
+            json!({"jsonrpc": "2.0", "id": "two", "result": "Alice"})
+        );
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 3, "method": "get_config",
+                   "params": {"key": "no_such_key"}}),
+        )
+        .await;
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[async_std::test]
+    async fn test_jsonrpc_errors() {
+        let t = dummy_context().await;
+        let server = RpcServer::new(t.ctx.clone());
+
+        let response: Value =
+            serde_json::from_str(&server.handle_request("{").await.unwrap()).unwrap();
+        assert_eq!(response["error"]["code"], PARSE_ERROR);
+        assert_eq!(response["id"], Value::Null);
+
+        let response = call(&server, json!({"jsonrpc": "2.0", "id": 1, "method": "foo"})).await;
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 1, "method": "get_chat", "params": {}}),
+        )
+        .await;
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 1, "method": "get_message",
+                   "params": {"msg_id": 12345}}),
+        )
+        .await;
+        assert_eq!(response["error"]["code"], APPLICATION_ERROR);
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 1, "method": "send_text_msg",
+                   "params": {"chat_id": DC_CHAT_ID_TRASH, "text": "hi"}}),
+        )
+        .await;
+        assert_eq!(response["error"]["code"], APPLICATION_ERROR);
+        assert_eq!(response["error"]["data"]["kind"], "SendMsg");
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "1.0", "id": 1, "method": "get_info"}),
+        )
+        .await;
+        assert_eq!(response["error"]["code"], INVALID_REQUEST);
+    }
+
+    #[async_std::test]
+    async fn test_jsonrpc_batch() {
+        let t = dummy_context().await;
+        let server = RpcServer::new(t.ctx.clone());
+
+        // notifications are not answered
+        let request = json!({"jsonrpc": "2.0", "method": "set_config",
+                             "params": {"key": "selfstatus", "value": "busy"}});
+        assert_eq!(server.handle_request(&request.to_string()).await, None);
+        assert_eq!(
+            t.ctx.get_config(Config::Selfstatus).await,
+            Some("busy".to_string())
+        );
+
+        let response = call(
+            &server,
+            json!([
+                {"jsonrpc": "2.0", "method": "set_config",
+                 "params": {"key": "selfstatus", "value": "away"}},
+                {"jsonrpc": "2.0", "id": 1, "method": "get_config",
+                 "params": {"key": "selfstatus"}},
+                {"jsonrpc": "2.0", "id": 2, "method": "is_configured"},
+            ]),
+        )
+        .await;
+        assert_eq!(
+            response,
+            json!([
+                {"jsonrpc": "2.0", "id": 1, "result": "away"},
+                {"jsonrpc": "2.0", "id": 2, "result": false},
+            ])
+        );
+    }
+
+    #[async_std::test]
+    async fn test_jsonrpc_chats_and_messages() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        let server = RpcServer::new(t.ctx.clone());
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 1, "method": "create_contact",
+                   "params": {"name": "Bob", "addr": "bob@example.net"}}),
+        )
+        .await;
+        let contact_id = response["result"].clone();
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 2, "method": "get_contact",
+                   "params": {"contact_id": contact_id}}),
+        )
+        .await;
+        assert_eq!(response["result"]["addr"], "bob@example.net");
+        assert_eq!(response["result"]["display_name"], "Bob");
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 3, "method": "create_chat_by_contact_id",
+                   "params": {"contact_id": contact_id}}),
+        )
+        .await;
+        let chat_id = response["result"].clone();
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 4, "method": "send_text_msg",
+                   "params": {"chat_id": chat_id, "text": "hi bob"}}),
+        )
+        .await;
+        let msg_id = response["result"].clone();
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 5, "method": "get_chat_msgs",
+                   "params": {"chat_id": chat_id}}),
+        )
+        .await;
+        assert_eq!(response["result"], json!([msg_id]));
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 6, "method": "get_message",
+                   "params": {"msg_id": msg_id}}),
+        )
+        .await;
+        assert_eq!(response["result"]["text"], "hi bob");
+        assert_eq!(response["result"]["chat_id"], chat_id);
+        assert_eq!(response["result"]["from_id"], DC_CONTACT_ID_SELF);
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 7, "method": "get_chat",
+                   "params": {"chat_id": chat_id}}),
+        )
+        .await;
+        assert_eq!(response["result"]["name"], "Bob");
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "id": 8, "method": "get_chatlist"}),
+        )
+        .await;
+        assert_eq!(response["result"], json!([chat_id]));
+    }
+
+    #[async_std::test]
+    async fn test_jsonrpc_serve() {
+        let t = dummy_context().await;
+        let server = RpcServer::new(t.ctx.clone());
+
+        let input = concat!(
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "is_configured"}"#,
+            "\n\n",
+            r#"{"jsonrpc": "2.0", "id": 2, "method": "get_config", "params": {"key": "addr"}}"#,
+            "\n",
+        );
+        let mut output = Vec::new();
+        server
+            .serve(futures::io::Cursor::new(input), &mut output)
+            .await
+            .unwrap();
+
+        let responses: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .filter(|response: &Value| response["method"] != "event")
+            .collect();
+        assert_eq!(
+            responses,
+            vec![
+                json!({"jsonrpc": "2.0", "id": 1, "result": false}),
+                json!({"jsonrpc": "2.0", "id": 2, "result": null}),
+            ]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Typed results of the JSON-RPC API.
+
+use serde::Serialize;
+
+use crate::chat::ChatId;
+use crate::contact::{Contact, VerifiedStatus};
+use crate::context::Context;
+use crate::error::Result;
+use crate::key;
+use crate::message::{Message, MsgId, SendError};
+use crate::reaction;
+
+/// A message as returned by `get_message`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MessageObject {
+    pub id: MsgId,
+    pub chat_id: ChatId,
+    pub from_id: u32,
+    pub text: Option<String>,
+
+    /// One of the `DC_MSG_*` viewtype constants of the C API.
+    pub viewtype: i32,
+
+    /// One of the `DC_STATE_*` constants of the C API.
+    pub state: i32,
+
+    /// Why sending failed, set for messages in the state `DC_STATE_OUT_FAILED`.
+    pub send_error: Option<SendError>,
+
+    pub timestamp: i64,
+    pub sort_timestamp: i64,
+    pub received_timestamp: i64,
+
+    /// Absolute path of the attached file.
+    pub file: Option<String>,
+    pub file_mime: Option<String>,
+    pub file_name: Option<String>,
+
+    pub is_info: bool,
+    pub is_forwarded: bool,
+
+    /// Original author of a forwarded message, if named by the forwarding user.
+    pub forwarded_from: Option<String>,
+    pub is_starred: bool,
+    pub is_edited: bool,
+    pub show_padlock: bool,
+    pub quoted_text: Option<String>,
+}
+
+impl MessageObject {
+    pub async fn load(context: &Context, msg_id: MsgId) -> Result<Self> {
+        let msg = Message::load_from_db(context, msg_id).await?;
+        Ok(MessageObject {
+            id: msg.get_id(),
+            chat_id: msg.get_chat_id(),
+            from_id: msg.get_from_id(),
+            text: msg.get_text(),
+            viewtype: msg.get_viewtype() as i32,
+            state: msg.get_state() as i32,
+            send_error: msg.get_send_error(),
+            timestamp: msg.get_timestamp(),
+            sort_timestamp: msg.get_sort_timestamp(),
+            received_timestamp: msg.get_received_timestamp(),
+            file: msg
+                .get_decrypted_file(context)
+                .await
+                .map(|file| file.to_string_lossy().to_string()),
+            file_mime: msg.get_filemime(),
+            file_name: msg.get_filename(),
+            is_info: msg.is_info(),
+            is_forwarded: msg.is_forwarded(),
+            forwarded_from: msg.get_forwarded_from(),
+            is_starred: msg.is_starred(),
+            is_edited: msg.is_edited(),
+            show_padlock: msg.get_showpadlock(),
+            quoted_text: msg.quoted_text(),
+        })
+    }
+}
+
+/// A contact as returned by `get_contact`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContactObject {
+    pub id: u32,
+    pub name: String,
+    pub display_name: String,
+    pub addr: String,
+    pub color: u32,
+
+    /// Absolute path of the profile image.
+    pub profile_image: Option<String>,
+
+    pub is_blocked: bool,
+
+    /// Whether the contact is verified in both directions.
+    pub is_verified: bool,
+
+    /// Timestamp of the last message received from the contact, 0 if unknown.
+    pub last_seen: i64,
+}
+
+impl ContactObject {
+    pub async fn load(context: &Context, contact_id: u32) -> Result<Self> {
+        let contact = Contact::get_by_id(context, contact_id).await?;
+        Ok(ContactObject {
+            id: contact.get_id(),
+            name: contact.get_name().to_string(),
+            display_name: contact.get_display_name().to_string(),
+            addr: contact.get_addr().to_string(),
+            color: contact.get_color(),
+            profile_image: contact
+                .get_profile_image(context)
+                .await
+                .map(|file| file.to_string_lossy().to_string()),
+            is_blocked: contact.is_blocked(),
+            is_verified: contact.is_verified(context).await == VerifiedStatus::BidirectVerified,
+            last_seen: contact.last_seen(),
+        })
+    }
+}
+
+/// A reaction as returned by `get_reactions`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReactionObject {
+    pub contact_id: u32,
+    pub reaction: String,
+}
+
+impl ReactionObject {
+    pub async fn load_all(context: &Context, msg_id: MsgId) -> Result<Vec<Self>> {
+        let reactions = reaction::get_reactions(context, msg_id).await?;
+        Ok(reactions
+            .into_iter()
+            .map(|(contact_id, reaction)| ReactionObject {
+                contact_id,
+                reaction,
+            })
+            .collect())
+    }
+}
+
+/// A read receipt as returned by `get_read_receipts`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReadReceiptObject {
+    pub contact_id: u32,
+    pub timestamp: i64,
+}
+
+/// The receipts of an outgoing message as returned by `get_read_receipts`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReceiptsObject {
+    /// Read receipts, oldest first.
+    pub read: Vec<ReadReceiptObject>,
+
+    /// IDs of the contacts the message was delivered to.
+    pub delivered_to: Vec<u32>,
+}
+
+impl ReceiptsObject {
+    pub async fn load(context: &Context, msg_id: MsgId) -> Result<Self> {
+        let read = msg_id
+            .get_read_receipts(context)
+            .await?
+            .into_iter()
+            .map(|(contact_id, timestamp)| ReadReceiptObject {
+                contact_id,
+                timestamp,
+            })
+            .collect();
+        Ok(ReceiptsObject {
+            read,
+            delivered_to: msg_id.get_delivered_to(context).await?,
+        })
+    }
+}
+
+/// An own key as returned by `get_self_keys`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KeyObject {
+    /// Fingerprint as uppercase hex string.
+    pub fingerprint: String,
+    pub addr: String,
+    pub created: i64,
+
+    /// Whether the key is used for new messages.
+    pub is_default: bool,
+}
+
+impl KeyObject {
+    pub async fn load_all(context: &Context) -> Result<Vec<Self>> {
+        Ok(key::get_self_keys(context)
+            .await?
+            .into_iter()
+            .map(|key| KeyObject {
+                fingerprint: key.fingerprint.hex(),
+                addr: key.addr,
+                created: key.created,
+                is_default: key.is_default,
+            })
+            .collect())
+    }
+}
@@ -41,6 +41,8 @@ pub enum Error {
     InvalidConfiguredAddr(#[from] InvalidEmailError),
     #[error("no data provided")]
     Empty,
+    #[error("No own key with fingerprint {}", _0)]
+    UnknownKey(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -354,6 +356,135 @@ pub async fn store_self_keypair(
     Ok(())
 }
 
+/// Generates a new keypair and makes it the default key.
+///
+/// The new key is announced in the Autocrypt header of all messages sent
+/// afterwards.  The previous keys are kept to decrypt messages that were
+/// encrypted to them, see [get_self_keys].  Contacts that verified the
+/// previous key have to verify the new key again.
+///
+/// Returns the fingerprint of the new key.
+pub async fn rotate_self_keypair(context: &Context) -> Result<Fingerprint> {
+    let addr = context
+        .get_config(Config::ConfiguredAddr)
+        .await
+        .ok_or_else(|| Error::NoConfiguredAddr)?;
+    let addr = EmailAddress::new(&addr)?;
+    let _guard = context.generating_key_mutex.lock().await;
+
+    let keytype =
+        KeyGenType::from_i32(context.get_config_int(Config::KeyGenType).await).unwrap_or_default();
+    info!(
+        context,
+        "Generating new default keypair with type {}", keytype
+    );
+    let keypair =
+        async_std::task::spawn_blocking(move || crate::pgp::create_keypair(addr, keytype)).await?;
+    store_self_keypair(context, &keypair, KeyPairUse::Default).await?;
+    Ok(DcKey::fingerprint(&keypair.public))
+}
+
+/// Makes the own key with the given fingerprint the default key.
+///
+/// This can be used to switch back to a previous key after
+/// [rotate_self_keypair].
+pub async fn set_default_self_key(context: &Context, fingerprint: &Fingerprint) -> Result<()> {
+    let id = get_self_keys(context)
+        .await?
+        .into_iter()
+        .find(|key| &key.fingerprint == fingerprint)
+        .map(|key| key.id)
+        .ok_or_else(|| Error::UnknownKey(fingerprint.hex()))?;
+    context
+        .sql
+        .execute(
+            "UPDATE keypairs SET is_default=(id=?);",
+            paramsv![id as i32],
+        )
+        .await?;
+    Ok(())
+}
+
+/// One of the own keys, as returned by [get_self_keys].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfKeyInfo {
+    /// ID of the key in the database.
+    pub id: u32,
+
+    /// Address the key was created for.
+    pub addr: String,
+
+    pub fingerprint: Fingerprint,
+
+    /// Unix timestamp of when the key was created or imported.
+    pub created: i64,
+
+    /// Whether the key is used for new messages.
+    ///
+    /// Other keys are only used for decryption.
+    pub is_default: bool,
+}
+
+/// Returns all own keys, the default key first, then the newest keys.
+pub async fn get_self_keys(context: &Context) -> Result<Vec<SelfKeyInfo>> {
+    let rows = context
+        .sql
+        .query_map(
+            "SELECT id, addr, public_key, created, is_default
+               FROM keypairs
+              ORDER BY is_default DESC, created DESC, id DESC;",
+            paramsv![],
+            |row| {
+                Ok((
+                    row.get::<_, u32>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Vec<u8>>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, bool>(4)?,
+                ))
+            },
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+
+    let mut keys = Vec::with_capacity(rows.len());
+    for (id, addr, public_key, created, is_default) in rows {
+        keys.push(SelfKeyInfo {
+            id,
+            addr,
+            fingerprint: DcKey::fingerprint(&SignedPublicKey::from_slice(&public_key)?),
+            created,
+            is_default,
+        });
+    }
+    Ok(keys)
+}
+
+/// Loads the own secret keys that are not the default key, the newest first.
+pub(crate) async fn load_previous_secret_keys(context: &Context) -> Result<Vec<SignedSecretKey>> {
+    let rows = context
+        .sql
+        .query_map(
+            "SELECT private_key FROM keypairs WHERE is_default=0 ORDER BY created DESC, id DESC;",
+            paramsv![],
+            |row| row.get::<_, Vec<u8>>(0),
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+
+    let mut keys = Vec::with_capacity(rows.len());
+    for bytes in rows {
+        keys.push(SignedSecretKey::from_slice(&bytes)?);
+    }
+    Ok(keys)
+}
+
 /// A key fingerprint
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Fingerprint(Vec<u8>);
@@ -632,6 +763,39 @@ i8pcjGO+IZffvyZJVRWfVooBJmWWbPB1pueo3tx8w3+fcuzpxz+RLFKaPyqXO+dD
         assert_eq!(nrows().await, 1);
     }
 
+    #[async_std::test]
+    async fn test_rotate_self_keypair() {
+        let alice = alice_keypair();
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+
+        let fingerprint = rotate_self_keypair(&t.ctx).await.unwrap();
+        let pubkey = SignedPublicKey::load_self(&t.ctx).await.unwrap();
+        assert_eq!(DcKey::fingerprint(&pubkey), fingerprint);
+
+        let keys = get_self_keys(&t.ctx).await.unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].fingerprint, fingerprint);
+        assert!(keys[0].is_default);
+        assert_eq!(keys[1].fingerprint, DcKey::fingerprint(&alice.public));
+        assert!(!keys[1].is_default);
+
+        // the previous key is still used for decryption
+        let secret_keys = load_previous_secret_keys(&t.ctx).await.unwrap();
+        assert_eq!(secret_keys, vec![alice.secret]);
+
+        set_default_self_key(&t.ctx, &DcKey::fingerprint(&alice.public))
+            .await
+            .unwrap();
+        let pubkey = SignedPublicKey::load_self(&t.ctx).await.unwrap();
+        assert_eq!(pubkey, alice.public);
+        let keys = get_self_keys(&t.ctx).await.unwrap();
+        assert_eq!(keys.iter().filter(|key| key.is_default).count(), 1);
+
+        let unknown: Fingerprint = "0102030405060708090A0B0c0d0e0F1011121314".parse().unwrap();
+        assert!(set_default_self_key(&t.ctx, &unknown).await.is_err());
+    }
+
     // Convenient way to create a new key if you need one, run with
     // `cargo test key::tests::gen_key`.
     // #[test]
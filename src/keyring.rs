@@ -3,7 +3,7 @@
 use anyhow::Result;
 
 use crate::context::Context;
-use crate::key::{self, DcKey};
+use crate::key::{self, DcKey, SignedSecretKey};
 
 /// An in-memory keyring.
 ///
@@ -58,10 +58,22 @@ where
     }
 }
 
+impl Keyring<SignedSecretKey> {
+    /// Load the user's previous secret keys into the keyring.
+    ///
+    /// Previous keys are not used for new messages but are needed
+    /// to decrypt messages that were encrypted to them.
+    pub async fn load_previous(&mut self, context: &Context) -> Result<(), key::Error> {
+        self.keys
+            .extend(key::load_previous_secret_keys(context).await?);
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::key::{SignedPublicKey, SignedSecretKey};
+    use crate::key::SignedPublicKey;
     use crate::test_utils::*;
 
     #[test]
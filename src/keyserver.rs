@@ -0,0 +1,277 @@
+//! # Keyserver lookup and publishing
+//!
+//! If enabled by [Config::KeyserverLookup], keys of recipients without a
+//! known Autocrypt key are looked up on the configured [Config::Keyserver]
+//! using the HKP protocol by a background job scheduled when a message is
+//! sent to them.  The lookup happens after the Web Key Directory lookup,
+//! see the `wkd` module, and found keys are stored the same way as gossip
+//! keys in the peerstate.  Keys are only imported from keyservers that
+//! publish user IDs after confirming the address, see
+//! [VERIFYING_KEYSERVERS], as any key could be uploaded to other keyservers.
+//! Where a key comes from is recorded and can be queried by
+//! [Contact::get_key_source].
+//!
+//! [publish_self_key] uploads the own public key to the keyserver.
+//! Keyservers as keys.openpgp.org only publish the user IDs of a key after
+//! the address was confirmed by an email sent by the keyserver.
+//!
+//! [Contact::get_key_source]: crate::contact::Contact::get_key_source
+
+use std::time::Duration;
+
+use http_types::{Method, Request, Url};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use url::form_urlencoded;
+
+use crate::config::Config;
+use crate::contact::addr_cmp;
+use crate::context::Context;
+use crate::dc_tools::time;
+use crate::error::{ensure, format_err, Error, Result};
+use crate::key::{DcKey, SignedPublicKey};
+use crate::param::Param;
+use crate::peerstate::{KeySource, Peerstate};
+use crate::socks::send_http;
+use crate::wkd;
+
+/// Timeout for a single keyserver request.
+const KEYSERVER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Keyservers that publish the user IDs of a key only after the address
+/// was confirmed by an email sent to it.
+pub const VERIFYING_KEYSERVERS: &[&str] =
+    &["https://keys.openpgp.org", "https://keys.mailvelope.com"];
+
+/// Returns true if keys found on `keyserver` may be imported.
+fn is_verifying_keyserver(keyserver: &str) -> bool {
+    VERIFYING_KEYSERVERS
+        .iter()
+        .any(|verifying| keyserver.eq_ignore_ascii_case(verifying))
+}
+
+/// Returns the configured keyserver without trailing slash.
+async fn keyserver_url(context: &Context) -> Result<String> {
+    let url = context
+        .get_config(Config::Keyserver)
+        .await
+        .unwrap_or_default();
+    let url = url.trim().trim_end_matches('/');
+    ensure!(
+        url.starts_with("https://"),
+        "Keyserver URL \"{}\" is not an HTTPS URL",
+        url
+    );
+    Ok(url.to_string())
+}
+
+/// Returns the HKP URL to look up the key of `addr` on `keyserver`.
+fn lookup_url(keyserver: &str, addr: &str) -> String {
+    format!(
+        "{}/pks/lookup?op=get&options=mr&search={}",
+        keyserver,
+        utf8_percent_encode(addr, NON_ALPHANUMERIC)
+    )
+}
+
+/// Queries the keyserver for the key of `addr`.
+///
+/// Returns the key and the URL it was found at or `None` if the keyserver
+/// has no valid key for the address.
+pub(crate) async fn lookup_key(
+    context: &Context,
+    addr: &str,
+) -> Result<Option<(SignedPublicKey, String)>> {
+    let url = lookup_url(&keyserver_url(context).await?, addr);
+    info!(context, "Keyserver: requesting {}", url);
+    let request = async {
+        let mut response = send_http(context, Request::new(Method::Get, Url::parse(&url)?))
+            .await
+            .map_err(|err| format_err!("Keyserver request failed: {}", err))?;
+        if u16::from(response.status()) == 404 {
+            return Ok(None);
+        }
+        ensure!(
+            response.status().is_success(),
+            "Keyserver request failed with status {}",
+            response.status()
+        );
+        response
+            .body_string()
+            .await
+            .map(Some)
+            .map_err(|err| format_err!("Cannot read keyserver response: {}", err))
+    };
+    let armored = match async_std::future::timeout(KEYSERVER_TIMEOUT, request)
+        .await
+        .map_err(|_| format_err!("Keyserver request timed out"))??
+    {
+        Some(armored) => armored,
+        None => return Ok(None),
+    };
+    match SignedPublicKey::from_asc(&armored)
+        .map_err(Error::from)
+        .and_then(|(key, _)| wkd::check_key(&key, addr).map(|()| key))
+    {
+        Ok(key) => Ok(Some((key, url))),
+        Err(err) => {
+            warn!(context, "Keyserver: ignoring key for {}: {}", addr, err);
+            Ok(None)
+        }
+    }
+}
+
+/// Looks up the keys of the recipients without a peerstate on the keyserver
+/// and stores found keys as gossip keys.
+///
+/// Does nothing if [Config::KeyserverLookup] is disabled.  The keyserver is
+/// queried at most once a week for each recipient.
+pub(crate) async fn import_missing_keys(context: &Context, recipients: &[String]) {
+    if !context.get_config_bool(Config::KeyserverLookup).await {
+        return;
+    }
+    match keyserver_url(context).await {
+        Ok(keyserver) if is_verifying_keyserver(&keyserver) => {}
+        Ok(keyserver) => {
+            info!(
+                context,
+                "Keyserver: not importing keys from {} as it does not verify addresses", keyserver
+            );
+            return;
+        }
+        Err(err) => {
+            warn!(context, "Keyserver: {}", err);
+            return;
+        }
+    }
+    let self_addr = context
+        .get_config(Config::ConfiguredAddr)
+        .await
+        .unwrap_or_default();
+    for addr in recipients {
+        if addr_cmp(addr, &self_addr) || Peerstate::from_addr(context, addr).await.is_some() {
+            continue;
+        }
+        if let Err(err) = import_key(context, addr).await {
+            warn!(
+                context,
+                "Keyserver: cannot import key for {}: {}", addr, err
+            );
+        }
+    }
+}
+
+async fn import_key(context: &Context, addr: &str) -> Result<()> {
+    if !wkd::start_lookup(context, addr, Param::KeyserverLookup).await? {
+        return Ok(());
+    }
+    if let Some((public_key, url)) = lookup_key(context, addr).await? {
+        info!(context, "Keyserver: importing key for {}", addr);
+        wkd::store_key(context, addr, public_key, KeySource::Keyserver, &url).await?;
+    }
+    Ok(())
+}
+
+/// Uploads the own default public key to the keyserver.
+///
+/// Calling this again refreshes the key on the keyserver, eg. after
+/// [crate::key::rotate_self_keypair].
+pub async fn publish_self_key(context: &Context) -> Result<()> {
+    let url = format!("{}/pks/add", keyserver_url(context).await?);
+    let keytext = SignedPublicKey::load_self(context).await?.to_asc(None);
+    info!(context, "Keyserver: publishing own key to {}", url);
+    let request = async {
+        let mut req = Request::new(Method::Post, Url::parse(&url)?);
+        req.set_body(
+            form_urlencoded::Serializer::new(String::new())
+                .append_pair("keytext", &keytext)
+                .finish(),
+        );
+        req.insert_header("Content-Type", "application/x-www-form-urlencoded");
+        let response = send_http(context, req)
+            .await
+            .map_err(|err| format_err!("Keyserver request failed: {}", err))?;
+        ensure!(
+            response.status().is_success(),
+            "Keyserver request failed with status {}",
+            response.status()
+        );
+        Ok(())
+    };
+    async_std::future::timeout(KEYSERVER_TIMEOUT, request)
+        .await
+        .map_err(|_| format_err!("Keyserver request timed out"))??;
+    context
+        .set_config(Config::KeyserverPublished, Some(&time().to_string()))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_lookup_url() {
+        assert_eq!(
+            lookup_url("https://keys.openpgp.org", "alice+foo@example.org"),
+            "https://keys.openpgp.org/pks/lookup?op=get&options=mr&search=alice%2Bfoo%40example%2Eorg"
+        );
+    }
+
+    #[async_std::test]
+    async fn test_keyserver_url() {
+        let t = test_context().await;
+        assert_eq!(
+            keyserver_url(&t.ctx).await.unwrap(),
+            "https://keys.openpgp.org"
+        );
+        t.ctx
+            .set_config(Config::Keyserver, Some("https://keys.example.org/ "))
+            .await
+            .unwrap();
+        assert_eq!(
+            keyserver_url(&t.ctx).await.unwrap(),
+            "https://keys.example.org"
+        );
+        t.ctx
+            .set_config(Config::Keyserver, Some("http://keys.example.org"))
+            .await
+            .unwrap();
+        assert!(keyserver_url(&t.ctx).await.is_err());
+    }
+
+    #[test]
+    fn test_is_verifying_keyserver() {
+        assert!(is_verifying_keyserver("https://keys.openpgp.org"));
+        assert!(is_verifying_keyserver("https://Keys.OpenPGP.org"));
+        assert!(!is_verifying_keyserver("https://keyserver.ubuntu.com"));
+        assert!(!is_verifying_keyserver(
+            "https://keys.openpgp.org.example.net"
+        ));
+    }
+
+    #[async_std::test]
+    async fn test_key_source() {
+        let t = test_context().await;
+        let alice = alice_keypair();
+        let addr = "alice@example.com";
+        let url = lookup_url("https://keys.openpgp.org", addr);
+        wkd::store_key(
+            &t.ctx,
+            addr,
+            alice.public.clone(),
+            KeySource::Keyserver,
+            &url,
+        )
+        .await
+        .unwrap();
+
+        let peerstate = Peerstate::from_addr(&t.ctx, addr).await.unwrap();
+        let record = peerstate.get_key_source().await.unwrap().unwrap();
+        assert_eq!(record.source, KeySource::Keyserver);
+        assert_eq!(record.fingerprint, alice.public.fingerprint());
+        assert_eq!(record.url, url);
+    }
+}
@@ -35,23 +35,36 @@ pub mod headerdef;
 pub(crate) mod events;
 pub use events::*;
 
+pub mod accounts;
 mod aheader;
+pub mod app;
 mod blob;
+mod blob_crypt;
+pub mod calendar;
 pub mod chat;
 pub mod chatlist;
 pub mod config;
 mod configure;
+pub mod connectivity;
 pub mod constants;
 pub mod contact;
 pub mod context;
+pub mod download;
 mod e2ee;
+pub mod ephemeral;
+pub mod folder_strategy;
+mod heif;
 mod imap;
 pub mod imex;
 mod scheduler;
 #[macro_use]
 pub mod job;
+#[cfg(feature = "jmap")]
+pub mod jmap;
+pub mod jsonrpc;
 pub mod key;
 mod keyring;
+pub mod keyserver;
 pub mod location;
 mod login_param;
 pub mod lot;
@@ -62,13 +75,22 @@ pub mod oauth2;
 mod param;
 pub mod peerstate;
 pub mod pgp;
+pub mod poll;
 pub mod provider;
+pub mod push;
 pub mod qr;
+pub mod reaction;
+pub mod search;
 pub mod securejoin;
 mod simplify;
+pub mod smime;
 mod smtp;
+mod socks;
 pub mod stock;
+mod sync;
 mod token;
+pub mod transport;
+mod wkd;
 #[macro_use]
 mod dehtml;
 
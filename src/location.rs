@@ -2,10 +2,12 @@
 
 use bitflags::bitflags;
 use quick_xml::events::{BytesEnd, BytesStart, BytesText};
+use serde::{Deserialize, Serialize};
 
 use crate::chat::{self, ChatId};
 use crate::config::Config;
 use crate::constants::*;
+use crate::contact::Contact;
 use crate::context::*;
 use crate::dc_tools::*;
 use crate::error::{ensure, Error};
@@ -29,6 +31,7 @@ pub struct Location {
     pub chat_id: ChatId,
     pub marker: Option<String>,
     pub independent: u32,
+    pub poi: Option<Poi>,
 }
 
 impl Location {
@@ -37,12 +40,27 @@ impl Location {
     }
 }
 
+/// A named point of interest bound to a message, see
+/// [crate::message::Message::set_poi].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Poi {
+    /// Label shown at the marker.
+    pub label: String,
+
+    /// Radius of the area around the point in meters.
+    pub radius: Option<f64>,
+
+    /// Marker color as 0xRRGGBB.
+    pub color: Option<u32>,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Kml {
     pub addr: Option<String>,
     pub locations: Vec<Location>,
     tag: KmlTag,
     pub curr: Location,
+    data_name: String,
 }
 
 bitflags! {
@@ -54,6 +72,9 @@ bitflags! {
         const WHEN = 0x04;
         const POINT = 0x08;
         const COORDINATES = 0x10;
+        const NAME = 0x20;
+        const DATA = 0x40;
+        const VALUE = 0x80;
     }
 }
 
@@ -96,7 +117,30 @@ impl Kml {
     }
 
     fn text_cb<B: std::io::BufRead>(&mut self, event: &BytesText, reader: &quick_xml::Reader<B>) {
-        if self.tag.contains(KmlTag::WHEN) || self.tag.contains(KmlTag::COORDINATES) {
+        if self.tag.contains(KmlTag::NAME) {
+            let label = event.unescape_and_decode(reader).unwrap_or_default();
+            self.curr.poi.get_or_insert_with(Poi::default).label = label.trim().to_string();
+        } else if self.tag.contains(KmlTag::VALUE) {
+            let val = event.unescape_and_decode(reader).unwrap_or_default();
+            let val = val.trim();
+            match self.data_name.as_str() {
+                "radius" => {
+                    if let Ok(radius) = val.parse::<f64>() {
+                        if radius > 0. {
+                            self.curr.poi.get_or_insert_with(Poi::default).radius = Some(radius);
+                        }
+                    }
+                }
+                "color" => {
+                    let hex = if val.starts_with('#') { &val[1..] } else { val };
+                    if let Ok(color) = u32::from_str_radix(hex, 16) {
+                        self.curr.poi.get_or_insert_with(Poi::default).color =
+                            Some(color & 0xff_ff_ff);
+                    }
+                }
+                _ => {}
+            }
+        } else if self.tag.contains(KmlTag::WHEN) || self.tag.contains(KmlTag::COORDINATES) {
             let val = event.unescape_and_decode(reader).unwrap_or_default();
 
             let val = val
@@ -138,11 +182,26 @@ impl Kml {
                 && 0. != self.curr.latitude
                 && 0. != self.curr.longitude
             {
+                if self
+                    .curr
+                    .poi
+                    .as_ref()
+                    .map_or(false, |poi| poi.label.is_empty())
+                {
+                    // radius and color are meaningless without a label
+                    self.curr.poi = None;
+                }
                 self.locations
                     .push(std::mem::replace(&mut self.curr, Location::new()));
             }
             self.tag = KmlTag::UNDEFINED;
-        };
+        } else if tag == "name" {
+            self.tag.remove(KmlTag::NAME);
+        } else if tag == "data" {
+            self.tag.remove(KmlTag::DATA | KmlTag::VALUE);
+        } else if tag == "value" {
+            self.tag.remove(KmlTag::VALUE);
+        }
     }
 
     fn starttag_cb<B: std::io::BufRead>(
@@ -164,7 +223,22 @@ impl Kml {
             self.curr.timestamp = 0;
             self.curr.latitude = 0.0;
             self.curr.longitude = 0.0;
-            self.curr.accuracy = 0.0
+            self.curr.accuracy = 0.0;
+            self.curr.poi = None;
+        } else if tag == "name" && self.tag.contains(KmlTag::PLACEMARK) {
+            self.tag = KmlTag::PLACEMARK | KmlTag::NAME
+        } else if tag == "data" && self.tag.contains(KmlTag::PLACEMARK) {
+            self.tag = KmlTag::PLACEMARK | KmlTag::DATA;
+            self.data_name = event
+                .attributes()
+                .filter_map(|attr| attr.ok())
+                .find(|attr| String::from_utf8_lossy(attr.key).trim().to_lowercase() == "name")
+                .and_then(|attr| attr.unescape_and_decode_value(reader).ok())
+                .unwrap_or_default()
+                .trim()
+                .to_lowercase();
+        } else if tag == "value" && self.tag.contains(KmlTag::DATA) {
+            self.tag = KmlTag::PLACEMARK | KmlTag::DATA | KmlTag::VALUE
         } else if tag == "timestamp" && self.tag.contains(KmlTag::PLACEMARK) {
             self.tag = KmlTag::PLACEMARK | KmlTag::TIMESTAMP
         } else if tag == "when" && self.tag.contains(KmlTag::TIMESTAMP) {
@@ -323,7 +397,8 @@ pub async fn get_range(
         .sql
         .query_map(
             "SELECT l.id, l.latitude, l.longitude, l.accuracy, l.timestamp, l.independent, \
-             COALESCE(m.id, 0) AS msg_id, l.from_id, l.chat_id, COALESCE(m.txt, '') AS txt \
+             COALESCE(m.id, 0) AS msg_id, l.from_id, l.chat_id, COALESCE(m.txt, '') AS txt, \
+             l.label, l.radius, l.color \
              FROM locations l  LEFT JOIN msgs m ON l.id=m.location_id  WHERE (? OR l.chat_id=?) \
              AND (? OR l.from_id=?) \
              AND (l.independent=1 OR (l.timestamp>=? AND l.timestamp<=?)) \
@@ -336,28 +411,7 @@ pub async fn get_range(
                 timestamp_from,
                 timestamp_to,
             ],
-            |row| {
-                let msg_id = row.get(6)?;
-                let txt: String = row.get(9)?;
-                let marker = if msg_id != 0 && is_marker(&txt) {
-                    Some(txt)
-                } else {
-                    None
-                };
-                let loc = Location {
-                    location_id: row.get(0)?,
-                    latitude: row.get(1)?,
-                    longitude: row.get(2)?,
-                    accuracy: row.get(3)?,
-                    timestamp: row.get(4)?,
-                    independent: row.get(5)?,
-                    msg_id,
-                    contact_id: row.get(7)?,
-                    chat_id: row.get(8)?,
-                    marker,
-                };
-                Ok(loc)
-            },
+            location_from_row,
             |locations| {
                 let mut ret = Vec::new();
 
@@ -371,6 +425,380 @@ pub async fn get_range(
         .unwrap_or_default()
 }
 
+/// Reads a location selected by the columns used in [get_range].
+fn location_from_row(row: &rusqlite::Row) -> rusqlite::Result<Location> {
+    let msg_id = row.get(6)?;
+    let txt: String = row.get(9)?;
+    let marker = if msg_id != 0 && is_marker(&txt) {
+        Some(txt)
+    } else {
+        None
+    };
+    Ok(Location {
+        location_id: row.get(0)?,
+        latitude: row.get(1)?,
+        longitude: row.get(2)?,
+        accuracy: row.get(3)?,
+        timestamp: row.get(4)?,
+        independent: row.get(5)?,
+        msg_id,
+        contact_id: row.get(7)?,
+        chat_id: row.get(8)?,
+        marker,
+        poi: poi_from_row(row.get(10)?, row.get(11)?, row.get(12)?),
+    })
+}
+
+/// A rectangular area on the map, eg. the viewport of a map frontend.
+///
+/// If `west` is greater than `east`, the box crosses the antimeridian.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub south: f64,
+    pub west: f64,
+    pub north: f64,
+    pub east: f64,
+}
+
+impl BoundingBox {
+    /// Compiles the box to SQL conditions on the r-tree `r` and the locations `l`,
+    /// the r-tree narrows down the candidates, the exact coordinates are checked
+    /// as the r-tree stores rounded values.
+    fn sql_condition(&self) -> (String, Vec<f64>) {
+        let lat = "r.max_lat>=? AND r.min_lat<=? AND l.latitude>=? AND l.latitude<=?";
+        let mut params = vec![self.south, self.north, self.south, self.north];
+        let lon = if self.west <= self.east {
+            "r.max_lon>=? AND r.min_lon<=? AND l.longitude>=? AND l.longitude<=?"
+        } else {
+            "(r.max_lon>=? OR r.min_lon<=?) AND (l.longitude>=? OR l.longitude<=?)"
+        };
+        params.extend(&[self.west, self.east, self.west, self.east]);
+        (format!("{} AND {}", lat, lon), params)
+    }
+}
+
+/// Returns the locations inside `bbox`, otherwise as [get_range] does.
+pub async fn get_range_in_box(
+    context: &Context,
+    chat_id: ChatId,
+    contact_id: u32,
+    bbox: &BoundingBox,
+    timestamp_from: i64,
+    mut timestamp_to: i64,
+) -> Result<Vec<Location>, Error> {
+    if timestamp_to == 0 {
+        timestamp_to = time() + 10;
+    }
+    let (bbox_condition, bbox_params) = bbox.sql_condition();
+    let mut params: Vec<&dyn crate::ToSql> = bbox_params
+        .iter()
+        .map(|param| param as &dyn crate::ToSql)
+        .collect();
+    let all_chats = chat_id.is_unset();
+    let all_contacts = contact_id == 0;
+    let contact_id = contact_id as i32;
+    params.extend(paramsv![
+        all_chats,
+        chat_id,
+        all_contacts,
+        contact_id,
+        timestamp_from,
+        timestamp_to,
+    ]);
+    let locations = context
+        .sql
+        .query_map(
+            format!(
+                "SELECT l.id, l.latitude, l.longitude, l.accuracy, l.timestamp, l.independent,
+                        COALESCE(m.id, 0) AS msg_id, l.from_id, l.chat_id, COALESCE(m.txt, '') AS txt,
+                        l.label, l.radius, l.color
+                   FROM locations_rtree r
+                  INNER JOIN locations l ON l.id=r.id
+                   LEFT JOIN msgs m ON l.id=m.location_id
+                  WHERE {}
+                    AND (? OR l.chat_id=?)
+                    AND (? OR l.from_id=?)
+                    AND (l.independent=1 OR (l.timestamp>=? AND l.timestamp<=?))
+                  ORDER BY l.timestamp DESC, l.id DESC, msg_id DESC;",
+                bbox_condition
+            ),
+            params,
+            location_from_row,
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    Ok(locations)
+}
+
+/// A cluster of locations, see [get_clusters].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Cluster {
+    /// Center of the clustered locations.
+    pub latitude: f64,
+    pub longitude: f64,
+
+    /// Number of clustered locations.
+    pub count: u32,
+
+    /// Id of the location if the cluster contains a single location, 0 otherwise.
+    pub location_id: u32,
+
+    /// Timestamp of the newest clustered location.
+    pub timestamp: i64,
+}
+
+/// Highest zoom level supported by [get_clusters].
+pub const MAX_CLUSTER_ZOOM: u32 = 20;
+
+/// Returns the locations inside `bbox` clustered for the given zoom level.
+///
+/// The zoom level is the one used by web map tiles, at zoom level 0 the
+/// whole world is one 256 pixel tile.  Locations are clustered on a grid of
+/// a quarter tile, so that clusters are about 64 pixels apart on the screen.
+/// The locations are selected as by [get_range_in_box], the clusters are
+/// returned with the biggest cluster first.
+pub async fn get_clusters(
+    context: &Context,
+    chat_id: ChatId,
+    bbox: &BoundingBox,
+    zoom: u32,
+    timestamp_from: i64,
+    mut timestamp_to: i64,
+) -> Result<Vec<Cluster>, Error> {
+    if timestamp_to == 0 {
+        timestamp_to = time() + 10;
+    }
+    let cell = 360.0 / f64::from(1u32 << zoom.min(MAX_CLUSTER_ZOOM)) / 4.0;
+    let (bbox_condition, bbox_params) = bbox.sql_condition();
+    let mut params: Vec<&dyn crate::ToSql> = bbox_params
+        .iter()
+        .map(|param| param as &dyn crate::ToSql)
+        .collect();
+    let all_chats = chat_id.is_unset();
+    params.extend(paramsv![
+        all_chats,
+        chat_id,
+        timestamp_from,
+        timestamp_to,
+        cell,
+        cell
+    ]);
+    let clusters = context
+        .sql
+        .query_map(
+            format!(
+                "SELECT AVG(l.latitude), AVG(l.longitude), COUNT(*), MIN(l.id), MAX(l.timestamp)
+                   FROM locations_rtree r
+                  INNER JOIN locations l ON l.id=r.id
+                  WHERE {}
+                    AND (? OR l.chat_id=?)
+                    AND (l.independent=1 OR (l.timestamp>=? AND l.timestamp<=?))
+                  GROUP BY CAST((l.latitude+90)/? AS INTEGER),
+                           CAST((l.longitude+180)/? AS INTEGER)
+                  ORDER BY COUNT(*) DESC, MAX(l.timestamp) DESC;",
+                bbox_condition
+            ),
+            params,
+            |row| {
+                let count: u32 = row.get(2)?;
+                let location_id: u32 = row.get(3)?;
+                Ok(Cluster {
+                    latitude: row.get(0)?,
+                    longitude: row.get(1)?,
+                    count,
+                    location_id: if count == 1 { location_id } else { 0 },
+                    timestamp: row.get(4)?,
+                })
+            },
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    Ok(clusters)
+}
+
+/// A point of the path of a contact, see [get_path].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PathPoint {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub accuracy: f64,
+    pub timestamp: i64,
+
+    /// The message the location is bound to, 0 if there is none.
+    pub msg_id: u32,
+
+    /// Speed since the previous point in meters per second,
+    /// `None` for the first point.
+    pub speed: Option<f64>,
+}
+
+/// Mean earth radius in meters.
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// Returns the great-circle distance between two points in meters.
+fn distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (lon2 - lon1).to_radians();
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+/// Returns the path of a contact, oldest point first.
+///
+/// Other than [get_range], only positions are returned, no locations
+/// marked by the user, and positions stored for several chats are
+/// returned once.  `chat_id` may be unset to get the path independently
+/// of the chat, `timestamp_to` may be 0 for "up to now".
+pub async fn get_path(
+    context: &Context,
+    chat_id: ChatId,
+    contact_id: u32,
+    timestamp_from: i64,
+    timestamp_to: i64,
+) -> Result<Vec<PathPoint>, Error> {
+    ensure!(contact_id != 0, "No contact given");
+    let timestamp_to = if timestamp_to == 0 {
+        time() + 10
+    } else {
+        timestamp_to
+    };
+    let mut path: Vec<PathPoint> = context
+        .sql
+        .query_map(
+            "SELECT l.latitude, l.longitude, l.accuracy, l.timestamp, COALESCE(MAX(m.id), 0)
+               FROM locations l
+               LEFT JOIN msgs m ON l.id=m.location_id
+              WHERE (? OR l.chat_id=?)
+                AND l.from_id=?
+                AND l.independent=0
+                AND l.timestamp>=? AND l.timestamp<=?
+              GROUP BY l.timestamp
+              ORDER BY l.timestamp;",
+            paramsv![
+                chat_id.is_unset(),
+                chat_id,
+                contact_id as i32,
+                timestamp_from,
+                timestamp_to
+            ],
+            |row| {
+                Ok(PathPoint {
+                    latitude: row.get(0)?,
+                    longitude: row.get(1)?,
+                    accuracy: row.get(2)?,
+                    timestamp: row.get(3)?,
+                    msg_id: row.get(4)?,
+                    speed: None,
+                })
+            },
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    for i in 1..path.len() {
+        let (prev, point) = (&path[i - 1], &path[i]);
+        let seconds = point.timestamp - prev.timestamp;
+        if seconds > 0 {
+            let meters = distance(
+                prev.latitude,
+                prev.longitude,
+                point.latitude,
+                point.longitude,
+            );
+            path[i].speed = Some(meters / seconds as f64);
+        }
+    }
+    Ok(path)
+}
+
+/// Returns the last known position of every member of a chat sharing locations.
+///
+/// One location per contact is returned, the newest first.
+pub async fn get_current_positions(
+    context: &Context,
+    chat_id: ChatId,
+) -> Result<Vec<Location>, Error> {
+    let positions = context
+        .sql
+        .query_map(
+            "SELECT l.id, l.latitude, l.longitude, l.accuracy, l.timestamp, l.from_id,
+                    COALESCE(m.id, 0)
+               FROM locations l
+               LEFT JOIN msgs m ON l.id=m.location_id
+              WHERE l.chat_id=?1
+                AND l.independent=0
+                AND l.timestamp=(SELECT MAX(timestamp) FROM locations
+                                  WHERE chat_id=?1 AND from_id=l.from_id AND independent=0)
+              GROUP BY l.from_id
+              ORDER BY l.timestamp DESC;",
+            paramsv![chat_id],
+            |row| {
+                Ok(Location {
+                    location_id: row.get(0)?,
+                    latitude: row.get(1)?,
+                    longitude: row.get(2)?,
+                    accuracy: row.get(3)?,
+                    timestamp: row.get(4)?,
+                    contact_id: row.get(5)?,
+                    msg_id: row.get(6)?,
+                    chat_id,
+                    marker: None,
+                    independent: 0,
+                    poi: None,
+                })
+            },
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    Ok(positions)
+}
+
+fn poi_from_row(label: Option<String>, radius: Option<f64>, color: Option<u32>) -> Option<Poi> {
+    label.map(|label| Poi {
+        label,
+        radius,
+        color,
+    })
+}
+
+/// Returns the named points of interest sent to a chat, newest first.
+///
+/// Other than [get_range], live locations and unnamed locations bound to
+/// messages are not returned, every returned location has `poi` set.
+pub async fn get_pois(context: &Context, chat_id: ChatId) -> Result<Vec<Location>, Error> {
+    let pois = context
+        .sql
+        .query_map(
+            "SELECT l.id, l.latitude, l.longitude, l.accuracy, l.timestamp, l.from_id,
+                    COALESCE(m.id, 0), l.label, l.radius, l.color
+               FROM locations l
+               LEFT JOIN msgs m ON l.id=m.location_id
+              WHERE l.chat_id=?
+                AND l.independent=1
+                AND l.label IS NOT NULL
+              ORDER BY l.timestamp DESC, l.id DESC;",
+            paramsv![chat_id],
+            |row| {
+                Ok(Location {
+                    location_id: row.get(0)?,
+                    latitude: row.get(1)?,
+                    longitude: row.get(2)?,
+                    accuracy: row.get(3)?,
+                    timestamp: row.get(4)?,
+                    contact_id: row.get(5)?,
+                    msg_id: row.get(6)?,
+                    chat_id,
+                    marker: None,
+                    independent: 1,
+                    poi: poi_from_row(row.get(7)?, row.get(8)?, row.get(9)?),
+                })
+            },
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    Ok(pois)
+}
+
 fn is_marker(txt: &str) -> bool {
     txt.len() == 1 && !txt.starts_with(' ')
 }
@@ -462,20 +890,172 @@ fn get_kml_timestamp(utc: i64) -> String {
         .to_string()
 }
 
-pub fn get_message_kml(timestamp: i64, latitude: f64, longitude: f64) -> String {
+pub fn get_message_kml(timestamp: i64, latitude: f64, longitude: f64, poi: Option<&Poi>) -> String {
+    let (name, data) = match poi {
+        Some(poi) => {
+            let mut data = String::new();
+            if let Some(radius) = poi.radius {
+                data += &format!("<Data name=\"radius\"><value>{}</value></Data>", radius);
+            }
+            if let Some(color) = poi.color {
+                data += &format!("<Data name=\"color\"><value>#{:06x}</value></Data>", color);
+            }
+            if !data.is_empty() {
+                data = format!("<ExtendedData>{}</ExtendedData>", data);
+            }
+            (format!("<name>{}</name>", escape_xml(&poi.label)), data)
+        }
+        None => (String::new(), String::new()),
+    };
     format!(
         "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
          <kml xmlns=\"http://www.opengis.net/kml/2.2\">\n\
          <Document>\n\
-         <Placemark>\
+         <Placemark>{}\
          <Timestamp><when>{}</when></Timestamp>\
-         <Point><coordinates>{:.2},{:.2}</coordinates></Point>\
+         <Point><coordinates>{:.2},{:.2}</coordinates></Point>{}\
          </Placemark>\n\
          </Document>\n\
          </kml>",
+        name,
         get_kml_timestamp(timestamp),
         longitude,
         latitude,
+        data,
+    )
+}
+
+/// File formats for [export].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(i32)]
+pub enum ExportFormat {
+    /// GPS Exchange Format 1.1
+    Gpx = 1,
+
+    /// Keyhole Markup Language 2.2
+    Kml = 2,
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Exports the location history as GPX or KML.
+///
+/// The locations are selected as by [get_range], `chat_id` and
+/// `contact_id` may be unset to export the locations of all chats or
+/// all contacts, `range` is the timespan `(timestamp_from, timestamp_to)`
+/// where 0 as end means "up to now".  Positions are exported as one track
+/// per contact, locations marked by the user as waypoints.  Every point
+/// carries its timestamp, its accuracy and the id of the linked message, if any.
+pub async fn export(
+    context: &Context,
+    chat_id: ChatId,
+    contact_id: u32,
+    range: (i64, i64),
+    format: ExportFormat,
+) -> Result<String, Error> {
+    let mut locations = get_range(context, chat_id, contact_id, range.0, range.1).await;
+    locations.reverse();
+
+    let mut addrs = std::collections::BTreeMap::new();
+    for location in &locations {
+        if !addrs.contains_key(&location.contact_id) {
+            let addr = Contact::load_from_db(context, location.contact_id)
+                .await
+                .map(|contact| contact.get_addr().to_string())
+                .unwrap_or_default();
+            addrs.insert(location.contact_id, addr);
+        }
+    }
+    let (waypoints, positions): (Vec<&Location>, Vec<&Location>) = locations
+        .iter()
+        .partition(|location| location.independent != 0);
+
+    let mut ret = String::new();
+    match format {
+        ExportFormat::Gpx => {
+            ret += "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                    <gpx version=\"1.1\" creator=\"Delta Chat\" \
+                    xmlns=\"http://www.topografix.com/GPX/1/1\" \
+                    xmlns:dc=\"https://delta.chat/gpx/1\">\n";
+            for location in &waypoints {
+                ret += &format!(
+                    "<wpt lat=\"{}\" lon=\"{}\"><time>{}</time>{}<extensions>{}</extensions></wpt>\n",
+                    location.latitude,
+                    location.longitude,
+                    get_kml_timestamp(location.timestamp),
+                    location
+                        .marker
+                        .as_ref()
+                        .map(|marker| format!("<sym>{}</sym>", escape_xml(marker)))
+                        .unwrap_or_default(),
+                    gpx_extensions(location)
+                );
+            }
+            for (contact_id, addr) in &addrs {
+                let mut points = positions
+                    .iter()
+                    .filter(|location| location.contact_id == *contact_id)
+                    .peekable();
+                if points.peek().is_none() {
+                    continue;
+                }
+                ret += &format!("<trk><name>{}</name><trkseg>\n", escape_xml(addr));
+                for location in points {
+                    ret += &format!(
+                        "<trkpt lat=\"{}\" lon=\"{}\"><time>{}</time><extensions>{}</extensions></trkpt>\n",
+                        location.latitude,
+                        location.longitude,
+                        get_kml_timestamp(location.timestamp),
+                        gpx_extensions(location)
+                    );
+                }
+                ret += "</trkseg></trk>\n";
+            }
+            ret += "</gpx>";
+        }
+        ExportFormat::Kml => {
+            ret += "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                    <kml xmlns=\"http://www.opengis.net/kml/2.2\">\n<Document>\n";
+            for location in waypoints.iter().chain(positions.iter()) {
+                ret += &format!(
+                    "<Placemark>{}<TimeStamp><when>{}</when></TimeStamp>\
+                     <ExtendedData>\
+                     <Data name=\"addr\"><value>{}</value></Data>\
+                     <Data name=\"accuracy\"><value>{}</value></Data>\
+                     <Data name=\"msg_id\"><value>{}</value></Data>\
+                     <Data name=\"independent\"><value>{}</value></Data>\
+                     </ExtendedData>\
+                     <Point><coordinates>{},{}</coordinates></Point></Placemark>\n",
+                    location
+                        .marker
+                        .as_ref()
+                        .map(|marker| format!("<name>{}</name>", escape_xml(marker)))
+                        .unwrap_or_default(),
+                    get_kml_timestamp(location.timestamp),
+                    escape_xml(addrs.get(&location.contact_id).map_or("", |addr| addr)),
+                    location.accuracy,
+                    location.msg_id,
+                    location.independent,
+                    location.longitude,
+                    location.latitude
+                );
+            }
+            ret += "</Document>\n</kml>";
+        }
+    }
+    Ok(ret)
+}
+
+fn gpx_extensions(location: &Location) -> String {
+    format!(
+        "<dc:accuracy>{}</dc:accuracy><dc:msg_id>{}</dc:msg_id>",
+        location.accuracy, location.msg_id
     )
 }
 
@@ -530,6 +1110,10 @@ pub async fn save(
             accuracy,
             ..
         } = location;
+        let (label, radius, color) = match &location.poi {
+            Some(poi) => (Some(poi.label.clone()), poi.radius, poi.color),
+            None => (None, None, None),
+        };
         context
             .sql
             .with_conn(move |mut conn| {
@@ -537,8 +1121,9 @@ pub async fn save(
                     .prepare_cached("SELECT id FROM locations WHERE timestamp=? AND from_id=?")?;
                 let mut stmt_insert = conn.prepare_cached(
                     "INSERT INTO locations\
-             (timestamp, from_id, chat_id, latitude, longitude, accuracy, independent) \
-             VALUES (?,?,?,?,?,?,?);",
+             (timestamp, from_id, chat_id, latitude, longitude, accuracy, independent, \
+              label, radius, color) \
+             VALUES (?,?,?,?,?,?,?,?,?,?);",
                 )?;
 
                 let exists = stmt_test.exists(paramsv![timestamp, contact_id as i32])?;
@@ -552,6 +1137,9 @@ pub async fn save(
                         longitude,
                         accuracy,
                         independent,
+                        label,
+                        radius,
+                        color,
                     ])?;
 
                     if timestamp > newest_timestamp {
@@ -755,4 +1343,203 @@ mod tests {
         assert!(locations_ref[1].accuracy < 2.6f64);
         assert_eq!(locations_ref[1].timestamp, 1544739072);
     }
+
+    #[async_std::test]
+    async fn test_export() {
+        let t = dummy_context().await;
+        t.ctx
+            .set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+        let chat_id = ChatId::new(10);
+        for (latitude, timestamp, independent) in &[(1.5, 1000, 0), (2.5, 2000, 0), (3.5, 3000, 1)]
+        {
+            t.ctx
+                .sql
+                .execute(
+                    "INSERT INTO locations (latitude, longitude, accuracy, timestamp, chat_id, from_id, independent)
+                     VALUES (?, 0.5, 10, ?, ?, ?, ?);",
+                    paramsv![*latitude, *timestamp as i64, chat_id, DC_CONTACT_ID_SELF as i32, *independent],
+                )
+                .await
+                .unwrap();
+        }
+
+        let gpx = export(&t.ctx, chat_id, 0, (0, 2500), ExportFormat::Gpx)
+            .await
+            .unwrap();
+        assert!(gpx.starts_with("<?xml"));
+        assert!(gpx.contains("<trk><name>self@example.com</name><trkseg>\n<trkpt lat=\"1.5\" lon=\"0.5\"><time>1970-01-01T00:16:40Z</time>"));
+        assert!(gpx.find("lat=\"1.5\"").unwrap() < gpx.find("lat=\"2.5\"").unwrap());
+        // independent locations are exported regardless of the timespan
+        assert!(gpx.contains("<wpt lat=\"3.5\""));
+        assert!(gpx.contains("<dc:accuracy>10</dc:accuracy><dc:msg_id>0</dc:msg_id>"));
+        assert!(gpx.ends_with("</gpx>"));
+
+        let kml = export(&t.ctx, chat_id, 0, (1500, 0), ExportFormat::Kml)
+            .await
+            .unwrap();
+        assert!(!kml.contains("<coordinates>0.5,1.5</coordinates>"));
+        assert!(kml.contains("<coordinates>0.5,2.5</coordinates>"));
+        assert!(kml.contains("<Data name=\"addr\"><value>self@example.com</value></Data>"));
+        assert!(kml.ends_with("</Document>\n</kml>"));
+
+        assert_eq!(escape_xml("<a & 'b'>"), "&lt;a &amp; &apos;b&apos;&gt;");
+    }
+
+    #[async_std::test]
+    async fn test_get_path() {
+        let t = dummy_context().await;
+        let chat_id = ChatId::new(10);
+        // one degree of latitude is about 111km
+        for (latitude, timestamp, from_id, chat_id) in &[
+            (50.0, 1000, 10, 10),
+            (50.01, 1100, 10, 10),
+            (50.01, 1100, 10, 11),
+            (50.02, 1300, 10, 10),
+            (40.0, 1200, 11, 10),
+        ] {
+            t.ctx
+                .sql
+                .execute(
+                    "INSERT INTO locations (latitude, longitude, accuracy, timestamp, chat_id, from_id)
+                     VALUES (?, 8.0, 5, ?, ?, ?);",
+                    paramsv![*latitude, *timestamp as i64, *chat_id, *from_id],
+                )
+                .await
+                .unwrap();
+        }
+
+        let path = get_path(&t.ctx, ChatId::new(0), 10, 0, 0).await.unwrap();
+        assert_eq!(path.len(), 3);
+        assert_eq!(path[0].timestamp, 1000);
+        assert_eq!(path[0].speed, None);
+        let speed = path[1].speed.unwrap();
+        assert!(speed > 11.0 && speed < 11.3, "speed {}", speed);
+        let speed = path[2].speed.unwrap();
+        assert!(speed > 5.5 && speed < 5.7, "speed {}", speed);
+
+        let path = get_path(&t.ctx, chat_id, 10, 1050, 1200).await.unwrap();
+        assert_eq!(path.len(), 1);
+        assert_eq!(path[0].latitude, 50.01);
+        assert!(get_path(&t.ctx, chat_id, 0, 0, 0).await.is_err());
+
+        let positions = get_current_positions(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(positions.len(), 2);
+        assert_eq!(positions[0].contact_id, 10);
+        assert_eq!(positions[0].latitude, 50.02);
+        assert_eq!(positions[1].contact_id, 11);
+    }
+
+    #[async_std::test]
+    async fn test_pois() {
+        let t = dummy_context().await;
+        let poi = Poi {
+            label: "Meeting <point>".to_string(),
+            radius: Some(50.0),
+            color: Some(0x00ff_8000),
+        };
+        let kml = get_message_kml(1_500_000_000, 53.55, 9.99, Some(&poi));
+        let parsed = Kml::parse(&t.ctx, kml.as_bytes()).unwrap();
+        assert_eq!(parsed.locations.len(), 1);
+        assert_eq!(parsed.locations[0].poi.as_ref(), Some(&poi));
+
+        let kml = get_message_kml(1_500_000_000, 53.55, 9.99, None);
+        let parsed = Kml::parse(&t.ctx, kml.as_bytes()).unwrap();
+        assert_eq!(parsed.locations.len(), 1);
+        assert_eq!(parsed.locations[0].poi, None);
+
+        let chat_id = ChatId::new(10);
+        let mut locations = parsed.locations;
+        save(&t.ctx, chat_id, 10, &locations, true).await.unwrap();
+        locations[0].poi = Some(poi.clone());
+        save(&t.ctx, chat_id, 10, &locations, true).await.unwrap();
+        locations[0].timestamp += 10;
+        save(&t.ctx, chat_id, 10, &locations, false).await.unwrap();
+
+        let pois = get_pois(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(pois.len(), 1);
+        assert_eq!(pois[0].contact_id, 10);
+        assert_eq!(pois[0].independent, 1);
+        assert_eq!(pois[0].poi, Some(poi));
+        assert!(get_pois(&t.ctx, ChatId::new(11)).await.unwrap().is_empty());
+        assert_eq!(get_range(&t.ctx, chat_id, 0, 0, 0).await.len(), 3);
+    }
+
+    #[async_std::test]
+    async fn test_get_clusters() {
+        let t = dummy_context().await;
+        let chat_id = ChatId::new(10);
+        for (latitude, longitude, timestamp) in &[
+            (53.55, 9.99, 1000),
+            (53.56, 10.0, 1100),
+            (52.52, 13.4, 1200),
+            (40.0, 179.9, 1300),
+            (-33.9, 151.2, 1400),
+        ] {
+            t.ctx
+                .sql
+                .execute(
+                    "INSERT INTO locations (latitude, longitude, timestamp, chat_id, from_id)
+                     VALUES (?, ?, ?, ?, 10);",
+                    paramsv![*latitude, *longitude, *timestamp as i64, chat_id],
+                )
+                .await
+                .unwrap();
+        }
+        let germany = BoundingBox {
+            south: 47.0,
+            west: 5.0,
+            north: 55.0,
+            east: 15.0,
+        };
+
+        let locations = get_range_in_box(&t.ctx, chat_id, 0, &germany, 0, 0)
+            .await
+            .unwrap();
+        assert_eq!(locations.len(), 3);
+        assert_eq!(locations[0].latitude, 52.52);
+        let berlin = locations[0].location_id;
+        let locations = get_range_in_box(&t.ctx, chat_id, 0, &germany, 1050, 1150)
+            .await
+            .unwrap();
+        assert_eq!(locations.len(), 1);
+
+        // at zoom level 8 the grid cells are about 0.35 degrees wide
+        let clusters = get_clusters(&t.ctx, chat_id, &germany, 8, 0, 0)
+            .await
+            .unwrap();
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].count, 2);
+        assert_eq!(clusters[0].location_id, 0);
+        assert_eq!(clusters[0].timestamp, 1100);
+        assert!((clusters[0].latitude - 53.555).abs() < 0.001);
+        assert_eq!(clusters[1].count, 1);
+        assert_eq!(clusters[1].location_id, berlin);
+
+        let clusters = get_clusters(&t.ctx, chat_id, &germany, 2, 0, 0)
+            .await
+            .unwrap();
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].count, 3);
+
+        // the box crosses the antimeridian
+        let pacific = BoundingBox {
+            south: -60.0,
+            west: 150.0,
+            north: 60.0,
+            east: -150.0,
+        };
+        let clusters = get_clusters(&t.ctx, ChatId::new(0), &pacific, 0, 0, 0)
+            .await
+            .unwrap();
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].timestamp, 1400);
+
+        delete_all(&t.ctx).await.unwrap();
+        assert!(get_clusters(&t.ctx, chat_id, &germany, 2, 0, 0)
+            .await
+            .unwrap()
+            .is_empty());
+    }
 }
@@ -91,6 +91,9 @@ pub enum LotState {
     /// text1=domain
     QrAccount = 250,
 
+    /// text1=address of the device providing the account
+    QrSecondDevice = 260,
+
     /// id=contact
     QrAddr = 320,
 
@@ -103,6 +106,9 @@ pub enum LotState {
     /// text1=error string
     QrError = 400,
 
+    /// text1=email address
+    QrLogin = 520,
+
     // Message States
     MsgInFresh = 10,
     MsgInNoticed = 13,
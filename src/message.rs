@@ -1,23 +1,36 @@
 //! # Messages and their identifiers
 
+use std::collections::BTreeMap;
+
 use async_std::path::{Path, PathBuf};
 use deltachat_derive::{FromSql, ToSql};
 use lazy_static::lazy_static;
+use num_traits::FromPrimitive;
 use serde::{Deserialize, Serialize};
 
+use crate::app::AppManifest;
+use crate::blob_crypt;
+use crate::calendar::{CalendarEvent, Rsvp};
 use crate::chat::{self, Chat, ChatId};
 use crate::constants::*;
 use crate::contact::*;
 use crate::context::*;
 use crate::dc_tools::*;
-use crate::error::{ensure, Error};
+use crate::download::DownloadState;
+use crate::ephemeral;
+use crate::error::{ensure, format_err, Error};
 use crate::events::Event;
 use crate::job::{self, Action};
+use crate::location;
 use crate::lot::{Lot, LotState, Meaning};
+use crate::mimefactory;
 use crate::mimeparser::SystemMessage;
 use crate::param::*;
 use crate::pgp::*;
+use crate::poll::PollState;
+use crate::smime::SmimeState;
 use crate::stock::StockMessage;
+use crate::sync;
 
 lazy_static! {
     static ref UNWRAP_RE: regex::Regex = regex::Regex::new(r"\s+").unwrap();
@@ -91,7 +104,7 @@ impl MsgId {
         context
             .sql
             .execute(
-                "UPDATE msgs SET chat_id=?, txt='', txt_raw='' WHERE id=?",
+                "UPDATE msgs SET chat_id=?, txt='', txt_raw='', mime_html='' WHERE id=?",
                 paramsv![chat_id, self],
             )
             .await?;
@@ -107,6 +120,13 @@ impl MsgId {
             .sql
             .execute("DELETE FROM msgs_mdns WHERE msg_id=?;", paramsv![self])
             .await?;
+        context
+            .sql
+            .execute(
+                "DELETE FROM msg_recipient_status WHERE msg_id=?;",
+                paramsv![self],
+            )
+            .await?;
         context
             .sql
             .execute("DELETE FROM msgs WHERE id=?;", paramsv![self])
@@ -132,6 +152,144 @@ impl MsgId {
         Ok(())
     }
 
+    /// Returns the sanitized HTML body of a received message,
+    /// if [Message::has_html] is set.
+    ///
+    /// The HTML may reference remote content that is not loaded
+    /// by Delta Chat; the UI should show it in a restricted view.
+    pub async fn get_html(self, context: &Context) -> Option<String> {
+        context
+            .sql
+            .query_get_value::<String>(
+                context,
+                "SELECT mime_html FROM msgs WHERE id=?;",
+                paramsv![self],
+            )
+            .await
+            .filter(|html| !html.is_empty())
+    }
+
+    /// Writes the message as RFC 822 file to `path`, typically with the suffix `.eml`.
+    ///
+    /// If the message was received while `save_mime_headers` was enabled,
+    /// the original message is written.  Otherwise, the message is
+    /// reconstructed unencrypted from the text and the attachment.
+    pub async fn export_eml(self, context: &Context, path: impl AsRef<Path>) -> Result<(), Error> {
+        let eml = match get_mime_headers(context, self).await {
+            Some(raw) if !raw.is_empty() => raw.into_bytes(),
+            _ => {
+                let msg = Message::load_from_db(context, self).await?;
+                mimefactory::render_eml(context, &msg).await?
+            }
+        };
+        dc_write_file(context, path, &eml).await?;
+        Ok(())
+    }
+
+    /// Returns the read receipts of an outgoing message as
+    /// `(contact_id, timestamp)`, oldest first.
+    pub async fn get_read_receipts(self, context: &Context) -> crate::sql::Result<Vec<(u32, i64)>> {
+        context
+            .sql
+            .query_map(
+                "SELECT contact_id, timestamp_sent FROM msgs_mdns
+                  WHERE msg_id=?
+                  ORDER BY timestamp_sent, contact_id;",
+                paramsv![self],
+                |row| Ok((row.get::<_, u32>(0)?, row.get::<_, i64>(1)?)),
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await
+    }
+
+    /// Returns the contacts an outgoing message was delivered to.
+    ///
+    /// These are the recipients the server accepted the message for when it was sent,
+    /// see [set_delivered_to_addrs], and the contacts that sent a read receipt.
+    /// Contacts added to the chat later are not included.
+    /// Returns an empty list if the message is not delivered yet.
+    pub async fn get_delivered_to(self, context: &Context) -> Result<Vec<u32>, Error> {
+        let mut contact_ids: Vec<u32> = context
+            .sql
+            .query_map(
+                "SELECT contact_id FROM msg_recipient_status
+                  WHERE msg_id=? AND state IN (?, ?)
+                  ORDER BY contact_id;",
+                paramsv![self, MessageState::OutDelivered, MessageState::OutMdnRcvd],
+                |row| row.get::<_, u32>(0),
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await?;
+        for (contact_id, _) in self.get_read_receipts(context).await? {
+            if !contact_ids.contains(&contact_id) {
+                contact_ids.push(contact_id);
+            }
+        }
+        Ok(contact_ids)
+    }
+
+    /// Returns the delivery state of an outgoing message for each recipient.
+    ///
+    /// The state is `OutDelivered` once the server accepted the message for
+    /// the recipient, `OutFailed` if the recipient was refused or a bounce
+    /// was received and `OutMdnRcvd` once a read receipt was received.
+    /// Members of the chat without recorded state share the state of the message.
+    pub async fn get_recipient_states(
+        self,
+        context: &Context,
+    ) -> Result<Vec<RecipientState>, Error> {
+        let msg = Message::load_from_db(context, self).await?;
+        let mut states = context
+            .sql
+            .query_map(
+                "SELECT contact_id, state, timestamp, error FROM msg_recipient_status
+                  WHERE msg_id=?
+                  ORDER BY contact_id;",
+                paramsv![self],
+                |row| {
+                    let error: String = row.get(3)?;
+                    Ok(RecipientState {
+                        contact_id: row.get(0)?,
+                        state: row.get(1)?,
+                        timestamp: row.get(2)?,
+                        error: Some(error).filter(|error| !error.is_empty()),
+                    })
+                },
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await?;
+
+        // read receipts received before the states were recorded
+        for (contact_id, timestamp) in self.get_read_receipts(context).await? {
+            if !states.iter().any(|state| state.contact_id == contact_id) {
+                states.push(RecipientState {
+                    contact_id,
+                    state: MessageState::OutMdnRcvd,
+                    timestamp,
+                    error: None,
+                });
+            }
+        }
+
+        let default_state = match msg.state {
+            MessageState::OutMdnRcvd => MessageState::OutDelivered,
+            state => state,
+        };
+        for contact_id in chat::get_chat_contacts(context, msg.chat_id).await {
+            if contact_id != DC_CONTACT_ID_SELF
+                && !states.iter().any(|state| state.contact_id == contact_id)
+            {
+                states.push(RecipientState {
+                    contact_id,
+                    state: default_state,
+                    timestamp: 0,
+                    error: None,
+                });
+            }
+        }
+        Ok(states)
+    }
+
     /// Bad evil escape hatch.
     ///
     /// Avoid using this, eventually types should be cleaned up enough
@@ -255,6 +413,9 @@ pub struct Message {
     pub(crate) starred: bool,
     pub(crate) chat_blocked: Blocked,
     pub(crate) location_id: u32,
+    pub(crate) download_state: DownloadState,
+    pub(crate) ephemeral_timer: u32,
+    pub(crate) ephemeral_timestamp: i64,
     pub(crate) param: Params,
 }
 
@@ -266,6 +427,18 @@ impl Message {
         msg
     }
 
+    /// Creates a poll, the options are checked when the poll is sent.
+    pub fn new_poll(question: impl AsRef<str>, options: &[impl AsRef<str>]) -> Self {
+        let options: Vec<&str> = options.iter().map(|option| option.as_ref()).collect();
+        let mut msg = Message::new(Viewtype::Poll);
+        msg.text = Some(question.as_ref().to_string());
+        msg.param.set(
+            Param::PollOptions,
+            serde_json::to_string(&options).unwrap_or_default(),
+        );
+        msg
+    }
+
     pub async fn load_from_db(context: &Context, id: MsgId) -> Result<Message, Error> {
         ensure!(
             !id.is_special(),
@@ -295,6 +468,9 @@ impl Message {
                     "    m.starred AS starred,",
                     "    m.hidden AS hidden,",
                     "    m.location_id AS location,",
+                    "    m.download_state AS download_state,",
+                    "    m.ephemeral_timer AS ephemeral_timer,",
+                    "    m.ephemeral_timestamp AS ephemeral_timestamp,",
                     "    c.blocked AS blocked",
                     " FROM msgs m LEFT JOIN chats c ON c.id=m.chat_id",
                     " WHERE m.id=?;"
@@ -342,6 +518,9 @@ impl Message {
                     msg.starred = row.get("starred")?;
                     msg.hidden = row.get("hidden")?;
                     msg.location_id = row.get("location")?;
+                    msg.download_state = row.get("download_state")?;
+                    msg.ephemeral_timer = row.get("ephemeral_timer")?;
+                    msg.ephemeral_timestamp = row.get("ephemeral_timestamp")?;
                     msg.chat_blocked = row
                         .get::<_, Option<Blocked>>("blocked")?
                         .unwrap_or_default();
@@ -368,33 +547,87 @@ impl Message {
         None
     }
 
+    /// Returns the path of the attached file.
+    ///
+    /// Attachments encrypted at rest are returned as stored,
+    /// use [Message::get_decrypted_file] to read them.
     pub fn get_file(&self, context: &Context) -> Option<PathBuf> {
         self.param.get_path(Param::File, context).unwrap_or(None)
     }
 
-    pub async fn try_calc_and_set_dimensions(&mut self, context: &Context) -> Result<(), Error> {
-        if chat::msgtype_has_file(self.viewtype) {
-            let file_param = self.param.get_path(Param::File, context)?;
-            if let Some(path_and_filename) = file_param {
-                if (self.viewtype == Viewtype::Image || self.viewtype == Viewtype::Gif)
-                    && !self.param.exists(Param::Width)
-                {
-                    self.param.set_int(Param::Width, 0);
-                    self.param.set_int(Param::Height, 0);
+    /// Returns the path of a readable copy of the attached file.
+    ///
+    /// Attachments encrypted at rest are decrypted to a temporary copy,
+    /// see `Config::EncryptBlobs`, others are returned as by [Message::get_file].
+    /// The copies are removed when the context is dropped
+    /// or by housekeeping once they are not used anymore.
+    pub async fn get_decrypted_file(&self, context: &Context) -> Option<PathBuf> {
+        let path = self.get_file(context)?;
+        Some(plaintext_path(context, path).await)
+    }
 
-                    if let Ok(buf) = dc_read_file(context, path_and_filename).await {
-                        if let Ok((width, height)) = dc_get_filemeta(&buf) {
-                            self.param.set_int(Param::Width, width as i32);
-                            self.param.set_int(Param::Height, height as i32);
-                        }
-                    }
+    /// Returns true if the message is a vCard attachment.
+    ///
+    /// UIs may offer to add the contained contacts using
+    /// `contact::import_vcard()`.
+    pub fn is_vcard(&self) -> bool {
+        if self.viewtype != Viewtype::File {
+            return false;
+        }
+        match self.get_filemime() {
+            Some(mime) => {
+                mime == "text/vcard"
+                    || mime == "text/x-vcard"
+                    || mime == "text/directory"
+                    || (mime == "application/octet-stream"
+                        && self
+                            .get_filename()
+                            .map(|name| name.to_lowercase().ends_with(".vcf"))
+                            .unwrap_or_default())
+            }
+            None => false,
+        }
+    }
 
-                    if !self.id.is_unset() {
-                        self.save_param_to_disk(context).await;
-                    }
-                }
+    /// Calculates width and height of an attached image if not yet known.
+    ///
+    /// The dimensions are cached in the params together with a
+    /// signature of the file, so they are only calculated again if
+    /// the file changes.  Dimensions set explicitly without a
+    /// signature, e.g. by [Message::set_dimension], are never
+    /// overwritten.
+    pub async fn try_calc_and_set_dimensions(&mut self, context: &Context) -> Result<(), Error> {
+        if self.viewtype != Viewtype::Image && self.viewtype != Viewtype::Gif {
+            return Ok(());
+        }
+        let path_and_filename = match self.param.get_path(Param::File, context)? {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let signature = get_file_signature(context, &path_and_filename).await;
+        if self.param.exists(Param::Width) {
+            match self.param.get(Param::FileSignature) {
+                None => return Ok(()),
+                Some(cached) if Some(cached) == signature.as_deref() => return Ok(()),
+                Some(_) => {}
             }
         }
+
+        self.param.set_int(Param::Width, 0);
+        self.param.set_int(Param::Height, 0);
+        if let Some((width, height)) = read_dimensions(context, &path_and_filename).await {
+            self.param.set_int(Param::Width, width as i32);
+            self.param.set_int(Param::Height, height as i32);
+        }
+        match signature {
+            Some(signature) => self.param.set(Param::FileSignature, signature),
+            None => self.param.remove(Param::FileSignature),
+        };
+
+        if !self.id.is_unset() {
+            self.save_param_to_disk(context).await;
+        }
         Ok(())
     }
 
@@ -430,6 +663,59 @@ impl Message {
         self.param.set_float(Param::SetLongitude, longitude);
     }
 
+    /// Bind a named point of interest to the message.
+    ///
+    /// Like set_location() but the location is shown on the map
+    /// with the given label, optionally as an area of `radius` meters
+    /// and with a marker `color` given as 0xRRGGBB.
+    /// Points of interest of a chat are returned by location::get_pois().
+    ///
+    /// @param latitude North-south position of the location.
+    /// @param longitude East-west position of the location.
+    /// @param label Label of the point, must not be empty.
+    /// @param radius Radius in meters, if any.
+    /// @param color Marker color, if any.
+    pub fn set_poi(
+        &mut self,
+        latitude: f64,
+        longitude: f64,
+        label: impl AsRef<str>,
+        radius: Option<f64>,
+        color: Option<u32>,
+    ) {
+        let label = label.as_ref().replace(|c: char| c.is_control(), " ");
+        let label = label.trim();
+        if label.is_empty() || (latitude == 0.0 && longitude == 0.0) {
+            return;
+        }
+
+        self.set_location(latitude, longitude);
+        self.param.set(Param::PoiLabel, label);
+        match radius {
+            Some(radius) if radius > 0.0 => self.param.set_float(Param::PoiRadius, radius),
+            _ => self.param.remove(Param::PoiRadius),
+        };
+        match color {
+            Some(color) => self
+                .param
+                .set_int(Param::PoiColor, (color & 0xff_ff_ff) as i32),
+            None => self.param.remove(Param::PoiColor),
+        };
+    }
+
+    /// Returns the point of interest set by set_poi(), if any.
+    pub fn get_poi(&self) -> Option<location::Poi> {
+        let label = self.param.get(Param::PoiLabel)?;
+        Some(location::Poi {
+            label: label.to_string(),
+            radius: self.param.get_float(Param::PoiRadius),
+            color: self
+                .param
+                .get_int(Param::PoiColor)
+                .map(|color| color as u32),
+        })
+    }
+
     pub fn get_timestamp(&self) -> i64 {
         if 0 != self.timestamp_sent {
             self.timestamp_sent
@@ -462,6 +748,19 @@ impl Message {
         self.state
     }
 
+    /// Returns true if the full HTML body of the message is available
+    /// using [MsgId::get_html], eg. for newsletters that cannot be shown
+    /// properly as plain text.
+    pub fn has_html(&self) -> bool {
+        self.param.get_bool(Param::HasHtml).unwrap_or_default()
+    }
+
+    /// Returns whether the message is fully downloaded,
+    /// see [MsgId::download_full] for partially downloaded messages.
+    pub fn download_state(&self) -> DownloadState {
+        self.download_state
+    }
+
     pub fn get_received_timestamp(&self) -> i64 {
         self.timestamp_rcvd
     }
@@ -470,6 +769,17 @@ impl Message {
         self.timestamp_sort
     }
 
+    /// Returns the ephemeral timer of the message in seconds, 0 if it is not ephemeral.
+    pub fn get_ephemeral_timer(&self) -> u32 {
+        self.ephemeral_timer
+    }
+
+    /// Returns the time the message is deleted at,
+    /// 0 if the timer has not started yet or the message is not ephemeral.
+    pub fn get_ephemeral_timestamp(&self) -> i64 {
+        self.ephemeral_timestamp
+    }
+
     pub fn get_text(&self) -> Option<String> {
         self.text
             .as_ref()
@@ -503,10 +813,56 @@ impl Message {
         self.param.get_int(Param::Duration).unwrap_or_default()
     }
 
+    /// Returns the error text of a failed message.
+    pub fn get_error(&self) -> Option<String> {
+        self.param.get(Param::Error).map(|s| s.to_string())
+    }
+
+    /// Returns why sending the message failed.
+    ///
+    /// Returns `None` if the message has not failed.
+    pub fn get_send_error(&self) -> Option<SendError> {
+        if self.state != MessageState::OutFailed {
+            return None;
+        }
+        Some(
+            self.param
+                .get_int(Param::SendError)
+                .and_then(SendError::from_i32)
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Returns when sending the message is tried again.
+    ///
+    /// Returns `None` if the message is not waiting for a retry,
+    /// see [job::Job::next_retry_timestamp].
+    pub async fn get_next_retry_timestamp(&self, context: &Context) -> Option<i64> {
+        context
+            .sql
+            .query_get_value(
+                context,
+                "SELECT desired_timestamp FROM jobs WHERE action=? AND foreign_id=? AND tries>0;",
+                paramsv![Action::SendMsgToSmtp, self.id],
+            )
+            .await
+    }
+
+    /// Returns the response of the SMTP server to the last failed
+    /// attempt of sending the message, e.g. `550 5.7.1 rejected`.
+    pub fn get_smtp_response(&self) -> Option<String> {
+        self.param.get(Param::SmtpResponse).map(|s| s.to_string())
+    }
+
     pub fn get_showpadlock(&self) -> bool {
         self.param.get_int(Param::GuaranteeE2ee).unwrap_or_default() != 0
     }
 
+    /// Returns whether the message was signed or encrypted using S/MIME.
+    pub fn get_smime_state(&self) -> SmimeState {
+        SmimeState::from_bits_truncate(self.param.get_int(Param::Smime).unwrap_or_default())
+    }
+
     pub async fn get_summary(&mut self, context: &Context, chat: Option<&Chat>) -> Lot {
         let mut ret = Lot::new();
 
@@ -521,7 +877,9 @@ impl Message {
         };
 
         let contact = if self.from_id != DC_CONTACT_ID_SELF as u32
-            && (chat.typ == Chattype::Group || chat.typ == Chattype::VerifiedGroup)
+            && (chat.typ == Chattype::Group
+                || chat.typ == Chattype::VerifiedGroup
+                || chat.typ == Chattype::Mailinglist)
         {
             Contact::get_by_id(context, self.from_id).await.ok()
         } else {
@@ -533,6 +891,29 @@ impl Message {
         ret
     }
 
+    /// Returns everything a chatlist row needs to display this message.
+    ///
+    /// This bundles the summary as returned by [Message::get_summary]
+    /// with some further information so that the UI does not need to
+    /// call several accessors for each row.
+    pub async fn get_list_item(&mut self, context: &Context, chat: Option<&Chat>) -> ListItem {
+        let summary = self.get_summary(context, chat).await;
+        let fresh_cnt_hint = if self.chat_id.is_special() {
+            0
+        } else {
+            self.chat_id.get_fresh_msg_cnt(context).await
+        };
+
+        ListItem {
+            summary_text: summary.text2.unwrap_or_default(),
+            state: summary.state,
+            timestamp: summary.timestamp,
+            from_name: summary.text1,
+            has_file: chat::msgtype_has_file(self.viewtype) && self.param.exists(Param::File),
+            fresh_cnt_hint,
+        }
+    }
+
     pub async fn get_summarytext(&self, context: &Context, approx_characters: usize) -> String {
         get_summarytext_by_raw(
             self.viewtype,
@@ -564,6 +945,16 @@ impl Message {
         0 != self.param.get_int(Param::Forwarded).unwrap_or_default()
     }
 
+    /// Returns the name of the original author of a forwarded message,
+    /// `None` if the forwarding user did not name the author.
+    pub fn get_forwarded_from(&self) -> Option<String> {
+        if self.is_forwarded() {
+            self.param.get(Param::ForwardedFrom).map(|s| s.to_string())
+        } else {
+            None
+        }
+    }
+
     pub fn is_info(&self) -> bool {
         let cmd = self.param.get_cmd();
         self.from_id == DC_CONTACT_ID_INFO as u32
@@ -596,7 +987,7 @@ impl Message {
             return None;
         }
 
-        if let Some(filename) = self.get_file(context) {
+        if let Ok(Some(filename)) = self.param.get_path(Param::File, context) {
             if let Ok(ref buf) = dc_read_file(context, filename).await {
                 if let Ok((typ, headers, _)) = split_armored_data(buf) {
                     if typ == pgp::armor::BlockType::Message {
@@ -613,6 +1004,131 @@ impl Message {
         self.text = text;
     }
 
+    /// Makes the message a reply quoting `quoted`.
+    ///
+    /// The quoted text is sent as a quote block on top of the text,
+    /// `In-Reply-To:` refers to the quoted message.
+    pub fn set_quote(&mut self, quoted: &Message) {
+        let text = match quoted.text {
+            Some(ref text) if !text.is_empty() => text.clone(),
+            _ => quoted
+                .get_filename()
+                .unwrap_or_else(|| quoted.viewtype.to_string()),
+        };
+        self.param.set(Param::Quote, text);
+        if quoted.rfc724_mid.is_empty() {
+            self.param.remove(Param::QuotedMsgId);
+        } else {
+            self.param.set(Param::QuotedMsgId, &quoted.rfc724_mid);
+        }
+    }
+
+    /// Returns the text quoted by a reply.
+    pub fn quoted_text(&self) -> Option<String> {
+        self.param.get(Param::Quote).map(|s| s.to_string())
+    }
+
+    /// Returns the message quoted by a reply, if it is known.
+    pub async fn quoted_message(&self, context: &Context) -> Result<Option<Message>, Error> {
+        if let Some(rfc724_mid) = self.param.get(Param::QuotedMsgId) {
+            if let Some((_, _, msg_id)) = rfc724_mid_exists(context, rfc724_mid).await? {
+                return Ok(Some(Message::load_from_db(context, msg_id).await?));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns true if the text of the message was edited after sending.
+    pub fn is_edited(&self) -> bool {
+        self.param.exists(Param::OriginalText)
+    }
+
+    /// Returns the text the message had before it was edited first.
+    ///
+    /// Returns `None` if the message was never edited.
+    pub fn get_original_text(&self) -> Option<String> {
+        self.param.get(Param::OriginalText).map(|s| s.to_string())
+    }
+
+    /// Replaces the text by an edited one, remembering the original text.
+    pub(crate) async fn apply_edit(
+        &mut self,
+        context: &Context,
+        text: String,
+    ) -> Result<(), Error> {
+        if !self.is_edited() {
+            let original_text = self.text.clone().unwrap_or_default();
+            self.param.set(Param::OriginalText, original_text);
+            self.save_param_to_disk(context).await;
+        }
+        self.update_text(context, Some(text)).await?;
+        Ok(())
+    }
+
+    /// Returns the reactions to the message as `(contact_id, reaction)`.
+    pub async fn get_reactions(&self, context: &Context) -> Result<Vec<(u32, String)>, Error> {
+        crate::reaction::get_reactions(context, self.id).await
+    }
+
+    /// Returns the question, the options and the votes of a poll.
+    pub async fn get_poll_state(&self, context: &Context) -> Result<PollState, Error> {
+        crate::poll::get_poll_state(context, self).await
+    }
+
+    /// Returns the event of a message of type [Viewtype::Calendar].
+    pub fn get_calendar_event(&self) -> Option<CalendarEvent> {
+        crate::calendar::get_calendar_event(&self.param)
+    }
+
+    /// Returns the response sent to an invitation, see [MsgId::respond_to_invite].
+    pub fn get_invite_response(&self) -> Option<Rsvp> {
+        self.param
+            .get_int(Param::CalendarRsvp)
+            .and_then(Rsvp::from_i32)
+    }
+
+    /// Returns the manifest of the app attached to a message of type [Viewtype::App].
+    pub async fn get_app_manifest(&self, context: &Context) -> Result<AppManifest, Error> {
+        crate::app::get_app_manifest(context, self).await
+    }
+
+    /// Returns the content of a file of the app attached to a message
+    /// of type [Viewtype::App], eg. `index.html`.
+    pub async fn get_app_file(&self, context: &Context, name: &str) -> Result<Vec<u8>, Error> {
+        crate::app::get_app_file(context, self, name).await
+    }
+
+    /// Sets the text of a message which is already saved in the database.
+    ///
+    /// Unlike [Message::set_text] this persists the new text.  `None`
+    /// is stored as empty text, the raw text of the message is not
+    /// changed.
+    pub async fn update_text(
+        &mut self,
+        context: &Context,
+        text: Option<String>,
+    ) -> crate::sql::Result<()> {
+        if self.id.is_special() {
+            return Err(crate::sql::Error::Other(format_err!(
+                "Can not update text of unsaved or special message {}",
+                self.id
+            )));
+        }
+
+        let text = text.unwrap_or_default();
+        context
+            .sql
+            .execute("UPDATE msgs SET txt=? WHERE id=?;", paramsv![text, self.id])
+            .await?;
+        self.text = Some(text);
+
+        context.emit_event(Event::MsgsChanged {
+            chat_id: self.chat_id,
+            msg_id: self.id,
+        });
+        Ok(())
+    }
+
     pub fn set_file(&mut self, file: impl AsRef<str>, filemime: Option<&str>) {
         self.param.set(Param::File, file);
         if let Some(filemime) = filemime {
@@ -620,6 +1136,23 @@ impl Message {
         }
     }
 
+    /// Sets a poster frame shown as preview of a video message.
+    ///
+    /// The core cannot extract frames from videos,
+    /// so the UI has to create the image, typically a JPEG file.
+    pub fn set_thumbnail(&mut self, file: impl AsRef<str>) {
+        self.param.set(Param::Thumbnail, file);
+    }
+
+    /// Returns the poster frame of a video message, if any.
+    pub async fn get_thumbnail(&self, context: &Context) -> Option<PathBuf> {
+        let path = self
+            .param
+            .get_path(Param::Thumbnail, context)
+            .unwrap_or(None)?;
+        Some(plaintext_path(context, path).await)
+    }
+
     pub fn set_dimension(&mut self, width: i32, height: i32) {
         self.param.set_int(Param::Width, width);
         self.param.set_int(Param::Height, height);
@@ -658,6 +1191,33 @@ impl Message {
     }
 }
 
+/// Data needed to display a message as a chatlist row.
+///
+/// Returned by [Message::get_list_item].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListItem {
+    /// The summary text, see [Message::get_summarytext].
+    pub summary_text: String,
+
+    /// The state of the message.
+    pub state: LotState,
+
+    /// The timestamp to display for the message.
+    pub timestamp: i64,
+
+    /// The name to prefix the summary with, e.g. the first name of
+    /// the sender in groups or "Me" for outgoing messages.
+    ///
+    /// `None` if no prefix should be shown, e.g. for info messages.
+    pub from_name: Option<String>,
+
+    /// Whether the message has an attached file.
+    pub has_file: bool,
+
+    /// Number of fresh messages in the chat of the message.
+    pub fresh_cnt_hint: usize,
+}
+
 #[derive(
     Debug,
     Clone,
@@ -760,6 +1320,66 @@ impl From<MessageState> for LotState {
     }
 }
 
+/// Delivery state of an outgoing message for one recipient,
+/// see [MsgId::get_recipient_states].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecipientState {
+    pub contact_id: u32,
+
+    /// One of `OutPending`, `OutDelivered`, `OutFailed` and `OutMdnRcvd`.
+    pub state: MessageState,
+
+    /// Time the state was recorded, 0 if unknown.
+    pub timestamp: i64,
+
+    /// Error reported by the server if the state is `OutFailed`.
+    pub error: Option<String>,
+}
+
+/// Reason why sending a message failed, see [Message::get_send_error].
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    FromPrimitive,
+    ToPrimitive,
+    ToSql,
+    FromSql,
+    Serialize,
+    Deserialize,
+)]
+#[repr(i32)]
+pub enum SendError {
+    /// Any other error, see [Message::get_error] for details.
+    Other = 0,
+
+    /// The SMTP server rejected the message, eg. as spam or by policy.
+    SmtpRejected = 1,
+
+    /// The SMTP server refused a recipient, eg. as the mailbox does not exist.
+    RecipientRefused = 2,
+
+    /// The message exceeds the size limit of the server.
+    MessageTooLarge = 3,
+
+    /// The server refuses to send more messages for now.
+    RateLimited = 4,
+
+    /// The message could not be end-to-end encrypted though this was required.
+    EncryptionFailed = 5,
+
+    /// The message could not be built, eg. as the attachment is missing.
+    RenderingFailed = 6,
+}
+
+impl Default for SendError {
+    fn default() -> Self {
+        SendError::Other
+    }
+}
+
 impl MessageState {
     pub fn can_fail(self) -> bool {
         match self {
@@ -804,7 +1424,10 @@ impl Lot {
                 );
                 self.text1_meaning = Meaning::Text1Self;
             }
-        } else if chat.typ == Chattype::Group || chat.typ == Chattype::VerifiedGroup {
+        } else if chat.typ == Chattype::Group
+            || chat.typ == Chattype::VerifiedGroup
+            || chat.typ == Chattype::Mailinglist
+        {
             if msg.is_info() || contact.is_none() {
                 self.text1 = None;
                 self.text1_meaning = Meaning::None;
@@ -840,142 +1463,353 @@ impl Lot {
     }
 }
 
-pub async fn get_msg_info(context: &Context, msg_id: MsgId) -> String {
-    let mut ret = String::new();
+/// Encryption state of a message as shown in the message info.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum MsgEncryption {
+    /// The message was not encrypted.
+    Unencrypted,
 
-    let msg = Message::load_from_db(context, msg_id).await;
-    if msg.is_err() {
-        return ret;
-    }
+    /// The message was encrypted and, if incoming, correctly signed.
+    Encrypted,
 
-    let msg = msg.unwrap_or_default();
+    /// The message was encrypted, but the signature could not be verified.
+    NoValidSignature,
 
-    let rawtxt: Option<String> = context
-        .sql
-        .query_get_value(
-            context,
-            "SELECT txt_raw FROM msgs WHERE id=?;",
-            paramsv![msg_id],
-        )
-        .await;
+    /// Decryption failed or the message was not encrypted as expected.
+    Erroneous,
+}
 
-    if rawtxt.is_none() {
-        ret += &format!("Cannot load message {}.", msg_id);
-        return ret;
-    }
-    let rawtxt = rawtxt.unwrap_or_default();
-    let rawtxt = dc_truncate(rawtxt.trim(), 100_000);
+/// Read receipt of a message, see [MsgInfo].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReadReceipt {
+    pub contact_id: u32,
 
-    let fts = dc_timestamp_to_str(msg.get_timestamp());
-    ret += &format!("Sent: {}", fts);
+    /// Name and address of the contact.
+    pub name: String,
 
-    let name = Contact::load_from_db(context, msg.from_id)
-        .await
-        .map(|contact| contact.get_name_n_addr())
-        .unwrap_or_default();
+    pub timestamp: i64,
+}
 
-    ret += &format!(" by {}", name);
-    ret += "\n";
+/// Details about a message, see [MsgId::get_info_struct].
+///
+/// This is the data shown by [get_msg_info]
+/// in a form that does not need to be parsed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MsgInfo {
+    pub msg_id: MsgId,
+
+    pub timestamp_sent: i64,
+
+    pub from_id: u32,
+
+    /// Name and address of the sender.
+    pub from: String,
+
+    /// Time the message was received, `None` for outgoing messages.
+    pub timestamp_received: Option<i64>,
+
+    /// True for device-internal info messages,
+    /// the remaining fields are of no interest for them.
+    pub is_info: bool,
+
+    /// Read receipts, oldest first.
+    pub read_receipts: Vec<ReadReceipt>,
+
+    /// Delivery state for each recipient, empty for incoming messages.
+    pub recipient_states: Vec<RecipientState>,
+
+    pub state: MessageState,
+
+    pub has_location: bool,
+
+    pub encryption: MsgEncryption,
+
+    pub error: Option<String>,
+
+    pub smtp_response: Option<String>,
+
+    /// Path of the attached file.
+    pub file: Option<String>,
+
+    /// Size of the attached file in bytes.
+    pub file_bytes: u64,
+
+    pub viewtype: Viewtype,
+
+    pub mimetype: Option<String>,
+
+    pub width: i32,
+
+    pub height: i32,
+
+    /// Duration of audio and video files in milliseconds.
+    pub duration: i32,
+
+    /// Raw text of the message, truncated.
+    pub raw_text: String,
+
+    pub rfc724_mid: String,
+
+    /// Folder and UID the message was last seen at on the server.
+    pub server_folder: Option<String>,
+
+    pub server_uid: u32,
+}
+
+impl MsgId {
+    /// Returns details about the message, see [MsgInfo].
+    pub async fn get_info_struct(self, context: &Context) -> Result<MsgInfo, Error> {
+        let msg = Message::load_from_db(context, self).await?;
+
+        let rawtxt: String = context
+            .sql
+            .query_get_value(
+                context,
+                "SELECT txt_raw FROM msgs WHERE id=?;",
+                paramsv![self],
+            )
+            .await
+            .ok_or_else(|| format_err!("Cannot load message {}.", self))?;
+        let raw_text = dc_truncate(rawtxt.trim(), 100_000).to_string();
 
-    if msg.from_id != DC_CONTACT_ID_SELF as u32 {
-        let s = dc_timestamp_to_str(if 0 != msg.timestamp_rcvd {
-            msg.timestamp_rcvd
+        let from = Contact::load_from_db(context, msg.from_id)
+            .await
+            .map(|contact| contact.get_name_n_addr())
+            .unwrap_or_default();
+
+        let timestamp_received = if msg.from_id != DC_CONTACT_ID_SELF as u32 {
+            Some(if 0 != msg.timestamp_rcvd {
+                msg.timestamp_rcvd
+            } else {
+                msg.timestamp_sort
+            })
         } else {
-            msg.timestamp_sort
-        });
-        ret += &format!("Received: {}", &s);
-        ret += "\n";
+            None
+        };
+
+        let mut read_receipts = Vec::new();
+        if let Ok(rows) = self.get_read_receipts(context).await {
+            for (contact_id, timestamp) in rows {
+                let name = Contact::load_from_db(context, contact_id)
+                    .await
+                    .map(|contact| contact.get_name_n_addr())
+                    .unwrap_or_default();
+                read_receipts.push(ReadReceipt {
+                    contact_id,
+                    name,
+                    timestamp,
+                });
+            }
+        }
+
+        let recipient_states = if msg.from_id == DC_CONTACT_ID_SELF {
+            self.get_recipient_states(context).await.unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+
+        let e2ee_errors = msg.param.get_int(Param::ErroneousE2ee).unwrap_or_default();
+        let encryption = if 0 != e2ee_errors {
+            if 0 != e2ee_errors & 0x2 {
+                MsgEncryption::NoValidSignature
+            } else {
+                MsgEncryption::Erroneous
+            }
+        } else if 0 != msg.param.get_int(Param::GuaranteeE2ee).unwrap_or_default() {
+            MsgEncryption::Encrypted
+        } else {
+            MsgEncryption::Unencrypted
+        };
+
+        let (file, file_bytes) = match msg.param.get_path(Param::File, context) {
+            Ok(Some(path)) => {
+                let bytes = dc_get_filebytes(context, &path).await;
+                (Some(path.display().to_string()), bytes)
+            }
+            _ => (None, 0),
+        };
+
+        Ok(MsgInfo {
+            msg_id: self,
+            timestamp_sent: msg.get_timestamp(),
+            from_id: msg.from_id,
+            from,
+            timestamp_received,
+            is_info: msg.from_id == DC_CONTACT_ID_INFO || msg.to_id == DC_CONTACT_ID_INFO,
+            read_receipts,
+            recipient_states,
+            state: msg.state,
+            has_location: msg.has_location(),
+            encryption,
+            error: msg.param.get(Param::Error).map(|err| err.to_string()),
+            smtp_response: msg.get_smtp_response(),
+            file,
+            file_bytes,
+            viewtype: msg.viewtype,
+            mimetype: msg.get_filemime(),
+            width: msg.param.get_int(Param::Width).unwrap_or_default(),
+            height: msg.param.get_int(Param::Height).unwrap_or_default(),
+            duration: msg.param.get_int(Param::Duration).unwrap_or_default(),
+            raw_text,
+            rfc724_mid: msg.rfc724_mid,
+            server_folder: msg.server_folder.filter(|folder| !folder.is_empty()),
+            server_uid: msg.server_uid,
+        })
     }
+}
 
-    if msg.from_id == DC_CONTACT_ID_INFO || msg.to_id == DC_CONTACT_ID_INFO {
-        // device-internal message, no further details needed
-        return ret;
+/// Returns details about the message as human-readable text,
+/// see [MsgId::get_info_struct] for the structured version.
+pub async fn get_msg_info(context: &Context, msg_id: MsgId) -> String {
+    match msg_id.get_info_struct(context).await {
+        Ok(info) => format_msg_info(&info),
+        Err(err) => {
+            warn!(context, "Cannot get info for message {}: {}", msg_id, err);
+            String::new()
+        }
     }
+}
 
-    if let Ok(rows) = context
-        .sql
-        .query_map(
-            "SELECT contact_id, timestamp_sent FROM msgs_mdns WHERE msg_id=?;",
-            paramsv![msg_id],
-            |row| {
-                let contact_id: i32 = row.get(0)?;
-                let ts: i64 = row.get(1)?;
-                Ok((contact_id, ts))
-            },
-            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
-        )
-        .await
-    {
-        for (contact_id, ts) in rows {
-            let fts = dc_timestamp_to_str(ts);
-            ret += &format!("Read: {}", fts);
+fn format_msg_info(info: &MsgInfo) -> String {
+    let mut ret = String::new();
 
-            let name = Contact::load_from_db(context, contact_id as u32)
-                .await
-                .map(|contact| contact.get_name_n_addr())
-                .unwrap_or_default();
+    ret += &format!(
+        "Sent: {} by {}\n",
+        dc_timestamp_to_str(info.timestamp_sent),
+        info.from
+    );
 
-            ret += &format!(" by {}", name);
-            ret += "\n";
-        }
+    if let Some(timestamp_received) = info.timestamp_received {
+        ret += &format!("Received: {}\n", dc_timestamp_to_str(timestamp_received));
     }
 
-    ret += &format!("State: {}", msg.state);
+    if info.is_info {
+        // device-internal message, no further details needed
+        return ret;
+    }
 
-    if msg.has_location() {
-        ret += ", Location sent";
+    for receipt in &info.read_receipts {
+        ret += &format!(
+            "Read: {} by {}\n",
+            dc_timestamp_to_str(receipt.timestamp),
+            receipt.name
+        );
     }
 
-    let e2ee_errors = msg.param.get_int(Param::ErroneousE2ee).unwrap_or_default();
+    ret += &format!("State: {}", info.state);
 
-    if 0 != e2ee_errors {
-        if 0 != e2ee_errors & 0x2 {
-            ret += ", Encrypted, no valid signature";
-        }
-    } else if 0 != msg.param.get_int(Param::GuaranteeE2ee).unwrap_or_default() {
-        ret += ", Encrypted";
+    if info.has_location {
+        ret += ", Location sent";
+    }
+
+    match info.encryption {
+        MsgEncryption::NoValidSignature => ret += ", Encrypted, no valid signature",
+        MsgEncryption::Encrypted => ret += ", Encrypted",
+        MsgEncryption::Unencrypted | MsgEncryption::Erroneous => {}
     }
 
     ret += "\n";
-    if let Some(err) = msg.param.get(Param::Error) {
+    if let Some(ref err) = info.error {
         ret += &format!("Error: {}", err)
     }
+    if let Some(ref response) = info.smtp_response {
+        ret += &format!("\nSMTP response: {}", response)
+    }
 
-    if let Some(path) = msg.get_file(context) {
-        let bytes = dc_get_filebytes(context, &path).await;
-        ret += &format!("\nFile: {}, {}, bytes\n", path.display(), bytes);
+    if let Some(ref file) = info.file {
+        ret += &format!("\nFile: {}, {}, bytes\n", file, info.file_bytes);
     }
 
-    if msg.viewtype != Viewtype::Text {
-        ret += "Type: ";
-        ret += &format!("{}", msg.viewtype);
-        ret += "\n";
-        ret += &format!("Mimetype: {}\n", &msg.get_filemime().unwrap_or_default());
+    if info.viewtype != Viewtype::Text {
+        ret += &format!("Type: {}\n", info.viewtype);
+        ret += &format!(
+            "Mimetype: {}\n",
+            info.mimetype.as_deref().unwrap_or_default()
+        );
     }
-    let w = msg.param.get_int(Param::Width).unwrap_or_default();
-    let h = msg.param.get_int(Param::Height).unwrap_or_default();
-    if w != 0 || h != 0 {
-        ret += &format!("Dimension: {} x {}\n", w, h,);
+    if info.width != 0 || info.height != 0 {
+        ret += &format!("Dimension: {} x {}\n", info.width, info.height);
     }
-    let duration = msg.param.get_int(Param::Duration).unwrap_or_default();
-    if duration != 0 {
-        ret += &format!("Duration: {} ms\n", duration,);
+    if info.duration != 0 {
+        ret += &format!("Duration: {} ms\n", info.duration);
     }
-    if !rawtxt.is_empty() {
-        ret += &format!("\n{}\n", rawtxt);
+    if !info.raw_text.is_empty() {
+        ret += &format!("\n{}\n", info.raw_text);
     }
-    if !msg.rfc724_mid.is_empty() {
-        ret += &format!("\nMessage-ID: {}", msg.rfc724_mid);
+    if !info.rfc724_mid.is_empty() {
+        ret += &format!("\nMessage-ID: {}", info.rfc724_mid);
     }
-    if let Some(ref server_folder) = msg.server_folder {
-        if server_folder != "" {
-            ret += &format!("\nLast seen as: {}/{}", server_folder, msg.server_uid);
-        }
+    if let Some(ref server_folder) = info.server_folder {
+        ret += &format!("\nLast seen as: {}/{}", server_folder, info.server_uid);
     }
 
     ret
 }
 
+/// Number of bytes read from the start of an image to find its dimensions.
+///
+/// This is enough for the headers of all common formats; only if
+/// detection fails on these bytes the whole file is read.
+const DIMENSIONS_HEADER_BYTES: u64 = 64 * 1024;
+
+#[cfg(test)]
+thread_local! {
+    static DIMENSIONS_READ_CNT: std::cell::Cell<usize> = std::cell::Cell::new(0);
+}
+
+/// Returns a signature of a file which changes when the file is modified.
+///
+/// The modification time is used with sub-second precision,
+/// so files replaced by others of the same size are detected
+/// even if this happens within a second.
+async fn get_file_signature(context: &Context, path: &Path) -> Option<String> {
+    let meta = async_std::fs::metadata(dc_get_abs_path(context, path))
+        .await
+        .ok()?;
+    let modified = meta
+        .modified()
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?;
+    Some(format!("{}:{}", meta.len(), modified.as_nanos()))
+}
+
+/// Returns the path to the plaintext of a file encrypted at rest.
+async fn plaintext_path(context: &Context, path: PathBuf) -> PathBuf {
+    match blob_crypt::decrypted_path(context, path.clone()).await {
+        Ok(path) => path,
+        Err(err) => {
+            warn!(context, "Cannot decrypt {}: {}", path.display(), err);
+            path
+        }
+    }
+}
+
+/// Reads width and height of an image file.
+async fn read_dimensions(context: &Context, path: &Path) -> Option<(u32, u32)> {
+    use async_std::io::ReadExt;
+
+    #[cfg(test)]
+    DIMENSIONS_READ_CNT.with(|cnt| cnt.set(cnt.get() + 1));
+
+    let file = dc_open_file(context, path).await.ok()?;
+    let mut buf = Vec::new();
+    file.take(DIMENSIONS_HEADER_BYTES)
+        .read_to_end(&mut buf)
+        .await
+        .ok()?;
+    if let Ok(dimensions) = dc_get_filemeta(&buf) {
+        return Some(dimensions);
+    }
+    if (buf.len() as u64) < DIMENSIONS_HEADER_BYTES && !blob_crypt::is_encrypted(&buf) {
+        // the whole file was read already
+        return None;
+    }
+
+    let buf = dc_read_file(context, path).await.ok()?;
+    dc_get_filemeta(&buf).ok()
+}
+
 pub fn guess_msgtype_from_suffix(path: &Path) -> Option<(Viewtype, &str)> {
     let extension: &str = &path.extension()?.to_str()?.to_lowercase();
     let info = match extension {
@@ -988,6 +1822,9 @@ pub fn guess_msgtype_from_suffix(path: &Path) -> Option<(Viewtype, &str)> {
         "jpe" => (Viewtype::Image, "image/jpeg"),
         "png" => (Viewtype::Image, "image/png"),
         "webp" => (Viewtype::Image, "image/webp"),
+        "heic" => (Viewtype::Image, "image/heic"),
+        "heif" => (Viewtype::Image, "image/heif"),
+        "avif" => (Viewtype::Image, "image/avif"),
         "gif" => (Viewtype::Gif, "image/gif"),
         "vcf" => (Viewtype::File, "text/vcard"),
         "vcard" => (Viewtype::File, "text/vcard"),
@@ -1009,49 +1846,96 @@ pub async fn get_mime_headers(context: &Context, msg_id: MsgId) -> Option<String
         .await
 }
 
+/// Maximum number of messages deleted from the server by a single job.
+const DELETE_MSGS_BATCH_SIZE: usize = 100;
+
 pub async fn delete_msgs(context: &Context, msg_ids: &[MsgId]) {
-    for msg_id in msg_ids.iter() {
-        if let Ok(msg) = Message::load_from_db(context, *msg_id).await {
-            if msg.location_id > 0 {
-                delete_poi_location(context, msg.location_id).await;
+    if msg_ids.is_empty() {
+        return;
+    }
+
+    let ids = msg_ids.to_vec();
+    let res = context
+        .sql
+        .with_conn(move |mut conn| {
+            let tx = conn.transaction()?;
+            let mut folders: BTreeMap<String, Vec<MsgId>> = BTreeMap::new();
+            {
+                let mut delete_poi = tx.prepare(
+                    "DELETE FROM locations WHERE independent=1 AND id=(SELECT location_id FROM msgs WHERE id=?);",
+                )?;
+                let mut get_folder = tx.prepare("SELECT server_folder FROM msgs WHERE id=?;")?;
+                for msg_id in ids {
+                    delete_poi.execute(paramsv![msg_id])?;
+                    let folder = get_folder
+                        .query_row(paramsv![msg_id], |row| row.get::<_, Option<String>>(0))
+                        .unwrap_or_default()
+                        .unwrap_or_default();
+                    folders.entry(folder).or_default().push(msg_id);
+                }
             }
+            tx.commit()?;
+            Ok(folders)
+        })
+        .await;
+    let folders = match res {
+        Ok(folders) => folders,
+        Err(err) => {
+            error!(context, "Unable to delete locations of messages: {}", err);
+            BTreeMap::new()
         }
+    };
+
+    for msg_id in msg_ids {
         if let Err(err) = msg_id.trash(context).await {
             error!(context, "Unable to trash message {}: {}", msg_id, err);
         }
-        job::add(
-            context,
-            job::Job::new(Action::DeleteMsgOnImap, msg_id.to_u32(), Params::new(), 0),
-        )
-        .await;
     }
 
-    if !msg_ids.is_empty() {
-        context.emit_event(Event::MsgsChanged {
-            chat_id: ChatId::new(0),
-            msg_id: MsgId::new(0),
-        });
-        job::kill_action(context, Action::Housekeeping).await;
-        job::add(
-            context,
-            job::Job::new(Action::Housekeeping, 0, Params::new(), 10),
-        )
-        .await;
+    // the messages are deleted from the server with one job per folder
+    let mut jobs = Vec::new();
+    for (_, folder_msg_ids) in folders {
+        for chunk in folder_msg_ids.chunks(DELETE_MSGS_BATCH_SIZE) {
+            let mut param = Params::new();
+            param.set(
+                Param::Arg4,
+                chunk
+                    .iter()
+                    .map(|msg_id| msg_id.to_u32().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            );
+            jobs.push(job::Job::new(
+                Action::DeleteMsgOnImap,
+                chunk[0].to_u32(),
+                param,
+                0,
+            ));
+        }
     }
-}
+    job::add_batch(context, jobs).await;
 
-async fn delete_poi_location(context: &Context, location_id: u32) -> bool {
-    context
-        .sql
-        .execute(
-            "DELETE FROM locations WHERE independent = 1 AND id=?;",
-            paramsv![location_id as i32],
-        )
-        .await
-        .is_ok()
+    context.emit_event(Event::MsgsChanged {
+        chat_id: ChatId::new(0),
+        msg_id: MsgId::new(0),
+    });
+    job::kill_action(context, Action::Housekeeping).await;
+    job::add(
+        context,
+        job::Job::new(Action::Housekeeping, 0, Params::new(), 10),
+    )
+    .await;
 }
 
+/// Marks messages as seen.
+///
+/// If the `sync_msgs` option is enabled, the messages are also marked as seen
+/// on the other devices.
 pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> bool {
+    markseen_msgs_ex(context, msg_ids, true).await
+}
+
+pub(crate) async fn markseen_msgs_ex(context: &Context, msg_ids: Vec<MsgId>, sync: bool) -> bool {
     if msg_ids.is_empty() {
         return false;
     }
@@ -1062,6 +1946,7 @@ pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> bool {
             let mut stmt = conn.prepare_cached(concat!(
                 "SELECT",
                 "    m.state AS state,",
+                "    m.rfc724_mid AS rfc724_mid,",
                 "    c.blocked AS blocked",
                 " FROM msgs m LEFT JOIN chats c ON c.id=m.chat_id",
                 " WHERE m.id=? AND m.chat_id>9"
@@ -1072,6 +1957,7 @@ pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> bool {
                 let query_res = stmt.query_row(paramsv![id], |row| {
                     Ok((
                         row.get::<_, MessageState>("state")?,
+                        row.get::<_, String>("rfc724_mid")?,
                         row.get::<_, Option<Blocked>>("blocked")?
                             .unwrap_or_default(),
                     ))
@@ -1079,8 +1965,9 @@ pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> bool {
                 if let Err(rusqlite::Error::QueryReturnedNoRows) = query_res {
                     continue;
                 }
-                let (state, blocked) = query_res.map_err(Into::<anyhow::Error>::into)?;
-                msgs.push((id, state, blocked));
+                let (state, rfc724_mid, blocked) =
+                    query_res.map_err(Into::<anyhow::Error>::into)?;
+                msgs.push((id, state, rfc724_mid, blocked));
             }
 
             Ok(msgs)
@@ -1088,26 +1975,55 @@ pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> bool {
         .await
         .unwrap_or_default();
 
-    let mut send_event = false;
+    let mut updates = Vec::new();
+    let mut jobs = Vec::new();
+    let mut seen_msg_ids = Vec::new();
+    let mut seen_rfc724_mids = Vec::new();
 
-    for (id, curr_state, curr_blocked) in msgs.into_iter() {
+    for (id, curr_state, rfc724_mid, curr_blocked) in msgs.into_iter() {
         if curr_blocked == Blocked::Not {
             if curr_state == MessageState::InFresh || curr_state == MessageState::InNoticed {
-                update_msg_state(context, id, MessageState::InSeen).await;
+                updates.push((id, MessageState::InSeen));
                 info!(context, "Seen message {}.", id);
-
-                job::add(
-                    context,
-                    job::Job::new(Action::MarkseenMsgOnImap, id.to_u32(), Params::new(), 0),
-                )
-                .await;
-                send_event = true;
+                seen_msg_ids.push(id);
+                if !rfc724_mid.is_empty() {
+                    seen_rfc724_mids.push(rfc724_mid);
+                }
+                jobs.push(job::Job::new(
+                    Action::MarkseenMsgOnImap,
+                    id.to_u32(),
+                    Params::new(),
+                    0,
+                ));
             }
         } else if curr_state == MessageState::InFresh {
-            update_msg_state(context, id, MessageState::InNoticed).await;
-            send_event = true;
+            updates.push((id, MessageState::InNoticed));
+        }
+    }
+
+    let send_event = !updates.is_empty();
+    if send_event {
+        let res = context
+            .sql
+            .with_conn(move |mut conn| {
+                let tx = conn.transaction()?;
+                {
+                    let mut stmt = tx.prepare("UPDATE msgs SET state=? WHERE id=?;")?;
+                    for (id, state) in updates {
+                        stmt.execute(paramsv![state, id])?;
+                    }
+                }
+                tx.commit()?;
+                Ok(())
+            })
+            .await;
+        if let Err(err) = res {
+            error!(context, "Unable to mark messages as seen: {}", err);
         }
     }
+    if !jobs.is_empty() {
+        job::add_batch(context, jobs).await;
+    }
 
     if send_event {
         context.emit_event(Event::MsgsChanged {
@@ -1115,10 +2031,160 @@ pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> bool {
             msg_id: MsgId::new(0),
         });
     }
+    if let Err(err) = ephemeral::start_timers(context, &seen_msg_ids).await {
+        warn!(context, "Unable to start ephemeral timers: {}", err);
+    }
+
+    if sync && !seen_rfc724_mids.is_empty() {
+        let item = sync::SyncItem::MarkSeen {
+            msgs: seen_rfc724_mids,
+        };
+        if let Err(err) = sync::send_sync_items(context, vec![item]).await {
+            warn!(context, "Cannot send sync message: {}", err);
+        }
+    }
 
     true
 }
 
+/// Returns the messages of a chat which are sorted after the given message.
+///
+/// This allows UIs which already show the messages up to `after` to
+/// only load newer messages.  If `after` is unset, all messages of
+/// the chat are returned.  Unlike [chat::get_chat_msgs] no markers
+/// are added and special chats are not supported.
+pub async fn get_chat_msgs_after(
+    context: &Context,
+    chat_id: ChatId,
+    after: MsgId,
+) -> Result<Vec<MsgId>, Error> {
+    ensure!(!chat_id.is_special(), "Invalid chat ID {}", chat_id);
+
+    let (timestamp, id) = if after.is_unset() {
+        (0, 0)
+    } else {
+        ensure!(!after.is_special(), "Invalid message ID {}", after);
+        let timestamp = context
+            .sql
+            .query_get_value_result::<i64>(
+                "SELECT timestamp FROM msgs WHERE id=?;",
+                paramsv![after],
+            )
+            .await?
+            .ok_or_else(|| format_err!("Message {} does not exist", after))?;
+        (timestamp, after.to_u32())
+    };
+
+    let ids = context
+        .sql
+        .query_map(
+            "SELECT id FROM msgs
+              WHERE chat_id=?
+                AND hidden=0
+                AND (ephemeral_timestamp=0 OR ephemeral_timestamp>?)
+                AND (timestamp>? OR (timestamp=? AND id>?))
+              ORDER BY timestamp, id;",
+            paramsv![chat_id, time(), timestamp, timestamp, id],
+            |row| row.get::<_, MsgId>(0),
+            |ids| ids.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    Ok(ids)
+}
+
+/// Converts a user-entered search query to an FTS5 query.
+///
+/// Every word of the query is quoted, so FTS5 operators are not
+/// interpreted, and matches as a prefix.  Returns `None` if the query
+/// contains no words.
+pub(crate) fn fts_query(query: &str) -> Option<String> {
+    let words: Vec<String> = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+    if words.is_empty() {
+        None
+    } else {
+        Some(words.join(" "))
+    }
+}
+
+/// Searches messages using the full-text index, most relevant first.
+///
+/// If `chat_id` is unset, all chats are searched.  Every word of the
+/// query has to match the beginning of a word in the message text,
+/// so other than [Context::search_msgs] words are not found by a part
+/// from their middle or end.
+pub async fn search_msgs(
+    context: &Context,
+    chat_id: ChatId,
+    query: impl AsRef<str>,
+) -> Result<Vec<MsgId>, Error> {
+    let fts_query = match fts_query(query.as_ref()) {
+        Some(fts_query) => fts_query,
+        None => return Ok(Vec::new()),
+    };
+    let (chat_cond, chat_id) = if chat_id.is_unset() {
+        ("m.chat_id>? AND c.blocked=0", DC_CHAT_ID_LAST_SPECIAL)
+    } else {
+        ("m.chat_id=?", chat_id.to_u32())
+    };
+
+    let ids = context
+        .sql
+        .query_map(
+            format!(
+                "SELECT m.id
+                   FROM msgs_search
+                  INNER JOIN msgs m ON m.id=msgs_search.rowid
+                   LEFT JOIN contacts ct ON m.from_id=ct.id
+                   LEFT JOIN chats c ON m.chat_id=c.id
+                  WHERE msgs_search MATCH ?
+                    AND {}
+                    AND m.hidden=0
+                    AND ct.blocked=0
+                    AND (m.ephemeral_timestamp=0 OR m.ephemeral_timestamp>?)
+                  ORDER BY msgs_search.rank, m.timestamp DESC, m.id DESC;",
+                chat_cond
+            ),
+            paramsv![fts_query, chat_id, time()],
+            |row| row.get::<_, MsgId>(0),
+            |ids| ids.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    Ok(ids)
+}
+
+/// Returns the reactions to a message as `(emoji, count, reacted_by_self)`.
+///
+/// Reactions are grouped by emoji, the most frequent ones come first.
+pub async fn get_reaction_counts(
+    context: &Context,
+    msg_id: MsgId,
+) -> Result<Vec<(String, usize, bool)>, Error> {
+    ensure!(!msg_id.is_special(), "Invalid message ID {}", msg_id);
+
+    let counts = context
+        .sql
+        .query_map(
+            "SELECT reaction, COUNT(*), MAX(contact_id=?)
+               FROM reactions
+              WHERE msg_id=? AND reaction!=''
+              GROUP BY reaction
+              ORDER BY COUNT(*) DESC, MIN(timestamp), reaction;",
+            paramsv![DC_CONTACT_ID_SELF as i32, msg_id],
+            |row| {
+                let reaction: String = row.get(0)?;
+                let count: isize = row.get(1)?;
+                let reacted_by_self: bool = row.get(2)?;
+                Ok((reaction, count as usize, reacted_by_self))
+            },
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    Ok(counts)
+}
+
 pub async fn update_msg_state(context: &Context, msg_id: MsgId, state: MessageState) -> bool {
     context
         .sql
@@ -1134,17 +2200,44 @@ pub async fn star_msgs(context: &Context, msg_ids: Vec<MsgId>, star: bool) -> bo
     if msg_ids.is_empty() {
         return false;
     }
-    context
+    let changed_ids = msg_ids.clone();
+    let res = context
         .sql
-        .with_conn(move |conn| {
-            let mut stmt = conn.prepare("UPDATE msgs SET starred=? WHERE id=?;")?;
-            for msg_id in msg_ids.into_iter() {
-                stmt.execute(paramsv![star as i32, msg_id])?;
+        .with_conn(move |mut conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut stmt = tx.prepare("UPDATE msgs SET starred=? WHERE id=?;")?;
+                for msg_id in msg_ids.into_iter() {
+                    stmt.execute(paramsv![star as i32, msg_id])?;
+                }
             }
+            tx.commit()?;
             Ok(())
         })
-        .await
-        .is_ok()
+        .await;
+    if let Err(err) = res {
+        error!(context, "Unable to star messages: {}", err);
+        return false;
+    }
+
+    // other mail clients show starred messages as flagged
+    for msg_id in changed_ids {
+        if let Ok(msg) = Message::load_from_db(context, msg_id).await {
+            if msg.server_uid != 0 {
+                job::add(
+                    context,
+                    job::Job::new(Action::SetFlaggedOnImap, msg_id.to_u32(), Params::new(), 0),
+                )
+                .await;
+            }
+        }
+    }
+
+    context.emit_event(Event::MsgsChanged {
+        chat_id: ChatId::new(0),
+        msg_id: MsgId::new(0),
+    });
+    true
 }
 
 /// Returns a summary test.
@@ -1161,11 +2254,16 @@ pub async fn get_summarytext_by_raw(
         Viewtype::Gif => context.stock_str(StockMessage::Gif).await.into_owned(),
         Viewtype::Sticker => context.stock_str(StockMessage::Sticker).await.into_owned(),
         Viewtype::Video => context.stock_str(StockMessage::Video).await.into_owned(),
+        Viewtype::Poll => context.stock_str(StockMessage::Poll).await.into_owned(),
+        Viewtype::Calendar => context
+            .stock_str(StockMessage::Invitation)
+            .await
+            .into_owned(),
         Viewtype::Voice => context
             .stock_str(StockMessage::VoiceMessage)
             .await
             .into_owned(),
-        Viewtype::Audio | Viewtype::File => {
+        Viewtype::Audio | Viewtype::File | Viewtype::App => {
             if param.get_cmd() == SystemMessage::AutocryptSetupMessage {
                 append_text = false;
                 context
@@ -1249,11 +2347,17 @@ pub async fn exists(context: &Context, msg_id: MsgId) -> bool {
     }
 }
 
-pub async fn set_msg_failed(context: &Context, msg_id: MsgId, error: Option<impl AsRef<str>>) {
+pub async fn set_msg_failed(
+    context: &Context,
+    msg_id: MsgId,
+    send_error: SendError,
+    error: Option<impl AsRef<str>>,
+) {
     if let Ok(mut msg) = Message::load_from_db(context, msg_id).await {
         if msg.state.can_fail() {
             msg.state = MessageState::OutFailed;
         }
+        msg.param.set_int(Param::SendError, send_error as i32);
         if let Some(error) = error {
             msg.param.set(Param::Error, error.as_ref());
             warn!(context, "Message failed: {}", error.as_ref());
@@ -1276,6 +2380,158 @@ pub async fn set_msg_failed(context: &Context, msg_id: MsgId, error: Option<impl
     }
 }
 
+/// Stores the response of the SMTP server to the last attempt of
+/// sending a message.
+///
+/// Passing `None` removes a previously stored response.
+pub(crate) async fn set_msg_smtp_response(
+    context: &Context,
+    msg_id: MsgId,
+    response: Option<&str>,
+) -> crate::sql::Result<()> {
+    let mut msg = Message::load_from_db(context, msg_id).await?;
+    match response {
+        Some(response) => {
+            msg.param.set(Param::SmtpResponse, response);
+        }
+        None => {
+            if !msg.param.exists(Param::SmtpResponse) {
+                return Ok(());
+            }
+            msg.param.remove(Param::SmtpResponse);
+        }
+    }
+    context
+        .sql
+        .execute(
+            "UPDATE msgs SET param=? WHERE id=?;",
+            paramsv![msg.param.to_string(), msg_id],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Records the delivery state of a message for a recipient.
+///
+/// A read receipt is never overwritten as the recipient obviously got the message.
+pub(crate) async fn set_recipient_state(
+    context: &Context,
+    msg_id: MsgId,
+    contact_id: u32,
+    state: MessageState,
+    error: Option<&str>,
+) -> crate::sql::Result<()> {
+    let current_state: Option<MessageState> = context
+        .sql
+        .query_get_value(
+            context,
+            "SELECT state FROM msg_recipient_status WHERE msg_id=? AND contact_id=?;",
+            paramsv![msg_id, contact_id],
+        )
+        .await;
+    if current_state == Some(MessageState::OutMdnRcvd) {
+        return Ok(());
+    }
+
+    context
+        .sql
+        .execute(
+            "INSERT OR REPLACE INTO msg_recipient_status (msg_id, contact_id, state, timestamp, error)
+             VALUES (?, ?, ?, ?, ?);",
+            paramsv![msg_id, contact_id, state, time(), error.unwrap_or_default()],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Records that the server accepted a message for the given addresses.
+pub(crate) async fn set_delivered_to_addrs(context: &Context, msg_id: MsgId, addrs: &[String]) {
+    for addr in addrs {
+        let contact_id = Contact::lookup_id_by_addr(context, addr, Origin::Unknown).await;
+        if contact_id <= DC_CONTACT_ID_LAST_SPECIAL {
+            continue;
+        }
+        set_recipient_state(
+            context,
+            msg_id,
+            contact_id,
+            MessageState::OutDelivered,
+            None,
+        )
+        .await
+        .unwrap_or_else(|err| {
+            warn!(context, "failed to store delivery state: {}", err);
+        });
+    }
+}
+
+/// Handles a non-delivery notification (bounce) for a recipient
+/// of the outgoing message `rfc724_mid`.
+///
+/// In groups, only the state of the recipient is updated,
+/// messages in one-to-one chats fail as a whole.
+/// Addresses that are no member of the chat are ignored.
+pub(crate) async fn ndn_from_ext(context: &Context, rfc724_mid: &str, addr: &str, error: &str) {
+    let res = context
+        .sql
+        .query_row(
+            "SELECT m.id, m.chat_id, c.type FROM msgs m LEFT JOIN chats c ON m.chat_id=c.id
+              WHERE m.rfc724_mid=? AND m.from_id=1
+              ORDER BY m.id;",
+            paramsv![rfc724_mid],
+            |row| {
+                Ok((
+                    row.get::<_, MsgId>(0)?,
+                    row.get::<_, ChatId>(1)?,
+                    row.get::<_, Chattype>(2)?,
+                ))
+            },
+        )
+        .await;
+    let (msg_id, chat_id, chat_type) = match res {
+        Ok(res) => res,
+        Err(err) => {
+            info!(context, "No message found for NDN: {}", err);
+            return;
+        }
+    };
+
+    let contact_id = Contact::lookup_id_by_addr(context, addr, Origin::Unknown).await;
+    if contact_id <= DC_CONTACT_ID_LAST_SPECIAL
+        || !chat::is_contact_in_chat(context, chat_id, contact_id).await
+    {
+        warn!(
+            context,
+            "Ignoring NDN for {}, not a recipient of message {}", addr, msg_id
+        );
+        return;
+    }
+    set_recipient_state(
+        context,
+        msg_id,
+        contact_id,
+        MessageState::OutFailed,
+        Some(error),
+    )
+    .await
+    .unwrap_or_else(|err| {
+        warn!(context, "failed to store delivery state: {}", err);
+    });
+
+    if chat_type == Chattype::Single {
+        set_msg_failed(context, msg_id, SendError::RecipientRefused, Some(error)).await;
+    } else if let Ok(msg) = Message::load_from_db(context, msg_id).await {
+        warn!(
+            context,
+            "Message {} not delivered to {}: {}", msg_id, addr, error
+        );
+        context.emit_event(Event::MsgsChanged {
+            chat_id: msg.chat_id,
+            msg_id,
+        });
+    }
+}
+
 /// returns Some if an event should be send
 pub async fn mdn_from_ext(
     context: &Context,
@@ -1339,6 +2595,11 @@ pub async fn mdn_from_ext(
                     .await
                            .unwrap_or_default(); // TODO: better error handling
             }
+            set_recipient_state(context, msg_id, from_id, MessageState::OutMdnRcvd, None)
+                .await
+                .unwrap_or_else(|err| {
+                    warn!(context, "failed to store delivery state: {}", err);
+                });
 
             // Normal chat? that's quite easy.
             if chat_type == Chattype::Single {
@@ -1566,6 +2827,14 @@ mod tests {
             guess_msgtype_from_suffix(Path::new("foo/bar-sth.mp3")),
             Some((Viewtype::Audio, "audio/mpeg"))
         );
+        assert_eq!(
+            guess_msgtype_from_suffix(Path::new("IMG_0042.HEIC")),
+            Some((Viewtype::Image, "image/heic"))
+        );
+        assert_eq!(
+            guess_msgtype_from_suffix(Path::new("photo.avif")),
+            Some((Viewtype::Image, "image/avif"))
+        );
     }
 
     #[async_std::test]
@@ -1594,6 +2863,549 @@ mod tests {
         assert_eq!(_msg2.get_filemime(), None);
     }
 
+    #[async_std::test]
+    async fn test_calc_dimensions_cached() {
+        let d = test::dummy_context().await;
+        let ctx = &d.ctx;
+
+        let file = ctx.get_blobdir().join("avatar.png");
+        async_std::fs::write(&file, include_bytes!("../test-data/image/avatar64x64.png"))
+            .await
+            .unwrap();
+
+        let mut msg = Message::new(Viewtype::Image);
+        msg.set_file(file.to_str().unwrap(), None);
+        let read_cnt = || DIMENSIONS_READ_CNT.with(|cnt| cnt.get());
+
+        msg.try_calc_and_set_dimensions(ctx).await.unwrap();
+        assert_eq!(read_cnt(), 1);
+        assert_eq!(msg.get_width(), 64);
+        assert_eq!(msg.get_height(), 64);
+        assert!(msg.param.exists(Param::FileSignature));
+
+        // signature matches, the file is not read again
+        msg.try_calc_and_set_dimensions(ctx).await.unwrap();
+        assert_eq!(read_cnt(), 1);
+        assert_eq!(msg.get_width(), 64);
+
+        // the file is replaced shortly after, it is read again
+        async_std::task::sleep(std::time::Duration::from_millis(50)).await;
+        async_std::fs::write(&file, include_bytes!("../test-data/image/avatar64x64.png"))
+            .await
+            .unwrap();
+        msg.try_calc_and_set_dimensions(ctx).await.unwrap();
+        assert_eq!(read_cnt(), 2);
+
+        // explicitly set dimensions are kept
+        let mut msg = Message::new(Viewtype::Image);
+        msg.set_file(file.to_str().unwrap(), None);
+        msg.set_dimension(10, 20);
+        msg.try_calc_and_set_dimensions(ctx).await.unwrap();
+        assert_eq!(read_cnt(), 2);
+        assert_eq!(msg.get_width(), 10);
+        assert_eq!(msg.get_height(), 20);
+    }
+
+    #[async_std::test]
+    async fn test_update_text() {
+        use crate::config::Config;
+
+        let d = test::dummy_context().await;
+        let ctx = &d.ctx;
+        ctx.set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+
+        let chat_id = chat::create_by_contact_id(ctx, DC_CONTACT_ID_SELF)
+            .await
+            .unwrap();
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("before".to_string()));
+        let msg_id = chat::prepare_msg(ctx, chat_id, &mut msg).await.unwrap();
+
+        let mut msg = Message::load_from_db(ctx, msg_id).await.unwrap();
+        msg.update_text(ctx, Some("after".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(msg.get_text(), Some("after".to_string()));
+        let loaded = Message::load_from_db(ctx, msg_id).await.unwrap();
+        assert_eq!(loaded.get_text(), Some("after".to_string()));
+
+        msg.update_text(ctx, None).await.unwrap();
+        let loaded = Message::load_from_db(ctx, msg_id).await.unwrap();
+        assert_eq!(loaded.get_text(), Some("".to_string()));
+        assert_eq!(msg.get_text(), loaded.get_text());
+
+        let mut unsaved = Message::new(Viewtype::Text);
+        assert!(unsaved
+            .update_text(ctx, Some("foo".to_string()))
+            .await
+            .is_err());
+    }
+
+    #[async_std::test]
+    async fn test_get_chat_msgs_after() {
+        use crate::config::Config;
+
+        let d = test::dummy_context().await;
+        let ctx = &d.ctx;
+        ctx.set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(ctx, DC_CONTACT_ID_SELF)
+            .await
+            .unwrap();
+
+        let mut ids = Vec::new();
+        for text in &["one", "two", "three"] {
+            let mut msg = Message::new(Viewtype::Text);
+            msg.set_text(Some(text.to_string()));
+            ids.push(chat::prepare_msg(ctx, chat_id, &mut msg).await.unwrap());
+        }
+
+        let all = get_chat_msgs_after(ctx, chat_id, MsgId::new_unset())
+            .await
+            .unwrap();
+        assert_eq!(all, ids);
+        let delta = get_chat_msgs_after(ctx, chat_id, ids[0]).await.unwrap();
+        assert_eq!(delta, &ids[1..]);
+        let delta = get_chat_msgs_after(ctx, chat_id, ids[2]).await.unwrap();
+        assert!(delta.is_empty());
+
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("four".to_string()));
+        let id4 = chat::prepare_msg(ctx, chat_id, &mut msg).await.unwrap();
+        let delta = get_chat_msgs_after(ctx, chat_id, ids[2]).await.unwrap();
+        assert_eq!(delta, vec![id4]);
+
+        assert!(get_chat_msgs_after(ctx, chat_id, MsgId::new(12345))
+            .await
+            .is_err());
+    }
+
+    #[async_std::test]
+    async fn test_batch_msg_ops() {
+        use crate::config::Config;
+
+        let d = test::dummy_context().await;
+        let ctx = &d.ctx;
+        ctx.set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(ctx, DC_CONTACT_ID_SELF)
+            .await
+            .unwrap();
+        let mut ids = Vec::new();
+        for _ in 0..5 {
+            let mut msg = Message::new(Viewtype::Text);
+            msg.set_text(Some("hi".to_string()));
+            ids.push(chat::prepare_msg(ctx, chat_id, &mut msg).await.unwrap());
+        }
+
+        assert!(star_msgs(ctx, ids.clone(), true).await);
+        for id in &ids {
+            assert!(Message::load_from_db(ctx, *id).await.unwrap().is_starred());
+        }
+
+        for id in &ids {
+            update_msg_state(ctx, *id, MessageState::InFresh).await;
+        }
+        assert!(markseen_msgs_ex(ctx, ids.clone(), false).await);
+        for id in &ids {
+            let msg = Message::load_from_db(ctx, *id).await.unwrap();
+            assert_eq!(msg.get_state(), MessageState::InSeen);
+        }
+
+        for (id, folder) in ids.iter().zip(&["INBOX", "DeltaChat", "DeltaChat"]) {
+            ctx.sql
+                .execute(
+                    "UPDATE msgs SET server_folder=?, server_uid=1 WHERE id=?;",
+                    paramsv![*folder, *id],
+                )
+                .await
+                .unwrap();
+        }
+        delete_msgs(ctx, &ids[..3]).await;
+        for id in &ids[..3] {
+            let msg = Message::load_from_db(ctx, *id).await.unwrap();
+            assert_eq!(msg.chat_id, ChatId::new(DC_CHAT_ID_TRASH));
+        }
+        assert_eq!(chat_id.get_msg_cnt(ctx).await, 2);
+
+        let jobs = job::list(ctx).await.unwrap();
+        let count = |action| jobs.iter().filter(|job| job.action == action).count();
+        assert_eq!(count(Action::MarkseenMsgOnImap), 5);
+        // one deletion job per folder
+        assert_eq!(count(Action::DeleteMsgOnImap), 2);
+    }
+
+    #[test]
+    fn test_fts_query() {
+        assert_eq!(fts_query(""), None);
+        assert_eq!(fts_query("  "), None);
+        assert_eq!(fts_query("foo"), Some("\"foo\"*".to_string()));
+        assert_eq!(
+            fts_query(" foo  OR\"bar "),
+            Some("\"foo\"* \"OR\"\"bar\"*".to_string())
+        );
+    }
+
+    #[async_std::test]
+    async fn test_search_msgs() {
+        use crate::config::Config;
+
+        let d = test::dummy_context().await;
+        let ctx = &d.ctx;
+        ctx.set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(ctx, DC_CONTACT_ID_SELF)
+            .await
+            .unwrap();
+
+        let mut ids = Vec::new();
+        for text in &[
+            "some bananas, an apple and lots of other fruits",
+            "Apples, apples!",
+            "nothing to see here",
+        ] {
+            let mut msg = Message::new(Viewtype::Text);
+            msg.set_text(Some(text.to_string()));
+            ids.push(chat::prepare_msg(ctx, chat_id, &mut msg).await.unwrap());
+        }
+
+        let found = search_msgs(ctx, chat_id, "appl").await.unwrap();
+        assert_eq!(found, vec![ids[1], ids[0]]);
+        let found = search_msgs(ctx, ChatId::new(0), "apple banana")
+            .await
+            .unwrap();
+        assert_eq!(found, vec![ids[0]]);
+        assert!(search_msgs(ctx, chat_id, "").await.unwrap().is_empty());
+        assert!(search_msgs(ctx, chat_id, "\"unbalanced")
+            .await
+            .unwrap()
+            .is_empty());
+
+        // the index follows changes of the text
+        let mut msg = Message::load_from_db(ctx, ids[2]).await.unwrap();
+        msg.update_text(ctx, Some("apple".to_string()))
+            .await
+            .unwrap();
+        let found = search_msgs(ctx, chat_id, "apple").await.unwrap();
+        assert_eq!(found.len(), 3);
+        assert!(found.contains(&ids[2]));
+        assert!(search_msgs(ctx, chat_id, "nothing")
+            .await
+            .unwrap()
+            .is_empty());
+
+        let found = ctx.search_msgs(chat_id, "apple").await;
+        assert_eq!(found, vec![ids[0], ids[1], ids[2]]);
+
+        // the index only matches the beginning of words,
+        // the simple search falls back to finding parts of words
+        assert!(search_msgs(ctx, chat_id, "ple").await.unwrap().is_empty());
+        let found = ctx.search_msgs(chat_id, "ple").await;
+        assert_eq!(found, vec![ids[0], ids[1], ids[2]]);
+        let found = ctx.search_msgs(ChatId::new(0), "anana").await;
+        assert_eq!(found, vec![ids[0]]);
+
+        // messages are also found by the name of the sender
+        let bob = Contact::create(ctx, "Bob", "bob@example.net")
+            .await
+            .unwrap();
+        let bob_chat_id = chat::create_by_contact_id(ctx, bob).await.unwrap();
+        crate::dc_receive_imf::dc_receive_imf(
+            ctx,
+            b"From: bob@example.net\n\
+              To: self@example.com\n\
+              Subject: Chat: hello\n\
+              Message-ID: <search@example.net>\n\
+              Chat-Version: 1.0\n\
+              Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+              \n\
+              hello\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        let found = ctx.search_msgs(ChatId::new(0), "bo").await;
+        assert_eq!(found.len(), 1);
+        assert_eq!(ctx.search_msgs(bob_chat_id, "bo").await, found);
+        assert!(search_msgs(ctx, ChatId::new(0), "bo")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_get_reaction_counts() {
+        let d = test::dummy_context().await;
+        let ctx = &d.ctx;
+        let msg_id = MsgId::new(100);
+        assert!(get_reaction_counts(ctx, msg_id).await.unwrap().is_empty());
+
+        for (contact_id, reaction) in &[
+            (DC_CONTACT_ID_SELF, "👍"),
+            (20, "👍"),
+            (21, "😀"),
+            (22, "👍"),
+            (23, "😀"),
+            (24, "❤"),
+        ] {
+            ctx.sql
+                .execute(
+                    "INSERT INTO reactions (msg_id, contact_id, reaction) VALUES (?,?,?);",
+                    paramsv![msg_id, *contact_id as i32, reaction.to_string()],
+                )
+                .await
+                .unwrap();
+        }
+        ctx.sql
+            .execute(
+                "INSERT INTO reactions (msg_id, contact_id, reaction) VALUES (101,20,'😀');",
+                paramsv![],
+            )
+            .await
+            .unwrap();
+
+        let counts = get_reaction_counts(ctx, msg_id).await.unwrap();
+        assert_eq!(
+            counts,
+            vec![
+                ("👍".to_string(), 3, true),
+                ("😀".to_string(), 2, false),
+                ("❤".to_string(), 1, false),
+            ]
+        );
+
+        assert!(get_reaction_counts(ctx, MsgId::new(0)).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn test_get_read_receipts() {
+        use crate::config::Config;
+
+        let d = test::dummy_context().await;
+        let ctx = &d.ctx;
+        ctx.set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+
+        let chat_id = chat::create_group_chat(ctx, VerifiedStatus::Unverified, "grp")
+            .await
+            .unwrap();
+        let alice = Contact::create(ctx, "Alice", "alice@example.com")
+            .await
+            .unwrap();
+        let bob = Contact::create(ctx, "Bob", "bob@example.com")
+            .await
+            .unwrap();
+        chat::add_contact_to_chat(ctx, chat_id, alice).await;
+        chat::add_contact_to_chat(ctx, chat_id, bob).await;
+
+        let msg_id = chat::send_text_msg(ctx, chat_id, "hi".to_string())
+            .await
+            .unwrap();
+        assert!(msg_id.get_read_receipts(ctx).await.unwrap().is_empty());
+        assert!(msg_id.get_delivered_to(ctx).await.unwrap().is_empty());
+
+        // the SMTP job records the recipients the message was sent to
+        update_msg_state(ctx, msg_id, MessageState::OutDelivered).await;
+        set_delivered_to_addrs(
+            ctx,
+            msg_id,
+            &[
+                "alice@example.com".to_string(),
+                "bob@example.com".to_string(),
+            ],
+        )
+        .await;
+        assert_eq!(
+            msg_id.get_delivered_to(ctx).await.unwrap(),
+            vec![alice, bob]
+        );
+
+        // members added later did not get the message
+        let claire = Contact::create(ctx, "Claire", "claire@example.com")
+            .await
+            .unwrap();
+        chat::add_contact_to_chat(ctx, chat_id, claire).await;
+        assert_eq!(
+            msg_id.get_delivered_to(ctx).await.unwrap(),
+            vec![alice, bob]
+        );
+
+        for (contact_id, timestamp) in &[(bob, 2000), (alice, 1000)] {
+            ctx.sql
+                .execute(
+                    "INSERT INTO msgs_mdns (msg_id, contact_id, timestamp_sent) VALUES (?,?,?);",
+                    paramsv![msg_id, *contact_id, *timestamp],
+                )
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            msg_id.get_read_receipts(ctx).await.unwrap(),
+            vec![(alice, 1000), (bob, 2000)]
+        );
+
+        let mut delivered_to = msg_id.get_delivered_to(ctx).await.unwrap();
+        delivered_to.sort();
+        assert_eq!(delivered_to, vec![alice, bob]);
+    }
+
+    #[async_std::test]
+    async fn test_get_recipient_states() {
+        use crate::config::Config;
+
+        let d = test::dummy_context().await;
+        let ctx = &d.ctx;
+        ctx.set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+
+        let chat_id = chat::create_group_chat(ctx, VerifiedStatus::Unverified, "grp")
+            .await
+            .unwrap();
+        let alice = Contact::create(ctx, "Alice", "alice@example.com")
+            .await
+            .unwrap();
+        let bob = Contact::create(ctx, "Bob", "bob@example.com")
+            .await
+            .unwrap();
+        let claire = Contact::create(ctx, "Claire", "claire@example.com")
+            .await
+            .unwrap();
+        for contact_id in &[alice, bob, claire] {
+            chat::add_contact_to_chat(ctx, chat_id, *contact_id).await;
+        }
+
+        let msg_id = chat::send_text_msg(ctx, chat_id, "hi".to_string())
+            .await
+            .unwrap();
+        let msg = Message::load_from_db(ctx, msg_id).await.unwrap();
+        update_msg_state(ctx, msg_id, MessageState::OutDelivered).await;
+        set_delivered_to_addrs(
+            ctx,
+            msg_id,
+            &[
+                "self@example.com".to_string(),
+                "alice@example.com".to_string(),
+                "bob@example.com".to_string(),
+            ],
+        )
+        .await;
+        ndn_from_ext(ctx, &msg.rfc724_mid, "bob@example.com", "550 user unknown").await;
+        ndn_from_ext(ctx, &msg.rfc724_mid, "mallory@example.com", "550").await;
+        assert!(mdn_from_ext(ctx, alice, &msg.rfc724_mid, 1000)
+            .await
+            .is_some());
+
+        let states = msg_id.get_recipient_states(ctx).await.unwrap();
+        let state_of = |contact_id| {
+            states
+                .iter()
+                .find(|state| state.contact_id == contact_id)
+                .unwrap()
+        };
+        assert_eq!(states.len(), 3);
+        assert_eq!(state_of(alice).state, MessageState::OutMdnRcvd);
+        assert_eq!(state_of(bob).state, MessageState::OutFailed);
+        assert_eq!(state_of(bob).error, Some("550 user unknown".to_string()));
+        assert_eq!(state_of(claire).state, MessageState::OutDelivered);
+
+        // the group message did not fail as a whole
+        let msg = Message::load_from_db(ctx, msg_id).await.unwrap();
+        assert_ne!(msg.get_state(), MessageState::OutFailed);
+    }
+
+    #[async_std::test]
+    async fn test_get_info_struct() {
+        use crate::config::Config;
+
+        let d = test::dummy_context().await;
+        let ctx = &d.ctx;
+        ctx.set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+
+        let alice = Contact::create(ctx, "Alice", "alice@example.com")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(ctx, alice).await.unwrap();
+        let msg_id = chat::send_text_msg(ctx, chat_id, "hi alice".to_string())
+            .await
+            .unwrap();
+        let msg = Message::load_from_db(ctx, msg_id).await.unwrap();
+        update_msg_state(ctx, msg_id, MessageState::OutDelivered).await;
+        assert!(mdn_from_ext(ctx, alice, &msg.rfc724_mid, 1000)
+            .await
+            .is_some());
+
+        let info = msg_id.get_info_struct(ctx).await.unwrap();
+        assert_eq!(info.msg_id, msg_id);
+        assert_eq!(info.from_id, DC_CONTACT_ID_SELF);
+        assert_eq!(info.timestamp_received, None);
+        assert!(!info.is_info);
+        assert_eq!(info.state, MessageState::OutMdnRcvd);
+        assert_eq!(info.encryption, MsgEncryption::Unencrypted);
+        assert_eq!(info.viewtype, Viewtype::Text);
+        assert_eq!(info.raw_text, "hi alice");
+        assert_eq!(info.read_receipts.len(), 1);
+        assert_eq!(info.read_receipts[0].contact_id, alice);
+        assert_eq!(info.read_receipts[0].timestamp, 1000);
+        assert!(info.read_receipts[0].name.contains("alice@example.com"));
+        assert_eq!(info.recipient_states.len(), 1);
+        assert_eq!(info.recipient_states[0].contact_id, alice);
+
+        let text = get_msg_info(ctx, msg_id).await;
+        assert!(text.starts_with("Sent: "));
+        assert!(text.contains("\nRead: "));
+        assert!(text.contains("\nhi alice\n"));
+        assert!(text.contains(&format!("Message-ID: {}", info.rfc724_mid)));
+        assert!(!text.contains("Received: "));
+
+        assert!(MsgId::new(12345).get_info_struct(ctx).await.is_err());
+        assert_eq!(get_msg_info(ctx, MsgId::new(12345)).await, "");
+    }
+
+    #[async_std::test]
+    async fn test_get_list_item_group() {
+        let d = test::dummy_context().await;
+        let ctx = &d.ctx;
+
+        let chat_id = chat::create_group_chat(ctx, VerifiedStatus::Unverified, "grp")
+            .await
+            .unwrap();
+        let bob = Contact::create(ctx, "Bob Smith", "bob@example.com")
+            .await
+            .unwrap();
+        chat::add_contact_to_chat(ctx, chat_id, bob).await;
+
+        let mut msg = Message::new(Viewtype::Text);
+        msg.chat_id = chat_id;
+        msg.from_id = bob;
+        msg.state = MessageState::InFresh;
+        msg.timestamp_sort = 1234;
+        msg.set_text(Some("hello   group".to_string()));
+
+        let item = msg.get_list_item(ctx, None).await;
+        assert_eq!(
+            item,
+            ListItem {
+                summary_text: "hello group".to_string(),
+                state: LotState::MsgInFresh,
+                timestamp: 1234,
+                from_name: Some("Bob".to_string()),
+                has_file: false,
+                fresh_cnt_hint: 0,
+            }
+        );
+    }
+
     #[async_std::test]
     async fn test_get_summarytext_by_raw() {
         let d = test::dummy_context().await;
@@ -1,7 +1,9 @@
 use chrono::TimeZone;
 use lettre_email::{mime, Address, Header, MimeMultipartType, PartBuilder};
+use num_traits::FromPrimitive;
 
 use crate::blob::BlobObject;
+use crate::calendar;
 use crate::chat::{self, Chat};
 use crate::config::Config;
 use crate::constants::*;
@@ -16,6 +18,7 @@ use crate::mimeparser::SystemMessage;
 use crate::param::*;
 use crate::peerstate::{Peerstate, PeerstateVerifiedStatus};
 use crate::simplify::escape_message_footer_marks;
+use crate::smime::{self, SmimeState};
 use crate::stock::StockMessage;
 
 // attachments of 25 mb brutto should work on the majority of providers
@@ -52,6 +55,9 @@ pub struct MimeFactory<'a, 'b> {
     attach_selfavatar: bool,
 }
 
+/// Subject of encrypted messages, the real subject is in the encrypted part.
+pub(crate) const SUBJECT_PLACEHOLDER: &str = "...";
+
 /// Result of rendering a message, ready to be submitted to a send job.
 #[derive(Debug, Clone)]
 pub struct RenderedEmail {
@@ -61,6 +67,9 @@ pub struct RenderedEmail {
     pub is_gossiped: bool,
     pub last_added_location_id: u32,
 
+    /// S/MIME protection of messages that are not encrypted with OpenPGP.
+    pub smime: SmimeState,
+
     /// Message ID (Message in the sense of Email)
     pub rfc724_mid: String,
 }
@@ -86,6 +95,11 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
 
         if chat.is_self_talk() {
             recipients.push((from_displayname.to_string(), from_addr.to_string()));
+        } else if chat.typ == Chattype::Mailinglist {
+            // answers to mailing lists go to the posting address, read receipts are not requested
+            if let Some(list_post) = chat.param.get(Param::ListPost) {
+                recipients.push(("".to_string(), list_post.to_string()));
+            }
         } else {
             context
                 .sql
@@ -116,27 +130,34 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
 
             if command != SystemMessage::AutocryptSetupMessage
                 && command != SystemMessage::SecurejoinMessage
-                && context.get_config_bool(Config::MdnsEnabled).await
+                && command != SystemMessage::Typing
+                && chat::mdns_enabled(context, msg.chat_id).await
             {
                 req_mdn = true;
             }
         }
-        let (in_reply_to, references) = context
-            .sql
-            .query_row(
-                "SELECT mime_in_reply_to, mime_references FROM msgs WHERE id=?",
-                paramsv![msg.id],
-                |row| {
-                    let in_reply_to: String = row.get(0)?;
-                    let references: String = row.get(1)?;
-
-                    Ok((
-                        render_rfc724_mid_list(&in_reply_to),
-                        render_rfc724_mid_list(&references),
-                    ))
-                },
-            )
-            .await?;
+        // messages not stored in the database, eg. typing notifications,
+        // do not refer to other messages
+        let (in_reply_to, references) = if msg.id.is_unset() {
+            (String::new(), String::new())
+        } else {
+            context
+                .sql
+                .query_row(
+                    "SELECT mime_in_reply_to, mime_references FROM msgs WHERE id=?",
+                    paramsv![msg.id],
+                    |row| {
+                        let in_reply_to: String = row.get(0)?;
+                        let references: String = row.get(1)?;
+
+                        Ok((
+                            render_rfc724_mid_list(&in_reply_to),
+                            render_rfc724_mid_list(&references),
+                        ))
+                    },
+                )
+                .await?
+        };
 
         let default_str = context
             .stock_str(StockMessage::StatusLine)
@@ -293,6 +314,24 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
     async fn should_do_gossip(&self) -> bool {
         match &self.loaded {
             Loaded::Message { chat } => {
+                // gossip would reveal the members of a broadcast list to each other
+                if chat.typ == Chattype::Broadcast {
+                    return false;
+                }
+
+                let policy =
+                    GossipPolicy::from_i32(self.context.get_config_int(Config::GossipPolicy).await)
+                        .unwrap_or_default();
+                match policy {
+                    GossipPolicy::Always => {}
+                    GossipPolicy::VerifiedOnly => {
+                        if !chat.is_verified() {
+                            return false;
+                        }
+                    }
+                    GossipPolicy::Never => return false,
+                }
+
                 // beside key- and member-changes, force re-gossip every 48 hours
                 let gossiped_timestamp = chat.get_gossiped_timestamp(self.context).await;
                 if time() > gossiped_timestamp + (2 * 24 * 60 * 60) {
@@ -409,24 +448,24 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
             .collect()
     }
 
-    pub async fn render(mut self) -> Result<RenderedEmail, Error> {
-        // Headers that are encrypted
-        // - Chat-*, except Chat-Version
-        // - Secure-Join*
-        // - Subject
-        let mut protected_headers: Vec<Header> = Vec::new();
-
-        // All other headers
-        let mut unprotected_headers: Vec<Header> = Vec::new();
-
-        let from = Address::new_mailbox_with_name(
-            self.from_displayname.to_string(),
-            self.from_addr.clone(),
-        );
+    /// Returns the address of the sender, with the display name if `with_name` is set.
+    fn render_from(&self, with_name: bool) -> Address {
+        if with_name {
+            Address::new_mailbox_with_name(
+                self.from_displayname.to_string(),
+                self.from_addr.clone(),
+            )
+        } else {
+            Address::new_mailbox(self.from_addr.clone())
+        }
+    }
 
+    /// Returns the addresses of the recipients,
+    /// with the display names if `with_names` is set.
+    fn render_to(&self, with_names: bool) -> Vec<Address> {
         let mut to = Vec::new();
         for (name, addr) in self.recipients.iter() {
-            if name.is_empty() {
+            if name.is_empty() || !with_names {
                 to.push(Address::new_mailbox(addr.clone()));
             } else {
                 to.push(Address::new_mailbox_with_name(
@@ -435,17 +474,41 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
                 ));
             }
         }
-
         if to.is_empty() {
-            to.push(from.clone());
+            to.push(self.render_from(with_names));
         }
+        to
+    }
+
+    pub async fn render(mut self) -> Result<RenderedEmail, Error> {
+        // Headers that are encrypted
+        // - Chat-*, except Chat-Version
+        // - Secure-Join*
+        // - Subject
+        let mut protected_headers: Vec<Header> = Vec::new();
+
+        // All other headers
+        let mut unprotected_headers: Vec<Header> = Vec::new();
+
+        // Copies of the headers needed for transport that are added to the
+        // encrypted part, so that the receiver can restore them if they are
+        // changed on the way
+        // - From, To, Date, Message-ID, In-Reply-To, References
+        //
+        // Outside of encrypted messages, From and To are minimized
+        // to the addresses, the display names are only in the encrypted part.
+        let mut protected_copies: Vec<Header> = Vec::new();
 
         if !self.references.is_empty() {
-            unprotected_headers.push(Header::new("References".into(), self.references.clone()));
+            let header = Header::new("References".into(), self.references.clone());
+            protected_copies.push(header.clone());
+            unprotected_headers.push(header);
         }
 
         if !self.in_reply_to.is_empty() {
-            unprotected_headers.push(Header::new("In-Reply-To".into(), self.in_reply_to.clone()));
+            let header = Header::new("In-Reply-To".into(), self.in_reply_to.clone());
+            protected_copies.push(header.clone());
+            unprotected_headers.push(header);
         }
 
         let date = chrono::Utc
@@ -453,7 +516,9 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
             .unwrap()
             .to_rfc2822();
 
-        unprotected_headers.push(Header::new("Date".into(), date));
+        let header = Header::new("Date".into(), date);
+        protected_copies.push(header.clone());
+        unprotected_headers.push(header);
 
         let os_name = &self.context.os_name;
         let os_part = os_name
@@ -465,8 +530,9 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
         // Add a X-Mailer header.
         // This is only informational for debugging and may be removed in the release.
         // We do not rely on this header as it may be removed by MTAs.
+        // It is protected as the version of the client is not needed for transport.
 
-        unprotected_headers.push(Header::new(
+        protected_headers.push(Header::new(
             "X-Mailer".into(),
             format!("Delta Chat Core {}{}", version, os_part),
         ));
@@ -530,15 +596,31 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
         // but they also strip the Autocrypt header so we probably
         // never get a chance to tunnel our protected headers in a
         // cryptographic payload.
-        unprotected_headers.push(Header::new(
-            "Message-ID".into(),
-            render_rfc724_mid(&rfc724_mid),
-        ));
+        let header = Header::new("Message-ID".into(), render_rfc724_mid(&rfc724_mid));
+        protected_copies.push(header.clone());
+        unprotected_headers.push(header);
 
-        unprotected_headers.push(Header::new_with_value("To".into(), to).unwrap());
+        let is_broadcast = match &self.loaded {
+            Loaded::Message { chat } => chat.typ == Chattype::Broadcast,
+            Loaded::MDN { .. } => false,
+        };
+        if is_broadcast {
+            // the members of a broadcast list must not learn about each other,
+            // they only appear in the envelope
+            unprotected_headers.push(Header::new("To".into(), "hidden-recipients:;".into()));
+        } else {
+            let to = self.render_to(true);
+            protected_copies.push(Header::new_with_value("To".into(), to).unwrap());
+            let to = self.render_to(!is_encrypted);
+            unprotected_headers.push(Header::new_with_value("To".into(), to).unwrap());
+        }
+        let from = self.render_from(true);
+        protected_copies.push(Header::new_with_value("From".into(), vec![from]).unwrap());
+        let from = self.render_from(!is_encrypted);
         unprotected_headers.push(Header::new_with_value("From".into(), vec![from]).unwrap());
 
         let mut is_gossiped = false;
+        let mut smime_state = SmimeState::empty();
 
         let outer_message = if is_encrypted {
             // Add gossip headers in chats with multiple recipients
@@ -555,7 +637,7 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
             }
 
             // Store protected headers in the inner message.
-            for header in protected_headers.into_iter() {
+            for header in protected_headers.into_iter().chain(protected_copies) {
                 message = message.header(header);
             }
 
@@ -616,10 +698,25 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
                         .body(encrypted)
                         .build(),
                 )
-                .header(("Subject".to_string(), "...".to_string()));
+                .header(("Subject".to_string(), SUBJECT_PLACEHOLDER.to_string()));
 
             outer_message
         } else {
+            if let Loaded::Message { .. } = self.loaded {
+                let recipients: Vec<String> = self
+                    .recipients
+                    .iter()
+                    .map(|(_, addr)| addr.clone())
+                    .collect();
+                let content = message.clone().build().as_string();
+                if let Some((part, state)) =
+                    smime::render(self.context, &content, &recipients).await
+                {
+                    message = part;
+                    smime_state = state;
+                }
+            }
+
             // In the unencrypted case, we add all headers to the outer message.
             for header in protected_headers.into_iter() {
                 message = message.header(header);
@@ -641,6 +738,7 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
             is_encrypted,
             is_gossiped,
             last_added_location_id,
+            smime: smime_state,
             rfc724_mid,
         })
     }
@@ -649,7 +747,12 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
         let latitude = self.msg.param.get_float(Param::SetLatitude)?;
         let longitude = self.msg.param.get_float(Param::SetLongitude)?;
 
-        let kml_file = location::get_message_kml(self.msg.timestamp_sort, latitude, longitude);
+        let kml_file = location::get_message_kml(
+            self.msg.timestamp_sort,
+            latitude,
+            longitude,
+            self.msg.get_poi().as_ref(),
+        );
         let part = PartBuilder::new()
             .content_type(
                 &"application/vnd.google-earth.kml+xml"
@@ -664,6 +767,30 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
         Some(part)
     }
 
+    /// Returns the iTIP reply part of a response to an invitation.
+    fn get_calendar_reply_part(&self) -> Option<PartBuilder> {
+        if self.msg.viewtype == Viewtype::Calendar {
+            return None;
+        }
+        let rsvp = self.msg.get_invite_response()?;
+        let event = self.msg.get_calendar_event()?;
+        let ics = calendar::build_reply(
+            &event,
+            rsvp,
+            &self.from_addr,
+            &self.from_displayname,
+            self.msg.timestamp_sort,
+        );
+        let part = PartBuilder::new()
+            .content_type(
+                &"text/calendar; method=REPLY; charset=utf-8"
+                    .parse::<mime::Mime>()
+                    .unwrap(),
+            )
+            .body(ics);
+        Some(part)
+    }
+
     async fn get_location_kml_part(&mut self) -> Result<PartBuilder, Error> {
         let (kml_content, last_added_location_id) =
             location::get_kml(self.context, self.msg.chat_id).await?;
@@ -743,6 +870,21 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
                         ));
                     }
                 }
+                SystemMessage::GroupAdminAdded | SystemMessage::GroupAdminRemoved => {
+                    protected_headers.push(Header::new(
+                        "Chat-Content".to_string(),
+                        "group-admin-changed".to_string(),
+                    ));
+                    let email = self.msg.param.get(Param::Arg).unwrap_or_default();
+                    protected_headers.push(Header::new(
+                        if command == SystemMessage::GroupAdminAdded {
+                            "Chat-Group-Admin-Added".into()
+                        } else {
+                            "Chat-Group-Admin-Removed".into()
+                        },
+                        email.into(),
+                    ));
+                }
                 SystemMessage::GroupNameChanged => {
                     let value_to_add = self.msg.param.get(Param::Arg).unwrap_or_default();
 
@@ -765,15 +907,82 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
                 }
                 _ => {}
             }
+
+            // new members and the members of a just promoted group
+            // need to learn who the admins are
+            if command == SystemMessage::MemberAddedToGroup
+                || self
+                    .msg
+                    .param
+                    .get_bool(Param::AttachGroupImage)
+                    .unwrap_or_default()
+            {
+                let mut admins = Vec::new();
+                for contact_id in chat::get_group_admins(context, chat.id).await {
+                    if contact_id == DC_CONTACT_ID_SELF {
+                        admins.push(self.from_addr.clone());
+                    } else if let Ok(contact) = Contact::get_by_id(context, contact_id).await {
+                        admins.push(contact.get_addr().to_string());
+                    }
+                }
+                if !admins.is_empty() {
+                    protected_headers.push(Header::new(
+                        "Chat-Group-Admin-Added".into(),
+                        admins.join(", "),
+                    ));
+                }
+            }
+        }
+
+        if self.msg.ephemeral_timer != 0 || command == SystemMessage::EphemeralTimerChanged {
+            protected_headers.push(Header::new(
+                "Chat-Ephemeral-Timer".into(),
+                self.msg.ephemeral_timer.to_string(),
+            ));
         }
 
         match command {
+            SystemMessage::EphemeralTimerChanged => {
+                protected_headers.push(Header::new(
+                    "Chat-Content".into(),
+                    "ephemeral-timer-changed".into(),
+                ));
+            }
             SystemMessage::LocationStreamingEnabled => {
                 protected_headers.push(Header::new(
                     "Chat-Content".into(),
                     "location-streaming-enabled".into(),
                 ));
             }
+            SystemMessage::MessageEdit => {
+                let rfc724_mid = self.msg.param.get(Param::Arg).unwrap_or_default();
+                protected_headers.push(Header::new(
+                    "Chat-Edit".into(),
+                    render_rfc724_mid(rfc724_mid),
+                ));
+            }
+            SystemMessage::AppUpdate => {
+                let rfc724_mid = self.msg.param.get(Param::Arg).unwrap_or_default();
+                protected_headers.push(Header::new(
+                    "Chat-App-Update".into(),
+                    render_rfc724_mid(rfc724_mid),
+                ));
+            }
+            SystemMessage::PollVote => {
+                let vote = self.msg.param.get(Param::Arg2).unwrap_or_default();
+                protected_headers.push(Header::new("Chat-Poll-Vote".into(), vote.to_string()));
+            }
+            SystemMessage::Typing => {
+                let typing = self.msg.param.get_int(Param::Arg).unwrap_or_default();
+                protected_headers.push(Header::new("Chat-Typing".into(), typing.to_string()));
+            }
+            SystemMessage::MultiDeviceSync => {
+                protected_headers.push(Header::new("Chat-Sync".into(), "v1".into()));
+            }
+            SystemMessage::Reaction => {
+                let reaction = self.msg.text.as_deref().unwrap_or_default();
+                protected_headers.push(Header::new("Chat-Reaction".into(), encode_words(reaction)));
+            }
             SystemMessage::AutocryptSetupMessage => {
                 unprotected_headers
                     .push(Header::new("Autocrypt-Setup-Message".into(), "v1".into()));
@@ -837,6 +1046,19 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
             protected_headers.push(Header::new("Chat-Content".into(), "sticker".into()));
         }
 
+        if self.msg.viewtype == Viewtype::App {
+            protected_headers.push(Header::new("Chat-Content".into(), "app".into()));
+        }
+
+        if self.msg.viewtype == Viewtype::Poll {
+            let options = self.msg.param.get(Param::PollOptions).unwrap_or_default();
+            protected_headers.push(Header::new("Chat-Content".into(), "poll".into()));
+            protected_headers.push(Header::new(
+                "Chat-Poll-Options".into(),
+                encode_words(options),
+            ));
+        }
+
         if self.msg.viewtype == Viewtype::Voice
             || self.msg.viewtype == Viewtype::Audio
             || self.msg.viewtype == Viewtype::Video
@@ -858,12 +1080,19 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
 
         let afwd_email = self.msg.param.exists(Param::Forwarded);
         let fwdhint = if afwd_email {
-            Some(
+            // the original author is only named if the user chose so
+            let author = self
+                .msg
+                .param
+                .get(Param::ForwardedFrom)
+                .map(|name| name.replace(|c| c == '\r' || c == '\n', " "))
+                .unwrap_or_else(|| "Delta Chat".to_string());
+            Some(format!(
                 "---------- Forwarded message ----------\r\n\
-                 From: Delta Chat\r\n\
-                 \r\n"
-                    .to_string(),
-            )
+                 From: {}\r\n\
+                 \r\n",
+                author
+            ))
         } else {
             None
         };
@@ -877,10 +1106,19 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
             }
         };
 
+        let quote = self.msg.param.get(Param::Quote).map(|quote| {
+            let quote: String = quote
+                .split('\n')
+                .map(|line| format!("> {}\r\n", line))
+                .collect();
+            quote + "\r\n"
+        });
+
         let footer = &self.selfstatus;
         let message_text = format!(
-            "{}{}{}{}{}",
+            "{}{}{}{}{}{}",
             fwdhint.unwrap_or_default(),
+            escape_message_footer_marks(&quote.unwrap_or_default()),
             escape_message_footer_marks(final_text),
             if !final_text.is_empty() && !footer.is_empty() {
                 "\r\n\r\n"
@@ -910,6 +1148,19 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
             }
         }
 
+        if self.msg.viewtype == Viewtype::Video {
+            if let Some(path) = self.msg.param.get(Param::Thumbnail) {
+                match build_named_file(context, path, "thumbnail").await {
+                    Ok((part, filename)) => {
+                        parts.push(part);
+                        protected_headers
+                            .push(Header::new("Chat-Video-Thumbnail".into(), filename));
+                    }
+                    Err(err) => warn!(context, "mimefactory: cannot attach thumbnail: {}", err),
+                }
+            }
+        }
+
         if let Some(meta_part) = meta_part {
             parts.push(meta_part);
         }
@@ -918,7 +1169,14 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
             parts.push(msg_kml_part);
         }
 
-        if location::is_sending_locations_to_chat(context, self.msg.chat_id).await {
+        if let Some(calendar_part) = self.get_calendar_reply_part() {
+            parts.push(calendar_part);
+        }
+
+        if command != SystemMessage::Typing
+            && command != SystemMessage::MultiDeviceSync
+            && location::is_sending_locations_to_chat(context, self.msg.chat_id).await
+        {
             match self.get_location_kml_part().await {
                 Ok(part) => parts.push(part),
                 Err(err) => {
@@ -929,7 +1187,7 @@ impl<'a, 'b> MimeFactory<'a, 'b> {
 
         if self.attach_selfavatar {
             match context.get_config(Config::Selfavatar).await {
-                Some(path) => match build_selfavatar_file(context, &path) {
+                Some(path) => match build_named_file(context, &path, "avatar").await {
                     Ok((part, filename)) => {
                         parts.push(part);
                         protected_headers.push(Header::new("Chat-User-Avatar".into(), filename))
@@ -1115,7 +1373,7 @@ async fn build_body_file(
         format!("attachment; filename=\"{}\"", &filename_to_send)
     };
 
-    let body = std::fs::read(blob.to_abs_path())?;
+    let body = dc_read_file(context, blob.to_abs_path()).await?;
     let encoded_body = wrapped_base64_encode(&body);
 
     let mail = PartBuilder::new()
@@ -1127,17 +1385,106 @@ async fn build_body_file(
     Ok((mail, filename_to_send))
 }
 
-fn build_selfavatar_file(context: &Context, path: &str) -> Result<(PartBuilder, String), Error> {
+/// Reconstructs an unencrypted RFC 822 message from a stored message,
+/// eg. to export a message whose original was not saved.
+///
+/// The message contains the text and the attachment of the message,
+/// not the headers used by Delta Chat to exchange metadata.
+pub(crate) async fn render_eml(context: &Context, msg: &Message) -> Result<Vec<u8>, Error> {
+    fn mailbox(name: &str, addr: &str) -> Address {
+        if name.is_empty() {
+            Address::new_mailbox(addr.to_string())
+        } else {
+            Address::new_mailbox_with_name(name.to_string(), addr.to_string())
+        }
+    }
+
+    let chat = Chat::load_from_db(context, msg.chat_id).await?;
+    let self_addr = context
+        .get_config(Config::ConfiguredAddr)
+        .await
+        .unwrap_or_default();
+    let self_name = context
+        .get_config(Config::Displayname)
+        .await
+        .unwrap_or_default();
+
+    let mut to = Vec::new();
+    let from = if msg.from_id == DC_CONTACT_ID_SELF {
+        for contact_id in chat::get_chat_contacts(context, msg.chat_id).await {
+            if contact_id > DC_CONTACT_ID_LAST_SPECIAL {
+                let contact = Contact::load_from_db(context, contact_id).await?;
+                to.push(mailbox(contact.get_authname(), contact.get_addr()));
+            }
+        }
+        mailbox(&self_name, &self_addr)
+    } else {
+        let contact = Contact::load_from_db(context, msg.from_id).await?;
+        mailbox(contact.get_authname(), contact.get_addr())
+    };
+    if to.is_empty() {
+        to.push(mailbox(&self_name, &self_addr));
+    }
+
+    let text = msg.text.clone().unwrap_or_default();
+    let subject = if chat.typ == Chattype::Group || chat.typ == Chattype::VerifiedGroup {
+        chat.name.clone()
+    } else {
+        format!("Chat: {}", dc_truncate(&text, 32))
+    };
+    let timestamp = if msg.timestamp_sent > 0 {
+        msg.timestamp_sent
+    } else {
+        msg.timestamp_sort
+    };
+    let date = chrono::Utc
+        .from_local_datetime(&chrono::NaiveDateTime::from_timestamp(timestamp, 0))
+        .unwrap()
+        .to_rfc2822();
+
+    let text_part = PartBuilder::new()
+        .content_type(&mime::TEXT_PLAIN_UTF_8)
+        .body(text);
+    let message = if chat::msgtype_has_file(msg.viewtype) && msg.param.exists(Param::File) {
+        let (file_part, _) = build_body_file(context, msg, "").await?;
+        PartBuilder::new()
+            .message_type(MimeMultipartType::Mixed)
+            .child(text_part.build())
+            .child(file_part.build())
+    } else {
+        text_part
+    };
+
+    let message = message
+        .header(Header::new("MIME-Version".into(), "1.0".into()))
+        .header(Header::new(
+            "Message-ID".into(),
+            render_rfc724_mid(&msg.rfc724_mid),
+        ))
+        .header(Header::new("Date".into(), date))
+        .header(Header::new_with_value("From".into(), vec![from]).unwrap())
+        .header(Header::new_with_value("To".into(), to).unwrap())
+        .header(Header::new("Subject".into(), encode_words(&subject)));
+    Ok(message.build().as_string().into_bytes())
+}
+
+/// Builds an attachment sent with a fixed file name, eg. the avatar
+/// or the thumbnail of a video referenced by a header.
+async fn build_named_file(
+    context: &Context,
+    path: &str,
+    base_name: &str,
+) -> Result<(PartBuilder, String), Error> {
     let blob = BlobObject::from_path(context, path)?;
     let filename_to_send = match blob.suffix() {
-        Some(suffix) => format!("avatar.{}", suffix),
-        None => "avatar".to_string(),
+        Some(suffix) => format!("{}.{}", base_name, suffix),
+        None => base_name.to_string(),
     };
     let mimetype = match message::guess_msgtype_from_suffix(blob.as_rel_path()) {
         Some(res) => res.1.parse()?,
         None => mime::APPLICATION_OCTET_STREAM,
     };
-    let body = std::fs::read(blob.to_abs_path())?;
+    let body = dc_read_file(context, blob.to_abs_path()).await?;
     let encoded_body = wrapped_base64_encode(&body);
 
     let part = PartBuilder::new()
@@ -1428,4 +1775,170 @@ mod tests {
             .unwrap();
         mf.subject_str().await
     }
+
+    #[async_std::test]
+    async fn test_render_quote() {
+        use crate::dc_receive_imf::dc_receive_imf;
+        use crate::headerdef::HeaderDef;
+        use crate::mimeparser::MimeMessage;
+        use crate::test_utils::configure_alice_keypair;
+
+        let t = configured_offline_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        dc_receive_imf(
+            &t.ctx,
+            b"From: Bob <bob@example.org>\n\
+              To: alice@example.com\n\
+              Chat-Version: 1.0\n\
+              Subject: Chat: hello\n\
+              Message-ID: <Mr.1111@example.org>\n\
+              Date: Sun, 22 Mar 2020 22:37:55 +0000\n\
+              \n\
+              hello\n\
+              world\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        let (_, _, quoted_id) = message::rfc724_mid_exists(&t.ctx, "Mr.1111@example.org")
+            .await
+            .unwrap()
+            .unwrap();
+        let quoted = Message::load_from_db(&t.ctx, quoted_id).await.unwrap();
+        let chat_id = chat::create_by_msg_id(&t.ctx, quoted_id).await.unwrap();
+
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("reply".to_string()));
+        msg.set_quote(&quoted);
+        chat::prepare_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+        let rendered = MimeFactory::from_msg(&t.ctx, &msg, false)
+            .await
+            .unwrap()
+            .render()
+            .await
+            .unwrap();
+
+        let parsed = MimeMessage::from_bytes(&t.ctx, &rendered.message)
+            .await
+            .unwrap();
+        assert_eq!(
+            parsed.get(HeaderDef::InReplyTo).unwrap(),
+            "<Mr.1111@example.org>"
+        );
+        let part = &parsed.parts[0];
+        assert_eq!(part.msg, "reply");
+        assert_eq!(part.param.get(Param::Quote), Some("hello\nworld"));
+        assert_eq!(
+            part.param.get(Param::QuotedMsgId),
+            Some("Mr.1111@example.org")
+        );
+    }
+
+    #[async_std::test]
+    async fn test_render_broadcast() {
+        use crate::test_utils::configure_alice_keypair;
+
+        let t = configured_offline_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        let chat_id = chat::create_broadcast_list(&t.ctx).await.unwrap();
+        for addr in &["bob@example.net", "claire@example.net"] {
+            let contact_id = Contact::create(&t.ctx, "", addr).await.unwrap();
+            chat::add_contact_to_chat(&t.ctx, chat_id, contact_id).await;
+        }
+
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("hi all".to_string()));
+        chat::prepare_msg(&t.ctx, chat_id, &mut msg).await.unwrap();
+        let mf = MimeFactory::from_msg(&t.ctx, &msg, false).await.unwrap();
+        let mut recipients = mf.recipients();
+        recipients.sort();
+        assert_eq!(recipients, vec!["bob@example.net", "claire@example.net"]);
+
+        let rendered = mf.render().await.unwrap();
+        let rendered = String::from_utf8_lossy(&rendered.message);
+        assert!(rendered.contains("To: hidden-recipients:;"));
+        assert!(!rendered.contains("bob@example.net"));
+        assert!(!rendered.contains("claire@example.net"));
+        assert!(!rendered.contains("Chat-Group-ID"));
+    }
+
+    #[async_std::test]
+    async fn test_protected_headers_roundtrip() {
+        use crate::aheader::{Aheader, EncryptPreference};
+        use crate::key;
+        use crate::mimeparser::MimeMessage;
+        use crate::test_utils::{bob_keypair, configure_alice_keypair};
+
+        let alice = configured_offline_context().await;
+        configure_alice_keypair(&alice.ctx).await;
+        alice
+            .ctx
+            .set_config(Config::Displayname, Some("Alice Wonderland"))
+            .await
+            .unwrap();
+        let bob = dummy_context().await;
+        let bob_keypair = bob_keypair();
+        bob.ctx
+            .set_config(Config::ConfiguredAddr, Some("bob@example.net"))
+            .await
+            .unwrap();
+        key::store_self_keypair(&bob.ctx, &bob_keypair, key::KeyPairUse::Default)
+            .await
+            .unwrap();
+
+        let aheader = Aheader::new(
+            "bob@example.net".to_string(),
+            bob_keypair.public,
+            EncryptPreference::Mutual,
+        );
+        Peerstate::from_header(&alice.ctx, &aheader, time())
+            .save_to_db(&alice.ctx.sql, true)
+            .await
+            .unwrap();
+        let contact_id = Contact::create(&alice.ctx, "Bobby", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&alice.ctx, contact_id)
+            .await
+            .unwrap();
+
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("hi".to_string()));
+        chat::prepare_msg(&alice.ctx, chat_id, &mut msg)
+            .await
+            .unwrap();
+        let mf = MimeFactory::from_msg(&alice.ctx, &msg, false)
+            .await
+            .unwrap();
+        let subject = mf.subject_str().await;
+        assert!(subject.contains("Alice Wonderland"));
+        let rendered = mf.render().await.unwrap();
+        assert!(rendered.is_encrypted);
+
+        // the outer headers contain only what is needed for transport
+        let raw = String::from_utf8_lossy(&rendered.message);
+        assert!(raw.contains("Subject: ...\r\n"));
+        assert!(raw.contains("alice@example.com"));
+        assert!(raw.contains("bob@example.net"));
+        assert!(!raw.contains("Alice Wonderland"));
+        assert!(!raw.contains("Bobby"));
+        assert!(!raw.contains("X-Mailer"));
+
+        // the receiver restores the protected headers
+        let parsed = MimeMessage::from_bytes(&bob.ctx, &rendered.message)
+            .await
+            .unwrap();
+        assert!(parsed.was_encrypted());
+        assert_eq!(parsed.get_subject(), Some(subject));
+        assert_eq!(parsed.from[0].addr, "alice@example.com");
+        assert_eq!(
+            parsed.from[0].display_name,
+            Some("Alice Wonderland".to_string())
+        );
+        assert_eq!(parsed.recipients[0].addr, "bob@example.net");
+        assert_eq!(parsed.recipients[0].display_name, Some("Bobby".to_string()));
+        assert_eq!(parsed.parts[0].msg, "hi");
+    }
 }
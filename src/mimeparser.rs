@@ -2,17 +2,22 @@ use std::collections::{HashMap, HashSet};
 use std::future::Future;
 use std::pin::Pin;
 
+use bitflags::bitflags;
 use deltachat_derive::{FromSql, ToSql};
 use lettre_email::mime::{self, Mime};
 use mailparse::{addrparse_header, DispositionType, MailHeader, MailHeaderMap, SingleInfo};
+use num_traits::FromPrimitive;
 
 use crate::aheader::Aheader;
 use crate::blob::BlobObject;
-use crate::constants::Viewtype;
+use crate::calendar;
+use crate::config::Config;
+use crate::constants::{GossipAccept, Viewtype};
 use crate::contact::*;
 use crate::context::Context;
 use crate::dc_tools::*;
-use crate::dehtml::dehtml;
+use crate::dehtml::{dehtml, sanitize_html};
+use crate::download::format_size;
 use crate::e2ee;
 use crate::error::{bail, Result};
 use crate::events::Event;
@@ -20,10 +25,13 @@ use crate::headerdef::{HeaderDef, HeaderDefMap};
 use crate::key::Fingerprint;
 use crate::location;
 use crate::message;
+use crate::mimefactory::SUBJECT_PLACEHOLDER;
 use crate::param::*;
 use crate::peerstate::Peerstate;
+use crate::poll;
 use crate::securejoin::handle_degrade_event;
 use crate::simplify::*;
+use crate::smime::{self, SmimeState, SmimeType};
 use crate::stock::StockMessage;
 
 /// A parsed MIME message.
@@ -48,12 +56,23 @@ pub struct MimeMessage {
     pub signatures: HashSet<Fingerprint>,
     pub gossipped_addr: HashSet<String>,
     pub is_forwarded: bool,
+
+    /// The original author of a forwarded message, if named.
+    pub(crate) forwarded_from: Option<String>,
     pub is_system_message: SystemMessage,
     pub location_kml: Option<location::Kml>,
     pub message_kml: Option<location::Kml>,
     pub(crate) user_avatar: Option<AvatarAction>,
     pub(crate) group_avatar: Option<AvatarAction>,
     pub(crate) reports: Vec<Report>,
+    pub(crate) delivery_reports: Vec<DeliveryReport>,
+
+    /// Sanitized HTML body of the message, if any.
+    pub(crate) html: Option<String>,
+
+    /// The standard footer of the first text part, if any,
+    /// used as status of the sender.
+    pub(crate) footer: Option<String>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -74,6 +93,15 @@ pub enum SystemMessage {
     SecurejoinMessage = 7,
     LocationStreamingEnabled = 8,
     LocationOnly = 9,
+    EphemeralTimerChanged = 10,
+    Reaction = 11,
+    MessageEdit = 12,
+    AppUpdate = 13,
+    PollVote = 14,
+    GroupAdminAdded = 15,
+    GroupAdminRemoved = 16,
+    Typing = 17,
+    MultiDeviceSync = 18,
 }
 
 impl Default for SystemMessage {
@@ -82,10 +110,63 @@ impl Default for SystemMessage {
     }
 }
 
+impl SystemMessage {
+    /// Returns the `ShowSystemMessages` flag controlling the visibility
+    /// of this kind of system message, if it can be hidden at all.
+    pub fn show_flag(self) -> Option<ShowSystemMessages> {
+        match self {
+            SystemMessage::GroupNameChanged => Some(ShowSystemMessages::GROUP_NAME_CHANGED),
+            SystemMessage::GroupImageChanged => Some(ShowSystemMessages::GROUP_IMAGE_CHANGED),
+            SystemMessage::MemberAddedToGroup => Some(ShowSystemMessages::MEMBER_ADDED),
+            SystemMessage::MemberRemovedFromGroup => Some(ShowSystemMessages::MEMBER_REMOVED),
+            SystemMessage::LocationStreamingEnabled => {
+                Some(ShowSystemMessages::LOCATION_STREAMING_ENABLED)
+            }
+            _ => None,
+        }
+    }
+}
+
+bitflags! {
+    /// System message kinds shown in chats, see `Config::ShowSystemMessages`.
+    ///
+    /// System messages of a kind not contained in the set are stored hidden.
+    pub struct ShowSystemMessages: i32 {
+        const GROUP_NAME_CHANGED = 0x01;
+        const GROUP_IMAGE_CHANGED = 0x02;
+        const MEMBER_ADDED = 0x04;
+        const MEMBER_REMOVED = 0x08;
+        const LOCATION_STREAMING_ENABLED = 0x10;
+    }
+}
+
+impl Default for ShowSystemMessages {
+    fn default() -> Self {
+        ShowSystemMessages::all()
+    }
+}
+
 const MIME_AC_SETUP_FILE: &str = "application/autocrypt-setup";
 
+/// HTML bodies larger than this are not stored,
+/// the plain text of the message is shown instead.
+const MAX_HTML_BYTES: usize = 1_000_000;
+
 impl MimeMessage {
     pub async fn from_bytes(context: &Context, body: &[u8]) -> Result<Self> {
+        MimeMessage::from_bytes_with_partial(context, body, None).await
+    }
+
+    /// Parses a message, `partial` is set to the size of the full message
+    /// if only the header is passed in `body`.
+    ///
+    /// Partially downloaded messages are not decrypted, instead
+    /// a single placeholder text part is created.
+    pub(crate) async fn from_bytes_with_partial(
+        context: &Context,
+        body: &[u8],
+        partial: Option<u32>,
+    ) -> Result<Self> {
         let mail = mailparse::parse_mail(body)?;
 
         let message_time = mail
@@ -115,8 +196,15 @@ impl MimeMessage {
         // Memory location for a possible decrypted message.
         let mail_raw;
         let mut gossipped_addr = Default::default();
+        let mut smime_state = SmimeState::empty();
 
-        let (mail, signatures) = match e2ee::try_decrypt(context, &mail, message_time).await {
+        let decrypted = if partial.is_some() {
+            // there is nothing to decrypt in the header.
+            Ok((None, Default::default()))
+        } else {
+            e2ee::try_decrypt(context, &mail, message_time).await
+        };
+        let (mail, signatures) = match decrypted {
             Ok((raw, signatures)) => {
                 if let Some(raw) = raw {
                     // Valid autocrypt message, encrypted
@@ -134,8 +222,18 @@ impl MimeMessage {
                         update_gossip_peerstates(context, message_time, &mail, gossip_headers)
                             .await?;
 
+                    // the real subject is in the encrypted part,
+                    // if there is none, the placeholder is not shown
+                    if headers
+                        .get("subject")
+                        .map_or(false, |subject| is_subject_placeholder(subject))
+                    {
+                        headers.remove("subject");
+                    }
+
                     // let known protected headers from the decrypted
                     // part override the unencrypted top-level
+                    let outer_from = from.clone();
                     MimeMessage::merge_headers(
                         context,
                         &mut headers,
@@ -145,7 +243,37 @@ impl MimeMessage {
                         &decrypted_mail.headers,
                     );
 
+                    // the signature was checked against the keys of the outer From
+                    if let (Some(outer), Some(inner)) = (outer_from.first(), from.first()) {
+                        if !addr_cmp(&outer.addr, &inner.addr) {
+                            warn!(
+                                context,
+                                "Ignoring protected From {} differing from {}",
+                                inner.addr,
+                                outer.addr
+                            );
+                            from = outer_from;
+                        }
+                    }
+
                     (decrypted_mail, signatures)
+                } else if partial.is_none() && smime::smime_type(&mail).is_some() {
+                    let from_addr = from
+                        .first()
+                        .map(|from| from.addr.clone())
+                        .unwrap_or_default();
+                    match smime::unwrap(context, body, &from_addr).await {
+                        Ok(unwrapped) => {
+                            mail_raw = unwrapped.content;
+                            smime_state = unwrapped.state;
+                            (mailparse::parse_mail(&mail_raw)?, signatures)
+                        }
+                        Err(err) => {
+                            // encrypted parts are replaced by an error message
+                            warn!(context, "S/MIME: {}", err);
+                            (mail, signatures)
+                        }
+                    }
                 } else {
                     // Message was not encrypted
                     (mail, signatures)
@@ -176,19 +304,50 @@ impl MimeMessage {
             signatures,
             gossipped_addr,
             is_forwarded: false,
+            forwarded_from: None,
             reports: Vec::new(),
+            delivery_reports: Vec::new(),
             is_system_message: SystemMessage::Unknown,
             location_kml: None,
             message_kml: None,
             user_avatar: None,
             group_avatar: None,
+            html: None,
+            footer: None,
         };
-        parser.parse_mime_recursive(context, &mail).await?;
+        match partial {
+            Some(org_bytes) => {
+                parser
+                    .create_stub_from_partial_download(context, org_bytes)
+                    .await;
+            }
+            None => {
+                parser.parse_mime_recursive(context, &mail).await?;
+            }
+        }
+        if !smime_state.is_empty() {
+            for part in parser.parts.iter_mut() {
+                part.param.set_int(Param::Smime, smime_state.bits());
+            }
+        }
         parser.parse_headers(context)?;
 
         Ok(parser)
     }
 
+    /// Creates the placeholder part shown for a partially downloaded message.
+    async fn create_stub_from_partial_download(&mut self, context: &Context, org_bytes: u32) {
+        let mut part = Part::default();
+        part.typ = Viewtype::Text;
+        part.msg = format!(
+            "[{}]",
+            context
+                .stock_string_repl_str(StockMessage::PartialDownloadMsgBody, format_size(org_bytes))
+                .await
+        );
+        self.parts.push(part);
+    }
+
     /// Parses system messages.
     fn parse_system_message_headers(&mut self, context: &Context) -> Result<()> {
         if self.get(HeaderDef::AutocryptSetupMessage).is_some() {
@@ -207,14 +366,45 @@ impl MimeMessage {
             } else {
                 warn!(context, "could not determine ASM mime-part");
             }
+        } else if self.get(HeaderDef::ChatReaction).is_some() {
+            self.is_system_message = SystemMessage::Reaction;
+        } else if self.get(HeaderDef::ChatEdit).is_some() {
+            self.is_system_message = SystemMessage::MessageEdit;
+        } else if self.get(HeaderDef::ChatAppUpdate).is_some() {
+            self.is_system_message = SystemMessage::AppUpdate;
+        } else if self.get(HeaderDef::ChatPollVote).is_some() {
+            self.is_system_message = SystemMessage::PollVote;
+        } else if self.get(HeaderDef::ChatTyping).is_some() {
+            self.is_system_message = SystemMessage::Typing;
+        } else if self.get(HeaderDef::ChatSync).is_some() {
+            self.is_system_message = SystemMessage::MultiDeviceSync;
         } else if let Some(value) = self.get(HeaderDef::ChatContent) {
             if value == "location-streaming-enabled" {
                 self.is_system_message = SystemMessage::LocationStreamingEnabled;
+            } else if value == "ephemeral-timer-changed" {
+                self.is_system_message = SystemMessage::EphemeralTimerChanged;
             }
         }
         Ok(())
     }
 
+    /// Moves the thumbnail referenced by `Chat-Video-Thumbnail` to the video part.
+    fn parse_video_thumbnail_header(&mut self) {
+        if let Some(filename) = self.get(HeaderDef::ChatVideoThumbnail).cloned() {
+            if self.parts.iter().any(|part| part.typ == Viewtype::Video) {
+                if let Some(blob) = self.take_attachment(&filename) {
+                    if let Some(part) = self
+                        .parts
+                        .iter_mut()
+                        .find(|part| part.typ == Viewtype::Video)
+                    {
+                        part.param.set(Param::Thumbnail, blob);
+                    }
+                }
+            }
+        }
+    }
+
     /// Parses avatar action headers.
     fn parse_avatar_headers(&mut self) {
         if let Some(header_value) = self.get(HeaderDef::ChatGroupAvatar).cloned() {
@@ -240,7 +430,8 @@ impl MimeMessage {
                         || filepart.typ == Viewtype::Audio
                         || filepart.typ == Viewtype::Voice
                         || filepart.typ == Viewtype::Video
-                        || filepart.typ == Viewtype::File)
+                        || filepart.typ == Viewtype::File
+                        || filepart.typ == Viewtype::Calendar)
             };
 
             if need_drop {
@@ -277,6 +468,29 @@ impl MimeMessage {
                     }
                 }
             }
+            if self.parts[0].typ == Viewtype::File {
+                if let Some(value) = self.get(HeaderDef::ChatContent) {
+                    if value == "app" {
+                        let part_mut = &mut self.parts[0];
+                        part_mut.typ = Viewtype::App;
+                    }
+                }
+            }
+            if self.parts[0].typ == Viewtype::Text
+                && self.get(HeaderDef::ChatContent).map(|v| v.as_str()) == Some("poll")
+            {
+                if let Some(options) = self
+                    .get(HeaderDef::ChatPollOptions)
+                    .and_then(|options| poll::parse_poll_options(options))
+                {
+                    let part_mut = &mut self.parts[0];
+                    part_mut.typ = Viewtype::Poll;
+                    part_mut.param.set(
+                        Param::PollOptions,
+                        serde_json::to_string(&options).unwrap_or_default(),
+                    );
+                }
+            }
             let part = &self.parts[0];
             if part.typ == Viewtype::Audio
                 || part.typ == Viewtype::Voice
@@ -296,6 +510,7 @@ impl MimeMessage {
     fn parse_headers(&mut self, context: &Context) -> Result<()> {
         self.parse_system_message_headers(context)?;
         self.parse_avatar_headers();
+        self.parse_video_thumbnail_header();
         self.squash_attachment_parts();
 
         if let Some(ref subject) = self.get_subject() {
@@ -331,13 +546,17 @@ impl MimeMessage {
         if self.is_forwarded {
             for part in self.parts.iter_mut() {
                 part.param.set_int(Param::Forwarded, 1);
+                if let Some(ref forwarded_from) = self.forwarded_from {
+                    part.param.set(Param::ForwardedFrom, forwarded_from);
+                }
             }
         }
 
         self.parse_attachments();
 
-        // See if an MDN is requested from the other side
-        if !self.decrypting_failed && !self.parts.is_empty() {
+        // See if an MDN is requested from the other side,
+        // never send them to mailing lists
+        if !self.decrypting_failed && !self.parts.is_empty() && !self.is_mailinglist_message() {
             if let Some(ref dn_to) = self.chat_disposition_notification_to {
                 if let Some(ref from) = self.from.get(0) {
                     if from.addr == dn_to.addr {
@@ -373,25 +592,23 @@ impl MimeMessage {
         if header_value == "0" {
             Some(AvatarAction::Delete)
         } else {
-            let mut i = 0;
-            while i != self.parts.len() {
-                let part = &mut self.parts[i];
-                if let Some(part_filename) = &part.org_filename {
-                    if part_filename == &header_value {
-                        if let Some(blob) = part.param.get(Param::File) {
-                            let res = Some(AvatarAction::Change(blob.to_string()));
-                            self.parts.remove(i);
-                            return res;
-                        }
-                        break;
-                    }
-                }
-                i += 1;
-            }
-            None
+            self.take_attachment(&header_value)
+                .map(AvatarAction::Change)
         }
     }
 
+    /// Removes the attachment with the given file name from the parts,
+    /// returning its blob.
+    fn take_attachment(&mut self, filename: &str) -> Option<String> {
+        let i = self
+            .parts
+            .iter()
+            .position(|part| part.org_filename.as_deref() == Some(filename))?;
+        let blob = self.parts[i].param.get(Param::File)?.to_string();
+        self.parts.remove(i);
+        Some(blob)
+    }
+
     pub fn was_encrypted(&self) -> bool {
         !self.signatures.is_empty()
     }
@@ -470,12 +687,34 @@ impl MimeMessage {
 
                     self.parse_mime_recursive(context, &mail).await
                 }
-                MimeS::Single => self.add_single_part_if_known(context, mail).await,
+                MimeS::Single => {
+                    if smime::smime_type(mail) == Some(SmimeType::Enveloped) {
+                        self.add_undecryptable_part(context).await;
+                        return Ok(true);
+                    }
+                    self.add_single_part_if_known(context, mail).await
+                }
             }
         }
         .boxed()
     }
 
+    /// Adds the error message shown instead of an encrypted part
+    /// that cannot be decrypted.
+    async fn add_undecryptable_part(&mut self, context: &Context) {
+        let msg_body = context.stock_str(StockMessage::CantDecryptMsgBody).await;
+        let txt = format!("[{}]", msg_body);
+
+        let mut part = Part::default();
+        part.typ = Viewtype::Text;
+        part.msg_raw = Some(txt.clone());
+        part.msg = txt;
+        part.param.set(Param::Error, "Decryption failed");
+
+        self.parts.push(part);
+        self.decrypting_failed = true;
+    }
+
     async fn handle_multiple(
         &mut self,
         context: &Context,
@@ -497,6 +736,28 @@ impl MimeMessage {
                         break;
                     }
                 }
+                for cur_data in &mail.subparts {
+                    if get_mime_type(cur_data)?.0 == mime::TEXT_HTML {
+                        if let Ok(html) = cur_data.get_body() {
+                            self.set_html(context, &html);
+                        }
+                        break;
+                    }
+                }
+                if !any_part_added {
+                    /* invitations are sent as text/calendar alternative,
+                    see RFC 6047 */
+                    for cur_data in &mail.subparts {
+                        if cur_data
+                            .ctype
+                            .mimetype
+                            .eq_ignore_ascii_case("text/calendar")
+                        {
+                            any_part_added = self.parse_mime_recursive(context, cur_data).await?;
+                            break;
+                        }
+                    }
+                }
                 if !any_part_added {
                     /* search for text/plain and add this */
                     for cur_data in &mail.subparts {
@@ -520,19 +781,8 @@ impl MimeMessage {
                 // we currently do not try to decrypt non-autocrypt messages
                 // at all. If we see an encrypted part, we set
                 // decrypting_failed.
-                let msg_body = context.stock_str(StockMessage::CantDecryptMsgBody).await;
-                let txt = format!("[{}]", msg_body);
-
-                let mut part = Part::default();
-                part.typ = Viewtype::Text;
-                part.msg_raw = Some(txt.clone());
-                part.msg = txt;
-                part.param.set(Param::Error, "Decryption failed");
-
-                self.parts.push(part);
-
+                self.add_undecryptable_part(context).await;
                 any_part_added = true;
-                self.decrypting_failed = true;
             }
             (mime::MULTIPART, "signed") => {
                 /* RFC 1847: "The multipart/signed content type
@@ -566,8 +816,13 @@ impl MimeMessage {
 
                             any_part_added = true;
                         } else {
-                            /* eg. `report-type=delivery-status`;
-                            maybe we should show them as a little error icon */
+                            if report_type == "delivery-status" {
+                                if let Some(report) = self.process_delivery_status(mail)? {
+                                    self.delivery_reports.push(report);
+                                }
+                            }
+
+                            // show the human-readable part of the report
                             if let Some(first) = mail.subparts.iter().next() {
                                 any_part_added = self.parse_mime_recursive(context, first).await?;
                             }
@@ -602,6 +857,15 @@ impl MimeMessage {
 
         let old_part_count = self.parts.len();
 
+        if raw_mime == "text/calendar" || raw_mime == "application/ics" {
+            if let Some(added) = self
+                .add_calendar_part(context, mail, filename.as_deref())
+                .await
+            {
+                return Ok(added);
+            }
+        }
+
         match filename {
             Some(filename) => {
                 self.do_add_single_file_part(
@@ -630,17 +894,22 @@ impl MimeMessage {
                             }
                         };
 
-                        let (simplified_txt, is_forwarded) = if decoded_data.is_empty() {
-                            ("".into(), false)
-                        } else {
-                            let is_html = mime_type == mime::TEXT_HTML;
-                            let out = if is_html {
-                                dehtml(&decoded_data)
+                        let (simplified_txt, is_forwarded, top_quote, footer) =
+                            if decoded_data.is_empty() {
+                                ("".into(), false, None, None)
                             } else {
-                                decoded_data.clone()
+                                let is_html = mime_type == mime::TEXT_HTML;
+                                let out = if is_html {
+                                    self.set_html(context, &decoded_data);
+                                    dehtml(&decoded_data)
+                                } else {
+                                    decoded_data.clone()
+                                };
+                                if self.forwarded_from.is_none() {
+                                    self.forwarded_from = get_forwarded_from(&out);
+                                }
+                                simplify(out, self.has_chat_version())
                             };
-                            simplify(out, self.has_chat_version())
-                        };
 
                         if !simplified_txt.is_empty() {
                             let mut part = Part::default();
@@ -648,12 +917,24 @@ impl MimeMessage {
                             part.mimetype = Some(mime_type);
                             part.msg = simplified_txt;
                             part.msg_raw = Some(decoded_data);
+                            if let Some(quote) = top_quote {
+                                part.param.set(Param::Quote, quote);
+                                if let Some(quoted_mid) = self
+                                    .get(HeaderDef::InReplyTo)
+                                    .and_then(|value| parse_message_id(value).ok())
+                                {
+                                    part.param.set(Param::QuotedMsgId, quoted_mid);
+                                }
+                            }
                             self.do_add_single_part(part);
                         }
 
                         if is_forwarded {
                             self.is_forwarded = true;
                         }
+                        if self.footer.is_none() {
+                            self.footer = footer;
+                        }
                     }
                     _ => {}
                 }
@@ -664,6 +945,76 @@ impl MimeMessage {
         Ok(self.parts.len() > old_part_count)
     }
 
+    /// Adds an iCalendar part as message of type [Viewtype::Calendar].
+    ///
+    /// Returns `None` if the part cannot be parsed and should be handled
+    /// as regular part, `Some(false)` if it should be ignored, eg. because
+    /// it is a reply to an invitation or the message already contains the event.
+    async fn add_calendar_part(
+        &mut self,
+        context: &Context,
+        mail: &mailparse::ParsedMail<'_>,
+        filename: Option<&str>,
+    ) -> Option<bool> {
+        let ics = mail.get_body().ok()?;
+        let event = match calendar::parse_ics(&ics) {
+            Ok(event) => event,
+            Err(err) => {
+                warn!(context, "Cannot parse calendar part: {}", err);
+                return None;
+            }
+        };
+        if event.method == "REPLY" {
+            info!(context, "Ignoring reply to calendar event {}", event.uid);
+            return Some(false);
+        }
+        if self.parts.iter().any(|part| part.typ == Viewtype::Calendar) {
+            return Some(false);
+        }
+
+        let filename = filename.unwrap_or("invite.ics");
+        let blob = match BlobObject::create(context, filename, ics.as_bytes()).await {
+            Ok(blob) => blob,
+            Err(err) => {
+                error!(
+                    context,
+                    "Could not add blob for calendar part {}, error {}", filename, err
+                );
+                return None;
+            }
+        };
+        let mut part = Part::default();
+        part.typ = Viewtype::Calendar;
+        part.mimetype = "text/calendar".parse().ok();
+        part.msg = event.summary.clone();
+        part.org_filename = Some(filename.to_string());
+        part.bytes = ics.len();
+        part.param.set(Param::File, blob.as_name());
+        part.param.set(Param::MimeType, "text/calendar");
+        part.param.set(
+            Param::CalendarEvent,
+            serde_json::to_string(&event).unwrap_or_default(),
+        );
+        self.do_add_single_part(part);
+        Some(true)
+    }
+
+    /// Remembers the first HTML body of the message
+    /// so that the UI can show the full message on demand.
+    fn set_html(&mut self, context: &Context, html: &str) {
+        if self.html.is_some() {
+            return;
+        }
+        if html.len() > MAX_HTML_BYTES {
+            info!(context, "HTML body of {} bytes is not stored", html.len());
+            return;
+        }
+        match sanitize_html(html) {
+            Some(html) => self.html = Some(html),
+            None => warn!(context, "Cannot sanitize HTML body"),
+        }
+    }
+
     async fn do_add_single_file_part(
         &mut self,
         context: &Context,
@@ -840,8 +1191,81 @@ impl MimeMessage {
         Ok(None)
     }
 
-    /// Handle reports (only MDNs for now)
+    /// Parses a delivery status notification, RFC 3464.
+    ///
+    /// Reports are applied only if sent by a mail server, see [is_mail_server_addr].
+    ///
+    /// Returns the recipients the message could not be delivered to;
+    /// the original message is identified by the returned headers
+    /// in the third part of the report.
+    fn process_delivery_status(
+        &self,
+        report: &mailparse::ParsedMail<'_>,
+    ) -> Result<Option<DeliveryReport>> {
+        let original_message_id = match report.subparts.get(2) {
+            Some(original) => {
+                let original_body = original.get_body_raw()?;
+                let (original_fields, _) = mailparse::parse_headers(&original_body)?;
+                original_fields
+                    .get_header_value(HeaderDef::MessageId)
+                    .and_then(|v| parse_message_id(&v).ok())
+            }
+            None => None,
+        };
+        let original_message_id = match original_message_id {
+            Some(original_message_id) => original_message_id,
+            None => return Ok(None),
+        };
+
+        // the per-message fields are followed by the per-recipient fields,
+        // the groups are separated by empty lines
+        let status_body = report.subparts[1].get_body_raw()?;
+        let status_body = String::from_utf8_lossy(&status_body).replace("\r\n", "\n");
+        let mut failed_recipients = Vec::new();
+        for group in status_body.split("\n\n").skip(1) {
+            let (fields, _) = mailparse::parse_headers(group.as_bytes())?;
+            let action = fields.get_header_value(HeaderDef::Action);
+            if action.map(|action| action.to_lowercase()) != Some("failed".to_string()) {
+                continue;
+            }
+            // the address is prefixed by its type, eg. `rfc822; bob@example.org`
+            let addr = fields
+                .get_header_value(HeaderDef::FinalRecipient)
+                .and_then(|v| v.rsplit(';').next().map(|addr| addr.trim().to_string()));
+            if let Some(addr) = addr {
+                let error = fields
+                    .get_header_value(HeaderDef::DiagnosticCode)
+                    .or_else(|| fields.get_header_value(HeaderDef::Status))
+                    .unwrap_or_default();
+                failed_recipients.push((addr, error));
+            }
+        }
+
+        Ok(Some(DeliveryReport {
+            original_message_id,
+            failed_recipients,
+        }))
+    }
+
+    /// Handle reports (MDNs and delivery status notifications)
     pub async fn handle_reports(&self, context: &Context, from_id: u32, sent_timestamp: i64) {
+        let from_mail_server = self
+            .from
+            .get(0)
+            .map_or(false, |from| is_mail_server_addr(&from.addr));
+        if !self.delivery_reports.is_empty() && !from_mail_server {
+            warn!(
+                context,
+                "Ignoring delivery report not sent by a mail server"
+            );
+        } else {
+            for report in &self.delivery_reports {
+                for (addr, error) in &report.failed_recipients {
+                    message::ndn_from_ext(context, &report.original_message_id, addr, error).await;
+                }
+            }
+        }
+
         if self.reports.is_empty() {
             return;
         }
@@ -870,6 +1294,12 @@ async fn update_gossip_peerstates(
     // XXX split the parsing from the modification part
     let mut gossipped_addr: HashSet<String> = Default::default();
 
+    let accept = GossipAccept::from_i32(context.get_config_int(Config::GossipAccept).await)
+        .unwrap_or_default();
+    if accept == GossipAccept::Never {
+        return Ok(gossipped_addr);
+    }
+
     for value in &gossip_headers {
         let gossip_header = value.parse::<Aheader>();
 
@@ -879,6 +1309,13 @@ async fn update_gossip_peerstates(
                 .any(|info| info.addr == header.addr.to_lowercase())
             {
                 let mut peerstate = Peerstate::from_addr(context, &header.addr).await;
+                if peerstate.is_some() && accept == GossipAccept::UnknownPeers {
+                    info!(
+                        context,
+                        "Ignoring gossipped \"{}\" as the key is already known.", &header.addr,
+                    );
+                    continue;
+                }
                 if let Some(ref mut peerstate) = peerstate {
                     peerstate.apply_gossip(header, message_time);
                     peerstate.save_to_db(&context.sql, false).await?;
@@ -914,6 +1351,16 @@ pub(crate) struct Report {
     additional_message_ids: Vec<String>,
 }
 
+/// A delivery status notification, RFC 3464.
+#[derive(Debug)]
+pub(crate) struct DeliveryReport {
+    /// Message-ID of the message the report is about
+    original_message_id: String,
+    /// Addresses the message could not be delivered to,
+    /// along with the error reported by the server
+    failed_recipients: Vec<(String, String)>,
+}
+
 pub(crate) fn parse_message_ids(ids: &str) -> Result<Vec<String>> {
     // take care with mailparse::msgidparse() that is pretty untolerant eg. wrt missing `<` or `>`
     let mut msgids = Vec::new();
@@ -940,6 +1387,19 @@ pub(crate) fn parse_message_id(ids: &str) -> Result<String> {
     }
 }
 
+/// Returns true if `subject` is a placeholder for an encrypted subject.
+///
+/// Besides "..." used by the protected headers draft,
+/// some clients use their own placeholders.
+fn is_subject_placeholder(subject: &str) -> bool {
+    match subject.trim() {
+        SUBJECT_PLACEHOLDER | "…" | "Encrypted Message" | "Encrypted message" | "p≡p" | "pEp" => {
+            true
+        }
+        _ => false,
+    }
+}
+
 fn is_known(key: &str) -> bool {
     match key {
         "return-path" | "date" | "from" | "sender" | "reply-to" | "to" | "cc" | "bcc"
@@ -1103,9 +1563,24 @@ where
     result
 }
 
+/// Returns true if the address is the one mail servers send bounces from.
+///
+/// Delivery reports of other senders are ignored, otherwise anyone knowing
+/// the Message-ID of a message could mark it as failed.
+fn is_mail_server_addr(addr: &str) -> bool {
+    match addr.rfind('@') {
+        Some(at) => {
+            let local = addr[..at].to_lowercase();
+            local == "mailer-daemon" || local == "postmaster"
+        }
+        None => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::key;
     use crate::test_utils::*;
 
     impl AvatarAction {
@@ -1272,6 +1747,24 @@ mod tests {
         assert!(mimeparser.get(HeaderDef::SecureJoinFingerprint).is_none());
     }
 
+    #[async_std::test]
+    async fn test_mimeparser_typing() {
+        let context = dummy_context().await;
+        let raw = b"From: bob@example.net\n\
+                    To: alice@example.com\n\
+                    Chat-Version: 1.0\n\
+                    Chat-Typing: 1\n\
+                    Message-ID: <typing@example.net>\n\
+                    \n\
+                    \n";
+
+        let mimeparser = MimeMessage::from_bytes(&context.ctx, &raw[..])
+            .await
+            .unwrap();
+        assert_eq!(mimeparser.is_system_message, SystemMessage::Typing);
+        assert_eq!(mimeparser.get(HeaderDef::ChatTyping).unwrap(), "1");
+    }
+
     #[async_std::test]
     async fn test_mimeparser_with_avatars() {
         let t = dummy_context().await;
@@ -1315,6 +1808,56 @@ mod tests {
         assert!(mimeparser.group_avatar.unwrap().is_change());
     }
 
+    #[async_std::test]
+    async fn test_mimeparser_video_thumbnail() {
+        let t = dummy_context().await;
+        let raw = b"Chat-Version: 1.0\n\
+From: foo <foo@example.org>\n\
+To: bar <bar@example.org>\n\
+Subject: Chat: video\n\
+Message-ID: <video@example.org>\n\
+Chat-Video-Thumbnail: thumbnail.jpg\n\
+Content-Type: multipart/mixed; boundary=\"==break==\"\n\
+\n\
+--==break==\n\
+Content-Type: text/plain; charset=utf-8\n\
+\n\
+--==break==\n\
+Content-Type: video/mp4\n\
+Content-Disposition: attachment; filename=\"video.mp4\"\n\
+Content-Transfer-Encoding: base64\n\
+\n\
+AAAAIGZ0eXBpc29t\n\
+--==break==\n\
+Content-Type: image/jpeg\n\
+Content-Disposition: attachment; filename=\"thumbnail.jpg\"\n\
+Content-Transfer-Encoding: base64\n\
+\n\
+/9j/4AAQSkZJRg==\n\
+--==break==--\n";
+
+        let mimeparser = MimeMessage::from_bytes(&t.ctx, &raw[..]).await.unwrap();
+        assert_eq!(mimeparser.parts.len(), 1);
+        let part = &mimeparser.parts[0];
+        assert_eq!(part.typ, Viewtype::Video);
+        let thumbnail = part.param.get(Param::Thumbnail).unwrap();
+        assert!(thumbnail.starts_with("$BLOBDIR/thumbnail"));
+
+        // without the header, the thumbnail is a normal attachment
+        let raw = String::from_utf8_lossy(raw).replace("Chat-Video-Thumbnail:", "X-Thumbnail:");
+        let mimeparser = MimeMessage::from_bytes(&t.ctx, raw.as_bytes())
+            .await
+            .unwrap();
+        assert!(mimeparser
+            .parts
+            .iter()
+            .any(|part| part.typ == Viewtype::Image));
+        assert!(mimeparser
+            .parts
+            .iter()
+            .all(|part| !part.param.exists(Param::Thumbnail)));
+    }
+
     #[async_std::test]
     async fn test_mimeparser_message_kml() {
         let context = dummy_context().await;
@@ -1538,6 +2081,104 @@ Additional-Message-IDs: <foo@example.com> <foo@example.net>\n\
         );
     }
 
+    /// A bounce for one of two recipients of a message.
+    const NDN: &[u8] = b"Subject: Undelivered Mail Returned to Sender\n\
+Date: Mon, 10 Jan 2020 00:00:00 +0000\n\
+Message-ID: <ndn@example.org>\n\
+To: alice@example.org\n\
+From: MAILER-DAEMON@example.org\n\
+Content-Type: multipart/report; report-type=delivery-status;\n\t\
+boundary=\"ndnboundary\"\n\
+\n\
+\n\
+--ndnboundary\n\
+Content-Type: text/plain; charset=utf-8\n\
+\n\
+Your message could not be delivered to one or more recipients.\n\
+\n\
+--ndnboundary\n\
+Content-Type: message/delivery-status\n\
+\n\
+Reporting-MTA: dns; mx.example.org\n\
+Arrival-Date: Mon, 10 Jan 2020 00:00:00 +0000\n\
+\n\
+Final-Recipient: rfc822; bob@example.net\n\
+Action: failed\n\
+Status: 5.1.1\n\
+Diagnostic-Code: smtp; 550 5.1.1 user unknown\n\
+\n\
+Final-Recipient: rfc822; claire@example.net\n\
+Action: delivered\n\
+Status: 2.0.0\n\
+\n\
+--ndnboundary\n\
+Content-Type: text/rfc822-headers\n\
+\n\
+Message-ID: <foo@example.org>\n\
+From: alice@example.org\n\
+To: bob@example.net, claire@example.net\n\
+Subject: hi\n\
+\n\
+--ndnboundary--\n\
+";
+
+    #[test]
+    fn test_is_mail_server_addr() {
+        assert!(is_mail_server_addr("MAILER-DAEMON@mx.example.org"));
+        assert!(is_mail_server_addr("postmaster@example.org"));
+        assert!(is_mail_server_addr("mailer-daemon@googlemail.com"));
+        assert!(!is_mail_server_addr("bob@example.org"));
+        assert!(!is_mail_server_addr("mailer-daemon.bob@example.org"));
+        assert!(!is_mail_server_addr("mailer-daemon"));
+    }
+
+    #[async_std::test]
+    async fn test_parse_ndn() {
+        let context = dummy_context().await;
+        let message = MimeMessage::from_bytes(&context.ctx, NDN).await.unwrap();
+
+        assert_eq!(message.parts.len(), 1);
+        assert!(message.reports.is_empty());
+        assert_eq!(message.delivery_reports.len(), 1);
+        let report = &message.delivery_reports[0];
+        assert_eq!(report.original_message_id, "foo@example.org");
+        assert_eq!(
+            report.failed_recipients,
+            vec![(
+                "bob@example.net".to_string(),
+                "smtp; 550 5.1.1 user unknown".to_string()
+            )]
+        );
+    }
+
+    #[async_std::test]
+    async fn test_parse_smime_encrypted_without_certificate() {
+        let context = dummy_context().await;
+        let raw = br#"Date: Thu, 13 Feb 2020 22:41:20 +0000 (UTC)
+From: sender@example.com
+To: receiver@example.com
+Subject: S/MIME encrypted
+MIME-Version: 1.0
+Content-Type: application/pkcs7-mime; smime-type=enveloped-data; name="smime.p7m"
+Content-Transfer-Encoding: base64
+Content-Disposition: attachment; filename="smime.p7m"
+
+MIAGCSqGSIb3DQEHA6CAMIACAQAxggFMMIIBSAIBADAwMCsxKTAnBgNVBAMTIGJvYkBleGFtcGxl
+"#;
+
+        let message = MimeMessage::from_bytes(&context.ctx, &raw[..])
+            .await
+            .unwrap();
+        assert!(message.decrypting_failed);
+        assert_eq!(message.parts.len(), 1);
+        assert_eq!(message.parts[0].typ, Viewtype::Text);
+        assert_eq!(
+            message.parts[0].param.get(Param::Error),
+            Some("Decryption failed")
+        );
+        assert_eq!(message.parts[0].param.get_int(Param::Smime), None);
+    }
+
     #[async_std::test]
     async fn test_parse_inline_attachment() {
         let context = dummy_context().await;
@@ -1770,6 +2411,67 @@ CWt6wx7fiLp0qS9RrX75g6Gqw7nfCs6EcBERcIPt7DTe8VStJwf3LWqVwxl4gQl46yhfoqwEO+I=
         assert_eq!(message.parts[0].msg, "Test");
     }
 
+    #[async_std::test]
+    async fn test_gossip_accept() {
+        let t = dummy_context().await;
+        let mail = mailparse::parse_mail(b"To: bob@example.net\n\nhello").unwrap();
+        let gossip = |key: key::SignedPublicKey| {
+            Aheader::new(
+                "bob@example.net".to_string(),
+                key,
+                crate::aheader::EncryptPreference::NoPreference,
+            )
+            .to_string()
+        };
+        let bob_key = bob_keypair().public;
+
+        t.ctx
+            .set_config(Config::GossipAccept, Some("2"))
+            .await
+            .unwrap();
+        let addrs = update_gossip_peerstates(&t.ctx, 1000, &mail, vec![gossip(bob_key.clone())])
+            .await
+            .unwrap();
+        assert!(addrs.is_empty());
+        assert!(Peerstate::from_addr(&t.ctx, "bob@example.net")
+            .await
+            .is_none());
+
+        // unknown peers are created from gossip
+        t.ctx
+            .set_config(Config::GossipAccept, Some("1"))
+            .await
+            .unwrap();
+        let addrs = update_gossip_peerstates(&t.ctx, 1000, &mail, vec![gossip(bob_key.clone())])
+            .await
+            .unwrap();
+        assert_eq!(addrs.len(), 1);
+
+        // known keys are not replaced
+        let addrs =
+            update_gossip_peerstates(&t.ctx, 2000, &mail, vec![gossip(alice_keypair().public)])
+                .await
+                .unwrap();
+        assert!(addrs.is_empty());
+        let peerstate = Peerstate::from_addr(&t.ctx, "bob@example.net")
+            .await
+            .unwrap();
+        assert_eq!(peerstate.gossip_key, Some(bob_key));
+        let record = peerstate.get_key_source().await.unwrap().unwrap();
+        assert_eq!(record.source, crate::peerstate::KeySource::Gossip);
+        assert_eq!(record.timestamp, 1000);
+    }
+
+    #[test]
+    fn test_is_subject_placeholder() {
+        assert!(is_subject_placeholder("..."));
+        assert!(is_subject_placeholder(" ... "));
+        assert!(is_subject_placeholder("Encrypted Message"));
+        assert!(!is_subject_placeholder("...and more"));
+        assert!(!is_subject_placeholder("Re: ..."));
+        assert!(!is_subject_placeholder(""));
+    }
+
     #[test]
     fn test_parse_message_id() {
         let test = parse_message_id("<foobar>");
@@ -1,14 +1,30 @@
 //! OAuth 2 module
+//!
+//! The supported providers are listed in [PROVIDERS], the provider database
+//! may assign further domains to them.  Their URLs are
+//! templates, `$CLIENT_ID`, `$REDIRECT_URI`, `$CODE` etc. are replaced
+//! before calling them.  Providers using `$CODE_CHALLENGE` are authorized
+//! using PKCE, see <https://tools.ietf.org/html/rfc7636>.
 
 use std::collections::HashMap;
 
+use http_types::{Method, Request, Url};
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use url::form_urlencoded;
 
 use crate::context::Context;
 use crate::dc_tools::*;
+use crate::events::Event;
+use crate::provider::{self, Oauth2Authorizer};
+use crate::socks::send_http;
 
 const OAUTH2_GMAIL: Oauth2 = Oauth2 {
+    name: "Gmail",
+    domains: &["gmail.com", "googlemail.com"],
     // see https://developers.google.com/identity/protocols/OAuth2InstalledApp
     client_id: "959970109878-4mvtgf6feshskf7695nfln6002mom908.apps.googleusercontent.com",
     get_code: "https://accounts.google.com/o/oauth2/auth?client_id=$CLIENT_ID&redirect_uri=$REDIRECT_URI&response_type=code&scope=https%3A%2F%2Fmail.google.com%2F%20email&access_type=offline",
@@ -18,6 +34,16 @@ const OAUTH2_GMAIL: Oauth2 = Oauth2 {
 };
 
 const OAUTH2_YANDEX: Oauth2 = Oauth2 {
+    name: "Yandex",
+    domains: &[
+        "yandex.com",
+        "yandex.by",
+        "yandex.kz",
+        "yandex.ru",
+        "yandex.ua",
+        "ya.ru",
+        "narod.ru",
+    ],
     // see https://tech.yandex.com/oauth/doc/dg/reference/auto-code-client-docpage/
     client_id: "c4d0b6735fc8420a816d7e1303469341",
     get_code: "https://oauth.yandex.com/authorize?client_id=$CLIENT_ID&response_type=code&scope=mail%3Aimap_full%20mail%3Asmtp&force_confirm=true",
@@ -26,8 +52,16 @@ const OAUTH2_YANDEX: Oauth2 = Oauth2 {
     get_userinfo: None,
 };
 
+/// Registry of the supported OAuth2 providers.
+///
+/// Office365 is not supported until a client ID is registered for it,
+/// see [Oauth2::from_address].
+const PROVIDERS: [Oauth2; 2] = [OAUTH2_GMAIL, OAUTH2_YANDEX];
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct Oauth2 {
+    name: &'static str,
+    domains: &'static [&'static str],
     client_id: &'static str,
     get_code: &'static str,
     init_token: &'static str,
@@ -41,11 +75,14 @@ struct Response {
     // Should always be there according to: https://www.oauth.com/oauth2-servers/access-tokens/access-token-response/
     // but previous code handled its abscense.
     access_token: Option<String>,
-    token_type: String,
+    token_type: Option<String>,
     /// Duration of time the token is granted for, in seconds
     expires_in: Option<u64>,
     refresh_token: Option<String>,
     scope: Option<String>,
+    /// Set instead of the token in error responses, eg. `invalid_grant`
+    /// if the refresh token was revoked.
+    error: Option<String>,
 }
 
 pub async fn dc_get_oauth2_url(
@@ -53,25 +90,9 @@ pub async fn dc_get_oauth2_url(
     addr: impl AsRef<str>,
     redirect_uri: impl AsRef<str>,
 ) -> Option<String> {
-    if let Some(oauth2) = Oauth2::from_address(addr) {
-        if context
-            .sql
-            .set_raw_config(
-                context,
-                "oauth2_pending_redirect_uri",
-                Some(redirect_uri.as_ref()),
-            )
-            .await
-            .is_err()
-        {
-            return None;
-        }
-        let oauth2_url = replace_in_uri(&oauth2.get_code, "$CLIENT_ID", &oauth2.client_id);
-        let oauth2_url = replace_in_uri(&oauth2_url, "$REDIRECT_URI", redirect_uri.as_ref());
-
-        Some(oauth2_url)
-    } else {
-        None
+    match Oauth2::from_address(addr) {
+        Some(oauth2) => oauth2.get_code_url(context, redirect_uri.as_ref()).await,
+        None => None,
     }
 }
 
@@ -81,7 +102,7 @@ pub async fn dc_get_oauth2_access_token(
     code: impl AsRef<str>,
     regenerate: bool,
 ) -> Option<String> {
-    if let Some(oauth2) = Oauth2::from_address(addr) {
+    if let Some(oauth2) = Oauth2::from_address(addr.as_ref()) {
         let lock = context.oauth2_mutex.lock().await;
 
         // read generated token
@@ -106,6 +127,11 @@ pub async fn dc_get_oauth2_access_token(
             .get_raw_config(context, "oauth2_refresh_token_for")
             .await
             .unwrap_or_else(|| "unset".into());
+        let code_verifier = context
+            .sql
+            .get_raw_config(context, "oauth2_pending_code_verifier")
+            .await
+            .unwrap_or_default();
 
         let (redirect_uri, token_url, update_redirect_uri_on_success) =
             if refresh_token.is_none() || refresh_token_for != code.as_ref() {
@@ -153,6 +179,8 @@ pub async fn dc_get_oauth2_access_token(
                 value = &redirect_uri;
             } else if value == "$CODE" {
                 value = code.as_ref();
+            } else if value == "$CODE_VERIFIER" {
+                value = &code_verifier;
             } else if value == "$REFRESH_TOKEN" && refresh_token.is_some() {
                 value = refresh_token.as_ref().unwrap();
             }
@@ -161,7 +189,20 @@ pub async fn dc_get_oauth2_access_token(
         }
 
         // ... and POST
-        let response = surf::post(post_url).body_form(&post_param);
+        let mut req = match Url::parse(post_url) {
+            Ok(url) => Request::new(Method::Post, url),
+            Err(err) => {
+                warn!(context, "Invalid OAuth2 URL {}: {}", token_url, err);
+                return None;
+            }
+        };
+        req.set_body(
+            form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(post_param.iter())
+                .finish(),
+        );
+        req.insert_header("Content-Type", "application/x-www-form-urlencoded");
+        let response = send_http(context, req).await;
         if response.is_err() {
             warn!(
                 context,
@@ -170,7 +211,7 @@ pub async fn dc_get_oauth2_access_token(
             return None;
         }
 
-        let parsed: Result<Response, _> = response.unwrap().recv_json().await;
+        let parsed: Result<Response, _> = response.unwrap().body_json().await;
         if parsed.is_err() {
             warn!(
                 context,
@@ -220,6 +261,16 @@ pub async fn dc_get_oauth2_access_token(
                     .await
                     .ok();
             }
+        } else if !update_redirect_uri_on_success {
+            // The refresh token is not valid anymore,
+            // logging in will fail until the user authorizes again.
+            warn!(
+                context,
+                "Failed to refresh OAuth2 access token at {}: {}",
+                oauth2.name,
+                response.error.as_deref().unwrap_or("no access token")
+            );
+            context.emit_event(Event::ErrorOauth2ReauthRequired(addr.as_ref().to_string()));
         } else {
             warn!(context, "Failed to find OAuth2 access token");
         }
@@ -234,6 +285,16 @@ pub async fn dc_get_oauth2_access_token(
     }
 }
 
+/// Marks the stored access token as expired, so that it is refreshed
+/// on the next login, eg. after the server rejected it.
+pub(crate) async fn invalidate_access_token(context: &Context) {
+    context
+        .sql
+        .set_raw_config_int64(context, "oauth2_timestamp_expires", 1)
+        .await
+        .ok();
+}
+
 pub async fn dc_get_oauth2_addr(
     context: &Context,
     addr: impl AsRef<str>,
@@ -263,21 +324,62 @@ pub async fn dc_get_oauth2_addr(
 }
 
 impl Oauth2 {
+    /// Returns the URL to let the user authorize, remembering `redirect_uri`
+    /// and the PKCE code verifier for getting the access token later on.
+    async fn get_code_url(&self, context: &Context, redirect_uri: &str) -> Option<String> {
+        if context
+            .sql
+            .set_raw_config(context, "oauth2_pending_redirect_uri", Some(redirect_uri))
+            .await
+            .is_err()
+        {
+            return None;
+        }
+        let oauth2_url = replace_in_uri(&self.get_code, "$CLIENT_ID", &self.client_id);
+        let mut oauth2_url = replace_in_uri(&oauth2_url, "$REDIRECT_URI", redirect_uri);
+        if oauth2_url.contains("$CODE_CHALLENGE") {
+            let code_verifier = create_code_verifier();
+            if context
+                .sql
+                .set_raw_config(
+                    context,
+                    "oauth2_pending_code_verifier",
+                    Some(&code_verifier),
+                )
+                .await
+                .is_err()
+            {
+                return None;
+            }
+            oauth2_url = replace_in_uri(
+                &oauth2_url,
+                "$CODE_CHALLENGE",
+                code_challenge(&code_verifier),
+            );
+        }
+
+        Some(oauth2_url)
+    }
+
     fn from_address(addr: impl AsRef<str>) -> Option<Self> {
         let addr_normalized = normalize_addr(addr.as_ref());
-        if let Some(domain) = addr_normalized
+        let domain = addr_normalized
             .find('@')
-            .map(|index| addr_normalized.split_at(index + 1).1)
-        {
-            match domain {
-                "gmail.com" | "googlemail.com" => Some(OAUTH2_GMAIL),
-                "yandex.com" | "yandex.by" | "yandex.kz" | "yandex.ru" | "yandex.ua" | "ya.ru"
-                | "narod.ru" => Some(OAUTH2_YANDEX),
-                _ => None,
-            }
-        } else {
-            None
+            .map(|index| addr_normalized.split_at(index + 1).1)?
+            .to_lowercase();
+        if let Some(authorizer) = provider::get_info(&domain).and_then(|p| p.oauth2_authorizer) {
+            return match authorizer {
+                Oauth2Authorizer::Gmail => Some(OAUTH2_GMAIL),
+                Oauth2Authorizer::Yandex => Some(OAUTH2_YANDEX),
+                // no client ID is registered for Office365 yet,
+                // the login is done using the password then.
+                Oauth2Authorizer::Office365 => None,
+            };
         }
+        PROVIDERS
+            .iter()
+            .find(|oauth2| oauth2.domains.contains(&domain.as_str()))
+            .cloned()
     }
 
     async fn get_addr(&self, context: &Context, access_token: impl AsRef<str>) -> Option<String> {
@@ -291,8 +393,18 @@ impl Oauth2 {
         //   "verified_email": true,
         //   "picture": "https://lh4.googleusercontent.com/-Gj5jh_9R0BY/AAAAAAAAAAI/AAAAAAAAAAA/IAjtjfjtjNA/photo.jpg"
         // }
-        let response: Result<HashMap<String, serde_json::Value>, surf::Error> =
-            surf::get(userinfo_url).recv_json().await;
+        let url = match Url::parse(&userinfo_url) {
+            Ok(url) => url,
+            Err(err) => {
+                warn!(context, "Invalid userinfo URL {}: {}", userinfo_url, err);
+                return None;
+            }
+        };
+        let response: Result<HashMap<String, serde_json::Value>, http_types::Error> =
+            match send_http(context, Request::new(Method::Get, url)).await {
+                Ok(mut response) => response.body_json().await,
+                Err(err) => Err(err),
+            };
         if response.is_err() {
             warn!(context, "Error getting userinfo: {:?}", response);
             return None;
@@ -337,6 +449,19 @@ fn replace_in_uri(uri: impl AsRef<str>, key: impl AsRef<str>, value: impl AsRef<
     uri.as_ref().replace(key.as_ref(), &value_urlencoded)
 }
 
+/// Creates a random PKCE code verifier.
+fn create_code_verifier() -> String {
+    thread_rng().sample_iter(&Alphanumeric).take(64).collect()
+}
+
+/// Returns the S256 code challenge for a PKCE code verifier.
+fn code_challenge(code_verifier: &str) -> String {
+    base64::encode_config(
+        &Sha256::digest(code_verifier.as_bytes()),
+        base64::URL_SAFE_NO_PAD,
+    )
+}
+
 fn normalize_addr(addr: &str) -> &str {
     let normalized = addr.trim();
     normalized.trim_start_matches("mailto:")
@@ -374,6 +499,8 @@ mod tests {
             Some(OAUTH2_YANDEX)
         );
         assert_eq!(Oauth2::from_address("hello@yandex.ru"), Some(OAUTH2_YANDEX));
+        assert_eq!(Oauth2::from_address("hello@Outlook.com"), None);
+        assert_eq!(Oauth2::from_address("hello@office365.com"), None);
 
         assert_eq!(Oauth2::from_address("hello@web.de"), None);
     }
@@ -398,6 +525,47 @@ mod tests {
         assert_eq!(res, Some("https://accounts.google.com/o/oauth2/auth?client_id=959970109878%2D4mvtgf6feshskf7695nfln6002mom908%2Eapps%2Egoogleusercontent%2Ecom&redirect_uri=chat%2Edelta%3A%2Fcom%2Eb44t%2Emessenger&response_type=code&scope=https%3A%2F%2Fmail.google.com%2F%20email&access_type=offline".into()));
     }
 
+    #[test]
+    fn test_code_challenge() {
+        // example from RFC 7636, appendix B
+        assert_eq!(
+            code_challenge("dBjftJeZ4CK-1uDMjcAFtEhZi7xEKNajJ2oK0g0D1y4"),
+            "E9Melhoa2OwvFWvnQFpasFZ5ecJwl6d3TtC8FGfZDa4"
+        );
+        let code_verifier = create_code_verifier();
+        assert_eq!(code_verifier.len(), 64);
+        assert_ne!(code_verifier, create_code_verifier());
+    }
+
+    #[async_std::test]
+    async fn test_get_code_url_pkce() {
+        let ctx = dummy_context().await;
+        let oauth2 = Oauth2 {
+            name: "Example",
+            domains: &["example.org"],
+            client_id: "client",
+            get_code: "https://login.example.org/authorize?client_id=$CLIENT_ID&redirect_uri=$REDIRECT_URI&code_challenge=$CODE_CHALLENGE&code_challenge_method=S256",
+            init_token: "",
+            refresh_token: "",
+            get_userinfo: None,
+        };
+        let res = oauth2
+            .get_code_url(&ctx.ctx, "chat.delta:/x")
+            .await
+            .unwrap();
+        let code_verifier = ctx
+            .ctx
+            .sql
+            .get_raw_config(&ctx.ctx, "oauth2_pending_code_verifier")
+            .await
+            .unwrap();
+        let challenge =
+            utf8_percent_encode(&code_challenge(&code_verifier), NON_ALPHANUMERIC).to_string();
+        assert!(res.starts_with("https://login.example.org/"));
+        assert!(res.contains(&format!("&code_challenge={}&", challenge)));
+        assert!(!res.contains('$'));
+    }
+
     #[async_std::test]
     async fn test_dc_get_oauth2_token() {
         let ctx = dummy_context().await;
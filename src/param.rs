@@ -50,6 +50,10 @@ pub enum Param {
     /// For Messages
     Forwarded = b'a',
 
+    /// For Messages: name of the original author of a forwarded message,
+    /// only set if the forwarding user chose to attribute the message.
+    ForwardedFrom = b'v',
+
     /// For Messages
     Cmd = b'S',
 
@@ -68,6 +72,44 @@ pub enum Param {
     /// For Messages
     Error = b'L',
 
+    /// For Messages: why sending failed, a `message::SendError`.
+    SendError = b'V',
+
+    /// For Messages: response of the SMTP server to the last failed
+    /// attempt of sending the message.
+    SmtpResponse = b'Q',
+
+    /// For Messages: size and modification time of the attached file
+    /// at the time `Width` and `Height` were calculated.
+    FileSignature = b'z',
+
+    /// For Messages: text of the message before it was edited first.
+    OriginalText = b'O',
+
+    /// For Messages: quoted text of a reply.
+    Quote = b'q',
+
+    /// For Messages: rfc724_mid of the message quoted by a reply.
+    QuotedMsgId = b'p',
+
+    /// For Messages: options of a poll as JSON array.
+    PollOptions = b'o',
+
+    /// For Messages: calendar event as JSON, see [crate::calendar::CalendarEvent]
+    CalendarEvent = b'j',
+
+    /// For Messages: response to an invitation, see [crate::calendar::Rsvp]
+    CalendarRsvp = b'k',
+
+    /// For Messages: set to 1 if the HTML body is stored, see `MsgId::get_html()`.
+    HasHtml = b'T',
+
+    /// For Messages: poster frame of a video.
+    Thumbnail = b'N',
+
+    /// For Chats: address to post to a mailing list, taken from `List-Post:`.
+    ListPost = b'C',
+
     /// For Messages
     AttachGroupImage = b'A',
 
@@ -88,18 +130,39 @@ pub enum Param {
     /// For Jobs
     SetLongitude = b'n',
 
+    /// For Messages: label of a point of interest set by [crate::message::Message::set_poi]
+    PoiLabel = b'B',
+
+    /// For Messages: radius in meters of a point of interest
+    PoiRadius = b'Y',
+
+    /// For Messages: marker color of a point of interest, as 0xRRGGBB
+    PoiColor = b'Z',
+
     /// For Jobs
     AlsoMove = b'M',
 
     /// For Jobs: space-separated list of message recipients
     Recipients = b'R',
 
+    /// For Jobs: timestamp after which a message is outdated and not sent anymore
+    Expires = b'b',
+
     /// For Groups
     Unpromoted = b'U',
 
     /// For Groups and Contacts
     ProfileImage = b'i',
 
+    /// For Contacts: timestamp of the last Web Key Directory lookup
+    WkdLookup = b'W',
+
+    /// For Contacts: timestamp of the last keyserver lookup
+    KeyserverLookup = b'J',
+
+    /// For Messages: S/MIME protection, see `smime::SmimeState`
+    Smime = b'X',
+
     /// For Chats
     Selftalk = b'K',
 
@@ -109,6 +172,9 @@ pub enum Param {
     /// For Chats
     Devicetalk = b'D',
 
+    /// For Chats: read receipt policy, see `constants::MdnPolicy`.
+    MdnPolicy = b'y',
+
     /// For QR
     Auth = b's',
 
@@ -140,13 +206,56 @@ pub struct Params {
     inner: BTreeMap<Param, String>,
 }
 
+/// Escapes backslashes and line breaks in a value.
+///
+/// Values may contain text received from other contacts, eg. quotes,
+/// so line breaks must not start a new `key=value` pair.
+fn escape_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Reverts [escape_value].
+///
+/// Backslashes not starting an escape sequence are kept as they are,
+/// so values stored before escaping was added are mostly read as before.
+fn unescape_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.peek() {
+            Some('\\') => unescaped.push('\\'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            _ => {
+                unescaped.push('\\');
+                continue;
+            }
+        }
+        chars.next();
+    }
+    unescaped
+}
+
 impl fmt::Display for Params {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for (i, (key, value)) in self.inner.iter().enumerate() {
             if i > 0 {
                 writeln!(f)?;
             }
-            write!(f, "{}={}", *key as u8 as char, value)?;
+            write!(f, "{}={}", *key as u8 as char, escape_value(value))?;
         }
         Ok(())
     }
@@ -156,7 +265,7 @@ impl str::FromStr for Params {
     type Err = error::Error;
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        let mut inner = BTreeMap::new();
+        let mut inner: BTreeMap<Param, String> = BTreeMap::new();
         for pair in s.trim().lines() {
             let pair = pair.trim();
             if pair.is_empty() {
@@ -175,7 +284,7 @@ impl str::FromStr for Params {
             let value = value.unwrap_or_default().trim();
 
             if let Some(key) = Param::from_u8(key.as_bytes()[0]) {
-                inner.insert(key, value.to_string());
+                inner.insert(key, unescape_value(value));
             } else {
                 bail!("Unknown key: {}", key);
             }
@@ -407,6 +516,28 @@ mod tests {
         assert_eq!(p1.len(), 0)
     }
 
+    #[test]
+    fn test_multiline_value() {
+        let mut p = Params::new();
+        p.set(Param::Quote, "hello\nf=/etc/passwd\r\n\n  x=1\n\\ny")
+            .set_int(Param::Width, 2);
+        let s = p.to_string();
+        assert_eq!(s, "q=hello\\nf=/etc/passwd\\r\\n\\n  x=1\\n\\\\ny\nw=2");
+
+        let p: Params = s.parse().unwrap();
+        assert_eq!(
+            p.get(Param::Quote).unwrap(),
+            "hello\nf=/etc/passwd\r\n\n  x=1\n\\ny"
+        );
+        assert!(p.get(Param::File).is_none());
+        assert_eq!(p.get_int(Param::Width), Some(2));
+        assert_eq!(p.len(), 2);
+
+        // backslashes not starting an escape sequence are kept
+        let p: Params = "f=C:\\tmp\\\\x\\".parse().unwrap();
+        assert_eq!(p.get(Param::File).unwrap(), "C:\\tmp\\x\\");
+    }
+
     #[test]
     fn test_regression() {
         let p1: Params = "a=cli%40deltachat.de\nn=\ni=TbnwJ6lSvD5\ns=0ejvbdFSQxB"
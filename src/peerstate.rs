@@ -3,10 +3,12 @@ use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::fmt;
 
+use deltachat_derive::{FromSql, ToSql};
 use num_traits::FromPrimitive;
 
 use crate::aheader::*;
 use crate::context::Context;
+use crate::dc_tools::time;
 use crate::key::{DcKey, Fingerprint, SignedPublicKey};
 use crate::sql::Sql;
 
@@ -24,6 +26,66 @@ pub enum PeerstateVerifiedStatus {
     BidirectVerified = 2,
 }
 
+/// An event in the verification history of a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql)]
+#[repr(i32)]
+pub enum VerificationEvent {
+    /// The key was verified by a QR code scan, either by us or by the peer.
+    SecureJoin = 1,
+
+    /// The key was introduced by a verified member of a verified group.
+    GroupIntroduction = 2,
+
+    /// The verification was revoked by the user.
+    Revoked = 3,
+}
+
+/// An entry of the verification audit trail of a peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerificationRecord {
+    pub event: VerificationEvent,
+
+    /// Fingerprint of the key that was verified or whose verification was revoked.
+    pub fingerprint: Fingerprint,
+
+    /// Address of the contact that introduced the key,
+    /// only set for `VerificationEvent::GroupIntroduction`.
+    pub verifier: Option<String>,
+
+    pub timestamp: i64,
+}
+
+/// Where the key of a peer comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive, FromSql, ToSql)]
+#[repr(i32)]
+pub enum KeySource {
+    /// The key was received in an Autocrypt header of the peer.
+    Autocrypt = 1,
+
+    /// The key was found in the Web Key Directory of the domain of the peer.
+    Wkd = 2,
+
+    /// The key was found on the configured keyserver.
+    Keyserver = 3,
+
+    /// The key was gossiped by another member of a group.
+    Gossip = 4,
+}
+
+/// Records where a key of a peer comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeySourceRecord {
+    pub source: KeySource,
+
+    /// Fingerprint of the imported key.
+    pub fingerprint: Fingerprint,
+
+    /// URL the key was fetched from, empty for keys received in messages.
+    pub url: String,
+
+    pub timestamp: i64,
+}
+
 /// Peerstate represents the state of an Autocrypt peer.
 pub struct Peerstate<'a> {
     pub context: &'a Context,
@@ -143,6 +205,25 @@ impl<'a> Peerstate<'a> {
         res
     }
 
+    /// Creates a peerstate for a key found in a key directory.
+    ///
+    /// The key is stored as gossip key without encryption preference, so it
+    /// is trusted no more than a gossiped key and keys received by Autocrypt
+    /// later on take precedence.
+    pub fn from_discovered_key(
+        context: &'a Context,
+        addr: &str,
+        public_key: SignedPublicKey,
+        time: i64,
+    ) -> Self {
+        let header = Aheader::new(
+            addr.to_string(),
+            public_key,
+            EncryptPreference::NoPreference,
+        );
+        Self::from_gossip(context, &header, time)
+    }
+
     pub async fn from_addr(context: &'a Context, addr: &str) -> Option<Peerstate<'a>> {
         let query = "SELECT addr, last_seen, last_seen_autocrypt, prefer_encrypted, public_key, gossip_timestamp, gossip_key, public_key_fingerprint, gossip_key_fingerprint, verified_key, verified_key_fingerprint FROM acpeerstates  WHERE addr=? COLLATE NOCASE;";
         Self::from_stmt(context, query, paramsv![addr]).await
@@ -402,6 +483,127 @@ impl<'a> Peerstate<'a> {
         }
     }
 
+    /// Removes the verified key, returns its fingerprint if there was one.
+    ///
+    /// The change has to be saved using `Peerstate::save_to_db`.
+    pub fn revoke_verification(&mut self) -> Option<Fingerprint> {
+        self.verified_key = None;
+        let fingerprint = self.verified_key_fingerprint.take();
+        if fingerprint.is_some() {
+            self.to_save = Some(ToSave::All);
+        }
+        fingerprint
+    }
+
+    /// Appends an entry to the verification audit trail of the peer.
+    pub async fn add_verification_record(
+        &self,
+        event: VerificationEvent,
+        fingerprint: &Fingerprint,
+        verifier: Option<&str>,
+    ) -> crate::sql::Result<()> {
+        self.context
+            .sql
+            .execute(
+                "INSERT INTO acpeerstate_verifications (addr, event, fingerprint, verifier, timestamp) \
+                 VALUES (?,?,?,?,?);",
+                paramsv![self.addr, event, fingerprint.hex(), verifier, time()],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the verification audit trail of the peer, oldest entries first.
+    pub async fn get_verification_records(&self) -> crate::sql::Result<Vec<VerificationRecord>> {
+        self.context
+            .sql
+            .query_map(
+                "SELECT event, fingerprint, verifier, timestamp FROM acpeerstate_verifications \
+                 WHERE addr=? COLLATE NOCASE ORDER BY timestamp, id;",
+                paramsv![self.addr],
+                |row| {
+                    Ok(VerificationRecord {
+                        event: row.get(0)?,
+                        fingerprint: row.get::<_, String>(1)?.parse::<Fingerprint>()?,
+                        verifier: row.get(2)?,
+                        timestamp: row.get(3)?,
+                    })
+                },
+                |rows| {
+                    rows.collect::<std::result::Result<Vec<_>, _>>()
+                        .map_err(Into::into)
+                },
+            )
+            .await
+    }
+
+    /// Records that the key with the given fingerprint was fetched from `url`.
+    pub async fn add_key_source(
+        &self,
+        source: KeySource,
+        fingerprint: &Fingerprint,
+        url: &str,
+    ) -> crate::sql::Result<()> {
+        self.context
+            .sql
+            .execute(
+                "INSERT INTO acpeerstate_key_sources (addr, source, fingerprint, url, timestamp) \
+                 VALUES (?,?,?,?,?);",
+                paramsv![self.addr, source, fingerprint.hex(), url, time()],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Returns where the key used for encryption to the peer comes from.
+    ///
+    /// Returns `None` if no key is known.
+    pub async fn get_key_source(&self) -> crate::sql::Result<Option<KeySourceRecord>> {
+        // keys from the Autocrypt header of the peer itself take precedence
+        Ok(self.get_key_sources().await?.into_iter().next())
+    }
+
+    /// Returns where the Autocrypt key and the gossip key of the peer come from.
+    ///
+    /// Gossip keys without a record were gossiped in a group.
+    pub async fn get_key_sources(&self) -> crate::sql::Result<Vec<KeySourceRecord>> {
+        let mut records = Vec::new();
+        if let Some(ref fingerprint) = self.public_key_fingerprint {
+            records.push(KeySourceRecord {
+                source: KeySource::Autocrypt,
+                fingerprint: fingerprint.clone(),
+                url: String::new(),
+                timestamp: self.last_seen_autocrypt,
+            });
+        }
+        if let Some(ref fingerprint) = self.gossip_key_fingerprint {
+            let record = self
+                .context
+                .sql
+                .query_row_optional(
+                    "SELECT source, url, timestamp FROM acpeerstate_key_sources \
+                     WHERE addr=? COLLATE NOCASE AND fingerprint=? ORDER BY timestamp DESC, id DESC LIMIT 1;",
+                    paramsv![self.addr, fingerprint.hex()],
+                    |row| {
+                        Ok(KeySourceRecord {
+                            source: row.get(0)?,
+                            fingerprint: fingerprint.clone(),
+                            url: row.get(1)?,
+                            timestamp: row.get(2)?,
+                        })
+                    },
+                )
+                .await?;
+            records.push(record.unwrap_or_else(|| KeySourceRecord {
+                source: KeySource::Gossip,
+                fingerprint: fingerprint.clone(),
+                url: String::new(),
+                timestamp: self.gossip_timestamp,
+            }));
+        }
+        Ok(records)
+    }
+
     pub async fn save_to_db(&self, sql: &Sql, create: bool) -> crate::sql::Result<()> {
         if create {
             sql.execute(
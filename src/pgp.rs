@@ -10,6 +10,7 @@ use pgp::composed::{
     SignedPublicSubKey, SignedSecretKey, SubkeyParamsBuilder,
 };
 use pgp::crypto::{HashAlgorithm, SymmetricKeyAlgorithm};
+use pgp::ser::Serialize;
 use pgp::types::{
     CompressionAlgorithm, KeyTrait, Mpi, PublicKeyTrait, SecretKeyTrait, StringToKey,
 };
@@ -340,6 +341,22 @@ pub async fn symm_encrypt(passphrase: &str, plain: &[u8]) -> Result<String> {
     .await
 }
 
+/// Symmetric encryption to a binary, unarmored message.
+pub async fn symm_encrypt_bytes(passphrase: &str, plain: &[u8]) -> Result<Vec<u8>> {
+    let lit_msg = Message::new_literal_bytes("", plain);
+    let passphrase = passphrase.to_string();
+
+    async_std::task::spawn_blocking(move || {
+        let mut rng = thread_rng();
+        let s2k = StringToKey::new_default(&mut rng);
+        let msg =
+            lit_msg.encrypt_with_password(&mut rng, s2k, Default::default(), || passphrase)?;
+
+        Ok(msg.to_bytes()?)
+    })
+    .await
+}
+
 /// Symmetric decryption.
 pub async fn symm_decrypt<T: std::io::Read + std::io::Seek>(
     passphrase: &str,
@@ -348,18 +365,27 @@ pub async fn symm_decrypt<T: std::io::Read + std::io::Seek>(
     let (enc_msg, _) = Message::from_armor_single(ctext)?;
 
     let passphrase = passphrase.to_string();
-    async_std::task::spawn_blocking(move || {
-        let decryptor = enc_msg.decrypt_with_password(|| passphrase)?;
+    async_std::task::spawn_blocking(move || decrypt_with_password(enc_msg, passphrase)).await
+}
 
-        let msgs = decryptor.collect::<pgp::errors::Result<Vec<_>>>()?;
-        ensure!(!msgs.is_empty(), "No valid messages found");
+/// Symmetric decryption of a binary message created by [symm_encrypt_bytes].
+pub async fn symm_decrypt_bytes(passphrase: &str, ctext: &[u8]) -> Result<Vec<u8>> {
+    let enc_msg = Message::from_bytes(Cursor::new(ctext))?;
 
-        match msgs[0].get_content()? {
-            Some(content) => Ok(content),
-            None => bail!("Decrypted message is empty"),
-        }
-    })
-    .await
+    let passphrase = passphrase.to_string();
+    async_std::task::spawn_blocking(move || decrypt_with_password(enc_msg, passphrase)).await
+}
+
+fn decrypt_with_password(enc_msg: Message, passphrase: String) -> Result<Vec<u8>> {
+    let decryptor = enc_msg.decrypt_with_password(|| passphrase)?;
+
+    let msgs = decryptor.collect::<pgp::errors::Result<Vec<_>>>()?;
+    ensure!(!msgs.is_empty(), "No valid messages found");
+
+    match msgs[0].get_content()? {
+        Some(content) => Ok(content),
+        None => bail!("Decrypted message is empty"),
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,387 @@
+//! # Polls
+//!
+//! A poll is a message of type [Viewtype::Poll] with the question as text;
+//! the options are sent as JSON array in the `Chat-Poll-Options:` header.
+//! A vote is sent as a small hidden chat message referring to the poll
+//! by `In-Reply-To:`, the index of the chosen option is carried in the
+//! `Chat-Poll-Vote:` header.  Every contact has at most one vote per poll,
+//! an empty vote removes the previous one.
+
+use serde::Serialize;
+
+use crate::chat::{self, ChatId};
+use crate::constants::*;
+use crate::context::Context;
+use crate::dc_tools::time;
+use crate::error::{bail, ensure, format_err, Result};
+use crate::events::Event;
+use crate::headerdef::HeaderDef;
+use crate::message::{self, Message, MsgId};
+use crate::mimeparser::{parse_message_id, MimeMessage, SystemMessage};
+use crate::param::*;
+
+/// Maximum number of options of a poll.
+pub const MAX_POLL_OPTIONS: usize = 32;
+
+/// Current results of a poll.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PollState {
+    pub question: String,
+    pub options: Vec<PollOption>,
+
+    /// Index of the option the self-user voted for.
+    pub self_vote: Option<usize>,
+}
+
+/// An option of a poll together with the contacts that voted for it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PollOption {
+    pub text: String,
+
+    /// Contacts that voted for the option, in the order they voted.
+    pub voters: Vec<u32>,
+}
+
+/// Returns the options stored in the params of a poll.
+pub(crate) fn get_poll_options(param: &Params) -> Vec<String> {
+    param
+        .get(Param::PollOptions)
+        .and_then(|options| serde_json::from_str(options).ok())
+        .unwrap_or_default()
+}
+
+/// Parses the options of a received poll, returns `None` if they are
+/// not a valid list of options.
+pub(crate) fn parse_poll_options(options: &str) -> Option<Vec<String>> {
+    let options: Vec<String> = serde_json::from_str(options).ok()?;
+    if options.len() >= 2 && options.len() <= MAX_POLL_OPTIONS {
+        Some(options)
+    } else {
+        None
+    }
+}
+
+/// Checks a poll before it is sent.
+pub(crate) fn check_poll(msg: &Message) -> Result<()> {
+    let options = get_poll_options(&msg.param);
+    ensure!(
+        options.len() >= 2 && options.len() <= MAX_POLL_OPTIONS,
+        "A poll needs 2 to {} options",
+        MAX_POLL_OPTIONS
+    );
+    ensure!(
+        options.iter().all(|option| !option.trim().is_empty()),
+        "Poll options must not be empty"
+    );
+    ensure!(
+        !msg.text.as_deref().unwrap_or_default().trim().is_empty(),
+        "Poll without question"
+    );
+    Ok(())
+}
+
+/// Votes for the option with the index `option` of the poll `msg_id`.
+///
+/// The vote replaces the previous vote of the self-user, `None` removes it.
+/// Returns the ID of the hidden message carrying the vote.
+pub async fn send_poll_vote(
+    context: &Context,
+    msg_id: MsgId,
+    option: Option<usize>,
+) -> Result<MsgId> {
+    let msg = Message::load_from_db(context, msg_id).await?;
+    ensure!(
+        msg.viewtype == Viewtype::Poll,
+        "Message {} is no poll",
+        msg_id
+    );
+    ensure!(
+        !msg.chat_id.is_special(),
+        "Cannot vote in poll {} in special chat",
+        msg_id
+    );
+    ensure!(
+        !msg.rfc724_mid.is_empty(),
+        "Cannot vote in poll {} without Message-ID",
+        msg_id
+    );
+    let options = get_poll_options(&msg.param);
+    let text = match option {
+        Some(option) => options
+            .get(option)
+            .ok_or_else(|| format_err!("Poll {} has no option {}", msg_id, option))?
+            .clone(),
+        None => String::new(),
+    };
+
+    set_vote(context, msg.chat_id, msg_id, DC_CONTACT_ID_SELF, option).await?;
+
+    let mut vote_msg = Message::new(Viewtype::Text);
+    vote_msg.text = Some(text);
+    vote_msg.hidden = true;
+    vote_msg.param.set_cmd(SystemMessage::PollVote);
+    vote_msg.param.set(Param::Arg, &msg.rfc724_mid);
+    if let Some(option) = option {
+        vote_msg.param.set_int(Param::Arg2, option as i32);
+    }
+    Ok(chat::send_msg(context, msg.chat_id, &mut vote_msg).await?)
+}
+
+/// Returns the question, the options and the votes of a poll.
+pub async fn get_poll_state(context: &Context, msg: &Message) -> Result<PollState> {
+    ensure!(
+        msg.viewtype == Viewtype::Poll,
+        "Message {} is no poll",
+        msg.id
+    );
+    let votes = context
+        .sql
+        .query_map(
+            "SELECT contact_id, option FROM poll_votes
+              WHERE msg_id=?
+              ORDER BY timestamp, contact_id;",
+            paramsv![msg.id],
+            |row| Ok((row.get::<_, u32>(0)?, row.get::<_, i64>(1)?)),
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+
+    let mut state = PollState {
+        question: msg.text.clone().unwrap_or_default(),
+        options: get_poll_options(&msg.param)
+            .into_iter()
+            .map(|text| PollOption {
+                text,
+                voters: Vec::new(),
+            })
+            .collect(),
+        self_vote: None,
+    };
+    for (contact_id, option) in votes {
+        let option = option as usize;
+        if let Some(poll_option) = state.options.get_mut(option) {
+            poll_option.voters.push(contact_id);
+            if contact_id == DC_CONTACT_ID_SELF {
+                state.self_vote = Some(option);
+            }
+        }
+    }
+    Ok(state)
+}
+
+/// Stores the vote of a contact, `None` removes it.
+async fn set_vote(
+    context: &Context,
+    chat_id: ChatId,
+    msg_id: MsgId,
+    contact_id: u32,
+    option: Option<usize>,
+) -> Result<()> {
+    match option {
+        Some(option) => {
+            context
+                .sql
+                .execute(
+                    "INSERT OR REPLACE INTO poll_votes (msg_id, contact_id, option, timestamp)
+                     VALUES (?,?,?,?);",
+                    paramsv![msg_id, contact_id as i32, option as i64, time()],
+                )
+                .await?;
+        }
+        None => {
+            context
+                .sql
+                .execute(
+                    "DELETE FROM poll_votes WHERE msg_id=? AND contact_id=?;",
+                    paramsv![msg_id, contact_id as i32],
+                )
+                .await?;
+        }
+    }
+    context.emit_event(Event::PollChanged { chat_id, msg_id });
+    Ok(())
+}
+
+/// Applies a vote received from `from_id`.
+///
+/// Votes for unknown polls are ignored.
+pub(crate) async fn receive_poll_vote(
+    context: &Context,
+    mime_parser: &MimeMessage,
+    from_id: u32,
+) -> Result<()> {
+    let in_reply_to = mime_parser
+        .get(HeaderDef::InReplyTo)
+        .ok_or_else(|| format_err!("Vote without In-Reply-To"))?;
+    let rfc724_mid = parse_message_id(in_reply_to)?;
+    let option = match mime_parser.get(HeaderDef::ChatPollVote).map(|v| v.trim()) {
+        None | Some("") => None,
+        Some(option) => match option.parse::<usize>() {
+            Ok(option) => Some(option),
+            Err(_) => bail!("Invalid vote {:?}", option),
+        },
+    };
+
+    match message::rfc724_mid_exists(context, &rfc724_mid).await? {
+        Some((_, _, msg_id)) => {
+            let msg = Message::load_from_db(context, msg_id).await?;
+            ensure!(
+                msg.viewtype == Viewtype::Poll,
+                "Message {} is no poll",
+                msg_id
+            );
+            ensure!(
+                chat::is_contact_in_chat(context, msg.chat_id, from_id).await,
+                "Vote in poll {} from contact {} not in chat",
+                msg_id,
+                from_id
+            );
+            if let Some(option) = option {
+                ensure!(
+                    option < get_poll_options(&msg.param).len(),
+                    "Poll {} has no option {}",
+                    msg_id,
+                    option
+                );
+            }
+            set_vote(context, msg.chat_id, msg_id, from_id, option).await
+        }
+        None => {
+            info!(context, "Ignoring vote in unknown poll {}", rfc724_mid);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::chat::get_chat_msgs;
+    use crate::contact::Contact;
+    use crate::dc_receive_imf::dc_receive_imf;
+    use crate::test_utils::*;
+
+    async fn send_poll(t: &TestContext) -> (u32, ChatId, MsgId) {
+        configure_alice_keypair(&t.ctx).await;
+        let bob = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob).await.unwrap();
+        let mut poll = Message::new_poll("Lunch?", &["Pizza", "Sushi", "Salad"]);
+        let msg_id = chat::send_msg(&t.ctx, chat_id, &mut poll).await.unwrap();
+        (bob, chat_id, msg_id)
+    }
+
+    #[async_std::test]
+    async fn test_send_poll() {
+        let t = dummy_context().await;
+        let (_, chat_id, msg_id) = send_poll(&t).await;
+
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_viewtype(), Viewtype::Poll);
+        let state = msg.get_poll_state(&t.ctx).await.unwrap();
+        assert_eq!(state.question, "Lunch?");
+        assert_eq!(
+            state
+                .options
+                .iter()
+                .map(|option| option.text.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Pizza", "Sushi", "Salad"]
+        );
+        assert!(state.options.iter().all(|option| option.voters.is_empty()));
+        assert_eq!(state.self_vote, None);
+
+        let mut poll = Message::new_poll("Lunch?", &["Pizza"]);
+        assert!(chat::send_msg(&t.ctx, chat_id, &mut poll).await.is_err());
+        let mut poll = Message::new_poll("", &["Pizza", "Sushi"]);
+        assert!(chat::send_msg(&t.ctx, chat_id, &mut poll).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn test_send_poll_vote() {
+        let t = dummy_context().await;
+        let (_, chat_id, msg_id) = send_poll(&t).await;
+
+        assert!(send_poll_vote(&t.ctx, msg_id, Some(3)).await.is_err());
+        let vote_msg_id = send_poll_vote(&t.ctx, msg_id, Some(1)).await.unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        let state = msg.get_poll_state(&t.ctx).await.unwrap();
+        assert_eq!(state.self_vote, Some(1));
+        assert_eq!(state.options[1].voters, vec![DC_CONTACT_ID_SELF]);
+        assert_eq!(get_chat_msgs(&t.ctx, chat_id, 0, None).await, vec![msg_id]);
+
+        let vote_msg = Message::load_from_db(&t.ctx, vote_msg_id).await.unwrap();
+        assert!(vote_msg.hidden);
+        assert_eq!(vote_msg.param.get_cmd(), SystemMessage::PollVote);
+        assert_eq!(vote_msg.get_text(), Some("Sushi".to_string()));
+
+        send_poll_vote(&t.ctx, msg_id, None).await.unwrap();
+        let state = msg.get_poll_state(&t.ctx).await.unwrap();
+        assert_eq!(state.self_vote, None);
+        assert!(state.options[1].voters.is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_receive_poll() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        let bob = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob).await.unwrap();
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.net\n\
+              To: alice@example.com\n\
+              Subject: Chat: Lunch?\n\
+              Message-ID: <poll@example.net>\n\
+              Chat-Version: 1.0\n\
+              Chat-Content: poll\n\
+              Chat-Poll-Options: =?utf-8?q?=5B=22Pizza=22=2C=22Sushi=22=5D?=\n\
+              Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+              \n\
+              Lunch?\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await
+        .unwrap();
+        let msg_id = message::rfc724_mid_exists(&t.ctx, "poll@example.net")
+            .await
+            .unwrap()
+            .unwrap()
+            .2;
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(msg.get_viewtype(), Viewtype::Poll);
+        let state = msg.get_poll_state(&t.ctx).await.unwrap();
+        assert_eq!(state.question, "Lunch?");
+        assert_eq!(state.options.len(), 2);
+        assert_eq!(state.options[1].text, "Sushi");
+
+        dc_receive_imf(
+            &t.ctx,
+            b"From: bob@example.net\n\
+              To: alice@example.com\n\
+              Subject: Chat: Sushi\n\
+              Message-ID: <vote@example.net>\n\
+              In-Reply-To: <poll@example.net>\n\
+              Chat-Version: 1.0\n\
+              Chat-Poll-Vote: 1\n\
+              Date: Sun, 22 Mar 2020 22:38:57 +0000\n\
+              \n\
+              Sushi\n",
+            "INBOX",
+            2,
+            false,
+        )
+        .await
+        .unwrap();
+        let state = msg.get_poll_state(&t.ctx).await.unwrap();
+        assert_eq!(state.options[1].voters, vec![bob]);
+        assert_eq!(state.self_vote, None);
+        assert_eq!(get_chat_msgs(&t.ctx, chat_id, 0, None).await, vec![msg_id]);
+    }
+}
@@ -58,6 +58,15 @@ impl Server {
     }
 }
 
+/// OAuth2 authorizer to use for logging in to the provider, see the `oauth2` module.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(u8)]
+pub enum Oauth2Authorizer {
+    Gmail = 1,
+    Office365 = 2,
+    Yandex = 3,
+}
+
 #[derive(Debug)]
 pub struct ConfigDefault {
     pub key: Config,
@@ -72,6 +81,7 @@ pub struct Provider {
     pub overview_page: &'static str,
     pub server: Vec<Server>,
     pub config_defaults: Option<Vec<ConfigDefault>>,
+    pub oauth2_authorizer: Option<Oauth2Authorizer>,
 }
 
 impl Provider {
@@ -93,18 +103,21 @@ impl Provider {
     }
 }
 
-pub fn get_provider_info(addr: &str) -> Option<&Provider> {
-    let domain = match addr.parse::<EmailAddress>() {
-        Ok(addr) => addr.domain,
-        Err(_err) => return None,
-    }
-    .to_lowercase();
-
-    if let Some(provider) = PROVIDER_DATA.get(domain.as_str()) {
-        return Some(*provider);
+/// Returns the provider information for the domain of the given email address.
+pub fn get_provider_info(addr: &str) -> Option<&'static Provider> {
+    match addr.parse::<EmailAddress>() {
+        Ok(addr) => get_info(&addr.domain),
+        Err(_err) => None,
     }
+}
 
-    None
+/// Returns the provider information for the given domain.
+///
+/// This allows showing provider specific hints before the user entered
+/// a complete email address.
+pub fn get_info(domain: &str) -> Option<&'static Provider> {
+    let domain = domain.trim().trim_end_matches('.').to_lowercase();
+    PROVIDER_DATA.get(domain.as_str()).copied()
 }
 
 #[cfg(test)]
@@ -151,4 +164,22 @@ mod tests {
         let provider = get_provider_info("user@googlemail.com").unwrap();
         assert!(provider.status == Status::PREPARATION);
     }
+
+    #[test]
+    fn test_get_info() {
+        assert!(get_info("unexistant.org").is_none());
+        assert!(get_info("user@gmail.com").is_none());
+
+        let provider = get_info("GMail.com").unwrap();
+        assert!(provider.status == Status::PREPARATION);
+        assert_eq!(provider.oauth2_authorizer, Some(Oauth2Authorizer::Gmail));
+
+        let provider = get_info("nauta.cu.").unwrap();
+        assert!(provider.status == Status::OK);
+        assert_eq!(provider.oauth2_authorizer, None);
+
+        let provider = get_info("example.com").unwrap();
+        assert!(provider.status == Status::BROKEN);
+        assert!(!provider.before_login_hint.is_empty());
+    }
 }
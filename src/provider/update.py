@@ -46,6 +46,21 @@ def process_config_defaults(data):
     return defaults
 
 
+def process_oauth2(data):
+    if not "oauth2" in data:
+        return "None"
+    authorizer = str(data["oauth2"]).lower()
+    if authorizer == "gmail":
+        return "Some(Oauth2Authorizer::Gmail)"
+    elif authorizer == "office365":
+        # no OAuth2 client ID is registered for Office365 yet,
+        # see the oauth2 module
+        return "None"
+    elif authorizer == "yandex":
+        return "Some(Oauth2Authorizer::Yandex)"
+    raise TypeError("bad oauth2 authorizer: " + authorizer)
+
+
 def process_data(data, file):
     status = data.get("status", "")
     if status != "OK" and status != "PREPARATION" and status != "BROKEN":
@@ -99,6 +114,7 @@ def process_data(data, file):
             + hostname + "\", port: " + str(port) + ", username_pattern: " + username_pattern + " },\n")
 
     config_defaults = process_config_defaults(data)
+    oauth2 = process_oauth2(data)
 
     provider = ""
     before_login_hint = cleanstr(data.get("before_login_hint", ""))
@@ -111,6 +127,7 @@ def process_data(data, file):
         provider += "        overview_page: \"" + file2url(file) + "\",\n"
         provider += "        server: vec![\n" + server + "        ],\n"
         provider += "        config_defaults: " + config_defaults + ",\n"
+        provider += "        oauth2_authorizer: " + oauth2 + ",\n"
         provider += "    };\n\n"
     else:
         raise TypeError("SMTP and IMAP must be specified together or left out both")
@@ -121,7 +138,7 @@ def process_data(data, file):
     # finally, add the provider
     global out_all, out_domains
     out_all += "    // " + file[file.rindex("/")+1:] + ": " + comment.strip(", ") + "\n"
-    if status == "OK" and before_login_hint == "" and after_login_hint == "" and server == "" and config_defaults == "None":
+    if status == "OK" and before_login_hint == "" and after_login_hint == "" and server == "" and config_defaults == "None" and oauth2 == "None":
         out_all += "    // - skipping provider with status OK and no special things to do\n\n"
     else:
         out_all += provider
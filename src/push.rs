@@ -0,0 +1,170 @@
+//! # Push notifications
+//!
+//! The UI registers the push token of the device using
+//! [Context::set_push_device_token].  If the IMAP server supports the
+//! `XDELTAPUSH` capability of the notification proxy, the token is stored
+//! as `/private/devicetoken` metadata of the inbox so that the server can
+//! wake up the device when new messages arrive.
+//!
+//! On wakeup, the UI calls [fetch_on_wakeup] which fetches the inbox once
+//! within the time the operating system grants for background execution.
+
+use std::time::Duration;
+
+use async_std::future::timeout;
+use async_std::sync::channel;
+
+use crate::config::Config;
+use crate::context::Context;
+use crate::error::{bail, ensure, format_err, Error, Result};
+use crate::imap::Imap;
+use crate::scheduler::InterruptInfo;
+use crate::sql;
+
+/// Raw config key of the push token of the device.
+const DEVICE_TOKEN_KEY: &str = "device_token";
+
+/// Raw config key of the push token last uploaded to the server.
+const UPLOADED_TOKEN_KEY: &str = "device_token_uploaded";
+
+/// Maximum duration of [fetch_on_wakeup].
+///
+/// iOS grants about 30 seconds of background execution on a notification.
+const WAKEUP_FETCH_TIMEOUT: Duration = Duration::from_secs(25);
+
+impl Context {
+    /// Sets the push token of the device.
+    ///
+    /// The token is uploaded to the server the next time IMAP is connected,
+    /// if the server supports push notifications.
+    pub async fn set_push_device_token(&self, token: &str) -> Result<()> {
+        ensure!(is_valid_token(token), "Invalid push device token");
+        self.sql
+            .set_raw_config(self, DEVICE_TOKEN_KEY, Some(token))
+            .await?;
+        self.interrupt_inbox(InterruptInfo::new(false, None)).await;
+        Ok(())
+    }
+
+    /// Returns the push token of the device, if one is set.
+    pub async fn get_push_device_token(&self) -> Option<String> {
+        self.sql.get_raw_config(self, DEVICE_TOKEN_KEY).await
+    }
+}
+
+/// Returns the push token if it is not yet uploaded to the server.
+pub(crate) async fn token_to_upload(context: &Context) -> Option<String> {
+    let token = context.get_push_device_token().await?;
+    let uploaded = context
+        .sql
+        .get_raw_config(context, UPLOADED_TOKEN_KEY)
+        .await;
+    if uploaded.as_ref() == Some(&token) {
+        None
+    } else {
+        Some(token)
+    }
+}
+
+pub(crate) async fn set_token_uploaded(context: &Context, token: &str) -> sql::Result<()> {
+    context
+        .sql
+        .set_raw_config(context, UPLOADED_TOKEN_KEY, Some(token))
+        .await
+}
+
+/// Tokens are embedded into IMAP commands as quoted strings,
+/// the common token formats are hexadecimal or base64.
+fn is_valid_token(token: &str) -> bool {
+    !token.is_empty()
+        && token.len() <= 1024
+        && token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_:.+/=".contains(c))
+}
+
+/// Fetches new messages from the inbox once and returns.
+///
+/// This is meant to be called when the app is woken up by a push
+/// notification while IO is not running, eg. on iOS.  The fetch is aborted
+/// after a fixed timeout.  If IO is running, the inbox loop is interrupted
+/// instead.
+pub async fn fetch_on_wakeup(context: &Context) -> Result<()> {
+    ensure!(context.is_configured().await, "Not configured");
+    if context.is_io_running().await {
+        context
+            .interrupt_inbox(InterruptInfo::new(false, None))
+            .await;
+        return Ok(());
+    }
+
+    info!(context, "fetching on wakeup");
+    // nobody interrupts this connection, the timeout below ends it.
+    let (_sender, receiver) = channel(1);
+    let mut imap = Imap::new(receiver);
+    let res = timeout(WAKEUP_FETCH_TIMEOUT, async {
+        imap.connect_configured(context).await?;
+        let folder = context
+            .get_config(Config::ConfiguredInboxFolder)
+            .await
+            .ok_or_else(|| format_err!("No inbox folder configured"))?;
+        imap.fetch(context, &folder).await?;
+        Ok::<(), Error>(())
+    })
+    .await;
+    imap.disconnect(context).await;
+
+    match res {
+        Ok(res) => res,
+        Err(_) => bail!(
+            "Fetching on wakeup timed out after {} seconds",
+            WAKEUP_FETCH_TIMEOUT.as_secs()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_is_valid_token() {
+        assert!(is_valid_token(
+            "740f4707bebcf74f9b7c25d48e3358945f6aa01da5ddb387462c7eaf61bb78ad"
+        ));
+        assert!(is_valid_token("fcm:dGVzdA+/="));
+        assert!(!is_valid_token(""));
+        assert!(!is_valid_token("abc\")"));
+        assert!(!is_valid_token("abc def"));
+    }
+
+    #[async_std::test]
+    async fn test_push_device_token() {
+        let t = dummy_context().await;
+        assert_eq!(t.ctx.get_push_device_token().await, None);
+        assert_eq!(token_to_upload(&t.ctx).await, None);
+        assert!(t.ctx.set_push_device_token("a\"b").await.is_err());
+
+        t.ctx.set_push_device_token("0123abcd").await.unwrap();
+        assert_eq!(
+            t.ctx.get_push_device_token().await,
+            Some("0123abcd".to_string())
+        );
+        assert_eq!(token_to_upload(&t.ctx).await, Some("0123abcd".to_string()));
+
+        set_token_uploaded(&t.ctx, "0123abcd").await.unwrap();
+        assert_eq!(token_to_upload(&t.ctx).await, None);
+
+        // a changed token is uploaded again
+        t.ctx.set_push_device_token("4567").await.unwrap();
+        assert_eq!(token_to_upload(&t.ctx).await, Some("4567".to_string()));
+    }
+
+    #[async_std::test]
+    async fn test_fetch_on_wakeup_unconfigured() {
+        let t = dummy_context().await;
+        assert!(fetch_on_wakeup(&t.ctx).await.is_err());
+    }
+}
@@ -1,22 +1,35 @@
 //! # QR code module
 
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use http_types::{Method, Request};
 use lazy_static::lazy_static;
+use num_traits::FromPrimitive;
 use percent_encoding::percent_decode_str;
 use serde::Deserialize;
 
 use crate::chat;
 use crate::config::*;
-use crate::constants::Blocked;
+use crate::constants::{
+    Blocked, DC_LP_IMAP_SOCKET_PLAIN, DC_LP_IMAP_SOCKET_SSL, DC_LP_IMAP_SOCKET_STARTTLS,
+    DC_LP_SMTP_SOCKET_PLAIN, DC_LP_SMTP_SOCKET_SSL, DC_LP_SMTP_SOCKET_STARTTLS,
+};
 use crate::contact::*;
 use crate::context::Context;
-use crate::error::{bail, ensure, format_err, Error};
+use crate::error::{ensure, format_err, Error};
 use crate::key::Fingerprint;
+use crate::login_param::CertificateChecks;
 use crate::lot::{Lot, LotState};
 use crate::param::*;
 use crate::peerstate::*;
+use crate::socks::send_http;
 
 const OPENPGP4FPR_SCHEME: &str = "OPENPGP4FPR:"; // yes: uppercase
 const DCACCOUNT_SCHEME: &str = "DCACCOUNT:";
+const DCLOGIN_SCHEME: &str = "dclogin:";
+const DCSECONDDEVICE_SCHEME: &str = "DCSECONDDEVICE:";
 const MAILTO_SCHEME: &str = "mailto:";
 const MATMSG_SCHEME: &str = "MATMSG:";
 const VCARD_SCHEME: &str = "BEGIN:VCARD";
@@ -51,6 +64,10 @@ pub async fn check_qr(context: &Context, qr: impl AsRef<str>) -> Lot {
         decode_openpgp(context, qr).await
     } else if starts_with_ignore_case(qr, DCACCOUNT_SCHEME) {
         decode_account(context, qr)
+    } else if starts_with_ignore_case(qr, DCLOGIN_SCHEME) {
+        decode_login(context, qr)
+    } else if starts_with_ignore_case(qr, DCSECONDDEVICE_SCHEME) {
+        decode_second_device(context, qr)
     } else if qr.starts_with(MAILTO_SCHEME) {
         decode_mailto(context, qr).await
     } else if qr.starts_with(SMTP_SCHEME) {
@@ -188,44 +205,264 @@ async fn decode_openpgp(context: &Context, qr: &str) -> Lot {
 
 /// scheme: `DCACCOUNT:https://example.org/new_email?t=1w_7wDjgjelxeX884x96v3`
 fn decode_account(_context: &Context, qr: &str) -> Lot {
-    let payload = &qr[DCACCOUNT_SCHEME.len()..];
-
     let mut lot = Lot::new();
-
-    if let Ok(url) = url::Url::parse(payload) {
-        if url.scheme() == "https" {
+    match parse_account_url(qr) {
+        Ok(url) => {
             lot.state = LotState::QrAccount;
             lot.text1 = url.host_str().map(|x| x.to_string());
-        } else {
+        }
+        Err(err) => {
             lot.state = LotState::QrError;
-            lot.text1 = Some(format!("Bad scheme for account url: {}", payload));
+            lot.text1 = Some(err.to_string());
         }
-    } else {
-        lot.state = LotState::QrError;
-        lot.text1 = Some(format!("Invalid account url: {}", payload));
     }
+    lot
+}
 
+/// Returns the account creation endpoint of a `DCACCOUNT:` QR code.
+fn parse_account_url(qr: &str) -> Result<url::Url, Error> {
+    ensure!(
+        starts_with_ignore_case(qr, DCACCOUNT_SCHEME),
+        "Not an account QR code"
+    );
+    let payload = &qr[DCACCOUNT_SCHEME.len()..];
+    let url =
+        url::Url::parse(payload).map_err(|_| format_err!("Invalid account url: {}", payload))?;
+    ensure!(
+        url.scheme() == "https",
+        "Bad scheme for account url: {}",
+        payload
+    );
+    Ok(url)
+}
+
+/// scheme: `dclogin:ADDR?p=PASSWORD&v=1`, see [parse_login_qr] for the optional parameters
+fn decode_login(_context: &Context, qr: &str) -> Lot {
+    let mut lot = Lot::new();
+    match parse_login_qr(qr) {
+        Ok(config) => {
+            lot.state = LotState::QrLogin;
+            lot.text1 = config
+                .into_iter()
+                .find(|(key, _)| *key == Config::Addr)
+                .and_then(|(_, addr)| addr);
+        }
+        Err(err) => {
+            lot.state = LotState::QrError;
+            lot.text1 = Some(err.to_string());
+        }
+    }
     lot
 }
 
+/// Returns the configuration given by a `dclogin:` QR code.
+///
+/// Besides the address and the password `p`, the QR code may contain the
+/// version `v`, which must be `1` if given, and advanced server parameters:
+/// `ih`, `ip`, `is`, `iu` and `ipw` set the IMAP server, port, security,
+/// user and password, `sh`, `sp`, `ss`, `su` and `spw` the same for SMTP.
+/// The security is one of `ssl`, `starttls`, `plain` or `default`.
+/// `ic` and `sc` set the IMAP and SMTP certificate checks
+/// as `imap_certificate_checks`.
+///
+/// Parameters not given are returned as `None`
+/// so that settings of a previous login are reset.
+fn parse_login_qr(qr: &str) -> Result<Vec<(Config, Option<String>)>, Error> {
+    ensure!(
+        starts_with_ignore_case(qr, DCLOGIN_SCHEME),
+        "Not a login QR code"
+    );
+    let payload = qr[DCLOGIN_SCHEME.len()..].trim_start_matches('/');
+    let (addr, query) = match payload.find('?') {
+        Some(index) => (&payload[..index], &payload[index + 1..]),
+        None => (payload, ""),
+    };
+    let addr = normalize_address(addr.trim_end_matches('/'))?;
+    let params: HashMap<String, String> = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .filter(|(_, value)| !value.is_empty())
+        .collect();
+    let get = |key: &str| params.get(key).cloned();
+
+    if let Some(version) = params.get("v") {
+        ensure!(
+            version == "1",
+            "Unsupported login QR code version {}",
+            version
+        );
+    }
+    let password = get("p").ok_or_else(|| format_err!("Missing password in login QR code"))?;
+
+    let server_flags = parse_socket_security(
+        get("is"),
+        DC_LP_IMAP_SOCKET_SSL,
+        DC_LP_IMAP_SOCKET_STARTTLS,
+        DC_LP_IMAP_SOCKET_PLAIN,
+    )? | parse_socket_security(
+        get("ss"),
+        DC_LP_SMTP_SOCKET_SSL as i32,
+        DC_LP_SMTP_SOCKET_STARTTLS as i32,
+        DC_LP_SMTP_SOCKET_PLAIN as i32,
+    )?;
+
+    Ok(vec![
+        (Config::Addr, Some(addr)),
+        (Config::MailPw, Some(get("ipw").unwrap_or(password))),
+        (Config::MailServer, get("ih")),
+        (Config::MailPort, parse_port(get("ip"))?),
+        (Config::MailUser, get("iu")),
+        (
+            Config::ImapCertificateChecks,
+            parse_certificate_checks(get("ic"))?,
+        ),
+        (Config::SendServer, get("sh")),
+        (Config::SendPort, parse_port(get("sp"))?),
+        (Config::SendUser, get("su")),
+        (Config::SendPw, get("spw")),
+        (
+            Config::SmtpCertificateChecks,
+            parse_certificate_checks(get("sc"))?,
+        ),
+        (
+            Config::ServerFlags,
+            if server_flags != 0 {
+                Some(server_flags.to_string())
+            } else {
+                None
+            },
+        ),
+    ])
+}
+
+fn parse_port(port: Option<String>) -> Result<Option<String>, Error> {
+    match port {
+        Some(port) => match port.parse::<u16>() {
+            Ok(port) if port > 0 => Ok(Some(port.to_string())),
+            _ => Err(format_err!("Invalid port {} in login QR code", port)),
+        },
+        None => Ok(None),
+    }
+}
+
+fn parse_certificate_checks(checks: Option<String>) -> Result<Option<String>, Error> {
+    match checks {
+        Some(checks) => {
+            let value = checks
+                .parse::<i32>()
+                .ok()
+                .and_then(CertificateChecks::from_i32)
+                .ok_or_else(|| {
+                    format_err!("Invalid certificate checks {} in login QR code", checks)
+                })?;
+            Ok(Some((value as i32).to_string()))
+        }
+        None => Ok(None),
+    }
+}
+
+fn parse_socket_security(
+    security: Option<String>,
+    ssl: i32,
+    starttls: i32,
+    plain: i32,
+) -> Result<i32, Error> {
+    match security.as_deref() {
+        None | Some("default") => Ok(0),
+        Some("ssl") => Ok(ssl),
+        Some("starttls") => Ok(starttls),
+        Some("plain") => Ok(plain),
+        Some(other) => Err(format_err!("Invalid security {} in login QR code", other)),
+    }
+}
+
+/// scheme: `DCSECONDDEVICE:SECRET#IP:PORT`
+fn decode_second_device(_context: &Context, qr: &str) -> Lot {
+    let mut lot = Lot::new();
+    match parse_second_device_qr(qr) {
+        Ok((_, addr)) => {
+            lot.state = LotState::QrSecondDevice;
+            lot.text1 = Some(addr.to_string());
+        }
+        Err(err) => {
+            lot.state = LotState::QrError;
+            lot.text1 = Some(err.to_string());
+        }
+    }
+    lot
+}
+
+/// Renders the QR code scanned by a second device, see `imex::provide_second_device`.
+pub(crate) fn render_second_device_qr(secret: &str, addr: &SocketAddr) -> String {
+    format!("{}{}#{}", DCSECONDDEVICE_SCHEME, secret, addr)
+}
+
+/// Returns the one-time secret and the address of the providing device.
+pub(crate) fn parse_second_device_qr(qr: &str) -> Result<(String, SocketAddr), Error> {
+    ensure!(
+        starts_with_ignore_case(qr, DCSECONDDEVICE_SCHEME),
+        "Not a second device QR code"
+    );
+    let payload = &qr[DCSECONDDEVICE_SCHEME.len()..];
+    let offset = payload
+        .find('#')
+        .ok_or_else(|| format_err!("Missing address in second device QR code"))?;
+    let (secret, addr) = payload.split_at(offset);
+    ensure!(
+        !secret.is_empty(),
+        "Missing secret in second device QR code"
+    );
+    let addr = addr[1..]
+        .parse::<SocketAddr>()
+        .map_err(|err| format_err!("Invalid address in second device QR code: {}", err))?;
+    Ok((secret.to_string(), addr))
+}
+
+/// Timeout for the request to the account creation endpoint.
+const CREATE_ACCOUNT_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[derive(Debug, Deserialize)]
 struct CreateAccountResponse {
     email: String,
     password: String,
 }
 
-/// take a qr of the type DC_QR_ACCOUNT, parse it's parameters,
-/// download additional information from the contained url and set the parameters.
-/// on success, a configure::configure() should be able to log in to the account
-pub async fn set_config_from_qr(context: &Context, qr: &str) -> Result<(), Error> {
-    let url_str = &qr[DCACCOUNT_SCHEME.len()..];
+#[derive(Debug, Deserialize)]
+struct CreateAccountErrorResponse {
+    reason: String,
+}
 
-    let response: Result<CreateAccountResponse, surf::Error> =
-        surf::post(url_str).recv_json().await;
-    if response.is_err() {
-        bail!("Cannot create account, request to {} failed", url_str);
+/// Sets the configuration given by a QR code of the type DC_QR_ACCOUNT or DC_QR_LOGIN.
+///
+/// For DC_QR_ACCOUNT, the credentials are requested from the url contained
+/// in the QR code.  On success, [Context::configure] should be able to log
+/// in to the account.
+pub async fn set_config_from_qr(context: &Context, qr: &str) -> Result<(), Error> {
+    if starts_with_ignore_case(qr, DCLOGIN_SCHEME) {
+        for (key, value) in parse_login_qr(qr)? {
+            context.set_config(key, value.as_deref()).await?;
+        }
+        return Ok(());
     }
-    let parsed = response.unwrap();
+
+    let url = parse_account_url(qr)?;
+    info!(context, "Requesting new account from {}", url.as_str());
+    let request = async {
+        let mut response = send_http(context, Request::new(Method::Post, url.clone()))
+            .await
+            .map_err(|err| {
+                format_err!("Cannot create account, request to {} failed: {}", url, err)
+            })?;
+        let body = response
+            .body_string()
+            .await
+            .map_err(|err| format_err!("Cannot create account, bad response: {}", err))?;
+        Ok::<_, Error>((response.status(), body))
+    };
+    let (status, body) = async_std::future::timeout(CREATE_ACCOUNT_TIMEOUT, request)
+        .await
+        .map_err(|_| format_err!("Cannot create account, request to {} timed out", url))??;
+    let parsed = parse_create_account_response(status.is_success(), &body)
+        .map_err(|err| format_err!("Cannot create account: {}", err))?;
 
     context
         .set_config(Config::Addr, Some(&parsed.email))
@@ -237,6 +474,33 @@ pub async fn set_config_from_qr(context: &Context, qr: &str) -> Result<(), Error
     Ok(())
 }
 
+/// Parses the JSON returned by an account creation endpoint.
+///
+/// On errors, the endpoint may return a `reason` to show to the user.
+fn parse_create_account_response(
+    success: bool,
+    body: &str,
+) -> Result<CreateAccountResponse, Error> {
+    if !success {
+        return match serde_json::from_str::<CreateAccountErrorResponse>(body) {
+            Ok(error) => Err(format_err!("{}", error.reason)),
+            Err(_) => Err(format_err!("The server refused to create an account")),
+        };
+    }
+    let parsed: CreateAccountResponse = serde_json::from_str(body)
+        .map_err(|err| format_err!("Invalid response from server: {}", err))?;
+    ensure!(
+        may_be_valid_addr(&parsed.email),
+        "Invalid address \"{}\" returned by server",
+        parsed.email
+    );
+    ensure!(
+        !parsed.password.is_empty(),
+        "No password returned by server"
+    );
+    Ok(parsed)
+}
+
 /// Extract address for the mailto scheme.
 ///
 /// Scheme: `mailto:addr...?subject=...&body=..`
@@ -611,6 +875,138 @@ mod tests {
         assert_eq!(res.get_text1().unwrap(), "example.org");
     }
 
+    #[test]
+    fn test_parse_create_account_response() {
+        let parsed = parse_create_account_response(
+            true,
+            r#"{"email":"a1b2c3@example.org","password":"s3cr3t"}"#,
+        )
+        .unwrap();
+        assert_eq!(parsed.email, "a1b2c3@example.org");
+        assert_eq!(parsed.password, "s3cr3t");
+
+        assert!(parse_create_account_response(true, "{}").is_err());
+        assert!(
+            parse_create_account_response(true, r#"{"email":"foo","password":"s3cr3t"}"#).is_err()
+        );
+        assert!(parse_create_account_response(
+            true,
+            r#"{"email":"a1b2c3@example.org","password":""}"#
+        )
+        .is_err());
+
+        let err =
+            parse_create_account_response(false, r#"{"reason":"no more accounts"}"#).unwrap_err();
+        assert_eq!(err.to_string(), "no more accounts");
+        assert!(parse_create_account_response(false, "Internal Server Error").is_err());
+    }
+
+    #[async_std::test]
+    async fn test_set_config_from_qr_bad_scheme() {
+        let ctx = dummy_context().await;
+        assert!(
+            set_config_from_qr(&ctx.ctx, "DCACCOUNT:http://example.org/new_email")
+                .await
+                .is_err()
+        );
+        assert!(
+            set_config_from_qr(&ctx.ctx, "https://example.org/new_email")
+                .await
+                .is_err()
+        );
+        assert!(ctx.ctx.get_config(Config::Addr).await.is_none());
+    }
+
+    #[async_std::test]
+    async fn test_decode_login() {
+        let ctx = dummy_context().await;
+
+        let res = check_qr(&ctx.ctx, "dclogin:alice@example.org?p=s3cr3t&v=1").await;
+        assert_eq!(res.get_state(), LotState::QrLogin);
+        assert_eq!(res.get_text1().unwrap(), "alice@example.org");
+
+        let res = check_qr(&ctx.ctx, "DCLOGIN://alice@example.org/?p=s3cr3t").await;
+        assert_eq!(res.get_state(), LotState::QrLogin);
+        assert_eq!(res.get_text1().unwrap(), "alice@example.org");
+
+        for qr in &[
+            "dclogin:alice@example.org",
+            "dclogin:alice@example.org?p=",
+            "dclogin:alice@example.org?p=s3cr3t&v=2",
+            "dclogin:alice?p=s3cr3t",
+            "dclogin:alice@example.org?p=s3cr3t&ip=imap",
+            "dclogin:alice@example.org?p=s3cr3t&is=tls",
+            "dclogin:alice@example.org?p=s3cr3t&sc=5",
+        ] {
+            let res = check_qr(&ctx.ctx, qr).await;
+            assert_eq!(res.get_state(), LotState::QrError, "{}", qr);
+        }
+    }
+
+    #[async_std::test]
+    async fn test_set_config_from_login_qr() {
+        let ctx = dummy_context().await;
+        ctx.ctx
+            .set_config(Config::MailServer, Some("old.example.org"))
+            .await
+            .unwrap();
+
+        set_config_from_qr(
+            &ctx.ctx,
+            "dclogin:alice@example.org?p=s3cr3t%26x&v=1&sh=smtp.example.org&sp=465&ss=ssl&su=alice&ic=3",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            ctx.ctx.get_config(Config::Addr).await.unwrap(),
+            "alice@example.org"
+        );
+        assert_eq!(
+            ctx.ctx.get_config(Config::MailPw).await.unwrap(),
+            "s3cr3t&x"
+        );
+        assert!(ctx.ctx.get_config(Config::MailServer).await.is_none());
+        assert_eq!(
+            ctx.ctx.get_config(Config::SendServer).await.unwrap(),
+            "smtp.example.org"
+        );
+        assert_eq!(ctx.ctx.get_config(Config::SendPort).await.unwrap(), "465");
+        assert_eq!(ctx.ctx.get_config(Config::SendUser).await.unwrap(), "alice");
+        assert_eq!(
+            ctx.ctx
+                .get_config(Config::ImapCertificateChecks)
+                .await
+                .unwrap(),
+            "3"
+        );
+        assert_eq!(
+            ctx.ctx.get_config_int(Config::ServerFlags).await,
+            DC_LP_SMTP_SOCKET_SSL as i32
+        );
+    }
+
+    #[async_std::test]
+    async fn test_decode_second_device() {
+        let ctx = dummy_context().await;
+
+        let res = check_qr(&ctx.ctx, "DCSECONDDEVICE:s3cr3t#192.168.1.2:4711").await;
+        assert_eq!(res.get_state(), LotState::QrSecondDevice);
+        assert_eq!(res.get_text1().unwrap(), "192.168.1.2:4711");
+
+        let (secret, addr) =
+            parse_second_device_qr("DCSECONDDEVICE:s3cr3t#[fe80::1]:4711").unwrap();
+        assert_eq!(secret, "s3cr3t");
+        assert_eq!(
+            render_second_device_qr(&secret, &addr),
+            "DCSECONDDEVICE:s3cr3t#[fe80::1]:4711"
+        );
+
+        let res = check_qr(&ctx.ctx, "DCSECONDDEVICE:s3cr3t").await;
+        assert_eq!(res.get_state(), LotState::QrError);
+        let res = check_qr(&ctx.ctx, "DCSECONDDEVICE:#192.168.1.2:4711").await;
+        assert_eq!(res.get_state(), LotState::QrError);
+    }
+
     #[async_std::test]
     async fn test_decode_account_bad_scheme() {
         let ctx = dummy_context().await;
@@ -0,0 +1,323 @@
+//! # Reactions to messages
+//!
+//! A reaction is sent as a small hidden chat message referring to the
+//! reacted message by `In-Reply-To:`; the reaction itself is carried in
+//! the `Chat-Reaction:` header.  Every contact has at most one reaction
+//! per message, an empty reaction removes the previous one.
+
+use crate::chat::{self, ChatId};
+use crate::constants::*;
+use crate::context::Context;
+use crate::dc_tools::time;
+use crate::error::{ensure, format_err, Result};
+use crate::events::Event;
+use crate::headerdef::HeaderDef;
+use crate::message::{self, Message, MsgId};
+use crate::mimeparser::{parse_message_id, MimeMessage, SystemMessage};
+use crate::param::*;
+
+/// Sends a reaction to a message.
+///
+/// The reaction replaces the previous reaction of the self-user,
+/// an empty reaction removes it.  Returns the ID of the hidden message
+/// carrying the reaction.
+pub async fn send_reaction(
+    context: &Context,
+    msg_id: MsgId,
+    reaction: impl AsRef<str>,
+) -> Result<MsgId> {
+    let reaction = reaction.as_ref().trim();
+    let msg = Message::load_from_db(context, msg_id).await?;
+    ensure!(
+        !msg.chat_id.is_special(),
+        "Cannot react to message {} in special chat",
+        msg_id
+    );
+    ensure!(
+        !msg.rfc724_mid.is_empty(),
+        "Cannot react to message {} without Message-ID",
+        msg_id
+    );
+
+    set_reaction(
+        context,
+        msg.chat_id,
+        msg_id,
+        DC_CONTACT_ID_SELF,
+        reaction,
+        time(),
+    )
+    .await?;
+
+    let mut reaction_msg = Message::new(Viewtype::Text);
+    reaction_msg.text = Some(reaction.to_string());
+    reaction_msg.hidden = true;
+    reaction_msg.param.set_cmd(SystemMessage::Reaction);
+    reaction_msg.param.set(Param::Arg, &msg.rfc724_mid);
+    Ok(chat::send_msg(context, msg.chat_id, &mut reaction_msg).await?)
+}
+
+/// Returns the reactions to a message as `(contact_id, reaction)`,
+/// oldest first.
+pub async fn get_reactions(context: &Context, msg_id: MsgId) -> Result<Vec<(u32, String)>> {
+    let reactions = context
+        .sql
+        .query_map(
+            "SELECT contact_id, reaction FROM reactions
+              WHERE msg_id=? AND reaction!=''
+              ORDER BY timestamp, contact_id;",
+            paramsv![msg_id],
+            |row| Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?)),
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    Ok(reactions)
+}
+
+/// Stores the reaction of a contact sent at `timestamp`, an empty reaction removes it.
+///
+/// Reactions older than the stored one are ignored, so reactions
+/// received out of order do not replace newer ones.  Removed reactions
+/// are stored as empty reactions to keep their timestamp.
+async fn set_reaction(
+    context: &Context,
+    chat_id: ChatId,
+    msg_id: MsgId,
+    contact_id: u32,
+    reaction: &str,
+    timestamp: i64,
+) -> Result<()> {
+    let stored_timestamp = context
+        .sql
+        .query_get_value_result::<i64>(
+            "SELECT timestamp FROM reactions WHERE msg_id=? AND contact_id=?;",
+            paramsv![msg_id, contact_id as i32],
+        )
+        .await?;
+    if let Some(stored_timestamp) = stored_timestamp {
+        if timestamp < stored_timestamp {
+            info!(
+                context,
+                "Ignoring outdated reaction of contact {} to message {}", contact_id, msg_id
+            );
+            return Ok(());
+        }
+    }
+
+    context
+        .sql
+        .execute(
+            "INSERT OR REPLACE INTO reactions (msg_id, contact_id, reaction, timestamp)
+             VALUES (?,?,?,?);",
+            paramsv![msg_id, contact_id as i32, reaction, timestamp],
+        )
+        .await?;
+    context.emit_event(Event::ReactionsChanged { chat_id, msg_id });
+    Ok(())
+}
+
+/// Applies a reaction received from `from_id` sent at `sent_timestamp`.
+///
+/// Reactions to unknown messages are ignored.
+pub(crate) async fn receive_reaction(
+    context: &Context,
+    mime_parser: &MimeMessage,
+    from_id: u32,
+    sent_timestamp: i64,
+) -> Result<()> {
+    let in_reply_to = mime_parser
+        .get(HeaderDef::InReplyTo)
+        .ok_or_else(|| format_err!("Reaction without In-Reply-To"))?;
+    let rfc724_mid = parse_message_id(in_reply_to)?;
+    let reaction = mime_parser
+        .get(HeaderDef::ChatReaction)
+        .map(|reaction| reaction.trim())
+        .unwrap_or_default();
+
+    match message::rfc724_mid_exists(context, &rfc724_mid).await? {
+        Some((_, _, msg_id)) => {
+            let msg = Message::load_from_db(context, msg_id).await?;
+            ensure!(
+                from_id == DC_CONTACT_ID_SELF
+                    || chat::is_contact_in_chat(context, msg.chat_id, from_id).await,
+                "Reaction to message {} from contact {} not in chat",
+                msg_id,
+                from_id
+            );
+            set_reaction(
+                context,
+                msg.chat_id,
+                msg_id,
+                from_id,
+                reaction,
+                sent_timestamp,
+            )
+            .await
+        }
+        None => {
+            info!(
+                context,
+                "Ignoring reaction to unknown message {}", rfc724_mid
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::chat::get_chat_msgs;
+    use crate::contact::Contact;
+    use crate::dc_receive_imf::dc_receive_imf;
+    use crate::message::get_reaction_counts;
+    use crate::test_utils::*;
+
+    #[async_std::test]
+    async fn test_send_reaction() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        let bob = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob).await.unwrap();
+        let msg_id = chat::send_text_msg(&t.ctx, chat_id, "hi".to_string())
+            .await
+            .unwrap();
+
+        let reaction_msg_id = send_reaction(&t.ctx, msg_id, "👍").await.unwrap();
+        assert_eq!(
+            get_reactions(&t.ctx, msg_id).await.unwrap(),
+            vec![(DC_CONTACT_ID_SELF, "👍".to_string())]
+        );
+        assert_eq!(get_chat_msgs(&t.ctx, chat_id, 0, None).await, vec![msg_id]);
+
+        let reaction_msg = Message::load_from_db(&t.ctx, reaction_msg_id)
+            .await
+            .unwrap();
+        assert!(reaction_msg.hidden);
+        assert_eq!(reaction_msg.param.get_cmd(), SystemMessage::Reaction);
+        let in_reply_to: String = t
+            .ctx
+            .sql
+            .query_get_value(
+                &t.ctx,
+                "SELECT mime_in_reply_to FROM msgs WHERE id=?;",
+                paramsv![reaction_msg_id],
+            )
+            .await
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(in_reply_to, msg.rfc724_mid);
+
+        send_reaction(&t.ctx, msg_id, "").await.unwrap();
+        assert!(get_reactions(&t.ctx, msg_id).await.unwrap().is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_receive_reaction() {
+        let t = dummy_context().await;
+        configure_alice_keypair(&t.ctx).await;
+        let bob = Contact::create(&t.ctx, "", "bob@example.net")
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, bob).await.unwrap();
+        let msg_id = chat::send_text_msg(&t.ctx, chat_id, "hi".to_string())
+            .await
+            .unwrap();
+        let msg = Message::load_from_db(&t.ctx, msg_id).await.unwrap();
+        send_reaction(&t.ctx, msg_id, "👍").await.unwrap();
+
+        for (uid, reaction) in &[
+            (1, "=?utf-8?q?=F0=9F=91=8D?="),
+            (2, "=?utf-8?q?=E2=9D=A4?="),
+        ] {
+            let raw = format!(
+                "From: bob@example.net\n\
+                 To: alice@example.com\n\
+                 Subject: Chat: reaction\n\
+                 Message-ID: <reaction{}@example.net>\n\
+                 In-Reply-To: <{}>\n\
+                 Chat-Version: 1.0\n\
+                 Chat-Reaction: {}\n\
+                 Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                 \n\
+                 reaction\n",
+                uid, msg.rfc724_mid, reaction
+            );
+            dc_receive_imf(&t.ctx, raw.as_bytes(), "INBOX", *uid, false)
+                .await
+                .unwrap();
+        }
+
+        // the second reaction of bob replaces the first one
+        assert_eq!(
+            get_reactions(&t.ctx, msg_id).await.unwrap(),
+            vec![
+                (DC_CONTACT_ID_SELF, "👍".to_string()),
+                (bob, "❤".to_string())
+            ]
+        );
+        let counts = get_reaction_counts(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(counts.len(), 2);
+        assert!(counts.contains(&("👍".to_string(), 1, true)));
+        assert!(counts.contains(&("❤".to_string(), 1, false)));
+        assert_eq!(get_chat_msgs(&t.ctx, chat_id, 0, None).await, vec![msg_id]);
+
+        // older reactions received later and reactions of contacts
+        // not in the chat are ignored
+        for (uid, from, date) in &[
+            (3, "bob@example.net", "Sun, 22 Mar 2020 22:30:00 +0000"),
+            (4, "claire@example.org", "Sun, 22 Mar 2020 22:40:00 +0000"),
+        ] {
+            let raw = format!(
+                "From: {}\n\
+                 To: alice@example.com\n\
+                 Subject: Chat: reaction\n\
+                 Message-ID: <reaction{}@example.net>\n\
+                 In-Reply-To: <{}>\n\
+                 Chat-Version: 1.0\n\
+                 Chat-Reaction: =?utf-8?q?=F0=9F=91=8E?=\n\
+                 Date: {}\n\
+                 \n\
+                 reaction\n",
+                from, uid, msg.rfc724_mid, date
+            );
+            dc_receive_imf(&t.ctx, raw.as_bytes(), "INBOX", *uid, false)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            get_reactions(&t.ctx, msg_id).await.unwrap(),
+            vec![
+                (DC_CONTACT_ID_SELF, "👍".to_string()),
+                (bob, "❤".to_string())
+            ]
+        );
+
+        // a removed reaction is not replaced by an older one
+        let raw = format!(
+            "From: bob@example.net\n\
+             To: alice@example.com\n\
+             Subject: Chat: reaction\n\
+             Message-ID: <reaction5@example.net>\n\
+             In-Reply-To: <{}>\n\
+             Chat-Version: 1.0\n\
+             Chat-Reaction: \n\
+             Date: Sun, 22 Mar 2020 22:50:00 +0000\n\
+             \n\
+             reaction\n",
+            msg.rfc724_mid
+        );
+        dc_receive_imf(&t.ctx, raw.as_bytes(), "INBOX", 5, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            get_reactions(&t.ctx, msg_id).await.unwrap(),
+            vec![(DC_CONTACT_ID_SELF, "👍".to_string())]
+        );
+        let counts = get_reaction_counts(&t.ctx, msg_id).await.unwrap();
+        assert_eq!(counts, vec![("👍".to_string(), 1, true)]);
+    }
+}
@@ -1,14 +1,45 @@
 use async_std::prelude::*;
-use async_std::sync::{channel, Receiver, Sender};
+use async_std::sync::{channel, Arc, Receiver, Sender};
 use async_std::task;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
+use crate::connectivity::{ConnectionKind, ConnectivityState};
 use crate::context::Context;
+use crate::dc_tools::time;
 use crate::imap::Imap;
 use crate::job::{self, Thread};
-use crate::{config::Config, message::MsgId, smtp::Smtp};
+use crate::smtp::{Smtp, SMTP_IDLE_TIMEOUT};
+use crate::transport::{self, FetchTransport};
+use crate::{config::Config, message::MsgId};
 
 pub(crate) struct StopToken;
 
+/// Time a loop is given to finish its current job or fetch when IO is stopped,
+/// it is aborted afterwards.
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Set when IO is stopped, checked by the loops between two iterations
+/// so that jobs and fetches in progress are not aborted half-way.
+#[derive(Debug, Clone, Default)]
+struct StopFlag(Arc<AtomicBool>);
+
+impl StopFlag {
+    fn is_set(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Waits for the stop request of the loop `name`, sets the flag
+    /// and returns after `STOP_TIMEOUT` if the loop did not stop by then.
+    async fn wait(&self, ctx: &Context, name: &str, stop_receiver: Receiver<()>) {
+        stop_receiver.recv().await.ok();
+        info!(ctx, "shutting down {}", name);
+        self.0.store(true, Ordering::SeqCst);
+        task::sleep(STOP_TIMEOUT).await;
+        warn!(ctx, "{} did not stop in time, aborting", name);
+    }
+}
+
 /// Job and connection scheduler.
 #[derive(Debug)]
 #[allow(clippy::large_enum_variant)]
@@ -21,14 +52,25 @@ pub(crate) enum Scheduler {
         mvbox_handle: Option<task::JoinHandle<()>>,
         sentbox: ImapConnectionState,
         sentbox_handle: Option<task::JoinHandle<()>>,
+        watch_folders: ImapConnectionState,
+        watch_folders_handle: Option<task::JoinHandle<()>>,
         smtp: SmtpConnectionState,
         smtp_handle: Option<task::JoinHandle<()>>,
     },
 }
 
 impl Context {
-    /// Indicate that the network likely has come back.
+    /// Indicate that the network likely has come back or changed.
+    ///
+    /// Failed jobs are retried right away instead of waiting for their backoff
+    /// and the connections are re-established, as they may be stale after a network change.
     pub async fn maybe_network(&self) {
+        info!(self, "network may have changed");
+        match job::reset_backoff(self).await {
+            Ok(count) if count > 0 => info!(self, "retrying {} failed jobs", count),
+            Ok(_) => {}
+            Err(err) => warn!(self, "failed to reset job backoff: {}", err),
+        }
         self.scheduler.read().await.maybe_network().await;
     }
 
@@ -44,14 +86,20 @@ impl Context {
         self.scheduler.read().await.interrupt_mvbox(info).await;
     }
 
+    pub(crate) async fn interrupt_watch_folders(&self, info: InterruptInfo) {
+        self.scheduler
+            .read()
+            .await
+            .interrupt_watch_folders(info)
+            .await;
+    }
+
     pub(crate) async fn interrupt_smtp(&self, info: InterruptInfo) {
         self.scheduler.read().await.interrupt_smtp(info).await;
     }
 }
 
 async fn inbox_loop(ctx: Context, started: Sender<()>, inbox_handlers: ImapConnectionHandlers) {
-    use futures::future::FutureExt;
-
     info!(ctx, "starting inbox loop");
     let ImapConnectionHandlers {
         mut connection,
@@ -59,6 +107,8 @@ async fn inbox_loop(ctx: Context, started: Sender<()>, inbox_handlers: ImapConne
         shutdown_sender,
     } = inbox_handlers;
 
+    let stop = StopFlag::default();
+    let stop1 = stop.clone();
     let ctx1 = ctx.clone();
     let fut = async move {
         started.send(()).await;
@@ -67,7 +117,7 @@ async fn inbox_loop(ctx: Context, started: Sender<()>, inbox_handlers: ImapConne
         // track number of continously executed jobs
         let mut jobs_loaded = 0;
         let mut info = InterruptInfo::default();
-        loop {
+        while !stop1.is_set() {
             match job::load_next(&ctx, Thread::Imap, &info).await {
                 Some(job) if jobs_loaded <= 20 => {
                     jobs_loaded += 1;
@@ -82,60 +132,97 @@ async fn inbox_loop(ctx: Context, started: Sender<()>, inbox_handlers: ImapConne
                 }
                 None => {
                     jobs_loaded = 0;
-                    info = fetch_idle(&ctx, &mut connection, Config::ConfiguredInboxFolder).await;
+                    info = if transport::is_configured(&ctx).await {
+                        transport_fetch_idle(&ctx, &mut connection).await
+                    } else {
+                        fetch_idle(&ctx, &mut connection, Config::ConfiguredInboxFolder).await
+                    };
                 }
             }
         }
     };
 
-    stop_receiver
-        .recv()
-        .map(|_| {
-            info!(ctx, "shutting down inbox loop");
-        })
-        .race(fut)
-        .await;
+    stop.wait(&ctx, "inbox loop", stop_receiver).race(fut).await;
     shutdown_sender.send(()).await;
 }
 
 async fn fetch(ctx: &Context, connection: &mut Imap) {
-    match ctx.get_config(Config::ConfiguredInboxFolder).await {
-        Some(watch_folder) => {
-            if let Err(err) = connection.connect_configured(&ctx).await {
-                error!(ctx, "{}", err);
-                return;
-            }
+    let res = match transport::fetch_configured(ctx).await {
+        Some(res) => res,
+        None => FetchTransport::fetch(connection, ctx).await,
+    };
+    if let Err(err) = res {
+        error!(ctx, "{}", err);
+    }
+}
 
-            // fetch
-            if let Err(err) = connection.fetch(&ctx, &watch_folder).await {
-                connection.trigger_reconnect();
-                error!(ctx, "{}", err);
-            }
-        }
-        None => {
-            warn!(ctx, "Can not fetch inbox folder, not set");
-            connection.fake_idle(&ctx, None).await;
-        }
+/// Time after which a failed connection is retried by fake idle.
+const RECONNECT_INTERVAL: i64 = 60;
+
+fn interrupted(err: &impl std::fmt::Display, next_retry: Option<i64>) -> ConnectivityState {
+    ConnectivityState::Interrupted {
+        error: err.to_string(),
+        next_retry,
+    }
+}
+
+fn connection_kind(folder: Config) -> ConnectionKind {
+    match folder {
+        Config::ConfiguredMvboxFolder => ConnectionKind::Mvbox,
+        Config::ConfiguredSentboxFolder => ConnectionKind::Sentbox,
+        _ => ConnectionKind::Inbox,
     }
 }
 
 async fn fetch_idle(ctx: &Context, connection: &mut Imap, folder: Config) -> InterruptInfo {
+    let info = fetch_idle_folder(ctx, connection, folder).await;
+    if info.probe_network {
+        // the connection may be stale after a network change,
+        // IDLE is tried again on the new connection
+        connection.trigger_reconnect();
+        connection.reset_idle_failures();
+    }
+    info
+}
+
+async fn fetch_idle_folder(ctx: &Context, connection: &mut Imap, folder: Config) -> InterruptInfo {
+    let kind = connection_kind(folder);
     match ctx.get_config(folder).await {
         Some(watch_folder) => {
             // connect and fake idle if unable to connect
+            if !connection.is_connected() {
+                ctx.set_connectivity(kind, ConnectivityState::Connecting)
+                    .await;
+            }
             if let Err(err) = connection.connect_configured(&ctx).await {
                 error!(ctx, "imap connection failed: {}", err);
-                return connection.fake_idle(&ctx, None).await;
+                ctx.set_connectivity(kind, interrupted(&err, Some(time() + RECONNECT_INTERVAL)))
+                    .await;
+                return connection
+                    .wait_for_interrupt(Duration::from_secs(RECONNECT_INTERVAL as u64))
+                    .await;
+            }
+
+            // the folders are configured again after the folder strategy changed
+            if folder == Config::ConfiguredInboxFolder {
+                if let Err(err) = connection.ensure_configured_folders(ctx).await {
+                    warn!(ctx, "failed to configure folders: {}", err);
+                }
             }
 
             // fetch
+            ctx.set_connectivity(kind, ConnectivityState::Working).await;
             if let Err(err) = connection.fetch(&ctx, &watch_folder).await {
                 connection.trigger_reconnect();
                 error!(ctx, "{}", err);
+                ctx.set_connectivity(kind, interrupted(&err, None)).await;
+            } else {
+                ctx.set_connectivity(kind, ConnectivityState::Connected)
+                    .await;
             }
 
             // idle
-            if connection.can_idle() {
+            if connection.use_idle() {
                 connection
                     .idle(&ctx, Some(watch_folder))
                     .await
@@ -150,19 +237,42 @@ async fn fetch_idle(ctx: &Context, connection: &mut Imap, folder: Config) -> Int
         }
         None => {
             warn!(ctx, "Can not watch inbox folder, not set");
+            ctx.set_connectivity(kind, ConnectivityState::NotConnected)
+                .await;
             connection.fake_idle(&ctx, None).await
         }
     }
 }
 
+/// Fetches using a transport replacing IMAP, which is then polled every minute.
+async fn transport_fetch_idle(ctx: &Context, connection: &mut Imap) -> InterruptInfo {
+    ctx.set_connectivity(ConnectionKind::Inbox, ConnectivityState::Working)
+        .await;
+    match transport::fetch_configured(ctx).await.unwrap_or(Ok(())) {
+        Ok(_) => {
+            ctx.set_connectivity(ConnectionKind::Inbox, ConnectivityState::Connected)
+                .await
+        }
+        Err(err) => {
+            error!(ctx, "{}", err);
+            ctx.set_connectivity(
+                ConnectionKind::Inbox,
+                interrupted(&err, Some(time() + RECONNECT_INTERVAL)),
+            )
+            .await;
+        }
+    }
+    connection
+        .wait_for_interrupt(Duration::from_secs(RECONNECT_INTERVAL as u64))
+        .await
+}
+
 async fn simple_imap_loop(
     ctx: Context,
     started: Sender<()>,
     inbox_handlers: ImapConnectionHandlers,
     folder: Config,
 ) {
-    use futures::future::FutureExt;
-
     info!(ctx, "starting simple loop for {}", folder.as_ref());
     let ImapConnectionHandlers {
         mut connection,
@@ -170,30 +280,107 @@ async fn simple_imap_loop(
         shutdown_sender,
     } = inbox_handlers;
 
+    let stop = StopFlag::default();
+    let stop1 = stop.clone();
     let ctx1 = ctx.clone();
 
     let fut = async move {
         started.send(()).await;
         let ctx = ctx1;
 
-        loop {
+        while !stop1.is_set() {
             fetch_idle(&ctx, &mut connection, folder).await;
         }
     };
 
-    stop_receiver
-        .recv()
-        .map(|_| {
-            info!(ctx, "shutting down simple loop");
-        })
+    stop.wait(&ctx, "simple loop", stop_receiver)
         .race(fut)
         .await;
     shutdown_sender.send(()).await;
 }
 
-async fn smtp_loop(ctx: Context, started: Sender<()>, smtp_handlers: SmtpConnectionHandlers) {
-    use futures::future::FutureExt;
+/// How often the additional folders set by `Config::WatchFolders`
+/// and the spam folder are scanned.
+const WATCH_FOLDERS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Scans the additional folders and the spam folder in turn on a connection of its own,
+/// so the IDLE of the other folders is not interrupted.
+async fn watch_folders_loop(ctx: Context, started: Sender<()>, handlers: ImapConnectionHandlers) {
+    info!(ctx, "starting watch folders loop");
+    let ImapConnectionHandlers {
+        mut connection,
+        stop_receiver,
+        shutdown_sender,
+    } = handlers;
+
+    let stop = StopFlag::default();
+    let stop1 = stop.clone();
+    let ctx1 = ctx.clone();
+    let fut = async move {
+        started.send(()).await;
+        let ctx = ctx1;
+
+        let mut next = 0;
+        while !stop1.is_set() {
+            let mut folders = ctx.get_watch_folders().await;
+            if ctx.get_config_bool(Config::SpamWatch).await {
+                if let Some(spam_folder) = ctx.get_config(Config::ConfiguredSpamFolder).await {
+                    if !folders.contains(&spam_folder) {
+                        folders.push(spam_folder);
+                    }
+                }
+            }
+            if folders.is_empty() {
+                // wait until folders are set
+                next = 0;
+                connection.fake_idle(&ctx, None).await;
+                continue;
+            }
+
+            // scan one folder per round, so the configuration is reloaded in between
+            let folder = &folders[next % folders.len()];
+            next = (next + 1) % folders.len();
+            let info = if let Err(err) = connection.connect_configured(&ctx).await {
+                error!(ctx, "imap connection failed: {}", err);
+                connection.wait_for_interrupt(WATCH_FOLDERS_INTERVAL).await
+            } else {
+                if let Err(err) = connection.fetch(&ctx, folder).await {
+                    connection.trigger_reconnect();
+                    error!(ctx, "{}", err);
+                }
+                if next == 0 {
+                    connection.wait_for_interrupt(WATCH_FOLDERS_INTERVAL).await
+                } else {
+                    InterruptInfo::default()
+                }
+            };
+            if info.probe_network {
+                connection.trigger_reconnect();
+            }
+        }
+    };
 
+    stop.wait(&ctx, "watch folders loop", stop_receiver)
+        .race(fut)
+        .await;
+    shutdown_sender.send(()).await;
+}
+
+/// Returns the SMTP state after a job: interrupted if a message is waiting for a retry.
+async fn smtp_connectivity(ctx: &Context) -> ConnectivityState {
+    let queue = ctx.get_outgoing_queue().await.unwrap_or_default();
+    match queue.into_iter().find(|msg| msg.tries > 0) {
+        Some(msg) => ConnectivityState::Interrupted {
+            error: msg
+                .last_error
+                .unwrap_or_else(|| "Sending failed".to_string()),
+            next_retry: Some(msg.next_run),
+        },
+        None => ConnectivityState::Connected,
+    }
+}
+
+async fn smtp_loop(ctx: Context, started: Sender<()>, smtp_handlers: SmtpConnectionHandlers) {
     info!(ctx, "starting smtp loop");
     let SmtpConnectionHandlers {
         mut connection,
@@ -202,55 +389,82 @@ async fn smtp_loop(ctx: Context, started: Sender<()>, smtp_handlers: SmtpConnect
         idle_interrupt_receiver,
     } = smtp_handlers;
 
+    let stop = StopFlag::default();
+    let stop1 = stop.clone();
     let ctx1 = ctx.clone();
     let fut = async move {
         started.send(()).await;
         let ctx = ctx1;
 
         let mut interrupt_info = Default::default();
-        loop {
+        while !stop1.is_set() {
+            // sending must not be canceled while the job is running
+            let job_lock = ctx.smtp_job_mutex.lock().await;
             match job::load_next(&ctx, Thread::Smtp, &interrupt_info).await {
                 Some(job) => {
                     info!(ctx, "executing smtp job");
+                    ctx.set_connectivity(ConnectionKind::Smtp, ConnectivityState::Working)
+                        .await;
                     job::perform_job(&ctx, job::Connection::Smtp(&mut connection), job).await;
+                    drop(job_lock);
+                    ctx.set_connectivity(ConnectionKind::Smtp, smtp_connectivity(&ctx).await)
+                        .await;
                     interrupt_info = Default::default();
                 }
                 None => {
-                    // Fake Idle
+                    drop(job_lock);
+                    // Fake Idle, keeping the connection open for some time
+                    // so that further messages can be sent without reconnecting
                     info!(ctx, "smtp fake idle - started");
-                    interrupt_info = idle_interrupt_receiver.recv().await.unwrap_or_default();
-                    info!(ctx, "smtp fake idle - interrupted")
+                    interrupt_info = if connection.is_connected().await {
+                        match idle_interrupt_receiver
+                            .recv()
+                            .timeout(SMTP_IDLE_TIMEOUT)
+                            .await
+                        {
+                            Ok(info) => info.unwrap_or_default(),
+                            Err(_) => {
+                                info!(ctx, "smtp connection idle, disconnecting");
+                                connection.disconnect().await;
+                                idle_interrupt_receiver.recv().await.unwrap_or_default()
+                            }
+                        }
+                    } else {
+                        idle_interrupt_receiver.recv().await.unwrap_or_default()
+                    };
+                    info!(ctx, "smtp fake idle - interrupted");
+                    if interrupt_info.probe_network && connection.is_connected().await {
+                        // the connection may be stale after a network change
+                        connection.disconnect().await;
+                    }
                 }
             }
         }
     };
 
-    stop_receiver
-        .recv()
-        .map(|_| {
-            info!(ctx, "shutting down smtp loop");
-        })
-        .race(fut)
-        .await;
+    stop.wait(&ctx, "smtp loop", stop_receiver).race(fut).await;
     shutdown_sender.send(()).await;
 }
 
 impl Scheduler {
     /// Start the scheduler, panics if it is already running.
     pub async fn start(&mut self, ctx: Context) {
-        let (mvbox, mvbox_handlers) = ImapConnectionState::new();
-        let (sentbox, sentbox_handlers) = ImapConnectionState::new();
+        let (mvbox, mvbox_handlers) = ImapConnectionState::new(1);
+        let (sentbox, sentbox_handlers) = ImapConnectionState::new(2);
+        let (watch_folders, watch_folders_handlers) = ImapConnectionState::new(3);
         let (smtp, smtp_handlers) = SmtpConnectionState::new();
-        let (inbox, inbox_handlers) = ImapConnectionState::new();
+        let (inbox, inbox_handlers) = ImapConnectionState::new(0);
 
         *self = Scheduler::Running {
             inbox,
             mvbox,
             sentbox,
+            watch_folders,
             smtp,
             inbox_handle: None,
             mvbox_handle: None,
             sentbox_handle: None,
+            watch_folders_handle: None,
             smtp_handle: None,
         };
 
@@ -290,6 +504,18 @@ impl Scheduler {
             }));
         }
 
+        let (watch_folders_start_send, watch_folders_start_recv) = channel(1);
+        if let Scheduler::Running {
+            watch_folders_handle,
+            ..
+        } = self
+        {
+            let ctx1 = ctx.clone();
+            *watch_folders_handle = Some(task::spawn(async move {
+                watch_folders_loop(ctx1, watch_folders_start_send, watch_folders_handlers).await
+            }));
+        }
+
         let (smtp_start_send, smtp_start_recv) = channel(1);
         if let Scheduler::Running { smtp_handle, .. } = self {
             let ctx1 = ctx.clone();
@@ -303,6 +529,7 @@ impl Scheduler {
             .recv()
             .try_join(mvbox_start_recv.recv())
             .try_join(sentbox_start_recv.recv())
+            .try_join(watch_folders_start_recv.recv())
             .try_join(smtp_start_recv.recv())
             .await
         {
@@ -320,6 +547,7 @@ impl Scheduler {
         self.interrupt_inbox(InterruptInfo::new(true, None))
             .join(self.interrupt_mvbox(InterruptInfo::new(true, None)))
             .join(self.interrupt_sentbox(InterruptInfo::new(true, None)))
+            .join(self.interrupt_watch_folders(InterruptInfo::new(true, None)))
             .join(self.interrupt_smtp(InterruptInfo::new(true, None)))
             .await;
     }
@@ -342,6 +570,15 @@ impl Scheduler {
         }
     }
 
+    async fn interrupt_watch_folders(&self, info: InterruptInfo) {
+        if let Scheduler::Running {
+            ref watch_folders, ..
+        } = self
+        {
+            watch_folders.interrupt(info).await;
+        }
+    }
+
     async fn interrupt_smtp(&self, info: InterruptInfo) {
         if let Scheduler::Running { ref smtp, .. } = self {
             smtp.interrupt(info).await;
@@ -358,6 +595,7 @@ impl Scheduler {
                 inbox,
                 mvbox,
                 sentbox,
+                watch_folders,
                 smtp,
                 ..
             } => {
@@ -365,6 +603,7 @@ impl Scheduler {
                     .stop()
                     .join(mvbox.stop())
                     .join(sentbox.stop())
+                    .join(watch_folders.stop())
                     .join(smtp.stop())
                     .await;
 
@@ -383,12 +622,17 @@ impl Scheduler {
                 inbox_handle,
                 mvbox_handle,
                 sentbox_handle,
+                watch_folders_handle,
                 smtp_handle,
                 ..
             } => {
                 inbox_handle.take().expect("inbox not started").await;
                 mvbox_handle.take().expect("mvbox not started").await;
                 sentbox_handle.take().expect("sentbox not started").await;
+                watch_folders_handle
+                    .take()
+                    .expect("watch folders loop not started")
+                    .await;
                 smtp_handle.take().expect("smtp not started").await;
 
                 *self = Scheduler::Stopped;
@@ -418,9 +662,13 @@ struct ConnectionState {
 
 impl ConnectionState {
     /// Shutdown this connection completely.
+    ///
+    /// The job or fetch in progress is finished first, see `StopFlag`.
     async fn stop(&self) {
         // Trigger shutdown of the run loop.
         self.stop_sender.send(()).await;
+        // Abort IDLE so the loop gets to check for the stop.
+        self.interrupt(InterruptInfo::new(false, None)).await;
         // Wait for a notification that the run loop has been shutdown.
         self.shutdown_receiver.recv().await.ok();
     }
@@ -485,14 +733,16 @@ pub(crate) struct ImapConnectionState {
 }
 
 impl ImapConnectionState {
-    /// Construct a new connection.
-    fn new() -> (Self, ImapConnectionHandlers) {
+    /// Construct a new connection, polling in the given slot if IDLE is not used.
+    fn new(poll_slot: u32) -> (Self, ImapConnectionHandlers) {
         let (stop_sender, stop_receiver) = channel(1);
         let (shutdown_sender, shutdown_receiver) = channel(1);
         let (idle_interrupt_sender, idle_interrupt_receiver) = channel(1);
 
+        let mut connection = Imap::new(idle_interrupt_receiver);
+        connection.set_poll_slot(poll_slot);
         let handlers = ImapConnectionHandlers {
-            connection: Imap::new(idle_interrupt_receiver),
+            connection,
             stop_receiver,
             shutdown_sender,
         };
@@ -0,0 +1,357 @@
+//! # Advanced message search
+//!
+//! Search queries may contain filters in addition to words searched in the
+//! message texts, eg. `from:alice@example.org has:file report` finds files
+//! sent by Alice containing the word "report".  Supported filters:
+//!
+//! - `from:<addr or name>`, `from:me` – messages sent by matching contacts or by self
+//! - `before:<YYYY-MM-DD>`, `after:<YYYY-MM-DD>` – messages sent before or after the given local day
+//! - `has:file` – messages with an attachment
+//! - `in:<chat name>` – messages in chats with a matching name
+//! - `is:starred` – starred messages
+//!
+//! Values containing spaces can be quoted, eg. `in:"Project Team"`.
+//! Words looking like filters but not matching any of the above are searched
+//! as text.  The words are searched using the full-text index as
+//! [crate::message::search_msgs] does.
+
+use serde::Serialize;
+
+use crate::chat::ChatId;
+use crate::constants::*;
+use crate::context::Context;
+use crate::dc_tools::dc_gm2local_offset;
+use crate::error::{bail, Result};
+use crate::message::{self, MsgId};
+
+/// A filter of a search query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "filter", content = "value", rename_all = "snake_case")]
+pub enum Filter {
+    /// Messages sent by contacts with a matching address or name,
+    /// `me` for messages sent by self.
+    From(String),
+
+    /// Messages sent before the given timestamp.
+    Before(i64),
+
+    /// Messages sent at or after the given timestamp.
+    After(i64),
+
+    /// Messages with an attachment.
+    HasFile,
+
+    /// Messages in chats with a matching name.
+    InChat(String),
+
+    /// Starred messages.
+    IsStarred,
+}
+
+/// A parsed search query.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SearchQuery {
+    /// Words searched in the message texts.
+    pub words: Vec<String>,
+
+    /// Filters all found messages have to match.
+    pub filters: Vec<Filter>,
+}
+
+/// The result of [search].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchResult {
+    /// Found messages, see [search] for the order.
+    pub msg_ids: Vec<MsgId>,
+
+    /// The filters recognized in the query and applied to the search.
+    pub filters: Vec<Filter>,
+}
+
+/// Splits a query into words, double quotes group words containing spaces.
+fn tokenize(query: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+    for c in query.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::replace(&mut token, String::new()));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// Returns the UTC timestamp of the start of the local day `date` given as `YYYY-MM-DD`.
+fn parse_day(date: &str) -> Result<i64> {
+    let day = match chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        Ok(day) => day,
+        Err(err) => bail!("Invalid date \"{}\": {}", date, err),
+    };
+    Ok(day.and_hms(0, 0, 0).timestamp() - dc_gm2local_offset())
+}
+
+impl SearchQuery {
+    /// Parses a search query, see the module documentation for the syntax.
+    ///
+    /// Fails if the value of a filter is invalid, eg. an unparsable date.
+    pub fn parse(query: &str) -> Result<Self> {
+        let mut parsed = SearchQuery::default();
+        for token in tokenize(query) {
+            let filter = if let Some(pos) = token.find(':') {
+                let (key, value) = (&token[..pos], &token[pos + 1..]);
+                match (key.to_lowercase().as_str(), value) {
+                    (_, "") => None,
+                    ("from", value) => Some(Filter::From(value.to_string())),
+                    ("before", value) => Some(Filter::Before(parse_day(value)?)),
+                    ("after", value) => Some(Filter::After(parse_day(value)? + 86400)),
+                    ("has", value) if value.eq_ignore_ascii_case("file") => Some(Filter::HasFile),
+                    ("in", value) => Some(Filter::InChat(value.to_string())),
+                    ("is", value) if value.eq_ignore_ascii_case("starred") => {
+                        Some(Filter::IsStarred)
+                    }
+                    _ => None,
+                }
+            } else {
+                None
+            };
+            match filter {
+                Some(filter) => parsed.filters.push(filter),
+                None => parsed.words.push(token),
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Returns true if the query neither contains words nor filters.
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty() && self.filters.is_empty()
+    }
+
+    /// Compiles the filters to SQL conditions on the message table `m`.
+    fn add_filter_conditions(
+        &self,
+        conditions: &mut Vec<String>,
+        params: &mut Vec<Box<dyn crate::ToSql>>,
+    ) {
+        for filter in &self.filters {
+            match filter {
+                Filter::From(value) if value.eq_ignore_ascii_case("me") => {
+                    conditions.push("m.from_id=?".to_string());
+                    params.push(Box::new(DC_CONTACT_ID_SELF as i32));
+                }
+                Filter::From(value) => {
+                    conditions.push(
+                        "m.from_id IN (SELECT id FROM contacts
+                                        WHERE addr LIKE ? OR name LIKE ? OR authname LIKE ?)"
+                            .to_string(),
+                    );
+                    let pattern = format!("%{}%", value);
+                    params.push(Box::new(pattern.clone()));
+                    params.push(Box::new(pattern.clone()));
+                    params.push(Box::new(pattern));
+                }
+                Filter::Before(timestamp) => {
+                    conditions.push("m.timestamp<?".to_string());
+                    params.push(Box::new(*timestamp));
+                }
+                Filter::After(timestamp) => {
+                    conditions.push("m.timestamp>=?".to_string());
+                    params.push(Box::new(*timestamp));
+                }
+                Filter::HasFile => {
+                    conditions.push(format!(
+                        "m.type IN ({}, {}, {}, {}, {}, {}, {}, {})",
+                        Viewtype::Image as i32,
+                        Viewtype::Gif as i32,
+                        Viewtype::Sticker as i32,
+                        Viewtype::Audio as i32,
+                        Viewtype::Voice as i32,
+                        Viewtype::Video as i32,
+                        Viewtype::File as i32,
+                        Viewtype::App as i32,
+                    ));
+                }
+                Filter::InChat(value) => {
+                    conditions
+                        .push("m.chat_id IN (SELECT id FROM chats WHERE name LIKE ?)".to_string());
+                    params.push(Box::new(format!("%{}%", value)));
+                }
+                Filter::IsStarred => conditions.push("m.starred=1".to_string()),
+            }
+        }
+    }
+}
+
+/// Searches messages matching `query`, see the module documentation for the syntax.
+///
+/// If `chat_id` is unset, all chats are searched and the results are sorted
+/// newest first, otherwise the results are sorted like the chat.
+pub async fn search(context: &Context, chat_id: ChatId, query: &str) -> Result<SearchResult> {
+    let query = SearchQuery::parse(query)?;
+    if query.is_empty() {
+        return Ok(SearchResult::default());
+    }
+
+    // the conditions use the indexes of msgs_search and msgs
+    let mut conditions = Vec::new();
+    let mut params: Vec<Box<dyn crate::ToSql>> = Vec::new();
+    let from = match message::fts_query(&query.words.join(" ")) {
+        Some(fts_query) => {
+            conditions.push("msgs_search MATCH ?".to_string());
+            params.push(Box::new(fts_query));
+            "msgs_search INNER JOIN msgs m ON m.id=msgs_search.rowid"
+        }
+        None => "msgs m",
+    };
+    query.add_filter_conditions(&mut conditions, &mut params);
+    let order = if chat_id.is_unset() {
+        conditions.push("m.chat_id>? AND c.blocked=0".to_string());
+        params.push(Box::new(DC_CHAT_ID_LAST_SPECIAL));
+        "m.timestamp DESC, m.id DESC"
+    } else {
+        conditions.push("m.chat_id=?".to_string());
+        params.push(Box::new(chat_id));
+        "m.timestamp, m.id"
+    };
+
+    let sql = format!(
+        "SELECT m.id
+           FROM {}
+           LEFT JOIN contacts ct ON m.from_id=ct.id
+           LEFT JOIN chats c ON m.chat_id=c.id
+          WHERE {}
+            AND m.hidden=0
+            AND ct.blocked=0
+          ORDER BY {};",
+        from,
+        conditions.join(" AND "),
+        order
+    );
+    let msg_ids = context
+        .sql
+        .query_map(
+            sql,
+            params.iter().map(|param| param.as_ref()).collect(),
+            |row| row.get::<_, MsgId>(0),
+            |ids| {
+                ids.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    Ok(SearchResult {
+        msg_ids,
+        filters: query.filters,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::chat;
+    use crate::config::Config;
+    use crate::message::{self, Message};
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_parse() {
+        let query =
+            SearchQuery::parse("from:alice@example.org  has:File report is:starred").unwrap();
+        assert_eq!(query.words, vec!["report".to_string()]);
+        assert_eq!(
+            query.filters,
+            vec![
+                Filter::From("alice@example.org".to_string()),
+                Filter::HasFile,
+                Filter::IsStarred
+            ]
+        );
+
+        let query = SearchQuery::parse("in:\"Project Team\" http://example.org is:foo").unwrap();
+        assert_eq!(
+            query.words,
+            vec!["http://example.org".to_string(), "is:foo".to_string()]
+        );
+        assert_eq!(
+            query.filters,
+            vec![Filter::InChat("Project Team".to_string())]
+        );
+
+        let query = SearchQuery::parse("after:2020-05-01 before:2020-05-03").unwrap();
+        assert!(query.words.is_empty());
+        let start = parse_day("2020-05-01").unwrap();
+        assert_eq!(
+            query.filters,
+            vec![
+                Filter::After(start + 86400),
+                Filter::Before(start + 2 * 86400)
+            ]
+        );
+
+        assert!(SearchQuery::parse("before:yesterday").is_err());
+        assert!(SearchQuery::parse("  ").unwrap().is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_search() {
+        let t = dummy_context().await;
+        t.ctx
+            .set_config(Config::ConfiguredAddr, Some("self@example.com"))
+            .await
+            .unwrap();
+        let chat_id = chat::create_by_contact_id(&t.ctx, DC_CONTACT_ID_SELF)
+            .await
+            .unwrap();
+        let mut ids = Vec::new();
+        for text in &["apples and bananas", "more apples", "a report"] {
+            let mut msg = Message::new(Viewtype::Text);
+            msg.set_text(Some(text.to_string()));
+            ids.push(chat::prepare_msg(&t.ctx, chat_id, &mut msg).await.unwrap());
+        }
+        let start = parse_day("2020-05-01").unwrap();
+        for (i, msg_id) in ids.iter().enumerate() {
+            t.ctx
+                .sql
+                .execute(
+                    "UPDATE msgs SET timestamp=? WHERE id=?;",
+                    paramsv![start + 86400 * i as i64 + 3600, *msg_id],
+                )
+                .await
+                .unwrap();
+        }
+        message::star_msgs(&t.ctx, vec![ids[1]], true).await;
+
+        let result = search(&t.ctx, ChatId::new(0), "apple from:me")
+            .await
+            .unwrap();
+        assert_eq!(result.msg_ids, vec![ids[1], ids[0]]);
+        assert_eq!(result.filters, vec![Filter::From("me".to_string())]);
+
+        let result = search(&t.ctx, chat_id, "is:starred").await.unwrap();
+        assert_eq!(result.msg_ids, vec![ids[1]]);
+
+        let result = search(&t.ctx, chat_id, "after:2020-05-01").await.unwrap();
+        assert_eq!(result.msg_ids, vec![ids[1], ids[2]]);
+        let result = search(&t.ctx, chat_id, "after:2020-05-01 before:2020-05-03 apples")
+            .await
+            .unwrap();
+        assert_eq!(result.msg_ids, vec![ids[1]]);
+
+        let result = search(&t.ctx, ChatId::new(0), "in:nochat").await.unwrap();
+        assert!(result.msg_ids.is_empty());
+        let result = search(&t.ctx, chat_id, "has:file").await.unwrap();
+        assert!(result.msg_ids.is_empty());
+        let result = search(&t.ctx, chat_id, "").await.unwrap();
+        assert_eq!(result, SearchResult::default());
+    }
+}
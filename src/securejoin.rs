@@ -342,7 +342,7 @@ async fn send_handshake_msg(
 
     chat::send_msg(context, contact_chat_id, &mut msg)
         .await
-        .map_err(HandshakeError::MsgSendFailed)?;
+        .map_err(|err| HandshakeError::MsgSendFailed(err.into()))?;
 
     Ok(())
 }
@@ -985,6 +985,10 @@ async fn mark_peer_as_verified(context: &Context, fingerprint: &Fingerprint) ->
                 .save_to_db(&context.sql, false)
                 .await
                 .unwrap_or_default();
+            peerstate
+                .add_verification_record(VerificationEvent::SecureJoin, fingerprint, None)
+                .await
+                .unwrap_or_default();
             return Ok(());
         }
     }
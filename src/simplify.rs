@@ -14,13 +14,14 @@ pub fn escape_message_footer_marks(text: &str) -> String {
     }
 }
 
-/// Remove standard (RFC 3676, §4.3) footer if it is found.
-fn remove_message_footer<'a>(lines: &'a [&str]) -> &'a [&'a str] {
+/// Remove standard (RFC 3676, §4.3) footer if it is found,
+/// the footer is returned as second value.
+fn remove_message_footer<'a>(lines: &'a [&str]) -> (&'a [&'a str], Option<&'a [&'a str]>) {
     let mut nearly_standard_footer = None;
     for (ix, &line) in lines.iter().enumerate() {
         match line {
             // some providers encode `-- ` to `-- =20` which results in `--  `
-            "-- " | "--  " => return &lines[..ix],
+            "-- " | "--  " => return (&lines[..ix], Some(&lines[ix + 1..])),
             // some providers encode `-- ` to `=2D-` which results in only `--`;
             // use that only when no other footer is found
             // and if the line before is empty and the line after is not empty
@@ -34,9 +35,9 @@ fn remove_message_footer<'a>(lines: &'a [&str]) -> &'a [&'a str] {
         }
     }
     if let Some(ix) = nearly_standard_footer {
-        return &lines[..ix];
+        return (&lines[..ix], Some(&lines[ix + 1..]));
     }
-    lines
+    (lines, None)
 }
 
 /// Remove nonstandard footer and a boolean indicating whether such
@@ -64,16 +65,29 @@ fn split_lines(buf: &str) -> Vec<&str> {
 
 /// Simplify message text for chat display.
 /// Remove quotes, signatures, trailing empty lines etc.
-pub fn simplify(mut input: String, is_chat_message: bool) -> (String, bool) {
+///
+/// Returns the text, whether the message is forwarded,
+/// for chat messages, the text quoted at the top of the message
+/// and the standard footer of the message, if any.
+pub fn simplify(
+    mut input: String,
+    is_chat_message: bool,
+) -> (String, bool, Option<String>, Option<String>) {
     input.retain(|c| c != '\r');
     let lines = split_lines(&input);
     let (lines, is_forwarded) = skip_forward_header(&lines);
 
     let original_lines = &lines;
 
-    let lines = remove_message_footer(lines);
+    let (lines, footer) = remove_message_footer(lines);
+    let footer = footer
+        .map(|footer| footer.join("\n").trim().to_string())
+        .filter(|footer| !footer.is_empty());
 
+    let mut top_quote = None;
     let text = if is_chat_message {
+        let (lines, quote) = split_top_quote(lines);
+        top_quote = quote;
         render_message(lines, false, false)
     } else {
         let (lines, has_nonstandard_footer) = remove_nonstandard_footer(lines);
@@ -90,7 +104,7 @@ pub fn simplify(mut input: String, is_chat_message: bool) -> (String, bool) {
             )
         }
     };
-    (text, is_forwarded)
+    (text, is_forwarded, top_quote, footer)
 }
 
 /// Skips "forwarded message" header.
@@ -107,6 +121,26 @@ fn skip_forward_header<'a>(lines: &'a [&str]) -> (&'a [&'a str], bool) {
     }
 }
 
+/// Returns the original author named in the "forwarded message" header,
+/// `None` if the message is not forwarded or the author is not named.
+pub(crate) fn get_forwarded_from(text: &str) -> Option<String> {
+    let mut lines = text.lines();
+    if lines.next()?.trim_end() != "---------- Forwarded message ----------" {
+        return None;
+    }
+    let from_line = lines.next()?.trim_end();
+    if !from_line.starts_with("From: ") {
+        return None;
+    }
+    let author = from_line["From: ".len()..].trim();
+    // Delta Chat sends this placeholder if the author should not be revealed
+    if author.is_empty() || author == "Delta Chat" {
+        None
+    } else {
+        Some(author.to_string())
+    }
+}
+
 fn remove_bottom_quote<'a>(lines: &'a [&str]) -> (&'a [&'a str], bool) {
     let mut last_quoted_line = None;
     for (l, line) in lines.iter().enumerate().rev() {
@@ -154,6 +188,31 @@ fn remove_top_quote<'a>(lines: &'a [&str]) -> (&'a [&'a str], bool) {
     }
 }
 
+/// Splits the quote at the top of a chat message from the reply.
+///
+/// Returns the remaining lines and the quoted text without quote marks.
+/// Messages consisting only of a quote are not split.
+fn split_top_quote<'a>(lines: &'a [&str]) -> (&'a [&'a str], Option<String>) {
+    let (rest, has_top_quote) = remove_top_quote(lines);
+    if !has_top_quote || rest.iter().all(|line| is_empty_line(line)) {
+        return (lines, None);
+    }
+    let quote = lines[..lines.len() - rest.len()]
+        .iter()
+        .filter(|line| is_plain_quote(line))
+        .map(|line| {
+            let line = &line[1..];
+            if line.starts_with(' ') {
+                &line[1..]
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    (rest, Some(quote))
+}
+
 fn render_message(lines: &[&str], is_cut_at_begin: bool, is_cut_at_end: bool) -> String {
     let mut ret = String::new();
     if is_cut_at_begin {
@@ -229,7 +288,7 @@ mod tests {
         #[test]
         // proptest does not support [[:graphical:][:space:]] regex.
         fn test_simplify_plain_text_fuzzy(input in "[!-~\t \n]+") {
-            let (output, _is_forwarded) = simplify(input, true);
+            let (output, _is_forwarded, _, _) = simplify(input, true);
             assert!(output.split('\n').all(|s| s != "-- "));
         }
     }
@@ -237,7 +296,7 @@ mod tests {
     #[test]
     fn test_dont_remove_whole_message() {
         let input = "\n------\nFailed\n------\n\nUh-oh, this workflow did not succeed!\n\nlots of other text".to_string();
-        let (plain, is_forwarded) = simplify(input, false);
+        let (plain, is_forwarded, _, _) = simplify(input, false);
         assert_eq!(
             plain,
             "------\nFailed\n------\n\nUh-oh, this workflow did not succeed!\n\nlots of other text"
@@ -248,7 +307,7 @@ mod tests {
     #[test]
     fn test_chat_message() {
         let input = "Hi! How are you?\n\n---\n\nI am good.\n-- \nSent with my Delta Chat Messenger: https://delta.chat".to_string();
-        let (plain, is_forwarded) = simplify(input, true);
+        let (plain, is_forwarded, _, _) = simplify(input, true);
         assert_eq!(plain, "Hi! How are you?\n\n---\n\nI am good.");
         assert!(!is_forwarded);
     }
@@ -256,7 +315,7 @@ mod tests {
     #[test]
     fn test_simplify_trim() {
         let input = "line1\n\r\r\rline2".to_string();
-        let (plain, is_forwarded) = simplify(input, false);
+        let (plain, is_forwarded, _, _) = simplify(input, false);
 
         assert_eq!(plain, "line1\nline2");
         assert!(!is_forwarded);
@@ -265,12 +324,21 @@ mod tests {
     #[test]
     fn test_simplify_forwarded_message() {
         let input = "---------- Forwarded message ----------\r\nFrom: test@example.com\r\n\r\nForwarded message\r\n-- \r\nSignature goes here".to_string();
-        let (plain, is_forwarded) = simplify(input, false);
+        let (plain, is_forwarded, _, _) = simplify(input, false);
 
         assert_eq!(plain, "Forwarded message");
         assert!(is_forwarded);
     }
 
+    #[test]
+    fn test_get_forwarded_from() {
+        let input = "---------- Forwarded message ----------\r\nFrom: Alice\r\n\r\ntext";
+        assert_eq!(get_forwarded_from(input), Some("Alice".to_string()));
+        let input = "---------- Forwarded message ----------\r\nFrom: Delta Chat\r\n\r\ntext";
+        assert_eq!(get_forwarded_from(input), None);
+        assert_eq!(get_forwarded_from("From: Alice\n\ntext"), None);
+    }
+
     #[test]
     fn test_simplify_utilities() {
         assert!(is_empty_line(" \t"));
@@ -298,6 +366,30 @@ mod tests {
         assert!(!has_top_quote);
     }
 
+    #[test]
+    fn test_split_top_quote() {
+        let input = "> quoted\n> > nested\n>no space\n\nreply\n-- \nfooter".to_string();
+        let (plain, _, quote, _) = simplify(input, true);
+        assert_eq!(plain, "reply");
+        assert_eq!(quote.unwrap(), "quoted\n> nested\nno space");
+
+        let input = "> only a quote".to_string();
+        let (plain, _, quote, _) = simplify(input, true);
+        assert_eq!(plain, "> only a quote");
+        assert!(quote.is_none());
+
+        let input = "reply\n> not a top quote".to_string();
+        let (plain, _, quote, _) = simplify(input, true);
+        assert_eq!(plain, "reply\n> not a top quote");
+        assert!(quote.is_none());
+
+        // quotes are only split from chat messages
+        let input = "> quoted\n\nreply".to_string();
+        let (plain, _, quote, _) = simplify(input, false);
+        assert_eq!(plain, "[...]reply");
+        assert!(quote.is_none());
+    }
+
     #[test]
     fn test_escape_message_footer_marks() {
         let esc = escape_message_footer_marks("--\n--text --in line");
@@ -310,33 +402,39 @@ mod tests {
     #[test]
     fn test_remove_message_footer() {
         let input = "text\n--\nno footer".to_string();
-        let (plain, _) = simplify(input, true);
+        let (plain, _, _, _) = simplify(input, true);
         assert_eq!(plain, "text\n--\nno footer");
 
         let input = "text\n\n--\n\nno footer".to_string();
-        let (plain, _) = simplify(input, true);
+        let (plain, _, _, _) = simplify(input, true);
         assert_eq!(plain, "text\n\n--\n\nno footer");
 
         let input = "text\n\n-- no footer\n\n".to_string();
-        let (plain, _) = simplify(input, true);
+        let (plain, _, _, _) = simplify(input, true);
         assert_eq!(plain, "text\n\n-- no footer");
 
         let input = "text\n\n--\nno footer\n-- \nfooter".to_string();
-        let (plain, _) = simplify(input, true);
+        let (plain, _, _, footer) = simplify(input, true);
         assert_eq!(plain, "text\n\n--\nno footer");
+        assert_eq!(footer, Some("footer".to_string()));
+
+        let input = "text\n-- \n\n".to_string();
+        let (plain, _, _, footer) = simplify(input, false);
+        assert_eq!(plain, "text");
+        assert_eq!(footer, None);
 
         let input = "text\n\n--\ntreated as footer when unescaped".to_string();
-        let (plain, _) = simplify(input.clone(), true);
+        let (plain, _, _, _) = simplify(input.clone(), true);
         assert_eq!(plain, "text"); // see remove_message_footer() for some explanations
         let escaped = escape_message_footer_marks(&input);
-        let (plain, _) = simplify(escaped, true);
+        let (plain, _, _, _) = simplify(escaped, true);
         assert_eq!(plain, "text\n\n--\ntreated as footer when unescaped");
 
         let input = "--\ntreated as footer when unescaped".to_string();
-        let (plain, _) = simplify(input.clone(), true);
+        let (plain, _, _, _) = simplify(input.clone(), true);
         assert_eq!(plain, ""); // see remove_message_footer() for some explanations
         let escaped = escape_message_footer_marks(&input);
-        let (plain, _) = simplify(escaped, true);
+        let (plain, _, _, _) = simplify(escaped, true);
         assert_eq!(plain, "--\ntreated as footer when unescaped");
     }
 }
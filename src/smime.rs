@@ -0,0 +1,469 @@
+//! # S/MIME
+//!
+//! Many organisations use S/MIME instead of OpenPGP.  If the crate is built
+//! with the `smime` feature, which links against OpenSSL, received S/MIME
+//! messages are decrypted and their signatures are verified; the
+//! certificates of valid signatures are stored per address.
+//!
+//! If a certificate and the matching private key are configured, see
+//! [Config::SmimeCertificate], outgoing messages that are not encrypted with
+//! OpenPGP are signed.  If certificates of all recipients are known, the
+//! messages are also encrypted.  Messages are signed opaquely, so the signed
+//! content is not altered by servers rewrapping lines of multipart bodies.
+//!
+//! Certificate chains are verified against the system trust store and the
+//! additional certificate authorities set in [Config::SmimeCa].
+
+use bitflags::bitflags;
+use lettre_email::PartBuilder;
+
+use crate::context::Context;
+use crate::error::{format_err, Result};
+
+#[cfg(feature = "smime")]
+use openssl::{
+    nid::Nid,
+    pkcs7::{Pkcs7, Pkcs7Flags},
+    pkey::{PKey, Private},
+    stack::Stack,
+    symm::Cipher,
+    x509::{store::X509Store, store::X509StoreBuilder, X509Ref, X509StoreContext, X509},
+};
+
+#[cfg(feature = "smime")]
+use crate::config::Config;
+#[cfg(feature = "smime")]
+use crate::contact::addr_cmp;
+#[cfg(feature = "smime")]
+use crate::dc_tools::time;
+#[cfg(feature = "smime")]
+use crate::error::ensure;
+
+/// Nested S/MIME structures, eg. signed data inside enveloped data,
+/// are unwrapped up to this depth.
+#[cfg(feature = "smime")]
+const MAX_NESTING: usize = 3;
+
+bitflags! {
+    /// How a message was protected by S/MIME.
+    #[derive(Default)]
+    pub struct SmimeState: i32 {
+        /// The message has a valid signature by the sender.
+        const SIGNED = 0x01;
+
+        /// The message was encrypted.
+        const ENCRYPTED = 0x02;
+    }
+}
+
+/// The kind of an S/MIME entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SmimeType {
+    /// `multipart/signed` with detached signature or opaque `signed-data`.
+    Signed,
+
+    /// `enveloped-data`, ie. encrypted content.
+    Enveloped,
+}
+
+/// Returns the kind of S/MIME entity `mail` is, if any.
+pub(crate) fn smime_type(mail: &mailparse::ParsedMail<'_>) -> Option<SmimeType> {
+    let mimetype = mail.ctype.mimetype.to_lowercase();
+    let param = |name: &str| {
+        mail.ctype
+            .params
+            .get(name)
+            .map(|value| value.to_lowercase())
+            .unwrap_or_default()
+    };
+    match mimetype.as_str() {
+        "multipart/signed" => match param("protocol").as_str() {
+            "application/pkcs7-signature" | "application/x-pkcs7-signature" => {
+                Some(SmimeType::Signed)
+            }
+            _ => None,
+        },
+        "application/pkcs7-mime" | "application/x-pkcs7-mime" => {
+            match param("smime-type").as_str() {
+                "signed-data" => Some(SmimeType::Signed),
+                "enveloped-data" | "authenveloped-data" => Some(SmimeType::Enveloped),
+                // some clients omit the parameter, encrypted messages are named smime.p7m
+                "" if param("name") == "smime.p7m" => Some(SmimeType::Enveloped),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The content of an S/MIME message.
+#[derive(Debug)]
+pub(crate) struct Unwrapped {
+    /// The innermost MIME entity.
+    pub content: Vec<u8>,
+
+    pub state: SmimeState,
+}
+
+/// Decrypts the S/MIME message `raw` from `from` and verifies its signature.
+///
+/// The certificate of a valid signature is stored for `from`.  Invalid
+/// signatures are logged and the content is returned as unsigned.
+#[cfg(feature = "smime")]
+pub(crate) async fn unwrap(context: &Context, raw: &[u8], from: &str) -> Result<Unwrapped> {
+    let store = trust_store(context).await?;
+    let mut content = raw.to_vec();
+    let mut state = SmimeState::empty();
+    for _ in 0..MAX_NESTING {
+        let smime_type = smime_type(&mailparse::parse_mail(&content)?);
+        match smime_type {
+            Some(SmimeType::Enveloped) => {
+                let identity = load_identity(context)
+                    .await?
+                    .ok_or_else(|| format_err!("No S/MIME certificate configured"))?;
+                let (pkcs7, _) = Pkcs7::from_smime(&content)?;
+                content = pkcs7.decrypt(&identity.key, &identity.cert, Pkcs7Flags::empty())?;
+                state |= SmimeState::ENCRYPTED;
+            }
+            Some(SmimeType::Signed) => {
+                let (pkcs7, detached) = Pkcs7::from_smime(&content)?;
+                let mut out = Vec::new();
+                match verify_signature(&pkcs7, &store, detached.as_deref(), &mut out, from) {
+                    Ok(cert) => {
+                        save_certificate(context, from, &cert).await?;
+                        state |= SmimeState::SIGNED;
+                    }
+                    Err(err) => {
+                        warn!(context, "S/MIME: invalid signature from {}: {}", from, err);
+                        out.clear();
+                        let certs: Stack<X509> = Stack::new()?;
+                        pkcs7.verify(
+                            &certs,
+                            &store,
+                            detached.as_deref(),
+                            Some(&mut out),
+                            Pkcs7Flags::NOVERIFY | Pkcs7Flags::NOSIGS,
+                        )?;
+                    }
+                }
+                content = out;
+            }
+            None => break,
+        }
+    }
+    Ok(Unwrapped { content, state })
+}
+
+/// Decrypts an S/MIME message, not supported without the `smime` feature.
+#[cfg(not(feature = "smime"))]
+pub(crate) async fn unwrap(_context: &Context, _raw: &[u8], _from: &str) -> Result<Unwrapped> {
+    Err(format_err!("S/MIME is not supported by this build"))
+}
+
+/// Signs the MIME entity `content` and encrypts it if certificates of all
+/// `recipients` are known.
+///
+/// Returns `None` if no valid certificate is configured; errors are logged
+/// and the message is sent without S/MIME then.
+pub(crate) async fn render(
+    context: &Context,
+    content: &str,
+    recipients: &[String],
+) -> Option<(PartBuilder, SmimeState)> {
+    match try_render(context, content, recipients).await {
+        Ok(res) => res,
+        Err(err) => {
+            warn!(context, "S/MIME: cannot sign message: {}", err);
+            None
+        }
+    }
+}
+
+#[cfg(feature = "smime")]
+async fn try_render(
+    context: &Context,
+    content: &str,
+    recipients: &[String],
+) -> Result<Option<(PartBuilder, SmimeState)>> {
+    let identity = match load_identity(context).await? {
+        Some(identity) => identity,
+        None => return Ok(None),
+    };
+    let store = trust_store(context).await?;
+    verify_chain(&store, &identity.cert, &identity.chain)?;
+
+    let signed = Pkcs7::sign(
+        &identity.cert,
+        &identity.key,
+        &identity.chain,
+        content.as_bytes(),
+        Pkcs7Flags::BINARY,
+    )?
+    .to_smime(&[], Pkcs7Flags::BINARY)?;
+    let mut state = SmimeState::SIGNED;
+
+    // encrypt to ourself as well, so that the sent message can be read
+    let mut certs = Stack::new()?;
+    certs.push(identity.cert.clone())?;
+    let own_addrs = cert_addrs(&identity.cert);
+    for addr in recipients {
+        if own_addrs.iter().any(|own_addr| addr_cmp(own_addr, addr)) {
+            continue;
+        }
+        match load_certificate(context, addr).await? {
+            Some(cert) => certs.push(cert)?,
+            None => {
+                info!(
+                    context,
+                    "S/MIME: no certificate for {}, not encrypting", addr
+                );
+                return Ok(Some((entity_to_part(&signed)?, state)));
+            }
+        }
+    }
+    let encrypted = Pkcs7::encrypt(&certs, &signed, Cipher::aes_256_cbc(), Pkcs7Flags::BINARY)?
+        .to_smime(&[], Pkcs7Flags::BINARY)?;
+    state |= SmimeState::ENCRYPTED;
+    Ok(Some((entity_to_part(&encrypted)?, state)))
+}
+
+#[cfg(not(feature = "smime"))]
+async fn try_render(
+    _context: &Context,
+    _content: &str,
+    _recipients: &[String],
+) -> Result<Option<(PartBuilder, SmimeState)>> {
+    Ok(None)
+}
+
+/// Converts the MIME entity written by OpenSSL into a part of the message.
+#[cfg(feature = "smime")]
+fn entity_to_part(entity: &[u8]) -> Result<PartBuilder> {
+    let entity = String::from_utf8_lossy(entity).replace("\r\n", "\n");
+    let mut split = entity.splitn(2, "\n\n");
+    let headers = split.next().unwrap_or_default();
+    let body = split
+        .next()
+        .ok_or_else(|| format_err!("S/MIME entity without body"))?;
+    let content_type = headers
+        .lines()
+        .find(|line| line.to_lowercase().starts_with("content-type:"))
+        .map(|line| line["content-type:".len()..].trim().to_string())
+        .ok_or_else(|| format_err!("S/MIME entity without Content-Type"))?;
+    Ok(PartBuilder::new()
+        .header(("Content-Type".to_string(), content_type))
+        .header(("Content-Transfer-Encoding", "base64"))
+        .header(("Content-Disposition", "attachment; filename=\"smime.p7m\""))
+        .body(body.trim_end().replace("\n", "\r\n")))
+}
+
+/// The own certificate with its private key.
+#[cfg(feature = "smime")]
+struct Identity {
+    cert: X509,
+
+    /// Intermediate certificates sent along with the signature.
+    chain: Stack<X509>,
+
+    key: PKey<Private>,
+}
+
+/// Loads the configured certificate and private key.
+///
+/// Returns `None` if S/MIME is not configured.
+#[cfg(feature = "smime")]
+async fn load_identity(context: &Context) -> Result<Option<Identity>> {
+    let certs = context
+        .get_config(Config::SmimeCertificate)
+        .await
+        .unwrap_or_default();
+    let key = context
+        .get_config(Config::SmimeKey)
+        .await
+        .unwrap_or_default();
+    if certs.trim().is_empty() || key.trim().is_empty() {
+        return Ok(None);
+    }
+    let mut certs = X509::stack_from_pem(certs.as_bytes())?.into_iter();
+    let cert = certs
+        .next()
+        .ok_or_else(|| format_err!("No certificate in smime_certificate"))?;
+    let mut chain = Stack::new()?;
+    for cert in certs {
+        chain.push(cert)?;
+    }
+    let key = PKey::private_key_from_pem(key.as_bytes())?;
+    ensure!(
+        cert.public_key()?.public_eq(&key),
+        "Private key does not match the certificate"
+    );
+    let self_addr = context
+        .get_config(Config::ConfiguredAddr)
+        .await
+        .unwrap_or_default();
+    ensure!(
+        cert_addrs(&cert)
+            .iter()
+            .any(|addr| addr_cmp(addr, &self_addr)),
+        "Certificate is not issued for {}",
+        self_addr
+    );
+    Ok(Some(Identity { cert, chain, key }))
+}
+
+/// Returns the system trust store with the authorities of [Config::SmimeCa] added.
+#[cfg(feature = "smime")]
+async fn trust_store(context: &Context) -> Result<X509Store> {
+    let mut builder = X509StoreBuilder::new()?;
+    builder.set_default_paths()?;
+    if let Some(pem) = context.get_config(Config::SmimeCa).await {
+        for cert in X509::stack_from_pem(pem.as_bytes())? {
+            builder.add_cert(cert)?;
+        }
+    }
+    Ok(builder.build())
+}
+
+/// Checks that `cert` is valid and issued by a trusted authority.
+#[cfg(feature = "smime")]
+fn verify_chain(store: &X509Store, cert: &X509Ref, chain: &Stack<X509>) -> Result<()> {
+    let mut store_context = X509StoreContext::new()?;
+    let (valid, error) = store_context.init(store, cert, chain, |ctx| {
+        let valid = ctx.verify_cert()?;
+        Ok((valid, ctx.error()))
+    })?;
+    ensure!(valid, "Invalid certificate chain: {}", error.error_string());
+    Ok(())
+}
+
+/// Verifies the signature of `pkcs7` and returns the certificate of the signer.
+///
+/// The signed content is written to `out`.
+#[cfg(feature = "smime")]
+fn verify_signature(
+    pkcs7: &Pkcs7,
+    store: &X509Store,
+    detached: Option<&[u8]>,
+    out: &mut Vec<u8>,
+    from: &str,
+) -> Result<X509> {
+    let certs: Stack<X509> = Stack::new()?;
+    pkcs7.verify(&certs, store, detached, Some(out), Pkcs7Flags::empty())?;
+    let signer = pkcs7
+        .signers(&certs, Pkcs7Flags::empty())?
+        .into_iter()
+        .next()
+        .ok_or_else(|| format_err!("No signer"))?;
+    ensure!(
+        cert_addrs(&signer).iter().any(|addr| addr_cmp(addr, from)),
+        "Certificate is not issued for {}",
+        from
+    );
+    Ok(signer)
+}
+
+/// Returns the email addresses a certificate is issued for.
+#[cfg(feature = "smime")]
+fn cert_addrs(cert: &X509Ref) -> Vec<String> {
+    let mut addrs: Vec<String> = cert
+        .subject_alt_names()
+        .map(|names| {
+            names
+                .iter()
+                .filter_map(|name| name.email().map(|email| email.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    for entry in cert.subject_name().entries_by_nid(Nid::PKCS9_EMAILADDRESS) {
+        if let Ok(email) = entry.data().as_utf8() {
+            addrs.push(email.to_string());
+        }
+    }
+    addrs
+}
+
+/// Returns the expiry of `cert` as unix timestamp.
+#[cfg(feature = "smime")]
+fn not_after(cert: &X509Ref) -> Result<i64> {
+    let diff = openssl::asn1::Asn1Time::from_unix(0)?.diff(cert.not_after())?;
+    Ok(i64::from(diff.days) * 24 * 60 * 60 + i64::from(diff.secs))
+}
+
+/// Stores the certificate of `addr`, replacing previous ones.
+#[cfg(feature = "smime")]
+async fn save_certificate(context: &Context, addr: &str, cert: &X509Ref) -> Result<()> {
+    let der = cert.to_der()?;
+    let not_after = not_after(cert)?;
+    context
+        .sql
+        .execute(
+            "DELETE FROM smime_certificates WHERE addr=? COLLATE NOCASE;",
+            paramsv![addr],
+        )
+        .await?;
+    context
+        .sql
+        .execute(
+            "INSERT INTO smime_certificates (addr, certificate, not_after, timestamp) \
+             VALUES (?,?,?,?);",
+            paramsv![addr, der, not_after, time()],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Loads the stored certificate of `addr` unless it is expired.
+#[cfg(feature = "smime")]
+async fn load_certificate(context: &Context, addr: &str) -> Result<Option<X509>> {
+    let der: Option<Vec<u8>> = context
+        .sql
+        .query_row_optional(
+            "SELECT certificate FROM smime_certificates \
+             WHERE addr=? COLLATE NOCASE AND not_after>?;",
+            paramsv![addr, time()],
+            |row| row.get(0),
+        )
+        .await?;
+    match der {
+        Some(der) => Ok(Some(X509::from_der(&der)?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smime_type() {
+        let check = |raw: &[u8]| smime_type(&mailparse::parse_mail(raw).unwrap());
+        assert_eq!(
+            check(b"Content-Type: application/pkcs7-mime; smime-type=enveloped-data; name=smime.p7m\n\nMIAG"),
+            Some(SmimeType::Enveloped)
+        );
+        assert_eq!(
+            check(b"Content-Type: application/x-pkcs7-mime; name=\"smime.p7m\"\n\nMIAG"),
+            Some(SmimeType::Enveloped)
+        );
+        assert_eq!(
+            check(b"Content-Type: application/pkcs7-mime; smime-type=signed-data\n\nMIAG"),
+            Some(SmimeType::Signed)
+        );
+        assert_eq!(
+            check(b"Content-Type: multipart/signed; protocol=\"application/pkcs7-signature\"; micalg=sha-256; boundary=x\n\n--x\n\nfoo\n--x--\n"),
+            Some(SmimeType::Signed)
+        );
+        assert_eq!(
+            check(b"Content-Type: multipart/signed; protocol=\"application/pgp-signature\"; boundary=x\n\n--x\n\nfoo\n--x--\n"),
+            None
+        );
+        assert_eq!(check(b"Content-Type: text/plain\n\nfoo"), None);
+    }
+
+    #[test]
+    fn test_smime_state() {
+        assert_eq!(SmimeState::default(), SmimeState::empty());
+        let state = SmimeState::from_bits_truncate(3);
+        assert!(state.contains(SmimeState::SIGNED | SmimeState::ENCRYPTED));
+    }
+}
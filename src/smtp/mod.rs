@@ -12,11 +12,16 @@ use crate::context::Context;
 use crate::events::Event;
 use crate::login_param::{dc_build_tls, LoginParam};
 use crate::oauth2::*;
+use crate::socks::Socks5Config;
 use crate::stock::StockMessage;
 
 /// SMTP write and read timeout in seconds.
 const SMTP_TIMEOUT: u64 = 30;
 
+/// The connection is kept open for further messages this long after the
+/// last message was sent.
+pub(crate) const SMTP_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Bad parameters")]
@@ -56,6 +61,9 @@ pub struct Smtp {
     /// (eg connect or send succeeded). On initialization and disconnect
     /// it is set to None.
     last_success: Option<Instant>,
+
+    /// Number of messages sent over the current connection.
+    sent_msgs: usize,
 }
 
 impl Smtp {
@@ -70,13 +78,18 @@ impl Smtp {
             transport.close().await.ok();
         }
         self.last_success = None;
+        self.sent_msgs = 0;
     }
 
     /// Return true if smtp was connected but is not known to
     /// have been successfully used the last 60 seconds
+    /// or if the connection was reused for several messages.
+    ///
+    /// Servers may close such connections without notice,
+    /// so errors on them are worth an immediate retry on a new connection.
     pub async fn has_maybe_stale_connection(&self) -> bool {
         if let Some(last_success) = self.last_success {
-            Instant::now().duration_since(last_success).as_secs() > 60
+            self.sent_msgs > 0 || Instant::now().duration_since(last_success).as_secs() > 60
         } else {
             false
         }
@@ -155,7 +168,15 @@ impl Smtp {
             smtp::ClientSecurity::Wrapper(tls_parameters)
         };
 
-        let client = smtp::SmtpClient::with_security((domain.as_str(), port), security)
+        let tls_wrapped = matches!(security, smtp::ClientSecurity::Wrapper(_));
+        let socks5 = Socks5Config::from_database(context).await;
+        // async-smtp resolves the address it is given even if the stream
+        // is opened by us, pass the proxy so the server name is not looked up.
+        let server_addr = match socks5 {
+            Some(ref socks5) => (socks5.host.as_str(), socks5.port),
+            None => (domain.as_str(), port),
+        };
+        let client = smtp::SmtpClient::with_security(server_addr, security)
             .await
             .map_err(Error::ConnectionSetupFailure)?;
 
@@ -167,7 +188,26 @@ impl Smtp {
             .timeout(Some(Duration::from_secs(SMTP_TIMEOUT)));
 
         let mut trans = client.into_transport();
-        if let Err(err) = trans.connect().await {
+        let connected = match socks5 {
+            Some(ref socks5) => {
+                info!(context, "Connecting to SMTP server through {}", socks5);
+                let tls = if tls_wrapped {
+                    Some(dc_build_tls(lp.smtp_certificate_checks))
+                } else {
+                    None
+                };
+                match connect_socks5(socks5, domain, port, tls).await {
+                    Ok(stream) => trans.connect_with_stream(stream).await,
+                    Err(err) => Err(err),
+                }
+            }
+            None => trans.connect().await,
+        };
+        if let Err(err) = connected {
+            if 0 != lp.server_flags & (DC_LP_AUTH_OAUTH2 as i32) {
+                // the token may be revoked before it expires, refresh it on the next try.
+                invalidate_access_token(context).await;
+            }
             let message = context
                 .stock_string_repl_str2(
                     StockMessage::ServerResponse,
@@ -182,6 +222,7 @@ impl Smtp {
 
         self.transport = Some(trans);
         self.last_success = Some(Instant::now());
+        self.sent_msgs = 0;
 
         context.emit_event(Event::SmtpConnected(format!(
             "SMTP-LOGIN as {} ok",
@@ -191,3 +232,279 @@ impl Smtp {
         Ok(())
     }
 }
+
+/// Opens a connection to `domain:port` through the proxy.
+///
+/// async-smtp only talks SMTP on the returned stream, so the TLS
+/// handshake for implicit TLS is done here.
+async fn connect_socks5(
+    socks5: &Socks5Config,
+    domain: &str,
+    port: u16,
+    tls: Option<async_native_tls::TlsConnector>,
+) -> std::result::Result<NetworkStream, smtp::error::Error> {
+    let stream = socks5.connect(domain, port).await?;
+    match tls {
+        Some(tls) => Ok(NetworkStream::Tls(tls.connect(domain, stream).await?)),
+        None => Ok(NetworkStream::Tcp(stream)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use async_std::io::{self, BufReader};
+    use async_std::net::{TcpListener, TcpStream};
+    use async_std::prelude::*;
+    use async_std::task;
+
+    use crate::config::Config;
+    use crate::test_utils::*;
+
+    /// SMTP server accepting all messages without TLS.
+    struct MockServer {
+        addr: SocketAddr,
+        connections: Arc<AtomicUsize>,
+        messages: Arc<AtomicUsize>,
+    }
+
+    impl MockServer {
+        /// Starts the server; if `close_after` is set,
+        /// connections are closed after this number of messages.
+        ///
+        /// With `pipelining`, the server announces PIPELINING and holds back
+        /// the replies to MAIL and RCPT until DATA is received.
+        async fn start(close_after: Option<usize>, pipelining: bool) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let connections = Arc::new(AtomicUsize::new(0));
+            let messages = Arc::new(AtomicUsize::new(0));
+            let server = MockServer {
+                addr,
+                connections: connections.clone(),
+                messages: messages.clone(),
+            };
+            task::spawn(async move {
+                let mut incoming = listener.incoming();
+                while let Some(Ok(stream)) = incoming.next().await {
+                    connections.fetch_add(1, Ordering::SeqCst);
+                    let messages = messages.clone();
+                    task::spawn(async move {
+                        Self::serve(stream, messages, close_after, pipelining)
+                            .await
+                            .ok();
+                    });
+                }
+            });
+            server
+        }
+
+        async fn serve(
+            stream: TcpStream,
+            messages: Arc<AtomicUsize>,
+            close_after: Option<usize>,
+            pipelining: bool,
+        ) -> std::io::Result<()> {
+            let mut reader = BufReader::new(&stream);
+            let mut writer = &stream;
+            writer.write_all(b"220 localhost ESMTP\r\n").await?;
+            let mut sent = 0;
+            let mut in_data = false;
+            let mut held_back = Vec::new();
+            let mut line = String::new();
+            loop {
+                line.clear();
+                if reader.read_line(&mut line).await? == 0 {
+                    return Ok(());
+                }
+                if in_data {
+                    if line == ".\r\n" {
+                        in_data = false;
+                        sent += 1;
+                        messages.fetch_add(1, Ordering::SeqCst);
+                        writer.write_all(b"250 OK\r\n").await?;
+                        if close_after == Some(sent) {
+                            return Ok(());
+                        }
+                    }
+                    continue;
+                }
+                let command = line.to_uppercase();
+                let reply: &[u8] = if command.starts_with("EHLO") && pipelining {
+                    b"250-localhost\r\n250-PIPELINING\r\n250-AUTH PLAIN LOGIN\r\n250 8BITMIME\r\n"
+                } else if command.starts_with("EHLO") {
+                    b"250-localhost\r\n250-AUTH PLAIN LOGIN\r\n250 8BITMIME\r\n"
+                } else if command.starts_with("AUTH") {
+                    b"235 OK\r\n"
+                } else if pipelining && (command.starts_with("MAIL") || command.starts_with("RCPT"))
+                {
+                    held_back.extend_from_slice(b"250 OK\r\n");
+                    continue;
+                } else if command.starts_with("DATA") {
+                    in_data = true;
+                    held_back.extend_from_slice(b"354 Go ahead\r\n");
+                    writer.write_all(&held_back).await?;
+                    held_back.clear();
+                    continue;
+                } else if command.starts_with("QUIT") {
+                    writer.write_all(b"221 Bye\r\n").await?;
+                    return Ok(());
+                } else {
+                    b"250 OK\r\n"
+                };
+                writer.write_all(reply).await?;
+            }
+        }
+
+        fn login_param(&self) -> LoginParam {
+            let mut lp = LoginParam::new();
+            lp.addr = "alice@example.org".to_string();
+            lp.send_server = self.addr.ip().to_string();
+            lp.send_port = self.addr.port() as i32;
+            lp.send_user = "alice".to_string();
+            lp.send_pw = "secret".to_string();
+            lp.server_flags = DC_LP_AUTH_NORMAL | DC_LP_SMTP_SOCKET_PLAIN as i32;
+            lp
+        }
+    }
+
+    /// Accepts one connection as a SOCKS5 proxy without authentication
+    /// and forwards it to `target`, whatever the client asked for.
+    ///
+    /// Returns the port and the received connect request.
+    async fn forwarding_proxy(target: SocketAddr) -> (u16, task::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = task::spawn(async move {
+            let (client, _) = listener.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            (&client).read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [5, 1, 0]);
+            (&client).write_all(&[5, 0]).await.unwrap();
+
+            let mut request = vec![0u8; 5];
+            (&client).read_exact(&mut request).await.unwrap();
+            let mut rest = vec![0u8; request[4] as usize + 2];
+            (&client).read_exact(&mut rest).await.unwrap();
+            request.extend_from_slice(&rest);
+            (&client)
+                .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 25])
+                .await
+                .unwrap();
+
+            let server = TcpStream::connect(target).await.unwrap();
+            let (client2, server2) = (client.clone(), server.clone());
+            task::spawn(async move { io::copy(&mut &client2, &mut &server2).await });
+            task::spawn(async move { io::copy(&mut &server, &mut &client).await });
+            request
+        });
+        (port, handle)
+    }
+
+    async fn send(t: &TestContext, smtp: &mut Smtp) -> send::Result<()> {
+        let recipients = vec![EmailAddress::new("bob@example.net".to_string()).unwrap()];
+        smtp.send(
+            &t.ctx,
+            recipients,
+            b"Subject: hi\r\n\r\nhello\r\n".to_vec(),
+            1,
+        )
+        .await
+    }
+
+    #[async_std::test]
+    async fn test_connection_reuse() {
+        let t = dummy_context().await;
+        let server = MockServer::start(None, false).await;
+        let mut smtp = Smtp::new();
+        smtp.connect(&t.ctx, &server.login_param()).await.unwrap();
+        assert!(!smtp.has_maybe_stale_connection().await);
+
+        for _ in 0..10 {
+            send(&t, &mut smtp).await.unwrap();
+        }
+        assert_eq!(server.messages.load(Ordering::SeqCst), 10);
+        assert_eq!(server.connections.load(Ordering::SeqCst), 1);
+
+        smtp.disconnect().await;
+        assert!(!smtp.is_connected().await);
+    }
+
+    #[async_std::test]
+    async fn test_pipelining() {
+        let t = dummy_context().await;
+        // the server replies to MAIL and RCPT only after DATA,
+        // so sending without pipelining would time out
+        let server = MockServer::start(None, true).await;
+        let mut smtp = Smtp::new();
+        smtp.connect(&t.ctx, &server.login_param()).await.unwrap();
+
+        let recipients = vec![
+            EmailAddress::new("bob@example.net".to_string()).unwrap(),
+            EmailAddress::new("claire@example.net".to_string()).unwrap(),
+        ];
+        smtp.send(
+            &t.ctx,
+            recipients,
+            b"Subject: hi\r\n\r\nhello\r\n".to_vec(),
+            1,
+        )
+        .await
+        .unwrap();
+        send(&t, &mut smtp).await.unwrap();
+        assert_eq!(server.messages.load(Ordering::SeqCst), 2);
+        assert_eq!(server.connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[async_std::test]
+    async fn test_reconnect_on_closed_connection() {
+        let t = dummy_context().await;
+        let server = MockServer::start(Some(1), false).await;
+        let mut smtp = Smtp::new();
+        smtp.connect(&t.ctx, &server.login_param()).await.unwrap();
+        send(&t, &mut smtp).await.unwrap();
+
+        // the server closed the connection after the first message
+        assert!(send(&t, &mut smtp).await.is_err());
+        assert!(smtp.has_maybe_stale_connection().await);
+
+        smtp.disconnect().await;
+        smtp.connect(&t.ctx, &server.login_param()).await.unwrap();
+        send(&t, &mut smtp).await.unwrap();
+        assert_eq!(server.messages.load(Ordering::SeqCst), 2);
+        assert_eq!(server.connections.load(Ordering::SeqCst), 2);
+    }
+
+    #[async_std::test]
+    async fn test_connect_through_socks5() {
+        let t = dummy_context().await;
+        let server = MockServer::start(None, false).await;
+        let (port, proxy) = forwarding_proxy(server.addr).await;
+        t.ctx
+            .set_config(Config::Socks5Host, Some("127.0.0.1"))
+            .await
+            .unwrap();
+        t.ctx
+            .set_config(Config::Socks5Port, Some(&port.to_string()))
+            .await
+            .unwrap();
+
+        // the name does not resolve, only the proxy can connect to it
+        let mut lp = server.login_param();
+        lp.send_server = "smtp.example.org".to_string();
+        let mut smtp = Smtp::new();
+        smtp.connect(&t.ctx, &lp).await.unwrap();
+        send(&t, &mut smtp).await.unwrap();
+        assert_eq!(server.messages.load(Ordering::SeqCst), 1);
+
+        let mut expected = vec![5, 1, 0, 3, 16];
+        expected.extend_from_slice(b"smtp.example.org");
+        expected.extend_from_slice(&server.addr.port().to_be_bytes());
+        assert_eq!(proxy.await, expected);
+    }
+}
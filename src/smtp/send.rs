@@ -2,9 +2,12 @@
 
 use super::Smtp;
 use async_smtp::*;
+use async_trait::async_trait;
 
 use crate::context::Context;
 use crate::events::Event;
+use crate::login_param::LoginParam;
+use crate::transport::SendTransport;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -23,6 +26,9 @@ pub enum Error {
 impl Smtp {
     /// Send a prepared mail to recipients.
     /// On successful send out Ok() is returned.
+    ///
+    /// If the server supports PIPELINING, MAIL, RCPT and DATA are sent
+    /// at once, see vendor/async-smtp.
     pub async fn send(
         &mut self,
         context: &Context,
@@ -54,6 +60,7 @@ impl Smtp {
                 message_len, recipients_display
             )));
             self.last_success = Some(std::time::Instant::now());
+            self.sent_msgs += 1;
 
             Ok(())
         } else {
@@ -65,3 +72,27 @@ impl Smtp {
         }
     }
 }
+
+#[async_trait]
+impl SendTransport for Smtp {
+    /// Connects if needed and sends the message.
+    ///
+    /// Failures while sending are returned as [Error].
+    async fn send(
+        &mut self,
+        context: &Context,
+        recipients: &[String],
+        message: &[u8],
+    ) -> crate::error::Result<()> {
+        if !self.is_connected().await {
+            let loginparam = LoginParam::from_database(context, "configured_").await;
+            self.connect(context, &loginparam).await?;
+        }
+        let recipients = recipients
+            .iter()
+            .map(|addr| EmailAddress::new(addr.to_string()).map_err(Error::EnvelopeError))
+            .collect::<Result<Vec<_>>>()?;
+        Smtp::send(self, context, recipients, message.to_vec(), 0).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,403 @@
+//! # SOCKS5 proxy support
+//!
+//! If `socks5_host` is configured, IMAP and SMTP connections and HTTP
+//! requests are made through a SOCKS5 proxy ([RFC 1928]), optionally
+//! authenticating with username and password ([RFC 1929]).  Host names
+//! are passed to the proxy unresolved, so no DNS requests leak around it.
+//!
+//! [RFC 1928]: https://tools.ietf.org/html/rfc1928
+//! [RFC 1929]: https://tools.ietf.org/html/rfc1929
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use async_std::io::{self, prelude::*};
+use async_std::net::TcpStream;
+use http_types::{Request, Response, StatusCode};
+
+use crate::config::Config;
+use crate::context::Context;
+
+/// Timeout for connecting to the proxy and for the proxy
+/// to connect to the target.
+const SOCKS5_TIMEOUT: Duration = Duration::from_secs(60);
+
+const SOCKS_VERSION: u8 = 0x05;
+const AUTH_NONE: u8 = 0x00;
+const AUTH_USER_PASSWORD: u8 = 0x02;
+const USER_PASSWORD_VERSION: u8 = 0x01;
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Socks5Config {
+    pub host: String,
+    pub port: u16,
+    pub user_password: Option<(String, String)>,
+}
+
+impl Socks5Config {
+    /// Reads the proxy configuration, returns `None` if no proxy is set.
+    pub async fn from_database(context: &Context) -> Option<Self> {
+        let host = context.get_config(Config::Socks5Host).await?;
+        let host = host.trim();
+        if host.is_empty() {
+            return None;
+        }
+        let port = context.get_config_int(Config::Socks5Port).await;
+        let user = context
+            .get_config(Config::Socks5User)
+            .await
+            .unwrap_or_default();
+        let password = context
+            .get_config(Config::Socks5Password)
+            .await
+            .unwrap_or_default();
+
+        Some(Socks5Config {
+            host: host.to_string(),
+            port: if port > 0 && port <= 0xffff {
+                port as u16
+            } else {
+                1080
+            },
+            user_password: if user.is_empty() {
+                None
+            } else {
+                Some((user, password))
+            },
+        })
+    }
+
+    /// Connects to `target_host:target_port` through the proxy.
+    pub async fn connect(&self, target_host: &str, target_port: u16) -> io::Result<TcpStream> {
+        io::timeout(SOCKS5_TIMEOUT, async {
+            let mut stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+            self.handshake(&mut stream, target_host, target_port)
+                .await?;
+            Ok(stream)
+        })
+        .await
+    }
+
+    async fn handshake(
+        &self,
+        stream: &mut TcpStream,
+        target_host: &str,
+        target_port: u16,
+    ) -> io::Result<()> {
+        // method negotiation
+        let method = if self.user_password.is_some() {
+            AUTH_USER_PASSWORD
+        } else {
+            AUTH_NONE
+        };
+        stream.write_all(&[SOCKS_VERSION, 1, method]).await?;
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != SOCKS_VERSION {
+            return Err(proxy_error("not a SOCKS5 proxy"));
+        }
+        if reply[1] != method {
+            return Err(proxy_error("authentication method not accepted"));
+        }
+
+        if let Some((user, password)) = &self.user_password {
+            let mut auth = vec![USER_PASSWORD_VERSION];
+            push_len_prefixed(&mut auth, user.as_bytes())?;
+            push_len_prefixed(&mut auth, password.as_bytes())?;
+            stream.write_all(&auth).await?;
+            let mut reply = [0u8; 2];
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0 {
+                return Err(proxy_error("authentication failed"));
+            }
+        }
+
+        // connect request
+        let mut request = vec![SOCKS_VERSION, CMD_CONNECT, 0];
+        match target_host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => {
+                request.push(ATYP_IPV4);
+                request.extend_from_slice(&ip.octets());
+            }
+            Ok(IpAddr::V6(ip)) => {
+                request.push(ATYP_IPV6);
+                request.extend_from_slice(&ip.octets());
+            }
+            Err(_) => {
+                request.push(ATYP_DOMAIN);
+                push_len_prefixed(&mut request, target_host.as_bytes())?;
+            }
+        }
+        request.extend_from_slice(&target_port.to_be_bytes());
+        stream.write_all(&request).await?;
+
+        let mut reply = [0u8; 4];
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != SOCKS_VERSION {
+            return Err(proxy_error("invalid reply"));
+        }
+        if reply[1] != 0 {
+            return Err(proxy_error(reply_message(reply[1])));
+        }
+        // skip the bound address and port
+        let addr_len = match reply[3] {
+            ATYP_IPV4 => 4,
+            ATYP_IPV6 => 16,
+            ATYP_DOMAIN => {
+                let mut len = [0u8; 1];
+                stream.read_exact(&mut len).await?;
+                len[0] as usize
+            }
+            _ => return Err(proxy_error("invalid address type in reply")),
+        };
+        let mut bound = vec![0u8; addr_len + 2];
+        stream.read_exact(&mut bound).await?;
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Socks5Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "socks5://{}:{}", self.host, self.port)
+    }
+}
+
+/// Opens a TCP connection to `host:port`, through the proxy if one is set.
+pub(crate) async fn connect_tcp(
+    socks5: Option<&Socks5Config>,
+    host: &str,
+    port: u16,
+) -> io::Result<TcpStream> {
+    match socks5 {
+        Some(socks5) => socks5.connect(host, port).await,
+        None => TcpStream::connect((host, port)).await,
+    }
+}
+
+/// Sends an HTTP request, through the proxy if one is set.
+///
+/// Only `http` and `https` URLs are supported, redirects are not followed.
+pub(crate) async fn send_http(context: &Context, req: Request) -> http_types::Result<Response> {
+    let url = req.url().clone();
+    let host = match url.host_str() {
+        Some(host) => host,
+        None => {
+            return Err(http_types::Error::from_str(
+                StatusCode::BadRequest,
+                format!("no host in URL {}", url),
+            ))
+        }
+    };
+    let port = url.port_or_known_default().unwrap_or(80);
+    let socks5 = Socks5Config::from_database(context).await;
+    let stream = connect_tcp(socks5.as_ref(), host, port).await?;
+    match url.scheme() {
+        "http" => async_h1::client::connect(stream, req).await,
+        "https" => {
+            let stream = async_native_tls::TlsConnector::new()
+                .connect(host, stream)
+                .await?;
+            async_h1::client::connect(stream, req).await
+        }
+        scheme => Err(http_types::Error::from_str(
+            StatusCode::BadRequest,
+            format!("unsupported URL scheme {}", scheme),
+        )),
+    }
+}
+
+fn push_len_prefixed(buf: &mut Vec<u8>, data: &[u8]) -> io::Result<()> {
+    if data.len() > 255 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "SOCKS5 field longer than 255 bytes",
+        ));
+    }
+    buf.push(data.len() as u8);
+    buf.extend_from_slice(data);
+    Ok(())
+}
+
+fn reply_message(code: u8) -> &'static str {
+    match code {
+        0x01 => "general SOCKS server failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+fn proxy_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("SOCKS5 proxy: {}", msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use async_std::net::TcpListener;
+    use async_std::task;
+    use http_types::{Method, Url};
+
+    use crate::test_utils::*;
+
+    /// Accepts one connection and acts as a SOCKS5 proxy requiring
+    /// `user`/`secret`.  After the handshake, it echoes five bytes or,
+    /// if `reply` is given, sends `reply` after reading a request header.
+    ///
+    /// Returns the port and the received connect request.
+    async fn fake_proxy(reply: Option<&'static [u8]>) -> (u16, task::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = task::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [SOCKS_VERSION, 1, AUTH_USER_PASSWORD]);
+            stream
+                .write_all(&[SOCKS_VERSION, AUTH_USER_PASSWORD])
+                .await
+                .unwrap();
+
+            let mut auth = [0u8; 13];
+            stream.read_exact(&mut auth).await.unwrap();
+            assert_eq!(&auth, b"\x01\x04user\x06secret");
+            stream.write_all(&[USER_PASSWORD_VERSION, 0]).await.unwrap();
+
+            let mut request = vec![0u8; 5];
+            stream.read_exact(&mut request).await.unwrap();
+            let mut rest = vec![0u8; request[4] as usize + 2];
+            stream.read_exact(&mut rest).await.unwrap();
+            request.extend_from_slice(&rest);
+            stream
+                .write_all(&[SOCKS_VERSION, 0, 0, ATYP_IPV4, 10, 0, 0, 1, 0, 25])
+                .await
+                .unwrap();
+
+            match reply {
+                Some(reply) => {
+                    let mut header = Vec::new();
+                    while !header.ends_with(b"\r\n\r\n") {
+                        let mut byte = [0u8; 1];
+                        stream.read_exact(&mut byte).await.unwrap();
+                        header.push(byte[0]);
+                    }
+                    stream.write_all(reply).await.unwrap();
+                }
+                None => {
+                    let mut buf = [0u8; 5];
+                    stream.read_exact(&mut buf).await.unwrap();
+                    stream.write_all(&buf).await.unwrap();
+                }
+            }
+            request
+        });
+        (port, handle)
+    }
+
+    fn proxy_config(port: u16) -> Socks5Config {
+        Socks5Config {
+            host: "127.0.0.1".to_string(),
+            port,
+            user_password: Some(("user".to_string(), "secret".to_string())),
+        }
+    }
+
+    #[async_std::test]
+    async fn test_socks5_from_database() {
+        let t = dummy_context().await;
+        assert_eq!(Socks5Config::from_database(&t.ctx).await, None);
+
+        t.ctx
+            .set_config(Config::Socks5Host, Some("127.0.0.1"))
+            .await
+            .unwrap();
+        assert_eq!(
+            Socks5Config::from_database(&t.ctx).await,
+            Some(Socks5Config {
+                host: "127.0.0.1".to_string(),
+                port: 1080,
+                user_password: None,
+            })
+        );
+
+        t.ctx
+            .set_config(Config::Socks5Port, Some("9050"))
+            .await
+            .unwrap();
+        t.ctx
+            .set_config(Config::Socks5User, Some("user"))
+            .await
+            .unwrap();
+        t.ctx
+            .set_config(Config::Socks5Password, Some("secret"))
+            .await
+            .unwrap();
+        assert_eq!(
+            Socks5Config::from_database(&t.ctx).await,
+            Some(proxy_config(9050))
+        );
+    }
+
+    #[async_std::test]
+    async fn test_socks5_connect() {
+        let (port, proxy) = fake_proxy(None).await;
+        let mut stream = proxy_config(port)
+            .connect("imap.example.org", 993)
+            .await
+            .unwrap();
+        stream.write_all(b"hello").await.unwrap();
+        let mut buf = [0u8; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        let mut expected = vec![SOCKS_VERSION, CMD_CONNECT, 0, ATYP_DOMAIN, 16];
+        expected.extend_from_slice(b"imap.example.org");
+        expected.extend_from_slice(&993u16.to_be_bytes());
+        assert_eq!(proxy.await, expected);
+    }
+
+    #[async_std::test]
+    async fn test_send_http_through_proxy() {
+        let t = dummy_context().await;
+        let (port, proxy) =
+            fake_proxy(Some(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")).await;
+        t.ctx
+            .set_config(Config::Socks5Host, Some("127.0.0.1"))
+            .await
+            .unwrap();
+        t.ctx
+            .set_config(Config::Socks5Port, Some(&port.to_string()))
+            .await
+            .unwrap();
+        t.ctx
+            .set_config(Config::Socks5User, Some("user"))
+            .await
+            .unwrap();
+        t.ctx
+            .set_config(Config::Socks5Password, Some("secret"))
+            .await
+            .unwrap();
+
+        let url = Url::parse("http://autoconfig.example.org/mail/config-v1.1.xml").unwrap();
+        let mut response = send_http(&t.ctx, Request::new(Method::Get, url))
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(response.body_string().await.unwrap(), "ok");
+        assert_eq!(proxy.await[5..27], b"autoconfig.example.org"[..]);
+    }
+}
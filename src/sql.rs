@@ -3,16 +3,19 @@
 use async_std::prelude::*;
 use async_std::sync::RwLock;
 
-use std::collections::HashSet;
-use std::path::Path;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::time::Duration;
 
-use rusqlite::{Connection, Error as SqlError, OpenFlags};
+use rusqlite::{Connection, Error as SqlError, OpenFlags, NO_PARAMS};
 
+use crate::blob_crypt;
 use crate::chat::{update_device_icon, update_saved_messages_icon};
-use crate::constants::{ShowEmails, DC_CHAT_ID_TRASH};
-use crate::context::Context;
+use crate::constants::{MvboxPolicy, ShowEmails, DC_CHAT_ID_TRASH};
+use crate::context::{Context, HousekeepingOptions, HousekeepingReport};
 use crate::dc_tools::*;
+use crate::events::Event;
 use crate::param::*;
 use crate::peerstate::*;
 
@@ -38,6 +41,10 @@ pub enum Error {
     SqlAlreadyOpen,
     #[error("Sqlite: Failed to open")]
     SqlFailedToOpen,
+    #[error("Sqlite: Database encryption is not supported by this build")]
+    EncryptionUnsupported,
+    #[error("Sqlite: Wrong passphrase")]
+    WrongPassphrase,
     #[error("{0}")]
     Io(#[from] std::io::Error),
     #[error("{0:?}")]
@@ -48,16 +55,73 @@ pub enum Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Maximum number of connections in the pool.
+const MAX_CONNECTIONS: u32 = 10;
+
+/// Number of prepared statements cached by each connection.
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+/// Hit statistics of the prepared statement caches, see [Sql::statement_cache_stats].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StatementCacheStats {
+    /// Number of statements reused from the cache.
+    pub hits: usize,
+
+    /// Number of statements compiled as they were not cached.
+    pub misses: usize,
+}
+
+/// Mirrors the least-recently-used statement caches of the connections
+/// to count hits, as rusqlite does not expose them.
+#[derive(Debug, Default)]
+struct StatementCacheTracker {
+    /// Cached statements of each connection, most recently used first.
+    connections: HashMap<usize, VecDeque<String>>,
+    stats: StatementCacheStats,
+}
+
+impl StatementCacheTracker {
+    fn record(&mut self, conn: &Connection, sql: &str) {
+        // the handle is only used to tell connections apart
+        let handle = unsafe { conn.handle() } as usize;
+        if !self.connections.contains_key(&handle)
+            && self.connections.len() >= 2 * MAX_CONNECTIONS as usize
+        {
+            // forget connections closed by the pool
+            self.connections.clear();
+        }
+        let cached = self.connections.entry(handle).or_default();
+        if let Some(pos) = cached.iter().position(|cached_sql| cached_sql == sql) {
+            if let Some(cached_sql) = cached.remove(pos) {
+                cached.push_front(cached_sql);
+            }
+            self.stats.hits += 1;
+        } else {
+            cached.push_front(sql.to_string());
+            cached.truncate(STATEMENT_CACHE_CAPACITY);
+            self.stats.misses += 1;
+        }
+    }
+}
+
 /// A wrapper around the underlying Sqlite3 object.
 #[derive(DebugStub)]
 pub struct Sql {
     pool: RwLock<Option<r2d2::Pool<r2d2_sqlite::SqliteConnectionManager>>>,
+
+    /// Passphrase of the database, `None` if it is not encrypted.
+    #[debug_stub = "Passphrase"]
+    passphrase: RwLock<Option<String>>,
+
+    statement_cache: Mutex<StatementCacheTracker>,
 }
 
 impl Default for Sql {
     fn default() -> Self {
         Self {
             pool: RwLock::new(None),
+            passphrase: RwLock::new(None),
+            statement_cache: Default::default(),
         }
     }
 }
@@ -67,6 +131,52 @@ impl Sql {
         Self::default()
     }
 
+    /// Creates a database handle that opens the database with SQLCipher,
+    /// using `passphrase` to derive the encryption key.
+    pub fn with_passphrase(passphrase: Option<String>) -> Sql {
+        Self {
+            passphrase: RwLock::new(passphrase),
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if the database is encrypted.
+    pub async fn is_encrypted(&self) -> bool {
+        self.passphrase.read().await.is_some()
+    }
+
+    pub(crate) async fn passphrase(&self) -> Option<String> {
+        self.passphrase.read().await.clone()
+    }
+
+    /// Re-encrypts the database with a new passphrase, `None` decrypts it.
+    ///
+    /// The database is closed, re-keyed and opened again, so no other
+    /// connections may be in use.  On errors, the database is reopened
+    /// with the old passphrase.
+    pub(crate) async fn change_passphrase(
+        &self,
+        context: &Context,
+        dbfile: impl AsRef<Path>,
+        passphrase: Option<String>,
+    ) -> crate::error::Result<()> {
+        let dbfile = dbfile.as_ref();
+        let old_passphrase = self.passphrase().await;
+        if old_passphrase == passphrase {
+            return Ok(());
+        }
+
+        self.close().await;
+        let res = rekey(dbfile, old_passphrase.as_deref(), passphrase.as_deref());
+        if res.is_ok() {
+            *self.passphrase.write().await = passphrase;
+        }
+        if !self.open(context, dbfile, false).await {
+            return Err(Error::SqlFailedToOpen.into());
+        }
+        res.map_err(Into::into)
+    }
+
     pub async fn is_open(&self) -> bool {
         self.pool.read().await.is_some()
     }
@@ -74,6 +184,23 @@ impl Sql {
     pub async fn close(&self) {
         let _ = self.pool.write().await.take();
         // drop closes the connection
+        self.statement_cache.lock().unwrap().connections.clear();
+    }
+
+    /// Returns how often the prepared statements of [Sql::execute],
+    /// [Sql::query_row], [Sql::query_map] and [Sql::exists] were reused.
+    pub fn statement_cache_stats(&self) -> StatementCacheStats {
+        self.statement_cache.lock().unwrap().stats
+    }
+
+    /// Prepares `sql` using the statement cache of the connection.
+    fn prepare_cached<'a>(
+        &self,
+        conn: &'a Connection,
+        sql: &str,
+    ) -> rusqlite::Result<rusqlite::CachedStatement<'a>> {
+        self.statement_cache.lock().unwrap().record(conn, sql);
+        conn.prepare_cached(sql)
     }
 
     // return true on success, false on failure
@@ -97,7 +224,8 @@ impl Sql {
     ) -> Result<usize> {
         let res = {
             let conn = self.get_conn().await?;
-            conn.execute(sql.as_ref(), params)
+            let mut stmt = self.prepare_cached(&conn, sql.as_ref())?;
+            stmt.execute(params)
         };
 
         res.map_err(Into::into)
@@ -120,7 +248,7 @@ impl Sql {
         let sql = sql.as_ref();
 
         let conn = self.get_conn().await?;
-        let mut stmt = conn.prepare(sql)?;
+        let mut stmt = self.prepare_cached(&conn, sql)?;
         let res = stmt.query_map(&params, f)?;
         g(res)
     }
@@ -166,7 +294,7 @@ impl Sql {
     pub async fn exists(&self, sql: &str, params: Vec<&dyn crate::ToSql>) -> Result<bool> {
         let res = {
             let conn = self.get_conn().await?;
-            let mut stmt = conn.prepare(sql)?;
+            let mut stmt = self.prepare_cached(&conn, sql)?;
             stmt.exists(&params)
         };
 
@@ -186,7 +314,8 @@ impl Sql {
         let sql = sql.as_ref();
         let res = {
             let conn = self.get_conn().await?;
-            conn.query_row(sql, params, f)
+            let mut stmt = self.prepare_cached(&conn, sql)?;
+            stmt.query_row(params, f)
         };
 
         res.map_err(Into::into)
@@ -451,11 +580,108 @@ pub fn get_rowid2(
     )
 }
 
-pub async fn housekeeping(context: &Context) {
+/// Runs the given housekeeping steps.
+///
+/// Emits [Event::HousekeepingProgress] events while running.
+pub async fn housekeeping(
+    context: &Context,
+    options: HousekeepingOptions,
+) -> crate::error::Result<HousekeepingReport> {
+    info!(context, "Start housekeeping...");
+    let mut report = HousekeepingReport::default();
+    let res = run_housekeeping_steps(context, options, &mut report).await;
+    context.emit_event(Event::HousekeepingProgress(if res.is_ok() {
+        1000
+    } else {
+        0
+    }));
+    res?;
+
+    info!(context, "Housekeeping done: {:?}", report);
+    Ok(report)
+}
+
+async fn run_housekeeping_steps(
+    context: &Context,
+    options: HousekeepingOptions,
+    report: &mut HousekeepingReport,
+) -> crate::error::Result<()> {
+    if options.remove_unused_blobs {
+        remove_unused_blobs(context, report).await?;
+        blob_crypt::prune_decrypted(context).await;
+    }
+    context.emit_event(Event::HousekeepingProgress(600));
+
+    if options.remove_orphaned_mdns {
+        report.removed_rows += context
+            .sql
+            .execute(
+                "DELETE FROM msgs_mdns WHERE msg_id NOT IN (SELECT id FROM msgs);",
+                paramsv![],
+            )
+            .await?;
+        report.removed_rows += context
+            .sql
+            .execute(
+                "DELETE FROM msg_recipient_status WHERE msg_id NOT IN (SELECT id FROM msgs);",
+                paramsv![],
+            )
+            .await?;
+    }
+    context.emit_event(Event::HousekeepingProgress(700));
+
+    if options.prune_tombstones {
+        report.removed_rows += prune_tombstones(context).await?;
+    }
+
+    report.repaired_counters = repair_unread_counts(&context.sql).await?;
+    if report.repaired_counters > 0 {
+        warn!(
+            context,
+            "Repaired unread counters of {} chats", report.repaired_counters
+        );
+    }
+    context.emit_event(Event::HousekeepingProgress(800));
+
+    if options.vacuum {
+        let size_before = dbfile_size(context).await;
+        context.sql.execute("VACUUM;", paramsv![]).await?;
+        report.freed_bytes += size_before.saturating_sub(dbfile_size(context).await);
+    }
+    Ok(())
+}
+
+/// Recalculates the unread counters of chats that do not match the messages.
+///
+/// The counters are maintained by triggers, so this should only find
+/// differences if the database was modified by other means.
+/// Returns the number of repaired chats.
+async fn repair_unread_counts(sql: &Sql) -> Result<usize> {
+    sql.execute(
+        "UPDATE chats
+            SET unread_count=(SELECT COUNT(*) FROM msgs m
+                               WHERE m.chat_id=chats.id AND m.state=10 AND m.hidden=0)
+          WHERE unread_count!=(SELECT COUNT(*) FROM msgs m
+                                WHERE m.chat_id=chats.id AND m.state=10 AND m.hidden=0);",
+        paramsv![],
+    )
+    .await
+}
+
+async fn dbfile_size(context: &Context) -> u64 {
+    async_std::fs::metadata(context.get_dbfile())
+        .await
+        .map(|meta| meta.len())
+        .unwrap_or_default()
+}
+
+async fn remove_unused_blobs(
+    context: &Context,
+    report: &mut HousekeepingReport,
+) -> crate::error::Result<()> {
     let mut files_in_use = HashSet::new();
     let mut unreferenced_count = 0;
 
-    info!(context, "Start housekeeping...");
     maybe_add_from_param(
         context,
         &mut files_in_use,
@@ -463,6 +689,13 @@ pub async fn housekeeping(context: &Context) {
         Param::File,
     )
     .await;
+    maybe_add_from_param(
+        context,
+        &mut files_in_use,
+        "SELECT param FROM msgs  WHERE chat_id!=3   AND type!=10;",
+        Param::Thumbnail,
+    )
+    .await;
     maybe_add_from_param(
         context,
         &mut files_in_use,
@@ -498,12 +731,11 @@ pub async fn housekeeping(context: &Context) {
                 Ok(())
             },
         )
-        .await
-        .unwrap_or_else(|err| {
-            warn!(context, "sql: failed query: {}", err);
-        });
+        .await?;
 
     info!(context, "{} files in use.", files_in_use.len(),);
+    context.emit_event(Event::HousekeepingProgress(100));
+
     /* go through directory and delete unused files */
     let p = context.get_blobdir();
     match async_std::fs::read_dir(p).await {
@@ -520,7 +752,8 @@ pub async fn housekeeping(context: &Context) {
                 let name_f = entry.file_name();
                 let name_s = name_f.to_string_lossy();
 
-                if is_file_in_use(&files_in_use, None, &name_s)
+                if name_s == blob_crypt::DECRYPTED_DIR
+                    || is_file_in_use(&files_in_use, None, &name_s)
                     || is_file_in_use(&files_in_use, Some(".increation"), &name_s)
                     || is_file_in_use(&files_in_use, Some(".waveform"), &name_s)
                     || is_file_in_use(&files_in_use, Some("-preview.jpg"), &name_s)
@@ -530,7 +763,8 @@ pub async fn housekeeping(context: &Context) {
 
                 unreferenced_count += 1;
 
-                if let Ok(stats) = async_std::fs::metadata(entry.path()).await {
+                let stats = async_std::fs::metadata(entry.path()).await.ok();
+                if let Some(ref stats) = stats {
                     let recently_created =
                         stats.created().is_ok() && stats.created().unwrap() > keep_files_newer_than;
                     let recently_modified = stats.modified().is_ok()
@@ -555,7 +789,10 @@ pub async fn housekeeping(context: &Context) {
                     entry.file_name()
                 );
                 let path = entry.path();
-                dc_delete_file(context, path).await;
+                if dc_delete_file(context, path).await {
+                    report.removed_blobs += 1;
+                    report.freed_bytes += stats.map(|stats| stats.len()).unwrap_or_default();
+                }
             }
         }
         Err(err) => {
@@ -567,15 +804,7 @@ pub async fn housekeeping(context: &Context) {
             );
         }
     }
-
-    if let Err(err) = prune_tombstones(context).await {
-        warn!(
-            context,
-            "Houskeeping: Cannot prune message tombstones: {}", err
-        );
-    }
-
-    info!(context, "Housekeeping done.",);
+    Ok(())
 }
 
 fn is_file_in_use(files_in_use: &HashSet<String>, namespc_opt: Option<&str>, name: &str) -> bool {
@@ -655,18 +884,25 @@ async fn open(
     // this actually creates min_idle database handles just now.
     // therefore, with_init() must not try to modify the database as otherwise
     // we easily get busy-errors (eg. table-creation, journal_mode etc. should be done on only one handle)
+    let passphrase = sql.passphrase().await;
+    let key = passphrase.clone();
     let mgr = r2d2_sqlite::SqliteConnectionManager::file(dbfile.as_ref())
         .with_flags(open_flags)
-        .with_init(|c| {
+        .with_init(move |c| {
+            // the key has to be set before anything else is done on the connection
+            if let Some(ref key) = key {
+                c.pragma_update(None, "key", key)?;
+            }
             c.execute_batch(&format!(
                 "PRAGMA secure_delete=on; PRAGMA busy_timeout = {};",
                 Duration::from_secs(10).as_millis()
             ))?;
+            c.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
             Ok(())
         });
     let pool = r2d2::Pool::builder()
         .min_idle(Some(2))
-        .max_size(10)
+        .max_size(MAX_CONNECTIONS)
         .connection_timeout(Duration::from_secs(60))
         .build(mgr)
         .map_err(Error::ConnectionPool)?;
@@ -675,6 +911,10 @@ async fn open(
         *sql.pool.write().await = Some(pool);
     }
 
+    if passphrase.is_some() {
+        sql.with_conn(|conn| check_key(&conn)).await?;
+    }
+
     if !readonly {
         // journal_mode is persisted, it is sufficient to change it only for one handle.
         // (nb: execute() always returns errors for this PRAGMA call, just discard it.
@@ -1241,6 +1481,500 @@ async fn open(
                 .await?;
             sql.set_raw_config_int(context, "dbversion", 63).await?;
         }
+        if dbversion < 64 {
+            info!(context, "[migration] v64");
+            sql.execute(
+                "CREATE TABLE reactions (
+                   msg_id INTEGER NOT NULL,
+                   contact_id INTEGER NOT NULL,
+                   reaction TEXT NOT NULL,
+                   timestamp INTEGER DEFAULT 0,
+                   UNIQUE(msg_id, contact_id));",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE INDEX reactions_index1 ON reactions (msg_id);",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 64).await?;
+        }
+        if dbversion < 65 {
+            info!(context, "[migration] v65");
+            // full-text index over msgs.txt, kept in sync by triggers;
+            // "rebuild" backfills the index from existing messages.
+            sql.execute(
+                "CREATE VIRTUAL TABLE msgs_search USING fts5(txt, content='msgs', content_rowid='id');",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE TRIGGER msgs_search_insert AFTER INSERT ON msgs BEGIN
+                   INSERT INTO msgs_search (rowid, txt) VALUES (new.id, new.txt);
+                 END;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE TRIGGER msgs_search_delete AFTER DELETE ON msgs BEGIN
+                   INSERT INTO msgs_search (msgs_search, rowid, txt) VALUES ('delete', old.id, old.txt);
+                 END;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE TRIGGER msgs_search_update AFTER UPDATE OF txt ON msgs BEGIN
+                   INSERT INTO msgs_search (msgs_search, rowid, txt) VALUES ('delete', old.id, old.txt);
+                   INSERT INTO msgs_search (rowid, txt) VALUES (new.id, new.txt);
+                 END;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "INSERT INTO msgs_search (msgs_search) VALUES ('rebuild');",
+                paramsv![],
+            )
+            .await?;
+            // ephemeral messages, see the `ephemeral` module
+            sql.execute(
+                "ALTER TABLE chats ADD COLUMN ephemeral_timer INTEGER DEFAULT 0;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "ALTER TABLE msgs ADD COLUMN ephemeral_timer INTEGER DEFAULT 0;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "ALTER TABLE msgs ADD COLUMN ephemeral_timestamp INTEGER DEFAULT 0;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE INDEX msgs_index7 ON msgs (ephemeral_timestamp);",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 65).await?;
+        }
+        if dbversion < 66 {
+            info!(context, "[migration] v66");
+            sql.execute(
+                "CREATE TABLE app_updates (
+                   id INTEGER PRIMARY KEY AUTOINCREMENT,
+                   msg_id INTEGER NOT NULL,
+                   contact_id INTEGER NOT NULL,
+                   payload TEXT NOT NULL);",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE INDEX app_updates_index1 ON app_updates (msg_id);",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 66).await?;
+        }
+        if dbversion < 67 {
+            info!(context, "[migration] v67");
+            sql.execute(
+                "CREATE TABLE poll_votes (
+                   msg_id INTEGER NOT NULL,
+                   contact_id INTEGER NOT NULL,
+                   option INTEGER NOT NULL,
+                   timestamp INTEGER DEFAULT 0,
+                   UNIQUE(msg_id, contact_id));",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 67).await?;
+        }
+        if dbversion < 68 {
+            info!(context, "[migration] v68");
+            sql.execute(
+                "ALTER TABLE chats_contacts ADD COLUMN is_admin INTEGER DEFAULT 0;",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 68).await?;
+        }
+        if dbversion < 69 {
+            info!(context, "[migration] v69");
+            sql.execute(
+                "ALTER TABLE msgs ADD COLUMN download_state INTEGER DEFAULT 0;",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 69).await?;
+        }
+        if dbversion < 70 {
+            info!(context, "[migration] v70");
+            sql.execute(
+                "CREATE TABLE imap_sync (
+                   folder TEXT PRIMARY KEY,
+                   uid_validity INTEGER DEFAULT 0,
+                   modseq INTEGER DEFAULT 0);",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 70).await?;
+        }
+        if dbversion < 71 {
+            info!(context, "[migration] v71");
+            sql.execute(
+                "CREATE TABLE backup_manifest (
+                   file_name TEXT PRIMARY KEY,
+                   hash TEXT NOT NULL);",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 71).await?;
+        }
+        if dbversion < 72 {
+            info!(context, "[migration] v72");
+            sql.execute(
+                "ALTER TABLE msgs ADD COLUMN mime_html TEXT DEFAULT '';",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 72).await?;
+        }
+        if dbversion < 73 {
+            info!(context, "[migration] v73");
+            sql.execute(
+                "CREATE TABLE msg_recipient_status (
+                   msg_id INTEGER NOT NULL,
+                   contact_id INTEGER NOT NULL,
+                   state INTEGER NOT NULL,
+                   timestamp INTEGER DEFAULT 0,
+                   error TEXT DEFAULT '',
+                   UNIQUE(msg_id, contact_id));",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 73).await?;
+        }
+        if dbversion < 74 {
+            info!(context, "[migration] v74");
+            sql.execute(
+                "ALTER TABLE contacts ADD COLUMN last_seen INTEGER DEFAULT 0;",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 74).await?;
+        }
+        if dbversion < 75 {
+            info!(context, "[migration] v75");
+            sql.execute(
+                "CREATE TABLE acpeerstate_verifications (
+                   id INTEGER PRIMARY KEY AUTOINCREMENT,
+                   addr TEXT NOT NULL,
+                   event INTEGER NOT NULL,
+                   fingerprint TEXT NOT NULL,
+                   verifier TEXT,
+                   timestamp INTEGER DEFAULT 0);",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE INDEX acpeerstate_verifications_index1 ON acpeerstate_verifications (addr);",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 75).await?;
+        }
+        if dbversion < 76 {
+            info!(context, "[migration] v76");
+            sql.execute(
+                "CREATE TABLE acpeerstate_key_sources (
+                   id INTEGER PRIMARY KEY AUTOINCREMENT,
+                   addr TEXT NOT NULL,
+                   source INTEGER NOT NULL,
+                   fingerprint TEXT NOT NULL,
+                   url TEXT NOT NULL DEFAULT '',
+                   timestamp INTEGER DEFAULT 0);",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE INDEX acpeerstate_key_sources_index1 ON acpeerstate_key_sources (addr);",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 76).await?;
+        }
+        if dbversion < 77 {
+            info!(context, "[migration] v77");
+            sql.execute(
+                "CREATE TABLE smime_certificates (
+                   id INTEGER PRIMARY KEY AUTOINCREMENT,
+                   addr TEXT NOT NULL,
+                   certificate BLOB NOT NULL,
+                   not_after INTEGER DEFAULT 0,
+                   timestamp INTEGER DEFAULT 0);",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE INDEX smime_certificates_index1 ON smime_certificates (addr);",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 77).await?;
+        }
+        if dbversion < 78 {
+            info!(context, "[migration] v78");
+            // priority lanes of jobs, see job::Priority
+            sql.execute(
+                "ALTER TABLE jobs ADD COLUMN priority INTEGER DEFAULT 1;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "UPDATE jobs SET priority=2 WHERE action IN (130, 250, 5901);",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "UPDATE jobs SET priority=0 WHERE action IN (105, 107);",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 78).await?;
+        }
+        if dbversion < 79 {
+            info!(context, "[migration] v79");
+            // chatlist summaries, see chatlist::Chatlist::try_load();
+            // the rows of a chat are removed by triggers when its messages or the sender
+            // of its last message change and rebuilt when the chatlist is loaded.
+            // `sort_timestamp` is the timestamp of the last message used to sort the chatlist,
+            // NULL for chats without messages.
+            sql.execute(
+                "CREATE TABLE chat_summaries (
+                   chat_id INTEGER PRIMARY KEY,
+                   msg_id INTEGER DEFAULT 0,
+                   from_id INTEGER DEFAULT 0,
+                   text1 TEXT,
+                   text1_meaning INTEGER DEFAULT 0,
+                   text2 TEXT,
+                   timestamp INTEGER DEFAULT 0,
+                   state INTEGER DEFAULT 0,
+                   sort_timestamp INTEGER);",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE INDEX chat_summaries_index1 ON chat_summaries (sort_timestamp);",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE INDEX chat_summaries_index2 ON chat_summaries (from_id);",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE TRIGGER chat_summaries_msgs_insert AFTER INSERT ON msgs BEGIN
+                   DELETE FROM chat_summaries WHERE chat_id=new.chat_id;
+                 END;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE TRIGGER chat_summaries_msgs_update
+                 AFTER UPDATE OF chat_id, from_id, type, state, hidden, timestamp, txt, param ON msgs
+                 BEGIN
+                   DELETE FROM chat_summaries WHERE chat_id IN (old.chat_id, new.chat_id);
+                 END;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE TRIGGER chat_summaries_msgs_delete AFTER DELETE ON msgs BEGIN
+                   DELETE FROM chat_summaries WHERE chat_id=old.chat_id;
+                 END;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE TRIGGER chat_summaries_contacts_update
+                 AFTER UPDATE OF name, authname ON contacts
+                 BEGIN
+                   DELETE FROM chat_summaries WHERE from_id=old.id;
+                 END;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE TRIGGER chat_summaries_chats_update AFTER UPDATE OF type ON chats BEGIN
+                   DELETE FROM chat_summaries WHERE chat_id=old.id;
+                 END;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE TRIGGER chat_summaries_chats_delete AFTER DELETE ON chats BEGIN
+                   DELETE FROM chat_summaries WHERE chat_id=old.id;
+                 END;",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 79).await?;
+        }
+        if dbversion < 80 {
+            info!(context, "[migration] v80");
+            // number of fresh messages per chat, kept up to date by triggers,
+            // see ChatId::get_fresh_msg_cnt()
+            sql.execute(
+                "ALTER TABLE chats ADD COLUMN unread_count INTEGER DEFAULT 0;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE TRIGGER chats_unread_count_insert AFTER INSERT ON msgs
+                 WHEN new.state=10 AND new.hidden=0
+                 BEGIN
+                   UPDATE chats SET unread_count=unread_count+1 WHERE id=new.chat_id;
+                 END;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE TRIGGER chats_unread_count_delete AFTER DELETE ON msgs
+                 WHEN old.state=10 AND old.hidden=0
+                 BEGIN
+                   UPDATE chats SET unread_count=unread_count-1 WHERE id=old.chat_id;
+                 END;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE TRIGGER chats_unread_count_update AFTER UPDATE OF chat_id, state, hidden ON msgs
+                 WHEN (old.state=10 AND old.hidden=0) OR (new.state=10 AND new.hidden=0)
+                 BEGIN
+                   UPDATE chats SET unread_count=unread_count-1
+                    WHERE id=old.chat_id AND old.state=10 AND old.hidden=0;
+                   UPDATE chats SET unread_count=unread_count+1
+                    WHERE id=new.chat_id AND new.state=10 AND new.hidden=0;
+                 END;",
+                paramsv![],
+            )
+            .await?;
+            repair_unread_counts(sql).await?;
+            sql.set_raw_config_int(context, "dbversion", 80).await?;
+        }
+        if dbversion < 81 {
+            info!(context, "[migration] v81");
+            // named points of interest, see Message::set_poi()
+            sql.execute("ALTER TABLE locations ADD COLUMN label TEXT;", paramsv![])
+                .await?;
+            sql.execute("ALTER TABLE locations ADD COLUMN radius REAL;", paramsv![])
+                .await?;
+            sql.execute(
+                "ALTER TABLE locations ADD COLUMN color INTEGER;",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 81).await?;
+        }
+        if dbversion < 82 {
+            info!(context, "[migration] v82");
+            // spatial index for map viewports, see location::get_clusters()
+            sql.execute(
+                "CREATE VIRTUAL TABLE locations_rtree USING rtree(id, min_lat, max_lat, min_lon, max_lon);",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "INSERT INTO locations_rtree
+                 SELECT id, latitude, latitude, longitude, longitude FROM locations;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE TRIGGER locations_rtree_insert AFTER INSERT ON locations
+                 BEGIN
+                   INSERT INTO locations_rtree
+                   VALUES (new.id, new.latitude, new.latitude, new.longitude, new.longitude);
+                 END;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE TRIGGER locations_rtree_delete AFTER DELETE ON locations
+                 BEGIN
+                   DELETE FROM locations_rtree WHERE id=old.id;
+                 END;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "CREATE TRIGGER locations_rtree_update AFTER UPDATE OF latitude, longitude ON locations
+                 BEGIN
+                   UPDATE locations_rtree
+                      SET min_lat=new.latitude, max_lat=new.latitude,
+                          min_lon=new.longitude, max_lon=new.longitude
+                    WHERE id=new.id;
+                 END;",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 82).await?;
+        }
+        if dbversion < 83 {
+            info!(context, "[migration] v83");
+            // last change of the name and of the blocked state by the user,
+            // used to resolve conflicts when syncing contacts, see the sync module
+            sql.execute(
+                "ALTER TABLE contacts ADD COLUMN name_timestamp INTEGER DEFAULT 0;",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "ALTER TABLE contacts ADD COLUMN blocked_timestamp INTEGER DEFAULT 0;",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 83).await?;
+        }
+        if dbversion < 84 {
+            info!(context, "[migration] v84");
+            // the status is taken from the footer of received messages
+            sql.execute(
+                "ALTER TABLE contacts ADD COLUMN status TEXT DEFAULT '';",
+                paramsv![],
+            )
+            .await?;
+            sql.execute(
+                "ALTER TABLE contacts ADD COLUMN status_timestamp INTEGER DEFAULT 0;",
+                paramsv![],
+            )
+            .await?;
+            sql.set_raw_config_int(context, "dbversion", 84).await?;
+        }
+        if dbversion < 85 {
+            info!(context, "[migration] v85");
+            // mvbox_watch and mvbox_move are replaced by mvbox_policy,
+            // both defaulted to 1
+            let mvbox_watch = sql.get_raw_config_int(context, "mvbox_watch").await;
+            let mvbox_move = sql.get_raw_config_int(context, "mvbox_move").await;
+            let mvbox_policy = if mvbox_move.unwrap_or(1) != 0 {
+                MvboxPolicy::MoveChats
+            } else if mvbox_watch.unwrap_or(1) != 0 {
+                MvboxPolicy::Watch
+            } else {
+                MvboxPolicy::Off
+            };
+            if mvbox_policy != MvboxPolicy::default() {
+                sql.set_raw_config_int(context, "mvbox_policy", mvbox_policy as i32)
+                    .await?;
+            }
+            sql.set_raw_config(context, "mvbox_watch", None).await?;
+            sql.set_raw_config(context, "mvbox_move", None).await?;
+            sql.set_raw_config_int(context, "dbversion", 85).await?;
+        }
 
         // (2) updates that require high-level objects
         // (the structure is complete now and all objects are usable)
@@ -1278,9 +2012,67 @@ async fn open(
     Ok(())
 }
 
+/// Checks that the database is readable with the key set on the connection.
+fn check_key(conn: &Connection) -> Result<()> {
+    // a plain SQLite silently ignores the key, do not pretend to encrypt then
+    conn.query_row("PRAGMA cipher_version;", NO_PARAMS, |row| {
+        row.get::<_, String>(0)
+    })
+    .map_err(|_| Error::EncryptionUnsupported)?;
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master;", NO_PARAMS, |row| {
+        row.get::<_, i64>(0)
+    })
+    .map_err(|_| Error::WrongPassphrase)?;
+    Ok(())
+}
+
+/// Changes the key of a closed database from `old` to `new`,
+/// `None` meaning an unencrypted database.
+fn rekey(dbfile: &Path, old: Option<&str>, new: Option<&str>) -> Result<()> {
+    let conn = Connection::open(dbfile)?;
+    if let Some(old) = old {
+        conn.pragma_update(None, "key", &old)?;
+    }
+    check_key(&conn)?;
+    // write the content of the write-ahead log into the database file,
+    // the log and shared memory files do not belong to the re-keyed
+    // file and are removed before it replaces the database
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE);", NO_PARAMS, |_| Ok(()))?;
+
+    // `PRAGMA rekey` can neither encrypt nor decrypt a database and does
+    // not work in WAL mode, so the content is exported to a new file
+    // replacing the old one.
+    let mut tmp_name = dbfile.as_os_str().to_owned();
+    tmp_name.push("-rekey");
+    let tmp_file = PathBuf::from(tmp_name);
+    if tmp_file.exists() {
+        std::fs::remove_file(&tmp_file)?;
+    }
+    conn.execute(
+        "ATTACH DATABASE ? AS rekeyed KEY ?;",
+        paramsv![
+            tmp_file.to_string_lossy().to_string(),
+            new.unwrap_or_default()
+        ],
+    )?;
+    conn.query_row("SELECT sqlcipher_export('rekeyed');", NO_PARAMS, |_| Ok(()))?;
+    conn.execute("DETACH DATABASE rekeyed;", NO_PARAMS)?;
+    drop(conn);
+    for suffix in &["-wal", "-shm"] {
+        let mut name = dbfile.as_os_str().to_owned();
+        name.push(suffix);
+        let file = PathBuf::from(name);
+        if file.exists() {
+            std::fs::remove_file(&file)?;
+        }
+    }
+    std::fs::rename(&tmp_file, dbfile)?;
+    Ok(())
+}
+
 /// Removes from the database locally deleted messages that also don't
 /// have a server UID.
-async fn prune_tombstones(context: &Context) -> Result<()> {
+async fn prune_tombstones(context: &Context) -> Result<usize> {
     context
         .sql
         .execute(
@@ -1289,8 +2081,7 @@ async fn prune_tombstones(context: &Context) -> Result<()> {
          AND server_uid = 0",
             paramsv![DC_CHAT_ID_TRASH],
         )
-        .await?;
-    Ok(())
+        .await
 }
 
 #[cfg(test)]
@@ -1320,4 +2111,41 @@ mod test {
         assert!(!is_file_in_use(&files, Some(".txt"), "hello"));
         assert!(is_file_in_use(&files, Some("-suffix"), "world.txt-suffix"));
     }
+
+    #[test]
+    fn test_statement_cache_tracker() {
+        let conn = Connection::open_in_memory().unwrap();
+        let other_conn = Connection::open_in_memory().unwrap();
+        let mut tracker = StatementCacheTracker::default();
+
+        tracker.record(&conn, "SELECT 1");
+        tracker.record(&conn, "SELECT 1");
+        tracker.record(&other_conn, "SELECT 1");
+        assert_eq!(tracker.stats, StatementCacheStats { hits: 1, misses: 2 });
+
+        // the least recently used statement is evicted
+        for i in 0..STATEMENT_CACHE_CAPACITY {
+            tracker.record(&conn, &format!("SELECT {}", i + 2));
+        }
+        tracker.record(&conn, "SELECT 1");
+        assert_eq!(tracker.stats.hits, 1);
+        tracker.record(&conn, &format!("SELECT {}", STATEMENT_CACHE_CAPACITY + 1));
+        assert_eq!(tracker.stats.hits, 2);
+    }
+
+    #[async_std::test]
+    async fn test_statement_cache_stats() {
+        let t = crate::test_utils::dummy_context().await;
+        let before = t.ctx.sql.statement_cache_stats();
+        for _ in 0..3 {
+            t.ctx
+                .sql
+                .query_get_value_result::<i64>("SELECT COUNT(*) FROM msgs;", paramsv![])
+                .await
+                .unwrap();
+        }
+        let after = t.ctx.sql.statement_cache_stats();
+        assert_eq!(after.hits + after.misses, before.hits + before.misses + 3);
+        assert!(after.hits > before.hits);
+    }
 }
@@ -7,6 +7,7 @@ use strum_macros::EnumProperty;
 
 use crate::blob::BlobObject;
 use crate::chat;
+use crate::chatlist;
 use crate::constants::{Viewtype, DC_CONTACT_ID_SELF};
 use crate::contact::*;
 use crate::context::Context;
@@ -182,6 +183,42 @@ pub enum StockMessage {
 
     #[strum(props(fallback = "Message from %1$s"))]
     SubjectForNewContact = 73,
+
+    #[strum(props(fallback = "Message deletion timer is disabled."))]
+    MsgEphemeralTimerDisabled = 74,
+
+    #[strum(props(fallback = "Message deletion timer is set to %1$s s."))]
+    MsgEphemeralTimerEnabled = 75,
+
+    #[strum(props(fallback = "Poll"))]
+    Poll = 76,
+
+    #[strum(props(fallback = "Broadcast List"))]
+    BroadcastList = 77,
+
+    #[strum(props(fallback = "%1$s is now admin."))]
+    MsgAdminAdded = 78,
+
+    #[strum(props(fallback = "%1$s is no longer admin."))]
+    MsgAdminRemoved = 79,
+
+    #[strum(props(fallback = "%1$s message"))]
+    PartialDownloadMsgBody = 80,
+
+    #[strum(props(fallback = "Invitation"))]
+    Invitation = 81,
+
+    #[strum(props(fallback = "Accepted: %1$s"))]
+    InviteAccepted = 82,
+
+    #[strum(props(fallback = "Declined: %1$s"))]
+    InviteDeclined = 83,
+
+    #[strum(props(fallback = "Tentatively accepted: %1$s"))]
+    InviteTentative = 84,
+
+    #[strum(props(fallback = "Message from %1$s was found in the spam folder."))]
+    MsgRescuedFromSpam = 85,
 }
 
 /*
@@ -219,10 +256,17 @@ impl Context {
                 id.fallback()
             );
         }
+        let changed = self.stock_str(id).await != stockstring;
         self.translated_stockstrings
             .write()
             .await
             .insert(id as usize, stockstring);
+        if changed {
+            // cached chatlist summaries may contain the old string
+            if let Err(err) = chatlist::clear_summaries(self).await {
+                warn!(self, "Cannot clear chatlist summaries: {}", err);
+            }
+        }
         Ok(())
     }
 
@@ -288,8 +332,9 @@ impl Context {
 
     /// Return some kind of stock message
     ///
-    /// If the `id` is [StockMessage::MsgAddMember] or
-    /// [StockMessage::MsgDelMember] then `param1` is considered to be the
+    /// If the `id` is [StockMessage::MsgAddMember],
+    /// [StockMessage::MsgDelMember], [StockMessage::MsgAdminAdded] or
+    /// [StockMessage::MsgAdminRemoved] then `param1` is considered to be the
     /// contact address and will be replaced by that contact's display
     /// name.
     ///
@@ -310,7 +355,11 @@ impl Context {
         param2: impl AsRef<str>,
         from_id: u32,
     ) -> String {
-        let insert1 = if id == StockMessage::MsgAddMember || id == StockMessage::MsgDelMember {
+        let insert1 = if id == StockMessage::MsgAddMember
+            || id == StockMessage::MsgDelMember
+            || id == StockMessage::MsgAdminAdded
+            || id == StockMessage::MsgAdminRemoved
+        {
             let contact_id =
                 Contact::lookup_id_by_addr(self, param1.as_ref(), Origin::Unknown).await;
             if contact_id != 0 {
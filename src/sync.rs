@@ -0,0 +1,546 @@
+//! # Synchronization between own devices
+//!
+//! Actions changing the local state of chats, eg. marking messages as seen
+//! or archiving a chat, are sent to the other devices of the user as small
+//! messages to self.  The messages are end-to-end encrypted, carry the
+//! `Chat-Sync:` header and a JSON list of [SyncItem]s as body.
+//!
+//! Chats are referred to by their group ID or, for one-to-one chats, by the
+//! address of the contact; messages are referred to by their Message-ID.
+//! Received items are applied without sending new sync messages and
+//! applying an item twice has no further effect.  Sync messages are only
+//! sent if [Config::SyncMsgs] is enabled.
+//!
+//! Contacts added or renamed by the user and blocked or unblocked contacts
+//! are synced as well.  The name and the blocked state carry the time of
+//! their last change so that the newest change wins if a contact is edited
+//! on several devices.  After the key was transferred to a new device using
+//! an Autocrypt Setup Message, the new device requests the address book
+//! from the other devices.
+
+use serde::{Deserialize, Serialize};
+
+use crate::chat::{self, Chat, ChatId, ChatVisibility, MuteDuration};
+use crate::config::Config;
+use crate::constants::*;
+use crate::contact::{self, Contact, Origin};
+use crate::context::Context;
+use crate::error::{ensure, format_err, Result};
+use crate::events::Event;
+use crate::job;
+use crate::key::{DcKey, SignedPublicKey};
+use crate::message::{self, Message};
+use crate::mimeparser::{MimeMessage, SystemMessage};
+
+/// Refers to a chat independently of the local chat ID.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ChatRef {
+    /// A group, mailing list or broadcast list identified by its group ID.
+    Group(String),
+
+    /// A one-to-one chat identified by the address of the contact.
+    Contact(String),
+}
+
+/// An action to apply on the other devices.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub(crate) enum SyncItem {
+    /// Messages, given by their Message-ID, were marked as seen.
+    MarkSeen { msgs: Vec<String> },
+
+    /// A chat was archived, unarchived, pinned or unpinned.
+    Visibility {
+        chat: ChatRef,
+        visibility: ChatVisibility,
+    },
+
+    /// A chat was muted or unmuted.
+    Mute {
+        chat: ChatRef,
+        duration: MuteDuration,
+    },
+
+    /// A chat was deleted.
+    Delete { chat: ChatRef },
+
+    /// Contacts were added, renamed, blocked or unblocked,
+    /// also sent as answer to [SyncItem::RequestContacts].
+    Contacts { contacts: Vec<ContactItem> },
+
+    /// A new device asks for the address book.
+    RequestContacts,
+}
+
+/// A contact as known on the sending device.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ContactItem {
+    pub addr: String,
+
+    /// Name of the contact, empty to use the name given by the contact.
+    pub name: String,
+
+    /// Time the user changed the name, 0 if the name was never edited.
+    pub name_timestamp: i64,
+
+    pub blocked: bool,
+
+    /// Time the user blocked or unblocked the contact, 0 if never.
+    pub blocked_timestamp: i64,
+}
+
+/// Returns how the other devices can find the given chat.
+///
+/// Returns `None` for chats that only exist locally, eg. the device chat.
+pub(crate) async fn chat_ref(context: &Context, chat_id: ChatId) -> Result<Option<ChatRef>> {
+    let chat = Chat::load_from_db(context, chat_id).await?;
+    if chat.is_device_talk() {
+        return Ok(None);
+    }
+    if chat.typ == Chattype::Single {
+        let contact_id = match chat::get_chat_contacts(context, chat_id).await.first() {
+            Some(contact_id) => *contact_id,
+            None => return Ok(None),
+        };
+        let addr = if contact_id == DC_CONTACT_ID_SELF {
+            context
+                .get_config(Config::ConfiguredAddr)
+                .await
+                .unwrap_or_default()
+        } else {
+            Contact::load_from_db(context, contact_id)
+                .await?
+                .get_addr()
+                .to_string()
+        };
+        Ok(Some(ChatRef::Contact(addr)))
+    } else if !chat.grpid.is_empty() {
+        Ok(Some(ChatRef::Group(chat.grpid)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Looks up the local chat referred to by `chat_ref`.
+async fn lookup_chat(context: &Context, chat_ref: &ChatRef) -> Result<Option<ChatId>> {
+    match chat_ref {
+        ChatRef::Group(grpid) => match chat::get_chat_id_by_grpid(context, grpid).await {
+            Ok((chat_id, _, _)) => Ok(Some(chat_id)),
+            Err(crate::sql::Error::Sql(rusqlite::Error::QueryReturnedNoRows)) => Ok(None),
+            Err(err) => Err(err.into()),
+        },
+        ChatRef::Contact(addr) => {
+            let contact_id = Contact::lookup_id_by_addr(context, addr, Origin::Unknown).await;
+            if contact_id == 0 {
+                return Ok(None);
+            }
+            Ok(chat::lookup_by_contact_id(context, contact_id)
+                .await
+                .ok()
+                .map(|(chat_id, _)| chat_id))
+        }
+    }
+}
+
+/// Sends `items` to the other devices of the user.
+///
+/// Nothing is sent if [Config::SyncMsgs] is disabled or if the message
+/// cannot be end-to-end encrypted.  Returns true if a message was sent.
+pub(crate) async fn send_sync_items(context: &Context, items: Vec<SyncItem>) -> Result<bool> {
+    if items.is_empty() || !context.get_config_bool(Config::SyncMsgs).await {
+        return Ok(false);
+    }
+
+    // the saved-messages chat is created hidden if the user did not create it yet
+    let (chat_id, _) =
+        chat::create_or_lookup_by_contact_id(context, DC_CONTACT_ID_SELF, Blocked::Manually)
+            .await?;
+    let mut msg = Message::new(Viewtype::Text);
+    msg.chat_id = chat_id;
+    msg.text = Some(serde_json::to_string(&items)?);
+    msg.param.set_cmd(SystemMessage::MultiDeviceSync);
+    job::send_unstored_msg(context, &mut msg, None).await
+}
+
+/// Sends an action on the chat referred to by `chat_ref` to the other devices.
+///
+/// Errors are only logged as the action was already applied locally.
+pub(crate) async fn sync_chat(
+    context: &Context,
+    chat_ref: Option<ChatRef>,
+    item: impl FnOnce(ChatRef) -> SyncItem,
+) {
+    if let Some(chat_ref) = chat_ref {
+        if let Err(err) = send_sync_items(context, vec![item(chat_ref)]).await {
+            warn!(context, "Cannot send sync message: {}", err);
+        }
+    }
+}
+
+/// Loads the contacts to sync, all known or edited contacts if `contact_id` is `None`.
+async fn load_contact_items(
+    context: &Context,
+    contact_id: Option<u32>,
+) -> Result<Vec<ContactItem>> {
+    let items = context
+        .sql
+        .query_map(
+            "SELECT addr, name, name_timestamp, blocked, blocked_timestamp
+               FROM contacts
+              WHERE id>?
+                AND (? OR id=?)
+                AND (origin>=? OR blocked=1 OR name_timestamp>0 OR blocked_timestamp>0)
+              ORDER BY id;",
+            paramsv![
+                DC_CONTACT_ID_LAST_SPECIAL as i32,
+                contact_id.is_none(),
+                contact_id.unwrap_or_default() as i32,
+                Origin::IncomingReplyTo
+            ],
+            |row| {
+                Ok(ContactItem {
+                    addr: row.get(0)?,
+                    name: row.get(1)?,
+                    name_timestamp: row.get(2)?,
+                    blocked: row.get::<_, Option<bool>>(3)?.unwrap_or_default(),
+                    blocked_timestamp: row.get(4)?,
+                })
+            },
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    Ok(items)
+}
+
+/// Sends the name and the blocked state of a contact edited by the user
+/// to the other devices.
+///
+/// Errors are only logged as the change was already applied locally.
+pub(crate) async fn sync_contact(context: &Context, contact_id: u32) {
+    let res = match load_contact_items(context, Some(contact_id)).await {
+        Ok(contacts) if !contacts.is_empty() => {
+            send_sync_items(context, vec![SyncItem::Contacts { contacts }]).await
+        }
+        Ok(_) => Ok(false),
+        Err(err) => Err(err),
+    };
+    if let Err(err) = res {
+        warn!(context, "Cannot send sync message: {}", err);
+    }
+}
+
+/// Asks the other devices for the address book, done on a new device
+/// once it shares the key with the other devices.
+pub(crate) async fn request_contacts(context: &Context) {
+    if let Err(err) = send_sync_items(context, vec![SyncItem::RequestContacts]).await {
+        warn!(context, "Cannot request contacts: {}", err);
+    }
+}
+
+/// Applies a contact received from another device if it was changed later
+/// than the local contact.
+async fn apply_contact_item(context: &Context, item: &ContactItem) -> Result<()> {
+    let row = context
+        .sql
+        .query_row_optional(
+            "SELECT id, name_timestamp, blocked_timestamp FROM contacts
+              WHERE addr=? COLLATE NOCASE;",
+            paramsv![item.addr],
+            |row| {
+                Ok((
+                    row.get::<_, u32>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            },
+        )
+        .await?;
+    let (contact_id, blocked_timestamp) = match row {
+        Some((contact_id, name_timestamp, blocked_timestamp))
+            if item.name_timestamp <= name_timestamp =>
+        {
+            (contact_id, blocked_timestamp)
+        }
+        _ => {
+            let origin = if item.name_timestamp > 0 || row.is_some() {
+                Origin::ManuallyCreated
+            } else {
+                Origin::IncomingReplyTo
+            };
+            let (contact_id, _) =
+                Contact::add_or_lookup(context, &item.name, &item.addr, origin).await?;
+            if contact_id <= DC_CONTACT_ID_LAST_SPECIAL {
+                return Ok(());
+            }
+            context
+                .sql
+                .execute(
+                    "UPDATE contacts SET name_timestamp=? WHERE id=?;",
+                    paramsv![item.name_timestamp, contact_id as i32],
+                )
+                .await?;
+            (contact_id, row.map(|row| row.2).unwrap_or_default())
+        }
+    };
+    if item.blocked_timestamp > blocked_timestamp {
+        contact::set_block_contact(context, contact_id, item.blocked, false).await;
+        context
+            .sql
+            .execute(
+                "UPDATE contacts SET blocked_timestamp=? WHERE id=?;",
+                paramsv![item.blocked_timestamp, contact_id as i32],
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Applies the items of a sync message received from another own device.
+pub(crate) async fn receive_sync_items(
+    context: &Context,
+    mime_parser: &MimeMessage,
+    from_id: u32,
+) -> Result<()> {
+    ensure!(
+        from_id == DC_CONTACT_ID_SELF,
+        "Sync message from contact {}",
+        from_id
+    );
+    let self_fingerprint = SignedPublicKey::load_self(context).await?.fingerprint();
+    ensure!(
+        mime_parser.signatures.contains(&self_fingerprint),
+        "Sync message is not signed by own key"
+    );
+    let body = mime_parser
+        .parts
+        .first()
+        .map(|part| part.msg.as_str())
+        .unwrap_or_default();
+    let items: Vec<SyncItem> =
+        serde_json::from_str(body).map_err(|err| format_err!("Invalid sync items: {}", err))?;
+
+    for item in items {
+        if let Err(err) = apply_sync_item(context, item).await {
+            warn!(context, "Cannot apply sync item: {}", err);
+        }
+    }
+    Ok(())
+}
+
+async fn apply_sync_item(context: &Context, item: SyncItem) -> Result<()> {
+    match item {
+        SyncItem::MarkSeen { msgs } => {
+            let mut msg_ids = Vec::with_capacity(msgs.len());
+            for rfc724_mid in msgs {
+                if let Some((_, _, msg_id)) =
+                    message::rfc724_mid_exists(context, &rfc724_mid).await?
+                {
+                    msg_ids.push(msg_id);
+                }
+            }
+            message::markseen_msgs_ex(context, msg_ids, false).await;
+        }
+        SyncItem::Visibility { chat, visibility } => {
+            if let Some(chat_id) = lookup_chat(context, &chat).await? {
+                chat_id
+                    .set_visibility_ex(context, visibility, false)
+                    .await?;
+            }
+        }
+        SyncItem::Mute { chat, duration } => {
+            if let Some(chat_id) = lookup_chat(context, &chat).await? {
+                chat_id
+                    .set_mute_duration_ex(context, duration, false)
+                    .await?;
+            }
+        }
+        SyncItem::Delete { chat } => {
+            if let Some(chat_id) = lookup_chat(context, &chat).await? {
+                chat_id.delete_ex(context, false).await?;
+            }
+        }
+        SyncItem::Contacts { contacts } => {
+            for item in &contacts {
+                if let Err(err) = apply_contact_item(context, item).await {
+                    warn!(context, "Cannot apply contact {}: {}", item.addr, err);
+                }
+            }
+            context.emit_event(Event::ContactsChanged(None));
+        }
+        SyncItem::RequestContacts => {
+            let contacts = load_contact_items(context, None).await?;
+            if !contacts.is_empty() {
+                send_sync_items(context, vec![SyncItem::Contacts { contacts }]).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::chat::create_group_chat;
+    use crate::contact::VerifiedStatus;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_sync_items_serialization() {
+        let items = vec![
+            SyncItem::MarkSeen {
+                msgs: vec!["abc@example.org".to_string()],
+            },
+            SyncItem::Visibility {
+                chat: ChatRef::Group("grpid".to_string()),
+                visibility: ChatVisibility::Archived,
+            },
+            SyncItem::Mute {
+                chat: ChatRef::Contact("bob@example.net".to_string()),
+                duration: MuteDuration::Forever,
+            },
+            SyncItem::Delete {
+                chat: ChatRef::Group("grpid".to_string()),
+            },
+            SyncItem::Contacts {
+                contacts: vec![ContactItem {
+                    addr: "bob@example.net".to_string(),
+                    name: "Bob".to_string(),
+                    name_timestamp: 1000,
+                    blocked: true,
+                    blocked_timestamp: 2000,
+                }],
+            },
+            SyncItem::RequestContacts,
+        ];
+        let json = serde_json::to_string(&items).unwrap();
+        assert!(json.starts_with(r#"[{"action":"mark_seen","msgs":["abc@example.org"]}"#));
+        let parsed: Vec<SyncItem> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, items);
+    }
+
+    #[async_std::test]
+    async fn test_apply_sync_items() {
+        let t = test_context().await;
+        let chat_id = create_group_chat(&t.ctx, VerifiedStatus::Unverified, "foo")
+            .await
+            .unwrap();
+        let chat_ref = chat_ref(&t.ctx, chat_id).await.unwrap().unwrap();
+
+        apply_sync_item(
+            &t.ctx,
+            SyncItem::Visibility {
+                chat: chat_ref.clone(),
+                visibility: ChatVisibility::Archived,
+            },
+        )
+        .await
+        .unwrap();
+        let chat = Chat::load_from_db(&t.ctx, chat_id).await.unwrap();
+        assert_eq!(chat.get_visibility(), ChatVisibility::Archived);
+
+        apply_sync_item(
+            &t.ctx,
+            SyncItem::Mute {
+                chat: chat_ref.clone(),
+                duration: MuteDuration::Forever,
+            },
+        )
+        .await
+        .unwrap();
+        assert!(chat_id.is_muted(&t.ctx).await);
+
+        // applying an item twice is fine
+        for _ in 0..2 {
+            apply_sync_item(
+                &t.ctx,
+                SyncItem::Delete {
+                    chat: chat_ref.clone(),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        assert!(Chat::load_from_db(&t.ctx, chat_id).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn test_apply_contacts() {
+        let t = test_context().await;
+        let contact_id = Contact::create(&t.ctx, "Bob", "bob@example.net")
+            .await
+            .unwrap();
+        let items = load_contact_items(&t.ctx, Some(contact_id)).await.unwrap();
+        assert_eq!(items.len(), 1);
+        let name_timestamp = items[0].name_timestamp;
+        assert!(name_timestamp > 0);
+        assert!(!items[0].blocked);
+
+        // an older name is ignored, a newer blocked state is applied
+        let item = ContactItem {
+            addr: "BOB@example.net".to_string(),
+            name: "Bobby".to_string(),
+            name_timestamp: name_timestamp - 10,
+            blocked: true,
+            blocked_timestamp: name_timestamp + 10,
+        };
+        apply_sync_item(
+            &t.ctx,
+            SyncItem::Contacts {
+                contacts: vec![item.clone()],
+            },
+        )
+        .await
+        .unwrap();
+        let contact = Contact::load_from_db(&t.ctx, contact_id).await.unwrap();
+        assert_eq!(contact.get_name(), "Bob");
+        assert!(contact.is_blocked());
+
+        // a newer name is applied, an older unblock is ignored
+        let item = ContactItem {
+            name_timestamp: name_timestamp + 20,
+            blocked: false,
+            blocked_timestamp: name_timestamp,
+            ..item
+        };
+        apply_sync_item(
+            &t.ctx,
+            SyncItem::Contacts {
+                contacts: vec![item],
+            },
+        )
+        .await
+        .unwrap();
+        let contact = Contact::load_from_db(&t.ctx, contact_id).await.unwrap();
+        assert_eq!(contact.get_name(), "Bobby");
+        assert!(contact.is_blocked());
+
+        // unknown contacts are added
+        let item = ContactItem {
+            addr: "claire@example.org".to_string(),
+            name: "Claire".to_string(),
+            name_timestamp: 1000,
+            blocked: false,
+            blocked_timestamp: 0,
+        };
+        apply_sync_item(
+            &t.ctx,
+            SyncItem::Contacts {
+                contacts: vec![item],
+            },
+        )
+        .await
+        .unwrap();
+        let contact_id =
+            Contact::lookup_id_by_addr(&t.ctx, "claire@example.org", Origin::Unknown).await;
+        assert!(contact_id > DC_CONTACT_ID_LAST_SPECIAL);
+        let items = load_contact_items(&t.ctx, None).await.unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].name, "Claire");
+        assert_eq!(items[1].name_timestamp, 1000);
+    }
+}
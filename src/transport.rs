@@ -0,0 +1,170 @@
+//! # Mail transports
+//!
+//! IMAP implements [FetchTransport] and SMTP implements [SendTransport];
+//! both are driven by their own connection loops.  Alternative transports
+//! implement both traits and, if configured, replace fetching from the
+//! inbox as well as sending.  A configured transport is created once and
+//! cached in the context until its configuration changes.
+
+use async_trait::async_trait;
+
+use crate::context::Context;
+use crate::error::Result;
+
+/// Used as `server_folder` for messages received by a transport replacing IMAP.
+///
+/// These messages are not on any IMAP server, so no IMAP jobs are run for them.
+pub(crate) const JMAP_FOLDER: &str = "JMAP";
+
+/// A transport fetching raw MIME messages.
+#[async_trait]
+pub trait FetchTransport: Send {
+    /// Fetches new messages from the inbox and passes them to `dc_receive_imf`.
+    async fn fetch(&mut self, context: &Context) -> Result<()>;
+}
+
+/// A transport sending raw MIME messages.
+#[async_trait]
+pub trait SendTransport: Send {
+    /// Sends a raw MIME message to the given recipients.
+    async fn send(
+        &mut self,
+        context: &Context,
+        recipients: &[String],
+        message: &[u8],
+    ) -> Result<()>;
+}
+
+/// A transport fetching and sending raw MIME messages.
+pub trait Transport: FetchTransport + SendTransport + std::fmt::Debug {}
+
+impl<T: FetchTransport + SendTransport + std::fmt::Debug> Transport for T {}
+
+/// The configured transport replacing IMAP and SMTP, cached in the context.
+#[derive(Debug, Default)]
+pub(crate) struct TransportCache {
+    /// The configuration the transport was created for and the transport itself.
+    transport: Option<(String, Box<dyn Transport>)>,
+}
+
+impl TransportCache {
+    /// Returns the configured transport, creating it if the configuration changed.
+    async fn get(&mut self, context: &Context) -> Option<&mut Box<dyn Transport>> {
+        let url = configured_url(context).await;
+        let outdated = match (&self.transport, &url) {
+            (Some((cached_url, _)), Some(url)) => cached_url != url,
+            (None, None) => false,
+            _ => true,
+        };
+        if outdated {
+            self.transport = match url {
+                Some(url) => new_transport(context, url.clone())
+                    .await
+                    .map(|transport| (url, transport)),
+                None => None,
+            };
+        }
+        self.transport.as_mut().map(|(_, transport)| transport)
+    }
+}
+
+#[cfg(feature = "jmap")]
+async fn configured_url(context: &Context) -> Option<String> {
+    context.get_config(crate::config::Config::JmapUrl).await
+}
+
+#[cfg(not(feature = "jmap"))]
+async fn configured_url(_context: &Context) -> Option<String> {
+    None
+}
+
+/// Creates the transport for `url`, `None` if it is not compiled in.
+#[cfg(feature = "jmap")]
+async fn new_transport(context: &Context, url: String) -> Option<Box<dyn Transport>> {
+    Some(Box::new(crate::jmap::JmapClient::new(context, url).await))
+}
+
+#[cfg(not(feature = "jmap"))]
+async fn new_transport(_context: &Context, _url: String) -> Option<Box<dyn Transport>> {
+    None
+}
+
+/// Returns true if a transport replacing IMAP and SMTP is configured.
+pub(crate) async fn is_configured(context: &Context) -> bool {
+    configured_url(context).await.is_some()
+}
+
+/// Fetches using the configured transport.
+///
+/// Returns `None` if no transport replacing IMAP is configured.
+pub(crate) async fn fetch_configured(context: &Context) -> Option<Result<()>> {
+    let mut cache = context.transport.lock().await;
+    match cache.get(context).await {
+        Some(transport) => Some(transport.fetch(context).await),
+        None => None,
+    }
+}
+
+/// Sends using the configured transport.
+///
+/// Returns `None` if no transport replacing SMTP is configured.
+pub(crate) async fn send_configured(
+    context: &Context,
+    recipients: &[String],
+    message: &[u8],
+) -> Option<Result<()>> {
+    let mut cache = context.transport.lock().await;
+    match cache.get(context).await {
+        Some(transport) => Some(transport.send(context, recipients, message).await),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::dummy_context;
+
+    #[async_std::test]
+    async fn test_no_transport_configured() {
+        let t = dummy_context().await;
+        assert!(!is_configured(&t.ctx).await);
+        assert!(fetch_configured(&t.ctx).await.is_none());
+        assert!(
+            send_configured(&t.ctx, &["bob@example.org".to_string()], b"")
+                .await
+                .is_none()
+        );
+    }
+
+    #[cfg(feature = "jmap")]
+    #[async_std::test]
+    async fn test_transport_cached() {
+        let t = dummy_context().await;
+        t.ctx
+            .set_config(
+                crate::config::Config::JmapUrl,
+                Some("https://jmap.example.org"),
+            )
+            .await
+            .unwrap();
+        let mut cache = TransportCache::default();
+        let first = cache
+            .get(&t.ctx)
+            .await
+            .map(|t| &**t as *const dyn Transport as *const ());
+        let second = cache
+            .get(&t.ctx)
+            .await
+            .map(|t| &**t as *const dyn Transport as *const ());
+        assert!(first.is_some());
+        assert_eq!(first, second);
+
+        t.ctx
+            .set_config(crate::config::Config::JmapUrl, None)
+            .await
+            .unwrap();
+        assert!(cache.get(&t.ctx).await.is_none());
+    }
+}
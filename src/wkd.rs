@@ -0,0 +1,248 @@
+//! # Web Key Directory lookup
+//!
+//! If enabled by [Config::WkdLookup], keys of recipients without a known
+//! Autocrypt key are looked up in the [Web Key Directory] of their domain
+//! by a background job scheduled when a message is sent to them.  Found
+//! keys are stored as gossip keys without encryption preference in the
+//! peerstate, so they are trusted no more than gossiped keys.  Keys
+//! received by Autocrypt later on take precedence.
+//!
+//! [Web Key Directory]: https://datatracker.ietf.org/doc/draft-koch-openpgp-webkey-service/
+
+use std::time::Duration;
+
+use http_types::{Method, Request, Url};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use sha1::{Digest, Sha1};
+
+use crate::config::Config;
+use crate::contact::{addr_cmp, Contact, Origin};
+use crate::context::Context;
+use crate::dc_tools::{time, EmailAddress};
+use crate::error::{ensure, format_err, Error, Result};
+use crate::key::{DcKey, SignedPublicKey};
+use crate::param::Param;
+use crate::peerstate::{KeySource, Peerstate};
+use crate::socks::send_http;
+
+/// Timeout for a single HTTPS request.
+const WKD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Keys of an address are looked up at most once in this period.
+const LOOKUP_INTERVAL: i64 = 7 * 24 * 60 * 60;
+
+/// Keys larger than this are not imported.
+const MAX_KEY_SIZE: usize = 256 * 1024;
+
+const ZBASE32_ALPHABET: &[u8] = b"ybndrfg8ejkmcpqxot1uwisza345h769";
+
+/// Encodes `data` using z-base-32 as required for WKD.
+fn zbase32(data: &[u8]) -> String {
+    let mut res = String::with_capacity((data.len() * 8 + 4) / 5);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            res.push(ZBASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        res.push(ZBASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    res
+}
+
+/// Returns the URLs of the advanced and of the direct method for `addr`.
+fn wkd_urls(addr: &str) -> Result<(String, String)> {
+    let addr = EmailAddress::new(addr).map_err(|err| format_err!("{}", err))?;
+    let domain = addr.domain.to_lowercase();
+    let hash = zbase32(&Sha1::digest(addr.local.to_lowercase().as_bytes()));
+    let local = utf8_percent_encode(&addr.local, NON_ALPHANUMERIC);
+    Ok((
+        format!(
+            "https://openpgpkey.{}/.well-known/openpgpkey/{}/hu/{}?l={}",
+            domain, domain, hash, local
+        ),
+        format!(
+            "https://{}/.well-known/openpgpkey/hu/{}?l={}",
+            domain, hash, local
+        ),
+    ))
+}
+
+/// Checks that `key` is valid and belongs to `addr`.
+pub(crate) fn check_key(key: &SignedPublicKey, addr: &str) -> Result<()> {
+    key.verify()
+        .map_err(|err| format_err!("Invalid key: {}", err))?;
+    let bracketed = format!("<{}>", addr.to_lowercase());
+    ensure!(
+        key.details.users.iter().any(|user| {
+            let id = user.id.id().to_lowercase();
+            id.contains(&bracketed) || addr_cmp(&id, addr)
+        }),
+        "Key has no user ID for {}",
+        addr
+    );
+    Ok(())
+}
+
+async fn fetch_key(context: &Context, url: &str) -> Result<Vec<u8>> {
+    info!(context, "WKD: requesting {}", url);
+    let request = async {
+        let url = Url::parse(url)?;
+        let mut response = send_http(context, Request::new(Method::Get, url))
+            .await
+            .map_err(|err| format_err!("WKD request failed: {}", err))?;
+        ensure!(
+            response.status().is_success(),
+            "WKD request failed with status {}",
+            response.status()
+        );
+        response
+            .body_bytes()
+            .await
+            .map_err(|err| format_err!("Cannot read WKD response: {}", err))
+    };
+    async_std::future::timeout(WKD_TIMEOUT, request)
+        .await
+        .map_err(|_| format_err!("WKD request timed out"))?
+}
+
+/// Queries the Web Key Directory of the domain of `addr` for its key.
+///
+/// Returns the key and the URL it was found at or `None` if there is no
+/// valid key for the address.
+pub(crate) async fn lookup_key(
+    context: &Context,
+    addr: &str,
+) -> Result<Option<(SignedPublicKey, String)>> {
+    let (advanced, direct) = wkd_urls(addr)?;
+    for url in vec![advanced, direct] {
+        let bytes = match fetch_key(context, &url).await {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                info!(context, "{}", err);
+                continue;
+            }
+        };
+        if bytes.is_empty() || bytes.len() > MAX_KEY_SIZE {
+            continue;
+        }
+        match SignedPublicKey::from_slice(&bytes)
+            .map_err(Error::from)
+            .and_then(|key| check_key(&key, addr).map(|()| key))
+        {
+            Ok(key) => return Ok(Some((key, url))),
+            Err(err) => warn!(context, "WKD: ignoring key for {}: {}", addr, err),
+        }
+    }
+    Ok(None)
+}
+
+/// Looks up the keys of the recipients without a peerstate in their
+/// Web Key Directories and stores found keys as gossip keys.
+///
+/// Does nothing if [Config::WkdLookup] is disabled.  The directory of
+/// each recipient is queried at most once a week.
+pub(crate) async fn import_missing_keys(context: &Context, recipients: &[String]) {
+    if !context.get_config_bool(Config::WkdLookup).await {
+        return;
+    }
+    let self_addr = context
+        .get_config(Config::ConfiguredAddr)
+        .await
+        .unwrap_or_default();
+    for addr in recipients {
+        if addr_cmp(addr, &self_addr) || Peerstate::from_addr(context, addr).await.is_some() {
+            continue;
+        }
+        if let Err(err) = import_key(context, addr).await {
+            warn!(context, "WKD: cannot import key for {}: {}", addr, err);
+        }
+    }
+}
+
+/// Returns true if `addr` was not looked up in the last week and records
+/// the current lookup in the contact parameter `param`.
+pub(crate) async fn start_lookup(context: &Context, addr: &str, param: Param) -> Result<bool> {
+    let contact_id = Contact::lookup_id_by_addr(context, addr, Origin::Unknown).await;
+    ensure!(contact_id != 0, "No contact for {}", addr);
+    let mut contact = Contact::load_from_db(context, contact_id).await?;
+    let now = time();
+    let last_lookup = contact
+        .param
+        .get(param)
+        .and_then(|timestamp| timestamp.parse::<i64>().ok())
+        .unwrap_or_default();
+    if last_lookup > now - LOOKUP_INTERVAL && last_lookup <= now {
+        return Ok(false);
+    }
+    contact.param.set(param, now.to_string());
+    contact.update_param(context).await?;
+    Ok(true)
+}
+
+/// Stores a key found in a key directory as gossip key of `addr`.
+pub(crate) async fn store_key(
+    context: &Context,
+    addr: &str,
+    public_key: SignedPublicKey,
+    source: KeySource,
+    url: &str,
+) -> Result<()> {
+    let fingerprint = public_key.fingerprint();
+    let peerstate = Peerstate::from_discovered_key(context, addr, public_key, time());
+    peerstate.save_to_db(&context.sql, true).await?;
+    peerstate.add_key_source(source, &fingerprint, url).await?;
+    Ok(())
+}
+
+async fn import_key(context: &Context, addr: &str) -> Result<()> {
+    if !start_lookup(context, addr, Param::WkdLookup).await? {
+        return Ok(());
+    }
+    if let Some((public_key, url)) = lookup_key(context, addr).await? {
+        info!(context, "WKD: importing key for {}", addr);
+        store_key(context, addr, public_key, KeySource::Wkd, &url).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_zbase32() {
+        assert_eq!(zbase32(&[]), "");
+        assert_eq!(zbase32(&[0xf0]), "6y");
+        assert_eq!(zbase32(&[0xd4, 0x7a, 0x04]), "4t7ye");
+    }
+
+    #[test]
+    fn test_wkd_urls() {
+        let (advanced, direct) = wkd_urls("Joe.Doe@Example.ORG").unwrap();
+        assert_eq!(
+            advanced,
+            "https://openpgpkey.example.org/.well-known/openpgpkey/example.org/hu/iy9q119eutrkn8s1mk4r39qejnbu3n5q?l=Joe%2EDoe"
+        );
+        assert_eq!(
+            direct,
+            "https://example.org/.well-known/openpgpkey/hu/iy9q119eutrkn8s1mk4r39qejnbu3n5q?l=Joe%2EDoe"
+        );
+        assert!(wkd_urls("not an address").is_err());
+    }
+
+    #[test]
+    fn test_check_key() {
+        let alice = alice_keypair();
+        assert!(check_key(&alice.public, "alice@example.com").is_ok());
+        assert!(check_key(&alice.public, "ALICE@example.com").is_ok());
+        assert!(check_key(&alice.public, "bob@example.net").is_err());
+    }
+}